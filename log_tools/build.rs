@@ -0,0 +1,13 @@
+//! Generates the `dumpsys --proto` message types from the trimmed AOSP
+//! protos under proto/, with a protoc built from source so no system
+//! install is needed.
+
+const PROTOS: &[&str] = &[
+    "proto/frameworks/base/core/proto/android/server/activitymanagerservice.proto",
+    "proto/frameworks/base/core/proto/android/service/batterystats.proto",
+];
+
+fn main() -> std::io::Result<()> {
+    std::env::set_var("PROTOC", protobuf_src::protoc());
+    prost_build::Config::new().include_file("dumpsys.rs").compile_protos(PROTOS, &["proto"])
+}
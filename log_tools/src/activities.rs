@@ -0,0 +1,80 @@
+//! Task stack and window focus from `dumpsys activity activities` and
+//! `dumpsys window windows`.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+/// Appended to by every automatic capture.
+pub const STACK_SNAPSHOTS_FILE: &str = "stack_snapshots.jsonl";
+
+// "ActivityRecord{5f2a1c3 u0 com.foo/.MainActivity t12}" (a trailing
+// " f}" or " d}" state suffix appears on some releases).
+static ACTIVITY_RECORD_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"ActivityRecord\{[0-9a-f]+ u\d+ (\S+) t(-?\d+)").unwrap());
+// "mCurrentFocus=Window{8c3e1d u0 com.foo/com.foo.MainActivity}"
+static FOCUS_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"mCurrentFocus=Window\{[0-9a-f]+ u\d+ ([^}]+)\}").unwrap());
+
+#[derive(Serialize)]
+pub struct TaskInfo {
+    pub id: i64,
+    /// Activities from the top of the task down.
+    pub activities: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct ActivityStackSnapshot {
+    /// Host wall-clock time, milliseconds since the Unix epoch.
+    pub time_ms: i64,
+    /// What caused the capture, e.g. a crash line or marker label.
+    pub trigger: String,
+    pub resumed_activity: Option<String>,
+    pub focused_window: Option<String>,
+    pub tasks: Vec<TaskInfo>,
+}
+
+/// A snapshot taken now, before anything is read into it.
+pub fn empty_snapshot(trigger: &str) -> ActivityStackSnapshot {
+    ActivityStackSnapshot {
+        time_ms: chrono::Local::now().timestamp_millis(),
+        trigger: trigger.to_string(),
+        resumed_activity: None,
+        focused_window: None,
+        tasks: Vec::new(),
+    }
+}
+
+pub fn parse_focused_window(windows: &str) -> Option<String> {
+    FOCUS_REGEX.captures(windows).map(|caps| caps[1].trim().to_string())
+}
+
+pub fn parse_activity_stack(activities: &str, windows: &str, trigger: &str) -> ActivityStackSnapshot {
+    let mut snap = empty_snapshot(trigger);
+
+    for line in activities.lines() {
+        let trimmed = line.trim();
+        let Some(caps) = ACTIVITY_RECORD_REGEX.captures(trimmed) else {
+            continue;
+        };
+        // mResumedActivity (pre-10), ResumedActivity and topResumedActivity.
+        if trimmed.contains("ResumedActivity") {
+            snap.resumed_activity.get_or_insert_with(|| caps[1].to_string());
+            continue;
+        }
+        // Stack listings: "* Hist #0: ActivityRecord{...}" before API 29,
+        // "* ActivityRecord{...}" under each Task after.
+        if !(trimmed.starts_with("* Hist #") || trimmed.starts_with("* ActivityRecord{")) {
+            continue;
+        }
+        let id: i64 = caps[2].parse().unwrap_or(-1);
+        let component = caps[1].to_string();
+        match snap.tasks.iter_mut().find(|task| task.id == id) {
+            Some(task) if !task.activities.contains(&component) => task.activities.push(component),
+            Some(_) => {}
+            None => snap.tasks.push(TaskInfo { id, activities: vec![component] }),
+        }
+    }
+
+    snap.focused_window = parse_focused_window(windows);
+    snap
+}
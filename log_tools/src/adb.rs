@@ -0,0 +1,225 @@
+//! Client for the adb server's TCP protocol, so shell commands and logcat
+//! streams go straight to the server socket instead of through a new `adb`
+//! process each time. Requests are a 4-hex-digit length and the service
+//! name; the server answers OKAY, or FAIL with a length-prefixed message.
+//! `host:transport:<serial>` binds the connection to a device, after which
+//! one `shell:` or `exec:` service runs on it until the socket closes.
+//!
+//! A [`ShellSession`] keeps one `sh` open on the device and runs many
+//! commands through it, for collectors that sample every interval.
+//!
+//! When the server cannot be reached (not started yet, or a different
+//! port than the environment says) callers fall back to the adb binary,
+//! which starts the server for the next request.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::process::Child;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+
+pub const DEFAULT_PORT: u16 = 5037;
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Clone)]
+pub struct Server {
+    host: String,
+    port: u16,
+    serial: Option<String>,
+}
+
+impl Server {
+    /// The server adb itself would use, from `ADB_SERVER_SOCKET`
+    /// ("tcp:host:port") or `ANDROID_ADB_SERVER_PORT`, talking to `serial`
+    /// or the only device.
+    pub fn from_env(serial: Option<&str>) -> Server {
+        let socket = std::env::var("ADB_SERVER_SOCKET").ok();
+        let (host, port) = match socket.as_deref().and_then(|s| s.strip_prefix("tcp:")).and_then(|s| s.rsplit_once(':')) {
+            Some((host, port)) => (host.to_string(), port.parse().unwrap_or(DEFAULT_PORT)),
+            None => {
+                let port = std::env::var("ANDROID_ADB_SERVER_PORT").ok().and_then(|p| p.parse().ok()).unwrap_or(DEFAULT_PORT);
+                ("127.0.0.1".to_string(), port)
+            }
+        };
+        Server { host, port, serial: serial.map(str::to_string) }
+    }
+
+    fn connect(&self) -> Result<TcpStream> {
+        let addr: SocketAddr = (self.host.as_str(), self.port)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| anyhow!("Cannot resolve adb server host {}", self.host))?;
+        Ok(TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)?)
+    }
+
+    /// Answer of a host service, such as `host:version`.
+    fn query(&self, service: &str) -> Result<String> {
+        let mut stream = self.connect()?;
+        request(&mut stream, service)?;
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len)?;
+        let mut answer = vec![0u8; usize::from_str_radix(std::str::from_utf8(&len)?, 16)?];
+        stream.read_exact(&mut answer)?;
+        Ok(String::from_utf8_lossy(&answer).into_owned())
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.query("host:version").is_ok()
+    }
+
+    /// Attached devices in the format of `adb devices -l`, without its
+    /// header line.
+    pub fn devices(&self) -> Result<String> {
+        self.query("host:devices-l")
+    }
+
+    /// A connection running `service` on the device.
+    pub fn open(&self, service: &str) -> Result<TcpStream> {
+        let mut stream = self.connect()?;
+        let transport = match &self.serial {
+            Some(serial) => format!("host:transport:{}", serial),
+            None => "host:transport-any".to_string(),
+        };
+        request(&mut stream, &transport)?;
+        request(&mut stream, service)?;
+        Ok(stream)
+    }
+
+    /// Output of a shell command line, run without a pty.
+    pub fn shell(&self, command: &str) -> Result<Vec<u8>> {
+        read_all(self.open(&format!("shell:{}", command))?)
+    }
+
+    /// Output of a command run without the shell's output processing, as
+    /// `adb exec-out` does.
+    pub fn exec(&self, command: &str) -> Result<Vec<u8>> {
+        read_all(self.open(&format!("exec:{}", command))?)
+    }
+}
+
+fn request(stream: &mut TcpStream, service: &str) -> Result<()> {
+    stream.write_all(format!("{:04x}{}", service.len(), service).as_bytes())?;
+    let mut status = [0u8; 4];
+    stream.read_exact(&mut status)?;
+    match &status {
+        b"OKAY" => Ok(()),
+        b"FAIL" => {
+            let mut len = [0u8; 4];
+            stream.read_exact(&mut len)?;
+            let mut message = vec![0u8; usize::from_str_radix(std::str::from_utf8(&len)?, 16)?];
+            stream.read_exact(&mut message)?;
+            Err(anyhow!("adb server: {}", String::from_utf8_lossy(&message)))
+        }
+        other => Err(anyhow!("adb server: unexpected reply {:?}", String::from_utf8_lossy(other))),
+    }
+}
+
+fn read_all(mut stream: TcpStream) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    stream.read_to_end(&mut output)?;
+    Ok(output)
+}
+
+/// Arguments joined into a device command line the way `adb logcat` does
+/// it, single-quoting those holding spaces; `adb shell` and `exec-out`
+/// join them as they are.
+pub fn command_line<S: AsRef<str>>(args: &[S]) -> String {
+    args.iter()
+        .map(|arg| {
+            let arg = arg.as_ref();
+            if arg.contains(' ') && !arg.starts_with('\'') { format!("'{}'", arg) } else { arg.to_string() }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Output of a long-running device command, such as logcat: a server
+/// connection, or an adb child process.
+pub enum Stream {
+    Socket(TcpStream),
+    Process(Child),
+}
+
+impl Stream {
+    /// Where the command's standard input goes.
+    pub fn input(&mut self) -> Result<Box<dyn Write + Send>> {
+        match self {
+            Stream::Socket(socket) => Ok(Box::new(socket.try_clone()?)),
+            Stream::Process(child) => Ok(Box::new(child.stdin.take().ok_or(anyhow!("Failed to get stdin"))?)),
+        }
+    }
+
+    pub fn output(&mut self) -> Result<Box<dyn Read + Send>> {
+        match self {
+            Stream::Socket(socket) => Ok(Box::new(socket.try_clone()?)),
+            Stream::Process(child) => Ok(Box::new(child.stdout.take().ok_or(anyhow!("Failed to get stdout"))?)),
+        }
+    }
+
+    /// Ends the command; the output then reaches its end.
+    pub fn stop(&mut self) -> io::Result<()> {
+        match self {
+            // Fails harmlessly when already shut down.
+            Stream::Socket(socket) => {
+                let _ = socket.shutdown(Shutdown::Both);
+                Ok(())
+            }
+            Stream::Process(child) => {
+                child.kill()?;
+                child.wait().map(|_| ())
+            }
+        }
+    }
+}
+
+/// A device `sh` reading command lines from its input, so each command
+/// costs a round trip on an open connection rather than a new connection
+/// and shell process. Every command runs in a subshell with no input and
+/// its stderr discarded, as one-shot `adb shell` output would have it,
+/// and is followed by a numbered marker line that ends its output.
+pub struct ShellSession {
+    stream: Stream,
+    input: Box<dyn Write + Send>,
+    output: BufReader<Box<dyn Read + Send>>,
+    commands: u64,
+}
+
+impl ShellSession {
+    /// `stream` runs `sh` without a pty: `shell:sh` on the server, or
+    /// `adb shell -T sh` with stdin piped.
+    pub fn new(mut stream: Stream) -> Result<ShellSession> {
+        let input = stream.input()?;
+        let output = BufReader::new(stream.output()?);
+        Ok(ShellSession { stream, input, output, commands: 0 })
+    }
+
+    /// Output of a shell command line. An error leaves the session unusable.
+    pub fn run(&mut self, command: &str) -> Result<Vec<u8>> {
+        self.commands += 1;
+        let marker = format!("__log_tools_end_{}__", self.commands);
+        // eval of a quoted line fails on bad syntax instead of leaving the
+        // shell waiting for the rest of it.
+        let line = format!("(eval '{}') </dev/null 2>/dev/null; printf '\\n%s\\n' {}\n", command.replace('\'', "'\\''"), marker);
+        self.input.write_all(line.as_bytes())?;
+        self.input.flush()?;
+        let mut output = Vec::new();
+        loop {
+            let start = output.len();
+            if self.output.read_until(b'\n', &mut output)? == 0 {
+                return Err(anyhow!("Device shell closed"));
+            }
+            if output[start..].strip_suffix(b"\n") == Some(marker.as_bytes()) {
+                // The marker's own line break and the one printed before it.
+                output.truncate(start.saturating_sub(1));
+                return Ok(output);
+            }
+        }
+    }
+}
+
+impl Drop for ShellSession {
+    fn drop(&mut self) {
+        let _ = self.stream.stop();
+    }
+}
@@ -0,0 +1,189 @@
+//! Absolute addresses in crash output ("fault addr 0x7b2c3d4e5f60", abort
+//! messages) put on the mapping they fall in, as "libfoo.so+0x1234", from
+//! a `/proc/<pid>/maps` snapshot of the process. The offset is into the
+//! file, as `#00 pc` lines give it, so it can go to addr2line or
+//! ndk-stack later. Maps are private to the app's uid, so they are read
+//! through root or run-as while the process is still there: at the
+//! crash's "Fatal signal" line, when debuggerd holds it for the dump, or
+//! from a snapshot taken while it was running.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// Native crashes of the app seen by logcat sessions, annotated.
+pub const NATIVE_CRASHES_FILE: &str = "native_crashes.txt";
+/// Minimum age of a running process's snapshot before it is read again.
+pub const REFRESH_SECS: u64 = 10;
+
+// Eight hex digits or more, so signal codes and flags are left alone.
+static ADDRESS_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b0x([0-9a-fA-F]{8,16})\b").unwrap());
+// "F/libc    ( 1234): Fatal signal 11 (SIGSEGV), code 1 (SEGV_MAPERR), fault addr 0x7b2c3d4e5f60 in tid 1250 (RenderThread), pid 1234 (com.example.app)"
+static FATAL_SIGNAL_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"Fatal signal \d+ .* pid (\d+) \(([^)]*)\)").unwrap());
+
+pub fn maps_cmd(pid: &str) -> String {
+    format!("cat /proc/{}/maps", pid)
+}
+
+/// Lines of a native crash: the signal line and debuggerd's dump.
+pub fn is_native_crash_line(line: &str) -> bool {
+    line.contains("Fatal signal") || line.contains("/DEBUG")
+}
+
+/// Whether the line ends debuggerd's dump.
+pub fn is_dump_end(line: &str) -> bool {
+    line.contains("Tombstone written to")
+}
+
+/// PID of the crashing process when a "Fatal signal" line is about
+/// `package`. The kernel keeps 15 characters of a process name, the
+/// runtime the last ones.
+pub fn fatal_signal_pid(line: &str, package: &str) -> Option<String> {
+    let caps = FATAL_SIGNAL_REGEX.captures(line)?;
+    let name = &caps[2];
+    (!name.is_empty() && package.ends_with(name)).then(|| caps[1].to_string())
+}
+
+struct Mapping {
+    start: u64,
+    end: u64,
+    offset: u64,
+    /// File path, or a bracketed name such as "[anon:scudo:primary]".
+    name: String,
+}
+
+#[derive(Default)]
+pub struct MapsSnapshot {
+    mappings: Vec<Mapping>,
+}
+
+impl MapsSnapshot {
+    /// Parses `cat /proc/<pid>/maps` output; unnamed mappings are left out.
+    pub fn parse(maps: &str) -> MapsSnapshot {
+        let mappings = maps
+            .lines()
+            .filter_map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let (range, offset) = (fields.first()?, fields.get(2)?);
+                let (start, end) = range.split_once('-')?;
+                let name = fields.get(5..).filter(|rest| !rest.is_empty())?.join(" ");
+                Some(Mapping {
+                    start: u64::from_str_radix(start, 16).ok()?,
+                    end: u64::from_str_radix(end, 16).ok()?,
+                    offset: u64::from_str_radix(offset, 16).ok()?,
+                    name,
+                })
+            })
+            .collect();
+        MapsSnapshot { mappings }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    /// "libfoo.so+0x1234" for a file, "[stack]+0x10" for a named region.
+    pub fn resolve(&self, address: u64) -> Option<String> {
+        let mapping = self.mappings.iter().find(|m| m.start <= address && address < m.end)?;
+        if mapping.name.starts_with('/') {
+            let file = mapping.name.rsplit('/').next().unwrap_or(&mapping.name);
+            Some(format!("{}+0x{:x}", file, address - mapping.start + mapping.offset))
+        } else {
+            Some(format!("{}+0x{:x}", mapping.name, address - mapping.start))
+        }
+    }
+
+    /// The line with each resolvable address followed by its mapping, e.g.
+    /// "0x7b2c3d4e5f60 (libfoo.so+0x1234)", and how many were resolved.
+    pub fn annotate(&self, line: &str) -> (String, usize) {
+        let mut resolved = 0;
+        let annotated = ADDRESS_REGEX.replace_all(line, |caps: &regex::Captures| {
+            match u64::from_str_radix(&caps[1], 16).ok().and_then(|address| self.resolve(address)) {
+                Some(location) => {
+                    resolved += 1;
+                    format!("{} ({})", &caps[0], location)
+                }
+                None => caps[0].to_string(),
+            }
+        });
+        (annotated.into_owned(), resolved)
+    }
+}
+
+/// Annotates the crashes of process `pid` of `package` in a crash buffer
+/// dump, from their signal line to the end of debuggerd's dump, with a
+/// snapshot of that process. Returns the text and the addresses resolved.
+pub fn annotate_crashes(text: &str, package: &str, pid: &str, snapshot: &MapsSnapshot) -> (String, usize) {
+    let (mut annotated, mut resolved, mut in_crash) = (String::with_capacity(text.len()), 0, false);
+    for line in text.lines() {
+        if line.contains("Fatal signal") {
+            in_crash = fatal_signal_pid(line, package).as_deref() == Some(pid);
+        }
+        if in_crash && is_native_crash_line(line) {
+            let (line, count) = snapshot.annotate(line);
+            resolved += count;
+            annotated.push_str(&line);
+        } else {
+            annotated.push_str(line);
+        }
+        annotated.push('\n');
+        in_crash &= !is_dump_end(line);
+    }
+    (annotated, resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAPS: &str = "\
+7b2c3d000000-7b2c3d4e0000 r--p 00000000 fd:05 802113                     /data/app/~~w3A9q==/com.example.app-Xq1b==/lib/arm64/libfoo.so
+7b2c3d4e0000-7b2c3d600000 r-xp 004e0000 fd:05 802113                     /data/app/~~w3A9q==/com.example.app-Xq1b==/lib/arm64/libfoo.so
+7b1000000000-7b1000100000 rw-p 00000000 00:00 0 
+7b2a00000000-7b2a40000000 rw-p 00000000 00:00 0                          [anon:scudo:primary]
+7ffe7a1000-7ffe7c2000 rw-p 00000000 00:00 0                              [stack]
+";
+
+    #[test]
+    fn addresses_resolve_to_file_offsets_and_regions() {
+        let snapshot = MapsSnapshot::parse(MAPS);
+        assert_eq!(snapshot.resolve(0x7b2c3d4e5f60).as_deref(), Some("libfoo.so+0x4e5f60"));
+        assert_eq!(snapshot.resolve(0x7b2a00001230).as_deref(), Some("[anon:scudo:primary]+0x1230"));
+        assert_eq!(snapshot.resolve(0x7ffe7a1010).as_deref(), Some("[stack]+0x10"));
+        // Unnamed mappings are not kept.
+        assert_eq!(snapshot.resolve(0x7b1000000010), None);
+        assert!(MapsSnapshot::parse("").is_empty());
+    }
+
+    #[test]
+    fn fatal_signal_names_the_process() {
+        let line = "03-10 15:00:05.100 F/libc    ( 4242): Fatal signal 11 (SIGSEGV), code 1 (SEGV_MAPERR), fault addr 0x7b2c3d4e5f60 in tid 4260 (RenderThread), pid 4242 (com.example.app)";
+        assert_eq!(fatal_signal_pid(line, "com.example.app").as_deref(), Some("4242"));
+        assert_eq!(fatal_signal_pid(line, "com.example.app2"), None);
+        let truncated = line.replace("(com.example.app)", "(ample.longname)");
+        assert_eq!(fatal_signal_pid(&truncated, "com.example.longname").as_deref(), Some("4242"));
+    }
+
+    #[test]
+    fn only_the_app_crash_is_annotated() {
+        let crash = "\
+03-10 15:00:05.100 F/libc    ( 4242): Fatal signal 11 (SIGSEGV), code 1 (SEGV_MAPERR), fault addr 0x7b2c3d4e5f60 in tid 4260 (RenderThread), pid 4242 (com.example.app)
+03-10 15:00:05.300 F/DEBUG   ( 5300): *** *** *** *** *** *** *** *** *** *** *** *** *** *** *** ***
+03-10 15:00:05.301 F/DEBUG   ( 5300): pid: 4242, tid: 4260, name: RenderThread  >>> com.example.app <<<
+03-10 15:00:05.302 F/DEBUG   ( 5300): signal 11 (SIGSEGV), code 1 (SEGV_MAPERR), fault addr 0x7b2c3d4e5f60
+03-10 15:00:05.303 F/DEBUG   ( 5300):     sp  0000007ffe7a1010  lr  0000007b2c3d4e1000  pc  0000007b2c3d4e5f60
+03-10 15:00:05.310 F/DEBUG   ( 5300): Abort message: 'stack at 0x7ffe7a1010'
+03-10 15:00:05.400 F/DEBUG   ( 5300): Tombstone written to: /data/tombstones/tombstone_03
+03-10 15:01:00.000 F/libc    ( 5120): Fatal signal 6 (SIGABRT), code -1 (SI_QUEUE) in tid 5120 (com.other.app), pid 5120 (com.other.app)
+03-10 15:01:00.200 F/DEBUG   ( 5400): Abort message: 'at 0x7b2c3d4e5f60'
+";
+        let (text, resolved) = annotate_crashes(crash, "com.example.app", "4242", &MapsSnapshot::parse(MAPS));
+        assert_eq!(resolved, 3);
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[0].ends_with("fault addr 0x7b2c3d4e5f60 (libfoo.so+0x4e5f60) in tid 4260 (RenderThread), pid 4242 (com.example.app)"));
+        assert!(lines[3].ends_with("fault addr 0x7b2c3d4e5f60 (libfoo.so+0x4e5f60)"));
+        // Register dumps have no 0x prefix and are left as they are.
+        assert_eq!(lines[4], crash.lines().nth(4).unwrap());
+        assert!(lines[5].ends_with("'stack at 0x7ffe7a1010 ([stack]+0x10)'"));
+        assert_eq!(lines[8], crash.lines().nth(8).unwrap());
+    }
+}
@@ -0,0 +1,212 @@
+//! ANR trace collection and main-thread stack aggregation. Collected
+//! traces accumulate in a SQLite store, one row per distinct trace, so
+//! fingerprints can be counted across sessions; each is also written to
+//! a local directory for `search`.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+
+use crate::bundle;
+
+/// Local directory holding a copy of every trace pulled from `/data/anr`.
+pub const ANR_DIR: &str = "anr_traces";
+/// The store `anr top` reads.
+pub const ANR_DB: &str = "anr_traces.db";
+/// Modification time, size and path of each file in `/data/anr`.
+pub const LIST_CMD: &str = "stat -c '%Y %s %n' /data/anr/*";
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS traces (
+    sha256 TEXT PRIMARY KEY,
+    device_name TEXT NOT NULL,
+    device_mtime INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    local_file TEXT NOT NULL,
+    collected_at TEXT NOT NULL,
+    contents TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS traces_device ON traces(device_name, device_mtime, size);
+";
+
+/// A trace file in `/data/anr` as listed by `LIST_CMD`.
+pub struct DeviceTrace {
+    pub name: String,
+    pub mtime: i64,
+    pub size: u64,
+}
+
+/// Trace files in `LIST_CMD` output. Lines of files that could not be
+/// read, without root, are skipped.
+pub fn parse_listing(output: &str) -> Vec<DeviceTrace> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.trim().splitn(3, ' ');
+            let (mtime, size) = (fields.next()?.parse().ok()?, fields.next()?.parse().ok()?);
+            let name = fields.next()?.rsplit('/').next()?;
+            (name.starts_with("anr_") || name.starts_with("traces")).then(|| DeviceTrace { name: name.to_string(), mtime, size })
+        })
+        .collect()
+}
+
+pub struct TraceStore {
+    conn: Connection,
+}
+
+impl TraceStore {
+    /// Opens `path`, creating the file and table if needed.
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Connection::open(path).with_context(|| format!("Cannot open database {}", path))?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(TraceStore { conn })
+    }
+
+    /// Whether the device file was collected as it is now. The device
+    /// reuses names, so a name alone does not tell.
+    pub fn has(&self, trace: &DeviceTrace) -> Result<bool> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT 1 FROM traces WHERE device_name = ?1 AND device_mtime = ?2 AND size = ?3",
+                params![trace.name, trace.mtime, trace.size],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some())
+    }
+
+    /// Stores a trace unless one with the same contents is, and returns
+    /// the local file name it gets: the device's, made unique with the
+    /// start of its hash when taken.
+    pub fn add(&self, trace: &DeviceTrace, contents: &str) -> Result<Option<String>> {
+        let sha256 = bundle::sha256_hex(contents.as_bytes());
+        let taken = |name: &str| -> Result<bool> {
+            Ok(self.conn.query_row("SELECT 1 FROM traces WHERE local_file = ?1", [name], |_| Ok(())).optional()?.is_some()
+                || Path::new(ANR_DIR).join(name).exists())
+        };
+        let local_file = if taken(&trace.name)? { format!("{}_{}", trace.name, &sha256[..12]) } else { trace.name.clone() };
+        let added = self.conn.execute(
+            "INSERT OR IGNORE INTO traces VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![sha256, trace.name, trace.mtime, trace.size, local_file, chrono::Local::now().to_rfc3339(), contents],
+        )?;
+        if added == 0 {
+            // Remembers where the known contents are now, so they are not read again.
+            self.conn.execute(
+                "UPDATE traces SET device_name = ?2, device_mtime = ?3, size = ?4 WHERE sha256 = ?1",
+                params![sha256, trace.name, trace.mtime, trace.size],
+            )?;
+        }
+        Ok((added > 0).then_some(local_file))
+    }
+
+    /// Every stored trace by local file name, oldest first.
+    pub fn traces(&self) -> Result<Vec<(String, String)>> {
+        let mut query = self.conn.prepare("SELECT local_file, contents FROM traces ORDER BY collected_at, local_file")?;
+        let rows = query.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+/// The stored traces; none before the first `anr collect`.
+pub fn stored_traces() -> Result<Vec<(String, String)>> {
+    if !Path::new(ANR_DB).exists() {
+        return Ok(Vec::new());
+    }
+    TraceStore::open(ANR_DB)?.traces()
+}
+
+#[derive(Serialize)]
+pub struct StackFingerprint {
+    /// Top frames of the main thread, innermost first.
+    pub frames: Vec<String>,
+    pub count: u64,
+    /// Trace files in which the fingerprint was seen.
+    pub traces: Vec<String>,
+}
+
+/// Returns the Java frames of the main thread of `package` in a trace
+/// dump, innermost first. Traces hold one "----- pid N at ... -----"
+/// section per process.
+pub fn main_thread_frames(trace: &str, package: &str) -> Option<Vec<String>> {
+    let mut in_package = false;
+    let mut in_main = false;
+    let mut frames = Vec::new();
+    for line in trace.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("----- pid ") {
+            in_package = false;
+            in_main = false;
+        } else if let Some(cmd) = trimmed.strip_prefix("Cmd line: ") {
+            in_package = cmd.trim() == package;
+        } else if in_package && trimmed.starts_with("\"main\" ") {
+            in_main = true;
+        } else if in_main {
+            if trimmed.is_empty() {
+                break;
+            }
+            if let Some(frame) = trimmed.strip_prefix("at ") {
+                frames.push(frame.to_string());
+            }
+        }
+    }
+    if frames.is_empty() { None } else { Some(frames) }
+}
+
+/// Groups traces by their top `depth` main-thread frames, most frequent
+/// first. `traces` pairs a trace name with its contents.
+pub fn aggregate(traces: &[(String, String)], package: &str, depth: usize) -> Vec<StackFingerprint> {
+    let mut groups: HashMap<Vec<String>, StackFingerprint> = HashMap::new();
+    for (name, trace) in traces {
+        let Some(mut frames) = main_thread_frames(trace, package) else {
+            continue;
+        };
+        frames.truncate(depth);
+        let group = groups
+            .entry(frames.clone())
+            .or_insert_with(|| StackFingerprint { frames, count: 0, traces: Vec::new() });
+        group.count += 1;
+        group.traces.push(name.clone());
+    }
+    let mut fingerprints: Vec<StackFingerprint> = groups.into_values().collect();
+    fingerprints.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.frames.cmp(&b.frames)));
+    fingerprints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_trace_listing() {
+        let output = "1700000000 2048 /data/anr/anr_2023-11-14-22-13-20-000\n\
+                      stat: '/data/anr/*': Permission denied\n\
+                      1700000100 512 /data/anr/dumptrace_abc\n";
+        let listing = parse_listing(output);
+        assert_eq!(listing.len(), 1);
+        assert_eq!((listing[0].name.as_str(), listing[0].mtime, listing[0].size), ("anr_2023-11-14-22-13-20-000", 1700000000, 2048));
+    }
+
+    #[test]
+    fn stores_new_contents_under_a_reused_name() {
+        let path = std::env::temp_dir().join(format!("anr_test_{}.db", std::process::id()));
+        let store = TraceStore::open(&path.to_string_lossy()).unwrap();
+        let first = DeviceTrace { name: "traces.txt".to_string(), mtime: 1, size: 5 };
+        assert!(!store.has(&first).unwrap());
+        assert_eq!(store.add(&first, "first").unwrap().as_deref(), Some("traces.txt"));
+        assert!(store.has(&first).unwrap());
+
+        let rewritten = DeviceTrace { name: "traces.txt".to_string(), mtime: 2, size: 6 };
+        assert!(!store.has(&rewritten).unwrap());
+        let local = store.add(&rewritten, "second").unwrap().unwrap();
+        assert!(local.starts_with("traces.txt_"));
+        // Same contents again, e.g. after a touch, are no new trace.
+        let touched = DeviceTrace { name: "traces.txt".to_string(), mtime: 3, size: 6 };
+        assert_eq!(store.add(&touched, "second").unwrap(), None);
+        assert!(store.has(&touched).unwrap());
+        assert_eq!(store.traces().unwrap().len(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
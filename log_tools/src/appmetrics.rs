@@ -0,0 +1,143 @@
+//! Custom app metrics reported through logcat. An app logs a line holding
+//! `ADT_METRIC` directly followed by a JSON object, with any tag and level:
+//!
+//! ```java
+//! Log.d("MyApp", "ADT_METRIC{\"name\":\"cache_size\",\"value\":123}");
+//! ```
+//!
+//! Memory sessions and logcat captures pick these up, plot one panel per
+//! name and export them with the session's other samples. Only lines
+//! the app's own processes logged after the session started count.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::sync::mpsc::{self, Receiver};
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::adb;
+use crate::clocksync::SampleTime;
+
+pub const METRIC_PREFIX: &str = "ADT_METRIC";
+pub const APP_METRICS_PLOT_FILE: &str = "app_metrics_plot.png";
+
+/// PID of a `-v time` line: "MM-DD HH:MM:SS.mmm D/Tag( 1234): ...".
+static PID_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\d\d-\d\d \d\d:\d\d:\d\d\.\d{3} [VDIWEFS]/[^(]*\(\s*(\d+)\):").unwrap());
+
+/// Follows logcat from `since` (see `ClockSync::logcat_time`) on, with
+/// timestamps that `ClockSync::host_time_ms` reads.
+pub fn logcat_args(since: &str) -> [&str; 5] {
+    ["logcat", "-v", "time", "-T", since]
+}
+
+#[derive(Deserialize)]
+struct Payload {
+    name: String,
+    value: f64,
+}
+
+#[derive(Clone, Serialize)]
+pub struct AppMetric {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    pub name: String,
+    pub value: f64,
+}
+
+pub fn is_metric(line: &str) -> bool {
+    line.contains(METRIC_PREFIX)
+}
+
+/// Name and value of the metric in a logcat line. Text after the JSON
+/// object is ignored.
+pub fn parse_metric(line: &str) -> Option<(String, f64)> {
+    let json = &line[line.find(METRIC_PREFIX)? + METRIC_PREFIX.len()..];
+    let payload: Payload = serde_json::Deserializer::from_str(json).into_iter().next()?.ok()?;
+    Some((payload.name, payload.value))
+}
+
+pub fn line_pid(line: &str) -> Option<&str> {
+    PID_REGEX.captures(line).and_then(|caps| caps.get(1)).map(|m| m.as_str())
+}
+
+/// Whether the PIDs that log metric lines are the app's. A PID is looked
+/// up once, against the app's process at the time it is first seen, so
+/// the metrics of a restarted app still count.
+#[derive(Default)]
+pub struct AppPids {
+    verdicts: HashMap<String, bool>,
+}
+
+impl AppPids {
+    pub fn is_app(&mut self, line: &str, current_pid: impl FnOnce() -> Option<String>) -> bool {
+        let Some(pid) = line_pid(line) else {
+            return false;
+        };
+        *self.verdicts.entry(pid.to_string()).or_insert_with(|| current_pid().as_deref() == Some(pid))
+    }
+}
+
+/// Metric lines of a running `adb logcat`, read on a thread so sampling
+/// loops can drain them between polls.
+pub struct MetricStream {
+    stream: adb::Stream,
+    lines: Receiver<String>,
+}
+
+impl MetricStream {
+    /// Keeps the lines `keep` accepts: metric lines, and any other kind a
+    /// session parses alongside them.
+    pub fn start(mut stream: adb::Stream, keep: impl Fn(&str) -> bool + Send + 'static) -> Result<Self> {
+        let stdout = stream.output()?;
+        let (tx, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(|line| line.ok()) {
+                if keep(&line) && tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(MetricStream { stream, lines })
+    }
+
+    /// Lines logged since the last call.
+    pub fn take(&self) -> Vec<String> {
+        self.lines.try_iter().collect()
+    }
+
+    pub fn stop(mut self) -> Result<Vec<String>> {
+        self.stream.stop()?;
+        Ok(self.take())
+    }
+}
+
+/// Metric names in order of first appearance.
+pub fn names(metrics: &[AppMetric]) -> Vec<&str> {
+    let mut names: Vec<&str> = Vec::new();
+    for metric in metrics {
+        if !names.contains(&metric.name.as_str()) {
+            names.push(&metric.name);
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_of_other_processes_are_left_out() {
+        let line = "10-16 17:36:58.123 D/MyApp( 4321): ADT_METRIC{\"name\":\"cache_size\",\"value\":123}";
+        assert_eq!(line_pid(line), Some("4321"));
+        assert_eq!(parse_metric(line), Some(("cache_size".to_string(), 123.0)));
+        let mut pids = AppPids::default();
+        assert!(!pids.is_app(line, || Some("1000".to_string())));
+        // The verdict is kept, the app is not looked up again.
+        assert!(!pids.is_app(line, || Some("4321".to_string())));
+        assert!(pids.is_app(&line.replace("4321", "1000"), || Some("1000".to_string())));
+    }
+}
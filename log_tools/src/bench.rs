@@ -0,0 +1,66 @@
+//! Warm-up, outlier, iteration-count and stabilization controls shared
+//! by the benchmark-style commands (startup, ab-test, bisect, scenario).
+
+use clap::{Arg, ArgMatches};
+
+use crate::stabilize::{self, StabilizeGate};
+use crate::stats;
+
+pub struct BenchOptions {
+    /// Iterations run first and never recorded.
+    pub warmup: u32,
+    /// Drop values outside Tukey's fences (1.5 IQR beyond the quartiles).
+    pub discard_outliers: bool,
+    pub min_iterations: u32,
+    pub stabilize: Option<StabilizeGate>,
+}
+
+pub fn args() -> impl IntoIterator<Item = Arg> {
+    let bench = [
+        Arg::new("warmup").long("warmup").value_name("N").default_value("0").help("Unrecorded iterations to run first").value_parser(clap::value_parser!(u32)),
+        Arg::new("discard_outliers").long("discard-outliers").help("Drop values beyond 1.5 IQR of the quartiles before statistics").action(clap::ArgAction::SetTrue),
+        Arg::new("min_iterations").long("min-iterations").value_name("N").default_value("1").help("Run at least N recorded iterations").value_parser(clap::value_parser!(u32)),
+    ];
+    bench.into_iter().chain(stabilize::args())
+}
+
+impl BenchOptions {
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        BenchOptions {
+            warmup: *matches.get_one::<u32>("warmup").unwrap_or(&0),
+            discard_outliers: matches.get_flag("discard_outliers"),
+            min_iterations: *matches.get_one::<u32>("min_iterations").unwrap_or(&1),
+            stabilize: StabilizeGate::from_matches(matches),
+        }
+    }
+
+    pub fn iterations(&self, requested: u32) -> u32 {
+        requested.max(self.min_iterations)
+    }
+
+    /// Applies outlier removal to the recorded values of `metric`.
+    pub fn clean(&self, metric: &str, values: Vec<f64>) -> Vec<f64> {
+        if !self.discard_outliers {
+            return values;
+        }
+        let (kept, removed) = discard_outliers(values);
+        if !removed.is_empty() {
+            info!("{}: discarded {} outlier(s): {:?}", metric, removed.len(), removed);
+        }
+        if (kept.len() as u32) < self.min_iterations {
+            warn!(format!("{}: only {} values left after outlier removal, fewer than --min-iterations {}", metric, kept.len(), self.min_iterations));
+        }
+        kept
+    }
+}
+
+/// Splits `values` into those inside Tukey's fences and the outliers.
+/// Fewer than four values are returned unchanged.
+pub fn discard_outliers(values: Vec<f64>) -> (Vec<f64>, Vec<f64>) {
+    if values.len() < 4 {
+        return (values, Vec::new());
+    }
+    let (q1, q3) = (stats::percentile(&values, 25.0), stats::percentile(&values, 75.0));
+    let fence = 1.5 * (q3 - q1);
+    values.into_iter().partition(|v| *v >= q1 - fence && *v <= q3 + fence)
+}
@@ -0,0 +1,182 @@
+//! Native decoding of the binary logcat format (`logcat -B`). Each entry is
+//! a little-endian `logger_entry` header followed by its payload, so tags
+//! and messages need no re-parsing from text and keep the kernel's
+//! nanosecond timestamps.
+
+use std::collections::VecDeque;
+use std::io::{self, BufReader, Read};
+
+use crate::pipeline::LineSource;
+
+/// Header size of v1 entries, which report `hdr_size` as 0.
+const V1_HEADER_SIZE: usize = 20;
+/// Log buffers (events, stats, security) whose payload is a binary event
+/// rather than priority, tag and message. Crash (4) and kernel (7) are text.
+const BINARY_LOG_IDS: [u32; 3] = [2, 5, 6];
+
+pub struct LogEntry {
+    pub pid: i32,
+    pub sec: u32,
+    pub nsec: u32,
+    pub priority: u8,
+    pub tag: String,
+    pub message: String,
+}
+
+impl LogEntry {
+    fn from_parts(header: &[u8], payload: &[u8]) -> LogEntry {
+        let u32_at = |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+        // v3 and later headers carry the log buffer id; v2 (Android 4.x) had
+        // the euid there, which is never one of the binary buffer ids.
+        let log_id = (header.len() > V1_HEADER_SIZE).then(|| u32_at(20));
+        let mut entry = LogEntry {
+            pid: u32_at(4) as i32,
+            sec: u32_at(12),
+            nsec: u32_at(16),
+            priority: 0,
+            tag: String::new(),
+            message: String::new(),
+        };
+        if log_id.is_some_and(|id| BINARY_LOG_IDS.contains(&id)) && payload.len() >= 4 {
+            entry.priority = 4;
+            entry.tag = format!("event_{}", u32::from_le_bytes(payload[..4].try_into().unwrap()));
+            entry.message = format!("{} bytes of event data", payload.len() - 4);
+        } else if let Some((&priority, rest)) = payload.split_first() {
+            let mut fields = rest.splitn(2, |b| *b == 0);
+            entry.priority = priority;
+            entry.tag = String::from_utf8_lossy(fields.next().unwrap_or_default()).into_owned();
+            let message = fields.next().unwrap_or_default();
+            let end = message.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+            entry.message = String::from_utf8_lossy(&message[..end]).into_owned();
+        }
+        entry
+    }
+
+    pub fn priority_char(&self) -> char {
+        match self.priority {
+            2 => 'V',
+            3 => 'D',
+            4 => 'I',
+            5 => 'W',
+            6 => 'E',
+            7 => 'F',
+            8 => 'S',
+            _ => '?',
+        }
+    }
+
+    /// Renders the entry like `logcat -v time`, one line per message line,
+    /// but with nanosecond instead of millisecond timestamps.
+    pub fn to_lines(&self) -> Vec<String> {
+        let time = chrono::DateTime::from_timestamp(self.sec as i64, self.nsec)
+            .map(|t| t.with_timezone(&chrono::Local).format("%m-%d %H:%M:%S%.9f").to_string())
+            .unwrap_or_default();
+        let message = self.message.trim_end_matches('\n');
+        message
+            .split('\n')
+            .map(|text| format!("{} {}/{}({:>5}): {}\n", time, self.priority_char(), self.tag, self.pid, text))
+            .collect()
+    }
+}
+
+pub struct EntryReader<R> {
+    reader: BufReader<R>,
+    lines: VecDeque<Vec<u8>>,
+}
+
+impl<R: Read> EntryReader<R> {
+    pub fn new(input: R) -> Self {
+        EntryReader { reader: BufReader::new(input), lines: VecDeque::new() }
+    }
+
+    /// The next entry, or None at the end of input. A truncated final entry
+    /// (e.g. logcat killed mid-write) also ends the stream.
+    pub fn next_entry(&mut self) -> io::Result<Option<LogEntry>> {
+        let mut header = vec![0; 4];
+        if !self.fill(&mut header)? {
+            return Ok(None);
+        }
+        let payload_len = u16::from_le_bytes([header[0], header[1]]) as usize;
+        let header_len = match u16::from_le_bytes([header[2], header[3]]) as usize {
+            0 => V1_HEADER_SIZE,
+            len if len < V1_HEADER_SIZE => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Invalid logcat entry header size {}", len)));
+            }
+            len => len,
+        };
+        header.resize(header_len, 0);
+        let mut payload = vec![0; payload_len];
+        if !self.fill(&mut header[4..])? || !self.fill(&mut payload)? {
+            return Ok(None);
+        }
+        Ok(Some(LogEntry::from_parts(&header, &payload)))
+    }
+
+    /// Reads exactly `buf.len()` bytes; false on end of input.
+    fn fill(&mut self, buf: &mut [u8]) -> io::Result<bool> {
+        match self.reader.read_exact(buf) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<R: Read> LineSource for EntryReader<R> {
+    fn next_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        while self.lines.is_empty() {
+            let Some(entry) = self.next_entry()? else {
+                return Ok(None);
+            };
+            self.lines.extend(entry.to_lines().into_iter().map(String::into_bytes));
+        }
+        Ok(self.lines.pop_front())
+    }
+
+    fn has_buffered(&self) -> bool {
+        !self.lines.is_empty() || !self.reader.buffer().is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A v4 entry: payload length, header size, pid, tid, sec, nsec, log id, uid.
+    fn entry(log_id: u32, payload: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend((payload.len() as u16).to_le_bytes());
+        bytes.extend(28u16.to_le_bytes());
+        for field in [1234u32, 1250, 1_700_000_000, 5, log_id, 10_010] {
+            bytes.extend(field.to_le_bytes());
+        }
+        bytes.extend(payload);
+        bytes
+    }
+
+    #[test]
+    fn decodes_crash_buffer_entries_as_text() {
+        let data = entry(4, b"\x06AndroidRuntime\0FATAL EXCEPTION: main\n\0");
+        let entry = EntryReader::new(&data[..]).next_entry().unwrap().unwrap();
+        assert_eq!(entry.pid, 1234);
+        assert_eq!(entry.priority_char(), 'E');
+        assert_eq!(entry.tag, "AndroidRuntime");
+        assert_eq!(entry.message, "FATAL EXCEPTION: main\n");
+        assert!(entry.to_lines()[0].ends_with(" E/AndroidRuntime( 1234): FATAL EXCEPTION: main\n"));
+    }
+
+    #[test]
+    fn decodes_events_entries_as_binary() {
+        let mut payload = 30_014u32.to_le_bytes().to_vec();
+        payload.extend([0x03, 1, 2, 3, 4, 5, 6, 7, 8]);
+        let entry = EntryReader::new(&entry(2, &payload)[..]).next_entry().unwrap().unwrap();
+        assert_eq!(entry.tag, "event_30014");
+        assert_eq!(entry.message, "9 bytes of event data");
+    }
+
+    #[test]
+    fn truncated_entry_ends_the_stream() {
+        let data = entry(0, b"\x04Tag\0message\0");
+        assert!(EntryReader::new(&data[..data.len() - 3]).next_entry().unwrap().is_none());
+    }
+}
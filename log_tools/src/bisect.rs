@@ -0,0 +1,64 @@
+//! Binary search for the first build whose measurement exceeds a
+//! threshold. Builds are ordered oldest to newest and are either APK
+//! paths or revisions turned into APKs by a build command.
+
+use std::process::Command;
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+
+use crate::console;
+
+#[derive(Serialize)]
+pub struct Measurement {
+    pub build: String,
+    /// Median of the build's runs, in the metric's unit.
+    pub value: u64,
+    pub exceeds: bool,
+}
+
+#[derive(Serialize)]
+pub struct BisectReport {
+    pub first_exceeding: Option<String>,
+    /// In the order measured.
+    pub measurements: Vec<Measurement>,
+}
+
+/// Index of the first build for which `exceeds` is true, assuming every
+/// later build exceeds too. None when even the newest build is fine.
+pub fn first_exceeding(len: usize, mut exceeds: impl FnMut(usize) -> Result<bool>) -> Result<Option<usize>> {
+    if len == 0 || !exceeds(len - 1)? {
+        return Ok(None);
+    }
+    if exceeds(0)? {
+        return Ok(Some(0));
+    }
+    // Invariant: `good` does not exceed, `bad` does.
+    let (mut good, mut bad) = (0, len - 1);
+    while bad - good > 1 {
+        let mid = good + (bad - good) / 2;
+        if exceeds(mid)? {
+            bad = mid;
+        } else {
+            good = mid;
+        }
+    }
+    Ok(Some(bad))
+}
+
+/// Runs `build_cmd` with `{rev}` replaced by `revision` through the host
+/// shell; the last line of its stdout names the APK.
+pub fn build_apk(build_cmd: &str, revision: &str) -> Result<String> {
+    let command = build_cmd.replace("{rev}", revision);
+    let (shell, flag) = if cfg!(windows) { ("cmd", "/C") } else { ("sh", "-c") };
+    let output = Command::new(shell).args([flag, &command]).output()?;
+    if !output.status.success() {
+        return Err(anyhow!("Build command failed for {}: {}", revision, console::decode(&output.stderr).trim()));
+    }
+    console::decode(&output.stdout)
+        .lines()
+        .map(str::trim)
+        .rfind(|line| !line.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| anyhow!("Build command printed no APK path for {}", revision))
+}
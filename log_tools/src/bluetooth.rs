@@ -0,0 +1,109 @@
+//! BLE scan and GATT activity of the target app from
+//! `dumpsys bluetooth_manager` (AppScanStats and GATT client map).
+
+use serde::Serialize;
+
+use crate::clocksync::SampleTime;
+
+#[derive(Clone, Default, Serialize)]
+pub struct BluetoothSnapshot {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    pub registered: bool,
+    pub scans_started: u64,
+    pub scans_stopped: u64,
+    pub total_scan_ms: u64,
+    pub unfiltered_scans: u64,
+    /// One entry per ongoing scan, e.g. "Low Latency Unfiltered".
+    pub ongoing_scans: Vec<String>,
+    pub gatt_clients: u64,
+    pub gatt_connections: u64,
+}
+
+impl BluetoothSnapshot {
+    pub fn has_unfiltered_ongoing_scan(&self) -> bool {
+        self.ongoing_scans.iter().any(|scan| !scan.contains("Filter"))
+    }
+}
+
+fn value_after_colon(line: &str) -> &str {
+    line.split_once(':').map_or("", |(_, value)| value.trim())
+}
+
+fn numbers(value: &str) -> Vec<u64> {
+    value.split('/').filter_map(|v| v.trim().parse().ok()).collect()
+}
+
+pub fn parse_bluetooth_manager(output: &str, package: &str, time: SampleTime) -> BluetoothSnapshot {
+    let mut snap = BluetoothSnapshot { time, ..Default::default() };
+    let lines: Vec<&str> = output.lines().collect();
+
+    // AppScanStats block: "  com.example.app (Registered)" followed by
+    // "  Label   : value" lines until the next blank line.
+    if let Some(start) = lines.iter().position(|line| {
+        let trimmed = line.trim();
+        trimmed == package || trimmed.strip_prefix(package).is_some_and(|rest| rest.starts_with(" ("))
+    }) {
+        snap.registered = lines[start].contains("(Registered)");
+        let mut in_ongoing = false;
+        for line in &lines[start + 1..] {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                break;
+            }
+            if in_ongoing && !trimmed.contains(" : ") {
+                snap.ongoing_scans.push(scan_description(trimmed));
+                continue;
+            }
+            in_ongoing = false;
+            if trimmed.starts_with("LE scans (started/stopped)") {
+                let n = numbers(value_after_colon(trimmed));
+                snap.scans_started = n.first().copied().unwrap_or(0);
+                snap.scans_stopped = n.get(1).copied().unwrap_or(0);
+            } else if trimmed.starts_with("Scan time in ms") {
+                snap.total_scan_ms = numbers(value_after_colon(trimmed)).last().copied().unwrap_or(0);
+            } else if trimmed.starts_with("Unfiltered scans") {
+                snap.unfiltered_scans = value_after_colon(trimmed).parse().unwrap_or(0);
+            } else if trimmed.starts_with("Ongoing scans") {
+                in_ongoing = true;
+            }
+        }
+    }
+
+    // GATT client registrations mention the owning package; a following
+    // "Connections: N" line counts its open connections.
+    let mut in_gatt_clients = false;
+    let mut ours = false;
+    for line in &lines {
+        let trimmed = line.trim();
+        if trimmed.starts_with("GATT Client Map") {
+            in_gatt_clients = true;
+            continue;
+        }
+        if in_gatt_clients && (trimmed.starts_with("GATT Server Map") || trimmed.is_empty()) {
+            in_gatt_clients = false;
+            continue;
+        }
+        if !in_gatt_clients {
+            continue;
+        }
+        if trimmed.starts_with("Application ID") {
+            ours = false;
+        }
+        if trimmed.contains(package) && !ours {
+            ours = true;
+            snap.gatt_clients += 1;
+        }
+        if ours && trimmed.starts_with("Connections") {
+            snap.gatt_connections += value_after_colon(trimmed).parse().unwrap_or(0);
+        }
+    }
+    snap
+}
+
+/// Reduces an ongoing-scan line to its mode and filter keywords.
+fn scan_description(line: &str) -> String {
+    let keywords = ["Low Power", "Balanced", "Low Latency", "Ambient Discovery", "Opp", "Batch", "Filter", "Unfiltered"];
+    let found: Vec<&str> = keywords.iter().filter(|k| line.contains(*k)).copied().collect();
+    if found.is_empty() { line.to_string() } else { found.join(" ") }
+}
@@ -0,0 +1,123 @@
+//! Boot-time analysis: `boot_progress_*` milestones from the events log
+//! buffer and init stages from the kernel log, all in milliseconds of
+//! uptime, plus the app's first cold start once boot completed.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+// "10-16 12:00:05.123 I/boot_progress_start(    0): 5432"; the value is
+// uptime in milliseconds.
+static EVENT_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"[A-Z]/(boot_progress_\w+|sf_stop_bootanim|wm_boot_animation_done)\(\s*\d+\): (\d+)").unwrap()
+});
+// "[    2.345678] init: init first stage started!"
+static DMESG_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\[\s*(\d+)\.(\d{6})\] (.*)$").unwrap());
+
+/// Kernel log lines marking the hand-over points of early boot.
+const KERNEL_MILESTONES: [(&str, &str); 3] = [
+    ("kernel_init_done", "Freeing unused kernel"),
+    ("init_first_stage", "init first stage started"),
+    ("init_second_stage", "init second stage started"),
+];
+
+#[derive(Serialize)]
+pub struct BootMilestone {
+    pub name: String,
+    pub uptime_ms: u64,
+}
+
+#[derive(Serialize)]
+pub struct BootReport {
+    pub milestones: Vec<BootMilestone>,
+    pub kernel: Vec<BootMilestone>,
+    /// Uptime of the last milestone, normally the end of the boot animation.
+    pub total_boot_ms: Option<u64>,
+    /// `am start -W` total time of the first launch after boot.
+    pub first_launch_ms: Option<u64>,
+}
+
+/// First occurrence of each boot milestone in `logcat -b events -v time`
+/// output, in uptime order.
+pub fn parse_boot_events(output: &str) -> Vec<BootMilestone> {
+    let mut milestones: Vec<BootMilestone> = Vec::new();
+    for caps in output.lines().filter_map(|line| EVENT_REGEX.captures(line)) {
+        if milestones.iter().any(|m| m.name == caps[1]) {
+            continue;
+        }
+        if let Ok(uptime_ms) = caps[2].parse() {
+            milestones.push(BootMilestone { name: caps[1].to_string(), uptime_ms });
+        }
+    }
+    milestones.sort_by_key(|m| m.uptime_ms);
+    milestones
+}
+
+pub fn parse_dmesg(output: &str) -> Vec<BootMilestone> {
+    let mut milestones = Vec::new();
+    for (name, needle) in KERNEL_MILESTONES {
+        let found = output.lines().filter(|line| line.contains(needle)).find_map(|line| DMESG_REGEX.captures(line));
+        if let Some(caps) = found {
+            let secs: u64 = caps[1].parse().unwrap_or(0);
+            let micros: u64 = caps[2].parse().unwrap_or(0);
+            milestones.push(BootMilestone { name: name.to_string(), uptime_ms: secs * 1000 + micros / 1000 });
+        }
+    }
+    milestones.sort_by_key(|m| m.uptime_ms);
+    milestones
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(milestones: &[BootMilestone]) -> Vec<(&str, u64)> {
+        milestones.iter().map(|m| (m.name.as_str(), m.uptime_ms)).collect()
+    }
+
+    #[test]
+    fn first_boot_events_in_uptime_order() {
+        let events = "\
+--------- beginning of events
+10-16 12:00:05.123 I/boot_progress_start(    0): 5432
+10-16 12:00:06.020 I/boot_progress_preload_start(  612): 6311
+10-16 12:00:07.845 I/boot_progress_preload_end(  612): 8136
+10-16 12:00:08.101 I/am_proc_start( 1021): [0,1190,10082,com.android.phone,added application,com.android.phone]
+10-16 12:00:08.420 I/boot_progress_system_run( 1021): 8711
+10-16 12:00:12.003 I/boot_progress_ams_ready( 1021): 12294
+10-16 12:00:14.880 I/boot_progress_enable_screen( 1021): 15171
+10-16 12:00:17.302 I/sf_stop_bootanim(  523): 17593
+10-16 12:00:17.410 I/wm_boot_animation_done( 1021): 17701
+10-16 12:05:00.000 I/boot_progress_enable_screen( 1021): 300291
+";
+        assert_eq!(
+            names(&parse_boot_events(events)),
+            [
+                ("boot_progress_start", 5432),
+                ("boot_progress_preload_start", 6311),
+                ("boot_progress_preload_end", 8136),
+                ("boot_progress_system_run", 8711),
+                ("boot_progress_ams_ready", 12294),
+                ("boot_progress_enable_screen", 15171),
+                ("sf_stop_bootanim", 17593),
+                ("wm_boot_animation_done", 17701),
+            ]
+        );
+    }
+
+    #[test]
+    fn kernel_milestones_from_dmesg() {
+        let dmesg = "\
+[    0.000000] Booting Linux on physical CPU 0x0000000000 [0x51df805e]
+[    0.912345] Freeing unused kernel memory: 6016K
+[    0.913002] Run /init as init process
+[    0.935871] init: init first stage started!
+[    2.104558] init: init second stage started!
+[    2.300112] init: Loading SELinux policy
+";
+        assert_eq!(
+            names(&parse_dmesg(dmesg)),
+            [("kernel_init_done", 912), ("init_first_stage", 935), ("init_second_stage", 2104)]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! Memory budgets checked at the end of a run. All limits are in KB:
+//!
+//! ```json
+//! {
+//!     "total_pss": 300000, "native_heap": 120000, "graphics": 60000,
+//!     "libraries": { "libffmpeg*.so": 20000 },
+//!     "owners": { "MediaTeam": 40000 }
+//! }
+//! ```
+//!
+//! Category limits apply to the peak over the run; library limits to the
+//! summed PSS of the libraries matching the glob, matched as in ownership
+//! maps; owner limits need an ownership map (`--so-owners`). A budget the
+//! run has no data for fails, so a renamed library cannot pass unseen.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::console;
+use crate::owners::{self, OwnerMap};
+use crate::{MemorySample, SoMemoryInfo};
+
+type CategoryFn = fn(&MemorySample) -> u64;
+
+#[derive(Default, Deserialize)]
+pub struct Budgets {
+    pub total_pss: Option<u64>,
+    pub native_heap: Option<u64>,
+    pub dalvik_heap: Option<u64>,
+    pub code: Option<u64>,
+    pub stack: Option<u64>,
+    pub graphics: Option<u64>,
+    pub private_dirty: Option<u64>,
+    #[serde(default)]
+    pub libraries: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub owners: BTreeMap<String, u64>,
+}
+
+#[derive(Serialize)]
+pub struct BudgetCheck {
+    pub item: String,
+    pub limit: u64,
+    /// None when the run collected no data for the item, which fails.
+    pub actual: Option<u64>,
+    pub pass: bool,
+}
+
+impl BudgetCheck {
+    fn new(item: &str, limit: u64, actual: Option<u64>) -> Self {
+        BudgetCheck { item: item.to_string(), limit, actual, pass: actual.is_some_and(|a| a <= limit) }
+    }
+}
+
+pub fn load_budgets(path: &str) -> Result<Budgets> {
+    Ok(serde_json::from_str(&console::read_text(path)?)?)
+}
+
+impl Budgets {
+    pub fn needs_libraries(&self) -> bool {
+        !self.libraries.is_empty() || !self.owners.is_empty()
+    }
+
+    pub fn check_samples(&self, samples: &[MemorySample]) -> Vec<BudgetCheck> {
+        let categories: [(&str, Option<u64>, CategoryFn); 7] = [
+            ("total_pss", self.total_pss, |s| s.total_pss),
+            ("native_heap", self.native_heap, |s| s.native_heap),
+            ("dalvik_heap", self.dalvik_heap, |s| s.dalvik_heap),
+            ("code", self.code, |s| s.code),
+            ("stack", self.stack, |s| s.stack),
+            ("graphics", self.graphics, |s| s.graphics),
+            ("private_dirty", self.private_dirty, |s| s.private_dirty),
+        ];
+        categories
+            .iter()
+            .filter_map(|(name, limit, value)| limit.map(|limit| BudgetCheck::new(name, limit, samples.iter().map(value).max())))
+            .collect()
+    }
+
+    pub fn check_libraries(&self, libs: &[SoMemoryInfo], owner_map: Option<&OwnerMap>) -> Result<Vec<BudgetCheck>> {
+        let mut checks = Vec::new();
+        for (pattern, limit) in &self.libraries {
+            let matching: Vec<&SoMemoryInfo> = libs
+                .iter()
+                .filter(|lib| owners::library_match(pattern, &lib.name))
+                .collect();
+            let actual = (!matching.is_empty()).then(|| matching.iter().map(|lib| lib.pss).sum());
+            checks.push(BudgetCheck::new(pattern, *limit, actual));
+        }
+        if !self.owners.is_empty() {
+            let owner_map = owner_map.ok_or_else(|| anyhow!("Owner budgets need an ownership map (--so-owners)"))?;
+            let totals = owner_map.totals(libs);
+            for (owner, limit) in &self.owners {
+                let actual = totals.iter().find(|t| &t.owner == owner).map(|t| t.pss);
+                checks.push(BudgetCheck::new(&format!("owner {}", owner), *limit, actual));
+            }
+        }
+        Ok(checks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lib(name: &str, pss: u64) -> SoMemoryInfo {
+        SoMemoryInfo { name: name.to_string(), pss, private_dirty: 0, shared_dirty: 0 }
+    }
+
+    #[test]
+    fn missing_data_fails() {
+        let budgets = Budgets { total_pss: Some(1000), libraries: BTreeMap::from([("libgone.so".to_string(), 10)]), ..Default::default() };
+        let checks = budgets.check_samples(&[]);
+        assert_eq!(checks[0].actual, None);
+        assert!(!checks[0].pass);
+        let checks = budgets.check_libraries(&[lib("/system/lib64/libc.so", 5)], None).unwrap();
+        assert!(!checks[0].pass);
+    }
+
+    #[test]
+    fn library_globs_with_a_slash_match_the_path() {
+        let budgets = Budgets { libraries: BTreeMap::from([("/vendor/*".to_string(), 100), ("libc.so".to_string(), 100)]), ..Default::default() };
+        let libs = [lib("/vendor/lib64/libgpu.so", 60), lib("/vendor/lib64/libcam.so", 60), lib("/system/lib64/libc.so", 30)];
+        let checks = budgets.check_libraries(&libs, None).unwrap();
+        let vendor = checks.iter().find(|c| c.item == "/vendor/*").unwrap();
+        assert_eq!((vendor.actual, vendor.pass), (Some(120), false));
+        let libc = checks.iter().find(|c| c.item == "libc.so").unwrap();
+        assert_eq!((libc.actual, libc.pass), (Some(30), true));
+    }
+}
@@ -0,0 +1,139 @@
+//! Session bundles: the artifacts of a session directory, or the files
+//! one run wrote, zipped together with a manifest of SHA-256 checksums,
+//! plus a checksum of the manifest.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use zip::write::SimpleFileOptions;
+
+use crate::naming;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const MANIFEST_CHECKSUM_FILE: &str = "manifest.sha256";
+
+/// File types sessions write: plots, reports, captures (compressed too), heap
+/// dumps and the --db and --parquet sinks.
+const ARTIFACT_EXTENSIONS: &[&str] =
+    &["png", "svg", "csv", "json", "jsonl", "txt", "log", "xml", "html", "pb", "gz", "zst", "hprof", "db", "sqlite", "parquet"];
+/// Directories whose whole contents are artifacts regardless of extension.
+const ARTIFACT_DIRS: &[&str] = &["anr_traces"];
+
+#[derive(Serialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Serialize)]
+pub struct Manifest {
+    pub tool_version: String,
+    pub created: String,
+    pub package: String,
+    pub files: Vec<ManifestEntry>,
+}
+
+/// Artifact paths under `dir`, relative to it and sorted. Hidden entries
+/// and build output are skipped.
+pub fn collect_artifacts(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    collect_into(dir, Path::new(""), false, &mut found)?;
+    found.sort();
+    Ok(found)
+}
+
+/// The files this run wrote, relative to the working directory, leaving
+/// out `exclude` (the config file, the bundle being written) and files
+/// outside the working directory.
+pub fn session_artifacts(exclude: &[&Path]) -> Result<Vec<PathBuf>> {
+    let cwd = std::fs::canonicalize(std::env::current_dir()?)?;
+    let excluded: Vec<PathBuf> = exclude.iter().filter_map(|path| std::fs::canonicalize(path).ok()).collect();
+    let mut found = Vec::new();
+    for name in naming::written_files() {
+        let Ok(path) = std::fs::canonicalize(&name) else {
+            continue;
+        };
+        if excluded.contains(&path) {
+            continue;
+        }
+        if let Ok(relative) = path.strip_prefix(&cwd) {
+            found.push(relative.to_path_buf());
+        }
+    }
+    Ok(found)
+}
+
+fn collect_into(root: &Path, relative: &Path, whole_dir: bool, found: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(root.join(relative))? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || name == "target" {
+            continue;
+        }
+        let path = relative.join(&name);
+        if entry.file_type()?.is_dir() {
+            collect_into(root, &path, whole_dir || ARTIFACT_DIRS.contains(&name.as_str()), found)?;
+        } else if whole_dir || is_artifact_name(&name) {
+            found.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_artifact_name(name: &str) -> bool {
+    if name == MANIFEST_FILE || name == MANIFEST_CHECKSUM_FILE {
+        return false;
+    }
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ARTIFACT_EXTENSIONS.contains(&ext))
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn build_manifest(dir: &Path, files: &[PathBuf], package: &str) -> Result<Manifest> {
+    let mut entries = Vec::with_capacity(files.len());
+    for path in files {
+        let mut bytes = Vec::new();
+        File::open(dir.join(path))?.read_to_end(&mut bytes)?;
+        entries.push(ManifestEntry {
+            path: path.to_string_lossy().replace('\\', "/"),
+            size: bytes.len() as u64,
+            sha256: sha256_hex(&bytes),
+        });
+    }
+    Ok(Manifest {
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        created: chrono::Local::now().to_rfc3339(),
+        package: package.to_string(),
+        files: entries,
+    })
+}
+
+/// Writes the zip and returns the manifest checksum.
+pub fn write_bundle(dir: &Path, manifest: &Manifest, output: &Path) -> Result<String> {
+    let mut zip = zip::ZipWriter::new(File::create(output)?);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for entry in &manifest.files {
+        zip.start_file(entry.path.as_str(), options)?;
+        let mut bytes = Vec::new();
+        File::open(dir.join(&entry.path))?.read_to_end(&mut bytes)?;
+        zip.write_all(&bytes)?;
+    }
+    let manifest_json = crate::schema::to_versioned_json("bundle_manifest", manifest)?;
+    let checksum = sha256_hex(manifest_json.as_bytes());
+    zip.start_file(MANIFEST_FILE, options)?;
+    zip.write_all(manifest_json.as_bytes())?;
+    zip.start_file(MANIFEST_CHECKSUM_FILE, options)?;
+    writeln!(zip, "{}  {}", checksum, MANIFEST_FILE)?;
+    zip.finish()?;
+    Ok(checksum)
+}
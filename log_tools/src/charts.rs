@@ -0,0 +1,255 @@
+//! `--chart-format interactive`: the memory plot as an HTML page instead
+//! of a PNG, for long runs where the static image is too dense to read.
+//! Drag across the chart to zoom into a time range and double-click to
+//! zoom out; hovering shows the exact values at that time, and clicking a
+//! legend entry hides or shows its series, the y axis fitting whatever is
+//! visible. Data and script are inline, so the page works offline and can
+//! be attached to a ticket as is.
+
+use anyhow::{Result, anyhow};
+use plotters::style::RGBColor;
+use serde::{Deserialize, Serialize};
+
+use crate::htmlreport::escape;
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChartFormat {
+    #[default]
+    Png,
+    Interactive,
+}
+
+impl ChartFormat {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "png" => Ok(ChartFormat::Png),
+            "interactive" => Ok(ChartFormat::Interactive),
+            other => Err(anyhow!("Unknown chart format {:?}, expected png or interactive", other)),
+        }
+    }
+}
+
+/// Where the interactive version of the plot `image` goes: the same name
+/// with an `.html` extension.
+pub fn chart_file(image: &str) -> String {
+    let stem = image.rsplit_once('.').filter(|(_, ext)| !ext.contains('/')).map_or(image, |(stem, _)| stem);
+    format!("{}.html", stem)
+}
+
+#[derive(Serialize)]
+pub struct ChartSeries {
+    pub name: String,
+    /// Appended to the values in the hover box.
+    pub unit: String,
+    pub color: String,
+    pub points: Vec<(f64, f64)>,
+}
+
+#[derive(Serialize)]
+pub struct Chart {
+    pub title: String,
+    pub x_label: String,
+    pub y_label: String,
+    pub series: Vec<ChartSeries>,
+    /// Vertical lines with a label, at x.
+    pub marks: Vec<(f64, String)>,
+    /// Shaded x ranges.
+    pub shaded: Vec<(f64, f64)>,
+    /// x ranges without samples; lines are not drawn across them.
+    pub gaps: Vec<(f64, f64)>,
+    pub background: String,
+    pub foreground: String,
+    pub grid: String,
+    pub shade: String,
+}
+
+pub fn css_color(color: &RGBColor) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.0, color.1, color.2)
+}
+
+pub fn render(chart: &Chart) -> Result<String> {
+    // "</" would end the script element early.
+    let data = serde_json::to_string(chart)?.replace("</", "<\\/");
+    Ok(format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{title}</title><style>{style}</style></head>\
+         <body style=\"background:{bg};color:{fg}\">\n<h1>{title}</h1>\n<div id=\"legend\"></div>\n\
+         <div id=\"wrap\"><svg id=\"chart\" viewBox=\"0 0 1200 600\"></svg><div id=\"tip\"></div></div>\n\
+         <p class=\"help\">Drag to zoom, double-click to zoom out, click a legend entry to hide or show it.</p>\n\
+         <script>const DATA = {data};\n{script}</script>\n</body></html>\n",
+        title = escape(&chart.title),
+        style = STYLE,
+        bg = chart.background,
+        fg = chart.foreground,
+        data = data,
+        script = SCRIPT,
+    ))
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:1.5em}\
+#wrap{position:relative}\
+svg{width:100%;height:auto;user-select:none;cursor:crosshair}\
+#legend span{display:inline-block;margin:0 1em .5em 0;cursor:pointer}\
+#legend span.off{opacity:.35}\
+#legend i{display:inline-block;width:1.5em;height:3px;margin-right:.4em;vertical-align:middle}\
+#tip{position:absolute;display:none;pointer-events:none;padding:4px 8px;font-size:13px;\
+border:1px solid #888;background:rgba(255,255,255,.92);color:#222;white-space:nowrap}\
+.help{font-size:13px;opacity:.7}";
+
+const SCRIPT: &str = r#"
+const W = 1200, H = 600, L = 80, R = 20, T = 20, B = 45;
+const svg = document.getElementById('chart');
+const tip = document.getElementById('tip');
+const hidden = new Set();
+let view = null, drag = null;
+
+const allX = DATA.series.flatMap(s => s.points.map(p => p[0]));
+const full = [Math.min(...allX, 0), Math.max(...allX, 1)];
+
+function el(name, attrs, text) {
+  const e = document.createElementNS('http://www.w3.org/2000/svg', name);
+  for (const k in attrs) e.setAttribute(k, attrs[k]);
+  if (text !== undefined) e.textContent = text;
+  return e;
+}
+
+function fmt(v) {
+  return Math.abs(v) >= 100 ? Math.round(v).toLocaleString() : (+v.toFixed(2)).toLocaleString();
+}
+
+function ticks(lo, hi, n) {
+  const step0 = (hi - lo) / n || 1;
+  const mag = Math.pow(10, Math.floor(Math.log10(step0)));
+  const step = [1, 2, 5, 10].map(m => m * mag).find(s => s >= step0);
+  const out = [];
+  for (let v = Math.ceil(lo / step) * step; v <= hi + step * 1e-9; v += step) out.push(v);
+  return out;
+}
+
+function visible() {
+  return DATA.series.filter(s => !hidden.has(s.name));
+}
+
+function range() {
+  const [x0, x1] = view || full;
+  let y1 = 0;
+  for (const s of visible()) for (const [x, y] of s.points) if (x >= x0 && x <= x1 && y > y1) y1 = y;
+  return [x0, x1 > x0 ? x1 : x0 + 1, 0, (y1 || 1) * 1.1];
+}
+
+function sx(x, r) { return L + (x - r[0]) / (r[1] - r[0]) * (W - L - R); }
+function sy(y, r) { return H - B - (y - r[2]) / (r[3] - r[2]) * (H - T - B); }
+function dataX(px, r) { return r[0] + (px - L) / (W - L - R) * (r[1] - r[0]); }
+
+function draw() {
+  const r = range();
+  svg.replaceChildren();
+  svg.appendChild(el('defs', {})).appendChild(el('clipPath', {id: 'plot'}))
+    .appendChild(el('rect', {x: L, y: T, width: W - L - R, height: H - T - B}));
+  for (const [a, b] of DATA.shaded) {
+    svg.appendChild(el('rect', {x: sx(a, r), y: T, width: Math.max(sx(b, r) - sx(a, r), 1), height: H - T - B,
+      fill: DATA.shade, opacity: 0.4, 'clip-path': 'url(#plot)'}));
+  }
+  for (const v of ticks(r[2], r[3], 6)) {
+    svg.appendChild(el('line', {x1: L, x2: W - R, y1: sy(v, r), y2: sy(v, r), stroke: DATA.grid}));
+    svg.appendChild(el('text', {x: L - 6, y: sy(v, r) + 4, 'text-anchor': 'end', 'font-size': 12, fill: DATA.foreground}, fmt(v)));
+  }
+  for (const v of ticks(r[0], r[1], 10)) {
+    svg.appendChild(el('line', {x1: sx(v, r), x2: sx(v, r), y1: T, y2: H - B, stroke: DATA.grid}));
+    svg.appendChild(el('text', {x: sx(v, r), y: H - B + 16, 'text-anchor': 'middle', 'font-size': 12, fill: DATA.foreground}, fmt(v)));
+  }
+  svg.appendChild(el('text', {x: (L + W - R) / 2, y: H - 6, 'text-anchor': 'middle', 'font-size': 13, fill: DATA.foreground}, DATA.x_label));
+  svg.appendChild(el('text', {x: 14, y: (T + H - B) / 2, 'text-anchor': 'middle', 'font-size': 13, fill: DATA.foreground,
+    transform: `rotate(-90 14 ${(T + H - B) / 2})`}, DATA.y_label));
+  for (const [x, label] of DATA.marks) {
+    if (x < r[0] || x > r[1]) continue;
+    svg.appendChild(el('line', {x1: sx(x, r), x2: sx(x, r), y1: T, y2: H - B, stroke: DATA.foreground, opacity: 0.5}));
+    svg.appendChild(el('text', {x: sx(x, r) + 3, y: T + 12, 'font-size': 12, fill: DATA.foreground}, label));
+  }
+  for (const s of visible()) {
+    const pts = s.points.filter((p, i, a) => (p[0] >= r[0] || (a[i + 1] && a[i + 1][0] >= r[0])) && (p[0] <= r[1] || (i > 0 && a[i - 1][0] <= r[1])));
+    const runs = [[]];
+    pts.forEach((p, i) => {
+      if (i > 0 && DATA.gaps.some(([, g1]) => pts[i - 1][0] < g1 && g1 <= p[0])) runs.push([]);
+      runs[runs.length - 1].push(p);
+    });
+    for (const run of runs) {
+      svg.appendChild(el('polyline', {points: run.map(p => `${sx(p[0], r)},${sy(p[1], r)}`).join(' '), fill: 'none',
+        stroke: s.color, 'stroke-width': 2, 'clip-path': 'url(#plot)'}));
+    }
+  }
+  svg.appendChild(el('line', {id: 'cursor', y1: T, y2: H - B, stroke: DATA.foreground, visibility: 'hidden'}));
+  svg.appendChild(el('rect', {id: 'zoom', y: T, height: H - T - B, fill: DATA.foreground, opacity: 0.15, visibility: 'hidden'}));
+}
+
+function legend() {
+  const div = document.getElementById('legend');
+  for (const s of DATA.series) {
+    const span = document.createElement('span');
+    span.innerHTML = `<i style="background:${s.color}"></i>`;
+    span.appendChild(document.createTextNode(s.name));
+    span.onclick = () => {
+      hidden.has(s.name) ? hidden.delete(s.name) : hidden.add(s.name);
+      span.classList.toggle('off');
+      draw();
+    };
+    div.appendChild(span);
+  }
+}
+
+function nearest(points, x) {
+  let lo = 0, hi = points.length - 1;
+  while (lo < hi) {
+    const mid = (lo + hi) >> 1;
+    if (points[mid][0] < x) lo = mid + 1; else hi = mid;
+  }
+  if (lo > 0 && x - points[lo - 1][0] < points[lo][0] - x) lo--;
+  return points[lo];
+}
+
+function svgX(event) {
+  const rect = svg.getBoundingClientRect();
+  return Math.min(Math.max((event.clientX - rect.left) * W / rect.width, L), W - R);
+}
+
+svg.addEventListener('mousedown', e => { drag = svgX(e); });
+svg.addEventListener('mousemove', e => {
+  const r = range(), px = svgX(e);
+  const zoom = document.getElementById('zoom'), cursor = document.getElementById('cursor');
+  if (drag !== null) {
+    zoom.setAttribute('x', Math.min(drag, px));
+    zoom.setAttribute('width', Math.abs(px - drag));
+    zoom.setAttribute('visibility', 'visible');
+  }
+  const shown = visible().filter(s => s.points.length);
+  if (!shown.length) return;
+  const x = nearest(shown[0].points, dataX(px, r))[0];
+  cursor.setAttribute('x1', sx(x, r));
+  cursor.setAttribute('x2', sx(x, r));
+  cursor.setAttribute('visibility', 'visible');
+  tip.innerHTML = `<b>${fmt(x)} s</b>` + shown.map(s => {
+    const p = nearest(s.points, x);
+    return `<br><span style="color:${s.color}">&#9632;</span> ${s.name}: ${fmt(p[1])} ${s.unit}`;
+  }).join('');
+  const rect = svg.getBoundingClientRect();
+  const left = e.clientX - rect.left + 16;
+  tip.style.display = 'block';
+  tip.style.top = `${e.clientY - rect.top + 16}px`;
+  tip.style.left = `${Math.min(left, rect.width - tip.offsetWidth)}px`;
+});
+svg.addEventListener('mouseleave', () => {
+  tip.style.display = 'none';
+  document.getElementById('cursor').setAttribute('visibility', 'hidden');
+});
+window.addEventListener('mouseup', e => {
+  if (drag === null) return;
+  const r = range(), px = svgX(e);
+  if (Math.abs(px - drag) > 5) view = [dataX(Math.min(drag, px), r), dataX(Math.max(drag, px), r)];
+  drag = null;
+  draw();
+});
+svg.addEventListener('dblclick', () => { view = null; draw(); });
+
+legend();
+draw();
+"#;
@@ -0,0 +1,845 @@
+//! The command line and the options it sets on the config. Among its
+//! subcommands are the measurement ones (`logcat`, `memory`, `top-apps`,
+//! `threads`, `so-memory`, and `session` running the first, second and
+//! fourth at once), with their per-mode options and the run they select.
+//!
+//! The top-level `-m`, `--top-apps`, `-t` and `-s` flags they replace are
+//! still accepted for a release or two, with a deprecation warning; they
+//! can be combined in one run as before, but not with a subcommand. Each
+//! subcommand takes the options it uses, after its name, so the collector
+//! options (`--dmabuf`, `--native-libs`, ...) go with the sessions they
+//! collect for; only `--config`, `--serial`, `--json` and `--force` are
+//! global.
+
+use anyhow::Result;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgMatches, Command as ClapCommand};
+use log_tools::{LogAnalyzerConfig, bench, charts, guard, timeline};
+
+pub const MEMORY_PLOT_FILE: &str = "memory_plot.png";
+/// Memory and top-apps sessions without `--duration`.
+pub const DEFAULT_DURATION_SECS: u64 = 60;
+/// Subcommands that run a measurement session on the device.
+pub const SESSION_SUBCOMMANDS: &[&str] = &["logcat", "memory", "top-apps", "threads", "so-memory", "session"];
+
+fn duration_arg(help: &'static str) -> Arg {
+    Arg::new("duration").long("duration").value_name("SECS").value_parser(clap::value_parser!(u64).range(1..)).help(help)
+}
+
+fn interval_arg() -> Arg {
+    Arg::new("interval")
+        .long("interval")
+        .value_name("SECS")
+        .value_parser(clap::value_parser!(f64))
+        .help("Seconds between samples, fractions down to 0.1 allowed, instead of the config's sample_interval")
+}
+
+fn output_arg(value_name: &'static str, help: &'static str) -> Arg {
+    Arg::new("output").short('o').long("output").value_name(value_name).help(help)
+}
+
+/// The app and device a subcommand works on, and how it reads the
+/// device's output.
+pub fn target_args() -> Vec<Arg> {
+    let mut args = vec![package_arg(), Arg::new("user").long("user").value_name("ID").help("Target the app in this Android user or work profile instead of the current user").value_parser(clap::value_parser!(u32))];
+    args.extend(device_args());
+    args
+}
+
+/// How a subcommand without an app target talks to the device.
+pub fn device_args() -> Vec<Arg> {
+    vec![
+        Arg::new("sdk").long("sdk").value_name("API_LEVEL").help("Override the device API level used to select output parsers").value_parser(clap::value_parser!(u32)),
+        Arg::new("strict_parse").long("strict-parse").help("Abort on any unrecognized or unparsable device output instead of recording 0").action(clap::ArgAction::SetTrue),
+        Arg::new("one_shot_shell").long("one-shot-shell").help("Start a device shell for every command instead of keeping one open across samples").action(clap::ArgAction::SetTrue),
+    ]
+}
+
+pub fn package_arg() -> Arg {
+    Arg::new("package").short('p').long("package").value_name("PACKAGE").help("Target package name")
+}
+
+pub fn regex_arg() -> Arg {
+    Arg::new("regex").short('r').long("regex").value_name("REGEX").help("Keyword regex for log filtering")
+}
+
+/// The keyword regex and the logcat filter presets.
+pub fn filter_args() -> Vec<Arg> {
+    vec![
+        regex_arg(),
+        Arg::new("preset")
+            .long("preset")
+            .value_name("NAME")
+            .action(clap::ArgAction::Append)
+            .help("Logcat filter, repeatable: a preset from the config's presets, or a built-in tag filter: app-only drops framework tags, no-gms drops Play services tags, network keeps networking tags only"),
+    ]
+}
+
+/// Unit and precision of memory values printed to the console.
+pub fn units_args() -> Vec<Arg> {
+    vec![
+        Arg::new("units").long("units").value_name("UNIT").value_parser(["auto", "kb", "mb", "gb"]).default_value("auto").help("Unit of memory values in console output; CSV and JSON files always keep KB"),
+        Arg::new("precision").long("precision").value_name("DIGITS").default_value("1").value_parser(clap::value_parser!(usize)).help("Decimal places of MB/GB values in console output"),
+    ]
+}
+
+/// The target, and activity stacks captured on the input markers, of the
+/// subcommands injecting input.
+pub fn input_args() -> Vec<Arg> {
+    let mut args = target_args();
+    args.push(stack_snapshots_arg());
+    args
+}
+
+fn stack_snapshots_arg() -> Arg {
+    Arg::new("stack_snapshots").long("stack-snapshots").help("Capture the activity stack on crashes, ANRs and input markers, and native crashes of the app with addresses resolved to libraries").action(clap::ArgAction::SetTrue)
+}
+
+/// Every measurement session's: the target and the devices to run on.
+fn session_args() -> Vec<Arg> {
+    let mut args = vec![
+        Arg::new("device_class").long("device-class").value_name("CLASS").value_parser(["phone", "wear", "tv", "auto"]).help("Apply the sampling and collector preset of a device class; auto reads ro.build.characteristics"),
+        Arg::new("devices").long("devices").value_name("SERIALS").value_delimiter(',').action(clap::ArgAction::Append).conflicts_with("serial").help("Run the memory or logcat session on these devices (or all) in parallel, each in a directory named after its serial, then compare their memory"),
+    ];
+    args.extend(target_args());
+    args
+}
+
+/// Sessions streaming logcat or sampling memory: compression, remote
+/// control, resource caps and slow queries, collected from both.
+fn capture_args() -> Vec<Arg> {
+    vec![
+        Arg::new("compress").long("compress").value_name("CODEC").value_parser(["none", "gzip", "zstd"]).help("Compress the logcat output, the memory, swap and PSI samples and the stack snapshots (.gz / .zst appended to their names)"),
+        Arg::new("control").long("control").value_name("ADDR").help("Take mark, snapshot, rotate, pause, resume and stop commands on a local TCP port, loopback host:port or Unix socket path while a session runs"),
+        Arg::new("max_rss_mb").long("max-rss-mb").value_name("MB").value_parser(clap::value_parser!(u64)).help("Cap on this tool's own resident memory (Linux)"),
+        Arg::new("max_open_files").long("max-open-files").value_name("N").value_parser(clap::value_parser!(u64)).help("Cap on this tool's own open file handles (Linux)"),
+        Arg::new("guard_action").long("guard-action").value_name("ACTION").value_parser(["stop", "downsample"]).help("What passing a cap does: stop the session, or keep 1 in 10 lines/samples and stop at twice the cap"),
+        Arg::new("slow_queries").long("slow-queries").value_name("MS").value_parser(clap::value_parser!(u64)).help("Collect the app's SQLite statements taking at least this long (userdebug builds) and its slow ContentResolver calls"),
+    ]
+}
+
+/// Sessions streaming logcat.
+fn logcat_args() -> Vec<Arg> {
+    let mut args = filter_args();
+    args.extend([
+        Arg::new("selinux").long("selinux").help("Collect and deduplicate SELinux denials of the app's domain from logcat").action(clap::ArgAction::SetTrue),
+        Arg::new("ui_churn").long("ui-churn").help("Count Compose recompositions and fragment lifecycle moves from logcat").action(clap::ArgAction::SetTrue),
+        Arg::new("binary_logcat").long("binary-logcat").help("Read logcat in binary form and decode it natively, with nanosecond timestamps").action(clap::ArgAction::SetTrue),
+        Arg::new("persist_across_reboot").long("persist-across-reboot").help("Keep capturing logcat through device reboots, resuming from the last seen timestamp").action(clap::ArgAction::SetTrue),
+        stack_snapshots_arg(),
+        Arg::new("append").long("append").conflicts_with("force").help("Append to an existing logcat output file instead of refusing to run").action(clap::ArgAction::SetTrue),
+        Arg::new("max_output_mb").long("max-output-mb").value_name("MB").value_parser(clap::value_parser!(u64)).help("Cap on the logcat output written by this run, before compression"),
+    ]);
+    args
+}
+
+/// Collectors and plots of sessions sampling memory.
+fn sampling_args() -> Vec<Arg> {
+    vec![
+        Arg::new("psi_alert").long("psi-alert").value_name("PERCENT").help("Warn when any PSI avg10 value reaches this percentage while monitoring (default 10)").value_parser(clap::value_parser!(f64)),
+        Arg::new("vm_pressure").long("vm-pressure").help("Also sample device swap, zram and PSI while monitoring (implied by --psi-alert)").action(clap::ArgAction::SetTrue),
+        Arg::new("dmabuf").long("dmabuf").help("Also sample DMA-BUF memory attributed to the process while monitoring").action(clap::ArgAction::SetTrue),
+        Arg::new("kernel_mem").long("kernel-mem").help("Snapshot /proc/slabinfo and /proc/vmallocinfo before and after monitoring (requires root)").action(clap::ArgAction::SetTrue),
+        Arg::new("idle_state").long("idle-state").help("Record Doze state and the app's standby bucket while monitoring").action(clap::ArgAction::SetTrue),
+        Arg::new("net_state").long("net-state").help("Record default network changes from dumpsys connectivity while monitoring").action(clap::ArgAction::SetTrue),
+        Arg::new("wifi_signal").long("wifi-signal").help("Record Wi-Fi RSSI and link speed while monitoring").action(clap::ArgAction::SetTrue),
+        Arg::new("bluetooth").long("bluetooth").help("Snapshot the app's BLE scans and GATT connections before and after monitoring").action(clap::ArgAction::SetTrue),
+        Arg::new("wakeups").long("wakeups").help("Count the app's alarm wakeups per hour while monitoring").action(clap::ArgAction::SetTrue),
+        Arg::new("wakeup_budget").long("wakeup-budget").value_name("PER_HOUR").help("Fail the run when any session hour exceeds this many wakeups (implies --wakeups)").value_parser(clap::value_parser!(u64)),
+        Arg::new("notifications").long("notifications").help("Audit notifications posted by the app while monitoring").action(clap::ArgAction::SetTrue),
+        Arg::new("components").long("components").help("Track broadcasts received and services started and stopped by the app while monitoring").action(clap::ArgAction::SetTrue),
+        Arg::new("native_libs").long("native-libs").help("Record when the app's native libraries load and unload while monitoring, with the PSS step of each").action(clap::ArgAction::SetTrue),
+        Arg::new("compare_package").long("compare-package").value_name("PACKAGE").help("Also sample PACKAGE during memory and write a side-by-side comparison with the main app"),
+        Arg::new("events_file").long("events-file").value_name("CSV").help("Draw the events of a timestamp,label CSV (epoch ms or s, RFC 3339, or local date and time) as markers on plots and CSVs"),
+        Arg::new("events_device_clock").long("events-device-clock").help("The --events-file timestamps come from the device clock").action(clap::ArgAction::SetTrue),
+        Arg::new("plot_overlay").long("plot-overlay").value_name("SERIES").value_delimiter(',').action(clap::ArgAction::Append).help("Draw these series (cpu_percent, fps, cpu_temp_c or derived metrics) on a right-hand axis of the memory plot"),
+        Arg::new("chart_format").long("chart-format").value_name("FORMAT").value_parser(["png", "interactive"]).help("Memory plot as a PNG, or as an HTML chart with zoom, hover values and series toggles (same name, .html)"),
+        Arg::new("smooth").long("smooth").value_name("WINDOW").value_parser(clap::value_parser!(usize)).help("Draw plot lines as a moving average over WINDOW samples; exported data stays raw"),
+        Arg::new("composite_plot").long("composite-plot").help("With memory, also plot memory, app CPU, FPS and CPU temperature in stacked panels").action(clap::ArgAction::SetTrue),
+    ]
+}
+
+/// Where memory, thread and .so results are also written.
+fn results_args() -> Vec<Arg> {
+    vec![
+        Arg::new("db").long("db").value_name("FILE").help("Also insert memory samples, thread snapshots and .so rows into this SQLite database, under a new run ID"),
+        Arg::new("parquet").long("parquet").help("Also write the memory sample, thread and .so CSV files as Parquet, with the same columns (builds with the parquet feature)").action(clap::ArgAction::SetTrue),
+    ]
+}
+
+fn html_arg() -> Arg {
+    Arg::new("html").long("html").help("Also write memory and thread results, plots, summary statistics and device info as one self-contained HTML report").action(clap::ArgAction::SetTrue)
+}
+
+fn budgets_arg() -> Arg {
+    Arg::new("budgets").long("budgets").value_name("FILE").help("Memory budgets JSON checked at the end of the run; exits non-zero on any breach")
+}
+
+fn so_owners_arg() -> Arg {
+    Arg::new("so_owners").long("so-owners").value_name("FILE").help("Glob-to-owner mapping used to total .so memory per team or vendor")
+}
+
+fn dry_run_arg() -> Arg {
+    Arg::new("dry_run").long("dry-run").help("Print the adb commands and output files without running anything").action(clap::ArgAction::SetTrue)
+}
+
+/// The command line: the global options, the top level's, and every
+/// subcommand with its own.
+pub fn command() -> ClapCommand {
+    let command = ClapCommand::new("Android Log Analyzer")
+        .version("1.0")
+        .about("Analyzes Android logs, memory, and threads via ADB")
+        .arg(Arg::new("config").short('c').long("config").value_name("CONFIG").help("Path to JSON config file").global(true))
+        .arg(Arg::new("serial").short('d').long("serial").value_name("SERIAL").help("Target this device when several are attached (see the devices subcommand); defaults to ANDROID_SERIAL").global(true))
+        .arg(Arg::new("json").long("json").help("Print results as versioned JSON documents on stdout, one per line").action(clap::ArgAction::SetTrue).global(true))
+        .arg(Arg::new("force").long("force").help("Replace existing plots, logs and other fixed-name outputs").action(clap::ArgAction::SetTrue).global(true))
+        .args(root_args())
+        .subcommands(subcommands())
+        .subcommand(ClapCommand::new("devices").about("List the attached devices and their serials"))
+        .subcommand(
+            ClapCommand::new("connect")
+                .about("Connect to a device over wireless debugging (Android 11+) and remember it in the config file")
+                .arg(Arg::new("endpoint").value_name("HOST:PORT").help("Address shown under Wireless debugging; without it, the remembered endpoints are reconnected"))
+                .arg(Arg::new("pair").long("pair").value_name("HOST:PORT").requires("code").help("Pair first, at the address shown with the pairing code"))
+                .arg(Arg::new("code").long("code").value_name("CODE").requires("pair").help("Pairing code shown on the device")),
+        )
+        .subcommand(
+            ClapCommand::new("procstats")
+                .about("Report time-weighted PSS by process state from dumpsys procstats")
+                .args(target_args())
+                .args(units_args())
+                .arg(Arg::new("hours").long("hours").value_name("HOURS").help("Aggregation window in hours").default_value("24").value_parser(clap::value_parser!(u32))),
+        )
+        .subcommand(
+            ClapCommand::new("battery").about("Show the app's estimated power use since the last charge from dumpsys batterystats")
+                .args(target_args()),
+        )
+        .subcommand(
+            ClapCommand::new("memtop")
+                .about("Rank all processes by PSS, or diff two saved memtop snapshots")
+                .args(target_args())
+                .args(units_args())
+                .arg(Arg::new("top").long("top").value_name("N").help("Number of processes to print").default_value("20").value_parser(clap::value_parser!(usize)))
+                .arg(Arg::new("diff").long("diff").value_names(["BEFORE", "AFTER"]).num_args(2).help("Compare two memtop_*.json snapshots instead of querying the device")),
+        )
+        .subcommand(
+            ClapCommand::new("heapdump")
+                .about("Dump the app's Java heap and list the classes taking the most bytes, or diff two dumps for leak suspects")
+                .args(target_args())
+                .args(units_args())
+                .arg(Arg::new("top").long("top").value_name("N").help("Number of classes to print").default_value("20").value_parser(clap::value_parser!(usize)))
+                .arg(Arg::new("diff").long("diff").value_names(["BEFORE", "AFTER"]).num_args(2).help("Compare the class histograms of two .hprof dumps instead of dumping the heap")),
+        )
+        .subcommand(
+            ClapCommand::new("doze")
+                .about("Force the device into deep Doze, restore it, or show the current idle state")
+                .args(device_args())
+                .arg(Arg::new("action").value_parser(["enter", "exit", "status"]).default_value("status")),
+        )
+        .subcommand(
+            ClapCommand::new("standby")
+                .about("Show or set the app's standby bucket")
+                .args(target_args())
+                .arg(Arg::new("set").long("set").value_name("BUCKET").help("active, working_set, frequent, rare or restricted")),
+        )
+        .subcommand(
+            ClapCommand::new("net")
+                .about("Control and inspect device connectivity")
+                .subcommand_required(true)
+                .subcommand(
+                    ClapCommand::new("toggle")
+                        .about("Switch Wi-Fi, mobile data or airplane mode")
+                        .args(device_args())
+                        .arg(Arg::new("target").required(true).value_parser(["wifi", "data", "airplane"]))
+                        .arg(Arg::new("state").long("state").value_parser(["on", "off"]).help("Desired state; flips the current state when omitted")),
+                )
+                .subcommand(ClapCommand::new("status").about("Show the current default network").args(device_args())),
+        )
+        .subcommand(
+            ClapCommand::new("device")
+                .about("Normalize screen and animation settings for measurements, or restore them")
+                .subcommand_required(true)
+                .subcommand(
+                    ClapCommand::new("prep")
+                        .about("Fix the brightness, keep the screen on and disable animations, saving the current values")
+                        .args(device_args())
+                        .arg(Arg::new("brightness").long("brightness").value_name("0-255").default_value("128").value_parser(clap::value_parser!(u32).range(0..=255))),
+                )
+                .subcommand(ClapCommand::new("restore").about("Restore the settings saved by 'device prep'").args(device_args())),
+        )
+        .subcommand(
+            ClapCommand::new("bluetooth").about("Show the app's BLE scan registrations and GATT connections")
+                .args(target_args()),
+        )
+        .subcommand(
+            ClapCommand::new("analyze")
+                .about("Filter a saved logcat file (plain, .gz or .zst) with the keyword regex, in parallel")
+                .arg(regex_arg())
+                .arg(Arg::new("file").required(true).value_name("FILE"))
+                .arg(Arg::new("output").long("output").value_name("FILE").help("Write matching lines here instead of printing them")),
+        )
+        .subcommand(
+            ClapCommand::new("report")
+                .about("List the artifacts of a session directory, optionally bundling them")
+                .arg(package_arg())
+                .arg(Arg::new("dir").long("dir").value_name("DIR").default_value(".").help("Session directory"))
+                .arg(Arg::new("bundle").long("bundle").value_name("ZIP").help("Write the artifacts and a checksummed manifest to a zip archive"))
+                .arg(Arg::new("upload").long("upload").requires("bundle").help("Upload the bundle by HTTP PUT to the URL in the config's \"upload\" section (S3/GCS through presigned URLs only)").action(clap::ArgAction::SetTrue)),
+        )
+        .subcommand(
+            ClapCommand::new("startup")
+                .about("Measure repeated cold starts and write them as an iterations file")
+                .args(target_args())
+                .args(units_args())
+                .args(bench::args())
+                .arg(Arg::new("runs").long("runs").value_name("N").default_value("10").value_parser(clap::value_parser!(u32)))
+                .arg(Arg::new("pss").long("pss").help("Also record total PSS 5s after each launch").action(clap::ArgAction::SetTrue)),
+        )
+        .subcommand(
+            ClapCommand::new("eviction")
+                .about("Measure how long the app survives in the background while filler apps are launched, and its PSS when killed")
+                .args(target_args())
+                .args(units_args())
+                .args(bench::args())
+                .arg(Arg::new("fillers").long("fillers").required(true).value_name("PACKAGE").num_args(1..).help("Memory-hungry apps launched in turn every 10s"))
+                .arg(Arg::new("runs").long("runs").value_name("N").default_value("3").value_parser(clap::value_parser!(u32)))
+                .arg(Arg::new("timeout").long("timeout").value_name("SECS").default_value("600").value_parser(clap::value_parser!(u64)).help("End a run when the app has survived this long")),
+        )
+        .subcommand(
+            ClapCommand::new("pkg")
+                .about("Inspect what the installed build declares")
+                .subcommand_required(true)
+                .subcommand(ClapCommand::new("info").about("Versions, SDK levels, permissions and components from dumpsys package").args(target_args()))
+                .subcommand(
+                    ClapCommand::new("diff")
+                        .about("Compare the package info recorded for two builds")
+                        .arg(Arg::new("a").required(true).value_name("A").help("package_info file or session directory"))
+                        .arg(Arg::new("b").required(true).value_name("B").help("package_info file or session directory")),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("compare")
+                .about("Compare two iterations files, or the memory_samples runs of two directories, with confidence intervals, effect sizes and a significance test")
+                .arg(Arg::new("before").required(true).help("iterations file, memory_samples file or directory of memory_samples files"))
+                .arg(Arg::new("after").required(true).help("iterations file, memory_samples file or directory of memory_samples files")),
+        )
+        .subcommand(
+            ClapCommand::new("diff-env")
+                .about("Compare the device properties and settings recorded by two sessions")
+                .arg(Arg::new("a").required(true).value_name("A").help("device_env file or session directory"))
+                .arg(Arg::new("b").required(true).value_name("B").help("device_env file or session directory")),
+        )
+        .subcommand(
+            ClapCommand::new("mark")
+                .about("Add a named marker to the sessions running in this directory")
+                .arg(Arg::new("label").required(true).value_name("LABEL")),
+        )
+        .subcommand(
+            ClapCommand::new("search")
+                .about("Search the logs of stored sessions, including compressed captures")
+                .arg(Arg::new("pattern").required(true).value_name("REGEX"))
+                .arg(Arg::new("dir").long("dir").value_name("DIR").default_value(".").action(clap::ArgAction::Append).help("Directory holding session directories; repeatable"))
+                .arg(Arg::new("ignore_case").short('i').long("ignore-case").action(clap::ArgAction::SetTrue)),
+        )
+        .subcommand(
+            ClapCommand::new("trend")
+                .about("Crash and ANR rates of stored sessions over time, from their stability files")
+                .arg(Arg::new("dir").long("dir").value_name("DIR").default_value(".").action(clap::ArgAction::Append).help("Directory searched for stability files; repeatable")),
+        )
+        .subcommand(
+            ClapCommand::new("bench-filter")
+                .about("Replay a saved capture through the configured filter rules and measure lines per second for each")
+                .args(filter_args())
+                .args(units_args())
+                .arg(Arg::new("file").required(true).value_name("LOG").help("Capture to replay, plain, .gz or .zst")),
+        )
+        .subcommand(
+            ClapCommand::new("regex")
+                .about("Check the config's regexes before a long capture relies on them")
+                .subcommand_required(true)
+                .subcommand(
+                    ClapCommand::new("test")
+                        .about("Compile the keyword regex and preset regexes, show the lines each matches and how fast it matches them")
+                        .args(device_args())
+                        .args(filter_args())
+                        .arg(Arg::new("file").long("file").value_name("LOG").help("Sample lines from this log, plain, .gz or .zst, instead of the device's logcat buffer"))
+                        .arg(Arg::new("examples").long("examples").value_name("N").default_value("5").value_parser(clap::value_parser!(usize)).help("Matching lines shown per regex")),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("ab-test")
+                .about("Interleave cold-start measurements of two builds and test the deltas for significance")
+                .args(target_args())
+                .args(bench::args())
+                .arg(Arg::new("apk_a").long("apk-a").required(true).value_name("APK"))
+                .arg(Arg::new("apk_b").long("apk-b").required(true).value_name("APK"))
+                .arg(Arg::new("iterations").long("iterations").value_name("N").default_value("10").value_parser(clap::value_parser!(u32))),
+        )
+        .subcommand(
+            ClapCommand::new("bisect")
+                .about("Binary-search builds (oldest first) for the first one whose startup time or PSS exceeds a threshold")
+                .args(target_args())
+                .args(bench::args())
+                .arg(Arg::new("apks").long("apks").value_name("APK").num_args(1..).conflicts_with("revs").help("APKs ordered oldest to newest"))
+                .arg(Arg::new("revs").long("revs").value_name("REV").num_args(1..).requires("build_cmd").help("Revisions ordered oldest to newest"))
+                .arg(Arg::new("build_cmd").long("build-cmd").value_name("CMD").help("Host command building {rev}; its last stdout line is the APK path"))
+                .arg(Arg::new("metric").long("metric").value_parser(["startup", "pss"]).default_value("startup"))
+                .arg(Arg::new("threshold").long("threshold").required(true).value_name("VALUE").help("Limit in ms (startup) or KB (pss)").value_parser(clap::value_parser!(u64)))
+                .arg(Arg::new("runs").long("runs").value_name("N").default_value("3").help("Cold starts per build; the median is used").value_parser(clap::value_parser!(u32))),
+        )
+        .subcommand(
+            ClapCommand::new("scenario")
+                .about("Run a scripted scenario of input steps")
+                .args(input_args())
+                .args(units_args())
+                .args(bench::args())
+                .arg(Arg::new("file").required(true).value_name("SCENARIO_JSON"))
+                .arg(
+                    Arg::new("restart_on_crash")
+                        .long("restart-on-crash")
+                        .value_name("MAX")
+                        .help("Relaunch the app after a crash and resume at the last checkpoint, up to MAX times")
+                        .num_args(0..=1)
+                        .default_missing_value("10")
+                        .value_parser(clap::value_parser!(u32)),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("anr")
+                .about("Collect ANR traces and rank main-thread blocking stacks across runs")
+                .subcommand_required(true)
+                .subcommand(ClapCommand::new("collect").about("Pull new traces from /data/anr").args(target_args()))
+                .subcommand(
+                    ClapCommand::new("top")
+                        .about("Most frequent main-thread stacks in the collected traces")
+                        .arg(package_arg())
+                        .arg(Arg::new("depth").long("depth").value_name("FRAMES").help("Frames per fingerprint").default_value("5").value_parser(clap::value_parser!(usize)))
+                        .arg(Arg::new("top").long("top").value_name("N").default_value("10").value_parser(clap::value_parser!(usize))),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("exits")
+                .about("List recent process deaths and their reasons (Android 11+)")
+                .args(target_args())
+                .arg(Arg::new("top").long("top").value_name("N").default_value("20").value_parser(clap::value_parser!(usize))),
+        )
+        .subcommand(
+            ClapCommand::new("boot").about("Reboot the device and report boot milestones, total boot time and the app's first launch after boot")
+                .args(target_args()),
+        )
+        .subcommand(
+            ClapCommand::new("splits").about("List the installed split APKs with sizes, native libraries and runtime load state")
+                .args(target_args())
+                .args(units_args()),
+        )
+        .subcommand(
+            ClapCommand::new("stack").about("Show the task stack, resumed activity and focused window")
+                .args(target_args()),
+        )
+        .subcommand(
+            ClapCommand::new("ui")
+                .about("UI hierarchy tools")
+                .subcommand_required(true)
+                .subcommand(
+                    ClapCommand::new("dump")
+                        .about("Capture the view hierarchy with uiautomator")
+                        .args(target_args())
+                        .arg(Arg::new("metrics").long("metrics").help("Report node count and view depth").action(clap::ArgAction::SetTrue)),
+                ),
+        )
+        .subcommand(
+            ClapCommand::new("input")
+                .about("Inject input events, recording each one as a timeline marker")
+                .subcommand_required(true)
+                .subcommand(
+                    ClapCommand::new("tap")
+                        .args(input_args())
+                        .arg(Arg::new("x").required(true).value_parser(clap::value_parser!(u32)))
+                        .arg(Arg::new("y").required(true).value_parser(clap::value_parser!(u32))),
+                )
+                .subcommand(
+                    ClapCommand::new("swipe")
+                        .args(input_args())
+                        .arg(Arg::new("coords").required(true).num_args(4).value_names(["X1", "Y1", "X2", "Y2"]).value_parser(clap::value_parser!(u32)))
+                        .arg(Arg::new("duration").long("duration").value_name("MS").default_value("300").value_parser(clap::value_parser!(u64))),
+                )
+                .subcommand(ClapCommand::new("text").args(input_args()).arg(Arg::new("text").required(true)))
+                .subcommand(ClapCommand::new("keyevent").args(input_args()).arg(Arg::new("key").required(true).help("Key code or name, e.g. 4 or KEYCODE_BACK")))
+                .subcommand(
+                    ClapCommand::new("gesture")
+                        .args(input_args())
+                        .arg(Arg::new("path").long("path").required(true).value_name("x,y;x,y;...").help("Touch path with at least two points"))
+                        .arg(Arg::new("duration").long("duration").value_name("MS").default_value("500").value_parser(clap::value_parser!(u64))),
+                ),
+        );
+    with_dry_run(command)
+}
+
+/// Overrides the config with the options given to the subcommand run.
+pub fn apply_options(config: &mut LogAnalyzerConfig, options: &ArgMatches) -> Result<()> {
+    if let Some(package) = value::<String>(options, "package") {
+        config.package_name = package.clone();
+    }
+    if let Some(regex) = value::<String>(options, "regex") {
+        config.keyword_regex = regex.clone();
+    }
+    if let Some(sdk) = value::<u32>(options, "sdk") {
+        config.sdk_level = Some(*sdk);
+    }
+    if let Some(user) = value::<u32>(options, "user") {
+        config.user = Some(*user);
+    }
+    if flag(options, "strict_parse") {
+        config.strict_parse = true;
+    }
+    if flag(options, "notifications") {
+        config.notifications = true;
+    }
+    if flag(options, "components") {
+        config.components = true;
+    }
+    if flag(options, "native_libs") {
+        config.native_libs = true;
+    }
+    if flag(options, "html") {
+        config.html_report = true;
+    }
+    if let Some(path) = value::<String>(options, "db") {
+        config.db = Some(path.clone());
+    }
+    if flag(options, "parquet") {
+        config.parquet = true;
+    }
+    if flag(options, "one_shot_shell") {
+        config.one_shot_shell = true;
+    }
+    if let Some(presets) = values(options, "preset") {
+        config.filter_presets = presets;
+    }
+    if let Some(threshold) = value::<u64>(options, "slow_queries") {
+        config.slow_queries = Some(*threshold);
+    }
+    if flag(options, "stack_snapshots") {
+        config.stack_snapshots = true;
+    }
+    if let Some(path) = value::<String>(options, "budgets") {
+        config.budgets = Some(path.clone());
+    }
+    if let Some(path) = value::<String>(options, "so_owners") {
+        config.so_owners = Some(path.clone());
+    }
+    if flag(options, "ui_churn") {
+        config.ui_churn = true;
+    }
+    if flag(options, "binary_logcat") {
+        config.binary_logcat = true;
+    }
+    if flag(options, "selinux") {
+        config.selinux = true;
+    }
+    if flag(options, "composite_plot") {
+        config.composite_plot = true;
+    }
+    if let Some(addr) = value::<String>(options, "control") {
+        config.control_socket = Some(addr.clone());
+    }
+    if let Some(package) = value::<String>(options, "compare_package") {
+        config.compare_package = Some(package.clone());
+    }
+    if let Some(mb) = value::<u64>(options, "max_output_mb") {
+        config.guard.max_output_mb = Some(*mb);
+    }
+    if let Some(mb) = value::<u64>(options, "max_rss_mb") {
+        config.guard.max_rss_mb = Some(*mb);
+    }
+    if let Some(files) = value::<u64>(options, "max_open_files") {
+        config.guard.max_open_files = Some(*files);
+    }
+    if let Some(format) = value::<String>(options, "chart_format") {
+        config.chart_format = charts::ChartFormat::parse(format)?;
+    }
+    if let Some(action) = value::<String>(options, "guard_action") {
+        config.guard.action = guard::GuardAction::parse(action)?;
+    }
+    if let Some(path) = value::<String>(options, "events_file") {
+        config.events_file = Some(path.clone());
+    }
+    if flag(options, "events_device_clock") {
+        config.events_device_clock = true;
+    }
+    if let Some(series) = values(options, "plot_overlay") {
+        config.plot_overlay = series;
+    }
+    if let Some(window) = value::<usize>(options, "smooth") {
+        config.smooth = Some(*window);
+    }
+    if flag(options, "persist_across_reboot") {
+        config.persist_across_reboot = true;
+    }
+    if flag(options, "wakeups") {
+        config.wakeups = true;
+    }
+    if let Some(budget) = value::<u64>(options, "wakeup_budget") {
+        config.wakeup_budget = Some(*budget);
+    }
+    if flag(options, "bluetooth") {
+        config.bluetooth = true;
+    }
+    if flag(options, "wifi_signal") {
+        config.wifi_signal = true;
+    }
+    if flag(options, "net_state") {
+        config.net_state = true;
+    }
+    if flag(options, "idle_state") {
+        config.idle_state = true;
+    }
+    if flag(options, "kernel_mem") {
+        config.kernel_mem = true;
+    }
+    if flag(options, "vm_pressure") {
+        config.vm_pressure = true;
+    }
+    if flag(options, "dmabuf") {
+        config.dmabuf = true;
+    }
+    if let Some(threshold) = value::<f64>(options, "psi_alert") {
+        config.psi_alert_threshold = Some(*threshold);
+    }
+    Ok(())
+}
+
+pub fn subcommands() -> impl IntoIterator<Item = ClapCommand> {
+    [
+        ClapCommand::new("logcat")
+            .about("Capture logcat lines matching the keyword regex; the default without a subcommand")
+            .args(session_args())
+            .args(capture_args())
+            .args(logcat_args())
+            .arg(duration_arg("Stop after this many seconds instead of when logcat ends or on stop"))
+            .arg(output_arg("FILE", "File the matching lines are written to, instead of the config's output_file")),
+        ClapCommand::new("memory")
+            .about("Monitor and plot the app's memory usage")
+            .args(session_args())
+            .args(units_args())
+            .args(capture_args())
+            .args(sampling_args())
+            .args(results_args())
+            .arg(html_arg())
+            .arg(budgets_arg())
+            .arg(duration_arg("Sampling time (default 60)"))
+            .arg(interval_arg())
+            .arg(output_arg("FILE", "Memory plot file (default memory_plot.png); a .svg name draws a vector plot")),
+        ClapCommand::new("top-apps")
+            .about("Track the N largest processes device-wide and plot the device's memory composition")
+            .args(session_args())
+            .args(units_args())
+            .arg(Arg::new("count").required(true).value_name("N").value_parser(clap::value_parser!(usize)))
+            .arg(duration_arg("Sampling time (default 60)"))
+            .arg(interval_arg()),
+        ClapCommand::new("threads")
+            .about("List the app's threads with their state, priority and CPU time")
+            .args(session_args())
+            .args(results_args())
+            .arg(html_arg())
+            .arg(output_arg("JSON", "Also write the threads to this file")),
+        ClapCommand::new("so-memory")
+            .about("Break down the app's memory by .so library")
+            .args(session_args())
+            .args(units_args())
+            .args(results_args())
+            .arg(budgets_arg())
+            .arg(so_owners_arg())
+            .arg(output_arg("JSON", "Also write the libraries to this file")),
+        ClapCommand::new("session")
+            .about("Stream logcat, sample memory and poll threads at the same time, merged into one timeline")
+            .args(session_args())
+            .args(units_args())
+            .args(capture_args())
+            .args(logcat_args())
+            .args(sampling_args())
+            .args(results_args())
+            .arg(html_arg())
+            .arg(budgets_arg())
+            .arg(duration_arg("Sampling time (default 60)"))
+            .arg(interval_arg())
+            .arg(
+                Arg::new("thread_interval")
+                    .long("thread-interval")
+                    .value_name("SECS")
+                    .value_parser(clap::value_parser!(u64).range(1..))
+                    .help("Seconds between thread polls (default 5)"),
+            )
+            .arg(output_arg("FILE", "Memory plot file (default memory_plot.png); a .svg name draws a vector plot"))
+            .arg(
+                Arg::new("tui")
+                    .long("tui")
+                    .action(clap::ArgAction::SetTrue)
+                    .help("Show a live dashboard of memory, threads and logcat; keys: p pause/resume, m mark, e export, q stop"),
+            ),
+    ]
+}
+
+/// The top level's options: those of the logcat capture it runs without
+/// a subcommand, and, hidden from the help, the deprecated flags and the
+/// options of the sessions they start.
+pub fn root_args() -> Vec<Arg> {
+    let mut args = session_args();
+    args.extend(capture_args());
+    args.extend(logcat_args());
+    let legacy = units_args().into_iter().chain(sampling_args()).chain(results_args()).chain([html_arg(), budgets_arg(), so_owners_arg()]);
+    args.extend(legacy.map(|arg| arg.hide(true)));
+    args.extend(legacy_args());
+    args
+}
+
+/// Adds `--dry-run` to each command that runs something: the top level,
+/// and every subcommand not requiring one of its own.
+pub fn with_dry_run(command: ClapCommand) -> ClapCommand {
+    let names: Vec<String> = command.get_subcommands().map(|sub| sub.get_name().to_string()).collect();
+    let command = names.iter().fold(command, |command, name| command.mut_subcommand(name, with_dry_run));
+    if command.is_subcommand_required_set() { command } else { command.arg(dry_run_arg()) }
+}
+
+/// A non-global top-level option given along with a subcommand, which
+/// takes its options after its name, and the subcommand's name.
+pub fn misplaced_option(matches: &ArgMatches) -> Option<(String, &str)> {
+    let subcommand = matches.subcommand_name()?;
+    root_args()
+        .into_iter()
+        .chain([dry_run_arg()])
+        .find(|arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .map(|arg| (arg.get_long().map_or_else(|| format!("-{}", arg.get_short().unwrap_or('?')), |long| format!("--{}", long)), subcommand))
+}
+
+/// The deprecated top-level flags, hidden from the help.
+fn legacy_args() -> impl IntoIterator<Item = Arg> {
+    [
+        Arg::new("memory").short('m').long("memory").value_name("DURATION").default_missing_value("60").hide(true),
+        Arg::new("top_apps").long("top-apps").value_name("N").value_parser(clap::value_parser!(usize)).hide(true),
+        Arg::new("threads").short('t').long("threads").action(clap::ArgAction::SetTrue).hide(true),
+        Arg::new("so_memory").short('s').long("so-memory").action(clap::ArgAction::SetTrue).hide(true),
+    ]
+}
+
+/// What a run measures, from its subcommand or the legacy flags.
+#[derive(Default)]
+pub struct Modes {
+    /// Seconds of app memory sampling.
+    pub memory: Option<u64>,
+    /// Processes tracked device-wide, and for how many seconds.
+    pub top_apps: Option<(usize, u64)>,
+    pub threads: bool,
+    pub so_memory: bool,
+    /// Seconds after which a logcat session stops by itself.
+    pub logcat_duration: Option<u64>,
+    /// Sampling interval overriding the config's.
+    pub interval: Option<f64>,
+    /// Seconds between thread polls of a `session`, which streams logcat
+    /// and polls threads alongside the `memory` sampling.
+    pub thread_interval: Option<u64>,
+    /// `session --tui`.
+    pub tui: bool,
+    /// `--output` of `memory` or `session`.
+    pub plot_file: Option<String>,
+    /// `--output` of `logcat`, `threads` or `so-memory`.
+    pub output: Option<String>,
+    /// Legacy flags used, with the subcommand replacing each.
+    pub deprecated: Vec<(&'static str, &'static str)>,
+}
+
+impl Modes {
+    pub fn from_matches(matches: &ArgMatches) -> Modes {
+        let mut modes = Modes::default();
+        let duration = |sub: &ArgMatches| sub.get_one::<u64>("duration").copied();
+        match matches.subcommand() {
+            Some(("logcat", sub)) => {
+                modes.logcat_duration = duration(sub);
+                modes.output = sub.get_one::<String>("output").cloned();
+            }
+            Some(("memory", sub)) => {
+                modes.memory = Some(duration(sub).unwrap_or(DEFAULT_DURATION_SECS));
+                modes.plot_file = sub.get_one::<String>("output").cloned();
+            }
+            Some(("session", sub)) => {
+                modes.memory = Some(duration(sub).unwrap_or(DEFAULT_DURATION_SECS));
+                modes.thread_interval = Some(sub.get_one::<u64>("thread_interval").copied().unwrap_or(timeline::DEFAULT_THREAD_INTERVAL_SECS));
+                modes.tui = sub.get_flag("tui");
+                modes.plot_file = sub.get_one::<String>("output").cloned();
+            }
+            Some(("top-apps", sub)) => {
+                modes.top_apps = Some((*sub.get_one::<usize>("count").unwrap(), duration(sub).unwrap_or(DEFAULT_DURATION_SECS)));
+            }
+            Some(("threads", sub)) => {
+                modes.threads = true;
+                modes.output = sub.get_one::<String>("output").cloned();
+            }
+            Some(("so-memory", sub)) => {
+                modes.so_memory = true;
+                modes.output = sub.get_one::<String>("output").cloned();
+            }
+            _ => {}
+        }
+        if let Some((_, sub)) = matches.subcommand().filter(|(name, _)| matches!(*name, "memory" | "top-apps" | "session")) {
+            modes.interval = sub.get_one::<f64>("interval").copied();
+        }
+
+        // `--top-apps` took its duration from `-m`, and replaced the app
+        // memory session.
+        let legacy_duration = matches.get_one::<String>("memory").map(|s| {
+            s.parse::<u64>().unwrap_or_else(|_| {
+                warn!("Invalid duration specified, using default 60s");
+                DEFAULT_DURATION_SECS
+            })
+        });
+        if let Some(count) = matches.get_one::<usize>("top_apps") {
+            modes.top_apps = Some((*count, legacy_duration.unwrap_or(DEFAULT_DURATION_SECS)));
+            modes.deprecated.push(("--top-apps N", "top-apps N --duration SECS"));
+        } else if legacy_duration.is_some() {
+            modes.memory = legacy_duration;
+            modes.deprecated.push(("-m/--memory", "memory --duration SECS"));
+        }
+        if matches.get_flag("threads") {
+            modes.threads = true;
+            modes.deprecated.push(("-t/--threads", "threads"));
+        }
+        if matches.get_flag("so_memory") {
+            modes.so_memory = true;
+            modes.deprecated.push(("-s/--so-memory", "so-memory"));
+        }
+        modes
+    }
+
+    pub fn plot_file(&self) -> &str {
+        self.plot_file.as_deref().unwrap_or(MEMORY_PLOT_FILE)
+    }
+}
+
+/// Where the run's options were given: the innermost subcommand, or the
+/// top level for a capture without one.
+pub fn options(matches: &ArgMatches) -> &ArgMatches {
+    match matches.subcommand() {
+        Some((_, sub)) => options(sub),
+        None => matches,
+    }
+}
+
+/// The value of an option, or None when not given or the subcommand has
+/// no such option.
+pub fn value<'a, T: Clone + Send + Sync + 'static>(options: &'a ArgMatches, id: &str) -> Option<&'a T> {
+    options.try_get_one::<T>(id).ok().flatten()
+}
+
+pub fn values(options: &ArgMatches, id: &str) -> Option<Vec<String>> {
+    options.try_get_many::<String>(id).ok().flatten().map(|values| values.cloned().collect())
+}
+
+pub fn flag(options: &ArgMatches, id: &str) -> bool {
+    value::<bool>(options, id).copied().unwrap_or(false)
+}
+
+/// Whether the run is a measurement session, by subcommand or by the
+/// legacy flags (or plain logcat) without one.
+pub fn is_session(matches: &ArgMatches) -> bool {
+    matches.subcommand_name().is_none_or(|name| SESSION_SUBCOMMANDS.contains(&name))
+}
@@ -0,0 +1,148 @@
+//! Host to device clock offset, measured by `date` round trips over adb,
+//! so device-timestamped logcat lines can be placed on the host timeline
+//! used by markers and samples.
+
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Datelike, NaiveDateTime, TimeZone, Utc};
+use serde::Serialize;
+
+/// Nanoseconds on toybox; toolbox `date` prints "%N" literally, in which
+/// case the seconds are used.
+pub const DEVICE_CLOCK_CMD: &str = "date +%s%N";
+pub const DEVICE_TZ_CMD: &str = "date +%z";
+/// Round trips per measurement; the one with the shortest round trip wins.
+pub const ROUND_TRIPS: usize = 5;
+/// Seconds between re-measurements during a session.
+pub const RESYNC_SECS: u64 = 60;
+
+#[derive(Clone, Serialize)]
+pub struct ClockSample {
+    /// Host time at the middle of the round trip, ms since the Unix epoch.
+    pub host_ms: i64,
+    pub device_ms: i64,
+    /// Device clock minus host clock.
+    pub offset_ms: i64,
+    pub round_trip_ms: i64,
+}
+
+/// When a sample was taken: seconds into the session plus the wall clock
+/// on both sides, for joining with backend logs and other tools' output.
+#[derive(Clone, Copy, Default, Serialize)]
+pub struct SampleTime {
+    pub timestamp: u64,
+    /// Host wall clock, ms since the Unix epoch.
+    pub host_time_ms: i64,
+    /// Device wall clock at the same instant, from the measured offset.
+    pub device_time_ms: i64,
+    /// `timestamp` to the millisecond, for sub-second intervals.
+    pub elapsed_ms: u64,
+}
+
+/// The `SampleTime` fields of a serialized sample.
+pub const TIME_FIELDS: &[&str] = &["timestamp", "elapsed_ms", "host_time_ms", "device_time_ms"];
+
+/// Seconds into the session of a serialized sample, to the millisecond
+/// where the file has `elapsed_ms` and whole seconds in older files.
+pub fn sample_secs(sample: &serde_json::Value) -> Option<f64> {
+    match sample["elapsed_ms"].as_f64() {
+        Some(ms) => Some(ms / 1000.0),
+        None => sample["timestamp"].as_f64(),
+    }
+}
+
+impl SampleTime {
+    /// Seconds into the session, with the fraction.
+    pub fn secs(&self) -> f64 {
+        self.elapsed_ms as f64 / 1000.0
+    }
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct ClockSync {
+    /// Device UTC offset in seconds, for its local-time logcat timestamps.
+    pub device_utc_offset_secs: i32,
+    pub samples: Vec<ClockSample>,
+}
+
+impl ClockSync {
+    /// Stamps a sample taken now, `elapsed` into the session.
+    pub fn sample_time(&self, elapsed: Duration) -> SampleTime {
+        let host_time_ms = Utc::now().timestamp_millis();
+        SampleTime {
+            timestamp: elapsed.as_secs(),
+            host_time_ms,
+            device_time_ms: host_time_ms + self.offset_at(host_time_ms),
+            elapsed_ms: elapsed.as_millis() as u64,
+        }
+    }
+
+    /// Stamps something the host saw at `host_ms`, in a session that
+    /// started at `start_ms`.
+    pub fn time_at(&self, start_ms: i64, host_ms: i64) -> SampleTime {
+        let elapsed_ms = (host_ms - start_ms).max(0) as u64;
+        SampleTime { timestamp: elapsed_ms / 1000, host_time_ms: host_ms, device_time_ms: host_ms + self.offset_at(host_ms), elapsed_ms }
+    }
+
+    /// Offset of the latest measurement taken at or before `host_ms`, or
+    /// the first one for earlier times.
+    pub fn offset_at(&self, host_ms: i64) -> i64 {
+        self.samples
+            .iter()
+            .rev()
+            .find(|s| s.host_ms <= host_ms)
+            .or(self.samples.first())
+            .map_or(0, |s| s.offset_ms)
+    }
+
+    /// Host epoch milliseconds of a logcat line's "MM-DD HH:MM:SS.mmm"
+    /// timestamp, written in the device's local time (`utc_offset_secs`
+    /// overrides that, e.g. for host-formatted binary logcat).
+    pub fn host_time_ms(&self, line: &str, utc_offset_secs: Option<i32>) -> Option<i64> {
+        let stamp = line.get(..18)?;
+        let now = Utc::now();
+        let device_ms = |year: i32| -> Option<i64> {
+            let naive = NaiveDateTime::parse_from_str(&format!("{}-{}", year, stamp), "%Y-%m-%d %H:%M:%S%.3f").ok()?;
+            let offset = utc_offset_secs.unwrap_or(self.device_utc_offset_secs);
+            Some(Utc.from_utc_datetime(&naive).timestamp_millis() - offset as i64 * 1000)
+        };
+        // Logcat omits the year; a date ahead of now belongs to last year.
+        let mut device = device_ms(now.year())?;
+        if device > now.timestamp_millis() + 86_400_000 {
+            device = device_ms(now.year() - 1)?;
+        }
+        Some(device - self.offset_at(device))
+    }
+
+    /// `host_ms` as the device's local "MM-DD HH:MM:SS.mmm", the form
+    /// `logcat -T` takes.
+    pub fn logcat_time(&self, host_ms: i64) -> String {
+        let local_ms = host_ms + self.offset_at(host_ms) + self.device_utc_offset_secs as i64 * 1000;
+        DateTime::from_timestamp_millis(local_ms).map_or_else(String::new, |t| t.format("%m-%d %H:%M:%S%.3f").to_string())
+    }
+}
+
+/// Device epoch milliseconds from `date +%s%N` (or plain seconds).
+pub fn parse_device_clock(output: &str) -> Result<i64> {
+    let text = output.trim();
+    let digits: String = text.chars().take_while(|c| c.is_ascii_digit()).collect();
+    match digits.len() {
+        19.. => Ok(digits[..digits.len() - 6].parse()?),
+        1..=12 => Ok(digits.parse::<i64>()? * 1000),
+        _ => Err(anyhow!("Unexpected device clock output {:?}", text)),
+    }
+}
+
+/// "+0530" into seconds east of UTC.
+pub fn parse_utc_offset(output: &str) -> Option<i32> {
+    let text = output.trim();
+    let sign = match text.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let hours: i32 = text.get(1..3)?.parse().ok()?;
+    let minutes: i32 = text.get(3..5)?.parse().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
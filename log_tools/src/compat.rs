@@ -0,0 +1,673 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::clocksync::SampleTime;
+use crate::{MemorySample, ThreadInfo};
+
+pub const MIN_SUPPORTED_SDK: u32 = 21;
+pub const MAX_SUPPORTED_SDK: u32 = 35;
+
+/// `--user <id>` for am, pm, cmd and dumpsys calls; empty targets the
+/// tools' default (the current or system user).
+pub fn user_args(user: Option<u32>) -> Vec<String> {
+    user.map_or(Vec::new(), |user| vec!["--user".to_string(), user.to_string()])
+}
+
+/// `args` followed by `user_args`, for the commands that take them.
+pub fn user_command(args: &[&str], user: Option<u32>) -> Vec<String> {
+    let mut command: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+    command.extend(user_args(user));
+    command
+}
+
+// Matches every `Key: value` pair on a line, e.g. "TOTAL PSS: 1 TOTAL RSS: 2"
+static MEM_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"([A-Za-z][A-Za-z .]*?):\s+(\d+)").unwrap());
+static TOOLBOX_TIMES_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\(u:\s*(\d+),\s*s:\s*(\d+)\)").unwrap());
+// "userId=10057" in `dumpsys package` up to API 28, "appId=10057" after.
+static APP_ID_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b(?:userId|appId)=(\d+)").unwrap());
+
+/// First uid of installed apps; `Process.FIRST_APPLICATION_UID`.
+const FIRST_APPLICATION_UID: u32 = 10000;
+/// Uids per user; `UserHandle.PER_USER_RANGE`.
+const PER_USER_RANGE: u32 = 100000;
+
+// Section headings that may legitimately appear in `dumpsys meminfo <package>`.
+const KNOWN_MEMINFO_SECTIONS: &[&str] = &[
+    "App Summary",
+    "Objects",
+    "SQL",
+    "DATABASES",
+    "Asset Allocations",
+    "Unreachable memory",
+    "Dalvik Details",
+];
+// Words found in the column headings above the category table.
+const MEMINFO_COLUMN_WORDS: &[&str] = &["Pss", "Rss", "Total", "Dirty", "Clean", "Size", "Alloc", "Free"];
+
+#[derive(Serialize)]
+pub struct ParseIssue {
+    pub source: String,
+    pub item: String,
+    pub message: String,
+}
+
+/// Collects parse problems. In strict mode the first problem aborts the run;
+/// otherwise the field falls back to 0 and the issue is kept for the report.
+#[derive(Default, Serialize)]
+pub struct ParseDiagnostics {
+    #[serde(skip)]
+    strict: bool,
+    pub issues: Vec<ParseIssue>,
+}
+
+impl ParseDiagnostics {
+    pub fn new(strict: bool) -> Self {
+        ParseDiagnostics { strict, issues: Vec::new() }
+    }
+
+    pub fn record(&mut self, source: &str, item: &str, message: String) -> Result<()> {
+        if self.strict {
+            return Err(anyhow!("Strict parse failed in {} ({}): {}", source, item, message));
+        }
+        warn!(format!("{} ({}): {}", source, item, message));
+        self.issues.push(ParseIssue { source: source.to_string(), item: item.to_string(), message });
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Layout of `dumpsys meminfo <package>` output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MeminfoLayout {
+    /// API 21-22: no App Summary, values come from the category table.
+    Table,
+    /// API 23-29: App Summary with a single Pss column and a `TOTAL:` line.
+    AppSummary,
+    /// API 30+: App Summary with Pss/Rss columns and a `TOTAL PSS:` line.
+    AppSummaryRss,
+}
+
+/// Layout of the "Estimated power use (mAh)" section of `dumpsys
+/// batterystats`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BatterystatsLayout {
+    /// API 21-30: BatterySipper rows, "Uid u0a57: 12.3 ( cpu=... )".
+    Sipper,
+    /// API 31+: BatteryUsageStats rows, "UID u0a57: 12.3 fg: ... ( cpu=... )".
+    UsageStats,
+}
+
+/// Layout of the per-thread `ps` listing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PsLayout {
+    /// API 21-25 toolbox `ps -t -p -x <pid>`:
+    /// USER PID PPID VSIZE RSS PRIO NICE RTPRI SCHED WCHAN PC S NAME (u:N, s:N)
+    Toolbox,
+    /// API 26+ toybox `ps -T -p <pid> -o TID,PRI,S,CMD`, with CPU times
+    /// read from `/proc/<pid>/task/*/stat` since toybox cannot split them.
+    Toybox,
+}
+
+/// Parsing strategies selected for a device's `ro.build.version.sdk`.
+#[derive(Clone, Copy, Debug)]
+pub struct ParserProfile {
+    pub meminfo: MeminfoLayout,
+    pub ps: PsLayout,
+    /// `dumpsys meminfo --proto` is available (API 29+).
+    pub meminfo_proto: bool,
+    /// `dumpsys activity --proto activities` has the stack supervisor
+    /// layout (API 28-29); API 30 moved the stacks under window containers.
+    pub activities_proto: bool,
+    /// `dumpsys batterystats --proto` is available (API 28+).
+    pub batterystats_proto: bool,
+    pub batterystats: BatterystatsLayout,
+    /// `dumpsys batterystats --charged` skips the history (API 24+).
+    pub batterystats_charged: bool,
+}
+
+/// The app's share of the battery since the last charge.
+#[derive(Debug, Serialize)]
+pub struct PowerUse {
+    /// The app's uid as batterystats prints it, e.g. "u0a57".
+    pub uid: String,
+    pub power_mah: f64,
+    /// Drain of all consumers as batterystats computes it.
+    pub computed_drain_mah: Option<f64>,
+}
+
+impl ParserProfile {
+    pub fn for_sdk(sdk: u32) -> Self {
+        if sdk < MIN_SUPPORTED_SDK {
+            warn!(format!("API level {} is older than {}, parsing may be incomplete", sdk, MIN_SUPPORTED_SDK));
+        } else if sdk > MAX_SUPPORTED_SDK {
+            warn!(format!("API level {} is newer than {}, using the latest known parsers", sdk, MAX_SUPPORTED_SDK));
+        }
+        let meminfo = match sdk {
+            0..=22 => MeminfoLayout::Table,
+            23..=29 => MeminfoLayout::AppSummary,
+            _ => MeminfoLayout::AppSummaryRss,
+        };
+        let ps = if sdk < 26 { PsLayout::Toolbox } else { PsLayout::Toybox };
+        let batterystats = if sdk < 31 { BatterystatsLayout::Sipper } else { BatterystatsLayout::UsageStats };
+        ParserProfile {
+            meminfo,
+            ps,
+            meminfo_proto: sdk >= 29,
+            activities_proto: (28..=29).contains(&sdk),
+            batterystats_proto: sdk >= 28,
+            batterystats,
+            batterystats_charged: sdk >= 24,
+        }
+    }
+
+    /// `dumpsys meminfo` of `target`. Before API 23 only the full (`-a`)
+    /// table has the Shared Dirty column.
+    pub fn meminfo_args(&self, target: &str) -> Vec<String> {
+        let mut args = vec!["dumpsys".to_string(), "meminfo".to_string()];
+        if self.meminfo == MeminfoLayout::Table {
+            args.push("-a".to_string());
+        }
+        args.push(target.to_string());
+        args
+    }
+
+    pub fn parse_meminfo(&self, mem_info: &str, time: SampleTime, diags: &mut ParseDiagnostics) -> Result<MemorySample> {
+        check_meminfo_sections(mem_info, diags)?;
+        let sample = match self.meminfo {
+            MeminfoLayout::Table => MemorySample {
+                time,
+                total_pss: parse_table_value(mem_info, "TOTAL", 0, diags)?,
+                native_heap: parse_table_value(mem_info, "Native Heap", 0, diags)?,
+                dalvik_heap: parse_table_value(mem_info, "Dalvik Heap", 0, diags)?,
+                code: sum_table_values(mem_info, &[".so mmap", ".jar mmap", ".apk mmap", ".ttf mmap", ".dex mmap", ".oat mmap", ".art mmap"]),
+                stack: parse_table_value(mem_info, "Stack", 0, diags)?,
+                graphics: sum_table_values(mem_info, &["Gfx dev", "EGL mtrack", "GL mtrack"]),
+                private_dirty: parse_table_value(mem_info, "TOTAL", table_column(mem_info, "Private Dirty").unwrap_or(1), diags)?,
+                shared_dirty: match table_column(mem_info, "Shared Dirty") {
+                    Some(column) => parse_table_value(mem_info, "TOTAL", column, diags)?,
+                    None => {
+                        diags.record("meminfo", "Shared Dirty", "No Shared Dirty column in meminfo".to_string())?;
+                        0
+                    }
+                },
+                derived: BTreeMap::new(),
+            },
+            MeminfoLayout::AppSummary | MeminfoLayout::AppSummaryRss => MemorySample {
+                time,
+                total_pss: parse_memory_value(mem_info, if self.meminfo == MeminfoLayout::AppSummary { "TOTAL" } else { "TOTAL PSS" }, diags)?,
+                native_heap: parse_memory_value(mem_info, "Native Heap", diags)?,
+                dalvik_heap: parse_memory_value(mem_info, "Java Heap", diags)?,
+                code: parse_memory_value(mem_info, "Code", diags)?,
+                stack: parse_memory_value(mem_info, "Stack", diags)?,
+                graphics: parse_memory_value(mem_info, "Graphics", diags)?,
+                private_dirty: parse_table_value(mem_info, "TOTAL", table_column(mem_info, "Private Dirty").unwrap_or(1), diags)?,
+                // Shared Dirty has not been reported per process since KitKat.
+                shared_dirty: 0,
+                derived: BTreeMap::new(),
+            },
+        };
+        Ok(sample)
+    }
+
+    pub fn batterystats_args(&self, package: &str) -> Vec<String> {
+        let mut args = vec!["dumpsys".to_string(), "batterystats".to_string()];
+        if self.batterystats_charged {
+            args.push("--charged".to_string());
+        }
+        args.push(package.to_string());
+        args
+    }
+
+    /// The row of `uid` (as `format_uid` prints it) under "Estimated power
+    /// use", or `None` when batterystats has no power estimate for it.
+    pub fn parse_power_use(&self, output: &str, uid: &str) -> Option<PowerUse> {
+        let prefix = match self.batterystats {
+            BatterystatsLayout::Sipper => format!("Uid {}:", uid),
+            BatterystatsLayout::UsageStats => format!("UID {}:", uid),
+        };
+        let mut lines = output.lines().skip_while(|line| line.trim() != "Estimated power use (mAh):");
+        let indent = |line: &str| line.len() - line.trim_start().len();
+        let heading = indent(lines.next()?);
+        let mut computed_drain_mah = None;
+        // The section ends at the next line indented no deeper than its heading.
+        for line in lines.take_while(|line| line.trim().is_empty() || indent(line) > heading) {
+            let line = line.trim();
+            if let Some(rest) = line.strip_prefix("Capacity:") {
+                computed_drain_mah = rest.split(", ").find_map(|part| part.strip_prefix("Computed drain: ")?.parse().ok());
+            } else if let Some(rest) = line.strip_prefix(&prefix) {
+                let power_mah = rest.split_whitespace().next()?.parse().ok()?;
+                return Some(PowerUse { uid: uid.to_string(), power_mah, computed_drain_mah });
+            }
+        }
+        None
+    }
+
+    pub fn pid_ps_args(&self) -> Vec<String> {
+        let args: &[&str] = match self.ps {
+            PsLayout::Toolbox => &["ps"],
+            PsLayout::Toybox => &["ps", "-A", "-o", "PID,USER,NAME"],
+        };
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// With `user` set, only processes of that Android user match: app
+    /// process owners are named `u<user>_a<app id>`.
+    pub fn parse_pid(&self, ps_output: &str, package: &str, user: Option<u32>) -> Option<String> {
+        let user_prefix = user.map(|user| format!("u{}_", user));
+        ps_output.lines().skip(1).find_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (pid, owner, name) = match self.ps {
+                PsLayout::Toolbox => (fields.get(1)?, fields.first()?, fields.last()?),
+                PsLayout::Toybox => (fields.first()?, fields.get(1)?, fields.get(2)?),
+            };
+            let user_matches = user_prefix.as_ref().is_none_or(|prefix| owner.starts_with(prefix.as_str()));
+            (*name == package && user_matches).then(|| pid.to_string())
+        })
+    }
+
+    pub fn thread_ps_args(&self, pid: &str) -> Vec<String> {
+        let args: &[&str] = match self.ps {
+            PsLayout::Toolbox => &["ps", "-t", "-p", "-x", pid],
+            PsLayout::Toybox => &["ps", "-T", "-p", pid, "-o", "TID,PRI,S,CMD"],
+        };
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// Whether thread CPU times must be filled in via `apply_task_times`.
+    pub fn needs_task_times(&self) -> bool {
+        self.ps == PsLayout::Toybox
+    }
+
+    pub fn parse_threads(&self, ps_output: &str, diags: &mut ParseDiagnostics) -> Result<Vec<ThreadInfo>> {
+        let mut threads = Vec::new();
+        for line in ps_output.lines().skip(1).filter(|line| !line.trim().is_empty()) {
+            match self.parse_thread_line(line) {
+                Some(thread) => threads.push(thread),
+                None => diags.record("ps", "thread", format!("Unrecognized line: {}", line))?,
+            }
+        }
+        Ok(threads)
+    }
+
+    fn parse_thread_line(&self, line: &str) -> Option<ThreadInfo> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match self.ps {
+            PsLayout::Toolbox => {
+                if fields.len() < 13 {
+                    return None;
+                }
+                let (name, user_time, system_time) = match TOOLBOX_TIMES_REGEX.captures(line) {
+                    Some(caps) => {
+                        let name_end = line.find("(u:").unwrap_or(line.len());
+                        let name = line[..name_end].split_whitespace().skip(12).collect::<Vec<_>>().join(" ");
+                        (name, caps[1].to_string(), caps[2].to_string())
+                    }
+                    None => (fields[12..].join(" "), "-".to_string(), "-".to_string()),
+                };
+                Some(ThreadInfo {
+                    tid: fields[1].to_string(),
+                    name,
+                    state: fields[11].to_string(),
+                    priority: fields[5].to_string(),
+                    user_time,
+                    system_time,
+                })
+            }
+            PsLayout::Toybox => {
+                if fields.len() < 4 {
+                    return None;
+                }
+                Some(ThreadInfo {
+                    tid: fields[0].to_string(),
+                    name: fields[3..].join(" "),
+                    state: fields[2].to_string(),
+                    priority: fields[1].to_string(),
+                    user_time: "-".to_string(),
+                    system_time: "-".to_string(),
+                })
+            }
+        }
+    }
+}
+
+/// Fills user/system CPU ticks from `cat /proc/<pid>/task/*/stat` output.
+pub fn apply_task_times(threads: &mut [ThreadInfo], stat_output: &str) {
+    for line in stat_output.lines() {
+        // The comm field is parenthesised and may contain spaces.
+        let (Some(open), Some(close)) = (line.find('('), line.rfind(')')) else {
+            continue;
+        };
+        let tid = line[..open].trim();
+        let fields: Vec<&str> = line[close + 1..].split_whitespace().collect();
+        // utime and stime are fields 14 and 15; `fields` starts at field 3.
+        if let (Some(utime), Some(stime)) = (fields.get(11), fields.get(12)) {
+            if let Some(thread) = threads.iter_mut().find(|t| t.tid == tid) {
+                thread.user_time = utime.to_string();
+                thread.system_time = stime.to_string();
+            }
+        }
+    }
+}
+
+/// The app id in `dumpsys package <package>` output.
+pub fn parse_app_id(output: &str) -> Option<u32> {
+    APP_ID_REGEX.captures(output)?[1].parse().ok()
+}
+
+/// A uid as `UserHandle.formatUid` prints it: "u10a57" for app id 10057
+/// of user 10, the plain number for system uids.
+pub fn format_uid(app_id: u32, user: Option<u32>) -> String {
+    match (app_id, user.unwrap_or(0)) {
+        (app_id, user) if app_id >= FIRST_APPLICATION_UID => format!("u{}a{}", user, app_id - FIRST_APPLICATION_UID),
+        (app_id, 0) => app_id.to_string(),
+        (app_id, user) => format!("u{}s{}", user, app_id),
+    }
+}
+
+/// The full uid of `app_id` for `user`, `UserHandle.getUid`.
+pub fn user_uid(app_id: u32, user: Option<u32>) -> u32 {
+    user.unwrap_or(0) * PER_USER_RANGE + app_id
+}
+
+pub fn parse_sdk_level(getprop_output: &str) -> Result<u32> {
+    let value = getprop_output.trim();
+    value.parse::<u32>().map_err(|_| anyhow!("Unexpected ro.build.version.sdk value: {:?}", value))
+}
+
+/// Looks up a `Key: value` pair, as used by the App Summary section.
+pub fn parse_memory_value(mem_info: &str, key: &str, diags: &mut ParseDiagnostics) -> Result<u64> {
+    for line in mem_info.lines() {
+        for caps in MEM_REGEX.captures_iter(line) {
+            if caps.get(1).is_some_and(|m| m.as_str().trim() == key) {
+                return caps.get(2)
+                    .and_then(|m| m.as_str().parse::<u64>().ok())
+                    .ok_or_else(|| anyhow!("Failed to parse {} value", key));
+            }
+        }
+    }
+    diags.record("meminfo", key, format!("Could not find {}: in meminfo", key))?;
+    Ok(0)
+}
+
+/// Reads the `column`-th number of a category table row such as
+/// `  Native Heap    10468    10408        0 ...`.
+pub fn parse_table_value(mem_info: &str, label: &str, column: usize, diags: &mut ParseDiagnostics) -> Result<u64> {
+    match find_table_value(mem_info, label, column) {
+        Some(value) => Ok(value),
+        None => {
+            diags.record("meminfo", label, format!("Could not find {} row (column {}) in meminfo", label, column))?;
+            Ok(0)
+        }
+    }
+}
+
+/// Flags heading-like lines that are not a known meminfo section, which
+/// usually means the output layout is not the one the profile expects.
+fn check_meminfo_sections(mem_info: &str, diags: &mut ParseDiagnostics) -> Result<()> {
+    for line in mem_info.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty()
+            || trimmed.starts_with('-')
+            || trimmed.starts_with("**")
+            || trimmed.contains(':')
+            || trimmed.chars().any(|c| c.is_ascii_digit())
+            || KNOWN_MEMINFO_SECTIONS.contains(&trimmed)
+            || MEMINFO_COLUMN_WORDS.iter().any(|word| trimmed.contains(word))
+        {
+            continue;
+        }
+        diags.record("meminfo", "section", format!("Unrecognized section: {}", trimmed))?;
+    }
+    Ok(())
+}
+
+/// Index of the category table column named e.g. "Shared Dirty", among
+/// the numbers of a row, from the two heading lines above the table.
+fn table_column(mem_info: &str, name: &str) -> Option<usize> {
+    let lines: Vec<&str> = mem_info.lines().collect();
+    lines.windows(2).find_map(|pair| {
+        let (top, bottom) = (pair[0].trim_start(), pair[1].trim_start());
+        if !top.starts_with("Pss") || !bottom.starts_with("Total") {
+            return None;
+        }
+        top.split_whitespace().zip(bottom.split_whitespace()).position(|(a, b)| format!("{} {}", a, b) == name)
+    })
+}
+
+fn sum_table_values(mem_info: &str, labels: &[&str]) -> u64 {
+    labels.iter().filter_map(|label| find_table_value(mem_info, label, 0)).sum()
+}
+
+fn find_table_value(mem_info: &str, label: &str, column: usize) -> Option<u64> {
+    mem_info.lines().find_map(|line| {
+        let rest = line.trim_start().strip_prefix(label)?;
+        if !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        rest.split_whitespace().nth(column)?.parse::<u64>().ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `dumpsys meminfo -a` on API 22.
+    const MEMINFO_TABLE: &str = "\
+Applications Memory Usage (kB):
+Uptime: 5837213 Realtime: 5837213
+
+** MEMINFO in pid 2410 [com.example.app] **
+                   Pss      Pss   Shared  Private   Shared  Private  Swapped     Heap     Heap     Heap
+                 Total    Clean    Dirty    Dirty    Clean    Clean    Dirty     Size    Alloc     Free
+                ------   ------   ------   ------   ------   ------   ------   ------   ------   ------
+  Native Heap     5628        0     1000     5572        0        0        0    14336    10268     4067
+  Dalvik Heap     9512        0     5000     9192        0        0        0    21552    16314     5238
+ Dalvik Other     1234        0      100     1228        0        0        0
+        Stack      312        0        0      312        0        0        0
+     .so mmap     1021      200     1400      264     5000       44        0
+    .apk mmap      260      100        0        0      400      108        0
+    .dex mmap     3636     3200        0        4        0     3264        0
+    .oat mmap     1470      100        0        0     9000      184        0
+    .art mmap     1290        0      800      836      500        8        0
+   EGL mtrack     6048        0        0     6048        0        0        0
+    GL mtrack     3772        0        0     3772        0        0        0
+      Unknown      712        0       20      712        0        0        0
+        TOTAL    34895     3600     8320    27928    14900     3608        0    35888    26582     9305
+
+ Objects
+               Views:       25         ViewRootImpl:        1
+         AppContexts:        3           Activities:        1
+";
+
+    /// `dumpsys meminfo` on API 28.
+    const MEMINFO_APP_SUMMARY: &str = "\
+Applications Memory Usage (in Kilobytes):
+Uptime: 123456 Realtime: 123456
+
+** MEMINFO in pid 1234 [com.example.app] **
+                   Pss  Private  Private  SwapPss     Heap     Heap     Heap
+                 Total    Dirty    Clean    Dirty     Size    Alloc     Free
+                ------   ------   ------   ------   ------   ------   ------
+  Native Heap    10468    10408        0        0    20480    14462     6017
+  Dalvik Heap     6764     6708        0        0    12768     8384     4384
+        Stack      404      404        0        0
+        TOTAL    43681    35880     3492        0    33248    22846    10401
+
+ App Summary
+                       Pss(KB)
+                        ------
+           Java Heap:     8016
+         Native Heap:    10408
+                Code:     3704
+               Stack:      404
+            Graphics:    14956
+       Private Other:     2884
+              System:     3309
+
+               TOTAL:    43681       TOTAL SWAP PSS:        0
+
+ Objects
+               Views:       25         ViewRootImpl:        1
+";
+
+    /// `dumpsys meminfo` on API 33.
+    const MEMINFO_APP_SUMMARY_RSS: &str = "\
+Applications Memory Usage (in Kilobytes):
+Uptime: 123456 Realtime: 123456
+
+** MEMINFO in pid 1234 [com.example.app] **
+                   Pss  Private  Private  SwapPss      Rss     Heap     Heap     Heap
+                 Total    Dirty    Clean    Dirty    Total     Size    Alloc     Free
+                ------   ------   ------   ------   ------   ------   ------   ------
+  Native Heap    10468    10408        0        0    12000    20480    14462     6017
+  Dalvik Heap     6764     6708        0        0     9000    12768     8384     4384
+        Stack      404      404        0        0      410
+        TOTAL    43681    35880     3492        0    98000    33248    22846    10401
+
+ App Summary
+                       Pss(KB)                        Rss(KB)
+                        ------                         ------
+           Java Heap:     8016                          20000
+         Native Heap:    10408                          12000
+                Code:     3704                          30000
+               Stack:      404                            410
+            Graphics:    14956                          14956
+       Private Other:     2884
+              System:     3309
+             Unknown:                                    1200
+
+           TOTAL PSS:    43681            TOTAL RSS:    98000       TOTAL SWAP PSS:        0
+";
+
+    fn sample(sdk: u32, meminfo: &str) -> (MemorySample, ParseDiagnostics) {
+        let mut diags = ParseDiagnostics::new(false);
+        let sample = ParserProfile::for_sdk(sdk).parse_meminfo(meminfo, SampleTime::default(), &mut diags).unwrap();
+        (sample, diags)
+    }
+
+    #[test]
+    fn legacy_table_reads_dirty_columns_by_heading() {
+        let (s, diags) = sample(22, MEMINFO_TABLE);
+        assert!(diags.is_empty());
+        assert_eq!((s.total_pss, s.native_heap, s.dalvik_heap, s.stack), (34895, 5628, 9512, 312));
+        assert_eq!(s.code, 1021 + 260 + 3636 + 1470 + 1290);
+        assert_eq!(s.graphics, 6048 + 3772);
+        assert_eq!((s.private_dirty, s.shared_dirty), (27928, 8320));
+        assert_eq!(ParserProfile::for_sdk(22).meminfo_args("com.example.app"), ["dumpsys", "meminfo", "-a", "com.example.app"]);
+    }
+
+    #[test]
+    fn app_summary_layouts_read_the_summary() {
+        for (sdk, meminfo) in [(28, MEMINFO_APP_SUMMARY), (33, MEMINFO_APP_SUMMARY_RSS)] {
+            let (s, diags) = sample(sdk, meminfo);
+            assert!(diags.is_empty(), "API {}", sdk);
+            assert_eq!((s.total_pss, s.native_heap, s.dalvik_heap, s.code, s.stack, s.graphics), (43681, 10408, 8016, 3704, 404, 14956));
+            assert_eq!((s.private_dirty, s.shared_dirty), (35880, 0));
+            assert_eq!(ParserProfile::for_sdk(sdk).meminfo_args("1234"), ["dumpsys", "meminfo", "1234"]);
+        }
+        // The API 23-29 parser finds no "TOTAL:" in the newer summary.
+        let (s, diags) = sample(28, MEMINFO_APP_SUMMARY_RSS);
+        assert_eq!(s.total_pss, 0);
+        assert!(!diags.is_empty());
+    }
+
+    #[test]
+    fn toolbox_ps_finds_the_pid_and_threads() {
+        let profile = ParserProfile::for_sdk(23);
+        let ps = "\
+USER      PID   PPID  VSIZE  RSS   WCHAN              PC  NAME
+root      1     0     8876   724   SyS_epoll_ 0000000000 S /init
+u10_a57   2410  1923  1520320 61432 SyS_epoll_ 0000000000 S com.example.app
+u0_a57    2411  1923  1520320 61432 SyS_epoll_ 0000000000 S com.example.app
+";
+        assert_eq!(profile.parse_pid(ps, "com.example.app", None), Some("2410".to_string()));
+        assert_eq!(profile.parse_pid(ps, "com.example.app", Some(0)), Some("2411".to_string()));
+        let threads = "\
+USER     PID   PPID  VSIZE  RSS   PRIO  NICE  RTPRI SCHED  WCHAN    PC         NAME
+u0_a57    2411  1923  1520320 61432 20    0     0     0     SyS_epoll_ 0000000000 S com.example.app (u:152, s:38)
+u0_a57    2418  2411  1520320 61432 24    4     0     0     futex_wait 0000000000 S Jit thread pool (u:12, s:3)
+";
+        let mut diags = ParseDiagnostics::new(true);
+        let parsed = profile.parse_threads(threads, &mut diags).unwrap();
+        assert_eq!(parsed.len(), 2);
+        let jit = &parsed[1];
+        assert_eq!((jit.tid.as_str(), jit.name.as_str(), jit.state.as_str(), jit.priority.as_str()), ("2418", "Jit thread pool", "S", "24"));
+        assert_eq!((jit.user_time.as_str(), jit.system_time.as_str()), ("12", "3"));
+        assert!(!profile.needs_task_times());
+    }
+
+    #[test]
+    fn toybox_ps_finds_the_pid_and_threads_with_task_times() {
+        let profile = ParserProfile::for_sdk(30);
+        let ps = "\
+  PID USER           NAME
+    1 root           init
+ 2410 u0_a57         com.example.app
+ 2480 u0_a57         com.example.app:remote
+";
+        assert_eq!(profile.parse_pid(ps, "com.example.app", None), Some("2410".to_string()));
+        assert_eq!(profile.parse_pid(ps, "com.example.app", Some(10)), None);
+        let threads = "\
+  TID PRI S CMD
+ 2410  19 S com.example.app
+ 2418  29 S Jit thread pool
+";
+        let mut diags = ParseDiagnostics::new(true);
+        let mut parsed = profile.parse_threads(threads, &mut diags).unwrap();
+        assert_eq!(parsed[1].name, "Jit thread pool");
+        assert!(profile.needs_task_times());
+        let stat = "2418 (Jit thread pool) S 1923 1923 0 0 -1 1077936192 1000 0 0 0 12 3 0 0 29 9 20 0\n";
+        apply_task_times(&mut parsed, stat);
+        assert_eq!((parsed[1].user_time.as_str(), parsed[1].system_time.as_str()), ("12", "3"));
+        assert_eq!(parsed[0].user_time, "-");
+    }
+
+    #[test]
+    fn batterystats_rows_follow_the_layout() {
+        let sipper = "\
+Statistics since last charge:
+  Estimated power use (mAh):
+    Capacity: 3000, Computed drain: 152, actual drain: 120-150
+    Screen: 60.2 Excluded from smearing
+    Uid 1000: 20.1 ( cpu=18.0 wake=2.1 ) Excluded from smearing
+    Uid u0a57: 12.3 ( cpu=10.1 wake=0.2 wifi=2.0 ) Including smearing: 15.0 ( screen=2.7 )
+
+  All kernel wake locks:
+    Uid u0a58: 99.0
+";
+        let usage_stats = "\
+  Estimated power use (mAh):
+    Capacity: 4000, Computed drain: 152, actual drain: 120-150
+    Global
+      screen: 60.2 apps: 80.1 duration: 2h 3m 4s 5ms
+    UID u0a57: 12.3 fg: 10.0 bg: 2.0 fgs: 0.3 cached: 0
+      cpu=10.1 (15m 12s 3ms) wifi=2.0 (1m)
+";
+        for (sdk, output) in [(28, sipper), (33, usage_stats)] {
+            let usage = ParserProfile::for_sdk(sdk).parse_power_use(output, "u0a57").unwrap();
+            assert_eq!((usage.power_mah, usage.computed_drain_mah), (12.3, Some(152.0)), "API {}", sdk);
+        }
+        assert!(ParserProfile::for_sdk(33).parse_power_use(sipper, "u0a57").is_none());
+        assert!(ParserProfile::for_sdk(28).parse_power_use(sipper, "u0a58").is_none());
+        assert_eq!(ParserProfile::for_sdk(22).batterystats_args("com.example.app"), ["dumpsys", "batterystats", "com.example.app"]);
+        assert_eq!(ParserProfile::for_sdk(24).batterystats_args("com.example.app"), ["dumpsys", "batterystats", "--charged", "com.example.app"]);
+    }
+
+    #[test]
+    fn app_uids_print_as_batterystats_does() {
+        assert_eq!(parse_app_id("  Package [com.example.app] (4b2c1f0):\n    userId=10057 gids=[3003]\n"), Some(10057));
+        assert_eq!(parse_app_id("  Package [com.example.app] (4b2c1f0):\n    appId=10057\n"), Some(10057));
+        assert_eq!(format_uid(10057, None), "u0a57");
+        assert_eq!(format_uid(10057, Some(10)), "u10a57");
+        assert_eq!(format_uid(1000, None), "1000");
+        assert_eq!((user_uid(10057, None), user_uid(10057, Some(10))), (10057, 1_010_057));
+    }
+}
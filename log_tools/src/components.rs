@@ -0,0 +1,269 @@
+//! `--components`: broadcasts the app received and services it started
+//! and stopped during a memory session, to catch chatty receivers.
+//!
+//! Broadcasts come from `dumpsys activity broadcasts history`, polled every
+//! sample; a record counts once, when one of its receivers belongs to the
+//! package. The history is a ring of recent broadcasts, so on a busy device
+//! a long sample interval can miss some. Services come from the
+//! `am_create_service` and `am_destroy_service` entries of the events
+//! buffer, read when the session ends; a service destroyed without a
+//! matching create in the session is left out.
+
+use std::collections::{BTreeMap, HashSet};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::clocksync::{ClockSync, SampleTime};
+
+pub const COMPONENTS_PLOT_FILE: &str = "components_plot.png";
+pub const BROADCAST_HISTORY_CMD: &[&str] = &["dumpsys", "activity", "broadcasts", "history"];
+pub const SERVICE_EVENTS_ARGS: &[&str] = &["logcat", "-b", "events", "-d", "-v", "time", "-s", "am_create_service", "am_destroy_service"];
+
+// "BroadcastRecord{2b1a3c4 u0 android.intent.action.SCREEN_ON}" or, on
+// newer releases, "BroadcastRecord{2b1a3c4 android.intent.action.SCREEN_ON/u0}".
+static RECORD_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"BroadcastRecord\{([0-9a-f]+) (?:u-?\d+ )?([^\s}/]+)").unwrap());
+// "enqueueClockTime=2026-10-16 15:00:00.123"
+static ENQUEUE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"enqueueClockTime=\d{4}-(\d\d-\d\d \d\d:\d\d:\d\d\.\d{3})").unwrap());
+// "10-16 15:00:00.123 I/am_create_service( 1234): [0,12345678,com.example.app/.SyncService,act=...,5678]"
+static SERVICE_EVENT_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"I/(am_create_service|am_destroy_service)\(\s*\d+\): \[(.*)\]").unwrap());
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentEventKind {
+    Broadcast,
+    ServiceStart,
+    ServiceStop,
+}
+
+#[derive(Serialize)]
+pub struct ComponentEvent {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    pub kind: ComponentEventKind,
+    /// Broadcast action or service component.
+    pub name: String,
+    /// Manifest receiver of a broadcast, or "dynamic" for a registered one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub receiver: Option<String>,
+}
+
+/// One broadcast of the history; kept when a receiver is in the package.
+struct Delivery {
+    hash: String,
+    action: String,
+    /// "MM-DD HH:MM:SS.mmm" device local time of the enqueue.
+    enqueued: Option<String>,
+    receiver: Option<String>,
+}
+
+impl Delivery {
+    fn key(&self) -> String {
+        format!("{}@{}", self.hash, self.enqueued.as_deref().unwrap_or(""))
+    }
+}
+
+fn parse_history(output: &str, package: &str) -> Vec<Delivery> {
+    let manifest = format!(" {}/", package);
+    let dynamic = Regex::new(&format!(r"ReceiverList\{{[0-9a-f]+ \d+ {}/", regex::escape(package))).unwrap();
+    let mut records: Vec<Delivery> = Vec::new();
+    for line in output.lines() {
+        let is_receiver = line.trim_start().starts_with("Receiver #");
+        if let Some(caps) = RECORD_REGEX.captures(line).filter(|_| !is_receiver) {
+            // Other lines of a record may name it again; only a new hash
+            // starts one.
+            if records.last().is_none_or(|record| record.hash != caps[1]) {
+                records.push(Delivery { hash: caps[1].to_string(), action: caps[2].to_string(), enqueued: None, receiver: None });
+            }
+            continue;
+        }
+        let Some(record) = records.last_mut() else {
+            continue;
+        };
+        if let Some(caps) = ENQUEUE_REGEX.captures(line) {
+            record.enqueued = Some(caps[1].to_string());
+        } else if is_receiver && record.receiver.is_none() {
+            if dynamic.is_match(line) {
+                record.receiver = Some("dynamic".to_string());
+            } else if let Some(start) = line.find(&manifest) {
+                record.receiver = line[start + 1..].split_whitespace().next().map(|c| c.trim_end_matches('}').to_string());
+            }
+        }
+    }
+    records.retain(|record| record.receiver.is_some());
+    records
+}
+
+#[derive(Default)]
+pub struct BroadcastTracker {
+    seen: HashSet<String>,
+    pub events: Vec<ComponentEvent>,
+}
+
+impl BroadcastTracker {
+    /// Takes the broadcasts already in the history as seen, so they do not
+    /// count towards the session.
+    pub fn baseline(&mut self, output: &str, package: &str) {
+        self.seen.extend(parse_history(output, package).iter().map(Delivery::key));
+    }
+
+    /// Records the broadcasts delivered to `package` since the last poll,
+    /// at their enqueue time when dumpsys prints it, else at `time`.
+    pub fn observe(&mut self, output: &str, package: &str, time: SampleTime, clock: &ClockSync, start_ms: i64) {
+        for delivery in parse_history(output, package) {
+            if !self.seen.insert(delivery.key()) {
+                continue;
+            }
+            let time = delivery
+                .enqueued
+                .and_then(|stamp| clock.host_time_ms(&stamp, None))
+                .map_or(time, |host_ms| clock.time_at(start_ms, host_ms.max(start_ms)));
+            self.events.push(ComponentEvent { time, kind: ComponentEventKind::Broadcast, name: delivery.action, receiver: delivery.receiver });
+        }
+    }
+}
+
+/// Service starts and stops of `package` in `logcat -b events` output,
+/// from `start_ms` on.
+pub fn parse_service_events(output: &str, package: &str, clock: &ClockSync, start_ms: i64) -> Vec<ComponentEvent> {
+    let prefix = format!("{}/", package);
+    // Service record to component, from the creates.
+    let mut records: BTreeMap<String, String> = BTreeMap::new();
+    let mut events = Vec::new();
+    for line in output.lines() {
+        let Some(caps) = SERVICE_EVENT_REGEX.captures(line) else {
+            continue;
+        };
+        let fields: Vec<&str> = caps[2].split(',').collect();
+        let Some(host_ms) = clock.host_time_ms(line, None).filter(|ms| *ms >= start_ms) else {
+            continue;
+        };
+        let (kind, name) = match (&caps[1], fields.as_slice()) {
+            ("am_create_service", [_, record, name, ..]) if name.starts_with(&prefix) => {
+                records.insert(record.to_string(), name.to_string());
+                (ComponentEventKind::ServiceStart, name.to_string())
+            }
+            ("am_destroy_service", [_, record, ..]) => match records.remove(*record) {
+                Some(name) => (ComponentEventKind::ServiceStop, name),
+                None => continue,
+            },
+            _ => continue,
+        };
+        events.push(ComponentEvent { time: clock.time_at(start_ms, host_ms), kind, name, receiver: None });
+    }
+    events
+}
+
+#[derive(Serialize)]
+pub struct ComponentSummary {
+    /// Broadcasts received per action.
+    pub broadcasts: BTreeMap<String, u64>,
+    pub service_starts: BTreeMap<String, u64>,
+    pub service_stops: BTreeMap<String, u64>,
+}
+
+pub fn summarize(events: &[ComponentEvent]) -> ComponentSummary {
+    let mut summary = ComponentSummary { broadcasts: BTreeMap::new(), service_starts: BTreeMap::new(), service_stops: BTreeMap::new() };
+    for event in events {
+        let counts = match event.kind {
+            ComponentEventKind::Broadcast => &mut summary.broadcasts,
+            ComponentEventKind::ServiceStart => &mut summary.service_starts,
+            ComponentEventKind::ServiceStop => &mut summary.service_stops,
+        };
+        *counts.entry(event.name.clone()).or_default() += 1;
+    }
+    summary
+}
+
+/// Running count of the events of `kind` over session time, as steps from
+/// 0 at the start to the total at `duration_secs`.
+pub fn cumulative(events: &[ComponentEvent], kind: ComponentEventKind, duration_secs: u64) -> Vec<(f64, f64)> {
+    let mut points = vec![(0.0, 0.0)];
+    let mut count = 0.0;
+    for event in events.iter().filter(|e| e.kind == kind) {
+        let at = event.time.secs();
+        points.push((at, count));
+        count += 1.0;
+        points.push((at, count));
+    }
+    points.push((duration_secs as f64, count));
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(records: &[&str]) -> String {
+        format!("ACTIVITY MANAGER BROADCAST STATE (dumpsys activity broadcasts)\n  Historical broadcasts [background]:\n{}", records.concat())
+    }
+
+    // `dumpsys activity broadcasts history` records on API 30.
+    const SCREEN_ON: &str = "\
+  Historical Broadcast background #0:
+    BroadcastRecord{2b1a3c4 u-1 android.intent.action.SCREEN_ON} to user -1
+    Intent { act=android.intent.action.SCREEN_ON flg=0x50200010 }
+    caller=android 1021:system/1000 pid=1021 uid=1000
+    enqueueClockTime=2024-03-10 15:00:00.123 dispatchClockTime=2024-03-10 15:00:00.125
+    resultAbort=false ordered=false sticky=false initialSticky=false
+    Receiver #0: BroadcastFilter{8c9d0e1 1021/u0 ReceiverList{f1e2d3c 4242 com.example.app/10094/u0 remote:9a8b7c6}}
+";
+    const BATTERY: &str = "\
+  Historical Broadcast background #1:
+    BroadcastRecord{5d6e7f8 u0 android.intent.action.BATTERY_OKAY} to user 0
+    Intent { act=android.intent.action.BATTERY_OKAY flg=0x85000010 }
+    enqueueClockTime=2024-03-10 15:00:01.500 dispatchClockTime=2024-03-10 15:00:01.502
+    Receiver #0: ResolveInfo{7a8b9c0 com.google.android.gms/.chimera.GmsIntentOperationService$PersistentTrustedReceiver m=0x108000}
+    Receiver #1: ResolveInfo{0c1d2e3 com.example.app/.PowerReceiver m=0x108000}
+";
+    const OTHER_APP: &str = "\
+  Historical Broadcast background #2:
+    BroadcastRecord{9e0f1a2 com.android.vending.INSTALL_REFERRER/u0} to user 0
+    enqueueClockTime=2024-03-10 15:00:02.000 dispatchClockTime=2024-03-10 15:00:02.004
+    Receiver #0: ResolveInfo{3b4c5d6 com.other.app/.ReferrerReceiver m=0x108000}
+";
+
+    #[test]
+    fn new_broadcasts_to_the_package() {
+        let clock = ClockSync::default();
+        let start_ms = clock.host_time_ms("03-10 14:59:59.000", None).unwrap();
+        let mut tracker = BroadcastTracker::default();
+        tracker.baseline(&history(&[SCREEN_ON]), "com.example.app");
+        tracker.observe(&history(&[SCREEN_ON, BATTERY, OTHER_APP]), "com.example.app", SampleTime::default(), &clock, start_ms);
+
+        let events: Vec<(&str, Option<&str>, u64)> =
+            tracker.events.iter().map(|e| (e.name.as_str(), e.receiver.as_deref(), e.time.elapsed_ms)).collect();
+        assert_eq!(events, [("android.intent.action.BATTERY_OKAY", Some("com.example.app/.PowerReceiver"), 2500)]);
+
+        let records = parse_history(&history(&[SCREEN_ON, OTHER_APP]), "com.example.app");
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].receiver.as_deref(), Some("dynamic"));
+    }
+
+    #[test]
+    fn service_starts_and_stops_in_the_session() {
+        let events = "\
+--------- beginning of events
+03-10 14:58:00.000 I/am_create_service( 1021): [0,99887766,com.example.app/.EarlyService,10094,4242]
+03-10 15:00:02.000 I/am_create_service( 1021): [0,154627938,com.example.app/.SyncService,10094,4242]
+03-10 15:00:02.100 I/am_create_service( 1021): [0,203948576,com.other.app/.Worker,10120,5120]
+03-10 15:00:03.000 I/am_destroy_service( 1021): [0,99887766,4242]
+03-10 15:00:04.250 I/am_destroy_service( 1021): [0,154627938,4242]
+03-10 15:00:05.000 I/am_destroy_service( 1021): [0,203948576,5120]
+";
+        let clock = ClockSync::default();
+        let start_ms = clock.host_time_ms("03-10 14:59:59.000", None).unwrap();
+        let parsed = parse_service_events(events, "com.example.app", &clock, start_ms);
+        let rows: Vec<(&str, u64)> = parsed.iter().map(|e| (e.name.as_str(), e.time.elapsed_ms)).collect();
+        assert_eq!(rows, [("com.example.app/.SyncService", 3000), ("com.example.app/.SyncService", 5250)]);
+
+        let summary = summarize(&parsed);
+        assert_eq!(summary.service_starts.get("com.example.app/.SyncService"), Some(&1));
+        assert_eq!(summary.service_stops.get("com.example.app/.SyncService"), Some(&1));
+        assert_eq!(
+            cumulative(&parsed, ComponentEventKind::ServiceStart, 10),
+            [(0.0, 0.0), (3.0, 0.0), (3.0, 1.0), (10.0, 1.0)]
+        );
+    }
+}
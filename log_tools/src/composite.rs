@@ -0,0 +1,73 @@
+//! Extra per-sample series for the composite plot: app CPU usage from
+//! `/proc/<pid>/stat`, rendered frames from `dumpsys gfxinfo` and the CPU
+//! temperature. The composite stacks memory, CPU, FPS and temperature in
+//! separate panels over one time axis, so each keeps a readable scale.
+
+use serde::Serialize;
+
+use crate::clocksync::SampleTime;
+
+pub const COMPOSITE_PLOT_FILE: &str = "composite_plot.png";
+
+/// Kernel clock ticks per second (USER_HZ), 100 on every Android kernel.
+const CLOCK_TICKS_PER_SEC: f64 = 100.0;
+
+#[derive(Clone, Serialize)]
+pub struct PanelSample {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    /// Process CPU time over wall time since the previous sample; can
+    /// exceed 100 with several busy cores.
+    pub cpu_percent: Option<f64>,
+    /// Frames rendered per second since the previous sample. An idle UI
+    /// renders nothing, so 0 does not mean jank.
+    pub fps: Option<f64>,
+    pub cpu_temp_c: Option<f64>,
+}
+
+/// Counters read at one poll, turned into rates against the previous one.
+#[derive(Clone, Copy)]
+pub struct PanelCounters {
+    pub cpu_ticks: Option<u64>,
+    pub frames: Option<u64>,
+}
+
+pub fn proc_stat_cmd(pid: &str) -> String {
+    format!("cat /proc/{}/stat", pid)
+}
+
+/// utime + stime of a `/proc/<pid>/stat` line. The command name may hold
+/// spaces, so fields are counted after its closing parenthesis.
+pub fn parse_cpu_ticks(stat: &str) -> Option<u64> {
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    // Fields 14 and 15 of proc(5), numbered from the state (field 3).
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    Some(utime + stime)
+}
+
+/// "Total frames rendered: N" of `dumpsys gfxinfo <package>`.
+pub fn parse_frames_rendered(gfxinfo: &str) -> Option<u64> {
+    gfxinfo
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Total frames rendered:"))
+        .and_then(|n| n.trim().parse().ok())
+}
+
+impl PanelSample {
+    /// Rates between `prev` and `now`, `elapsed` seconds apart. Counter
+    /// resets (a restarted process) give no value rather than a spike.
+    pub fn from_counters(time: SampleTime, now: PanelCounters, prev: Option<PanelCounters>, elapsed: f64, cpu_temp_c: Option<f64>) -> Self {
+        let rate = |now: Option<u64>, before: Option<u64>| match (now, before) {
+            (Some(now), Some(before)) if now >= before && elapsed > 0.0 => Some((now - before) as f64 / elapsed),
+            _ => None,
+        };
+        let prev = prev.unwrap_or(PanelCounters { cpu_ticks: None, frames: None });
+        PanelSample {
+            time,
+            cpu_percent: rate(now.cpu_ticks, prev.cpu_ticks).map(|ticks| ticks / CLOCK_TICKS_PER_SEC * 100.0),
+            fps: rate(now.frames, prev.frames),
+            cpu_temp_c,
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Optional gzip or zstd compression of captured streams such as the
+//! logcat output file, the memory and PSI samples and the activity stack
+//! snapshots. Appending adds a new gzip member or zstd frame, which both
+//! formats decode as one continuous stream.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+
+use anyhow::{Result, anyhow};
+
+/// Lines written between flushes. A soak run killed with Ctrl-C gets no
+/// trailer, but what the last flush wrote decodes; `offline::open_log`
+/// reads such files up to there.
+pub const FLUSH_LINES: u64 = 1000;
+
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+pub enum Writer {
+    Plain(File),
+    Gzip(flate2::write::GzEncoder<File>),
+    Zstd(zstd::Encoder<'static, File>),
+}
+
+impl Compression {
+    pub fn parse(name: &str) -> Result<Option<Self>> {
+        match name {
+            "none" => Ok(None),
+            "gzip" => Ok(Some(Compression::Gzip)),
+            "zstd" => Ok(Some(Compression::Zstd)),
+            other => Err(anyhow!("Unknown compression {:?}, expected none, gzip or zstd", other)),
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+}
+
+/// `path` with the compressed extension appended.
+pub fn output_path(path: &str, compression: Option<Compression>) -> String {
+    match compression {
+        Some(compression) => format!("{}.{}", path, compression.extension()),
+        None => path.to_string(),
+    }
+}
+
+/// `path` without a ".gz" or ".zst" extension.
+pub fn plain_name(path: &str) -> &str {
+    path.strip_suffix(".gz").or_else(|| path.strip_suffix(".zst")).unwrap_or(path)
+}
+
+/// Creates `path` for buffered writing. Pass the writer to `close` when
+/// done, or a compressed file lacks its trailer.
+pub fn create(path: &str, compression: Option<Compression>) -> Result<BufWriter<Writer>> {
+    Ok(BufWriter::new(Writer::new(File::create(path)?, compression)?))
+}
+
+pub fn close(writer: BufWriter<Writer>) -> Result<()> {
+    writer.into_inner().map_err(|e| e.into_error())?.finish()
+}
+
+/// Writes `contents` to `path`, like `std::fs::write`.
+pub fn write(path: &str, contents: impl AsRef<[u8]>, compression: Option<Compression>) -> Result<()> {
+    let mut writer = create(path, compression)?;
+    writer.write_all(contents.as_ref())?;
+    close(writer)
+}
+
+/// The contents of `path`, decompressed when its extension says so.
+pub fn read(path: &str) -> Result<Vec<u8>> {
+    let file = File::open(path)?;
+    let mut contents = Vec::new();
+    if path.ends_with(".gz") {
+        flate2::read::MultiGzDecoder::new(file).read_to_end(&mut contents)?;
+    } else if path.ends_with(".zst") {
+        zstd::Decoder::new(file)?.read_to_end(&mut contents)?;
+    } else {
+        io::BufReader::new(file).read_to_end(&mut contents)?;
+    }
+    Ok(contents)
+}
+
+impl Writer {
+    pub fn new(file: File, compression: Option<Compression>) -> Result<Self> {
+        Ok(match compression {
+            None => Writer::Plain(file),
+            Some(Compression::Gzip) => Writer::Gzip(flate2::write::GzEncoder::new(file, flate2::Compression::default())),
+            Some(Compression::Zstd) => Writer::Zstd(zstd::Encoder::new(file, ZSTD_LEVEL)?),
+        })
+    }
+
+    /// Writes the compressed stream's trailer.
+    pub fn finish(self) -> Result<()> {
+        match self {
+            Writer::Plain(mut file) => file.flush()?,
+            Writer::Gzip(encoder) => {
+                encoder.finish()?;
+            }
+            Writer::Zstd(encoder) => {
+                encoder.finish()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Writer::Plain(file) => file.write(buf),
+            Writer::Gzip(encoder) => encoder.write(buf),
+            Writer::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Writer::Plain(file) => file.flush(),
+            Writer::Gzip(encoder) => encoder.flush(),
+            Writer::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn written_files_read_back_in_every_codec() {
+        let dir = std::env::temp_dir().join(format!("compress_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for compression in [None, Some(Compression::Gzip), Some(Compression::Zstd)] {
+            let path = output_path(&dir.join("samples.json").to_string_lossy(), compression);
+            write(&path, "{\"a\": 1}\n", compression).unwrap();
+            assert_eq!(plain_name(&path), dir.join("samples.json").to_string_lossy());
+            assert_eq!(read(&path).unwrap(), b"{\"a\": 1}\n");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,79 @@
+//! Console setup and text decoding that behave the same on Windows as on
+//! Unix: UTF-8 code pages, ANSI colors through virtual-terminal processing,
+//! and tolerance for UTF-16 or CRLF text from adb and PowerShell.
+
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+
+pub const YELLOW: &str = "33";
+
+/// Whether the console interprets ANSI escapes. Unix terminals always do;
+/// Windows consoles only once virtual-terminal processing is enabled.
+static ANSI_SUPPORTED: AtomicBool = AtomicBool::new(cfg!(not(windows)));
+
+/// Switches the console to UTF-8 and enables ANSI escapes where possible.
+#[cfg(windows)]
+pub fn setup() {
+    use windows_sys::Win32::System::Console::{
+        CONSOLE_MODE, ENABLE_VIRTUAL_TERMINAL_PROCESSING, GetConsoleMode, GetStdHandle, STD_ERROR_HANDLE, STD_OUTPUT_HANDLE,
+        SetConsoleCP, SetConsoleMode, SetConsoleOutputCP,
+    };
+    const CP_UTF8: u32 = 65001;
+
+    // SAFETY: plain Win32 calls on the process's own standard handles;
+    // failures (e.g. redirected output) are reported through return values.
+    unsafe {
+        SetConsoleOutputCP(CP_UTF8);
+        SetConsoleCP(CP_UTF8);
+        let mut ansi = true;
+        for std_handle in [STD_OUTPUT_HANDLE, STD_ERROR_HANDLE] {
+            let handle = GetStdHandle(std_handle);
+            let mut mode: CONSOLE_MODE = 0;
+            if GetConsoleMode(handle, &mut mode) == 0 {
+                continue;
+            }
+            ansi &= SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0;
+        }
+        ANSI_SUPPORTED.store(ansi, Ordering::Relaxed);
+    }
+}
+
+#[cfg(not(windows))]
+pub fn setup() {}
+
+/// Colors `text` when human-readable output goes to a terminal that
+/// supports it and NO_COLOR is unset.
+pub fn paint(color: &str, text: &str) -> String {
+    let terminal = if crate::output::json_output() { std::io::stderr().is_terminal() } else { std::io::stdout().is_terminal() };
+    if terminal && ANSI_SUPPORTED.load(Ordering::Relaxed) && std::env::var_os("NO_COLOR").is_none() {
+        format!("\x1b[{}m{}\x1b[0m", color, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Decodes device or file text: UTF-16 with a byte-order mark (PowerShell
+/// redirection), otherwise UTF-8 with any BOM dropped. CRLF line endings
+/// from older adb shells become LF.
+pub fn decode(bytes: &[u8]) -> String {
+    let text = match bytes {
+        [0xFF, 0xFE, rest @ ..] => decode_utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => decode_utf16(rest, u16::from_be_bytes),
+        [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8_lossy(rest).into_owned(),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    };
+    if text.contains('\r') { text.replace("\r\n", "\n") } else { text }
+}
+
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> String {
+    let units = bytes.chunks_exact(2).map(|pair| unit([pair[0], pair[1]]));
+    char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect()
+}
+
+/// Reads a text file written by any editor or shell, see [`decode`].
+/// Files written with `--compress` are decompressed.
+pub fn read_text(path: &str) -> Result<String> {
+    Ok(decode(&crate::compress::read(path)?))
+}
@@ -0,0 +1,360 @@
+//! `--control <ADDR>`: a local socket that takes one command per line
+//! while a memory session or logcat capture runs, so scripts can steer
+//! it without killing the process:
+//!
+//! ```text
+//! mark <label>   add a marker, as typed on the terminal
+//! snapshot       save the app's full meminfo now
+//! rotate         start a new logcat output file (captures only)
+//! pause          stop sampling until resume (memory sessions only)
+//! resume         continue sampling after pause
+//! stop           end the session and write its reports
+//! ```
+//!
+//! ADDR is a TCP port or a loopback host:port, or on Unix a socket path.
+//! Each command gets an "ok ..." or "error: ..." line back.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+
+use crate::{markers, pause};
+
+static STOP: AtomicBool = AtomicBool::new(false);
+static ROTATE: AtomicBool = AtomicBool::new(false);
+/// Run once on `stop`, e.g. to end a blocking logcat read.
+static STOP_HOOK: Mutex<Option<StopHook>> = Mutex::new(None);
+
+type StopHook = Box<dyn FnOnce() + Send>;
+
+pub enum ControlCommand {
+    Mark(String),
+    Snapshot,
+    Rotate,
+    Pause,
+    Resume,
+    Stop,
+}
+
+/// What the running session supports besides `mark` and `stop`.
+pub struct Handlers {
+    /// Saves a snapshot, returning the file written.
+    pub snapshot: Box<dyn Fn() -> Result<String> + Send + Sync>,
+    pub rotate: bool,
+    pub pause: bool,
+}
+
+pub fn parse_command(line: &str) -> Result<ControlCommand> {
+    let line = line.trim();
+    let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    match word {
+        "mark" => markers::parse_mark_command(line, 1)
+            .filter(|_| !rest.trim().is_empty())
+            .map(ControlCommand::Mark)
+            .ok_or_else(|| anyhow!("mark needs a label")),
+        "snapshot" => Ok(ControlCommand::Snapshot),
+        "rotate" => Ok(ControlCommand::Rotate),
+        "pause" => Ok(ControlCommand::Pause),
+        "resume" => Ok(ControlCommand::Resume),
+        "stop" => Ok(ControlCommand::Stop),
+        other => Err(anyhow!("unknown command {:?}, expected mark, snapshot, rotate, pause, resume or stop", other)),
+    }
+}
+
+/// Keeps the control socket open; dropping it closes the socket and, on
+/// Unix, removes the socket file.
+pub struct ControlSocket {
+    endpoint: Endpoint,
+    closed: Arc<AtomicBool>,
+}
+
+enum Endpoint {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+        // A connection of our own wakes the accept loop so it sees the flag.
+        match &self.endpoint {
+            Endpoint::Tcp(addr) => {
+                let _ = TcpStream::connect(addr);
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                let _ = std::os::unix::net::UnixStream::connect(path);
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+/// Starts accepting commands on `addr` in the background, until the
+/// returned socket is dropped. TCP addresses must be loopback ones, as
+/// anyone who can connect can stop the session.
+pub fn listen(addr: &str, handlers: Handlers) -> Result<ControlSocket> {
+    let handlers = Arc::new(handlers);
+    let closed = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    if addr.contains('/') {
+        use std::os::unix::fs::FileTypeExt;
+        // Only a socket left behind by an earlier session is replaced.
+        if let Ok(meta) = std::fs::symlink_metadata(addr) {
+            if !meta.file_type().is_socket() {
+                return Err(anyhow!("{} exists and is not a socket", addr));
+            }
+            std::fs::remove_file(addr)?;
+        }
+        let listener = std::os::unix::net::UnixListener::bind(addr)?;
+        info!("Control socket listening on {}", addr);
+        accept_loop(move || listener.accept().map(|(stream, _)| stream), |s| s.try_clone(), handlers, closed.clone());
+        return Ok(ControlSocket { endpoint: Endpoint::Unix(PathBuf::from(addr)), closed });
+    }
+    let addr = if addr.contains(':') { addr.to_string() } else { format!("127.0.0.1:{}", addr) };
+    let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+    if let Some(remote) = addrs.iter().find(|a| !a.ip().is_loopback()) {
+        return Err(anyhow!("The control socket only listens on loopback addresses, not {}", remote.ip()));
+    }
+    let listener = TcpListener::bind(&addrs[..])?;
+    let local = listener.local_addr()?;
+    info!("Control socket listening on {}", local);
+    accept_loop(move || listener.accept().map(|(stream, _)| stream), |s| s.try_clone(), handlers, closed.clone());
+    Ok(ControlSocket { endpoint: Endpoint::Tcp(local), closed })
+}
+
+/// Serves each connection on its own thread, so an idle client does not
+/// hold up the others.
+fn accept_loop<S: Read + Write + Send + 'static>(
+    mut accept: impl FnMut() -> std::io::Result<S> + Send + 'static,
+    try_clone: fn(&S) -> std::io::Result<S>,
+    handlers: Arc<Handlers>,
+    closed: Arc<AtomicBool>,
+) {
+    std::thread::spawn(move || {
+        while let Ok(stream) = accept() {
+            if closed.load(Ordering::Relaxed) {
+                break;
+            }
+            let Ok(clone) = try_clone(&stream) else {
+                continue;
+            };
+            let handlers = handlers.clone();
+            std::thread::spawn(move || serve(BufReader::new(clone), stream, &handlers));
+        }
+    });
+}
+
+/// Answers the commands of one connection until it closes.
+fn serve(reader: impl BufRead, mut writer: impl Write, handlers: &Handlers) {
+    for line in reader.lines().map_while(|l| l.ok()) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match parse_command(&line).and_then(|command| run(command, handlers)) {
+            Ok(reply) => format!("ok {}", reply),
+            Err(e) => format!("error: {}", e),
+        };
+        if writeln!(writer, "{}", reply.trim_end()).is_err() {
+            return;
+        }
+    }
+}
+
+fn run(command: ControlCommand, handlers: &Handlers) -> Result<String> {
+    match command {
+        ControlCommand::Mark(label) => {
+            let marker = markers::record_marker(&label)?;
+            info!("Marker: {}", marker.label);
+            Ok(String::new())
+        }
+        ControlCommand::Snapshot => (handlers.snapshot)(),
+        ControlCommand::Rotate if handlers.rotate => {
+            ROTATE.store(true, Ordering::Relaxed);
+            Ok(String::new())
+        }
+        ControlCommand::Rotate => Err(anyhow!("this session has no output file to rotate")),
+        ControlCommand::Pause | ControlCommand::Resume if !handlers.pause => Err(anyhow!("only memory sessions can be paused")),
+        ControlCommand::Pause if pause::set_paused(true) => Ok(String::new()),
+        ControlCommand::Pause => Err(anyhow!("already paused")),
+        ControlCommand::Resume if pause::set_paused(false) => Ok(String::new()),
+        ControlCommand::Resume => Err(anyhow!("not paused")),
+        ControlCommand::Stop => {
+            info!("Stop requested over the control socket");
+            request_stop();
+            Ok(String::new())
+        }
+    }
+}
+
+/// Clears a stop, rotation and stop hook left over from an earlier
+/// session of this process, e.g. a memory session ahead of a capture.
+pub fn begin_session() {
+    let mut hook = STOP_HOOK.lock().unwrap();
+    *hook = None;
+    STOP.store(false, Ordering::Relaxed);
+    ROTATE.store(false, Ordering::Relaxed);
+}
+
+/// Stops the session after a while unless dropped first, so the timer of
+/// a session that ended early cannot stop the next one.
+pub struct StopTimer {
+    _cancel: Sender<()>,
+}
+
+pub fn stop_after(duration: Duration) -> StopTimer {
+    let (cancel, cancelled) = mpsc::channel::<()>();
+    std::thread::spawn(move || {
+        if cancelled.recv_timeout(duration) == Err(RecvTimeoutError::Timeout) {
+            request_stop();
+        }
+    });
+    StopTimer { _cancel: cancel }
+}
+
+/// Device changes sessions undo when they end; Ctrl-C runs them too.
+static TEARDOWN: Mutex<Vec<(u64, Teardown)>> = Mutex::new(Vec::new());
+static NEXT_TEARDOWN: AtomicU64 = AtomicU64::new(0);
+
+type Teardown = Box<dyn FnOnce() + Send>;
+
+/// Runs its teardown when dropped, unless Ctrl-C ran it first.
+pub struct TeardownGuard(u64);
+
+impl Drop for TeardownGuard {
+    fn drop(&mut self) {
+        let teardown = {
+            let mut teardowns = TEARDOWN.lock().unwrap();
+            teardowns.iter().position(|(id, _)| *id == self.0).map(|i| teardowns.remove(i).1)
+        };
+        if let Some(teardown) = teardown {
+            teardown();
+        }
+    }
+}
+
+pub fn on_teardown(teardown: impl FnOnce() + Send + 'static) -> TeardownGuard {
+    let id = NEXT_TEARDOWN.fetch_add(1, Ordering::Relaxed);
+    TEARDOWN.lock().unwrap().push((id, Box::new(teardown)));
+    TeardownGuard(id)
+}
+
+/// Runs every pending teardown, latest first, ahead of exiting on Ctrl-C.
+pub fn run_teardowns() {
+    let teardowns = std::mem::take(&mut *TEARDOWN.lock().unwrap());
+    for (_, teardown) in teardowns.into_iter().rev() {
+        teardown();
+    }
+}
+
+/// Ends the session as `stop` does, running the stop hook.
+pub fn request_stop() {
+    let hook = {
+        let mut hook = STOP_HOOK.lock().unwrap();
+        STOP.store(true, Ordering::Relaxed);
+        hook.take()
+    };
+    if let Some(hook) = hook {
+        hook();
+    }
+}
+
+pub fn stop_requested() -> bool {
+    STOP.load(Ordering::Relaxed)
+}
+
+/// Whether a rotation was requested since the last call.
+pub fn take_rotate() -> bool {
+    ROTATE.swap(false, Ordering::Relaxed)
+}
+
+/// Sets what `stop` runs besides raising the flag, replacing an earlier
+/// hook. Runs at once if a stop already came in.
+pub fn on_stop(hook: impl FnOnce() + Send + 'static) {
+    let mut slot = STOP_HOOK.lock().unwrap();
+    if stop_requested() {
+        drop(slot);
+        hook();
+    } else {
+        *slot = Some(Box::new(hook));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handlers() -> Handlers {
+        Handlers { snapshot: Box::new(|| Ok("meminfo.txt".to_string())), rotate: false, pause: false }
+    }
+
+    #[test]
+    fn rejects_non_loopback_addresses() {
+        assert!(listen("0.0.0.0:0", handlers()).is_err());
+    }
+
+    #[test]
+    fn serves_connections_side_by_side() {
+        let socket = listen("127.0.0.1:0", handlers()).unwrap();
+        let Endpoint::Tcp(addr) = socket.endpoint else { unreachable!() };
+        // An idle connection must not keep the next one waiting.
+        let _idle = TcpStream::connect(addr).unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        writeln!(stream, "snapshot").unwrap();
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply).unwrap();
+        assert_eq!(reply, "ok meminfo.txt\n");
+    }
+
+    #[test]
+    fn a_new_session_is_not_stopped_by_the_last_one() {
+        begin_session();
+        drop(stop_after(Duration::from_millis(10)));
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!stop_requested());
+        request_stop();
+        begin_session();
+        assert!(!stop_requested());
+    }
+
+    #[test]
+    fn teardowns_run_once() {
+        use std::sync::atomic::AtomicUsize;
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        drop(on_teardown(|| {
+            RUNS.fetch_add(1, Ordering::Relaxed);
+        }));
+        assert_eq!(RUNS.load(Ordering::Relaxed), 1);
+        let pending = on_teardown(|| {
+            RUNS.fetch_add(1, Ordering::Relaxed);
+        });
+        run_teardowns();
+        drop(pending);
+        assert_eq!(RUNS.load(Ordering::Relaxed), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replaces_only_sockets_and_removes_its_own() {
+        let dir = std::env::temp_dir().join(format!("control_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("not_a_socket");
+        std::fs::write(&file, "keep").unwrap();
+        assert!(listen(file.to_str().unwrap(), handlers()).is_err());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "keep");
+
+        let path = dir.join("control.sock");
+        let path = path.to_str().unwrap();
+        drop(listen(path, handlers()).unwrap());
+        assert!(!std::path::Path::new(path).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
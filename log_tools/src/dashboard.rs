@@ -0,0 +1,292 @@
+//! `session --tui`: the session drawn full screen with ratatui and redrawn
+//! twice a second instead of progress output: total PSS and the heaps
+//! charted over time, a table of the app's threads per state, and the
+//! latest matched logcat lines. Keys: p pauses or resumes sampling, m adds a marker, e
+//! exports the timeline collected so far, q stops the session.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::symbols::Marker;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Axis, Block, Chart, Dataset, GraphType, List, Paragraph, Row, Table};
+use ratatui::{DefaultTerminal, Frame};
+
+use crate::timeline::{TimelineEntry, TimelineEvent};
+use crate::{control, markers, naming, output, pause, schema, units};
+
+pub const EXPORT_FILE_STEM: &str = "timeline_export";
+const REDRAW_INTERVAL: Duration = Duration::from_millis(500);
+/// Logcat lines kept for the screen; the timeline keeps all of them.
+const LOG_LINES_KEPT: usize = 200;
+const KEYS_HELP: &str = "p pause/resume   m mark   e export   q stop";
+/// The memory series drawn, with their colours.
+const SERIES: [(&str, Color); 4] = [("PSS", Color::Yellow), ("native", Color::Cyan), ("dalvik", Color::Green), ("graphics", Color::Magenta)];
+
+pub struct Dashboard {
+    package: String,
+    started: Instant,
+    /// The session is over; the next key closes the dashboard.
+    ended: AtomicBool,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Everything received, for exports and the final timeline.
+    entries: Vec<TimelineEntry>,
+    /// Seconds into the session and total PSS, native heap, Dalvik heap
+    /// and graphics per memory sample, in KB.
+    memory: Vec<(f64, [u64; 4])>,
+    threads: Option<(usize, BTreeMap<String, usize>)>,
+    log: VecDeque<String>,
+    marks: usize,
+    /// Outcome of the last key, shown in the header.
+    status: String,
+}
+
+impl Dashboard {
+    pub fn new(package: &str) -> Self {
+        Dashboard {
+            package: package.to_string(),
+            started: Instant::now(),
+            ended: AtomicBool::new(false),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Draws the entries arriving on `rx` until every sender is gone, and
+    /// returns them. Console output stays off meanwhile so nothing else
+    /// scribbles over the screen.
+    pub fn show(self: Arc<Self>, rx: Receiver<TimelineEntry>) -> Result<Vec<TimelineEntry>> {
+        let console = output::console_output();
+        output::set_console_output(false);
+        let result = ratatui::try_init().map_err(anyhow::Error::from).and_then(|mut terminal| self.run(&mut terminal, rx));
+        ratatui::restore();
+        output::set_console_output(console);
+        result?;
+        let state = std::mem::take(&mut *self.state.lock().unwrap());
+        Ok(state.entries)
+    }
+
+    fn run(self: &Arc<Self>, terminal: &mut DefaultTerminal, rx: Receiver<TimelineEntry>) -> Result<()> {
+        let keys = {
+            let dashboard = self.clone();
+            std::thread::spawn(move || dashboard.read_keys())
+        };
+        let mut next_draw = Instant::now();
+        loop {
+            match rx.recv_timeout(next_draw.saturating_duration_since(Instant::now())) {
+                Ok(entry) => self.state.lock().unwrap().add(entry),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            if Instant::now() >= next_draw {
+                self.draw(terminal)?;
+                next_draw = Instant::now() + REDRAW_INTERVAL;
+            }
+        }
+        self.ended.store(true, Ordering::Relaxed);
+        if !keys.is_finished() {
+            self.state.lock().unwrap().status = "session ended, press any key".to_string();
+            self.draw(terminal)?;
+        }
+        let _ = keys.join();
+        Ok(())
+    }
+
+    fn read_keys(&self) {
+        loop {
+            let key = match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+                Ok(_) => continue,
+                Err(_) => return,
+            };
+            if self.ended.load(Ordering::Relaxed) {
+                return;
+            }
+            let status = match key.code {
+                KeyCode::Char('p') => {
+                    let paused = !pause::is_paused();
+                    pause::set_paused(paused);
+                    if paused { "paused".to_string() } else { "resumed".to_string() }
+                }
+                KeyCode::Char('m') => self.mark(),
+                KeyCode::Char('e') => self.export().unwrap_or_else(|e| format!("export failed: {}", e)),
+                // Raw mode delivers Ctrl-C as a key rather than a signal.
+                KeyCode::Char('q') | KeyCode::Esc => return self.stop(),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return self.stop(),
+                _ => continue,
+            };
+            self.state.lock().unwrap().status = status;
+        }
+    }
+
+    fn stop(&self) {
+        control::request_stop();
+        self.state.lock().unwrap().status = "stopping".to_string();
+    }
+
+    fn mark(&self) -> String {
+        let label = {
+            let mut state = self.state.lock().unwrap();
+            state.marks += 1;
+            format!("mark {}", state.marks)
+        };
+        match markers::record_marker(&label) {
+            Ok(_) => format!("marker: {}", label),
+            Err(e) => format!("could not record marker {:?}: {}", label, e),
+        }
+    }
+
+    /// Writes the entries so far, in host time order; markers are in
+    /// `markers.jsonl` and join the final timeline.
+    fn export(&self) -> Result<String> {
+        let mut state = self.state.lock().unwrap();
+        state.entries.sort_by_key(|entry| entry.time.host_time_ms);
+        let json_file = naming::output_file(EXPORT_FILE_STEM, chrono::Local::now().format("%Y%m%d_%H%M%S"), "json");
+        std::fs::write(&json_file, schema::to_versioned_json("timeline", &state.entries)?)?;
+        Ok(format!("{} entries exported to {}", state.entries.len(), json_file))
+    }
+
+    fn draw(&self, terminal: &mut DefaultTerminal) -> Result<()> {
+        let state = self.state.lock().unwrap();
+        terminal.draw(|frame| state.render(frame, &self.package, self.started.elapsed()))?;
+        Ok(())
+    }
+}
+
+impl State {
+    fn add(&mut self, entry: TimelineEntry) {
+        match &entry.event {
+            TimelineEvent::Memory { total_pss, native_heap, dalvik_heap, graphics } => {
+                self.memory.push((entry.time.secs(), [*total_pss, *native_heap, *dalvik_heap, *graphics]));
+            }
+            TimelineEvent::Threads { count, by_state } => self.threads = Some((*count, by_state.clone())),
+            TimelineEvent::Log { line } => {
+                if self.log.len() == LOG_LINES_KEPT {
+                    self.log.pop_front();
+                }
+                self.log.push_back(line.clone());
+            }
+            TimelineEvent::Marker { .. } => {}
+        }
+        self.entries.push(entry);
+    }
+
+    /// Header, memory chart, threads beside the logcat lines, key help.
+    fn render(&self, frame: &mut Frame, package: &str, elapsed: Duration) {
+        let [header, memory, lower, help] =
+            Layout::vertical([Constraint::Length(1), Constraint::Percentage(50), Constraint::Fill(1), Constraint::Length(1)]).areas(frame.area());
+        let [threads, log] = Layout::horizontal([Constraint::Length(30), Constraint::Fill(1)]).areas(lower);
+
+        let secs = elapsed.as_secs();
+        let title = format!(
+            "{}  {:02}:{:02}{}  {}",
+            package,
+            secs / 60,
+            secs % 60,
+            if pause::is_paused() { "  PAUSED" } else { "" },
+            self.status
+        );
+        frame.render_widget(Paragraph::new(title).style(Style::new().add_modifier(Modifier::BOLD)), header);
+        self.render_memory(frame, memory);
+        self.render_threads(frame, threads);
+        self.render_log(frame, log);
+        frame.render_widget(Paragraph::new(KEYS_HELP).style(Style::new().add_modifier(Modifier::DIM)), help);
+    }
+
+    fn render_memory(&self, frame: &mut Frame, area: Rect) {
+        let Some((_, latest)) = self.memory.last() else {
+            frame.render_widget(Paragraph::new("waiting for the first sample").block(Block::bordered().title("Memory")), area);
+            return;
+        };
+        // The figures in their series' colours stand in for a legend.
+        let mut title = vec![Span::raw("Memory")];
+        for ((name, color), kb) in SERIES.iter().zip(latest) {
+            title.push(Span::styled(format!("  {} {}", name, units::kb(*kb)), *color));
+        }
+        let points: Vec<Vec<(f64, f64)>> =
+            (0..SERIES.len()).map(|i| self.memory.iter().map(|(secs, values)| (*secs, values[i] as f64)).collect()).collect();
+        let datasets = SERIES
+            .iter()
+            .zip(&points)
+            .map(|((name, color), data)| Dataset::default().name(*name).marker(Marker::Braille).graph_type(GraphType::Line).style(*color).data(data))
+            .collect();
+        let first = self.memory[0].0;
+        let last = self.memory[self.memory.len() - 1].0.max(first + 1.0);
+        let high = self.memory.iter().flat_map(|(_, values)| values).max().copied().unwrap_or(0).max(1);
+        let chart = Chart::new(datasets)
+            .block(Block::bordered().title(Line::from(title)))
+            .x_axis(Axis::default().bounds([first, last]).labels([format!("{:.0}s", first), format!("{:.0}s", last)]))
+            .y_axis(Axis::default().bounds([0.0, high as f64]).labels(["0".to_string(), units::kb(high)]));
+        frame.render_widget(chart, area);
+    }
+
+    fn render_threads(&self, frame: &mut Frame, area: Rect) {
+        let Some((count, by_state)) = &self.threads else {
+            frame.render_widget(Paragraph::new("waiting for the first poll").block(Block::bordered().title("Threads")), area);
+            return;
+        };
+        let bar_width = area.width.saturating_sub(15) as usize;
+        let rows = by_state.iter().map(|(state, n)| Row::new([state.clone(), n.to_string(), "█".repeat((*n).min(bar_width))]));
+        let table = Table::new(rows, [Constraint::Length(5), Constraint::Length(5), Constraint::Fill(1)])
+            .header(Row::new(["State", "Count", ""]).style(Style::new().add_modifier(Modifier::BOLD)))
+            .block(Block::bordered().title(format!("Threads  {}", count)));
+        frame.render_widget(table, area);
+    }
+
+    fn render_log(&self, frame: &mut Frame, area: Rect) {
+        let room = area.height.saturating_sub(2) as usize;
+        let lines = self.log.iter().skip(self.log.len().saturating_sub(room)).map(|line| Line::raw(line.replace('\t', " ")));
+        frame.render_widget(List::new(lines).block(Block::bordered().title(format!("Logcat  {} lines", self.log.len()))), area);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+
+    use super::*;
+    use crate::clocksync::SampleTime;
+
+    fn at(secs: u64) -> SampleTime {
+        SampleTime { timestamp: secs, elapsed_ms: secs * 1000, ..SampleTime::default() }
+    }
+
+    fn screen(state: &State) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| state.render(frame, "com.example.app", Duration::from_secs(75))).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer.content.chunks(buffer.area.width as usize).map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>() + "\n").collect()
+    }
+
+    #[test]
+    fn panels_wait_for_data_then_show_it() {
+        let mut state = State::default();
+        let empty = screen(&state);
+        assert!(empty.starts_with("com.example.app  01:15"));
+        assert!(empty.contains("waiting for the first sample") && empty.contains("waiting for the first poll"));
+
+        for secs in 0..5 {
+            let event = TimelineEvent::Memory { total_pss: 100_000 + secs * 2048, native_heap: 30_000, dalvik_heap: 20_000, graphics: 10_000 };
+            state.add(TimelineEntry { time: at(secs), event });
+        }
+        let by_state = BTreeMap::from([("R".to_string(), 2), ("S".to_string(), 40)]);
+        state.add(TimelineEntry { time: at(4), event: TimelineEvent::Threads { count: 42, by_state } });
+        state.add(TimelineEntry::log(at(4), "10-16 12:00:04.000 E/AndroidRuntime( 4242): FATAL EXCEPTION: main"));
+        let shown = screen(&state);
+        assert!(shown.contains("Threads  42") && shown.contains("Logcat  1 lines"));
+        assert!(shown.contains("E/AndroidRuntime( 4242): FATAL EXCEPTION: main"));
+        assert!(shown.lines().last().unwrap().starts_with(KEYS_HELP));
+        assert_eq!(state.entries.len(), 7);
+    }
+}
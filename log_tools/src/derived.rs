@@ -0,0 +1,257 @@
+//! Derived series from the config's "derived_metrics" list:
+//!
+//! ```json
+//! "derived_metrics": [
+//!     "rel_native = native_heap / total_pss",
+//!     "non_graphics = total_pss - graphics"
+//! ]
+//! ```
+//!
+//! Expressions use `+ - * /`, parentheses, numbers and the names of the
+//! memory sample fields or the composite panel series (`cpu_percent`,
+//! `fps`, `cpu_temp_c`, which are then polled), and a metric can use the
+//! ones defined before it. A metric without a value at a sample
+//! (a missing input, a division by zero) is left out of that sample.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow, bail};
+
+use crate::clocksync;
+
+pub const DERIVED_PLOT_FILE: &str = "derived_plot.png";
+
+/// Fields of a memory sample, in KB.
+pub const SAMPLE_METRICS: &[&str] =
+    &["total_pss", "native_heap", "dalvik_heap", "code", "stack", "graphics", "private_dirty", "shared_dirty"];
+
+/// Fields of a composite panel sample.
+pub const PANEL_METRICS: &[&str] = &["cpu_percent", "fps", "cpu_temp_c"];
+
+/// Every name an expression can use besides earlier derived metrics.
+pub fn known_metrics() -> Vec<&'static str> {
+    SAMPLE_METRICS.iter().chain(PANEL_METRICS).copied().collect()
+}
+
+pub struct DerivedMetric {
+    pub name: String,
+    expr: Expr,
+}
+
+enum Expr {
+    Number(f64),
+    Metric(String),
+    Negate(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Op(char),
+}
+
+/// Parses "name = expression" definitions, checking every name they use
+/// against `known` and the metrics defined before them.
+pub fn parse_definitions(definitions: &[String], known: &[&str]) -> Result<Vec<DerivedMetric>> {
+    let mut metrics: Vec<DerivedMetric> = Vec::new();
+    for definition in definitions {
+        let (name, expression) = definition
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Derived metric {:?} is not of the form \"name = expression\"", definition))?;
+        let name = name.trim();
+        if !is_name(name) {
+            bail!("Invalid derived metric name {:?}", name);
+        }
+        if known.contains(&name) || metrics.iter().any(|m| m.name == name) {
+            bail!("Derived metric {:?} is already defined", name);
+        }
+        let expr = parse_expression(expression).map_err(|e| anyhow!("Derived metric {:?}: {}", name, e))?;
+        let mut used = Vec::new();
+        expr.names(&mut used);
+        if let Some(unknown) = used.iter().find(|n| !known.contains(n) && !metrics.iter().any(|m| m.name == **n)) {
+            bail!("Derived metric {:?} uses unknown metric {:?}", name, unknown);
+        }
+        metrics.push(DerivedMetric { name: name.to_string(), expr });
+    }
+    Ok(metrics)
+}
+
+/// Whether any of `metrics` reads one of `names`, directly or through
+/// another derived metric.
+pub fn uses_any(metrics: &[DerivedMetric], names: &[&str]) -> bool {
+    metrics.iter().any(|m| {
+        let mut used = Vec::new();
+        m.expr.names(&mut used);
+        used.iter().any(|n| names.contains(n))
+    })
+}
+
+/// Computes `metrics` in order from the numeric fields of `values`; each
+/// result is added to `values` for the metrics after it.
+pub fn evaluate(metrics: &[DerivedMetric], values: &mut BTreeMap<String, f64>) -> BTreeMap<String, f64> {
+    let mut derived = BTreeMap::new();
+    for metric in metrics {
+        if let Some(value) = metric.expr.eval(values).filter(|v| v.is_finite()) {
+            values.insert(metric.name.clone(), value);
+            derived.insert(metric.name.clone(), value);
+        }
+    }
+    derived
+}
+
+/// Numeric fields of a serialized sample, without its time stamps.
+pub fn numeric_fields(sample: &serde_json::Value) -> BTreeMap<String, f64> {
+    sample
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(key, _)| !clocksync::TIME_FIELDS.contains(&key.as_str()))
+        .filter_map(|(key, value)| value.as_f64().map(|v| (key.clone(), v)))
+        .collect()
+}
+
+impl Expr {
+    fn eval(&self, values: &BTreeMap<String, f64>) -> Option<f64> {
+        match self {
+            Expr::Number(n) => Some(*n),
+            Expr::Metric(name) => values.get(name).copied(),
+            Expr::Negate(e) => e.eval(values).map(|v| -v),
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(values)?, b.eval(values)?);
+                match op {
+                    '+' => Some(a + b),
+                    '-' => Some(a - b),
+                    '*' => Some(a * b),
+                    _ => (b != 0.0).then(|| a / b),
+                }
+            }
+        }
+    }
+
+    fn names<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Metric(name) => out.push(name),
+            Expr::Negate(e) => e.names(out),
+            Expr::Binary(_, a, b) => {
+                a.names(out);
+                b.names(out);
+            }
+        }
+    }
+}
+
+fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = expression.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if "+-*/()".contains(c) {
+            tokens.push(Token::Op(c));
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' || c.is_ascii_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let word = &expression[start..end];
+            if is_name(word) {
+                tokens.push(Token::Name(word.to_string()));
+            } else {
+                tokens.push(Token::Number(word.parse().map_err(|_| anyhow!("invalid number {:?}", word))?));
+            }
+        } else {
+            bail!("unexpected {:?}", c);
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_expression(expression: &str) -> Result<Expr> {
+    let tokens = tokenize(expression)?;
+    let mut pos = 0;
+    let expr = parse_sum(&tokens, &mut pos)?;
+    match tokens.get(pos) {
+        None => Ok(expr),
+        Some(_) => bail!("unexpected input after position {}", pos),
+    }
+}
+
+fn parse_sum(tokens: &[Token], pos: &mut usize) -> Result<Expr> {
+    let mut expr = parse_product(tokens, pos)?;
+    while let Some(Token::Op(op @ ('+' | '-'))) = tokens.get(*pos) {
+        *pos += 1;
+        expr = Expr::Binary(*op, Box::new(expr), Box::new(parse_product(tokens, pos)?));
+    }
+    Ok(expr)
+}
+
+fn parse_product(tokens: &[Token], pos: &mut usize) -> Result<Expr> {
+    let mut expr = parse_factor(tokens, pos)?;
+    while let Some(Token::Op(op @ ('*' | '/'))) = tokens.get(*pos) {
+        *pos += 1;
+        expr = Expr::Binary(*op, Box::new(expr), Box::new(parse_factor(tokens, pos)?));
+    }
+    Ok(expr)
+}
+
+fn parse_factor(tokens: &[Token], pos: &mut usize) -> Result<Expr> {
+    let token = tokens.get(*pos).cloned().ok_or_else(|| anyhow!("expression ends early"))?;
+    *pos += 1;
+    match token {
+        Token::Number(n) => Ok(Expr::Number(n)),
+        Token::Name(name) => Ok(Expr::Metric(name)),
+        Token::Op('-') => Ok(Expr::Negate(Box::new(parse_factor(tokens, pos)?))),
+        Token::Op('(') => {
+            let expr = parse_sum(tokens, pos)?;
+            if tokens.get(*pos) != Some(&Token::Op(')')) {
+                bail!("missing closing parenthesis");
+            }
+            *pos += 1;
+            Ok(expr)
+        }
+        Token::Op(op) => bail!("unexpected {:?}", op),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definitions(lines: &[&str]) -> Result<Vec<DerivedMetric>> {
+        parse_definitions(&lines.iter().map(|l| l.to_string()).collect::<Vec<_>>(), &known_metrics())
+    }
+
+    #[test]
+    fn expressions_follow_precedence_and_earlier_metrics() {
+        let metrics = definitions(&["rest = total_pss - graphics * 2", "share = -(rest - 10) / total_pss", "none = code / stack"]).unwrap();
+        let mut values = BTreeMap::from([("total_pss".to_string(), 100.0), ("graphics".to_string(), 20.0), ("code".to_string(), 5.0), ("stack".to_string(), 0.0)]);
+        let derived = evaluate(&metrics, &mut values);
+        assert_eq!(derived.get("rest"), Some(&60.0));
+        assert_eq!(derived.get("share"), Some(&-0.5));
+        // Division by zero leaves the metric out of the sample.
+        assert_eq!(derived.get("none"), None);
+        assert!(uses_any(&metrics, &["graphics"]) && !uses_any(&metrics, &["fps"]));
+    }
+
+    #[test]
+    fn invalid_definitions_are_rejected() {
+        for bad in ["total_pss", "1x = code", "code = stack", "a = (code + stack", "a = code +", "a = code stack", "a = code % 2", "a = 1.2.3", "a = heap"] {
+            assert!(definitions(&[bad]).is_err(), "{}", bad);
+        }
+        assert!(definitions(&["a = code", "a = stack"]).is_err());
+        assert!(definitions(&["b = a * 2", "a = code"]).is_err());
+    }
+}
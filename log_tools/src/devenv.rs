@@ -0,0 +1,117 @@
+//! Device environment of a measurement session: system properties and
+//! settings, saved once per session so `diff-env` can explain why the same
+//! build measures differently on two devices or two days.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::compress;
+use crate::webview::WebViewProvider;
+
+pub const ENV_FILE_STEM: &str = "device_env";
+pub const SETTINGS_NAMESPACES: [&str; 3] = ["global", "system", "secure"];
+/// Settings identifying the device or its owner, never saved.
+const PRIVATE_KEYS: [&str; 5] = ["android_id", "bluetooth_address", "bluetooth_name", "device_name", "account"];
+/// Keys that differ between any two boots or units, left out of diffs.
+const VOLATILE_PREFIXES: [&str; 7] = [
+    "ro.serialno",
+    "ro.boot.serialno",
+    "ro.boottime.",
+    "init.svc_debug_pid.",
+    "ro.runtime.firstboot",
+    "sys.boot.reason",
+    "persist.sys.boot.reason",
+];
+
+// "[ro.build.fingerprint]: [google/oriole/oriole:14/...]"
+static PROP_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\[(.+?)\]: \[(.*)\]$").unwrap());
+
+#[derive(Serialize, Deserialize)]
+pub struct DeviceEnv {
+    pub props: BTreeMap<String, String>,
+    /// Namespace ("global", "system", "secure") to key and value.
+    pub settings: BTreeMap<String, BTreeMap<String, String>>,
+    /// WebView provider; absent in files from older versions.
+    #[serde(default)]
+    pub webview: Option<WebViewProvider>,
+}
+
+#[derive(Serialize)]
+pub struct EnvChange {
+    /// "prop <name>" or "<namespace> <key>".
+    pub key: String,
+    pub a: Option<String>,
+    pub b: Option<String>,
+}
+
+pub fn parse_getprop(output: &str) -> BTreeMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| PROP_REGEX.captures(line.trim()))
+        .map(|caps| (caps[1].to_string(), caps[2].to_string()))
+        .collect()
+}
+
+pub fn parse_settings(output: &str) -> BTreeMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .filter(|(key, _)| !PRIVATE_KEYS.iter().any(|private| key.contains(private)))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+fn diff_maps(prefix: &str, a: &BTreeMap<String, String>, b: &BTreeMap<String, String>, changes: &mut Vec<EnvChange>) {
+    let keys = a.keys().chain(b.keys().filter(|key| !a.contains_key(*key)));
+    for key in keys {
+        if VOLATILE_PREFIXES.iter().any(|volatile| key.starts_with(volatile)) {
+            continue;
+        }
+        let (value_a, value_b) = (a.get(key), b.get(key));
+        if value_a != value_b {
+            changes.push(EnvChange { key: format!("{} {}", prefix, key), a: value_a.cloned(), b: value_b.cloned() });
+        }
+    }
+}
+
+pub fn diff(a: &DeviceEnv, b: &DeviceEnv) -> Vec<EnvChange> {
+    let mut changes = Vec::new();
+    diff_maps("prop", &a.props, &b.props, &mut changes);
+    let empty = BTreeMap::new();
+    for namespace in SETTINGS_NAMESPACES {
+        let settings_a = a.settings.get(namespace).unwrap_or(&empty);
+        let settings_b = b.settings.get(namespace).unwrap_or(&empty);
+        diff_maps(namespace, settings_a, settings_b, &mut changes);
+    }
+    if a.webview != b.webview {
+        changes.push(EnvChange { key: "webview".to_string(), a: a.webview.as_ref().map(|w| w.to_string()), b: b.webview.as_ref().map(|w| w.to_string()) });
+    }
+    changes
+}
+
+/// `path` itself, or the newest `<stem>_*.json` file in it when it is a
+/// session directory.
+pub fn resolve(path: &str, stem: &str) -> Result<String> {
+    if !Path::new(path).is_dir() {
+        return Ok(path.to_string());
+    }
+    let mut newest = None;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(stem) && compress::plain_name(&name).ends_with(".json") {
+            let modified = entry.metadata()?.modified()?;
+            if newest.as_ref().is_none_or(|(time, _)| modified > *time) {
+                newest = Some((modified, entry.path()));
+            }
+        }
+    }
+    newest
+        .map(|(_, file)| file.to_string_lossy().into_owned())
+        .ok_or_else(|| anyhow!("No {}_*.json in {}", stem, path))
+}
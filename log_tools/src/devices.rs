@@ -0,0 +1,71 @@
+//! Attached devices from `adb devices -l`, for `devices` and for telling
+//! the user to pick one with `--serial` when several are attached.
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+
+pub const DEVICES_ARGS: &[&str] = &["devices", "-l"];
+/// Subcommands working on files only, which run whatever is attached.
+pub const HOST_SUBCOMMANDS: &[&str] = &["analyze", "report", "compare", "diff-env", "mark", "search", "trend", "bench-filter"];
+
+#[derive(Serialize)]
+pub struct Device {
+    pub serial: String,
+    /// "device", "offline", "unauthorized", "recovery", ...
+    pub state: String,
+    pub model: Option<String>,
+    pub product: Option<String>,
+    pub transport_id: Option<String>,
+}
+
+impl Device {
+    pub fn is_online(&self) -> bool {
+        self.state == "device"
+    }
+}
+
+/// Parses lines such as
+/// "emulator-5554  device product:sdk_gphone64 model:sdk_gphone64 device:emu64a transport_id:1".
+pub fn parse_devices(output: &str) -> Vec<Device> {
+    output
+        .lines()
+        .filter(|line| !line.starts_with("List of devices") && !line.starts_with('*'))
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let serial = fields.next()?.to_string();
+            let state = fields.next()?.to_string();
+            let mut device = Device { serial, state, model: None, product: None, transport_id: None };
+            for field in fields {
+                match field.split_once(':') {
+                    Some(("model", value)) => device.model = Some(value.to_string()),
+                    Some(("product", value)) => device.product = Some(value.to_string()),
+                    Some(("transport_id", value)) => device.transport_id = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+            Some(device)
+        })
+        .collect()
+}
+
+/// `-s <serial>` ahead of an adb command, or nothing to let adb pick the
+/// device (it reads ANDROID_SERIAL itself).
+pub fn serial_args(serial: Option<&str>) -> Vec<&str> {
+    serial.map(|serial| vec!["-s", serial]).unwrap_or_default()
+}
+
+/// Fails early, instead of on the first adb command, when `serial` is not
+/// attached or when none is given and adb would have to choose between
+/// several online devices.
+pub fn check_target(devices: &[Device], serial: Option<&str>) -> Result<()> {
+    let online: Vec<&str> = devices.iter().filter(|d| d.is_online()).map(|d| d.serial.as_str()).collect();
+    match serial {
+        Some(serial) => match devices.iter().find(|d| d.serial == serial) {
+            Some(device) if !device.is_online() => Err(anyhow!("Device {} is {}", serial, device.state)),
+            Some(_) => Ok(()),
+            None => Err(anyhow!("Device {} is not attached; attached: {}", serial, online.join(", "))),
+        },
+        None if online.len() > 1 => Err(anyhow!("{} devices attached ({}); pick one with --serial", online.len(), online.join(", "))),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,49 @@
+//! Screen and animation settings normalization for measurements. The
+//! values in place before `device prep` are saved so `device restore` can
+//! put them back.
+
+use serde::{Deserialize, Serialize};
+
+/// Originals saved by `device prep`, removed again by `device restore`.
+pub const PREP_STATE_FILE: &str = "device_prep_state.json";
+
+/// Keep the screen on while plugged into AC, USB or wireless power.
+const STAY_ON_ALL_SOURCES: &str = "7";
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedSetting {
+    pub namespace: String,
+    pub key: String,
+    /// `None` when the setting was unset; restoring deletes it.
+    pub value: Option<String>,
+}
+
+/// `(namespace, key, value)` triples applied by `device prep`.
+pub fn prep_settings(brightness: u32) -> Vec<(&'static str, &'static str, String)> {
+    vec![
+        ("system", "screen_brightness_mode", "0".to_string()),
+        ("system", "screen_brightness", brightness.to_string()),
+        ("global", "stay_on_while_plugged_in", STAY_ON_ALL_SOURCES.to_string()),
+        ("global", "window_animation_scale", "0".to_string()),
+        ("global", "transition_animation_scale", "0".to_string()),
+        ("global", "animator_duration_scale", "0".to_string()),
+    ]
+}
+
+/// `settings get` prints "null" for unset keys.
+pub fn parse_setting_value(output: &str) -> Option<String> {
+    match output.trim() {
+        "" | "null" => None,
+        value => Some(value.to_string()),
+    }
+}
+
+/// Shell arguments putting `saved` back, deleting keys that were unset.
+pub fn restore_args(saved: &SavedSetting) -> Vec<String> {
+    let mut args = vec!["settings".to_string()];
+    match &saved.value {
+        Some(value) => args.extend(["put".to_string(), saved.namespace.clone(), saved.key.clone(), value.clone()]),
+        None => args.extend(["delete".to_string(), saved.namespace.clone(), saved.key.clone()]),
+    }
+    args
+}
@@ -0,0 +1,64 @@
+//! DMA-BUF attribution for the target process, via `dmabuf_dump <pid>`
+//! (Android 12+) or the dma-buf entries of `/proc/<pid>/fdinfo`.
+
+use serde::Serialize;
+
+use crate::clocksync::SampleTime;
+
+#[derive(Serialize)]
+pub struct DmaBufSample {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    pub rss_kb: u64,
+    /// Only `dmabuf_dump` reports a proportional share; fdinfo gives 0.
+    pub pss_kb: u64,
+    pub buffers: u64,
+}
+
+/// Parses `dmabuf_dump <pid>`: one row per buffer and a "PROCESS TOTAL" row.
+pub fn parse_dmabuf_dump(output: &str, time: SampleTime) -> Option<DmaBufSample> {
+    let mut buffers = 0;
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if let ["PROCESS", "TOTAL", rss, "kB", pss, "kB", ..] = fields.as_slice() {
+            return Some(DmaBufSample {
+                time,
+                rss_kb: rss.parse().ok()?,
+                pss_kb: pss.parse().ok()?,
+                buffers,
+            });
+        }
+        if fields.iter().filter(|f| **f == "kB").count() == 2 {
+            buffers += 1;
+        }
+    }
+    None
+}
+
+/// Sums `size:` of every fdinfo block that carries an `exp_name:` line,
+/// which only dma-buf file descriptors have.
+pub fn parse_fdinfo(output: &str, time: SampleTime) -> Option<DmaBufSample> {
+    let mut pending_size = None;
+    let mut total_bytes = 0u64;
+    let mut buffers = 0;
+    let mut saw_fdinfo = false;
+    for line in output.lines() {
+        let mut parts = line.splitn(2, ':');
+        let (key, value) = (parts.next().unwrap_or("").trim(), parts.next().unwrap_or("").trim());
+        match key {
+            "pos" => {
+                saw_fdinfo = true;
+                pending_size = None;
+            }
+            "size" => pending_size = value.parse::<u64>().ok(),
+            "exp_name" => {
+                if let Some(size) = pending_size.take() {
+                    total_bytes += size;
+                    buffers += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    saw_fdinfo.then_some(DmaBufSample { time, rss_kb: total_bytes / 1024, pss_kb: 0, buffers })
+}
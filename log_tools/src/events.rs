@@ -0,0 +1,75 @@
+//! `--events-file`: markers from a `timestamp,label` CSV written by other
+//! tools or the app itself, e.g. analytics events, drawn on the session
+//! plots and CSVs next to the markers recorded here. The file is read when
+//! the session ends, so it can be written while the session runs.
+//!
+//! Timestamps are Unix epoch milliseconds or seconds, RFC 3339, or
+//! "YYYY-MM-DD HH:MM:SS[.fff]" in local time. They are host wall-clock
+//! times unless `--events-device-clock` says they come from the device.
+
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone};
+
+use crate::clocksync::ClockSync;
+use crate::markers::Marker;
+
+/// Epoch values above this are milliseconds, below it seconds (1e11 s is
+/// in the year 5138, 1e11 ms in 1973).
+const EPOCH_MS_THRESHOLD: f64 = 1e11;
+
+/// Events of `path` on the host timeline; rows that do not parse are
+/// skipped with a warning.
+pub fn load_events(path: &str, clock: &ClockSync, device_clock: bool) -> Result<Vec<Marker>> {
+    let text = std::fs::read_to_string(path)?;
+    let local_offset = if device_clock { FixedOffset::east_opt(clock.device_utc_offset_secs) } else { None };
+    let mut events = Vec::new();
+    let mut skipped = 0;
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some((time, label)) = line.split_once(',') else {
+            skipped += 1;
+            continue;
+        };
+        match parse_time_ms(time.trim(), local_offset) {
+            Some(ms) => {
+                let time_ms = if device_clock { ms - clock.offset_at(ms) } else { ms };
+                events.push(Marker { time_ms, label: unquote(label.trim()) });
+            }
+            // A header row.
+            None if i == 0 => {}
+            None => skipped += 1,
+        }
+    }
+    if skipped > 0 {
+        warn!(format!("Skipped {} unreadable row(s) of {}, expected timestamp,label", skipped, path));
+    }
+    Ok(events)
+}
+
+/// Epoch milliseconds of `text`. Local times without an offset are read
+/// in `local_offset`, or the host's time zone.
+fn parse_time_ms(text: &str, local_offset: Option<FixedOffset>) -> Option<i64> {
+    if let Ok(value) = text.parse::<f64>() {
+        return Some(if value >= EPOCH_MS_THRESHOLD { value as i64 } else { (value * 1000.0) as i64 });
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.timestamp_millis());
+    }
+    let naive = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S%.f")
+        .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f"))
+        .ok()?;
+    match local_offset {
+        Some(offset) => offset.from_local_datetime(&naive).single().map(|t| t.timestamp_millis()),
+        None => Local.from_local_datetime(&naive).earliest().map(|t| t.timestamp_millis()),
+    }
+}
+
+/// A CSV field without its quotes, `""` read as one quote.
+fn unquote(field: &str) -> String {
+    match field.strip_prefix('"').and_then(|f| f.strip_suffix('"')) {
+        Some(inner) => inner.replace("\"\"", "\""),
+        None => field.to_string(),
+    }
+}
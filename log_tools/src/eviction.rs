@@ -0,0 +1,82 @@
+//! `eviction`: how long the app survives in the background cache while
+//! other apps compete for memory. Each run launches the app, sends it
+//! home, then launches the filler packages in turn every
+//! `FILLER_INTERVAL_SECS` until the low memory killer takes its process or
+//! the timeout passes. Survival times and PSS at the kill are also written
+//! as an iterations file, so `compare` can tell two builds apart.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::exitinfo::ProcessExit;
+
+/// Time given to the launched app before it is sent home.
+pub const SETTLE_SECS: u64 = 5;
+pub const FILLER_INTERVAL_SECS: u64 = 10;
+pub const POLL_SECS: u64 = 1;
+pub const HOME_CMD: &[&str] = &["input", "keyevent", "KEYCODE_HOME"];
+
+#[derive(Serialize)]
+pub struct EvictionRun {
+    pub run: u32,
+    pub evicted: bool,
+    /// From going home until the process was gone, or the timeout.
+    pub survival_secs: u64,
+    /// Last PSS sampled while the process was alive, in KB.
+    pub last_pss_kb: Option<u64>,
+    /// From ApplicationExitInfo (API 30+), along with the reason.
+    pub exit_pss_kb: Option<u64>,
+    pub exit_reason: Option<String>,
+    pub fillers_launched: u32,
+}
+
+/// The recorded runs, warm-up runs left out, and their iteration values
+/// per metric for `compare`.
+pub struct EvictionReport {
+    pub runs: Vec<EvictionRun>,
+    pub series: BTreeMap<String, Vec<f64>>,
+}
+
+impl EvictionRun {
+    /// The recorded PSS at death when the device has it, else the last
+    /// sampled one.
+    pub fn pss_at_kill_kb(&self) -> Option<u64> {
+        if !self.evicted {
+            return None;
+        }
+        self.exit_pss_kb.or(self.last_pss_kb)
+    }
+}
+
+/// The exit record of process `pid`, if dumpsys still lists it.
+pub fn exit_of(exits: Vec<ProcessExit>, pid: &str) -> Option<ProcessExit> {
+    exits.into_iter().find(|e| e.pid.to_string() == pid)
+}
+
+/// KB in an exit-info size such as "28MB", "512KB" or "1.2GB"; a bare
+/// number is taken as KB.
+pub fn parse_size_kb(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let split = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+    let value: f64 = text[..split].parse().ok()?;
+    let factor = match text[split..].to_ascii_uppercase().as_str() {
+        "" | "K" | "KB" => 1.0,
+        "M" | "MB" => 1024.0,
+        "G" | "GB" => 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some((value * factor) as u64)
+}
+
+/// Per-metric values for an iterations file. Runs that reached the
+/// timeout count with the timeout as their survival time.
+pub fn iteration_series(runs: &[EvictionRun]) -> BTreeMap<String, Vec<f64>> {
+    let mut series = BTreeMap::new();
+    series.insert("survival_s".to_string(), runs.iter().map(|r| r.survival_secs as f64).collect());
+    let pss: Vec<f64> = runs.iter().filter_map(|r| r.pss_at_kill_kb()).map(|kb| kb as f64).collect();
+    if !pss.is_empty() {
+        series.insert("pss_at_kill_kb".to_string(), pss);
+    }
+    series
+}
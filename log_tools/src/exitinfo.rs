@@ -0,0 +1,74 @@
+//! Recent process deaths from `dumpsys activity exit-info <package>`
+//! (ApplicationExitInfo, API 30+).
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+/// First release that records ApplicationExitInfo.
+pub const EXIT_INFO_MIN_SDK: u32 = 30;
+
+static TIMESTAMP_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"timestamp=(\S+ \S+)").unwrap());
+static PID_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\bpid=(\d+)").unwrap());
+static PROCESS_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\bprocess=(\S+)").unwrap());
+// "reason=3 (LOW_MEMORY) subreason=0 (UNKNOWN)"
+static REASON_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\breason=\d+ \(([^)]+)\)").unwrap());
+static SUBREASON_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\bsubreason=\d+ \(([^)]+)\)").unwrap());
+static IMPORTANCE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\bimportance=(\d+)").unwrap());
+static PSS_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\bpss=(\S+)").unwrap());
+static DESCRIPTION_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\bdescription=(.*)$").unwrap());
+
+#[derive(Default, Serialize)]
+pub struct ProcessExit {
+    /// Device local time as printed by dumpsys.
+    pub timestamp: String,
+    pub pid: u32,
+    pub process: String,
+    /// e.g. ANR, CRASH, CRASH_NATIVE, LOW_MEMORY, EXCESSIVE_RESOURCE_USAGE.
+    pub reason: String,
+    pub subreason: String,
+    pub importance: u32,
+    pub pss: String,
+    pub description: String,
+}
+
+/// Parses the "ApplicationExitInfo #N:" records, newest first as dumpsys
+/// lists them.
+pub fn parse_exit_info(output: &str) -> Vec<ProcessExit> {
+    let mut exits: Vec<ProcessExit> = Vec::new();
+    for line in output.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("ApplicationExitInfo #") {
+            exits.push(ProcessExit::default());
+            continue;
+        }
+        let Some(exit) = exits.last_mut() else {
+            continue;
+        };
+        if let Some(caps) = TIMESTAMP_REGEX.captures(trimmed) {
+            exit.timestamp = caps[1].to_string();
+        }
+        if let Some(caps) = PID_REGEX.captures(trimmed) {
+            exit.pid = caps[1].parse().unwrap_or(0);
+        }
+        if let Some(caps) = PROCESS_REGEX.captures(trimmed) {
+            exit.process = caps[1].to_string();
+        }
+        if let Some(caps) = REASON_REGEX.captures(trimmed) {
+            exit.reason = caps[1].to_string();
+        }
+        if let Some(caps) = SUBREASON_REGEX.captures(trimmed) {
+            exit.subreason = caps[1].to_string();
+        }
+        if let Some(caps) = IMPORTANCE_REGEX.captures(trimmed) {
+            exit.importance = caps[1].parse().unwrap_or(0);
+        }
+        if let Some(caps) = PSS_REGEX.captures(trimmed) {
+            exit.pss = caps[1].to_string();
+        }
+        if let Some(caps) = DESCRIPTION_REGEX.captures(trimmed) {
+            exit.description = caps[1].trim().to_string();
+        }
+    }
+    exits
+}
@@ -0,0 +1,116 @@
+//! `bench-filter`: a saved capture replayed through the filter rules a
+//! live capture with the same config uses, timing each rule on its own and
+//! all of them together the way a pipeline worker runs them (decoding
+//! included). The busiest second of the capture stands in for the
+//! device's log volume, so the rates show how much headroom the rule set
+//! has before live capture starts dropping batches.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::filters::TagFilter;
+use crate::pipeline::{self, FilterOptions};
+use crate::{LogAnalyzerConfig, addresses, appmetrics, console, leakcanary, queries, reboot, selinux, stability, webview};
+
+/// Rules are timed over the lines again until this much time went by, so
+/// small captures still give a stable rate.
+const MIN_TIMING: Duration = Duration::from_millis(200);
+
+#[derive(Serialize)]
+pub struct RuleRate {
+    pub rule: String,
+    /// Lines the rule lets through on its own.
+    pub kept: usize,
+    pub lines_per_sec: f64,
+}
+
+#[derive(Serialize)]
+pub struct FilterBench {
+    pub lines: usize,
+    pub bytes: usize,
+    /// Most lines stamped with the same second, if the capture has
+    /// logcat timestamps.
+    pub peak_lines_per_sec: Option<usize>,
+    /// Pipeline workers a live capture on this host runs.
+    pub workers: usize,
+    pub rules: Vec<RuleRate>,
+    /// Every rule together, decoding included, on one worker.
+    pub combined: RuleRate,
+}
+
+impl FilterBench {
+    /// Lines per second the pipeline keeps up with on all its workers.
+    pub fn capacity(&self) -> f64 {
+        self.combined.lines_per_sec * self.workers as f64
+    }
+}
+
+type Rule<'a> = (String, Box<dyn Fn(&str) -> bool + 'a>);
+
+/// Times the rules of `config` over `raw`, a capture as read from disk.
+pub fn run(config: &LogAnalyzerConfig, raw: &[u8]) -> Result<FilterBench> {
+    let options = FilterOptions::from_config(config)?;
+    let raw_lines: Vec<&[u8]> = raw.split_inclusive(|b| *b == b'\n').collect();
+    let text: Vec<String> = raw_lines.iter().map(|line| console::decode(line)).collect();
+
+    let mut rules: Vec<Rule> = vec![("keyword_regex".to_string(), Box::new(|line| options.regex.is_match(line)))];
+    for name in &config.filter_presets {
+        let filter = TagFilter::load(std::slice::from_ref(name), &config.presets)?;
+        rules.push((format!("preset {}", name), Box::new(move |line| filter.allows(line))));
+    }
+    if options.crash_triggers {
+        rules.push((
+            "crash triggers".to_string(),
+            Box::new(|line| pipeline::crash_trigger(line).is_some() || addresses::is_native_crash_line(line)),
+        ));
+    }
+    if options.selinux {
+        rules.push(("selinux denials".to_string(), Box::new(selinux::is_denial)));
+    }
+    if options.slow_queries {
+        rules.push(("slow queries".to_string(), Box::new(queries::is_slow_query_log)));
+    }
+    rules.push(("app metrics".to_string(), Box::new(appmetrics::is_metric)));
+    rules.push(("webview crashes".to_string(), Box::new(webview::is_renderer_crash)));
+    rules.push(("leakcanary".to_string(), Box::new(leakcanary::is_leakcanary_line)));
+    rules.push(("stability reports".to_string(), Box::new(stability::is_report_line)));
+
+    let rules = rules.iter().map(|(rule, matches)| time_rule(rule, &text, |line: &String| matches(line))).collect();
+    let combined = time_rule("all rules", &raw_lines, |line: &&[u8]| pipeline::keep(&options, &console::decode(line)).is_some());
+    Ok(FilterBench {
+        lines: text.len(),
+        bytes: raw.len(),
+        peak_lines_per_sec: peak_rate(&text),
+        workers: pipeline::worker_count(),
+        rules,
+        combined,
+    })
+}
+
+fn time_rule<T>(rule: &str, lines: &[T], matches: impl Fn(&T) -> bool) -> RuleRate {
+    let started = Instant::now();
+    let kept = lines.iter().filter(|line| matches(line)).count();
+    let mut passes = 1;
+    while !lines.is_empty() && started.elapsed() < MIN_TIMING {
+        for line in lines {
+            std::hint::black_box(matches(line));
+        }
+        passes += 1;
+    }
+    let secs = started.elapsed().as_secs_f64().max(f64::EPSILON);
+    RuleRate { rule: rule.to_string(), kept, lines_per_sec: (lines.len() * passes) as f64 / secs }
+}
+
+fn peak_rate(lines: &[String]) -> Option<usize> {
+    let mut per_second: HashMap<&str, usize> = HashMap::new();
+    for line in lines {
+        // "MM-DD HH:MM:SS" of "MM-DD HH:MM:SS.mmm".
+        if let Some(second) = reboot::logcat_timestamp(line).and_then(|stamp| stamp.get(..14)) {
+            *per_second.entry(second).or_insert(0) += 1;
+        }
+    }
+    per_second.into_values().max()
+}
@@ -0,0 +1,97 @@
+//! Built-in logcat filter presets (`--preset`), kept as data files under
+//! `filters/` in the crate: lines of `exclude <tag>` drop a tag, lines of
+//! `include <tag>` keep only the listed tags. A trailing `*` matches any
+//! tag with that prefix. Several presets combine: their exclusions add up,
+//! and so do their inclusions.
+//!
+//! The config's `presets` adds team presets by name, each a regex: lines
+//! matching it are kept, like an inclusion.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+pub const PRESETS: &[(&str, &str)] = &[
+    ("app-only", include_str!("../filters/app-only.txt")),
+    ("no-gms", include_str!("../filters/no-gms.txt")),
+    ("network", include_str!("../filters/network.txt")),
+];
+
+// "10-16 16:02:03.000 E/Tag( 1234): message", also as rendered from `logcat -B`.
+static TAG_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\S+ \S+ [VDIWEFS]/([^(]*?)\s*\(").unwrap());
+
+pub fn preset_names() -> Vec<&'static str> {
+    PRESETS.iter().map(|(name, _)| *name).collect()
+}
+
+/// Tag of a `logcat -v time` line.
+pub fn line_tag(line: &str) -> Option<&str> {
+    TAG_REGEX.captures(line).and_then(|caps| caps.get(1)).map(|tag| tag.as_str())
+}
+
+#[derive(Default)]
+pub struct TagFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    /// Regexes of config presets, matched against the whole line.
+    keep: Vec<Regex>,
+}
+
+fn matches_any(patterns: &[String], tag: &str) -> bool {
+    patterns.iter().any(|pattern| match pattern.strip_suffix('*') {
+        Some(prefix) => tag.starts_with(prefix),
+        None => tag == pattern,
+    })
+}
+
+impl TagFilter {
+    /// The presets named, in any order, built in or from `custom`.
+    pub fn load(names: &[String], custom: &BTreeMap<String, String>) -> Result<TagFilter> {
+        if let Some(name) = custom.keys().find(|name| preset_names().contains(&name.as_str())) {
+            return Err(anyhow!("Config preset {:?} has the name of a built-in preset", name));
+        }
+        let mut filter = TagFilter::default();
+        for name in names {
+            if let Some(pattern) = custom.get(name) {
+                let regex = Regex::new(pattern).map_err(|e| anyhow!("Config preset {}: {}", name, e))?;
+                filter.keep.push(regex);
+                continue;
+            }
+            let (_, text) = PRESETS.iter().find(|(preset, _)| preset == name).ok_or_else(|| {
+                let known: Vec<&str> = preset_names().into_iter().chain(custom.keys().map(String::as_str)).collect();
+                anyhow!("Unknown preset {:?}, expected one of {}", name, known.join(", "))
+            })?;
+            filter.add(name, text)?;
+        }
+        Ok(filter)
+    }
+
+    fn add(&mut self, name: &str, text: &str) -> Result<()> {
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(char::is_whitespace) {
+                Some(("include", tag)) => self.include.push(tag.trim().to_string()),
+                Some(("exclude", tag)) => self.exclude.push(tag.trim().to_string()),
+                _ => return Err(anyhow!("Preset {} line {}: expected include <tag> or exclude <tag>", name, n + 1)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a line gets past the presets. Lines without a tag, such as
+    /// logcat's "--------- beginning of" separators, always do.
+    pub fn allows(&self, line: &str) -> bool {
+        let Some(tag) = line_tag(line) else {
+            return true;
+        };
+        let kept = (self.include.is_empty() && self.keep.is_empty())
+            || matches_any(&self.include, tag)
+            || self.keep.iter().any(|regex| regex.is_match(line));
+        !matches_any(&self.exclude, tag) && kept
+    }
+}
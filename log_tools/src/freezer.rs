@@ -0,0 +1,44 @@
+//! Cached-apps freezer tracking via the cgroup v2 `cgroup.freeze` file
+//! (Android 11+), e.g. `/sys/fs/cgroup/uid_10123/pid_4567/cgroup.freeze`.
+
+use serde::Serialize;
+
+#[derive(Clone, Serialize)]
+pub struct FrozenInterval {
+    pub start: u64,
+    pub end: u64,
+}
+
+pub fn freeze_state_cmd(pid: &str) -> String {
+    format!("cat /sys/fs/cgroup/uid_*/pid_{}/cgroup.freeze 2>/dev/null", pid)
+}
+
+/// `None` when the file does not exist (no freezer, or process gone).
+pub fn parse_freeze_state(output: &str) -> Option<bool> {
+    match output.trim() {
+        "1" => Some(true),
+        "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Folds per-sample freeze states into closed intervals; an interval still
+/// open at the end of the run is closed at `end`.
+pub fn frozen_intervals(states: &[(u64, bool)], end: u64) -> Vec<FrozenInterval> {
+    let mut intervals = Vec::new();
+    let mut frozen_since = None;
+    for &(timestamp, frozen) in states {
+        match (frozen, frozen_since) {
+            (true, None) => frozen_since = Some(timestamp),
+            (false, Some(since)) => {
+                intervals.push(FrozenInterval { start: since, end: timestamp });
+                frozen_since = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(since) = frozen_since {
+        intervals.push(FrozenInterval { start: since, end });
+    }
+    intervals
+}
@@ -0,0 +1,175 @@
+//! Caps on the tool's own footprint, so an unattended run with a too broad
+//! regex cannot fill the host's disk or memory overnight:
+//!
+//! ```json
+//! "guard": {
+//!     "max_output_mb": 2048,
+//!     "max_rss_mb": 1024,
+//!     "max_open_files": 512,
+//!     "action": "downsample"
+//! }
+//! ```
+//!
+//! With the default `"stop"` action the session ends, writing its reports,
+//! once a cap is passed. `"downsample"` keeps one logcat line in ten (or
+//! one memory sample in ten) past a cap and stops at twice the cap.
+//! Output size counts logcat capture bytes before compression and every
+//! timestamped file the session has written, snapshots and heap dumps
+//! included. Memory and file handles are read from /proc/self, so only
+//! on Linux.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::naming;
+
+/// How often usage is measured.
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// One in this many lines or samples is kept while downsampling.
+pub const DOWNSAMPLE_FACTOR: u64 = 10;
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GuardAction {
+    #[default]
+    Stop,
+    Downsample,
+}
+
+impl GuardAction {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "stop" => Ok(GuardAction::Stop),
+            "downsample" => Ok(GuardAction::Downsample),
+            other => Err(anyhow!("Unknown guard action {:?}, expected stop or downsample", other)),
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct GuardConfig {
+    pub max_output_mb: Option<u64>,
+    pub max_rss_mb: Option<u64>,
+    pub max_open_files: Option<u64>,
+    #[serde(default)]
+    pub action: GuardAction,
+}
+
+impl GuardConfig {
+    pub fn is_set(&self) -> bool {
+        self.max_output_mb.is_some() || self.max_rss_mb.is_some() || self.max_open_files.is_some()
+    }
+}
+
+pub enum Verdict {
+    Continue,
+    /// Keeping one in `DOWNSAMPLE_FACTOR` from now on, for the reason given.
+    Downsample(String),
+    Stop(String),
+}
+
+pub struct Guard {
+    config: GuardConfig,
+    /// Output files named before the session are not its own.
+    first_file: usize,
+    last_check: Option<Instant>,
+    downsampling: bool,
+    seen: u64,
+}
+
+impl Guard {
+    /// `None` without any cap.
+    pub fn new(config: &GuardConfig) -> Option<Guard> {
+        if !config.is_set() {
+            return None;
+        }
+        if (config.max_rss_mb.is_some() || config.max_open_files.is_some()) && !cfg!(target_os = "linux") {
+            warn!("Memory and file handle caps are only enforced on Linux");
+        }
+        Some(Guard { config: config.clone(), first_file: naming::issued_count(), last_check: None, downsampling: false, seen: 0 })
+    }
+
+    /// Measures usage, at most every couple of seconds, against the caps;
+    /// `output_bytes` is what the session has streamed to its capture
+    /// file so far, the files it named are added here.
+    pub fn check(&mut self, output_bytes: u64) -> Verdict {
+        if self.last_check.is_some_and(|at| at.elapsed() < CHECK_INTERVAL) {
+            return Verdict::Continue;
+        }
+        self.last_check = Some(Instant::now());
+        let output_bytes = output_bytes + naming::issued_bytes(self.first_file);
+        let usage = [
+            ("output", Some(output_bytes / (1024 * 1024)), self.config.max_output_mb, "MB"),
+            ("memory", own_rss_mb(), self.config.max_rss_mb, "MB"),
+            ("open files", own_open_files(), self.config.max_open_files, ""),
+        ];
+        // Downsampling stops at twice the cap.
+        let stop_factor = if self.config.action == GuardAction::Downsample { 2 } else { 1 };
+        let mut over = None;
+        for (what, used, cap, unit) in usage {
+            let (Some(used), Some(cap)) = (used, cap) else {
+                continue;
+            };
+            let reason = format!("{} at {}{} passed the cap of {}{}", what, used, unit, cap, unit);
+            if used >= cap.saturating_mul(stop_factor) {
+                return Verdict::Stop(reason);
+            }
+            if used >= cap {
+                over = Some(reason);
+            }
+        }
+        match over {
+            Some(reason) if !self.downsampling => {
+                self.downsampling = true;
+                Verdict::Downsample(reason)
+            }
+            _ => Verdict::Continue,
+        }
+    }
+
+    /// Whether the next line or sample is kept.
+    pub fn keep(&mut self) -> bool {
+        if !self.downsampling {
+            return true;
+        }
+        self.seen += 1;
+        self.seen % DOWNSAMPLE_FACTOR == 1
+    }
+}
+
+/// Resident memory of this process, from the VmRSS line of
+/// /proc/self/status.
+fn own_rss_mb() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb / 1024)
+}
+
+fn own_open_files() -> Option<u64> {
+    Some(std::fs::read_dir("/proc/self/fd").ok()?.count() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_files_the_session_named() {
+        let dir = std::env::temp_dir().join(format!("guard_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let before = naming::output_file(&dir.join("before").to_string_lossy(), 1, "bin");
+        std::fs::write(&before, vec![0u8; 2 * 1024 * 1024]).unwrap();
+
+        let config = GuardConfig { max_output_mb: Some(1), ..Default::default() };
+        let mut guard = Guard::new(&config).unwrap();
+        assert!(matches!(guard.check(0), Verdict::Continue));
+        guard.last_check = None;
+        let dump = naming::output_file(&dir.join("heap_dump").to_string_lossy(), 1, "hprof");
+        std::fs::write(&dump, vec![0u8; 1024 * 1024]).unwrap();
+        assert!(matches!(guard.check(0), Verdict::Stop(_)));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,352 @@
+//! Class histograms from Java heap dumps (`am dumpheap`), in the Android
+//! format as pulled or converted with hprof-conv. Each class gets its
+//! instance count and shallow bytes, the field data of its instances or
+//! the elements of its arrays. Comparing the histograms of two dumps
+//! taken some time apart gives the classes growing between them, a first
+//! leak-suspect list before opening the dumps in a heap analyzer.
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+/// Where the dump is written on the device before being pulled.
+pub const DEVICE_DUMP_PATH: &str = "/data/local/tmp/log_tools.hprof";
+pub const HEAP_DUMP_FILE_STEM: &str = "heapdump";
+
+// Top-level record tags.
+const STRING: u8 = 0x01;
+const LOAD_CLASS: u8 = 0x02;
+const HEAP_DUMP: u8 = 0x0C;
+const HEAP_DUMP_SEGMENT: u8 = 0x1C;
+
+// Heap dump sub-record tags, Android's extensions included.
+const CLASS_DUMP: u8 = 0x20;
+const INSTANCE_DUMP: u8 = 0x21;
+const OBJECT_ARRAY_DUMP: u8 = 0x22;
+const PRIMITIVE_ARRAY_DUMP: u8 = 0x23;
+const PRIMITIVE_ARRAY_NODATA: u8 = 0xC3;
+const HEAP_DUMP_INFO: u8 = 0xFE;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ClassCount {
+    pub class: String,
+    pub instances: u64,
+    pub bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct ClassDelta {
+    pub class: String,
+    pub instances_before: u64,
+    pub instances_after: u64,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    pub instance_delta: i64,
+    pub byte_delta: i64,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    id_size: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(n).filter(|end| *end <= self.data.len());
+        let end = end.ok_or_else(|| anyhow!("Heap dump truncated at byte {}", self.pos))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, n: usize) -> Result<()> {
+        self.bytes(n).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_be_bytes(self.bytes(2)?.try_into()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn id(&mut self) -> Result<u64> {
+        Ok(self.bytes(self.id_size)?.iter().fold(0, |id, b| id << 8 | *b as u64))
+    }
+
+    /// Size of a value of basic type `kind`.
+    fn value_size(&self, kind: u8) -> Result<usize> {
+        match kind {
+            2 => Ok(self.id_size),
+            4 | 8 => Ok(1),
+            5 | 9 => Ok(2),
+            6 | 10 => Ok(4),
+            7 | 11 => Ok(8),
+            _ => Err(anyhow!("Unknown basic type {} in heap dump at byte {}", kind, self.pos)),
+        }
+    }
+}
+
+fn primitive_array_name(kind: u8) -> &'static str {
+    match kind {
+        4 => "boolean[]",
+        5 => "char[]",
+        6 => "float[]",
+        7 => "double[]",
+        8 => "byte[]",
+        9 => "short[]",
+        10 => "int[]",
+        _ => "long[]",
+    }
+}
+
+/// "java.lang.String" for both Android's names and the JVM's
+/// "java/lang/String", "java.lang.String[]" for "[Ljava/lang/String;".
+fn pretty_class_name(name: &str) -> String {
+    let dims = name.chars().take_while(|c| *c == '[').count();
+    let base = &name[dims..];
+    let base = match (dims, base.strip_prefix('L').and_then(|b| b.strip_suffix(';'))) {
+        (0, _) | (_, None) => base,
+        (_, Some(class)) => class,
+    };
+    format!("{}{}", base.replace('/', "."), "[]".repeat(dims))
+}
+
+/// Instance counts and shallow bytes per class, most bytes first.
+pub fn parse_histogram(data: &[u8]) -> Result<Vec<ClassCount>> {
+    let header_end = data
+        .iter()
+        .position(|b| *b == 0)
+        .filter(|end| data[..*end].starts_with(b"JAVA PROFILE "))
+        .ok_or_else(|| anyhow!("Not an hprof heap dump"))?;
+    let mut reader = Reader { data, pos: header_end + 1, id_size: 4 };
+    reader.id_size = reader.u32()? as usize;
+    if reader.id_size != 4 && reader.id_size != 8 {
+        return Err(anyhow!("Unsupported heap dump identifier size {}", reader.id_size));
+    }
+    reader.skip(8)?;
+
+    let mut strings: HashMap<u64, String> = HashMap::new();
+    let mut class_names: HashMap<u64, u64> = HashMap::new();
+    // Count and bytes by class object ID; primitive arrays by type name,
+    // as they have no class record.
+    let mut instances: HashMap<u64, (u64, u64)> = HashMap::new();
+    let mut primitive_arrays: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+    while reader.pos < data.len() {
+        let tag = reader.u8()?;
+        reader.skip(4)?;
+        let length = reader.u32()? as usize;
+        match tag {
+            STRING => {
+                let id = reader.id()?;
+                let text = reader.bytes(length.saturating_sub(reader.id_size))?;
+                strings.insert(id, String::from_utf8_lossy(text).into_owned());
+            }
+            LOAD_CLASS => {
+                reader.skip(4)?;
+                let class = reader.id()?;
+                reader.skip(4)?;
+                class_names.insert(class, reader.id()?);
+            }
+            HEAP_DUMP | HEAP_DUMP_SEGMENT => {
+                let end = reader.pos + length;
+                while reader.pos < end {
+                    read_heap_record(&mut reader, &mut instances, &mut primitive_arrays)?;
+                }
+            }
+            _ => reader.skip(length)?,
+        }
+    }
+
+    let mut by_name: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+    for (class, (count, bytes)) in instances {
+        let name = class_names
+            .get(&class)
+            .and_then(|name| strings.get(name))
+            .map(|name| pretty_class_name(name))
+            .unwrap_or_else(|| format!("unknown class 0x{:x}", class));
+        let entry = by_name.entry(name).or_default();
+        entry.0 += count;
+        entry.1 += bytes;
+    }
+    for (name, (count, bytes)) in primitive_arrays {
+        let entry = by_name.entry(name.to_string()).or_default();
+        entry.0 += count;
+        entry.1 += bytes;
+    }
+    let mut histogram: Vec<ClassCount> =
+        by_name.into_iter().map(|(class, (instances, bytes))| ClassCount { class, instances, bytes }).collect();
+    histogram.sort_by_key(|c| std::cmp::Reverse(c.bytes));
+    Ok(histogram)
+}
+
+fn read_heap_record(
+    reader: &mut Reader,
+    instances: &mut HashMap<u64, (u64, u64)>,
+    primitive_arrays: &mut BTreeMap<&'static str, (u64, u64)>,
+) -> Result<()> {
+    let id = reader.id_size;
+    let tag = reader.u8()?;
+    match tag {
+        // GC roots: an object ID and, for some, thread and frame numbers.
+        0xFF | 0x05 | 0x07 | 0x89 | 0x8A | 0x8B | 0x8C | 0x8D | 0x90 => reader.skip(id)?,
+        0x01 => reader.skip(2 * id)?,
+        0x04 | 0x06 => reader.skip(id + 4)?,
+        0x02 | 0x03 | 0x08 | 0x8E => reader.skip(id + 8)?,
+        HEAP_DUMP_INFO => reader.skip(4 + id)?,
+        CLASS_DUMP => {
+            reader.skip(id + 4 + 6 * id + 4)?;
+            for _ in 0..reader.u16()? {
+                reader.skip(2)?;
+                let kind = reader.u8()?;
+                reader.skip(reader.value_size(kind)?)?;
+            }
+            for _ in 0..reader.u16()? {
+                reader.skip(id)?;
+                let kind = reader.u8()?;
+                reader.skip(reader.value_size(kind)?)?;
+            }
+            let fields = reader.u16()? as usize;
+            reader.skip(fields * (id + 1))?;
+        }
+        INSTANCE_DUMP => {
+            reader.skip(id + 4)?;
+            let class = reader.id()?;
+            let size = reader.u32()? as u64;
+            reader.skip(size as usize)?;
+            let entry = instances.entry(class).or_default();
+            entry.0 += 1;
+            entry.1 += size;
+        }
+        OBJECT_ARRAY_DUMP => {
+            reader.skip(id + 4)?;
+            let length = reader.u32()? as usize;
+            let class = reader.id()?;
+            reader.skip(length * id)?;
+            let entry = instances.entry(class).or_default();
+            entry.0 += 1;
+            entry.1 += (length * id) as u64;
+        }
+        PRIMITIVE_ARRAY_DUMP | PRIMITIVE_ARRAY_NODATA => {
+            reader.skip(id + 4)?;
+            let length = reader.u32()? as usize;
+            let kind = reader.u8()?;
+            let size = length * reader.value_size(kind)?;
+            if tag == PRIMITIVE_ARRAY_DUMP {
+                reader.skip(size)?;
+            }
+            let entry = primitive_arrays.entry(primitive_array_name(kind)).or_default();
+            entry.0 += 1;
+            entry.1 += size as u64;
+        }
+        _ => return Err(anyhow!("Unknown heap dump record 0x{:02x} at byte {}", tag, reader.pos - 1)),
+    }
+    Ok(())
+}
+
+/// Compares two histograms by class name, most bytes grown first.
+/// Classes missing from one side count as 0.
+pub fn diff_histograms(before: &[ClassCount], after: &[ClassCount]) -> Vec<ClassDelta> {
+    let mut totals: BTreeMap<&str, (&ClassCount, &ClassCount)> = BTreeMap::new();
+    let empty = ClassCount { class: String::new(), instances: 0, bytes: 0 };
+    for c in before {
+        totals.insert(&c.class, (c, &empty));
+    }
+    for c in after {
+        totals.entry(&c.class).or_insert((&empty, &empty)).1 = c;
+    }
+    let mut deltas: Vec<ClassDelta> = totals
+        .into_iter()
+        .map(|(class, (before, after))| ClassDelta {
+            class: class.to_string(),
+            instances_before: before.instances,
+            instances_after: after.instances,
+            bytes_before: before.bytes,
+            bytes_after: after.bytes,
+            instance_delta: after.instances as i64 - before.instances as i64,
+            byte_delta: after.bytes as i64 - before.bytes as i64,
+        })
+        .collect();
+    deltas.sort_by_key(|d| (std::cmp::Reverse(d.byte_delta), std::cmp::Reverse(d.instance_delta)));
+    deltas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(tag: u8, body: &[u8]) -> Vec<u8> {
+        [&[tag][..], &0u32.to_be_bytes(), &(body.len() as u32).to_be_bytes(), body].concat()
+    }
+
+    /// A dump with 4-byte IDs: two instances of one class, an array of
+    /// them, an int array and a GC root.
+    fn dump() -> Vec<u8> {
+        let id = |n: u32| n.to_be_bytes();
+        let name = [&id(1)[..], b"com/example/Leak"].concat();
+        let load_class = [id(1), id(100), id(0), id(1)].concat();
+        let heap = [
+            &[0xFF][..],
+            &id(7),
+            &[INSTANCE_DUMP],
+            &id(200),
+            &id(0),
+            &id(100),
+            &12u32.to_be_bytes(),
+            &[0; 12],
+            &[INSTANCE_DUMP],
+            &id(201),
+            &id(0),
+            &id(100),
+            &8u32.to_be_bytes(),
+            &[0; 8],
+            &[OBJECT_ARRAY_DUMP],
+            &id(202),
+            &id(0),
+            &2u32.to_be_bytes(),
+            &id(101),
+            &id(200),
+            &id(201),
+            &[PRIMITIVE_ARRAY_DUMP],
+            &id(203),
+            &id(0),
+            &3u32.to_be_bytes(),
+            &[10],
+            &[0; 12],
+        ]
+        .concat();
+        let header = [&b"JAVA PROFILE 1.0.3\0"[..], &id(4), &[0; 8]].concat();
+        [header, record(STRING, &name), record(LOAD_CLASS, &load_class), record(HEAP_DUMP_SEGMENT, &heap)].concat()
+    }
+
+    #[test]
+    fn histogram_counts_instances_and_arrays() {
+        let histogram = parse_histogram(&dump()).unwrap();
+        let counts: Vec<(&str, u64, u64)> = histogram.iter().map(|c| (c.class.as_str(), c.instances, c.bytes)).collect();
+        assert_eq!(counts, vec![("com.example.Leak", 2, 20), ("int[]", 1, 12), ("unknown class 0x65", 1, 8)]);
+    }
+
+    #[test]
+    fn truncated_and_foreign_files_are_errors() {
+        let dump = dump();
+        assert!(parse_histogram(&dump[..dump.len() - 1]).is_err());
+        assert!(parse_histogram(b"PK\x03\x04").is_err());
+        assert_eq!(pretty_class_name("[[Ljava/lang/String;"), "java.lang.String[][]");
+        assert_eq!(pretty_class_name("java.lang.Object[]"), "java.lang.Object[]");
+    }
+
+    #[test]
+    fn diff_puts_the_most_grown_class_first() {
+        let count = |class: &str, instances, bytes| ClassCount { class: class.to_string(), instances, bytes };
+        let deltas = diff_histograms(&[count("a", 1, 10), count("b", 5, 50)], &[count("b", 2, 20), count("c", 3, 300)]);
+        let order: Vec<(&str, i64)> = deltas.iter().map(|d| (d.class.as_str(), d.byte_delta)).collect();
+        assert_eq!(order, vec![("c", 300), ("a", -10), ("b", -30)]);
+    }
+}
@@ -0,0 +1,197 @@
+//! `--html`: one self-contained HTML file per run, for attaching to a bug
+//! ticket in place of the separate plots, CSVs and JSON files. The plots
+//! are embedded as data URIs; the device, summary statistics, LeakCanary
+//! traces and the raw sample and thread tables are plain HTML, with no
+//! scripts or external resources.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use anyhow::Result;
+use base64::Engine;
+
+use crate::devenv::DeviceEnv;
+use crate::leakcanary::LeakTrace;
+use crate::{MemorySample, ThreadInfo, stats, units};
+
+pub const REPORT_FILE_STEM: &str = "report";
+
+/// Device properties shown in the report; the device environment file
+/// next to it has all of them.
+const DEVICE_PROPS: &[(&str, &str)] = &[
+    ("Manufacturer", "ro.product.manufacturer"),
+    ("Model", "ro.product.model"),
+    ("Android", "ro.build.version.release"),
+    ("SDK", "ro.build.version.sdk"),
+    ("Build", "ro.build.fingerprint"),
+];
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;margin-bottom:1.5em}\
+th,td{border:1px solid #ccc;padding:2px 8px;text-align:right}\
+th:first-child,td:first-child{text-align:left}\
+img{max-width:100%;display:block;margin-bottom:1em}";
+
+type MemoryField = fn(&MemorySample) -> u64;
+
+const MEMORY_FIELDS: &[(&str, MemoryField)] = &[
+    ("Total PSS", |s| s.total_pss),
+    ("Native heap", |s| s.native_heap),
+    ("Dalvik heap", |s| s.dalvik_heap),
+    ("Code", |s| s.code),
+    ("Stack", |s| s.stack),
+    ("Graphics", |s| s.graphics),
+    ("Private dirty", |s| s.private_dirty),
+    ("Shared dirty", |s| s.shared_dirty),
+];
+
+pub struct Report<'a> {
+    pub package: &'a str,
+    pub env: Option<&'a DeviceEnv>,
+    pub samples: Option<&'a [MemorySample]>,
+    pub threads: Option<&'a [ThreadInfo]>,
+    /// Leak traces logcat showed during the run, next to the PSS trend.
+    pub leaks: &'a [LeakTrace],
+    /// PNG and SVG plots to embed; missing files and interactive charts
+    /// are left out.
+    pub plots: Vec<String>,
+}
+
+pub fn render(report: &Report) -> Result<String> {
+    let title = format!("{} report", report.package);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{}</title><style>{}</style></head><body>\n<h1>{}</h1>\n<p>Generated {}</p>\n",
+        escape(&title),
+        STYLE,
+        escape(&title),
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S %z")
+    );
+    if let Some(env) = report.env {
+        let mut rows: Vec<Vec<String>> = DEVICE_PROPS
+            .iter()
+            .filter_map(|(label, prop)| env.props.get(*prop).map(|value| vec![label.to_string(), value.clone()]))
+            .collect();
+        if let Some(webview) = &env.webview {
+            rows.push(vec!["WebView".to_string(), webview.to_string()]);
+        }
+        if !rows.is_empty() {
+            html.push_str("<h2>Device</h2>\n");
+            table(&mut html, &[], &rows);
+        }
+    }
+    if let Some(samples) = report.samples.filter(|samples| !samples.is_empty()) {
+        html.push_str("<h2>Memory</h2>\n");
+        let rows: Vec<Vec<String>> = MEMORY_FIELDS
+            .iter()
+            .map(|(label, field)| {
+                let values: Vec<f64> = samples.iter().map(|s| field(s) as f64).collect();
+                let (first, last) = (field(&samples[0]), field(&samples[samples.len() - 1]));
+                vec![
+                    label.to_string(),
+                    units::kb(values.iter().copied().fold(f64::INFINITY, f64::min) as u64),
+                    units::kb(stats::mean(&values) as u64),
+                    units::kb(stats::percentile(&values, 95.0) as u64),
+                    units::kb(values.iter().copied().fold(0.0, f64::max) as u64),
+                    units::delta_kb(last as i64 - first as i64),
+                ]
+            })
+            .collect();
+        table(&mut html, &["", "Min", "Mean", "P95", "Max", "Last - first"], &rows);
+    }
+    for plot in &report.plots {
+        let mime = if crate::is_svg(plot) {
+            "image/svg+xml"
+        } else if plot.ends_with(".png") {
+            "image/png"
+        } else {
+            continue;
+        };
+        let Ok(image) = std::fs::read(plot) else {
+            continue;
+        };
+        writeln!(
+            html,
+            "<img alt=\"{0}\" title=\"{0}\" src=\"data:{1};base64,{2}\">",
+            escape(plot),
+            mime,
+            base64::engine::general_purpose::STANDARD.encode(image)
+        )?;
+    }
+    if !report.leaks.is_empty() {
+        writeln!(html, "<h2>LeakCanary leaks ({})</h2>", report.leaks.len())?;
+        for leak in report.leaks {
+            let retained = leak.retained_bytes.map_or(String::new(), |bytes| format!(", {} bytes retained", bytes));
+            writeln!(html, "<h3>{} at {:.1} s ({} leak{})</h3>", escape(leak.leaking_object()), leak.time.secs(), escape(&leak.kind), escape(&retained))?;
+            if let Some(reason) = &leak.leaking_reason {
+                writeln!(html, "<p>Leaking: {}</p>", escape(reason))?;
+            }
+            let mut rows = Vec::new();
+            if let Some(root) = &leak.gc_root {
+                rows.push(vec!["GC root".to_string(), root.clone()]);
+            }
+            for (i, object) in leak.path.iter().enumerate() {
+                rows.push(vec![String::new(), object.clone()]);
+                if let Some(reference) = leak.references.get(i) {
+                    let suspect = if leak.suspects.contains(reference) { "likely cause" } else { "" };
+                    rows.push(vec![suspect.to_string(), format!("↓ {}", reference)]);
+                }
+            }
+            table(&mut html, &[], &rows);
+        }
+    }
+    if let Some(threads) = report.threads {
+        let mut by_state: BTreeMap<&str, usize> = BTreeMap::new();
+        for thread in threads {
+            *by_state.entry(thread.state.as_str()).or_insert(0) += 1;
+        }
+        writeln!(html, "<h2>Threads ({})</h2>", threads.len())?;
+        let rows: Vec<Vec<String>> = by_state.iter().map(|(state, n)| vec![state.to_string(), n.to_string()]).collect();
+        table(&mut html, &["State", "Threads"], &rows);
+        let rows: Vec<Vec<String>> = threads
+            .iter()
+            .map(|t| vec![t.name.clone(), t.tid.clone(), t.state.clone(), t.priority.clone(), t.user_time.clone(), t.system_time.clone()])
+            .collect();
+        table(&mut html, &["Name", "TID", "State", "Priority", "User time", "System time"], &rows);
+    }
+    if let Some(samples) = report.samples.filter(|samples| !samples.is_empty()) {
+        html.push_str("<h2>Memory samples (KB)</h2>\n");
+        let mut headers = vec!["Seconds"];
+        headers.extend(MEMORY_FIELDS.iter().map(|(label, _)| *label));
+        let rows: Vec<Vec<String>> = samples
+            .iter()
+            .map(|s| {
+                let mut row = vec![format!("{:.1}", s.time.secs())];
+                row.extend(MEMORY_FIELDS.iter().map(|(_, field)| field(s).to_string()));
+                row
+            })
+            .collect();
+        table(&mut html, &headers, &rows);
+    }
+    html.push_str("</body></html>\n");
+    Ok(html)
+}
+
+/// An HTML table; no header row when `headers` is empty.
+fn table(html: &mut String, headers: &[&str], rows: &[Vec<String>]) {
+    html.push_str("<table>\n");
+    if !headers.is_empty() {
+        html.push_str("<tr>");
+        for header in headers {
+            html.push_str(&format!("<th>{}</th>", escape(header)));
+        }
+        html.push_str("</tr>\n");
+    }
+    for row in rows {
+        html.push_str("<tr>");
+        for cell in row {
+            html.push_str(&format!("<td>{}</td>", escape(cell)));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</table>\n");
+}
+
+/// `text` escaped for HTML content and attribute values.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
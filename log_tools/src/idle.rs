@@ -0,0 +1,62 @@
+//! Doze (deviceidle) state and App Standby bucket sampling.
+
+use serde::Serialize;
+
+use crate::clocksync::SampleTime;
+
+#[derive(Clone, Serialize)]
+pub struct IdleSample {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    pub deep: String,
+    pub light: String,
+    pub standby_bucket: String,
+}
+
+/// One shell call printing deep state, light state and the standby bucket.
+pub fn idle_state_cmd(package: &str, user: Option<u32>) -> String {
+    format!(
+        "dumpsys deviceidle get deep; dumpsys deviceidle get light; am get-standby-bucket {}{}",
+        user.map_or(String::new(), |user| format!("--user {} ", user)),
+        package
+    )
+}
+
+pub fn parse_idle_state(output: &str, time: SampleTime) -> IdleSample {
+    let mut lines = output.lines().map(str::trim).filter(|l| !l.is_empty());
+    let mut next = || lines.next().unwrap_or("unknown").to_string();
+    let deep = next();
+    let light = next();
+    let standby_bucket = standby_bucket_name(&next());
+    IdleSample { time, deep, light, standby_bucket }
+}
+
+/// Maps UsageStatsManager bucket values to their names; `am` prints the
+/// number on most releases.
+pub fn standby_bucket_name(value: &str) -> String {
+    match value {
+        "5" => "exempted",
+        "10" => "active",
+        "20" => "working_set",
+        "30" => "frequent",
+        "40" => "rare",
+        "45" => "restricted",
+        "50" => "never",
+        other => other,
+    }
+    .to_string()
+}
+
+/// Records a sample only when something changed, keeping long runs compact.
+pub fn push_if_changed(samples: &mut Vec<IdleSample>, sample: IdleSample) {
+    let changed = samples.last().is_none_or(|last| {
+        last.deep != sample.deep || last.light != sample.light || last.standby_bucket != sample.standby_bucket
+    });
+    if changed {
+        info!(
+            "[{}s] Doze deep: {}  light: {}  standby bucket: {}",
+            sample.time.timestamp, sample.deep, sample.light, sample.standby_bucket
+        );
+        samples.push(sample);
+    }
+}
@@ -0,0 +1,118 @@
+//! Input injection through the device `input` command.
+
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use clap::ArgMatches;
+
+pub enum InputAction {
+    Tap { x: u32, y: u32 },
+    Swipe { from: (u32, u32), to: (u32, u32), duration_ms: u64 },
+    Text(String),
+    KeyEvent(String),
+    /// A touch path: DOWN at the first point, MOVE through the rest, UP at
+    /// the last, spread over `duration_ms`.
+    Gesture { points: Vec<(u32, u32)>, duration_ms: u64 },
+}
+
+impl InputAction {
+    /// Short description used as the timeline marker label.
+    pub fn label(&self) -> String {
+        match self {
+            InputAction::Tap { x, y } => format!("input tap {} {}", x, y),
+            InputAction::Swipe { from, to, duration_ms } => {
+                format!("input swipe {},{} -> {},{} ({}ms)", from.0, from.1, to.0, to.1, duration_ms)
+            }
+            InputAction::Text(text) => format!("input text {:?}", text),
+            InputAction::KeyEvent(key) => format!("input keyevent {}", key),
+            InputAction::Gesture { points, duration_ms } => format!("input gesture {} points ({}ms)", points.len(), duration_ms),
+        }
+    }
+
+    /// Device shell commands performing the action. `input motionevent`
+    /// (API 30+) gives a gesture a single continuous touch; older releases
+    /// approximate the path with back-to-back swipes.
+    pub fn commands(&self, sdk: u32) -> Vec<Vec<String>> {
+        let cmd = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<String>>();
+        match self {
+            InputAction::Tap { x, y } => vec![cmd(&["input", "tap", &x.to_string(), &y.to_string()])],
+            InputAction::Swipe { from, to, duration_ms } => vec![swipe(*from, *to, *duration_ms)],
+            InputAction::Text(text) => vec![cmd(&["input", "text", &escape_text(text)])],
+            InputAction::KeyEvent(key) => vec![cmd(&["input", "keyevent", key])],
+            InputAction::Gesture { points, .. } if sdk >= 30 => points
+                .iter()
+                .enumerate()
+                .map(|(i, (x, y))| {
+                    let action = match i {
+                        0 => "DOWN",
+                        i if i == points.len() - 1 => "UP",
+                        _ => "MOVE",
+                    };
+                    cmd(&["input", "motionevent", action, &x.to_string(), &y.to_string()])
+                })
+                .collect(),
+            InputAction::Gesture { points, duration_ms } => {
+                let segment_ms = duration_ms / (points.len() as u64 - 1);
+                points.windows(2).map(|pair| swipe(pair[0], pair[1], segment_ms)).collect()
+            }
+        }
+    }
+
+    /// Pause between consecutive commands. Each is an adb round trip, so
+    /// gesture timing is only approximate.
+    pub fn step_delay(&self, sdk: u32) -> Duration {
+        match self {
+            InputAction::Gesture { points, duration_ms } if sdk >= 30 => {
+                Duration::from_millis(duration_ms / (points.len() as u64 - 1))
+            }
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+fn swipe(from: (u32, u32), to: (u32, u32), duration_ms: u64) -> Vec<String> {
+    let mut args = vec!["input".to_string(), "swipe".to_string()];
+    args.extend([from.0, from.1, to.0, to.1].map(|v| v.to_string()));
+    args.push(duration_ms.to_string());
+    args
+}
+
+/// Builds the action from the matches of an `input` subcommand.
+pub fn action_from_matches(matches: &ArgMatches) -> Result<InputAction> {
+    Ok(match matches.subcommand() {
+        Some(("tap", m)) => InputAction::Tap { x: *m.get_one::<u32>("x").unwrap(), y: *m.get_one::<u32>("y").unwrap() },
+        Some(("swipe", m)) => {
+            let c: Vec<u32> = m.get_many::<u32>("coords").unwrap().copied().collect();
+            InputAction::Swipe { from: (c[0], c[1]), to: (c[2], c[3]), duration_ms: *m.get_one::<u64>("duration").unwrap() }
+        }
+        Some(("text", m)) => InputAction::Text(m.get_one::<String>("text").unwrap().clone()),
+        Some(("keyevent", m)) => InputAction::KeyEvent(m.get_one::<String>("key").unwrap().clone()),
+        Some(("gesture", m)) => InputAction::Gesture {
+            points: parse_points(m.get_one::<String>("path").unwrap())?,
+            duration_ms: *m.get_one::<u64>("duration").unwrap(),
+        },
+        _ => unreachable!("input requires a subcommand"),
+    })
+}
+
+/// Parses "x1,y1;x2,y2;..." into gesture points.
+pub fn parse_points(path: &str) -> Result<Vec<(u32, u32)>> {
+    let points = path
+        .split(';')
+        .filter(|p| !p.trim().is_empty())
+        .map(|p| {
+            let (x, y) = p.split_once(',').ok_or_else(|| anyhow!("Invalid point {:?}, expected x,y", p))?;
+            Ok((x.trim().parse()?, y.trim().parse()?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if points.len() < 2 {
+        return Err(anyhow!("A gesture path needs at least two points"));
+    }
+    Ok(points)
+}
+
+/// Escapes text for `input text`: spaces become %s and the whole argument
+/// is single-quoted for the device shell.
+pub fn escape_text(text: &str) -> String {
+    format!("'{}'", text.replace(' ', "%s").replace('\'', r"'\''"))
+}
@@ -0,0 +1,93 @@
+//! Kernel slab and vmalloc snapshots for platform debugging (root only).
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::clocksync::SampleTime;
+
+#[derive(Clone, Serialize)]
+pub struct SlabCache {
+    pub name: String,
+    pub active_objs: u64,
+    pub num_objs: u64,
+    pub size_kb: u64,
+}
+
+#[derive(Serialize)]
+pub struct VmallocCaller {
+    pub caller: String,
+    pub size_kb: u64,
+    pub allocations: u64,
+}
+
+#[derive(Serialize)]
+pub struct KernelMemSnapshot {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    pub slabs: Vec<SlabCache>,
+    pub vmalloc_total_kb: u64,
+    pub vmalloc_callers: Vec<VmallocCaller>,
+}
+
+#[derive(Serialize)]
+pub struct SlabGrowth {
+    pub name: String,
+    pub start_kb: u64,
+    pub end_kb: u64,
+    pub delta_kb: i64,
+}
+
+/// Parses `/proc/slabinfo` (version 2.1), largest caches first.
+pub fn parse_slabinfo(output: &str) -> Vec<SlabCache> {
+    let mut slabs: Vec<SlabCache> = output
+        .lines()
+        .filter(|line| !line.starts_with("slabinfo") && !line.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let active_objs = fields.get(1)?.parse::<u64>().ok()?;
+            let num_objs = fields.get(2)?.parse::<u64>().ok()?;
+            let objsize = fields.get(3)?.parse::<u64>().ok()?;
+            Some(SlabCache { name: fields[0].to_string(), active_objs, num_objs, size_kb: num_objs * objsize / 1024 })
+        })
+        .collect();
+    slabs.sort_by_key(|s| std::cmp::Reverse(s.size_kb));
+    slabs
+}
+
+/// Parses `/proc/vmallocinfo` into a total and per-caller sizes (largest first).
+/// Lines look like `0xffff...-0xffff...   16384 binder_alloc_mmap+0x44/0x180 pages=3 vmalloc`.
+pub fn parse_vmallocinfo(output: &str) -> (u64, Vec<VmallocCaller>) {
+    let mut callers: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+    let mut total = 0;
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let Some(size) = fields.get(1).and_then(|s| s.parse::<u64>().ok()) else {
+            continue;
+        };
+        total += size;
+        let caller = fields.get(2).map_or("unknown", |c| c.split('+').next().unwrap_or(c));
+        let entry = callers.entry(caller.to_string()).or_default();
+        entry.0 += size;
+        entry.1 += 1;
+    }
+    let mut callers: Vec<VmallocCaller> = callers
+        .into_iter()
+        .map(|(caller, (size, allocations))| VmallocCaller { caller, size_kb: size / 1024, allocations })
+        .collect();
+    callers.sort_by_key(|c| std::cmp::Reverse(c.size_kb));
+    (total / 1024, callers)
+}
+
+/// Slab caches ordered by growth between two snapshots.
+pub fn slab_growth(start: &[SlabCache], end: &[SlabCache]) -> Vec<SlabGrowth> {
+    let mut growth: Vec<SlabGrowth> = end
+        .iter()
+        .map(|e| {
+            let start_kb = start.iter().find(|s| s.name == e.name).map_or(0, |s| s.size_kb);
+            SlabGrowth { name: e.name.clone(), start_kb, end_kb: e.size_kb, delta_kb: e.size_kb as i64 - start_kb as i64 }
+        })
+        .collect();
+    growth.sort_by_key(|g| std::cmp::Reverse(g.delta_kb));
+    growth
+}
@@ -0,0 +1,230 @@
+//! Leak traces LeakCanary prints to logcat after analysing a heap dump
+//! of a debug build, read during capture so a session growing in PSS
+//! comes with the references that keep objects alive. Each trace runs
+//! from its GC root through the referencing objects to the leaking
+//! object; references LeakCanary underlines with `~~~` are the likely
+//! causes.
+//!
+//! ```text
+//! 111729 bytes retained by leaking objects
+//! Signature: e030ebe81011d69c7a43074e799951b65ea73a
+//! ┬───
+//! │ GC Root: System class
+//! │
+//! ├─ com.example.ExampleApplication class
+//! │    Leaking: NO (Application is a singleton)
+//! │    ↓ static ExampleApplication.leakedViews
+//! │                                ~~~~~~~~~~~
+//! ╰→ android.widget.TextView instance
+//!      Leaking: YES (View.mContext references a destroyed activity)
+//! ```
+
+use serde::Serialize;
+
+use crate::clocksync::SampleTime;
+use crate::filters;
+
+pub const LEAKS_FILE_STEM: &str = "leaks";
+const TAG: &str = "LeakCanary";
+
+/// Lines of the LeakCanary tag.
+pub fn is_leakcanary_line(line: &str) -> bool {
+    filters::line_tag(line) == Some(TAG)
+}
+
+#[derive(Clone, Serialize)]
+pub struct LeakTrace {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    /// "application" or "library", the section of the analysis.
+    pub kind: String,
+    pub signature: Option<String>,
+    /// Bytes retained by the leaking objects of this signature.
+    pub retained_bytes: Option<u64>,
+    pub gc_root: Option<String>,
+    /// Objects from the GC root to the leaking object, which comes last.
+    pub path: Vec<String>,
+    /// References between them, `path[i]` referencing `path[i + 1]`.
+    pub references: Vec<String>,
+    /// References marked as likely causes.
+    pub suspects: Vec<String>,
+    /// Why LeakCanary considers the last object leaking.
+    pub leaking_reason: Option<String>,
+}
+
+impl LeakTrace {
+    pub fn leaking_object(&self) -> &str {
+        self.path.last().map_or("unknown object", String::as_str)
+    }
+}
+
+/// A trace being read, without its time yet.
+#[derive(Default)]
+struct Partial {
+    gc_root: Option<String>,
+    path: Vec<String>,
+    references: Vec<String>,
+    suspects: Vec<String>,
+    leaking_reason: Option<String>,
+    /// The leaking object was read; its details follow.
+    at_leak: bool,
+}
+
+/// Reads traces line by line out of the LeakCanary tag's messages.
+#[derive(Default)]
+pub struct LeakParser {
+    kind: String,
+    signature: Option<String>,
+    retained_bytes: Option<u64>,
+    trace: Option<Partial>,
+}
+
+impl LeakParser {
+    /// Takes a logcat line of any tag; returns a trace once its last
+    /// line went by.
+    pub fn observe(&mut self, line: &str, time: impl FnOnce() -> SampleTime) -> Option<LeakTrace> {
+        if !is_leakcanary_line(line) {
+            return None;
+        }
+        let message = line.split_once("): ").map_or("", |(_, message)| message).trim_end();
+        // Detail lines under the leaking object start with a zero-width space.
+        let text = message.trim_start_matches(|c: char| c.is_whitespace() || c == '\u{200b}');
+
+        if let Some(trace) = self.trace.as_mut() {
+            if let Some(rest) = text.strip_prefix('│') {
+                let rest = rest.trim();
+                if let Some(root) = rest.strip_prefix("GC Root:") {
+                    trace.gc_root = Some(root.trim().to_string());
+                } else if let Some(reference) = rest.strip_prefix('↓') {
+                    trace.references.push(reference.trim().to_string());
+                } else if rest.starts_with('~') {
+                    if let Some(reference) = trace.references.last() {
+                        trace.suspects.push(reference.clone());
+                    }
+                }
+                return None;
+            }
+            if let Some(object) = text.strip_prefix("├─") {
+                trace.path.push(object.trim().to_string());
+                return None;
+            }
+            if let Some(object) = text.strip_prefix("╰→") {
+                trace.path.push(object.trim().to_string());
+                trace.at_leak = true;
+                return None;
+            }
+            if trace.at_leak && !text.is_empty() && !text.starts_with('=') && !text.starts_with('┬') {
+                if let Some(reason) = text.strip_prefix("Leaking:") {
+                    trace.leaking_reason = Some(reason.trim().to_string());
+                }
+                return None;
+            }
+        }
+        let finished = self.finish(time);
+
+        if let Some(count) = text.split_whitespace().next().filter(|_| text.ends_with(" LEAKS") || text.ends_with(" LEAK")) {
+            if count.parse::<u64>().is_ok() {
+                self.kind = if text.contains("LIBRARY") { "library".to_string() } else { "application".to_string() };
+            }
+        } else if let Some(bytes) = text.strip_suffix(" bytes retained by leaking objects") {
+            self.retained_bytes = bytes.trim().parse().ok();
+            self.signature = None;
+        } else if let Some(signature) = text.strip_prefix("Signature:") {
+            self.signature = Some(signature.trim().to_string());
+        } else if text.starts_with('┬') {
+            self.trace = Some(Partial::default());
+        }
+        finished
+    }
+
+    /// The trace being read, if any; called at the end of the capture too.
+    pub fn finish(&mut self, time: impl FnOnce() -> SampleTime) -> Option<LeakTrace> {
+        let trace = self.trace.take().filter(|trace| trace.at_leak)?;
+        Some(LeakTrace {
+            time: time(),
+            kind: if self.kind.is_empty() { "application".to_string() } else { self.kind.clone() },
+            signature: self.signature.clone(),
+            retained_bytes: self.retained_bytes,
+            gc_root: trace.gc_root,
+            path: trace.path,
+            references: trace.references,
+            suspects: trace.suspects,
+            leaking_reason: trace.leaking_reason,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// LeakCanary 2.x heap analysis result as `logcat -v time` prints it.
+    const ANALYSIS: &str = "\
+03-10 15:02:10.000 D/LeakCanary( 4242): ====================================
+03-10 15:02:10.000 D/LeakCanary( 4242): HEAP ANALYSIS RESULT
+03-10 15:02:10.000 D/LeakCanary( 4242): ====================================
+03-10 15:02:10.000 D/LeakCanary( 4242): 1 APPLICATION LEAKS
+03-10 15:02:10.000 D/LeakCanary( 4242): 
+03-10 15:02:10.000 D/LeakCanary( 4242): References underlined with \"~~~\" are likely causes.
+03-10 15:02:10.000 D/LeakCanary( 4242): Learn more at https://squ.re/leaks.
+03-10 15:02:10.000 D/LeakCanary( 4242): 
+03-10 15:02:10.000 D/LeakCanary( 4242): 111729 bytes retained by leaking objects
+03-10 15:02:10.000 D/LeakCanary( 4242): Signature: e030ebe81011d69c7a43074e799951b65ea73a
+03-10 15:02:10.000 D/LeakCanary( 4242): ┬───
+03-10 15:02:10.000 D/LeakCanary( 4242): │ GC Root: System class
+03-10 15:02:10.000 D/LeakCanary( 4242): │
+03-10 15:02:10.000 D/LeakCanary( 4242): ├─ com.example.ExampleApplication class
+03-10 15:02:10.000 D/LeakCanary( 4242): │    Leaking: NO (Application is a singleton)
+03-10 15:02:10.000 D/LeakCanary( 4242): │    ↓ static ExampleApplication.leakedViews
+03-10 15:02:10.000 D/LeakCanary( 4242): │                                ~~~~~~~~~~~
+03-10 15:02:10.000 D/LeakCanary( 4242): ├─ java.util.ArrayList instance
+03-10 15:02:10.000 D/LeakCanary( 4242): │    Leaking: UNKNOWN
+03-10 15:02:10.001 I/chatty  ( 4242): uid=10094(com.example.app) identical 2 lines
+03-10 15:02:10.001 D/LeakCanary( 4242): │    ↓ ArrayList.elementData
+03-10 15:02:10.001 D/LeakCanary( 4242): ├─ java.lang.Object[] array
+03-10 15:02:10.001 D/LeakCanary( 4242): │    Leaking: UNKNOWN
+03-10 15:02:10.001 D/LeakCanary( 4242): │    ↓ Object[].[0]
+03-10 15:02:10.001 D/LeakCanary( 4242): ╰→ android.widget.TextView instance
+03-10 15:02:10.001 D/LeakCanary( 4242): \u{200b}     Leaking: YES (View.mContext references a destroyed activity)
+03-10 15:02:10.001 D/LeakCanary( 4242): \u{200b}     mContext instance of com.example.MainActivity with mDestroyed = true
+03-10 15:02:10.001 D/LeakCanary( 4242): ====================================
+03-10 15:02:10.001 D/LeakCanary( 4242): 1 LIBRARY LEAK
+03-10 15:02:10.001 D/LeakCanary( 4242): 
+03-10 15:02:10.001 D/LeakCanary( 4242): 2048 bytes retained by leaking objects
+03-10 15:02:10.001 D/LeakCanary( 4242): Signature: 8b7f6a1d0c2e4b3a9f8e7d6c5b4a3f2e1d0c9b8a
+03-10 15:02:10.001 D/LeakCanary( 4242): ┬───
+03-10 15:02:10.001 D/LeakCanary( 4242): │ GC Root: Global variable in native code
+03-10 15:02:10.001 D/LeakCanary( 4242): │
+03-10 15:02:10.001 D/LeakCanary( 4242): ├─ android.view.inputmethod.InputMethodManager instance
+03-10 15:02:10.001 D/LeakCanary( 4242): │    ↓ InputMethodManager.mCurRootView
+03-10 15:02:10.001 D/LeakCanary( 4242): ╰→ com.android.internal.policy.DecorView instance
+03-10 15:02:10.001 D/LeakCanary( 4242): \u{200b}     Leaking: YES (The DecorView's activity is destroyed)
+";
+
+    #[test]
+    fn application_and_library_traces() {
+        let mut parser = LeakParser::default();
+        let mut traces: Vec<LeakTrace> = ANALYSIS.lines().filter_map(|line| parser.observe(line, SampleTime::default)).collect();
+        // The last trace ends with the capture.
+        traces.extend(parser.finish(SampleTime::default));
+        assert_eq!(traces.len(), 2);
+
+        let app = &traces[0];
+        assert_eq!(app.kind, "application");
+        assert_eq!(app.signature.as_deref(), Some("e030ebe81011d69c7a43074e799951b65ea73a"));
+        assert_eq!(app.retained_bytes, Some(111729));
+        assert_eq!(app.gc_root.as_deref(), Some("System class"));
+        assert_eq!(
+            app.path,
+            ["com.example.ExampleApplication class", "java.util.ArrayList instance", "java.lang.Object[] array", "android.widget.TextView instance"]
+        );
+        assert_eq!(app.references, ["static ExampleApplication.leakedViews", "ArrayList.elementData", "Object[].[0]"]);
+        assert_eq!(app.suspects, ["static ExampleApplication.leakedViews"]);
+        assert_eq!(app.leaking_reason.as_deref(), Some("YES (View.mContext references a destroyed activity)"));
+
+        let library = &traces[1];
+        assert_eq!((library.kind.as_str(), library.retained_bytes), ("library", Some(2048)));
+        assert_eq!(library.leaking_object(), "com.android.internal.policy.DecorView instance");
+        assert!(library.suspects.is_empty());
+    }
+}
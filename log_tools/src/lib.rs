@@ -0,0 +1,4039 @@
+//! Collectors, parsers and plots behind the `log_tools` command line,
+//! usable from other Rust code.
+//!
+//! [`LogAnalyzer`] drives a device through adb according to a
+//! [`LogAnalyzerConfig`] (`LogAnalyzerConfig::default()` or deserialized
+//! from a config file) and returns typed results: [`MemorySample`]s,
+//! [`ThreadInfo`]s, [`SoMemoryInfo`]s, and the records of the feature
+//! modules. The parsers in those modules take command output as text and
+//! need no device.
+//!
+//! Nothing is printed unless [`output::set_console_output`] turns on the
+//! progress messages and bars the CLI shows.
+
+use anyhow::{Result, anyhow};
+use plotters::prelude::*;
+use plotters::chart::DualCoordChartContext;
+use plotters::coord::Shift;
+use plotters::coord::types::RangedCoordf64;
+use plotters::style::RGBColor;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use once_cell::sync::Lazy; // Add dependency: once_cell
+
+/// Human-readable output. Printed only once the CLI has turned the
+/// console on, so library callers get typed results and no chatter; goes
+/// to stderr under `--json` so stdout only carries JSON documents.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::output::console_output() {
+            if $crate::output::json_output() { eprintln!($($arg)*) } else { println!($($arg)*) }
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! warn {
+    ($msg:expr) => {
+        $crate::info!("{}: {}", $crate::console::paint($crate::console::YELLOW, "WARNING"), $msg);
+    };
+}
+
+pub mod activities;
+pub mod adb;
+pub mod addresses;
+pub mod anr;
+pub mod appmetrics;
+pub mod bench;
+pub mod binlog;
+pub mod bisect;
+pub mod bluetooth;
+pub mod boot;
+pub mod budgets;
+pub mod bundle;
+pub mod charts;
+pub mod clocksync;
+pub mod components;
+pub mod composite;
+pub mod compat;
+pub mod compress;
+pub mod console;
+pub mod control;
+pub mod dashboard;
+pub mod derived;
+pub mod devenv;
+pub mod devices;
+pub mod devprep;
+pub mod dmabuf;
+pub mod events;
+pub mod eviction;
+pub mod exitinfo;
+pub mod filterbench;
+pub mod filters;
+pub mod freezer;
+pub mod guard;
+pub mod heapdump;
+pub mod htmlreport;
+pub mod idle;
+pub mod input;
+pub mod kernelmem;
+pub mod leakcanary;
+pub mod markers;
+pub mod matrix;
+pub mod multidevice;
+pub mod naming;
+pub mod nativelibs;
+pub mod memtop;
+pub mod net;
+pub mod notifications;
+pub mod offline;
+pub mod output;
+pub mod overhead;
+pub mod owners;
+#[cfg(feature = "parquet")]
+pub mod parquetfile;
+pub mod pause;
+pub mod pipeline;
+pub mod pkgcompare;
+pub mod pkginfo;
+pub mod preset;
+pub mod procstats;
+pub mod progress;
+pub mod proto;
+pub mod pstore;
+pub mod queries;
+pub mod reboot;
+pub mod regexcheck;
+pub mod runsdb;
+pub mod scenario;
+pub mod search;
+pub mod selinux;
+pub mod smoothing;
+pub mod stability;
+pub mod splits;
+pub mod stabilize;
+pub mod schema;
+pub mod startup;
+pub mod stats;
+pub mod theme;
+pub mod timeline;
+pub mod topapps;
+pub mod uichurn;
+pub mod uidump;
+pub mod units;
+pub mod upload;
+pub mod vmstats;
+pub mod wakeups;
+pub mod webview;
+pub mod wireless;
+
+use activities::ActivityStackSnapshot;
+use appmetrics::{AppMetric, MetricStream};
+use bench::BenchOptions;
+use matrix::{ConfigMatrix, MatrixCell};
+use bluetooth::BluetoothSnapshot;
+use clocksync::SampleTime;
+use components::{BroadcastTracker, ComponentEvent, ComponentEventKind};
+use composite::{PanelCounters, PanelSample};
+use compat::{ParseDiagnostics, ParserProfile};
+use dashboard::Dashboard;
+use derived::DerivedMetric;
+use dmabuf::DmaBufSample;
+use exitinfo::ProcessExit;
+use freezer::FrozenInterval;
+use input::InputAction;
+use kernelmem::KernelMemSnapshot;
+use markers::SessionMark;
+use memtop::{ProcessPss, PssDelta};
+use nativelibs::LibraryTracker;
+use net::{NetTarget, WifiSample};
+use notifications::NotificationTracker;
+use pause::{PauseTracker, PausedInterval};
+use procstats::ProcStateStats;
+use queries::{QuerySource, SlowQuery};
+use scenario::{Scenario, Step};
+use theme::PlotTheme;
+use timeline::TimelineEntry;
+use uichurn::UiChurn;
+use vmstats::{DeviceMemorySample, PsiAlerter, PsiSample, VmSnapshot};
+use wakeups::WakeupHour;
+
+/// What to measure and how; the CLI reads it from `--config` and applies
+/// its flags on top.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LogAnalyzerConfig {
+    pub package_name: String,
+    pub keyword_regex: String,
+    pub output_file: Option<String>,
+    /// Seconds between samples; fractions down to `MIN_SAMPLE_INTERVAL`.
+    pub sample_interval: f64,
+    pub sdk_level: Option<u32>,
+    /// Android user (secondary user or work profile) the app runs in.
+    pub user: Option<u32>,
+    #[serde(default)]
+    pub strict_parse: bool,
+    pub psi_alert_threshold: Option<f64>,
+    /// Also sample swap, zram and PSI while monitoring memory.
+    #[serde(default)]
+    pub vm_pressure: bool,
+    #[serde(default)]
+    pub dmabuf: bool,
+    #[serde(default)]
+    pub kernel_mem: bool,
+    #[serde(default)]
+    pub idle_state: bool,
+    #[serde(default)]
+    pub net_state: bool,
+    #[serde(default)]
+    pub wifi_signal: bool,
+    #[serde(default)]
+    pub bluetooth: bool,
+    #[serde(default)]
+    pub wakeups: bool,
+    pub wakeup_budget: Option<u64>,
+    #[serde(default)]
+    pub notifications: bool,
+    #[serde(default)]
+    pub stack_snapshots: bool,
+    #[serde(default)]
+    pub ui_churn: bool,
+    #[serde(default)]
+    pub upload: Option<upload::UploadConfig>,
+    pub so_owners: Option<String>,
+    pub budgets: Option<String>,
+    /// "phone", "wear", "tv" or "auto"; see `preset::DeviceClass`.
+    pub device_class: Option<String>,
+    /// "none", "gzip" or "zstd" for the logcat output file.
+    pub compress: Option<String>,
+    /// Read `logcat -B` and decode entries natively.
+    #[serde(default)]
+    pub binary_logcat: bool,
+    /// Restart logcat after reboots and adb disconnects instead of ending.
+    #[serde(default)]
+    pub persist_across_reboot: bool,
+    #[serde(default)]
+    pub selinux: bool,
+    #[serde(default)]
+    pub plot_theme: PlotTheme,
+    /// PNG memory plot, or an interactive HTML chart in its place.
+    #[serde(default)]
+    pub chart_format: charts::ChartFormat,
+    /// Also render memory, CPU, FPS and temperature as stacked panels.
+    #[serde(default)]
+    pub composite_plot: bool,
+    /// "name = expression" series computed from each memory sample.
+    #[serde(default)]
+    pub derived_metrics: Vec<String>,
+    /// Moving-average window, in samples, of plotted lines.
+    #[serde(default)]
+    pub smooth: Option<usize>,
+    /// Panel or derived series drawn on a right-hand axis of the memory plot.
+    #[serde(default)]
+    pub plot_overlay: Vec<String>,
+    /// "timestamp,label" CSV of external events drawn as markers.
+    #[serde(default)]
+    pub events_file: Option<String>,
+    /// The events file holds device rather than host timestamps.
+    #[serde(default)]
+    pub events_device_clock: bool,
+    /// TCP port, host:port or Unix socket path taking runtime commands.
+    #[serde(default)]
+    pub control_socket: Option<String>,
+    /// Caps on the tool's own memory, file handles and output size.
+    #[serde(default)]
+    pub guard: guard::GuardConfig,
+    /// A second package sampled alongside for a side-by-side comparison.
+    #[serde(default)]
+    pub compare_package: Option<String>,
+    /// Track broadcasts received and services started by the app.
+    #[serde(default)]
+    pub components: bool,
+    /// Device targeted by every adb command, as listed by `adb devices`.
+    #[serde(default)]
+    pub serial: Option<String>,
+    /// Serials, or "all", each running the session in parallel.
+    #[serde(default)]
+    pub devices: Vec<String>,
+    /// Threshold in ms above which SQLite statements are logged and
+    /// collected, along with slow ContentResolver calls.
+    #[serde(default)]
+    pub slow_queries: Option<u64>,
+    /// Wireless debugging endpoints (host:port) saved by `connect`,
+    /// reconnected before each run.
+    #[serde(default)]
+    pub wireless_endpoints: Vec<String>,
+    /// Track when the app's native libraries load and unload.
+    #[serde(default)]
+    pub native_libs: bool,
+    /// Logcat filters applied to captures: built-in tag filters
+    /// (`filters::PRESETS`) or names from `presets`.
+    #[serde(default)]
+    pub filter_presets: Vec<String>,
+    /// Also write memory and thread results as one self-contained HTML
+    /// report.
+    #[serde(default)]
+    pub html_report: bool,
+    /// SQLite database memory samples, thread snapshots and `.so` rows
+    /// are also inserted into, under an ID per run.
+    #[serde(default)]
+    pub db: Option<String>,
+    /// Also write the memory sample, thread and `.so` CSVs as Parquet;
+    /// needs a build with the `parquet` feature.
+    #[serde(default)]
+    pub parquet: bool,
+    /// Team logcat filters by name, each a regex of lines to keep,
+    /// selectable with `--preset` next to the built-in ones.
+    #[serde(default)]
+    pub presets: BTreeMap<String, String>,
+    /// Start a device shell for every command instead of keeping one open.
+    #[serde(default)]
+    pub one_shot_shell: bool,
+}
+
+impl LogAnalyzerConfig {
+    pub fn sample_period(&self) -> Duration {
+        Duration::from_secs_f64(self.sample_interval)
+    }
+
+    /// Whether memory monitoring polls app CPU, frames and temperature:
+    /// for the composite plot, or for overlays and derived metrics using
+    /// them.
+    pub fn collects_panels(&self) -> bool {
+        let derived = derived::parse_definitions(&self.derived_metrics, &derived::known_metrics()).unwrap_or_default();
+        self.composite_plot
+            || self.plot_overlay.iter().any(|name| derived::PANEL_METRICS.contains(&name.as_str()))
+            || derived::uses_any(&derived, derived::PANEL_METRICS)
+    }
+
+    /// The codec of `--compress`, `None` for uncompressed output.
+    pub fn compression(&self) -> Result<Option<compress::Compression>> {
+        compress::Compression::parse(self.compress.as_deref().unwrap_or("none"))
+    }
+
+    /// Whether memory monitoring reads vmstat, zram and PSI each sample:
+    /// asked for directly, or needed for PSI alerts.
+    pub fn samples_vm(&self) -> bool {
+        self.vm_pressure || self.psi_alert_threshold.is_some()
+    }
+}
+
+impl Default for LogAnalyzerConfig {
+    fn default() -> Self {
+        LogAnalyzerConfig {
+            package_name: "com.example.app".to_string(),
+            keyword_regex: "ERROR|WARNING".to_string(),
+            output_file: Some("filtered_logs.txt".to_string()),
+            sample_interval: 1.0,
+            sdk_level: None,
+            user: None,
+            strict_parse: false,
+            psi_alert_threshold: None,
+            vm_pressure: false,
+            dmabuf: false,
+            kernel_mem: false,
+            idle_state: false,
+            net_state: false,
+            wifi_signal: false,
+            bluetooth: false,
+            wakeups: false,
+            wakeup_budget: None,
+            notifications: false,
+            stack_snapshots: false,
+            ui_churn: false,
+            upload: None,
+            so_owners: None,
+            budgets: None,
+            device_class: None,
+            compress: None,
+            binary_logcat: false,
+            persist_across_reboot: false,
+            selinux: false,
+            plot_theme: PlotTheme::default(),
+            chart_format: charts::ChartFormat::Png,
+            composite_plot: false,
+            derived_metrics: Vec::new(),
+            smooth: None,
+            plot_overlay: Vec::new(),
+            events_file: None,
+            events_device_clock: false,
+            control_socket: None,
+            guard: guard::GuardConfig::default(),
+            compare_package: None,
+            components: false,
+            serial: None,
+            devices: Vec::new(),
+            slow_queries: None,
+            wireless_endpoints: Vec::new(),
+            native_libs: false,
+            filter_presets: Vec::new(),
+            html_report: false,
+            db: None,
+            parquet: false,
+            presets: BTreeMap::new(),
+            one_shot_shell: false,
+        }
+    }
+}
+
+/// Collectors run against one device through adb.
+#[derive(Clone)]
+pub struct LogAnalyzer {
+    pub config: LogAnalyzerConfig,
+    adb_path: String,
+    /// Tried first for shell commands and streams; the binary is the
+    /// fallback.
+    server: adb::Server,
+    /// Device shell kept open for `shell()`, shared by clones.
+    shell_session: Arc<Mutex<ShellState>>,
+}
+
+enum ShellState {
+    Closed,
+    Open(adb::ShellSession),
+    /// Could not be started; commands go one shot from then on.
+    Unavailable,
+}
+
+/// One sample of the app's meminfo, in KB.
+#[derive(Serialize)]
+pub struct MemorySample {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    pub total_pss: u64,
+    pub native_heap: u64,
+    pub dalvik_heap: u64,
+    pub code: u64,
+    pub stack: u64,
+    pub graphics: u64,
+    pub private_dirty: u64,
+    pub shared_dirty: u64,
+    /// Values of the config's derived metrics.
+    #[serde(flatten)]
+    pub derived: BTreeMap<String, f64>,
+}
+
+/// Memory of one `.so` mapping of the app, in KB.
+#[derive(Serialize)]
+pub struct SoMemoryInfo {
+    pub name: String,
+    pub pss: u64,
+    pub private_dirty: u64,
+    pub shared_dirty: u64,
+}
+
+/// One thread of the app, with fields as `ps -T` prints them.
+#[derive(Serialize)]
+pub struct ThreadInfo {
+    pub tid: String,
+    pub name: String,
+    pub state: String,
+    pub priority: String,
+    pub user_time: String,
+    pub system_time: String,
+}
+
+/// The app's threads at one poll of a session.
+pub struct ThreadSnapshot {
+    pub time: SampleTime,
+    pub threads: Vec<ThreadInfo>,
+}
+
+/// What `run_session` collected.
+pub struct Session {
+    pub samples: Vec<MemorySample>,
+    pub timeline: Vec<TimelineEntry>,
+    pub leaks: Vec<leakcanary::LeakTrace>,
+    pub thread_snapshots: Vec<ThreadSnapshot>,
+}
+
+/// Shortest `sample_interval`, in seconds; a dumpsys round trip on an
+/// open shell takes most of that on a typical device.
+pub const MIN_SAMPLE_INTERVAL: f64 = 0.1;
+const DEFAULT_PSI_ALERT_THRESHOLD: f64 = 10.0;
+const PSI_PLOT_FILE: &str = "psi_plot.png";
+const WAKEUPS_PLOT_FILE: &str = "wakeups_plot.png";
+pub const ANR_FINGERPRINTS_FILE: &str = "anr_fingerprints.json";
+
+type SeriesFn = fn(&MemorySample) -> (f64, f64);
+/// Lines of the memory plot, by legend label; see `memory_curve_series`.
+const MEMORY_CURVE_SERIES: &[(&str, SeriesFn)] = &[
+    ("Total PSS", |s| (s.time.secs(), s.total_pss as f64)),
+    ("Native Heap", |s| (s.time.secs(), s.native_heap as f64)),
+    ("Dalvik Heap", |s| (s.time.secs(), s.dalvik_heap as f64)),
+    ("Code", |s| (s.time.secs(), s.code as f64)),
+    ("Stack", |s| (s.time.secs(), s.stack as f64)),
+    ("Graphics", |s| (s.time.secs(), s.graphics as f64)),
+    ("Private Dirty", |s| (s.time.secs(), s.private_dirty as f64)),
+    ("Shared Dirty", |s| (s.time.secs(), s.shared_dirty as f64)),
+];
+/// The memory plot's lines. Text meminfo from API 23 on has no Shared
+/// Dirty value, so its always-zero line is left out there.
+fn memory_curve_series(samples: &[MemorySample]) -> Vec<(&'static str, SeriesFn)> {
+    let shared_dirty = samples.iter().any(|s| s.shared_dirty > 0);
+    MEMORY_CURVE_SERIES.iter().copied().filter(|(label, _)| shared_dirty || *label != "Shared Dirty").collect()
+}
+
+/// A labelled line of (seconds, value) points.
+type Series<'a> = (&'a str, Vec<(f64, f64)>);
+/// A chart with left and right y axes over one time axis.
+type DualChart<'a, DB> = DualCoordChartContext<'a, DB, Cartesian2d<RangedCoordf64, RangedCoordf64>, Cartesian2d<RangedCoordf64, RangedCoordf64>>;
+type PsiSeriesFn = fn(&PsiSample) -> f64;
+
+// Precompiled regexes
+static SO_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s*(\d+)\s+(\d+)\s+(\d+)\s+(.+\.so)").unwrap());
+
+impl LogAnalyzer {
+    /// Talks to the adb server, or runs adb from PATH when it is not up,
+    /// against `config.serial` or the only device.
+    pub fn new(config: LogAnalyzerConfig) -> Self {
+        LogAnalyzer {
+            server: adb::Server::from_env(config.serial.as_deref()),
+            shell_session: Arc::new(Mutex::new(if config.one_shot_shell { ShellState::Unavailable } else { ShellState::Closed })),
+            config,
+            adb_path: "adb".to_string(),
+        }
+    }
+
+    /// Captures until logcat ends (for good, with --persist-across-reboot),
+    /// a stop comes in or `duration` seconds pass.
+    pub fn start_logcat(&self, duration: Option<u64>) -> Result<()> {
+        control::begin_session();
+        self.capture_logcat(duration, None).map(|_| ())
+    }
+
+    /// `start_logcat`, also handing matched lines to a session's timeline.
+    /// Returns the LeakCanary traces seen.
+    fn capture_logcat(&self, duration: Option<u64>, timeline: Option<&Sender<TimelineEntry>>) -> Result<Vec<leakcanary::LeakTrace>> {
+        let options = pipeline::FilterOptions::from_config(&self.config)?;
+        if self.config.ui_churn {
+            self.shell(&[uichurn::FRAGMENT_VERBOSE_CMD])?;
+        }
+        naming::ensure_replaceable(appmetrics::APP_METRICS_PLOT_FILE)?;
+        if self.config.slow_queries.is_some() {
+            naming::ensure_replaceable(queries::QUERIES_PLOT_FILE)?;
+        }
+        let _slow_query_log = self.enable_slow_query_log()?;
+        let compression = self.config.compression()?;
+        let output_path = self.config.output_file.as_ref().map(|path| compress::output_path(path, compression));
+        let mut file = match &output_path {
+            Some(path) => Some(BufWriter::new(compress::Writer::new(naming::open_output(path)?, compression)?)),
+            None => None,
+        };
+        // Closed when the session returns.
+        let _control = match &self.config.control_socket {
+            Some(addr) => {
+                let analyzer = self.clone();
+                Some(control::listen(addr, control::Handlers { snapshot: Box::new(move || analyzer.meminfo_snapshot()), rotate: file.is_some(), pause: false })?)
+            }
+            None => None,
+        };
+        let mut selinux = if self.config.selinux {
+            let domain = selinux::parse_domain(&self.shell(&[selinux::DOMAIN_PS_CMD])?, &self.config.package_name);
+            if domain.is_none() {
+                warn!("App not running, SELinux denials are matched by app= tag and process name only");
+            }
+            Some(selinux::SelinuxTracker::new(&self.config.package_name, domain))
+        } else {
+            None
+        };
+        markers::follow();
+        markers::listen_on_terminal(false);
+        // Cancelled when the capture returns.
+        let _timer = duration.map(|secs| control::stop_after(Duration::from_secs(secs)));
+        let boot_id = if self.config.persist_across_reboot { self.shell(&[reboot::BOOT_ID_CMD])?.trim().to_string() } else { String::new() };
+        let mut clock = self.start_clock_sync()?;
+        let mut last_sync = Instant::now();
+        let start_ms = chrono::Utc::now().timestamp_millis();
+        let mut app_metrics = Vec::new();
+        let mut metric_pids = appmetrics::AppPids::default();
+        let mut slow_queries = Vec::new();
+        let mut crash_maps = None;
+        let webview_provider = self.webview_provider()?;
+        let mut renderer_crashes = Vec::new();
+        let mut leak_parser = leakcanary::LeakParser::default();
+        let mut leaks = Vec::new();
+        let mut reports = stability::ReportReader::new(&self.config.package_name);
+        let mut stability = stability::StabilityTracker::new(&self.config.package_name, start_ms);
+        // Binary entries are rendered in host local time.
+        let line_utc_offset = self.config.binary_logcat.then(|| chrono::Local::now().offset().local_minus_utc());
+        let mut since: Option<String> = None;
+        let mut churn = UiChurn::default();
+        let mut dropped = 0;
+        let mut written = 0;
+        let mut guard = guard::Guard::new(&self.config.guard);
+        let mut output_bytes = 0;
+        let mut left_out = 0;
+        loop {
+            // exec-out keeps the binary stream clear of pty newline translation.
+            let mut args = if self.config.binary_logcat { vec!["exec-out", "logcat", "-B"] } else { vec!["logcat", "-v", "time"] };
+            // `adb logcat` escapes its arguments; exec-out hands them to the
+            // device shell as they are, so the space needs quoting there.
+            let since_arg = since.as_ref().map(|since| if self.config.binary_logcat { format!("'{}'", since) } else { since.clone() });
+            if let Some(since) = &since_arg {
+                args.extend(["-T", since]);
+            }
+            let mut output = self.stream(&args, Stdio::inherit())?;
+            let stdout = output.output()?;
+            // `stop` over the control socket ends the stream like the device would.
+            let output = Arc::new(Mutex::new(output));
+            let child = output.clone();
+            control::on_stop(move || {
+                let _ = child.lock().unwrap().stop();
+            });
+            let options = options.clone();
+            let mut pipeline = if self.config.binary_logcat {
+                pipeline::Pipeline::start(binlog::EntryReader::new(stdout), options)
+            } else {
+                pipeline::Pipeline::start(BufReader::new(stdout), options)
+            };
+
+            while let Some(batch) = pipeline.next_batch() {
+                for marker in markers::take_live() {
+                    if let Some(file) = file.as_mut() {
+                        file.write_all(markers::marker_line(&marker.label).as_bytes())?;
+                    }
+                }
+                if let (Some(path), true) = (&output_path, control::take_rotate()) {
+                    if let Some(current) = file.take() {
+                        current.into_inner().map_err(|e| e.into_error())?.finish()?;
+                    }
+                    let rotated = naming::rotated_name(path, chrono::Local::now().format("%Y%m%d_%H%M%S"));
+                    std::fs::rename(path, &rotated)?;
+                    info!("Rotated {} to {}", path, rotated);
+                    file = Some(BufWriter::new(compress::Writer::new(File::create(path)?, compression)?));
+                }
+                for line in batch {
+                    if line.matched && !guard.as_mut().is_none_or(|g| g.keep()) {
+                        left_out += 1;
+                    } else if line.matched {
+                        info!("Match found: {}", line.text);
+                        let host_time_ms = clock.host_time_ms(&line.text, line_utc_offset);
+                        output::emit("logcat", &output::LogcatMatch { line: line.text.trim_end(), host_time_ms })?;
+                        if let Some(timeline) = timeline {
+                            let host_ms = host_time_ms.unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+                            let _ = timeline.send(TimelineEntry::log(clock.time_at(start_ms, host_ms), &line.text));
+                        }
+                        if let Some(file) = file.as_mut() {
+                            file.write_all(&line.raw)?;
+                            written += 1;
+                            output_bytes += line.raw.len() as u64;
+                            if compression.is_some() && written % compress::FLUSH_LINES == 0 {
+                                file.flush()?;
+                            }
+                        }
+                    }
+                    if let Some((name, value)) = appmetrics::parse_metric(&line.text) {
+                        let host_ms = clock.host_time_ms(&line.text, line_utc_offset).unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+                        if host_ms >= start_ms && metric_pids.is_app(&line.text, || self.parser_profile().and_then(|p| self.get_pid(&p)).ok()) {
+                            app_metrics.push(AppMetric { time: clock.time_at(start_ms, host_ms), name, value });
+                        }
+                    }
+                    if queries::is_slow_query_log(&line.text) {
+                        self.collect_slow_queries(std::slice::from_ref(&line.text), &clock, start_ms, &mut slow_queries);
+                    }
+                    if webview::is_renderer_crash(&line.text) {
+                        let host_ms = clock.host_time_ms(&line.text, line_utc_offset).unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+                        let provider = webview_provider.as_ref().map_or_else(|| "unknown WebView".to_string(), |p| p.to_string());
+                        warn!(format!("WebView renderer gone ({}): {}", provider, line.text.trim_end()));
+                        renderer_crashes.push(webview::RendererCrash { time: clock.time_at(start_ms, host_ms), line: line.text.trim_end().to_string() });
+                    }
+                    let leak_time = || {
+                        let host_ms = clock.host_time_ms(&line.text, line_utc_offset).unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+                        clock.time_at(start_ms, host_ms)
+                    };
+                    if let Some(leak) = leak_parser.observe(&line.text, leak_time) {
+                        record_leak(leak, &mut leaks)?;
+                    }
+                    if let Some(kind) = reports.read(&line.text) {
+                        let host_ms = clock.host_time_ms(&line.text, line_utc_offset).unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+                        // The first read dumps the buffer from before the capture.
+                        if host_ms >= start_ms {
+                            stability.record(kind, (host_ms - start_ms) as f64 / 1000.0, None);
+                        }
+                    }
+                    self.capture_stack_on_crash(&line.text);
+                    self.record_native_crash(&line.text, &mut crash_maps);
+                    if let Some(tracker) = selinux.as_mut() {
+                        tracker.observe(&line.text);
+                    }
+                }
+                if let Some(dropped) = pipeline.new_drops() {
+                    warn!(format!("Filtering is falling behind logcat, {} lines dropped so far", dropped));
+                }
+                match guard.as_mut().map(|g| g.check(output_bytes)) {
+                    Some(guard::Verdict::Downsample(reason)) => {
+                        warn!(format!("{}, keeping 1 in {} matched lines from now on", reason, guard::DOWNSAMPLE_FACTOR));
+                    }
+                    Some(guard::Verdict::Stop(reason)) => {
+                        warn!(format!("{}, stopping the capture", reason));
+                        control::request_stop();
+                    }
+                    _ => {}
+                }
+                if last_sync.elapsed().as_secs() >= clocksync::RESYNC_SECS {
+                    clock.samples.push(self.measure_clock(clocksync::ROUND_TRIPS)?);
+                    last_sync = Instant::now();
+                }
+            }
+            output.lock().unwrap().stop()?;
+            let finished = pipeline.finish()?;
+            churn.merge(finished.churn);
+            dropped += finished.dropped;
+            if let Some(timestamp) = finished.last_line.as_deref().and_then(reboot::logcat_timestamp) {
+                since = Some(timestamp.to_string());
+            }
+
+            if !self.config.persist_across_reboot || control::stop_requested() {
+                break;
+            }
+            let Some(rebooted) = self.wait_for_device(&boot_id, "Logcat stream ended, waiting for the device")? else {
+                warn!(format!("Device did not come back within {}s, ending the capture", reboot::RECONNECT_TIMEOUT_SECS));
+                break;
+            };
+            if rebooted {
+                self.collect_panic_log()?;
+            }
+            // A reboot may come back with the clock set differently.
+            clock.samples.push(self.measure_clock(clocksync::ROUND_TRIPS)?);
+            last_sync = Instant::now();
+            let stitch = reboot::stitch_line(rebooted, since.as_deref());
+            info!("{}", stitch.trim_end());
+            markers::record_marker(if rebooted { "device rebooted" } else { "adb reconnected" })?;
+            if let Some(file) = file.as_mut() {
+                file.write_all(stitch.as_bytes())?;
+                file.flush()?;
+            }
+            // Verbose log tags are system properties, which a reboot resets.
+            if rebooted && self.config.ui_churn {
+                self.shell(&[uichurn::FRAGMENT_VERBOSE_CMD])?;
+            }
+            if let (true, Some(threshold)) = (rebooted, self.config.slow_queries) {
+                self.shell(&[&queries::threshold_cmd(threshold)])?;
+            }
+        }
+        if let Some(file) = file {
+            file.into_inner().map_err(|e| e.into_error())?.finish()?;
+        }
+        if dropped > 0 {
+            warn!(format!("{} logcat lines were dropped because filtering could not keep up", dropped));
+        }
+        if left_out > 0 {
+            info!("{} matched lines were left out by downsampling", left_out);
+        }
+        if self.config.ui_churn {
+            self.write_ui_churn(&churn)?;
+        }
+        if let Some(tracker) = selinux {
+            self.write_selinux_report(tracker.report())?;
+        }
+        if !renderer_crashes.is_empty() {
+            self.write_webview_crashes(webview_provider.as_ref(), &renderer_crashes)?;
+        }
+        let end_ms = chrono::Utc::now().timestamp_millis();
+        if let Some(leak) = leak_parser.finish(|| clock.time_at(start_ms, end_ms)) {
+            record_leak(leak, &mut leaks)?;
+        }
+        if !leaks.is_empty() {
+            let json_file = naming::output_file(leakcanary::LEAKS_FILE_STEM, chrono::Local::now().format("%Y%m%d_%H%M%S"), "json");
+            std::fs::write(&json_file, schema::to_versioned_json(leakcanary::LEAKS_FILE_STEM, &leaks)?)?;
+            info!("{} LeakCanary leak traces written to {}", leaks.len(), json_file);
+        }
+        self.write_stability(&stability.summary((end_ms - start_ms) as f64 / 1000.0, 0, 0))?;
+        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+        let marks = markers::session_marks(start_ms, end_ms, Vec::new());
+        if !app_metrics.is_empty() {
+            self.write_app_metrics(&app_metrics, &timestamp, &[], &marks)?;
+        }
+        if self.config.slow_queries.is_some() {
+            let queries = self.finish_slow_queries(slow_queries, &clock, start_ms)?;
+            self.write_slow_queries(&queries, &timestamp, ((end_ms - start_ms) / 1000) as u64, &[], &marks)?;
+        }
+        self.write_clock_sync(&clock, &timestamp)?;
+        Ok(leaks)
+    }
+
+    /// One host/device clock comparison: the shortest of `round_trips`
+    /// `date` round trips, with the host time taken at its middle.
+    fn measure_clock(&self, round_trips: usize) -> Result<clocksync::ClockSample> {
+        let mut best: Option<clocksync::ClockSample> = None;
+        for _ in 0..round_trips {
+            let before = chrono::Utc::now().timestamp_millis();
+            let device_ms = clocksync::parse_device_clock(&self.shell(&[clocksync::DEVICE_CLOCK_CMD])?)?;
+            let after = chrono::Utc::now().timestamp_millis();
+            let host_ms = before + (after - before) / 2;
+            let sample = clocksync::ClockSample { host_ms, device_ms, offset_ms: device_ms - host_ms, round_trip_ms: after - before };
+            if best.as_ref().is_none_or(|b| sample.round_trip_ms < b.round_trip_ms) {
+                best = Some(sample);
+            }
+        }
+        Ok(best.unwrap())
+    }
+
+    fn start_clock_sync(&self) -> Result<clocksync::ClockSync> {
+        let device_utc_offset_secs = clocksync::parse_utc_offset(&self.shell(&[clocksync::DEVICE_TZ_CMD])?).unwrap_or_else(|| {
+            warn!("Could not read the device time zone, assuming UTC");
+            0
+        });
+        let sample = self.measure_clock(clocksync::ROUND_TRIPS)?;
+        info!("Device clock is {:+} ms from the host (round trip {} ms)", sample.offset_ms, sample.round_trip_ms);
+        Ok(clocksync::ClockSync { device_utc_offset_secs, samples: vec![sample] })
+    }
+
+    /// Time of a one-off snapshot outside a sampling session, from a single
+    /// clock round trip.
+    pub fn snapshot_time(&self) -> Result<SampleTime> {
+        let clock = clocksync::ClockSync { samples: vec![self.measure_clock(1)?], ..Default::default() };
+        Ok(clock.sample_time(Duration::ZERO))
+    }
+
+    fn write_clock_sync(&self, clock: &clocksync::ClockSync, timestamp: &str) -> Result<()> {
+        let json_file = naming::output_file("clock_sync", timestamp, "json");
+        std::fs::write(&json_file, schema::to_versioned_json("clock_sync", clock)?)?;
+        info!("Clock offsets written to {}", json_file);
+        Ok(())
+    }
+
+    fn write_selinux_report(&self, report: selinux::SelinuxReport) -> Result<()> {
+        if report.denials.is_empty() {
+            info!("No SELinux denials seen for {}", self.config.package_name);
+            return Ok(());
+        }
+        for denial in &report.denials {
+            info!("{:>6}x {{ {} }} {} {}{}", denial.count, denial.permissions.join(" "), denial.tcontext, denial.tclass,
+                if denial.permissive { " (permissive)" } else { "" });
+        }
+        for rule in &report.suggested_rules {
+            info!("  {}", rule);
+        }
+        let json_file = naming::output_file("selinux_denials", chrono::Local::now().format("%Y%m%d_%H%M%S"), "json");
+        std::fs::write(&json_file, schema::to_versioned_json("selinux_denials", &report)?)?;
+        info!("SELinux denials written to {}", json_file);
+        Ok(())
+    }
+
+    /// Saves the device's properties and settings for `env diff`.
+    pub fn capture_env(&self) -> Result<devenv::DeviceEnv> {
+        let mut settings = BTreeMap::new();
+        for namespace in devenv::SETTINGS_NAMESPACES {
+            settings.insert(namespace.to_string(), devenv::parse_settings(&self.shell(&["settings", "list", namespace])?));
+        }
+        let webview = self.webview_provider()?;
+        let env = devenv::DeviceEnv { props: devenv::parse_getprop(&self.shell(&["getprop"])?), settings, webview };
+        let json_file = naming::output_file(devenv::ENV_FILE_STEM, chrono::Local::now().format("%Y%m%d_%H%M%S"), "json");
+        std::fs::write(&json_file, schema::to_versioned_json(devenv::ENV_FILE_STEM, &env)?)?;
+        info!("Device environment ({} properties) written to {}", env.props.len(), json_file);
+        match &env.webview {
+            Some(provider) => info!("WebView provider: {}", provider),
+            None => info!("No WebView provider reported"),
+        }
+        Ok(env)
+    }
+
+    /// Lines now in the device's main logcat buffer, as `logcat -v time`
+    /// prints them.
+    pub fn logcat_buffer(&self) -> Result<String> {
+        self.shell(regexcheck::LOGCAT_DUMP_ARGS)
+    }
+
+    /// The WebView implementation apps get, from the update service.
+    pub fn webview_provider(&self) -> Result<Option<webview::WebViewProvider>> {
+        Ok(webview::parse_provider(&self.shell(webview::WEBVIEW_CMD)?))
+    }
+
+    fn write_webview_crashes(&self, provider: Option<&webview::WebViewProvider>, crashes: &[webview::RendererCrash]) -> Result<()> {
+        let report = webview::WebViewCrashReport { provider, renderer_crashes: crashes };
+        let json_file = naming::output_file(webview::WEBVIEW_CRASHES_FILE_STEM, chrono::Local::now().format("%Y%m%d_%H%M%S"), "json");
+        std::fs::write(&json_file, schema::to_versioned_json(webview::WEBVIEW_CRASHES_FILE_STEM, &report)?)?;
+        info!("{} WebView renderer crashes written to {}", crashes.len(), json_file);
+        Ok(())
+    }
+
+    /// What the installed build of the package declares.
+    pub fn package_info(&self) -> Result<pkginfo::PackageInfo> {
+        let output = self.shell(&["dumpsys", "package", &self.config.package_name])?;
+        pkginfo::parse_dumpsys_package(&output, &self.config.package_name)
+    }
+
+    /// Saves what the installed build declares, for `pkg diff`. Returns the
+    /// file written.
+    pub fn write_package_info(&self, info: &pkginfo::PackageInfo) -> Result<String> {
+        let json_file = naming::output_file(pkginfo::PACKAGE_INFO_STEM, chrono::Local::now().format("%Y%m%d_%H%M%S"), "json");
+        std::fs::write(&json_file, schema::to_versioned_json(pkginfo::PACKAGE_INFO_STEM, info)?)?;
+        Ok(json_file)
+    }
+
+    /// Pulls the previous boot's kernel log after a reboot the tool did not
+    /// ask for and records whether the session was cut short by a panic.
+    fn collect_panic_log(&self) -> Result<()> {
+        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+        let boot_reason = self.shell(&[pstore::BOOT_REASON_CMD])?.trim().to_string();
+        let mut report = pstore::PanicReport { boot_reason, source: None, log_file: None, interrupted_by_panic: false, panic_lines: Vec::new() };
+        for path in pstore::LAST_KMSG_PATHS {
+            let log = self.root_shell(&format!("cat {} 2>/dev/null", path))?;
+            if log.trim().is_empty() {
+                continue;
+            }
+            let log_file = naming::output_file("last_kmsg", &timestamp, "txt");
+            std::fs::write(&log_file, &log)?;
+            report.panic_lines = pstore::panic_lines(&log);
+            report.source = Some(path.to_string());
+            report.log_file = Some(log_file);
+            break;
+        }
+        report.interrupted_by_panic = pstore::is_panic(&report.boot_reason, &report.panic_lines);
+        match &report.log_file {
+            Some(file) => info!("Kernel log of the previous boot written to {}", file),
+            None => {
+                warn!("No pstore console or last_kmsg readable, the previous boot's kernel log is lost");
+            }
+        }
+        if report.interrupted_by_panic {
+            warn!(format!("Session interrupted by a kernel panic (boot reason {:?})", report.boot_reason));
+            for line in &report.panic_lines {
+                info!("  {}", line);
+            }
+            markers::record_marker("interrupted by kernel panic")?;
+        }
+        let json_file = naming::output_file("panic", &timestamp, "json");
+        std::fs::write(&json_file, schema::to_versioned_json("panic", &report)?)?;
+        Ok(())
+    }
+
+    /// Waits until the device is back on adb and done booting. Returns
+    /// whether it rebooted since `boot_id` was read, or None on timeout.
+    fn wait_for_device(&self, boot_id: &str, message: &str) -> Result<Option<bool>> {
+        let start = Instant::now();
+        let spinner = progress::spinner(message);
+        let result = loop {
+            let state = self.adb().arg("get-state").output()?;
+            if console::decode(&state.stdout).trim() == "device" && self.shell(&["getprop", "sys.boot_completed"])?.trim() == "1" {
+                break Some(self.shell(&[reboot::BOOT_ID_CMD])?.trim() != boot_id);
+            }
+            if start.elapsed().as_secs() >= reboot::RECONNECT_TIMEOUT_SECS {
+                break None;
+            }
+            std::thread::sleep(Duration::from_secs(reboot::POLL_SECS));
+        };
+        spinner.finish_and_clear();
+        Ok(result)
+    }
+
+    /// `.so` mappings of process `pid`, as root or, for a debuggable app,
+    /// through run-as. Empty when unreadable.
+    fn library_maps(&self, pid: &str, run_as: bool) -> Result<String> {
+        let command = nativelibs::maps_cmd(pid);
+        if run_as {
+            self.shell(&[format!("run-as {} {}", self.config.package_name, command)])
+        } else {
+            self.root_shell(&command)
+        }
+    }
+
+    fn write_library_timeline(&self, events: &[nativelibs::LibraryEvent], timestamp: &str) -> Result<()> {
+        if events.is_empty() {
+            info!("No native libraries loaded or unloaded by {} during the session", self.config.package_name);
+            return Ok(());
+        }
+        for event in events {
+            let kind = if event.kind == nativelibs::LibraryEventKind::Load { "load" } else { "unload" };
+            let step = event.pss_step_kb.map(units::delta_kb).unwrap_or_else(|| "-".to_string());
+            let after = event.after_marker.as_deref().map(|m| format!("  after {:?}", m)).unwrap_or_default();
+            info!("{:>6}s  {:<6} {:<40} PSS {:>10}{}", event.time.timestamp, kind, event.library, step, after);
+        }
+        let json_file = naming::output_file("native_libs", timestamp, "json");
+        std::fs::write(&json_file, schema::to_versioned_json("native_libs", events)?)?;
+        info!("Native library timeline written to {}", json_file);
+        Ok(())
+    }
+
+    fn write_ui_churn(&self, churn: &UiChurn) -> Result<()> {
+        if churn.is_empty() {
+            info!("No Compose recomposition or FragmentManager logs seen");
+            return Ok(());
+        }
+        for (label, count) in &churn.recompositions {
+            info!("Recompositions {:<40} {}", label, count);
+        }
+        for (fragment, counts) in &churn.fragments {
+            info!("Fragment {:<40} transitions {:>5}  resumes {:>4}", fragment, counts.transitions, counts.resumes);
+        }
+        let json_file = naming::output_file("ui_churn", chrono::Local::now().format("%Y%m%d_%H%M%S"), "json");
+        std::fs::write(&json_file, schema::to_versioned_json("ui_churn", churn)?)?;
+        info!("UI churn counters written to {}", json_file);
+        Ok(())
+    }
+
+    /// Samples the app's memory for `duration` seconds, with the enabled
+    /// collectors, and plots it to `output_image`.
+    pub fn monitor_memory(&self, duration: u64, output_image: &str) -> Result<Vec<MemorySample>> {
+        control::begin_session();
+        self.sample_memory(duration, output_image, None)
+    }
+
+    /// The plots `monitor_memory` writes with this config.
+    pub fn memory_plots(&self, output_image: &str) -> Vec<String> {
+        let memory_plot = match self.config.chart_format {
+            charts::ChartFormat::Png => output_image.to_string(),
+            charts::ChartFormat::Interactive => charts::chart_file(output_image),
+        };
+        // The app metrics plot is written whenever the app logs any.
+        let mut plots = vec![memory_plot, appmetrics::APP_METRICS_PLOT_FILE.to_string()];
+        if self.config.samples_vm() {
+            plots.push(PSI_PLOT_FILE.to_string());
+        }
+        if self.config.wakeups || self.config.wakeup_budget.is_some() {
+            plots.push(WAKEUPS_PLOT_FILE.to_string());
+        }
+        if self.config.composite_plot {
+            plots.push(composite::COMPOSITE_PLOT_FILE.to_string());
+        }
+        if self.config.components {
+            plots.push(components::COMPONENTS_PLOT_FILE.to_string());
+        }
+        if self.config.slow_queries.is_some() {
+            plots.push(queries::QUERIES_PLOT_FILE.to_string());
+        }
+        if !self.config.derived_metrics.is_empty() {
+            plots.push(derived::DERIVED_PLOT_FILE.to_string());
+        }
+        if self.config.compare_package.is_some() {
+            plots.push(pkgcompare::COMPARISON_PLOT_FILE.to_string());
+        }
+        plots
+    }
+
+    /// `monitor_memory`, also handing each sample to a session's timeline.
+    fn sample_memory(&self, duration: u64, output_image: &str, timeline: Option<&Sender<TimelineEntry>>) -> Result<Vec<MemorySample>> {
+        let derived_metrics = self.derived_metrics()?;
+        if self.config.compare_package.as_ref() == Some(&self.config.package_name) {
+            return Err(anyhow!("--compare-package needs a package other than {}", self.config.package_name));
+        }
+        for plot in self.memory_plots(output_image) {
+            naming::ensure_replaceable(&plot)?;
+        }
+        let start = Instant::now();
+        let start_ms = chrono::Utc::now().timestamp_millis();
+        markers::listen_on_terminal(true);
+        let mut samples = Vec::with_capacity((duration as f64 / self.config.sample_interval) as usize);
+        let mut buffer = String::new();
+        let profile = self.parser_profile()?;
+        let mut diags = ParseDiagnostics::new(self.config.strict_parse);
+        let mut use_proto = profile.meminfo_proto;
+        let mut device_samples = Vec::new();
+        let mut prev_vm: Option<(VmSnapshot, Instant)> = None;
+        let mut psi_samples = Vec::new();
+        let mut psi_alerter = PsiAlerter::new(self.config.psi_alert_threshold.unwrap_or(DEFAULT_PSI_ALERT_THRESHOLD));
+        let pid = match self.get_pid(&profile) {
+            Ok(pid) => Some(pid),
+            Err(e) if !self.config.dmabuf => {
+                warn!(format!("{}, freezer state will not be tracked", e));
+                None
+            }
+            Err(e) => return Err(e),
+        };
+        let mut dmabuf_pid = if self.config.dmabuf { pid.clone() } else { None };
+        let collect_panels = self.config.collects_panels();
+        let panel_pid = if collect_panels { pid.clone() } else { None };
+        let mut maps_pid = if self.config.native_libs { pid.clone() } else { None };
+        let log_pid = pid.clone();
+        let mut freezer_pid = pid;
+        let mut freeze_states = Vec::new();
+        let mut idle_samples = Vec::new();
+        let mut net_samples = Vec::new();
+        let mut wifi_samples = Vec::new();
+        let track_wakeups = self.config.wakeups || self.config.wakeup_budget.is_some();
+        let mut wakeup_counts = Vec::new();
+        let mut notification_tracker = NotificationTracker::default();
+        let mut broadcast_tracker = BroadcastTracker::default();
+        let mut library_tracker = LibraryTracker::default();
+        let mut maps_run_as = false;
+        if self.config.native_libs {
+            self.shell(&[nativelibs::linker_debug_cmd(&self.config.package_name)])?;
+            if let Some(pid) = &maps_pid {
+                let mut maps = self.library_maps(pid, false)?;
+                if maps.trim().is_empty() {
+                    maps_run_as = true;
+                    maps = self.library_maps(pid, true)?;
+                }
+                if maps.trim().is_empty() {
+                    warn!("Could not read the app's mappings (needs root or a debuggable app), native libraries are tracked from logcat only");
+                    maps_pid = None;
+                }
+                library_tracker.baseline(&maps);
+            } else {
+                warn!("App not running, native libraries are tracked from linker logs of its next start only");
+            }
+        }
+        if self.config.components {
+            broadcast_tracker.baseline(&self.shell(components::BROADCAST_HISTORY_CMD)?, &self.config.package_name);
+        }
+        let mut dmabuf_samples = Vec::new();
+        let mut panel_samples = Vec::new();
+        let mut prev_panel: Option<(PanelCounters, Instant)> = None;
+        let mut clock = self.start_clock_sync()?;
+        let _slow_query_log = self.enable_slow_query_log()?;
+        let metric_stream = self.start_metric_stream(&clock)?;
+        // Closed when the session returns.
+        let _control = match &self.config.control_socket {
+            Some(addr) => {
+                let analyzer = self.clone();
+                Some(control::listen(addr, control::Handlers { snapshot: Box::new(move || analyzer.meminfo_snapshot()), rotate: false, pause: true })?)
+            }
+            None => None,
+        };
+        let mut app_metrics = Vec::new();
+        let mut metric_pids = appmetrics::AppPids::default();
+        let mut slow_queries = Vec::new();
+        let kernel_start = if self.config.kernel_mem { Some(self.kernel_mem_snapshot(clock.sample_time(Duration::ZERO))?) } else { None };
+        let bluetooth_start = if self.config.bluetooth { Some(self.bluetooth_snapshot(clock.sample_time(Duration::ZERO))?) } else { None };
+        let bar = progress::timed_bar(duration);
+        let compare = self.config.compare_package.as_ref().map(|package| self.for_package(package));
+        let mut compare_samples = Vec::new();
+        let mut compare_buffer = String::new();
+        // Parse issues of the other app stay out of the session's diagnostics.
+        let mut compare_diags = ParseDiagnostics::new(false);
+        let mut compare_running = true;
+        let mut pauses = PauseTracker::default();
+        let mut guard = guard::Guard::new(&self.config.guard);
+        let mut timings = Vec::new();
+        let mut prev_sample_at: Option<Instant> = None;
+        overhead::start();
+
+        // Paused time does not count towards the duration.
+        while start.elapsed().saturating_sub(pauses.paused_for()).as_secs() < duration && !control::stop_requested() {
+            let elapsed = start.elapsed();
+            let timestamp = elapsed.as_secs();
+            if pauses.update(timestamp) {
+                bar.set_message("paused");
+                prev_sample_at = None;
+                std::thread::sleep(pause::POLL_INTERVAL);
+                continue;
+            }
+            // Nothing is streamed; the guard counts the snapshots and dumps written.
+            match guard.as_mut().map(|g| g.check(0)) {
+                Some(guard::Verdict::Downsample(reason)) => {
+                    warn!(format!("{}, keeping 1 in {} samples from now on", reason, guard::DOWNSAMPLE_FACTOR));
+                }
+                Some(guard::Verdict::Stop(reason)) => {
+                    warn!(format!("{}, stopping the session", reason));
+                    control::request_stop();
+                    break;
+                }
+                _ => {}
+            }
+            if !guard.as_mut().is_none_or(|g| g.keep()) {
+                prev_sample_at = None;
+                std::thread::sleep(self.config.sample_period());
+                continue;
+            }
+            let sample_at = Instant::now();
+            let time = clock.sample_time(elapsed);
+            if self.config.samples_vm() {
+                let vm = vmstats::parse_vm_snapshot(&self.shell(vmstats::VM_SNAPSHOT_CMD)?);
+                let now = Instant::now();
+                let elapsed = prev_vm.as_ref().map_or(0.0, |(_, at)| now.duration_since(*at).as_secs_f64());
+                device_samples.push(DeviceMemorySample::from_snapshots(time, &vm, prev_vm.as_ref().map(|(snap, _)| snap), elapsed));
+                let psi_sample = PsiSample::from_snapshot(time, &vm);
+                psi_alerter.check(&psi_sample);
+                psi_samples.push(psi_sample);
+                prev_vm = Some((vm, now));
+            }
+            if let Some(pid) = &dmabuf_pid {
+                match self.sample_dmabuf(pid, time)? {
+                    Some(sample) => dmabuf_samples.push(sample),
+                    None => {
+                        warn!("Neither dmabuf_dump nor dma-buf fdinfo is readable on this device, disabling DMA-BUF sampling");
+                        dmabuf_pid = None;
+                    }
+                }
+            }
+            if self.config.idle_state {
+                let output = self.shell(&[idle::idle_state_cmd(&self.config.package_name, self.config.user)])?;
+                idle::push_if_changed(&mut idle_samples, idle::parse_idle_state(&output, time));
+            }
+            if self.config.net_state {
+                let output = self.shell(&["dumpsys", "connectivity"])?;
+                net::push_if_changed(&mut net_samples, net::parse_connectivity(&output, time));
+            }
+            if self.config.wifi_signal {
+                if let Some(sample) = net::parse_wifi_info(&self.shell(&[net::WIFI_INFO_CMD])?, time) {
+                    wifi_samples.push(sample);
+                }
+            }
+            if track_wakeups {
+                let output = self.shell(&[wakeups::wakeup_stats_cmd(&self.config.package_name)])?;
+                wakeup_counts.push((timestamp, wakeups::parse_wakeup_count(&output, &self.config.package_name, self.config.user).unwrap_or(0)));
+            }
+            if self.config.notifications {
+                let output = self.shell(&["dumpsys", "notification", "--noredact"])?;
+                notification_tracker.observe(&output, &self.config.package_name, self.config.user, time);
+            }
+            if self.config.components {
+                let output = self.shell(components::BROADCAST_HISTORY_CMD)?;
+                broadcast_tracker.observe(&output, &self.config.package_name, time, &clock, start_ms);
+            }
+            if collect_panels {
+                let counters = self.panel_counters(panel_pid.as_deref())?;
+                let cpu_temp_c = stabilize::parse_cpu_temp(&self.shell(&[stabilize::THERMAL_ZONES_CMD])?);
+                let elapsed = prev_panel.as_ref().map_or(0.0, |(_, at)| at.elapsed().as_secs_f64());
+                panel_samples.push(PanelSample::from_counters(time, counters, prev_panel.map(|(c, _)| c), elapsed, cpu_temp_c));
+                prev_panel = Some((counters, Instant::now()));
+            }
+            if let Some(pid) = &maps_pid {
+                let maps = self.library_maps(pid, maps_run_as)?;
+                if maps.trim().is_empty() {
+                    warn!("The app's mappings are gone, library unloads are no longer tracked");
+                    maps_pid = None;
+                } else {
+                    library_tracker.observe_maps(&maps, time);
+                }
+            }
+            if let Some(pid) = &freezer_pid {
+                match freezer::parse_freeze_state(&self.shell(&[freezer::freeze_state_cmd(pid)])?) {
+                    Some(frozen) => freeze_states.push((timestamp, frozen)),
+                    None => {
+                        warn!("No cgroup v2 freezer state for the process, freezer tracking disabled");
+                        freezer_pid = None;
+                    }
+                }
+            }
+
+            let proto_sample = if use_proto {
+                self.get_memory_sample_proto(time)
+                    .inspect_err(|e| {
+                        warn!(format!("meminfo --proto unusable ({}), falling back to text parsing", e));
+                    })
+                    .ok()
+            } else {
+                None
+            };
+            let mut sample = match proto_sample {
+                Some(sample) => sample,
+                None => {
+                    use_proto = false;
+                    self.get_sample_meminfo_into(&profile, &mut buffer)?;
+                    profile.parse_meminfo(&buffer, time, &mut diags)?
+                }
+            };
+            if !derived_metrics.is_empty() {
+                let mut values = derived::numeric_fields(&serde_json::to_value(&sample)?);
+                if let Some(panel) = panel_samples.last() {
+                    values.extend(derived::numeric_fields(&serde_json::to_value(panel)?));
+                }
+                sample.derived = derived::evaluate(&derived_metrics, &mut values);
+            }
+            if let Some(other) = &compare {
+                other.get_sample_meminfo_into(&profile, &mut compare_buffer)?;
+                if pkgcompare::is_not_running(&compare_buffer) {
+                    if compare_running {
+                        warn!(format!("{} is not running, its samples are skipped until it is", other.config.package_name));
+                    }
+                    compare_running = false;
+                } else {
+                    compare_running = true;
+                    compare_samples.push(profile.parse_meminfo(&compare_buffer, time, &mut compare_diags)?);
+                }
+            }
+            if clock.samples.last().is_some_and(|s| chrono::Utc::now().timestamp_millis() - s.host_ms >= clocksync::RESYNC_SECS as i64 * 1000) {
+                clock.samples.push(self.measure_clock(clocksync::ROUND_TRIPS)?);
+            }
+            let lines = metric_stream.take();
+            self.collect_app_metrics(&lines, &clock, start_ms, &mut metric_pids, &mut app_metrics);
+            self.collect_slow_queries(&lines, &clock, start_ms, &mut slow_queries);
+            for line in &lines {
+                library_tracker.observe_log(line, log_pid.as_deref(), &clock, start_ms);
+            }
+            bar.set_position(timestamp.saturating_sub(pauses.paused_for().as_secs()).min(duration));
+            bar.set_message(format!("sample {}: PSS {}", samples.len() + 1, units::kb(sample.total_pss)));
+            if let Some(timeline) = timeline {
+                let _ = timeline.send(TimelineEntry::memory(&sample));
+            }
+            samples.push(sample);
+            timings.push(overhead::take_sample(timestamp, prev_sample_at.map(|at| sample_at.duration_since(at))));
+            prev_sample_at = Some(sample_at);
+            std::thread::sleep(self.config.sample_period());
+        }
+        bar.finish_and_clear();
+        overhead::stop();
+
+        let kernel_end = match kernel_start {
+            Some(_) => Some(self.kernel_mem_snapshot(clock.sample_time(start.elapsed()))?),
+            None => None,
+        };
+        let bluetooth_end = match bluetooth_start {
+            Some(_) => Some(self.bluetooth_snapshot(clock.sample_time(start.elapsed()))?),
+            None => None,
+        };
+        let frozen = freezer::frozen_intervals(&freeze_states, start.elapsed().as_secs());
+        let paused = pauses.finish(start.elapsed().as_secs());
+        let events = match &self.config.events_file {
+            Some(path) => events::load_events(path, &clock, self.config.events_device_clock)
+                .unwrap_or_else(|e| {
+                    warn!(format!("Could not read events file {}: {}", path, e));
+                    Vec::new()
+                }),
+            None => Vec::new(),
+        };
+        let end_ms = chrono::Utc::now().timestamp_millis();
+        let marks = markers::session_marks(start_ms, end_ms, events.clone());
+        let lines = metric_stream.stop()?;
+        self.collect_app_metrics(&lines, &clock, start_ms, &mut metric_pids, &mut app_metrics);
+        self.collect_slow_queries(&lines, &clock, start_ms, &mut slow_queries);
+        for line in &lines {
+            library_tracker.observe_log(line, log_pid.as_deref(), &clock, start_ms);
+        }
+        let mut library_events = library_tracker.events;
+        let pss: Vec<(i64, u64)> = samples.iter().map(|s| (s.time.host_time_ms, s.total_pss)).collect();
+        nativelibs::attribute(&mut library_events, &pss, &marks);
+
+        if self.config.native_libs {
+            // Loads go on the memory plot only, next to the steps they cause.
+            let extra = events.into_iter().chain(nativelibs::load_markers(&library_events)).collect();
+            let memory_marks = markers::session_marks(start_ms, end_ms, extra);
+            self.plot_memory_curve(&samples, &panel_samples, output_image, &frozen, &paused, &memory_marks)?;
+        } else {
+            self.plot_memory_curve(&samples, &panel_samples, output_image, &frozen, &paused, &marks)?;
+        }
+        if self.config.composite_plot {
+            self.plot_composite(&samples, &panel_samples, composite::COMPOSITE_PLOT_FILE, &frozen, &paused, &marks)?;
+        }
+        if !derived_metrics.is_empty() {
+            self.plot_derived(&samples, &derived_metrics, &frozen, &paused, &marks)?;
+        }
+
+        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+        write_parse_diagnostics(&diags, &timestamp)?;
+        self.write_clock_sync(&clock, &timestamp)?;
+        if self.config.composite_plot {
+            let json_file = naming::output_file("panels", &timestamp, "json");
+            std::fs::write(&json_file, schema::to_versioned_json("panels", &panel_samples)?)?;
+            info!("CPU, FPS and temperature samples written to {}", json_file);
+        }
+        if !idle_samples.is_empty() {
+            let json_file = naming::output_file("idle_states", &timestamp, "json");
+            std::fs::write(&json_file, schema::to_versioned_json("idle_states", &idle_samples)?)?;
+            info!("Doze/standby transitions written to {}", json_file);
+        }
+        if !net_samples.is_empty() {
+            let json_file = naming::output_file("connectivity", &timestamp, "json");
+            std::fs::write(&json_file, schema::to_versioned_json("connectivity", &net_samples)?)?;
+            info!("Connectivity transitions written to {}", json_file);
+        }
+        if self.config.wifi_signal {
+            self.write_wifi_samples(&wifi_samples, &timestamp)?;
+        }
+        if let (Some(bluetooth_start), Some(bluetooth_end)) = (bluetooth_start, bluetooth_end) {
+            self.write_bluetooth_report(bluetooth_start, bluetooth_end, &timestamp)?;
+        }
+        if self.config.notifications {
+            self.write_notification_audit(&notification_tracker, &timestamp)?;
+        }
+        if self.config.components {
+            let output = self.adb().args(components::SERVICE_EVENTS_ARGS).output()?;
+            let mut events = broadcast_tracker.events;
+            events.extend(components::parse_service_events(&console::decode(&output.stdout), &self.config.package_name, &clock, start_ms));
+            events.sort_by_key(|e| e.time.host_time_ms);
+            self.write_component_activity(&events, &timestamp, start.elapsed().as_secs(), &frozen, &marks)?;
+        }
+        if self.config.slow_queries.is_some() {
+            let queries = self.finish_slow_queries(slow_queries, &clock, start_ms)?;
+            self.write_slow_queries(&queries, &timestamp, start.elapsed().as_secs(), &frozen, &marks)?;
+        }
+        if self.config.native_libs {
+            self.write_library_timeline(&library_events, &timestamp)?;
+        }
+        if !frozen.is_empty() {
+            let frozen_secs: u64 = frozen.iter().map(|i| i.end - i.start).sum();
+            let json_file = naming::output_file("frozen_intervals", &timestamp, "json");
+            std::fs::write(&json_file, schema::to_versioned_json("frozen_intervals", &frozen)?)?;
+            info!("App was frozen for {}s in {} intervals, written to {}", frozen_secs, frozen.len(), json_file);
+        }
+        self.write_sampling_overhead(timings, &timestamp)?;
+        if !paused.is_empty() {
+            let paused_secs: u64 = paused.iter().map(|i| i.end - i.start).sum();
+            let json_file = naming::output_file("paused_intervals", &timestamp, "json");
+            std::fs::write(&json_file, schema::to_versioned_json("paused_intervals", &paused)?)?;
+            info!("Sampling was paused for {}s in {} intervals, written to {}", paused_secs, paused.len(), json_file);
+        }
+        if let (Some(kernel_start), Some(kernel_end)) = (kernel_start, kernel_end) {
+            self.write_kernel_mem_report(kernel_start, kernel_end, &timestamp)?;
+        }
+        if self.sdk_level()? >= exitinfo::EXIT_INFO_MIN_SDK {
+            let exits = self.exit_info()?;
+            let json_file = naming::output_file("exit_info", &timestamp, "json");
+            std::fs::write(&json_file, schema::to_versioned_json("exit_info", &exits)?)?;
+            info!("{} recorded process exit(s) written to {}", exits.len(), json_file);
+        }
+        let compression = self.config.compression()?;
+        let json_file = naming::output_file("memory_samples", &timestamp, &compress::output_path("json", compression));
+        let csv_file_path = naming::output_file("memory_samples", &timestamp, &compress::output_path("csv", compression));
+
+        let json = schema::to_versioned_json("memory_samples", &samples)?;
+        compress::write(&json_file, json, compression)?;
+        info!("Memory samples written to {}", json_file);
+
+        let mut csv_file = compress::create(&csv_file_path, compression)?;
+        let derived_columns: String = derived_metrics.iter().map(|m| format!(",{}", m.name)).collect();
+        writeln!(
+            csv_file,
+            "timestamp,host_time_ms,device_time_ms,total_pss,native_heap,dalvik_heap,code,stack,graphics,private_dirty,shared_dirty{},markers",
+            derived_columns
+        )?;
+        let mut prev_ms = start_ms;
+        let mut sample_labels = Vec::new();
+        for sample in &samples {
+            // Markers since the previous sample, or the session start.
+            let labels: Vec<&str> = marks
+                .iter()
+                .filter(|m| m.time_ms > prev_ms && m.time_ms <= sample.time.host_time_ms)
+                .map(|m| m.label.as_str())
+                .collect();
+            prev_ms = sample.time.host_time_ms;
+            sample_labels.push(labels.join("; "));
+            // Empty where a derived metric has no value.
+            let derived_values: String = derived_metrics
+                .iter()
+                .map(|m| format!(",{}", sample.derived.get(&m.name).map(f64::to_string).unwrap_or_default()))
+                .collect();
+            writeln!(
+                csv_file,
+                "{},{},{},{},{},{},{},{},{},{},{}{},\"{}\"",
+                sample.time.timestamp,
+                sample.time.host_time_ms,
+                sample.time.device_time_ms,
+                sample.total_pss,
+                sample.native_heap,
+                sample.dalvik_heap,
+                sample.code,
+                sample.stack,
+                sample.graphics,
+                sample.private_dirty,
+                sample.shared_dirty,
+                derived_values,
+                sample_labels.last().unwrap().replace('"', "\"\"")
+            )?;
+        }
+        compress::close(csv_file)?;
+        info!("Memory samples written to {}", csv_file_path);
+        #[cfg(feature = "parquet")]
+        self.write_parquet(&csv_file_path, |path| {
+            let derived: Vec<String> = derived_metrics.iter().map(|m| m.name.clone()).collect();
+            parquetfile::write_memory_samples(path, &samples, &derived, &sample_labels)
+        })?;
+
+        if let Some(other) = &compare {
+            self.write_package_comparison(&samples, &compare_samples, &other.config.package_name, &timestamp, &marks)?;
+        }
+
+        let wakeup_hours = wakeups::wakeups_per_hour(&wakeup_counts);
+        if track_wakeups {
+            self.write_wakeups(&wakeup_hours, &timestamp)?;
+        }
+
+        if self.config.samples_vm() {
+            self.write_device_memory_samples(&device_samples, &timestamp)?;
+        }
+        if !app_metrics.is_empty() {
+            self.write_app_metrics(&app_metrics, &timestamp, &frozen, &marks)?;
+        }
+        if self.config.samples_vm() {
+            self.write_psi_samples(&psi_samples, &psi_alerter, &timestamp, &frozen, &paused, &marks)?;
+        }
+        if !dmabuf_samples.is_empty() {
+            self.write_dmabuf_samples(&dmabuf_samples, &timestamp)?;
+        }
+
+        if let Some(budget) = self.config.wakeup_budget {
+            let over: Vec<String> = wakeup_hours.iter().filter(|h| h.wakeups > budget).map(|h| format!("hour {}: {}", h.hour, h.wakeups)).collect();
+            if !over.is_empty() {
+                return Err(anyhow!("Wakeup budget of {}/hour exceeded ({})", budget, over.join(", ")));
+            }
+        }
+
+        Ok(samples)
+    }
+
+    fn write_package_comparison(&self, samples: &[MemorySample], other_samples: &[MemorySample], other: &str, timestamp: &str, marks: &[SessionMark]) -> Result<()> {
+        let pairs = pkgcompare::aligned(samples, other_samples);
+        if pairs.is_empty() {
+            warn!(format!("No samples of {} were taken, skipping the package comparison", other));
+            return Ok(());
+        }
+        let comparison = pkgcompare::compare(&pairs);
+        info!("Package comparison over {} aligned samples, A: {}  B: {}", pairs.len(), self.config.package_name, other);
+        for (metric, title) in pkgcompare::METRICS {
+            let d = &comparison[metric];
+            info!("{:<14} mean A: {:>10.1} KB  B: {:>10.1} KB  Delta: {:>+9.1} ({:>+6.2}%)  peak A: {:>10.1} KB  B: {:>10.1} KB",
+                title, d.mean_a, d.mean_b, d.delta, d.delta_percent, d.peak_a, d.peak_b);
+        }
+        info!("One session is one run of each app; compare the memory_samples files of several sessions to test the difference");
+
+        let csv_path = naming::output_file("package_comparison", timestamp, "csv");
+        let mut csv_file = BufWriter::new(File::create(&csv_path)?);
+        let columns: String = pkgcompare::METRICS
+            .iter()
+            .map(|(metric, _)| format!(",{m}_{a},{m}_{b}", m = metric, a = self.config.package_name, b = other))
+            .collect();
+        writeln!(csv_file, "timestamp,host_time_ms{}", columns)?;
+        for (a, b) in &pairs {
+            let values: String = pkgcompare::METRICS
+                .iter()
+                .map(|(metric, _)| format!(",{},{}", pkgcompare::value(a, metric), pkgcompare::value(b, metric)))
+                .collect();
+            writeln!(csv_file, "{},{}{}", a.time.timestamp, a.time.host_time_ms, values)?;
+        }
+        csv_file.flush()?;
+
+        let report = pkgcompare::PackageComparison { packages: [&self.config.package_name, other], samples_compared: pairs.len(), comparison, samples: other_samples };
+        let json_file = naming::output_file("package_comparison", timestamp, "json");
+        std::fs::write(&json_file, schema::to_versioned_json("package_comparison", &report)?)?;
+        info!("Package comparison written to {} and {}", json_file, csv_path);
+        self.plot_package_comparison(&pairs, other, marks)
+    }
+
+    fn plot_package_comparison(&self, pairs: &[(&MemorySample, &MemorySample)], other: &str, marks: &[SessionMark]) -> Result<()> {
+        let output = pkgcompare::COMPARISON_PLOT_FILE;
+        let theme = &self.config.plot_theme;
+        let metrics = pkgcompare::METRICS;
+        let root = BitMapBackend::new(output, (1200, 400 * metrics.len() as u32)).into_drawing_area();
+        root.fill(&theme.background())?;
+        let title = format!("{} vs {}", self.config.package_name, other);
+        let root = root.titled(&title, ("sans-serif", 40).into_font().color(&theme.foreground()))?;
+        let max_time = pairs.last().map_or(1.0, |(a, _)| a.time.secs()).max(1.0);
+        for (area, (metric, name)) in root.split_evenly((metrics.len(), 1)).iter().zip(metrics) {
+            let a = pairs.iter().map(|(a, _)| (a.time.secs(), pkgcompare::value(a, metric) as f64)).collect();
+            let b = pairs.iter().map(|(a, b)| (a.time.secs(), pkgcompare::value(b, metric) as f64)).collect();
+            let series: Vec<Series> = vec![(self.config.package_name.as_str(), self.smoothed(a)), (other, self.smoothed(b))];
+            draw_panel(area, theme, &format!("{} (KB)", name), &series, max_time, &[], &[], marks)?;
+        }
+        root.present()?;
+        info!("Package comparison plot saved to {}", output);
+        Ok(())
+    }
+
+    /// Plots the sessions of `serials`, run by `--devices`, side by side.
+    pub fn write_device_comparison(&self, attached: &[devices::Device], serials: &[String]) -> Result<()> {
+        let mut summaries = Vec::new();
+        let mut labelled = Vec::new();
+        for serial in serials {
+            let (path, samples) = match multidevice::load_samples(&multidevice::device_dir(serial)) {
+                Ok(loaded) if !loaded.1.is_empty() => loaded,
+                Ok(_) => {
+                    warn!(format!("No memory samples from {}", serial));
+                    continue;
+                }
+                Err(e) => {
+                    warn!(format!("No memory samples from {}: {}", serial, e));
+                    continue;
+                }
+            };
+            let model = attached.iter().find(|d| d.serial == *serial).and_then(|d| d.model.clone());
+            let label = match &model {
+                Some(model) => format!("{} ({})", serial, model),
+                None => serial.clone(),
+            };
+            summaries.push(multidevice::summarize(serial, model, path, &samples));
+            labelled.push((label, samples));
+        }
+        if summaries.is_empty() {
+            return Ok(());
+        }
+        info!("Memory of {} across {} devices:", self.config.package_name, summaries.len());
+        for (summary, (label, _)) in summaries.iter().zip(&labelled) {
+            if let Some(pss) = summary.metrics.get("total_pss") {
+                info!("{:<40} Samples: {:>5}  Total PSS mean: {:>10}  peak: {:>10}  last: {:>10}",
+                    label, summary.samples, units::kb(pss.mean as u64), units::kb(pss.peak as u64), units::kb(pss.last as u64));
+            }
+        }
+        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+        let json_file = naming::output_file("devices_comparison", &timestamp, "json");
+        std::fs::write(&json_file, schema::to_versioned_json("devices_comparison", &summaries)?)?;
+        info!("Device comparison written to {}", json_file);
+        output::emit("devices_comparison", &summaries)?;
+        self.plot_device_comparison(&labelled)
+    }
+
+    fn plot_device_comparison(&self, devices: &[(String, Vec<serde_json::Value>)]) -> Result<()> {
+        let output = multidevice::DEVICES_PLOT_FILE;
+        let theme = &self.config.plot_theme;
+        let metrics = pkgcompare::METRICS;
+        let root = BitMapBackend::new(output, (1200, 400 * metrics.len() as u32)).into_drawing_area();
+        root.fill(&theme.background())?;
+        let title = format!("{} across devices", self.config.package_name);
+        let root = root.titled(&title, ("sans-serif", 40).into_font().color(&theme.foreground()))?;
+        let max_time = devices
+            .iter()
+            .filter_map(|(_, samples)| clocksync::sample_secs(samples.last()?))
+            .fold(1.0, f64::max);
+        for (area, (metric, name)) in root.split_evenly((metrics.len(), 1)).iter().zip(metrics) {
+            let series: Vec<Series> = devices
+                .iter()
+                .map(|(label, samples)| (label.as_str(), self.smoothed(multidevice::series(samples, metric))))
+                .collect();
+            draw_panel(area, theme, &format!("{} (KB)", name), &series, max_time, &[], &[], &[])?;
+        }
+        root.present()?;
+        info!("Device comparison plot saved to {}", output);
+        Ok(())
+    }
+
+    fn write_sampling_overhead(&self, timings: Vec<overhead::SampleTiming>, timestamp: &str) -> Result<()> {
+        let summary = overhead::summarize(&timings, self.config.sample_period());
+        info!("Sampling overhead: reads p50 {:.0} ms, p95 {:.0} ms ({:.1}% of the interval), interval jitter p95 {:.0} ms",
+            summary.read_p50_ms, summary.read_p95_ms, summary.overhead_p95_percent, summary.jitter_p95_ms);
+        for warning in overhead::distortion_warnings(&summary) {
+            warn!(warning);
+        }
+        let json_file = naming::output_file("sampling_overhead", timestamp, "json");
+        let report = serde_json::json!({ "summary": summary, "samples": timings });
+        std::fs::write(&json_file, schema::to_versioned_json("sampling_overhead", &report)?)?;
+        info!("Sampling overhead written to {}", json_file);
+        Ok(())
+    }
+
+    fn write_component_activity(&self, events: &[ComponentEvent], timestamp: &str, duration: u64, frozen: &[FrozenInterval], marks: &[SessionMark]) -> Result<()> {
+        let summary = components::summarize(events);
+        let hours = (duration as f64 / 3600.0).max(1.0 / 60.0);
+        info!("Broadcasts received by {}: {}", self.config.package_name, summary.broadcasts.values().sum::<u64>());
+        let mut actions: Vec<(&String, &u64)> = summary.broadcasts.iter().collect();
+        actions.sort_by_key(|(_, count)| std::cmp::Reverse(**count));
+        for (action, count) in actions {
+            info!("Action: {:<60} Received: {:>6}  ({:.0}/hour)", action, count, *count as f64 / hours);
+        }
+        for (service, starts) in &summary.service_starts {
+            info!("Service: {:<59} Started: {:>6}  Stopped: {:>6}", service, starts, summary.service_stops.get(service).unwrap_or(&0));
+        }
+        let json_file = naming::output_file("components", timestamp, "json");
+        let report = serde_json::json!({ "summary": summary, "events": events });
+        std::fs::write(&json_file, schema::to_versioned_json("components", &report)?)?;
+        let csv_file_path = naming::output_file("components", timestamp, "csv");
+        let mut csv_file = BufWriter::new(File::create(&csv_file_path)?);
+        writeln!(csv_file, "timestamp,host_time_ms,device_time_ms,kind,name,receiver")?;
+        for event in events {
+            let kind = serde_json::to_value(event.kind)?;
+            writeln!(csv_file, "{},{},{},{},{},{}", event.time.timestamp, event.time.host_time_ms, event.time.device_time_ms,
+                kind.as_str().unwrap_or_default(), event.name, event.receiver.as_deref().unwrap_or(""))?;
+        }
+        csv_file.flush()?;
+        info!("Broadcast and service activity written to {} and {}", json_file, csv_file_path);
+        let series: Vec<Series> = vec![
+            ("Broadcasts received", components::cumulative(events, ComponentEventKind::Broadcast, duration)),
+            ("Service starts", components::cumulative(events, ComponentEventKind::ServiceStart, duration)),
+        ];
+        self.plot_panels("Broadcasts and Services", &series, components::COMPONENTS_PLOT_FILE, duration as f64, frozen, &[], marks)
+    }
+
+    fn write_notification_audit(&self, tracker: &NotificationTracker, timestamp: &str) -> Result<()> {
+        let counts = tracker.counts_by_channel();
+        info!("Notifications posted by {}: {}", self.config.package_name, tracker.events.len());
+        for (channel, count) in &counts {
+            info!("Channel: {:<30} Posted: {}", channel, count);
+        }
+        let json_file = naming::output_file("notifications", timestamp, "json");
+        let report = serde_json::json!({ "by_channel": counts, "events": tracker.events });
+        std::fs::write(&json_file, schema::to_versioned_json("notifications", &report)?)?;
+        info!("Notification audit written to {}", json_file);
+        Ok(())
+    }
+
+    fn write_wakeups(&self, hours: &[WakeupHour], timestamp: &str) -> Result<()> {
+        let csv_file_path = naming::output_file("wakeups", timestamp, "csv");
+        let csv_file = File::create(&csv_file_path)?;
+        let mut csv_file = BufWriter::new(csv_file);
+        writeln!(csv_file, "hour,wakeups")?;
+        for h in hours {
+            writeln!(csv_file, "{},{}", h.hour, h.wakeups)?;
+        }
+        csv_file.flush()?;
+        info!("Wakeups per hour written to {}", csv_file_path);
+        self.plot_wakeups(hours, WAKEUPS_PLOT_FILE)
+    }
+
+    fn plot_wakeups(&self, hours: &[WakeupHour], output: &str) -> Result<()> {
+        let theme = &self.config.plot_theme;
+        let (fg, colors) = (theme.foreground(), theme.series()?);
+        let root = BitMapBackend::new(output, (1200, 800)).into_drawing_area();
+        root.fill(&theme.background())?;
+
+        let budget = self.config.wakeup_budget;
+        let max_wakeups = hours.iter().map(|h| h.wakeups).chain(budget).max().unwrap_or(1).max(1) as f64 * 1.2;
+        let max_hour = hours.len().max(1) as f64;
+        let mut chart = ChartBuilder::on(&root)
+            .caption("Alarm Wakeups per Hour", ("sans-serif", 40).into_font().color(&fg))
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(50)
+            .build_cartesian_2d(0f64..max_hour, 0f64..max_wakeups)?;
+
+        configure_mesh(&mut chart, theme, "Session hour", "Wakeups")?;
+        chart.draw_series(hours.iter().map(|h| {
+            let color = if budget.is_some_and(|b| h.wakeups > b) { colors[0] } else { colors[1 % colors.len()] };
+            Rectangle::new([(h.hour as f64 + 0.1, 0.0), (h.hour as f64 + 0.9, h.wakeups as f64)], color.filled())
+        }))?;
+        if let Some(budget) = budget {
+            let style = fg.stroke_width(theme.line_width);
+            chart.draw_series(LineSeries::new([(0.0, budget as f64), (max_hour, budget as f64)], style))?
+                .label(format!("Budget ({}/hour)", budget))
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+            draw_legend(&mut chart, theme)?;
+        }
+
+        root.present()?;
+        info!("Wakeup plot saved to {}", output);
+        Ok(())
+    }
+
+    fn kernel_mem_snapshot(&self, time: SampleTime) -> Result<KernelMemSnapshot> {
+        let slabinfo = self.root_shell("cat /proc/slabinfo")?;
+        let slabs = kernelmem::parse_slabinfo(&slabinfo);
+        if slabs.is_empty() {
+            return Err(anyhow!("/proc/slabinfo is not readable; kernel memory sampling needs adb root or su"));
+        }
+        let (vmalloc_total_kb, vmalloc_callers) = kernelmem::parse_vmallocinfo(&self.root_shell("cat /proc/vmallocinfo")?);
+        Ok(KernelMemSnapshot { time, slabs, vmalloc_total_kb, vmalloc_callers })
+    }
+
+    fn write_kernel_mem_report(&self, start: KernelMemSnapshot, end: KernelMemSnapshot, timestamp: &str) -> Result<()> {
+        let growth = kernelmem::slab_growth(&start.slabs, &end.slabs);
+        info!(
+            "vmalloc total: {} -> {} ({})",
+            units::kb(start.vmalloc_total_kb),
+            units::kb(end.vmalloc_total_kb),
+            units::delta_kb(end.vmalloc_total_kb as i64 - start.vmalloc_total_kb as i64)
+        );
+        info!("Top slab growth:");
+        for g in growth.iter().take(10) {
+            info!("Slab: {:<28} Start: {:>10}  End: {:>10}  Delta: {:>11}", g.name, units::kb(g.start_kb), units::kb(g.end_kb), units::delta_kb(g.delta_kb));
+        }
+
+        let json_file = naming::output_file("kernel_mem", timestamp, "json");
+        let report = serde_json::json!({ "start": start, "end": end, "slab_growth": growth });
+        std::fs::write(&json_file, schema::to_versioned_json("kernel_mem", &report)?)?;
+        info!("Kernel memory snapshots written to {}", json_file);
+        Ok(())
+    }
+
+    fn sample_dmabuf(&self, pid: &str, time: SampleTime) -> Result<Option<DmaBufSample>> {
+        let dump = self.shell(&["dmabuf_dump", pid, "2>/dev/null"])?;
+        if let Some(sample) = dmabuf::parse_dmabuf_dump(&dump, time) {
+            return Ok(Some(sample));
+        }
+        let fdinfo = self.shell(&["cat", &format!("/proc/{}/fdinfo/*", pid), "2>/dev/null"])?;
+        Ok(dmabuf::parse_fdinfo(&fdinfo, time))
+    }
+
+    fn write_dmabuf_samples(&self, samples: &[DmaBufSample], timestamp: &str) -> Result<()> {
+        let json_file = naming::output_file("dmabuf", timestamp, "json");
+        let csv_file_path = naming::output_file("dmabuf", timestamp, "csv");
+
+        let json = schema::to_versioned_json("dmabuf", samples)?;
+        std::fs::write(&json_file, json)?;
+        info!("DMA-BUF samples written to {}", json_file);
+
+        let csv_file = File::create(&csv_file_path)?;
+        let mut csv_file = BufWriter::new(csv_file);
+        writeln!(csv_file, "timestamp,host_time_ms,device_time_ms,rss_kb,pss_kb,buffers")?;
+        for s in samples {
+            writeln!(csv_file, "{},{},{},{},{},{}", s.time.timestamp, s.time.host_time_ms, s.time.device_time_ms, s.rss_kb, s.pss_kb, s.buffers)?;
+        }
+        csv_file.flush()?;
+        info!("DMA-BUF samples written to {}", csv_file_path);
+
+        let peak = samples.iter().max_by_key(|s| s.rss_kb).unwrap();
+        info!("DMA-BUF peak: {} in {} buffers at {}s", units::kb(peak.rss_kb), peak.buffers, peak.time.timestamp);
+        Ok(())
+    }
+
+    /// Bluetooth state and the app's connections and scans.
+    pub fn bluetooth_snapshot(&self, time: SampleTime) -> Result<BluetoothSnapshot> {
+        let output = self.shell(&["dumpsys", "bluetooth_manager"])?;
+        Ok(bluetooth::parse_bluetooth_manager(&output, &self.config.package_name, time))
+    }
+
+    fn write_bluetooth_report(&self, start: BluetoothSnapshot, end: BluetoothSnapshot, timestamp: &str) -> Result<()> {
+        info!(
+            "Bluetooth: {} scans started, {} ms scanning, {} unfiltered scans, {} GATT connections during the run",
+            end.scans_started.saturating_sub(start.scans_started),
+            end.total_scan_ms.saturating_sub(start.total_scan_ms),
+            end.unfiltered_scans.saturating_sub(start.unfiltered_scans),
+            end.gatt_connections
+        );
+        // An unfiltered scan running at both ends of the session is almost
+        // certainly a scan that was never stopped.
+        if start.has_unfiltered_ongoing_scan() && end.has_unfiltered_ongoing_scan() {
+            warn!(format!("{} kept an unfiltered BLE scan running for the whole session", self.config.package_name));
+        }
+        let json_file = naming::output_file("bluetooth", timestamp, "json");
+        let report = serde_json::json!({ "start": start, "end": end });
+        std::fs::write(&json_file, schema::to_versioned_json("bluetooth", &report)?)?;
+        info!("Bluetooth snapshots written to {}", json_file);
+        Ok(())
+    }
+
+    fn write_wifi_samples(&self, samples: &[WifiSample], timestamp: &str) -> Result<()> {
+        if samples.is_empty() {
+            warn!("Wi-Fi was never connected during monitoring, no signal samples recorded");
+            return Ok(());
+        }
+        let json_file = naming::output_file("wifi", timestamp, "json");
+        let csv_file_path = naming::output_file("wifi", timestamp, "csv");
+
+        let json = schema::to_versioned_json("wifi", samples)?;
+        std::fs::write(&json_file, json)?;
+        info!("Wi-Fi samples written to {}", json_file);
+
+        let csv_file = File::create(&csv_file_path)?;
+        let mut csv_file = BufWriter::new(csv_file);
+        writeln!(csv_file, "timestamp,host_time_ms,device_time_ms,rssi_dbm,link_speed_mbps,frequency_mhz")?;
+        for s in samples {
+            writeln!(csv_file, "{},{},{},{},{},{}", s.time.timestamp, s.time.host_time_ms, s.time.device_time_ms, s.rssi_dbm, s.link_speed_mbps, s.frequency_mhz)?;
+        }
+        csv_file.flush()?;
+        info!("Wi-Fi samples written to {}", csv_file_path);
+
+        let avg_rssi = samples.iter().map(|s| s.rssi_dbm as f64).sum::<f64>() / samples.len() as f64;
+        info!(
+            "Wi-Fi RSSI: min {} dBm, avg {:.1} dBm; link speed min {} Mbps",
+            samples.iter().map(|s| s.rssi_dbm).min().unwrap_or(0),
+            avg_rssi,
+            samples.iter().map(|s| s.link_speed_mbps).min().unwrap_or(0)
+        );
+        Ok(())
+    }
+
+    fn write_psi_samples(
+        &self,
+        samples: &[PsiSample],
+        alerter: &PsiAlerter,
+        timestamp: &str,
+        frozen: &[FrozenInterval],
+        paused: &[PausedInterval],
+        marks: &[SessionMark],
+    ) -> Result<()> {
+        let compression = self.config.compression()?;
+        let json_file = naming::output_file("psi", timestamp, &compress::output_path("json", compression));
+        let csv_file_path = naming::output_file("psi", timestamp, &compress::output_path("csv", compression));
+
+        let json = schema::to_versioned_json("psi", samples)?;
+        compress::write(&json_file, json, compression)?;
+        info!("PSI samples written to {}", json_file);
+
+        let mut csv_file = compress::create(&csv_file_path, compression)?;
+        write!(csv_file, "timestamp,host_time_ms,device_time_ms")?;
+        for resource in vmstats::PSI_RESOURCES {
+            write!(csv_file, ",{0}_some_avg10,{0}_some_avg60,{0}_full_avg10,{0}_full_avg60", resource)?;
+        }
+        writeln!(csv_file)?;
+        for s in samples {
+            write!(csv_file, "{},{},{}", s.time.timestamp, s.time.host_time_ms, s.time.device_time_ms)?;
+            for resource in vmstats::PSI_RESOURCES {
+                let r = s.reading(resource);
+                write!(csv_file, ",{:.2},{:.2},{:.2},{:.2}", r.some_avg10, r.some_avg60, r.full_avg10, r.full_avg60)?;
+            }
+            writeln!(csv_file)?;
+        }
+        compress::close(csv_file)?;
+        info!("PSI samples written to {}", csv_file_path);
+
+        if !alerter.alerts.is_empty() {
+            let alerts_file = naming::output_file("psi_alerts", timestamp, &compress::output_path("json", compression));
+            compress::write(&alerts_file, schema::to_versioned_json("psi_alerts", &alerter.alerts)?, compression)?;
+            warn!(format!("{} PSI alerts raised, see {}", alerter.alerts.len(), alerts_file));
+        }
+        for resource in vmstats::PSI_RESOURCES {
+            let peak = |f: fn(&vmstats::PsiReading) -> f64| samples.iter().map(|s| f(s.reading(resource))).fold(0.0, f64::max);
+            info!(
+                "PSI {:<6} peak some avg10 {:>6.2}%  peak full avg10 {:>6.2}%",
+                resource,
+                peak(|r| r.some_avg10),
+                peak(|r| r.full_avg10)
+            );
+        }
+
+        self.plot_psi(samples, PSI_PLOT_FILE, frozen, paused, marks)
+    }
+
+    fn plot_psi(&self, samples: &[PsiSample], output: &str, frozen: &[FrozenInterval], paused: &[PausedInterval], marks: &[SessionMark]) -> Result<()> {
+        let theme = &self.config.plot_theme;
+        let colors = theme.series()?;
+        let root = BitMapBackend::new(output, (1200, 800)).into_drawing_area();
+        root.fill(&theme.background())?;
+
+        let max_time = samples.last().map(|s| s.time.secs()).unwrap_or(1.0);
+        let mut chart = ChartBuilder::on(&root)
+            .caption("Pressure Stall Information (avg10)", ("sans-serif", 40).into_font().color(&theme.foreground()))
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(50)
+            .build_cartesian_2d(0f64..max_time, 0f64..100f64)?;
+
+        configure_mesh(&mut chart, theme, "Time (s)", "Stalled time (%)")?;
+        draw_frozen_intervals(&mut chart, frozen, 100.0, theme.shade())?;
+        draw_marks(&mut chart, marks, 100.0, theme)?;
+
+        let series: [(&str, PsiSeriesFn); 6] = [
+            ("cpu some", |s| s.cpu.some_avg10),
+            ("cpu full", |s| s.cpu.full_avg10),
+            ("io some", |s| s.io.some_avg10),
+            ("io full", |s| s.io.full_avg10),
+            ("memory some", |s| s.memory.some_avg10),
+            ("memory full", |s| s.memory.full_avg10),
+        ];
+        for (i, (label, value)) in series.into_iter().enumerate() {
+            let style = colors[i % colors.len()].stroke_width(theme.line_width);
+            let data = self.smoothed(samples.iter().map(|s| (s.time.secs(), value(s))).collect());
+            chart.draw_series(pause::split_at_pauses(data, paused).into_iter().flat_map(|run| LineSeries::new(run, style)))?
+                .label(label)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+        }
+
+        draw_legend(&mut chart, theme)?;
+
+        root.present()?;
+        info!("PSI plot saved to {}", output);
+        Ok(())
+    }
+
+    fn write_device_memory_samples(&self, samples: &[DeviceMemorySample], timestamp: &str) -> Result<()> {
+        let compression = self.config.compression()?;
+        let json_file = naming::output_file("device_memory", timestamp, &compress::output_path("json", compression));
+        let csv_file_path = naming::output_file("device_memory", timestamp, &compress::output_path("csv", compression));
+
+        let json = schema::to_versioned_json("device_memory", samples)?;
+        compress::write(&json_file, json, compression)?;
+        info!("Device memory samples written to {}", json_file);
+
+        let mut csv_file = compress::create(&csv_file_path, compression)?;
+        writeln!(
+            csv_file,
+            "timestamp,host_time_ms,device_time_ms,swap_used_kb,zram_orig_kb,zram_compr_kb,zram_used_kb,swap_in_rate,swap_out_rate,kswapd_scan_rate,psi_some_avg10,psi_full_avg10"
+        )?;
+        for s in samples {
+            writeln!(
+                csv_file,
+                "{},{},{},{},{},{},{},{:.2},{:.2},{:.2},{:.2},{:.2}",
+                s.time.timestamp,
+                s.time.host_time_ms,
+                s.time.device_time_ms,
+                s.swap_used_kb,
+                s.zram_orig_kb,
+                s.zram_compr_kb,
+                s.zram_used_kb,
+                s.swap_in_rate,
+                s.swap_out_rate,
+                s.kswapd_scan_rate,
+                s.psi_some_avg10,
+                s.psi_full_avg10
+            )?;
+        }
+        compress::close(csv_file)?;
+        info!("Device memory samples written to {}", csv_file_path);
+
+        let peak = |f: fn(&DeviceMemorySample) -> f64| samples.iter().map(f).fold(0.0, f64::max);
+        info!(
+            "Device memory: peak swap {}, peak swap-in {:.1} pages/s, peak swap-out {:.1} pages/s, peak kswapd scan {:.1} pages/s, peak PSI some/full avg10 {:.2}%/{:.2}%",
+            units::kb(samples.iter().map(|s| s.swap_used_kb).max().unwrap_or(0)),
+            peak(|s| s.swap_in_rate),
+            peak(|s| s.swap_out_rate),
+            peak(|s| s.kswapd_scan_rate),
+            peak(|s| s.psi_some_avg10),
+            peak(|s| s.psi_full_avg10)
+        );
+        Ok(())
+    }
+
+    /// Cumulative CPU ticks of the process and frames rendered by the app.
+    fn panel_counters(&self, pid: Option<&str>) -> Result<PanelCounters> {
+        let cpu_ticks = match pid {
+            Some(pid) => composite::parse_cpu_ticks(&self.shell(&[composite::proc_stat_cmd(pid)])?),
+            None => None,
+        };
+        let frames = composite::parse_frames_rendered(&self.shell(&["dumpsys", "gfxinfo", &self.meminfo_target()?])?);
+        Ok(PanelCounters { cpu_ticks, frames })
+    }
+
+    /// Memory, CPU, FPS and temperature in stacked panels over one time
+    /// axis, each with its own y scale.
+    fn plot_composite(
+        &self,
+        samples: &[MemorySample],
+        panels: &[PanelSample],
+        output: &str,
+        frozen: &[FrozenInterval],
+        paused: &[PausedInterval],
+        marks: &[SessionMark],
+    ) -> Result<()> {
+        let theme = &self.config.plot_theme;
+        let root = BitMapBackend::new(output, (1200, 1600)).into_drawing_area();
+        root.fill(&theme.background())?;
+        let root = root.titled("Memory, CPU, FPS and Temperature", ("sans-serif", 40).into_font().color(&theme.foreground()))?;
+        let areas = root.split_evenly((4, 1));
+        let max_time = samples.last().map_or(1.0, |s| s.time.secs()).max(1.0);
+
+        let mb = |f: fn(&MemorySample) -> u64| self.smoothed(samples.iter().map(|s| (s.time.secs(), f(s) as f64 / 1024.0)).collect());
+        let panel = |f: fn(&PanelSample) -> Option<f64>| self.smoothed(panels.iter().filter_map(|p| f(p).map(|v| (p.time.secs(), v))).collect());
+        let memory = [
+            ("Total PSS", mb(|s| s.total_pss)),
+            ("Native Heap", mb(|s| s.native_heap)),
+            ("Dalvik Heap", mb(|s| s.dalvik_heap)),
+            ("Graphics", mb(|s| s.graphics)),
+        ];
+        draw_panel(&areas[0], theme, "Memory (MB)", &memory, max_time, frozen, paused, marks)?;
+        draw_panel(&areas[1], theme, "App CPU (%)", &[("CPU", panel(|p| p.cpu_percent))], max_time, frozen, paused, marks)?;
+        draw_panel(&areas[2], theme, "Frames/s", &[("Rendered frames", panel(|p| p.fps))], max_time, frozen, paused, marks)?;
+        draw_panel(&areas[3], theme, "CPU temperature (°C)", &[("Hottest CPU zone", panel(|p| p.cpu_temp_c))], max_time, frozen, paused, marks)?;
+
+        root.present()?;
+        info!("Composite plot saved to {}", output);
+        Ok(())
+    }
+
+    /// `points` as drawn: raw, or averaged with `--smooth`.
+    fn smoothed(&self, points: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
+        match self.config.smooth {
+            Some(window) if window > 1 => smoothing::moving_average(&points, window),
+            _ => points,
+        }
+    }
+
+    /// The config's derived metrics, checked against the collected series,
+    /// along with the plot overlay names.
+    fn derived_metrics(&self) -> Result<Vec<DerivedMetric>> {
+        let metrics = derived::parse_definitions(&self.config.derived_metrics, &derived::known_metrics())?;
+        if let Some(unknown) = self.config.plot_overlay.iter().find(|name| {
+            !derived::PANEL_METRICS.contains(&name.as_str()) && !metrics.iter().any(|m| &m.name == *name)
+        }) {
+            return Err(anyhow!("Unknown plot overlay series {:?}, expected one of {} or a derived metric", unknown, derived::PANEL_METRICS.join(", ")));
+        }
+        Ok(metrics)
+    }
+
+    /// One panel per derived metric, so ratios and KB values each keep a
+    /// readable scale.
+    fn plot_derived(&self, samples: &[MemorySample], metrics: &[DerivedMetric], frozen: &[FrozenInterval], paused: &[PausedInterval], marks: &[SessionMark]) -> Result<()> {
+        let series: Vec<Series> = metrics
+            .iter()
+            .map(|m| (m.name.as_str(), samples.iter().filter_map(|s| s.derived.get(&m.name).map(|v| (s.time.secs(), *v))).collect()))
+            .collect();
+        let max_time = samples.last().map_or(1.0, |s| s.time.secs());
+        self.plot_panels("Derived Metrics", &series, derived::DERIVED_PLOT_FILE, max_time, frozen, paused, marks)
+    }
+
+    /// Each of `series` in a panel of its own, over one time axis.
+    #[allow(clippy::too_many_arguments)]
+    fn plot_panels(
+        &self,
+        title: &str,
+        series: &[Series],
+        output: &str,
+        max_time: f64,
+        frozen: &[FrozenInterval],
+        paused: &[PausedInterval],
+        marks: &[SessionMark],
+    ) -> Result<()> {
+        let theme = &self.config.plot_theme;
+        let root = BitMapBackend::new(output, (1200, 400 * series.len() as u32)).into_drawing_area();
+        root.fill(&theme.background())?;
+        let root = root.titled(title, ("sans-serif", 40).into_font().color(&theme.foreground()))?;
+        let areas = root.split_evenly((series.len(), 1));
+        for (area, (name, data)) in areas.iter().zip(series) {
+            draw_panel(area, theme, name, &[(name, self.smoothed(data.clone()))], max_time.max(1.0), frozen, paused, marks)?;
+        }
+        root.present()?;
+        info!("{} plot saved to {}", title, output);
+        Ok(())
+    }
+
+    /// Follows logcat from now on, as `-T 1` would replay the last line
+    /// logged before the session.
+    fn start_metric_stream(&self, clock: &clocksync::ClockSync) -> Result<MetricStream> {
+        let since = clock.logcat_time(chrono::Utc::now().timestamp_millis());
+        let stream = self.stream(&appmetrics::logcat_args(&since), Stdio::null())?;
+        let (slow_queries, native_libs) = (self.config.slow_queries.is_some(), self.config.native_libs);
+        MetricStream::start(stream, move |line| {
+            appmetrics::is_metric(line) || (slow_queries && queries::is_slow_query_log(line)) || (native_libs && nativelibs::is_library_log(line))
+        })
+    }
+
+    /// Sets the slow query threshold for a session. The device's earlier
+    /// value is put back when the returned guard drops, or on Ctrl-C.
+    fn enable_slow_query_log(&self) -> Result<Option<control::TeardownGuard>> {
+        let Some(threshold) = self.config.slow_queries else {
+            return Ok(None);
+        };
+        let old = self.shell(&["getprop", queries::THRESHOLD_PROP])?.trim().to_string();
+        self.shell(&[&queries::threshold_cmd(threshold)])?;
+        let analyzer = self.clone();
+        Ok(Some(control::on_teardown(move || {
+            if let Err(e) = analyzer.shell(&[&queries::restore_threshold_cmd(&old)]) {
+                warn!(format!("Could not restore {}: {}", queries::THRESHOLD_PROP, e));
+            }
+        })))
+    }
+
+    /// Parses slow statements of the app out of `lines`, dropping ones
+    /// logged before the session.
+    fn collect_slow_queries(&self, lines: &[String], clock: &clocksync::ClockSync, start_ms: i64, queries: &mut Vec<SlowQuery>) {
+        for query in lines.iter().filter_map(|line| queries::parse_sqlite_line(line, &self.config.package_name, clock, start_ms)) {
+            info!("Slow query: {} ms {} {}", query.duration_ms, query.operation, query.statement);
+            queries.push(query);
+        }
+    }
+
+    /// Adds the session's slow ContentResolver calls from the events buffer
+    /// to the statements collected from logcat, in time order.
+    fn finish_slow_queries(&self, mut queries: Vec<SlowQuery>, clock: &clocksync::ClockSync, start_ms: i64) -> Result<Vec<SlowQuery>> {
+        let output = self.adb().args(queries::CONTENT_EVENTS_ARGS).output()?;
+        queries.extend(queries::parse_content_events(&console::decode(&output.stdout), &self.config.package_name, clock, start_ms));
+        queries.sort_by_key(|q| q.time.host_time_ms);
+        Ok(queries)
+    }
+
+    fn write_slow_queries(&self, queries: &[SlowQuery], timestamp: &str, duration: u64, frozen: &[FrozenInterval], marks: &[SessionMark]) -> Result<()> {
+        if queries.is_empty() {
+            info!("No slow SQLite statements or ContentResolver calls seen for {}", self.config.package_name);
+            return Ok(());
+        }
+        let stats = queries::by_statement(queries);
+        info!("Slowest queries of {}:", self.config.package_name);
+        for stat in stats.iter().take(queries::SLOWEST_ROWS) {
+            let source = if stat.source == QuerySource::Sqlite { "sqlite" } else { "provider" };
+            info!("{:>7} ms max {:>8} ms total {:>5}x  {:<8} {}", stat.max_ms, stat.total_ms, stat.count, source, stat.statement);
+        }
+        let json_file = naming::output_file("slow_queries", timestamp, "json");
+        let report = serde_json::json!({ "threshold_ms": self.config.slow_queries, "statements": stats, "queries": queries });
+        std::fs::write(&json_file, schema::to_versioned_json("slow_queries", &report)?)?;
+        let csv_file_path = naming::output_file("slow_queries", timestamp, "csv");
+        let mut csv_file = BufWriter::new(File::create(&csv_file_path)?);
+        writeln!(csv_file, "timestamp,host_time_ms,device_time_ms,source,operation,duration_ms,statement,database")?;
+        for query in queries {
+            let source = serde_json::to_value(query.source)?;
+            writeln!(csv_file, "{},{},{},{},{},{},\"{}\",{}", query.time.timestamp, query.time.host_time_ms, query.time.device_time_ms,
+                source.as_str().unwrap_or_default(), query.operation, query.duration_ms, query.statement.replace('"', "\"\""),
+                query.database.as_deref().unwrap_or(""))?;
+        }
+        csv_file.flush()?;
+        info!("{} slow queries written to {} and {}", queries.len(), json_file, csv_file_path);
+        self.plot_slow_queries(queries, duration as f64, frozen, marks)
+    }
+
+    /// Each query as a stem up to its duration at its session time, so
+    /// stalls line up with the session's markers. Not smoothed.
+    fn plot_slow_queries(&self, queries: &[SlowQuery], max_time: f64, frozen: &[FrozenInterval], marks: &[SessionMark]) -> Result<()> {
+        let output = queries::QUERIES_PLOT_FILE;
+        let theme = &self.config.plot_theme;
+        let colors = theme.series()?;
+        let root = BitMapBackend::new(output, (1200, 600)).into_drawing_area();
+        root.fill(&theme.background())?;
+        let title = format!("{} slow queries", self.config.package_name);
+        let root = root.titled(&title, ("sans-serif", 40).into_font().color(&theme.foreground()))?;
+        let max_y = queries.iter().map(|q| q.duration_ms as f64).fold(1.0, f64::max) * 1.2;
+        let mut chart = ChartBuilder::on(&root)
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(60)
+            .build_cartesian_2d(0f64..max_time.max(1.0), 0f64..max_y)?;
+        configure_mesh(&mut chart, theme, "Time (s)", "Duration (ms)")?;
+        draw_frozen_intervals(&mut chart, frozen, max_y, theme.shade())?;
+        draw_marks(&mut chart, marks, max_y, theme)?;
+        for (i, (source, label)) in [(QuerySource::Sqlite, "SQLite statement"), (QuerySource::ContentProvider, "ContentResolver call")].into_iter().enumerate() {
+            let style = colors[i % colors.len()].stroke_width(theme.line_width);
+            let points: Vec<(f64, f64)> = queries.iter().filter(|q| q.source == source).map(|q| (q.time.secs(), q.duration_ms as f64)).collect();
+            chart.draw_series(points.iter().map(|&(x, y)| PathElement::new(vec![(x, 0.0), (x, y)], style)))?;
+            chart.draw_series(points.iter().map(|&point| Circle::new(point, 4, style.filled())))?
+                .label(label)
+                .legend(move |(x, y)| Circle::new((x + 10, y), 4, style.filled()));
+        }
+        draw_legend(&mut chart, theme)?;
+        root.present()?;
+        info!("Slow query plot saved to {}", output);
+        Ok(())
+    }
+
+    /// Parses metric `lines` from the stream, dropping ones logged before
+    /// the session or by other processes.
+    fn collect_app_metrics(&self, lines: &[String], clock: &clocksync::ClockSync, start_ms: i64, pids: &mut appmetrics::AppPids, metrics: &mut Vec<AppMetric>) {
+        for line in lines {
+            let (Some((name, value)), Some(host_ms)) = (appmetrics::parse_metric(line), clock.host_time_ms(line, None)) else {
+                continue;
+            };
+            if host_ms >= start_ms && pids.is_app(line, || self.parser_profile().and_then(|p| self.get_pid(&p)).ok()) {
+                info!("App metric {} = {}", name, value);
+                metrics.push(AppMetric { time: clock.time_at(start_ms, host_ms), name, value });
+            }
+        }
+    }
+
+    fn write_app_metrics(&self, metrics: &[AppMetric], timestamp: &str, frozen: &[FrozenInterval], marks: &[SessionMark]) -> Result<()> {
+        let json_file = naming::output_file("app_metrics", timestamp, "json");
+        std::fs::write(&json_file, schema::to_versioned_json("app_metrics", metrics)?)?;
+        let csv_file_path = naming::output_file("app_metrics", timestamp, "csv");
+        let mut csv_file = BufWriter::new(File::create(&csv_file_path)?);
+        writeln!(csv_file, "timestamp,host_time_ms,device_time_ms,name,value")?;
+        for metric in metrics {
+            writeln!(csv_file, "{},{},{},{},{}", metric.time.timestamp, metric.time.host_time_ms, metric.time.device_time_ms, metric.name, metric.value)?;
+        }
+        csv_file.flush()?;
+        info!("{} app metric value(s) written to {} and {}", metrics.len(), json_file, csv_file_path);
+
+        let series: Vec<Series> = appmetrics::names(metrics)
+            .into_iter()
+            .map(|name| (name, metrics.iter().filter(|m| m.name == name).map(|m| (m.time.secs(), m.value)).collect()))
+            .collect();
+        let max_time = metrics.iter().map(|m| m.time.secs()).fold(0.0, f64::max);
+        // The app logs them through pauses too.
+        self.plot_panels("App Metrics", &series, appmetrics::APP_METRICS_PLOT_FILE, max_time, frozen, &[], marks)
+    }
+
+    /// The `--plot-overlay` series, from the panel samples or the derived
+    /// metrics.
+    fn overlay_series(&self, samples: &[MemorySample], panels: &[PanelSample]) -> Result<Vec<Series<'_>>> {
+        let mut series = Vec::new();
+        for name in &self.config.plot_overlay {
+            let mut data = Vec::new();
+            if derived::PANEL_METRICS.contains(&name.as_str()) {
+                for panel in panels {
+                    if let Some(value) = derived::numeric_fields(&serde_json::to_value(panel)?).get(name) {
+                        data.push((panel.time.secs(), *value));
+                    }
+                }
+            } else {
+                data.extend(samples.iter().filter_map(|s| s.derived.get(name).map(|v| (s.time.secs(), *v))));
+            }
+            series.push((name.as_str(), self.smoothed(data)));
+        }
+        Ok(series)
+    }
+
+    fn plot_memory_curve(
+        &self,
+        samples: &[MemorySample],
+        panels: &[PanelSample],
+        output: &str,
+        frozen: &[FrozenInterval],
+        paused: &[PausedInterval],
+        marks: &[SessionMark],
+    ) -> Result<()> {
+        if self.config.chart_format == charts::ChartFormat::Interactive {
+            return self.chart_memory_curve(samples, panels, &charts::chart_file(output), frozen, paused, marks);
+        }
+        if is_svg(output) {
+            self.draw_memory_curve(SVGBackend::new(output, (1200, 800)).into_drawing_area(), samples, panels, frozen, paused, marks)?;
+        } else {
+            self.draw_memory_curve(BitMapBackend::new(output, (1200, 800)).into_drawing_area(), samples, panels, frozen, paused, marks)?;
+        }
+        info!("Memory usage plot saved to {}", output);
+        Ok(())
+    }
+
+    fn draw_memory_curve<DB: DrawingBackend>(
+        &self,
+        root: DrawingArea<DB, Shift>,
+        samples: &[MemorySample],
+        panels: &[PanelSample],
+        frozen: &[FrozenInterval],
+        paused: &[PausedInterval],
+        marks: &[SessionMark],
+    ) -> Result<()>
+    where
+        DB::ErrorType: 'static,
+    {
+        let theme = &self.config.plot_theme;
+        let colors = theme.series()?;
+        root.fill(&theme.background())?;
+
+        let max_pss = samples.iter().map(|s| s.total_pss as f64).max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap_or(1000.0) * 1.2;
+        let max_time = samples.last().map(|s| s.time.secs()).unwrap_or(1.0);
+
+        let overlay = self.overlay_series(samples, panels)?;
+
+        let mut builder = ChartBuilder::on(&root);
+        builder
+            .caption("Detailed Memory Usage Over Time", ("sans-serif", 40).into_font().color(&theme.foreground()))
+            .margin(10)
+            .x_label_area_size(30)
+            .y_label_area_size(50);
+        if !overlay.is_empty() {
+            builder.right_y_label_area_size(60);
+        }
+        let names: Vec<&str> = overlay.iter().map(|(name, _)| *name).collect();
+        let mut chart = with_right_axis(builder.build_cartesian_2d(0f64..max_time, 0f64..max_pss)?, theme, &names.join(", "), &overlay)?;
+
+        configure_mesh(&mut chart, theme, "Time (s)", "Memory (KB)")?;
+        draw_frozen_intervals(&mut chart, frozen, max_pss, theme.shade())?;
+        draw_marks(&mut chart, marks, max_pss, theme)?;
+
+        let curves = memory_curve_series(samples);
+        for (i, (label, data_fn)) in curves.iter().enumerate() {
+            let data = self.smoothed(samples.iter().map(data_fn).collect());
+            let style = colors[i % colors.len()].stroke_width(theme.line_width);
+            chart.draw_series(pause::split_at_pauses(data, paused).into_iter().flat_map(|run| LineSeries::new(run, style)))?
+                .label(*label)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+        }
+
+        draw_right_series(&mut chart, theme, &overlay, curves.len(), paused)?;
+        draw_legend(&mut chart, theme)?;
+
+        root.present()?;
+        Ok(())
+    }
+
+    /// `plot_memory_curve` as an interactive HTML chart. Overlay series
+    /// share its y axis; hiding the memory series rescales it to them.
+    fn chart_memory_curve(
+        &self,
+        samples: &[MemorySample],
+        panels: &[PanelSample],
+        output: &str,
+        frozen: &[FrozenInterval],
+        paused: &[PausedInterval],
+        marks: &[SessionMark],
+    ) -> Result<()> {
+        let theme = &self.config.plot_theme;
+        let colors = theme.series()?;
+        let mut series: Vec<charts::ChartSeries> = memory_curve_series(samples)
+            .iter()
+            .map(|(label, data_fn)| (label.to_string(), "KB", self.smoothed(samples.iter().map(data_fn).collect())))
+            .chain(self.overlay_series(samples, panels)?.into_iter().map(|(name, data)| (name.to_string(), "", data)))
+            .enumerate()
+            .map(|(i, (name, unit, points))| charts::ChartSeries {
+                name,
+                unit: unit.to_string(),
+                color: charts::css_color(&colors[i % colors.len()]),
+                points,
+            })
+            .collect();
+        series.retain(|s| !s.points.is_empty());
+        let chart = charts::Chart {
+            title: "Detailed Memory Usage Over Time".to_string(),
+            x_label: "Time (s)".to_string(),
+            y_label: "Memory (KB)".to_string(),
+            series,
+            marks: marks.iter().map(|m| (m.offset_secs, m.label.clone())).collect(),
+            shaded: frozen.iter().map(|f| (f.start as f64, f.end as f64)).collect(),
+            gaps: paused.iter().map(|p| (p.start as f64, p.end as f64)).collect(),
+            background: charts::css_color(&theme.background()),
+            foreground: charts::css_color(&theme.foreground()),
+            grid: charts::css_color(&theme.grid()),
+            shade: charts::css_color(&theme.shade()),
+        };
+        std::fs::write(output, charts::render(&chart)?)?;
+        info!("Interactive memory chart saved to {}", output);
+        Ok(())
+    }
+
+    /// The app's threads, as `ps -T` lists them.
+    pub fn analyze_threads(&self) -> Result<Vec<ThreadInfo>> {
+        let profile = self.parser_profile()?;
+        let pid = self.get_pid(&profile)?;
+        let mut diags = ParseDiagnostics::new(self.config.strict_parse);
+        let threads = self.read_threads(&profile, &pid, &mut diags)?;
+
+        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+        write_parse_diagnostics(&diags, &timestamp)?;
+        let json_file = naming::output_file("thread_info", &timestamp, "json");
+        let csv_file_path = naming::output_file("thread_info", &timestamp, "csv");
+
+        let json = schema::to_versioned_json("thread_info", &threads)?;
+        std::fs::write(&json_file, json)?;
+        info!("Thread info written to {}", json_file);
+
+        let csv_file = File::create(&csv_file_path)?;
+        let mut csv_file = BufWriter::new(csv_file);
+        writeln!(csv_file, "tid,name,state,priority,user_time,system_time")?;
+        for thread in &threads {
+            writeln!(
+                csv_file,
+                "{},{},{},{},{},{}",
+                thread.tid, thread.name, thread.state, thread.priority, thread.user_time, thread.system_time
+            )?;
+        }
+        csv_file.flush()?;
+        info!("Thread info written to {}", csv_file_path);
+        #[cfg(feature = "parquet")]
+        self.write_parquet(&csv_file_path, |path| parquetfile::write_threads(path, &threads))?;
+
+        Ok(threads)
+    }
+
+    fn read_threads(&self, profile: &ParserProfile, pid: &str, diags: &mut ParseDiagnostics) -> Result<Vec<ThreadInfo>> {
+        let ps_output = self.shell(&profile.thread_ps_args(pid))?;
+        let mut threads = profile.parse_threads(&ps_output, diags)?;
+        if profile.needs_task_times() {
+            let stat_output = self.shell(&["cat", &format!("/proc/{}/task/*/stat", pid)])?;
+            compat::apply_task_times(&mut threads, &stat_output);
+        }
+        Ok(threads)
+    }
+
+    /// Streams logcat, samples memory and polls the app's threads at the
+    /// same time, each on its own thread, for `duration` seconds of memory
+    /// sampling, and merges them into one timeline written to
+    /// `timeline_<timestamp>.json`. The memory sampling owns the control
+    /// socket, pauses and the end of the session. With `tui` the session
+    /// is shown on a live dashboard rather than as progress output.
+    /// LeakCanary traces from logcat come back alongside.
+    pub fn run_session(&self, duration: u64, output_image: &str, thread_interval: u64, tui: bool) -> Result<Session> {
+        control::begin_session();
+        let dashboard = tui.then(|| Arc::new(Dashboard::new(&self.config.package_name)));
+        if dashboard.is_some() {
+            // Keys are read by the dashboard instead.
+            markers::claim_terminal();
+        }
+        // Before logcat gets to it, so pause and resume are read.
+        markers::listen_on_terminal(true);
+        let clock = self.start_clock_sync()?;
+        let start_ms = chrono::Utc::now().timestamp_millis();
+        let (tx, rx) = mpsc::channel();
+        let collected = match dashboard.clone() {
+            Some(dashboard) => std::thread::spawn(move || dashboard.show(rx)),
+            None => std::thread::spawn(move || Ok(rx.into_iter().collect())),
+        };
+        let logcat = {
+            let mut analyzer = self.clone();
+            analyzer.config.control_socket = None;
+            // Slow queries are the memory sampling's, which owns the
+            // threshold property too.
+            analyzer.config.slow_queries = None;
+            let tx = tx.clone();
+            std::thread::spawn(move || {
+                let result = analyzer.capture_logcat(None, Some(&tx));
+                if result.is_err() {
+                    control::request_stop();
+                }
+                result
+            })
+        };
+        let threads = {
+            let (analyzer, clock, tx) = (self.clone(), clock.clone(), tx.clone());
+            std::thread::spawn(move || analyzer.poll_threads(thread_interval, &clock, start_ms, &tx))
+        };
+        let samples = self.sample_memory(duration, output_image, Some(&tx));
+        drop(tx);
+        control::request_stop();
+        let logcat = logcat.join().map_err(|_| anyhow!("The logcat thread panicked"))?;
+        let threads = threads.join().map_err(|_| anyhow!("The thread polling thread panicked"))?;
+        let entries = collected.join().map_err(|_| anyhow!("The dashboard thread panicked"))?;
+        let samples = samples?;
+        let leaks = logcat?;
+        let thread_snapshots = threads?;
+
+        let end_ms = chrono::Utc::now().timestamp_millis();
+        let marks = markers::session_marks(start_ms, end_ms, Vec::new());
+        let timeline = timeline::merge(entries?, &marks, &clock, start_ms);
+        let json_file = naming::output_file(timeline::TIMELINE_FILE_STEM, chrono::Local::now().format("%Y%m%d_%H%M%S"), "json");
+        std::fs::write(&json_file, schema::to_versioned_json("timeline", &timeline)?)?;
+        info!("Timeline of {} entries written to {}", timeline.len(), json_file);
+        Ok(Session { samples, timeline, leaks, thread_snapshots })
+    }
+
+    /// Sends a summary of the app's threads every `interval` seconds until
+    /// the session stops, and returns the threads of each poll. Polls
+    /// while the app is not running are skipped.
+    fn poll_threads(&self, interval: u64, clock: &clocksync::ClockSync, start_ms: i64, timeline: &Sender<TimelineEntry>) -> Result<Vec<ThreadSnapshot>> {
+        let profile = self.parser_profile()?;
+        // Polls only feed summaries, so their parse issues go unreported.
+        let mut diags = ParseDiagnostics::new(false);
+        let mut snapshots = Vec::new();
+        while !control::stop_requested() {
+            let next = Instant::now() + Duration::from_secs(interval);
+            if let Ok(pid) = self.get_pid(&profile) {
+                let threads = self.read_threads(&profile, &pid, &mut diags)?;
+                let time = clock.time_at(start_ms, chrono::Utc::now().timestamp_millis());
+                let _ = timeline.send(TimelineEntry::threads(time, &threads));
+                snapshots.push(ThreadSnapshot { time, threads });
+            }
+            while Instant::now() < next && !control::stop_requested() {
+                std::thread::sleep(pause::POLL_INTERVAL);
+            }
+        }
+        Ok(snapshots)
+    }
+
+    /// The `.so` rows of the app's meminfo.
+    pub fn analyze_so_memory(&self) -> Result<Vec<SoMemoryInfo>> {
+        let mut buffer = String::new();
+        self.get_memory_info_into(&mut buffer)?;
+        let mut so_libs = Vec::new();
+        let lines = buffer.lines().collect::<Vec<_>>();
+        let mut in_so_section = false;
+
+        for (i, line) in lines.iter().enumerate() {
+            if line.contains("Native Heap") {
+                in_so_section = true;
+                continue;
+            }
+            if in_so_section && (line.contains("Dalvik Heap") || line.trim().is_empty()) {
+                break;
+            }
+            if i > 1000 { // Safety limit
+                warn!("Reached line limit in .so parsing, stopping");
+                break;
+            }
+            if in_so_section && !line.trim().is_empty() {
+                if let Some(caps) = SO_REGEX.captures(line) {
+                    let pss = caps.get(1).and_then(|m| m.as_str().parse::<u64>().ok()).unwrap_or(0);
+                    let private_dirty = caps.get(2).and_then(|m| m.as_str().parse::<u64>().ok()).unwrap_or(0);
+                    let shared_dirty = caps.get(3).and_then(|m| m.as_str().parse::<u64>().ok()).unwrap_or(0);
+                    let name = caps.get(4).map_or("unknown.so", |m| m.as_str()).to_string();
+                    so_libs.push(SoMemoryInfo { name, pss, private_dirty, shared_dirty });
+                } else {
+                    warn!(format!("Failed to parse .so line: {}", line));
+                }
+            }
+        }
+
+        if so_libs.is_empty() {
+            warn!(format!("No .so libraries found in memory info for {}", self.config.package_name));
+        } else {
+            so_libs.sort_unstable_by_key(|so| std::cmp::Reverse(so.pss));
+        }
+
+        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+        let json_file = naming::output_file("so_memory", &timestamp, "json");
+        let csv_file_path = naming::output_file("so_memory", &timestamp, "csv");
+
+        let json = schema::to_versioned_json("so_memory", &so_libs)?;
+        std::fs::write(&json_file, json)?;
+        info!("SO memory info written to {}", json_file);
+
+        let csv_file = File::create(&csv_file_path)?;
+        let mut csv_file = BufWriter::new(csv_file);
+        writeln!(csv_file, "name,pss,private_dirty,shared_dirty")?;
+        for so in &so_libs {
+            writeln!(csv_file, "{},{},{},{}", so.name, so.pss, so.private_dirty, so.shared_dirty)?;
+        }
+        csv_file.flush()?;
+        info!("SO memory info written to {}", csv_file_path);
+        #[cfg(feature = "parquet")]
+        self.write_parquet(&csv_file_path, |path| parquetfile::write_so_memory(path, &so_libs))?;
+
+        Ok(so_libs)
+    }
+
+    /// Prints the pass/fail table and fails the run on any breach. Library
+    /// budgets take a fresh .so snapshot when the run did not collect one.
+    pub fn check_budgets(&self, path: &str, samples: Option<&[MemorySample]>, so_libs: Option<Vec<SoMemoryInfo>>) -> Result<()> {
+        let budgets = budgets::load_budgets(path)?;
+        let mut checks = budgets.check_samples(samples.unwrap_or_default());
+        if budgets.needs_libraries() {
+            let so_libs = match so_libs {
+                Some(libs) => libs,
+                None => self.analyze_so_memory()?,
+            };
+            let owner_map = self.config.so_owners.as_deref().map(owners::load_owner_map).transpose()?;
+            checks.extend(budgets.check_libraries(&so_libs, owner_map.as_ref())?);
+        }
+
+        info!("Memory budgets ({}):", path);
+        for check in &checks {
+            let actual = check.actual.map_or("no data".to_string(), units::kb);
+            info!("{:<4} {:<30} Limit: {:>10}  Actual: {:>10}", if check.pass { "PASS" } else { "FAIL" }, check.item, units::kb(check.limit), actual);
+        }
+        output::emit("budgets", &checks)?;
+        let failed = checks.iter().filter(|c| !c.pass).count();
+        if failed > 0 {
+            return Err(anyhow!("{} of {} memory budgets exceeded or without data", failed, checks.len()));
+        }
+        Ok(())
+    }
+
+    /// Totals the libraries per owner when an ownership map is configured.
+    pub fn so_owner_totals(&self, so_libs: &[SoMemoryInfo]) -> Result<Option<Vec<owners::OwnerTotal>>> {
+        let Some(path) = &self.config.so_owners else {
+            return Ok(None);
+        };
+        let totals = owners::load_owner_map(path)?.totals(so_libs);
+        let json_file = naming::output_file("so_memory_owners", chrono::Local::now().format("%Y%m%d_%H%M%S"), "json");
+        std::fs::write(&json_file, schema::to_versioned_json("so_memory_owners", &totals)?)?;
+        info!("SO memory by owner written to {}", json_file);
+        Ok(Some(totals))
+    }
+
+    /// The app's estimated power use since the last charge, from the
+    /// batterystats layout of the device's release.
+    pub fn power_use(&self) -> Result<compat::PowerUse> {
+        let profile = self.parser_profile()?;
+        let package = self.shell(&["dumpsys", "package", &self.config.package_name])?;
+        let app_id = compat::parse_app_id(&package).ok_or_else(|| anyhow!("Package {} not found on device", self.config.package_name))?;
+        let uid = compat::format_uid(app_id, self.config.user);
+        if profile.batterystats_proto {
+            let output = self.dumpsys_proto(&["batterystats", "--proto"])?;
+            match proto::parse_power_use_proto(&output, compat::user_uid(app_id, self.config.user), &uid) {
+                Ok(usage) => return Ok(usage),
+                Err(e) => {
+                    warn!(format!("batterystats --proto unusable ({}), falling back to text parsing", e));
+                }
+            }
+        }
+        let output = self.shell(&profile.batterystats_args(&self.config.package_name))?;
+        profile
+            .parse_power_use(&output, &uid)
+            .ok_or_else(|| anyhow!("batterystats has no power estimate for {} ({}) since the last charge", self.config.package_name, uid))
+    }
+
+    /// Time the app spent in each process state over the last `hours`.
+    pub fn analyze_procstats(&self, hours: u32) -> Result<Vec<ProcStateStats>> {
+        let output = self.shell(&["dumpsys", "procstats", "--hours", &hours.to_string(), &self.config.package_name])?;
+        let stats = procstats::parse_procstats(&output, &self.config.package_name);
+        if stats.is_empty() {
+            warn!(format!("No procstats data for {} in the last {} hours", self.config.package_name, hours));
+        }
+
+        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+        let json_file = naming::output_file("procstats", &timestamp, "json");
+        let csv_file_path = naming::output_file("procstats", &timestamp, "csv");
+
+        let json = schema::to_versioned_json("procstats", &stats)?;
+        std::fs::write(&json_file, json)?;
+        info!("Procstats written to {}", json_file);
+
+        let csv_file = File::create(&csv_file_path)?;
+        let mut csv_file = BufWriter::new(csv_file);
+        writeln!(csv_file, "process,state,time_percent,min_pss,avg_pss,max_pss,samples")?;
+        for s in &stats {
+            writeln!(
+                csv_file,
+                "{},{},{},{},{},{},{}",
+                s.process, s.state, s.time_percent, s.min_pss, s.avg_pss, s.max_pss, s.samples
+            )?;
+        }
+        csv_file.flush()?;
+        info!("Procstats written to {}", csv_file_path);
+
+        Ok(stats)
+    }
+
+    /// Forces the device into deep Doze (unplugging the battery virtually,
+    /// since Doze never engages while charging) or restores normal behavior.
+    pub fn set_doze(&self, enter: bool) -> Result<()> {
+        let commands: &[&[&str]] = if enter {
+            &[&["dumpsys", "battery", "unplug"], &["dumpsys", "deviceidle", "force-idle", "deep"]]
+        } else {
+            &[&["dumpsys", "deviceidle", "unforce"], &["dumpsys", "battery", "reset"]]
+        };
+        for command in commands {
+            info!("{}", self.shell(command)?.trim_end());
+        }
+        Ok(())
+    }
+
+    /// Applies the measurement settings, saving the current values first.
+    /// A saved state from an earlier prep is kept so repeated preps never
+    /// record prepped values as the originals. Returns the settings applied.
+    pub fn prep_device(&self, brightness: u32) -> Result<Vec<devprep::SavedSetting>> {
+        let settings = devprep::prep_settings(brightness);
+        if std::path::Path::new(devprep::PREP_STATE_FILE).exists() {
+            info!("Keeping the originals already saved in {}", devprep::PREP_STATE_FILE);
+        } else {
+            let mut saved = Vec::new();
+            for (namespace, key, _) in &settings {
+                let value = devprep::parse_setting_value(&self.shell(&["settings", "get", namespace, key])?);
+                saved.push(devprep::SavedSetting { namespace: namespace.to_string(), key: key.to_string(), value });
+            }
+            std::fs::write(devprep::PREP_STATE_FILE, schema::to_versioned_json("device_prep", &saved)?)?;
+        }
+        let mut applied = Vec::new();
+        for (namespace, key, value) in settings {
+            self.shell(&["settings", "put", namespace, key, &value])?;
+            info!("{} {} = {}", namespace, key, value);
+            applied.push(devprep::SavedSetting { namespace: namespace.to_string(), key: key.to_string(), value: Some(value) });
+        }
+        Ok(applied)
+    }
+
+    /// Puts back the settings `prep_device` saved and returns them.
+    pub fn restore_device(&self) -> Result<Vec<devprep::SavedSetting>> {
+        if !std::path::Path::new(devprep::PREP_STATE_FILE).exists() {
+            return Err(anyhow!("No {} found; run 'device prep' first", devprep::PREP_STATE_FILE));
+        }
+        let saved: Vec<devprep::SavedSetting> = schema::read_json_file(devprep::PREP_STATE_FILE, "device_prep")?;
+        for setting in &saved {
+            self.shell(&devprep::restore_args(setting))?;
+            info!("{} {} = {}", setting.namespace, setting.key, setting.value.as_deref().unwrap_or("(unset)"));
+        }
+        std::fs::remove_file(devprep::PREP_STATE_FILE)?;
+        Ok(saved)
+    }
+
+    /// Doze state and the app's standby bucket.
+    pub fn idle_status(&self) -> Result<idle::IdleSample> {
+        let output = self.shell(&[idle::idle_state_cmd(&self.config.package_name, self.config.user)])?;
+        Ok(idle::parse_idle_state(&output, self.snapshot_time()?))
+    }
+
+    /// Moves the app to `bucket` ("active", "rare", ...).
+    pub fn set_standby_bucket(&self, bucket: &str) -> Result<()> {
+        let mut command = self.user_command(&["am", "set-standby-bucket"]);
+        command.extend([self.config.package_name.clone(), bucket.to_string()]);
+        info!("{}", self.shell(&command)?.trim_end());
+        Ok(())
+    }
+
+    /// Switches Wi-Fi, mobile data or airplane mode; `None` flips the
+    /// current state. Returns the state that was requested.
+    pub fn toggle_network(&self, target: NetTarget, enable: Option<bool>) -> Result<bool> {
+        let enable = match enable {
+            Some(enable) => enable,
+            None => self.shell(&["settings", "get", "global", target.setting()])?.trim() != "1",
+        };
+        for command in target.commands(enable, self.sdk_level()?) {
+            info!("{}", self.shell(&[command])?.trim_end());
+        }
+        Ok(enable)
+    }
+
+    /// The default network and its state.
+    pub fn connectivity_status(&self) -> Result<net::ConnectivitySample> {
+        Ok(net::parse_connectivity(&self.shell(&["dumpsys", "connectivity"])?, self.snapshot_time()?))
+    }
+
+    /// Injects one input action and records it as a timeline marker.
+    pub fn inject(&self, action: &InputAction) -> Result<markers::Marker> {
+        // Only gestures depend on the release, so avoid a getprop per tap.
+        let sdk = match action {
+            InputAction::Gesture { .. } => self.sdk_level()?,
+            _ => 0,
+        };
+        let delay = action.step_delay(sdk);
+        for command in action.commands(sdk) {
+            self.shell(&command)?;
+            std::thread::sleep(delay);
+        }
+        let marker = markers::record_marker(&action.label())?;
+        info!("Injected {} (marker at {} ms)", marker.label, marker.time_ms);
+        if self.config.stack_snapshots {
+            self.record_activity_stack(&marker.label)?;
+        }
+        Ok(marker)
+    }
+
+    /// Dumps the current window hierarchy and pulls it to the working
+    /// directory, returning the local path.
+    pub fn ui_dump(&self) -> Result<String> {
+        let output = self.shell(&["uiautomator", "dump", uidump::DEVICE_DUMP_PATH])?;
+        if !output.contains("dumped to") {
+            return Err(anyhow!("uiautomator dump failed: {}", output.trim()));
+        }
+        let local = naming::output_file("ui_dump", chrono::Local::now().format("%Y%m%d_%H%M%S"), "xml");
+        let spinner = progress::spinner("Pulling UI hierarchy");
+        let pull = self.adb().args(["pull", uidump::DEVICE_DUMP_PATH, &local]).output()?;
+        spinner.finish_and_clear();
+        if !pull.status.success() {
+            return Err(anyhow!("adb pull failed: {}", console::decode(&pull.stderr).trim()));
+        }
+        info!("UI hierarchy written to {}", local);
+        Ok(local)
+    }
+
+    /// Dumps the app's Java heap and pulls it to the working directory,
+    /// returning the local path. The app has to be debuggable, or the
+    /// device rooted.
+    pub fn heap_dump(&self) -> Result<String> {
+        let mut command = self.user_command(&["am", "dumpheap"]);
+        command.extend([self.config.package_name.clone(), heapdump::DEVICE_DUMP_PATH.to_string()]);
+        let spinner = progress::spinner("Dumping the Java heap");
+        let output = self.shell(&command)?;
+        if output.contains("Error") || output.contains("Exception") {
+            spinner.finish_and_clear();
+            return Err(anyhow!("am dumpheap failed: {}", output.trim()));
+        }
+        let local = naming::output_file(heapdump::HEAP_DUMP_FILE_STEM, chrono::Local::now().format("%Y%m%d_%H%M%S"), "hprof");
+        spinner.set_message("Pulling the heap dump");
+        let pull = self.adb().args(["pull", heapdump::DEVICE_DUMP_PATH, &local]).output()?;
+        spinner.finish_and_clear();
+        self.shell(&["rm", "-f", heapdump::DEVICE_DUMP_PATH])?;
+        if !pull.status.success() {
+            return Err(anyhow!("adb pull failed: {}", console::decode(&pull.stderr).trim()));
+        }
+        info!("Heap dump written to {}", local);
+        Ok(local)
+    }
+
+    /// The task and activity stack and the focused window, labelled
+    /// with what triggered the snapshot.
+    pub fn activity_stack(&self, trigger: &str) -> Result<ActivityStackSnapshot> {
+        let windows = self.shell(&["dumpsys", "window", "windows"])?;
+        if self.parser_profile()?.activities_proto {
+            let output = self.dumpsys_proto(&["activity", "--proto", "activities"])?;
+            match proto::parse_activities_proto(&output, &windows, trigger) {
+                Ok(snap) => return Ok(snap),
+                Err(e) => {
+                    warn!(format!("activity --proto unusable ({}), falling back to text parsing", e));
+                }
+            }
+        }
+        let activities = self.shell(&["dumpsys", "activity", "activities"])?;
+        Ok(activities::parse_activity_stack(&activities, &windows, trigger))
+    }
+
+    /// Appends the current activity stack to the snapshots file.
+    fn record_activity_stack(&self, trigger: &str) -> Result<()> {
+        let snap = self.activity_stack(trigger)?;
+        let path = compress::output_path(activities::STACK_SNAPSHOTS_FILE, self.config.compression()?);
+        let file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+        naming::record(&path);
+        let mut file = compress::Writer::new(file, self.config.compression()?)?;
+        writeln!(file, "{}", serde_json::to_string(&snap)?)?;
+        file.finish()?;
+        info!("Activity stack ({}) appended to {}", trigger, path);
+        Ok(())
+    }
+
+    /// Snapshots the stack when a logcat line reports a crash or ANR. The
+    /// logcat stream keeps running if the capture fails.
+    fn capture_stack_on_crash(&self, line: &str) {
+        if !self.config.stack_snapshots {
+            return;
+        }
+        let Some(trigger) = pipeline::crash_trigger(line) else {
+            return;
+        };
+        if let Err(e) = self.record_activity_stack(trigger) {
+            warn!(format!("Failed to capture activity stack: {}", e));
+        }
+    }
+
+    /// Appends the lines of the app's native crashes to the native crash
+    /// report, with addresses resolved against the maps of the crashing
+    /// process read at its "Fatal signal" line. Like the stack snapshot,
+    /// failing leaves the logcat stream running.
+    fn record_native_crash(&self, line: &str, crash_maps: &mut Option<addresses::MapsSnapshot>) {
+        if !self.config.stack_snapshots {
+            return;
+        }
+        if let Err(e) = self.annotate_native_crash(line, crash_maps) {
+            warn!(format!("Failed to record native crash: {}", e));
+        }
+    }
+
+    fn annotate_native_crash(&self, line: &str, crash_maps: &mut Option<addresses::MapsSnapshot>) -> Result<()> {
+        let file = || std::fs::OpenOptions::new().create(true).append(true).open(addresses::NATIVE_CRASHES_FILE);
+        if let Some(pid) = addresses::fatal_signal_pid(line, &self.config.package_name) {
+            let maps = addresses::MapsSnapshot::parse(&self.process_maps(&pid)?);
+            if maps.is_empty() {
+                warn!(format!("Could not read the maps of crashing process {} (needs root or a debuggable app), addresses stay unresolved", pid));
+            }
+            writeln!(file()?, "===== native crash of {} (pid {}) =====", self.config.package_name, pid)?;
+            *crash_maps = Some(maps);
+        } else if line.contains("Fatal signal") {
+            // Another process's crash; its dump is not the app's.
+            *crash_maps = None;
+        }
+        let Some(maps) = crash_maps.as_ref().filter(|_| addresses::is_native_crash_line(line)) else {
+            return Ok(());
+        };
+        let (annotated, resolved) = maps.annotate(line.trim_end());
+        writeln!(file()?, "{}", annotated)?;
+        if resolved > 0 {
+            info!("Resolved: {}", annotated);
+        }
+        if addresses::is_dump_end(line) {
+            info!("Native crash written to {}", addresses::NATIVE_CRASHES_FILE);
+            *crash_maps = None;
+        }
+        Ok(())
+    }
+
+    /// All mappings of a process of the app, through root or else run-as
+    /// (debuggable builds); empty when neither works.
+    fn process_maps(&self, pid: &str) -> Result<String> {
+        let command = addresses::maps_cmd(pid);
+        let maps = self.root_shell(&command)?;
+        if !maps.trim().is_empty() {
+            return Ok(maps);
+        }
+        self.shell(&[format!("run-as {} {}", self.config.package_name, command)])
+    }
+
+    /// Adds the ANR traces in `/data/anr` not collected before to the
+    /// trace store, with a copy in the local trace directory. Reading them
+    /// usually needs root.
+    pub fn collect_anr_traces(&self) -> Result<Vec<String>> {
+        std::fs::create_dir_all(anr::ANR_DIR)?;
+        let store = anr::TraceStore::open(anr::ANR_DB)?;
+        let listing = anr::parse_listing(&self.root_shell(anr::LIST_CMD)?);
+        let mut collected = Vec::new();
+        let spinner = progress::spinner("Pulling ANR traces");
+        for trace in listing {
+            if store.has(&trace)? {
+                continue;
+            }
+            spinner.set_message(format!("Pulling {}", trace.name));
+            let contents = self.root_shell(&format!("cat /data/anr/{}", trace.name))?;
+            if contents.trim().is_empty() {
+                warn!(format!("Could not read /data/anr/{}", trace.name));
+                continue;
+            }
+            // A changed file whose contents were stored before is no new ANR.
+            if let Some(local_file) = store.add(&trace, &contents)? {
+                let path = std::path::Path::new(anr::ANR_DIR).join(&local_file);
+                std::fs::write(&path, contents)?;
+                naming::record(&path.to_string_lossy());
+                collected.push(local_file);
+            }
+        }
+        spinner.finish_and_clear();
+        Ok(collected)
+    }
+
+    /// Sizes of the base APK and its splits.
+    pub fn split_report(&self) -> Result<splits::SplitReport> {
+        let package = &self.config.package_name;
+        let mut pm_path = self.user_command(&["pm", "path"]);
+        pm_path.push(package.clone());
+        let paths = splits::parse_pm_path(&self.shell(&pm_path)?);
+        if paths.is_empty() {
+            return Err(anyhow!("{} is not installed", package));
+        }
+        let mut stat = vec!["stat".to_string(), "-c".to_string(), "'%s %n'".to_string()];
+        stat.extend(paths.iter().cloned());
+        let sizes = splits::parse_sizes(&self.shell(&stat)?, &paths);
+
+        let mut list_instant = self.user_command(&["pm", "list", "packages", "--instant"]);
+        list_instant.push(package.clone());
+        let instant = splits::parse_pm_path(&self.shell(&list_instant)?).iter().any(|p| p == package);
+
+        // App maps are private to the app's uid, so this needs root.
+        let maps = match self.get_pid(&self.parser_profile()?) {
+            Ok(pid) => Some(self.root_shell(&format!("cat /proc/{}/maps", pid))?).filter(|maps| !maps.trim().is_empty()),
+            Err(_) => None,
+        };
+        let mapped = maps.as_deref().map(splits::mapped_paths);
+
+        let spinner = progress::spinner("Reading split APKs");
+        let mut apks = Vec::new();
+        for (path, size_bytes) in paths.into_iter().zip(sizes) {
+            spinner.set_message(format!("Reading {}", splits::split_name(&path)));
+            let apk = self.adb().args(["exec-out", "cat", &path]).output()?.stdout;
+            let native_libs = splits::native_libs(apk).unwrap_or_else(|e| {
+                warn!(format!("Could not read {}: {}", path, e));
+                Vec::new()
+            });
+            let loaded = mapped.as_ref().map(|mapped| mapped.contains(path.as_str()));
+            apks.push(splits::SplitApk { name: splits::split_name(&path), path, size_bytes, native_libs, loaded });
+        }
+        spinner.finish_and_clear();
+        Ok(splits::SplitReport { package: package.clone(), instant, splits: apks })
+    }
+
+    /// Recent exits of the app, with their reasons.
+    pub fn exit_info(&self) -> Result<Vec<ProcessExit>> {
+        let mut command = self.user_command(&["dumpsys", "activity", "exit-info"]);
+        command.push(self.config.package_name.clone());
+        let output = self.shell(&command)?;
+        Ok(exitinfo::parse_exit_info(&output))
+    }
+
+    fn install_apk(&self, apk: &str) -> Result<()> {
+        let output = self.adb()
+            .arg("install")
+            .args(compat::user_args(self.config.user))
+            .args(["-r", "-d", apk])
+            .output()?;
+        let stdout = console::decode(&output.stdout);
+        if !output.status.success() || !stdout.contains("Success") {
+            return Err(anyhow!("Installing {} failed: {}{}", apk, stdout.trim(), console::decode(&output.stderr).trim()));
+        }
+        Ok(())
+    }
+
+    /// Waits for the `--stabilize` gate, if configured, and records the
+    /// state the device reached. A timeout warns but does not abort.
+    pub fn stabilize(&self, bench: &BenchOptions) -> Result<()> {
+        let Some(gate) = &bench.stabilize else {
+            return Ok(());
+        };
+        let start = Instant::now();
+        let spinner = progress::spinner("Waiting for the device to settle");
+        let (state, stable) = loop {
+            let state = stabilize::parse_device_state(&self.shell(&[stabilize::DEVICE_STATE_CMD])?);
+            let blockers = gate.blockers(&state);
+            if blockers.is_empty() || start.elapsed().as_secs() >= gate.timeout_secs {
+                break (state, blockers.is_empty());
+            }
+            spinner.set_message(format!("Waiting for the device to settle: {}", blockers.join(", ")));
+            std::thread::sleep(Duration::from_secs(stabilize::POLL_SECS));
+        };
+        spinner.finish_and_clear();
+
+        let result = stabilize::Stabilization { stable, waited_secs: start.elapsed().as_secs(), gate: gate.clone(), state };
+        if stable {
+            info!("Device settled after {}s", result.waited_secs);
+        } else {
+            warn!(format!("Device not settled after {}s ({}), measuring anyway", result.waited_secs, gate.blockers(&result.state).join(", ")));
+        }
+        markers::record_marker(if stable { "device settled" } else { "device settle timeout" })?;
+        let json_file = naming::output_file("stabilization", chrono::Local::now().format("%Y%m%d_%H%M%S"), "json");
+        std::fs::write(&json_file, schema::to_versioned_json("stabilization", &result)?)?;
+        info!("Device state written to {}", json_file);
+        Ok(())
+    }
+
+    /// Reboots the device, reads the boot milestones once boot completed
+    /// and then cold-starts the app. The raw event log and dmesg are kept.
+    pub fn boot_report(&self) -> Result<boot::BootReport> {
+        let boot_id = self.shell(&[reboot::BOOT_ID_CMD])?.trim().to_string();
+        markers::record_marker("reboot")?;
+        self.adb().arg("reboot").status()?;
+        // The device can answer for a moment after `adb reboot`, still on
+        // the old boot.
+        let start = Instant::now();
+        loop {
+            match self.wait_for_device(&boot_id, "Waiting for the device to boot")? {
+                Some(true) => break,
+                Some(false) if start.elapsed().as_secs() < reboot::RECONNECT_TIMEOUT_SECS => {
+                    std::thread::sleep(Duration::from_secs(reboot::POLL_SECS));
+                }
+                _ => return Err(anyhow!("Device did not finish booting within {}s", reboot::RECONNECT_TIMEOUT_SECS)),
+            }
+        }
+        markers::record_marker("boot completed")?;
+
+        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+        let events = self.adb().args(["logcat", "-b", "events", "-d", "-v", "time"]).output()?;
+        let events = console::decode(&events.stdout);
+        let events_file = naming::output_file("boot_events", &timestamp, "txt");
+        std::fs::write(&events_file, &events)?;
+        let dmesg = self.root_shell("dmesg")?;
+        if dmesg.trim().is_empty() {
+            warn!("dmesg is not readable on this device, kernel milestones are skipped");
+        } else {
+            let dmesg_file = naming::output_file("boot_dmesg", &timestamp, "txt");
+            std::fs::write(&dmesg_file, &dmesg)?;
+            info!("Kernel log written to {}", dmesg_file);
+        }
+        info!("Event log written to {}", events_file);
+
+        let milestones = boot::parse_boot_events(&events);
+        let first_launch_ms = match self.cold_start(false) {
+            Ok(start) => Some(start.startup_ms),
+            Err(e) => {
+                warn!(format!("First launch after boot failed: {}", e));
+                None
+            }
+        };
+        Ok(boot::BootReport {
+            total_boot_ms: milestones.last().map(|m| m.uptime_ms),
+            milestones,
+            kernel: boot::parse_dmesg(&dmesg),
+            first_launch_ms,
+        })
+    }
+
+    /// Force-stops the app and cold-starts it. PSS is read five seconds
+    /// after launch when `with_pss` is set.
+    pub fn cold_start(&self, with_pss: bool) -> Result<startup::ColdStart> {
+        let component = self.launcher_component()?;
+        self.force_stop()?;
+        let mut start = self.user_command(&["am", "start", "-W"]);
+        start.extend(["-n".to_string(), component]);
+        let startup_ms = startup::parse_total_time(&self.shell(&start)?)?;
+        let pss_kb = if with_pss {
+            std::thread::sleep(Duration::from_secs(5));
+            Some(self.total_pss()?)
+        } else {
+            None
+        };
+        Ok(startup::ColdStart { startup_ms, pss_kb })
+    }
+
+    /// The cold starts of `startup`: warm-up ones first, then `runs`
+    /// recorded ones, as cleaned values per metric.
+    pub fn startup_iterations(&self, runs: u32, with_pss: bool, bench: &BenchOptions) -> Result<BTreeMap<String, Vec<f64>>> {
+        let runs = bench.warmup + bench.iterations(runs);
+        let mut series: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        self.stabilize(bench)?;
+        for i in 0..runs {
+            let cold = self.cold_start(with_pss)?;
+            let warmup = i < bench.warmup;
+            info!("Run {}/{}: startup {} ms{}{}", i + 1, runs, cold.startup_ms,
+                cold.pss_kb.map_or(String::new(), |p| format!(", PSS {}", units::kb(p))), if warmup { " (warm-up)" } else { "" });
+            if warmup {
+                continue;
+            }
+            for metric in [startup::Metric::Startup, startup::Metric::Pss] {
+                if let Some(value) = metric.value(&cold) {
+                    series.entry(metric.name().to_string()).or_default().push(value as f64);
+                }
+            }
+        }
+        Ok(series.into_iter().map(|(metric, values)| {
+            let values = bench.clean(&metric, values);
+            (metric, values)
+        }).collect())
+    }
+
+    fn force_stop(&self) -> Result<()> {
+        let mut force_stop = self.user_command(&["am", "force-stop"]);
+        force_stop.push(self.config.package_name.clone());
+        self.shell(&force_stop)?;
+        Ok(())
+    }
+
+    /// One eviction run: launch, go home, then launch `fillers` in turn
+    /// until the app's process is gone or `timeout` seconds have passed.
+    pub fn eviction_run(&self, run: u32, fillers: &[String], timeout: u64, profile: &ParserProfile) -> Result<eviction::EvictionRun> {
+        self.force_stop()?;
+        self.launch_app()?;
+        std::thread::sleep(Duration::from_secs(eviction::SETTLE_SECS));
+        let pid = self.get_pid(profile)?;
+        self.shell(eviction::HOME_CMD)?;
+        markers::record_marker(&format!("eviction run {}: {} in the background", run, self.config.package_name))?;
+        let start = Instant::now();
+        let mut last_pss_kb = None;
+        let mut fillers_launched = 0;
+        let mut evicted = false;
+        let bar = progress::timed_bar(timeout);
+        while start.elapsed().as_secs() < timeout && !control::stop_requested() {
+            let ps_output = self.shell(&profile.pid_ps_args())?;
+            if profile.parse_pid(&ps_output, &self.config.package_name, self.config.user).as_deref() != Some(pid.as_str()) {
+                evicted = true;
+                break;
+            }
+            // A process dying between ps and dumpsys reads as zeros.
+            if let Some(pss) = self.total_pss().ok().filter(|pss| *pss > 0) {
+                last_pss_kb = Some(pss);
+            }
+            if start.elapsed().as_secs() >= fillers_launched as u64 * eviction::FILLER_INTERVAL_SECS {
+                let filler = &fillers[fillers_launched as usize % fillers.len()];
+                self.for_package(filler).launch_app()?;
+                fillers_launched += 1;
+            }
+            bar.set_position(start.elapsed().as_secs().min(timeout));
+            bar.set_message(format!("{} fillers launched, PSS {}", fillers_launched, last_pss_kb.map_or("-".to_string(), units::kb)));
+            std::thread::sleep(Duration::from_secs(eviction::POLL_SECS));
+        }
+        bar.finish_and_clear();
+        let survival_secs = start.elapsed().as_secs().min(timeout);
+        let exit = if evicted && self.sdk_level()? >= exitinfo::EXIT_INFO_MIN_SDK { eviction::exit_of(self.exit_info()?, &pid) } else { None };
+        for filler in fillers {
+            self.for_package(filler).force_stop()?;
+        }
+        if !evicted {
+            self.force_stop()?;
+        }
+        Ok(eviction::EvictionRun {
+            run,
+            evicted,
+            survival_secs,
+            last_pss_kb,
+            exit_pss_kb: exit.as_ref().and_then(|e| eviction::parse_size_kb(&e.pss)),
+            exit_reason: exit.map(|e| e.reason),
+            fillers_launched,
+        })
+    }
+
+    /// The runs of `eviction`: warm-up ones first, then `runs` recorded
+    /// ones, each ending after `timeout` seconds at the latest.
+    pub fn eviction_runs(&self, fillers: &[String], timeout: u64, runs: u32, bench: &BenchOptions) -> Result<eviction::EvictionReport> {
+        let runs = bench.warmup + bench.iterations(runs);
+        let profile = self.parser_profile()?;
+        self.stabilize(bench)?;
+        let mut results = Vec::new();
+        for i in 0..runs {
+            let run = self.eviction_run(i + 1, fillers, timeout, &profile)?;
+            let warmup = i < bench.warmup;
+            let outcome = match &run.exit_reason {
+                _ if !run.evicted => format!("survived the {}s timeout", timeout),
+                Some(reason) => format!("killed ({}) after {}s", reason, run.survival_secs),
+                None => format!("gone after {}s", run.survival_secs),
+            };
+            info!("Run {}/{}: {} with {} fillers launched, PSS at kill {}{}", i + 1, runs, outcome, run.fillers_launched,
+                run.pss_at_kill_kb().map_or("-".to_string(), units::kb), if warmup { " (warm-up)" } else { "" });
+            if !warmup {
+                results.push(run);
+            }
+        }
+        let series = eviction::iteration_series(&results).into_iter().map(|(metric, values)| {
+            let values = bench.clean(&metric, values);
+            (metric, values)
+        }).collect();
+        Ok(eviction::EvictionReport { runs: results, series })
+    }
+
+    fn total_pss(&self) -> Result<u64> {
+        let profile = self.parser_profile()?;
+        let mut diags = ParseDiagnostics::new(self.config.strict_parse);
+        let mut buffer = String::new();
+        self.get_sample_meminfo_into(&profile, &mut buffer)?;
+        Ok(profile.parse_meminfo(&buffer, SampleTime::default(), &mut diags)?.total_pss)
+    }
+
+    /// Median of `runs` cold starts.
+    pub fn measure_build(&self, apk: &str, metric: startup::Metric, runs: u32, bench: &BenchOptions) -> Result<u64> {
+        self.install_apk(apk)?;
+        self.stabilize(bench)?;
+        for _ in 0..bench.warmup {
+            self.cold_start(metric == startup::Metric::Pss)?;
+        }
+        let mut values = Vec::new();
+        for _ in 0..bench.iterations(runs) {
+            let cold = self.cold_start(metric == startup::Metric::Pss)?;
+            values.extend(metric.value(&cold).map(|v| v as f64));
+        }
+        Ok(stats::median(&bench.clean(metric.name(), values)) as u64)
+    }
+
+    /// Bisects `builds`, oldest first, for the first whose median `metric`
+    /// over `runs` cold starts exceeds `threshold`. With `build_cmd`, the
+    /// builds are revisions it turns into APKs.
+    pub fn bisect(&self, builds: &[String], build_cmd: Option<&str>, metric: startup::Metric, threshold: u64, runs: u32, bench: &BenchOptions) -> Result<bisect::BisectReport> {
+        let mut measurements = Vec::new();
+        let first = bisect::first_exceeding(builds.len(), |i| {
+            let apk = match build_cmd {
+                Some(cmd) => bisect::build_apk(cmd, &builds[i])?,
+                None => builds[i].clone(),
+            };
+            let value = self.measure_build(&apk, metric, runs, bench)?;
+            let exceeds = value > threshold;
+            info!("{:<40} {:>8} {}  {}", builds[i], value, metric.unit(), if exceeds { "EXCEEDS" } else { "ok" });
+            measurements.push(bisect::Measurement { build: builds[i].clone(), value, exceeds });
+            Ok(exceeds)
+        })?;
+        Ok(bisect::BisectReport { first_exceeding: first.map(|i| builds[i].clone()), measurements })
+    }
+
+    /// Alternates installs of the two builds so thermal and device-state
+    /// drift hits both equally, then compares every metric. Each install
+    /// is followed by the stabilize gate and a launch that is not
+    /// measured, as the first start after an install also pays for
+    /// compiling and setting up the app.
+    pub fn ab_test(&self, apk_a: &str, apk_b: &str, iterations: u32, bench: &BenchOptions) -> Result<Vec<serde_json::Value>> {
+        let metrics = [startup::Metric::Startup, startup::Metric::Pss];
+        let mut values: [[Vec<f64>; 2]; 2] = Default::default();
+        let iterations = bench.warmup + bench.iterations(iterations);
+        for i in 0..iterations {
+            // Swap the order every iteration so neither build always runs first.
+            let order = if i % 2 == 0 { [0, 1] } else { [1, 0] };
+            for build in order {
+                self.install_apk([apk_a, apk_b][build])?;
+                self.stabilize(bench)?;
+                self.cold_start(false)?;
+                let cold = self.cold_start(true)?;
+                let warmup = i < bench.warmup;
+                if !warmup {
+                    for (m, metric) in metrics.iter().enumerate() {
+                        values[m][build].extend(metric.value(&cold).map(|v| v as f64));
+                    }
+                }
+                info!("Iteration {}/{} build {}: startup {} ms, PSS {}{}",
+                    i + 1, iterations, ["A", "B"][build], cold.startup_ms, units::kb(cold.pss_kb.unwrap_or(0)), if warmup { " (warm-up)" } else { "" });
+            }
+        }
+
+        let mut report = Vec::new();
+        for (m, metric) in metrics.iter().enumerate() {
+            let [a, b] = std::mem::take(&mut values[m]);
+            let (a, b) = (bench.clean(&format!("{} A", metric.name()), a), bench.clean(&format!("{} B", metric.name()), b));
+            let comparison = stats::compare(&a, &b);
+            print_comparison(metric.name(), metric.unit(), &comparison);
+            report.push(serde_json::json!({ "metric": metric.name(), "unit": metric.unit(), "a": a, "b": b, "comparison": comparison }));
+        }
+        Ok(report)
+    }
+
+    fn launcher_component(&self) -> Result<String> {
+        let mut command = self.user_command(&["cmd", "package", "resolve-activity", "--brief", "-c", "android.intent.category.LAUNCHER"]);
+        command.push(self.config.package_name.clone());
+        startup::parse_launcher_activity(&self.shell(&command)?)
+            .ok_or_else(|| anyhow!("No launcher activity found for {}", self.config.package_name))
+    }
+
+    /// Starts the app through its launcher activity. monkey only launches
+    /// in the current user, so other users go through `am start --user`.
+    fn launch_app(&self) -> Result<()> {
+        if self.config.user.is_some() {
+            let mut start = self.user_command(&["am", "start"]);
+            start.extend(["-n".to_string(), self.launcher_component()?]);
+            self.shell(&start)?;
+        } else {
+            self.shell(&["monkey", "-p", &self.config.package_name, "-c", "android.intent.category.LAUNCHER", "1"])?;
+        }
+        markers::record_marker(&format!("launch {}", self.config.package_name))?;
+        Ok(())
+    }
+
+    /// Runs a scenario. With `max_restarts` set, a process that disappears
+    /// after having been seen is treated as a crash: the crash buffer is
+    /// appended to crashes.log, the app is relaunched and the pass resumes
+    /// at its latest checkpoint.
+    pub fn run_scenario(&self, scenario: &Scenario, max_restarts: Option<u32>, bench: &BenchOptions) -> Result<u32> {
+        let profile = self.parser_profile()?;
+        let mut restarts = 0;
+        let mut crash_maps = None;
+        let passes = bench.warmup + bench.iterations(scenario.repeat);
+        self.stabilize(bench)?;
+        let start = Instant::now();
+        let mut stability = stability::StabilityTracker::new(&self.config.package_name, chrono::Utc::now().timestamp_millis());
+        for pass in 1..=passes {
+            // Markers let later analysis drop the warm-up passes.
+            let label = if pass <= bench.warmup {
+                format!("warm-up pass {}/{}", pass, bench.warmup)
+            } else {
+                format!("scenario pass {}/{}", pass - bench.warmup, passes - bench.warmup)
+            };
+            info!("{}", label);
+            markers::record_marker(&label)?;
+            let mut resume_at = 0;
+            let mut seen_alive = self.get_pid(&profile).is_ok();
+            let mut i = 0;
+            while i < scenario.steps.len() {
+                let pid = if max_restarts.is_some() { self.get_pid(&profile).ok() } else { None };
+                let alive = pid.is_some();
+                if let Some(max) = max_restarts {
+                    if seen_alive && !alive {
+                        restarts += 1;
+                        let kind = self.log_crash(restarts, crash_maps.as_ref())?;
+                        stability.record(kind, start.elapsed().as_secs_f64(), Some(pass as usize - 1));
+                        if restarts > max {
+                            self.write_stability(&stability.summary(start.elapsed().as_secs_f64(), passes as usize, bench.warmup))?;
+                            return Err(anyhow!("App crashed {} times, giving up", restarts));
+                        }
+                        self.launch_app()?;
+                        std::thread::sleep(Duration::from_secs(2));
+                        seen_alive = false;
+                        i = resume_at;
+                        continue;
+                    }
+                }
+                seen_alive |= alive;
+                // Kept fresh while the process runs, for the crash report
+                // once it is gone.
+                if let Some(pid) = pid {
+                    if crash_maps.as_ref().is_none_or(|(maps_pid, read, _)| *maps_pid != pid || read.elapsed().as_secs() >= addresses::REFRESH_SECS) {
+                        let maps = addresses::MapsSnapshot::parse(&self.process_maps(&pid)?);
+                        crash_maps = Some((pid, Instant::now(), maps));
+                    }
+                }
+
+                let step = &scenario.steps[i];
+                match step {
+                    Step::Sleep { ms } => std::thread::sleep(Duration::from_millis(*ms)),
+                    Step::Checkpoint { name } => {
+                        resume_at = i + 1;
+                        markers::record_marker(&format!("checkpoint {}", name))?;
+                    }
+                    Step::Launch => self.launch_app()?,
+                    _ => {
+                        if let Some(action) = step.input_action()? {
+                            self.inject(&action)?;
+                        }
+                    }
+                }
+                i += 1;
+            }
+        }
+        if restarts > 0 {
+            info!("Scenario finished after {} crash restart(s)", restarts);
+        }
+        if max_restarts.is_some() {
+            self.write_stability(&stability.summary(start.elapsed().as_secs_f64(), passes as usize, bench.warmup))?;
+        }
+        Ok(restarts)
+    }
+
+    /// Runs the scenario once per matrix configuration, cold-starting the
+    /// app under each so the new configuration applies. The original
+    /// settings are restored even when a cell fails.
+    pub fn run_matrix(&self, scenario: &Scenario, matrix: &ConfigMatrix, max_restarts: Option<u32>, bench: &BenchOptions) -> Result<Vec<MatrixCell>> {
+        let package = &self.config.package_name;
+        if !matrix.locales.is_empty() && self.sdk_level()? < matrix::APP_LOCALES_MIN_SDK {
+            return Err(anyhow!("Per-app locales need API {} or later", matrix::APP_LOCALES_MIN_SDK));
+        }
+        let mut get_locales = self.user_command(&["cmd", "locale", "get-app-locales"]);
+        get_locales.push(package.clone());
+        let original = matrix::OriginalConfig {
+            app_locales: matrix::parse_app_locales(&self.shell(&get_locales)?),
+            font_scale: devprep::parse_setting_value(&self.shell(&["settings", "get", "system", "font_scale"])?),
+            density: matrix::parse_override_density(&self.shell(&["wm", "density"])?),
+        };
+        let mut cells = Vec::new();
+        let result = self.measure_matrix_cells(scenario, matrix, max_restarts, bench, &mut cells);
+        for command in original.restore_commands(package, self.config.user, matrix) {
+            self.shell(&command)?;
+        }
+        result.map(|_| cells)
+    }
+
+    fn measure_matrix_cells(
+        &self,
+        scenario: &Scenario,
+        matrix: &ConfigMatrix,
+        max_restarts: Option<u32>,
+        bench: &BenchOptions,
+        cells: &mut Vec<MatrixCell>,
+    ) -> Result<()> {
+        let configs = matrix.configs();
+        for (i, config) in configs.iter().enumerate() {
+            let label = config.label();
+            info!("Configuration {}/{}: {}", i + 1, configs.len(), label);
+            for command in config.commands(&self.config.package_name, self.config.user) {
+                self.shell(&command)?;
+            }
+            markers::record_marker(&format!("config {}", label))?;
+            let cold = self.cold_start(true)?;
+            self.run_scenario(scenario, max_restarts, bench)?;
+            let pss_after_scenario_kb = self.total_pss()?;
+            cells.push(MatrixCell { config: config.clone(), startup_ms: cold.startup_ms, pss_after_launch_kb: cold.pss_kb, pss_after_scenario_kb });
+        }
+        Ok(())
+    }
+
+    /// Appends the crash buffer to crashes.log, with the addresses of a
+    /// native crash of the process in `maps` (PID, time read, snapshot)
+    /// resolved. Returns what the buffer says the crash was.
+    fn log_crash(&self, count: u32, maps: Option<&(String, Instant, addresses::MapsSnapshot)>) -> Result<stability::Kind> {
+        let marker = markers::record_marker(&format!("crash #{} {}", count, self.config.package_name))?;
+        warn!(format!("{} died during the scenario (crash #{}), relaunching", self.config.package_name, count));
+        let output = self.adb().args(["logcat", "-b", "crash", "-d", "-t", "200"]).output()?;
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open("crashes.log")?;
+        writeln!(file, "===== crash #{} at {} ms =====", count, marker.time_ms)?;
+        match maps {
+            Some((pid, _, snapshot)) if !snapshot.is_empty() => {
+                let (text, resolved) = addresses::annotate_crashes(&console::decode(&output.stdout), &self.config.package_name, pid, snapshot);
+                file.write_all(text.as_bytes())?;
+                if resolved > 0 {
+                    info!("{} address(es) of the crash resolved to libraries in crashes.log", resolved);
+                }
+            }
+            _ => file.write_all(&output.stdout)?,
+        }
+        Ok(stability::crash_buffer_kind(&console::decode(&output.stdout), &self.config.package_name))
+    }
+
+    /// Under `--parquet`, has `write` put the Parquet copy of `csv_path`
+    /// next to it.
+    #[cfg(feature = "parquet")]
+    fn write_parquet(&self, csv_path: &str, write: impl FnOnce(&str) -> Result<()>) -> Result<()> {
+        if self.config.parquet {
+            let path = parquetfile::parquet_path(csv_path);
+            naming::record(&path);
+            write(&path)?;
+            info!("Parquet copy written to {}", path);
+        }
+        Ok(())
+    }
+
+    fn write_stability(&self, summary: &stability::StabilitySummary) -> Result<()> {
+        let json_file = naming::output_file(stability::STABILITY_FILE_STEM, chrono::Local::now().format("%Y%m%d_%H%M%S"), "json");
+        std::fs::write(&json_file, schema::to_versioned_json(stability::STABILITY_FILE_STEM, summary)?)?;
+        info!(
+            "{} crash(es), {} native crash(es), {} ANR(s): {} crashes/h, {} ANRs/h, written to {}",
+            summary.counts.crashes,
+            summary.counts.native_crashes,
+            summary.counts.anrs,
+            stability::format_rate(summary.crashes_per_hour),
+            stability::format_rate(summary.anrs_per_hour),
+            json_file
+        );
+        Ok(())
+    }
+
+    /// Tracks the `count` largest processes for `duration` seconds and
+    /// plots the device's memory composition.
+    pub fn monitor_top_apps(&self, count: usize, duration: u64) -> Result<Vec<topapps::TopAppsSample>> {
+        naming::ensure_replaceable(topapps::TOP_APPS_PLOT_FILE)?;
+        control::begin_session();
+        let start = Instant::now();
+        let clock = self.start_clock_sync()?;
+        let mut tracker = topapps::TopAppsTracker::new(count);
+        let mut samples = Vec::new();
+        let bar = progress::timed_bar(duration);
+        while start.elapsed().as_secs() < duration && !control::stop_requested() {
+            let elapsed = start.elapsed();
+            let timestamp = elapsed.as_secs();
+            let processes = memtop::parse_total_pss(&self.shell(&["dumpsys", "meminfo"])?);
+            if processes.is_empty() {
+                return Err(anyhow!("No 'Total PSS by process' section in dumpsys meminfo output"));
+            }
+            let sample = tracker.sample(clock.sample_time(elapsed), &processes);
+            bar.set_position(timestamp.min(duration));
+            bar.set_message(format!("device PSS {}", units::kb(sample.total_pss)));
+            samples.push(sample);
+            std::thread::sleep(self.config.sample_period());
+        }
+        bar.finish_and_clear();
+
+        let names = topapps::ranked_names(&samples);
+        if let Some(last) = samples.last() {
+            info!("Largest processes at the end (device total {}):", units::kb(last.total_pss));
+            let mut last_ranked: Vec<(&String, &u64)> = last.processes.iter().collect();
+            last_ranked.sort_by_key(|(_, pss)| std::cmp::Reverse(**pss));
+            for (name, pss) in last_ranked {
+                info!("Name: {:<40} PSS: {:>10}", name, units::kb(*pss));
+            }
+            info!("Name: {:<40} PSS: {:>10}", topapps::OTHER, units::kb(last.other));
+        }
+        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+        self.write_clock_sync(&clock, &timestamp)?;
+        let json_file = naming::output_file("top_apps", &timestamp, "json");
+        std::fs::write(&json_file, schema::to_versioned_json("top_apps", &samples)?)?;
+        let csv_path = naming::output_file("top_apps", &timestamp, "csv");
+        let mut csv_file = BufWriter::new(File::create(&csv_path)?);
+        let columns: String = names.iter().map(|name| format!(",{}", name)).collect();
+        writeln!(csv_file, "timestamp,host_time_ms,device_time_ms,total_pss{},{}", columns, topapps::OTHER)?;
+        for sample in &samples {
+            // Empty while a process was not among the tracked ones.
+            let values: String = names.iter().map(|name| format!(",{}", sample.processes.get(*name).map(u64::to_string).unwrap_or_default())).collect();
+            writeln!(csv_file, "{},{},{},{}{},{}", sample.time.timestamp, sample.time.host_time_ms, sample.time.device_time_ms, sample.total_pss, values, sample.other)?;
+        }
+        csv_file.flush()?;
+        info!("Top apps samples written to {} and {}", json_file, csv_path);
+        self.plot_top_apps(&samples, &names)?;
+        Ok(samples)
+    }
+
+    /// Stacked areas of the tracked processes, largest at the bottom, with
+    /// "other" on top reaching the device total.
+    fn plot_top_apps(&self, samples: &[topapps::TopAppsSample], names: &[&str]) -> Result<()> {
+        let output = topapps::TOP_APPS_PLOT_FILE;
+        let theme = &self.config.plot_theme;
+        let colors = theme.series()?;
+        let root = BitMapBackend::new(output, (1200, 800)).into_drawing_area();
+        root.fill(&theme.background())?;
+        let max_time = samples.last().map_or(1.0, |s| s.time.secs()).max(1.0);
+        let max_y = samples.iter().map(|s| s.total_pss).max().unwrap_or(0).max(1) as f64 * 1.1;
+        let mut chart = ChartBuilder::on(&root)
+            .caption("Device Memory by Process", ("sans-serif", 40).into_font().color(&theme.foreground()))
+            .margin(10)
+            .x_label_area_size(40)
+            .y_label_area_size(80)
+            .build_cartesian_2d(0f64..max_time, 0f64..max_y)?;
+        configure_mesh(&mut chart, theme, "Time (s)", "PSS (KB)")?;
+        // Running sums per sample; layer i is the top edge of names[..=i].
+        let mut layers: Vec<(&str, Vec<(f64, f64)>)> = Vec::new();
+        let mut below = vec![0.0; samples.len()];
+        for name in names.iter().copied().chain([topapps::OTHER]) {
+            for (sum, sample) in below.iter_mut().zip(samples) {
+                *sum += match name {
+                    topapps::OTHER => sample.other,
+                    name => sample.processes.get(name).copied().unwrap_or(0),
+                } as f64;
+            }
+            layers.push((name, samples.iter().zip(&below).map(|(s, sum)| (s.time.secs(), *sum)).collect()));
+        }
+        // Highest edge first, so each lower layer is painted over it.
+        for (i, (name, points)) in layers.iter().enumerate().rev() {
+            let color = colors[i % colors.len()];
+            chart.draw_series(AreaSeries::new(points.clone(), 0.0, color.mix(0.8)).border_style(color))?
+                .label(*name)
+                .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 15, y + 5)], color.filled()));
+        }
+        draw_legend(&mut chart, theme)?;
+        root.present()?;
+        info!("Top apps plot saved to {}", output);
+        Ok(())
+    }
+
+    /// PSS of every process on the device.
+    pub fn memtop_snapshot(&self) -> Result<Vec<ProcessPss>> {
+        let output = self.shell(&["dumpsys", "meminfo"])?;
+        let processes = memtop::parse_total_pss(&output);
+        if processes.is_empty() {
+            return Err(anyhow!("No 'Total PSS by process' section in dumpsys meminfo output"));
+        }
+
+        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+        let json_file = naming::output_file("memtop", &timestamp, "json");
+        let csv_file_path = naming::output_file("memtop", &timestamp, "csv");
+
+        let json = schema::to_versioned_json("memtop", &processes)?;
+        std::fs::write(&json_file, json)?;
+        info!("Memtop snapshot written to {}", json_file);
+
+        let csv_file = File::create(&csv_file_path)?;
+        let mut csv_file = BufWriter::new(csv_file);
+        writeln!(csv_file, "rank,name,pid,pss")?;
+        for (i, p) in processes.iter().enumerate() {
+            writeln!(csv_file, "{},{},{},{}", i + 1, p.name, p.pid, p.pss)?;
+        }
+        csv_file.flush()?;
+        info!("Memtop snapshot written to {}", csv_file_path);
+
+        Ok(processes)
+    }
+
+    /// Process argument for `dumpsys meminfo`. A package name matches its
+    /// processes in every user, so a PID pins the one of `--user`.
+    fn meminfo_target(&self) -> Result<String> {
+        match self.config.user {
+            Some(_) => self.get_pid(&self.parser_profile()?),
+            None => Ok(self.config.package_name.clone()),
+        }
+    }
+
+    /// The same analyzer aimed at another package.
+    fn for_package(&self, package: &str) -> LogAnalyzer {
+        let mut analyzer = self.clone();
+        analyzer.config.package_name = package.to_string();
+        analyzer
+    }
+
+    /// Full `dumpsys meminfo` of the app saved as text; returns the file.
+    fn meminfo_snapshot(&self) -> Result<String> {
+        let mut buffer = String::new();
+        self.get_memory_info_into(&mut buffer)?;
+        let path = naming::output_file("meminfo_snapshot", chrono::Local::now().format("%Y%m%d_%H%M%S"), "txt");
+        std::fs::write(&path, buffer)?;
+        info!("Meminfo snapshot written to {}", path);
+        Ok(path)
+    }
+
+    fn get_memory_info_into(&self, buffer: &mut String) -> Result<()> {
+        let output = self.shell(&["dumpsys", "meminfo", &self.meminfo_target()?])?;
+        buffer.clear();
+        buffer.push_str(&output);
+        Ok(())
+    }
+
+    /// Text meminfo in the form `profile` parses into samples.
+    fn get_sample_meminfo_into(&self, profile: &ParserProfile, buffer: &mut String) -> Result<()> {
+        let output = self.shell(&profile.meminfo_args(&self.meminfo_target()?))?;
+        buffer.clear();
+        buffer.push_str(&output);
+        Ok(())
+    }
+
+    fn get_memory_sample_proto(&self, time: SampleTime) -> Result<MemorySample> {
+        let output = self.dumpsys_proto(&["meminfo", "--proto", &self.meminfo_target()?])?;
+        proto::parse_meminfo_proto(&output, &self.config.package_name, time)
+    }
+
+    /// Binary output of `dumpsys <args>`; exec-out keeps the stream
+    /// intact where `adb shell` may rewrite newlines.
+    fn dumpsys_proto(&self, args: &[&str]) -> Result<Vec<u8>> {
+        let started = Instant::now();
+        let output = match self.server.exec(&format!("dumpsys {}", args.join(" "))) {
+            Ok(output) => output,
+            Err(_) => self.adb().arg("exec-out").arg("dumpsys").args(args).output()?.stdout,
+        };
+        overhead::record(&["dumpsys", args[0]], started.elapsed());
+        Ok(output)
+    }
+
+    /// `args` followed by `--user <id>` when a user is targeted.
+    fn user_command(&self, args: &[&str]) -> Vec<String> {
+        compat::user_command(args, self.config.user)
+    }
+
+    fn get_sdk_level(&self) -> Result<u32> {
+        compat::parse_sdk_level(&self.shell(&["getprop", "ro.build.version.sdk"])?)
+    }
+
+    /// The config's SDK level, or the device's.
+    pub fn sdk_level(&self) -> Result<u32> {
+        match self.config.sdk_level {
+            Some(sdk) => Ok(sdk),
+            None => self.get_sdk_level(),
+        }
+    }
+
+    /// Parsers matching the device's release.
+    pub fn parser_profile(&self) -> Result<ParserProfile> {
+        Ok(ParserProfile::for_sdk(self.sdk_level()?))
+    }
+
+    fn get_pid(&self, profile: &ParserProfile) -> Result<String> {
+        let ps_output = self.shell(&profile.pid_ps_args())?;
+        profile
+            .parse_pid(&ps_output, &self.config.package_name, self.config.user)
+            .ok_or_else(|| anyhow!("Process {} not found on device", self.config.package_name))
+    }
+
+    /// Runs `command` directly (works under `adb root`), falling back to the
+    /// AOSP (`su 0`) and Magisk (`su -c`) su syntaxes on rooted user builds.
+    fn root_shell(&self, command: &str) -> Result<String> {
+        let quoted = format!("'{}'", command);
+        for args in [vec![command], vec!["su", "0", "sh", "-c", &quoted], vec!["su", "-c", &quoted]] {
+            let output = self.shell(&args)?;
+            if !output.trim().is_empty() {
+                return Ok(output);
+            }
+        }
+        Ok(String::new())
+    }
+
+    /// An adb command bound to the configured device.
+    fn adb(&self) -> Command {
+        let mut command = Command::new(&self.adb_path);
+        command.args(devices::serial_args(self.config.serial.as_deref()));
+        command
+    }
+
+    /// Runs a device shell command under the C locale so OEM shells and
+    /// non-English devices produce the same column and number formats.
+    fn shell<S: AsRef<std::ffi::OsStr>>(&self, args: &[S]) -> Result<String> {
+        let started = Instant::now();
+        let mut line = String::from("LC_ALL=C");
+        for arg in args {
+            line.push(' ');
+            line.push_str(&arg.as_ref().to_string_lossy());
+        }
+        let output = match self.session_shell(&line) {
+            Some(output) => output,
+            None => match self.server.shell(&line) {
+                Ok(output) => output,
+                Err(_) => self.adb().args(["shell", "LC_ALL=C"]).args(args).output()?.stdout,
+            },
+        };
+        overhead::record(args, started.elapsed());
+        Ok(console::decode(&output))
+    }
+
+    /// Output of `line` from the open device shell, starting it on first
+    /// use; none when it is unavailable or just failed, and a new one is
+    /// started for the next command after a failure.
+    fn session_shell(&self, line: &str) -> Option<Vec<u8>> {
+        let mut state = self.shell_session.lock().unwrap();
+        if let ShellState::Closed = *state {
+            *state = match self.open_shell_session() {
+                Ok(session) => ShellState::Open(session),
+                Err(_) => ShellState::Unavailable,
+            };
+        }
+        let ShellState::Open(session) = &mut *state else {
+            return None;
+        };
+        match session.run(line) {
+            Ok(output) => Some(output),
+            Err(_) => {
+                *state = ShellState::Closed;
+                None
+            }
+        }
+    }
+
+    fn open_shell_session(&self) -> Result<adb::ShellSession> {
+        let stream = match self.server.open("shell:sh") {
+            Ok(socket) => adb::Stream::Socket(socket),
+            Err(_) => adb::Stream::Process(
+                self.adb().args(["shell", "-T", "sh"]).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::null()).spawn()?,
+            ),
+        };
+        let mut session = adb::ShellSession::new(stream)?;
+        // A device that is not there fails here rather than on a sample.
+        session.run("true")?;
+        Ok(session)
+    }
+
+    /// Starts a long-running adb command (`logcat ...`, `exec-out ...`,
+    /// `shell ...`) on a server connection, or as a child when the server
+    /// is not up.
+    fn stream(&self, args: &[&str], stderr: Stdio) -> Result<adb::Stream> {
+        let service = match args.split_first() {
+            Some((&"exec-out", rest)) => format!("exec:{}", rest.join(" ")),
+            Some((&"shell", rest)) => format!("shell:{}", rest.join(" ")),
+            _ => format!("shell:{}", adb::command_line(args)),
+        };
+        match self.server.open(&service) {
+            Ok(socket) => Ok(adb::Stream::Socket(socket)),
+            Err(_) => Ok(adb::Stream::Process(self.adb().args(args).stdout(Stdio::piped()).stderr(stderr).spawn()?)),
+        }
+    }
+}
+
+pub fn print_comparison(metric: &str, unit: &str, c: &stats::Comparison) {
+    info!("{:<14} A: {:>10.1} {}  B: {:>10.1} {}  Delta: {:>+9.1} ({:>+6.2}%)  Shift: {:+.1}, 95% CI [{:+.1}, {:+.1}]  g: {:+.2} ({})  p: {:.4}  {}",
+        metric, c.mean_a, unit, c.mean_b, unit, c.delta, c.delta_percent, c.shift, c.ci_low, c.ci_high,
+        c.effect_size, stats::effect_label(c.effect_size), c.mann_whitney.p_value,
+        if c.significant { "SIGNIFICANT" } else { "not significant" });
+}
+
+/// Comparisons of the metrics two result files share, by metric name;
+/// each file is read as by [`iteration_series`].
+pub fn compare_iterations(before_path: &str, after_path: &str) -> Result<BTreeMap<String, stats::Comparison>> {
+    let before = iteration_series(before_path)?;
+    let after = iteration_series(after_path)?;
+    Ok(before.iter().filter_map(|(metric, a)| after.get(metric).map(|b| (metric.clone(), stats::compare(a, b)))).collect())
+}
+
+/// Per-metric iteration values of a result file, keyed by metric name.
+/// A memory_samples file is one run and gives one value per metric, the
+/// mean of its samples, as samples in a row are not independent; a
+/// directory gives one value for each memory_samples file in it.
+pub fn iteration_series(path: &str) -> Result<BTreeMap<String, Vec<f64>>> {
+    if std::path::Path::new(path).is_dir() {
+        let mut runs = Vec::new();
+        for entry in std::fs::read_dir(path)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if name.starts_with(multidevice::SAMPLES_STEM) && compress::plain_name(&name).ends_with(".json") {
+                runs.push(std::path::Path::new(path).join(name));
+            }
+        }
+        runs.sort();
+        let mut series: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        for run in &runs {
+            for (metric, values) in iteration_series(&run.to_string_lossy())? {
+                series.entry(metric).or_default().extend(values);
+            }
+        }
+        if series.is_empty() {
+            return Err(anyhow!("No {}_*.json in {}", multidevice::SAMPLES_STEM, path));
+        }
+        return Ok(series);
+    }
+    let (kind, data) = schema::read_any(path)?;
+    match kind.as_str() {
+        "iterations" => Ok(serde_json::from_value(data)?),
+        "memory_samples" => {
+            let mut series: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+            for sample in data.as_array().into_iter().flatten() {
+                for (key, value) in sample.as_object().into_iter().flatten() {
+                    if !clocksync::TIME_FIELDS.contains(&key.as_str()) {
+                        series.entry(key.clone()).or_default().extend(value.as_f64());
+                    }
+                }
+            }
+            Ok(series.into_iter().map(|(metric, values)| (metric, vec![stats::mean(&values)])).collect())
+        }
+        other => Err(anyhow!("{} holds {:?} data, which has no repeated iterations to compare", path, other)),
+    }
+}
+
+/// One panel of the composite plot, scaled to its own series.
+#[allow(clippy::too_many_arguments)]
+fn draw_panel<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    theme: &PlotTheme,
+    y_desc: &str,
+    series: &[Series],
+    max_time: f64,
+    frozen: &[FrozenInterval],
+    paused: &[PausedInterval],
+    marks: &[SessionMark],
+) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let colors = theme.series()?;
+    let values = || series.iter().flat_map(|(_, data)| data.iter().map(|(_, y)| *y));
+    let max_y = values().fold(0.0, f64::max).max(1.0) * 1.2;
+    // Derived metrics can go negative; memory and rates never do.
+    let min_y = values().fold(0.0, f64::min) * 1.2;
+    let mut chart = ChartBuilder::on(area)
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0f64..max_time, min_y..max_y)?;
+    configure_mesh(&mut chart, theme, "Time (s)", y_desc)?;
+    draw_frozen_intervals(&mut chart, frozen, max_y, theme.shade())?;
+    draw_marks(&mut chart, marks, max_y, theme)?;
+    for (i, (label, data)) in series.iter().enumerate() {
+        let style = colors[i % colors.len()].stroke_width(theme.line_width);
+        chart.draw_series(pause::split_at_pauses(data.clone(), paused).into_iter().flat_map(|run| LineSeries::new(run, style)))?
+            .label(*label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+    }
+    draw_legend(&mut chart, theme)
+}
+
+/// Axis, grid and label colors of the theme.
+/// Plots named `*.svg` are drawn as vector graphics, others as PNG.
+pub fn is_svg(path: &str) -> bool {
+    std::path::Path::new(path).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("svg"))
+}
+
+fn configure_mesh<DB: DrawingBackend>(
+    chart: &mut ChartContext<DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+    theme: &PlotTheme,
+    x_desc: &str,
+    y_desc: &str,
+) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let fg = theme.foreground();
+    chart
+        .configure_mesh()
+        .x_desc(x_desc)
+        .y_desc(y_desc)
+        .axis_style(fg)
+        .bold_line_style(theme.grid())
+        .light_line_style(theme.grid().mix(0.4))
+        .label_style(("sans-serif", 15).into_font().color(&fg))
+        .axis_desc_style(("sans-serif", 15).into_font().color(&fg))
+        .draw()?;
+    Ok(())
+}
+
+fn draw_legend<'a, DB: DrawingBackend + 'a>(
+    chart: &mut ChartContext<'a, DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+    theme: &PlotTheme,
+) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    chart
+        .configure_series_labels()
+        .background_style(theme.background().mix(0.8))
+        .border_style(theme.foreground())
+        .label_font(("sans-serif", 15).into_font().color(&theme.foreground()))
+        .position(SeriesLabelPosition::UpperRight)
+        .draw()?;
+    Ok(())
+}
+
+/// `chart` with a right-hand y axis scaled to `series`, such as CPU % or
+/// temperature over memory in KB. Without series the axis is not drawn.
+/// Must come before the left axis mesh, which otherwise takes both sides.
+fn with_right_axis<'a, DB: DrawingBackend + 'a>(
+    chart: ChartContext<'a, DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+    theme: &PlotTheme,
+    y_desc: &str,
+    series: &[Series],
+) -> Result<DualChart<'a, DB>>
+where
+    DB::ErrorType: 'static,
+{
+    let values = || series.iter().flat_map(|(_, data)| data.iter().map(|(_, y)| *y));
+    let max_y = values().fold(0.0, f64::max).max(1.0) * 1.2;
+    let min_y = values().fold(0.0, f64::min) * 1.2;
+    let x_range = chart.x_range();
+    let mut chart = chart.set_secondary_coord(x_range, min_y..max_y);
+    if !series.is_empty() {
+        let fg = theme.foreground();
+        chart
+            .configure_secondary_axes()
+            .y_desc(y_desc)
+            .axis_style(fg)
+            .label_style(("sans-serif", 15).into_font().color(&fg))
+            .axis_desc_style(("sans-serif", 15).into_font().color(&fg))
+            .draw()?;
+    }
+    Ok(chart)
+}
+
+/// Dashed lines on the right axis. Colors continue the palette after the
+/// `first_color` left-axis series.
+fn draw_right_series<'a, DB: DrawingBackend + 'a>(
+    chart: &mut DualChart<'a, DB>,
+    theme: &PlotTheme,
+    series: &[Series],
+    first_color: usize,
+    paused: &[PausedInterval],
+) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let colors = theme.series()?;
+    for (i, (label, data)) in series.iter().enumerate() {
+        let style = colors[(first_color + i) % colors.len()].stroke_width(theme.line_width);
+        let runs = pause::split_at_pauses(data.clone(), paused);
+        chart.draw_secondary_series(runs.into_iter().flat_map(|run| DashedLineSeries::new(run, 8, 4, style)))?
+            .label(format!("{} (right)", label))
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+    }
+    Ok(())
+}
+
+/// A labelled vertical line at each session marker.
+fn draw_marks<DB: DrawingBackend>(
+    chart: &mut ChartContext<DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+    marks: &[SessionMark],
+    max_y: f64,
+    theme: &PlotTheme,
+) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let fg = theme.foreground();
+    for mark in marks {
+        chart.draw_series(LineSeries::new([(mark.offset_secs, 0.0), (mark.offset_secs, max_y)], fg.mix(0.5)))?;
+        chart.draw_series(std::iter::once(Text::new(
+            mark.label.clone(),
+            (mark.offset_secs, max_y * 0.97),
+            ("sans-serif", 14).into_font().color(&fg),
+        )))?;
+    }
+    Ok(())
+}
+
+/// Shades the periods in which the cached-apps freezer had the app frozen.
+fn draw_frozen_intervals<DB: DrawingBackend>(
+    chart: &mut ChartContext<DB, Cartesian2d<RangedCoordf64, RangedCoordf64>>,
+    frozen: &[FrozenInterval],
+    max_y: f64,
+    color: RGBColor,
+) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    if frozen.is_empty() {
+        return Ok(());
+    }
+    let shade = color.mix(0.2);
+    chart
+        .draw_series(frozen.iter().map(|i| Rectangle::new([(i.start as f64, 0.0), (i.end as f64, max_y)], shade.filled())))?
+        .label("Frozen")
+        .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], shade.filled()));
+    Ok(())
+}
+
+pub fn diff_memtop_files(before_path: &str, after_path: &str) -> Result<Vec<PssDelta>> {
+    let before: Vec<ProcessPss> = schema::read_json_file(before_path, "memtop")?;
+    let after: Vec<ProcessPss> = schema::read_json_file(after_path, "memtop")?;
+    let deltas = memtop::diff_snapshots(&before, &after);
+
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+    let csv_file_path = naming::output_file("memtop_diff", &timestamp, "csv");
+    let csv_file = File::create(&csv_file_path)?;
+    let mut csv_file = BufWriter::new(csv_file);
+    writeln!(csv_file, "name,before,after,delta")?;
+    for d in &deltas {
+        writeln!(csv_file, "{},{},{},{}", d.name, d.before, d.after, d.delta)?;
+    }
+    csv_file.flush()?;
+    info!("Memtop diff written to {}", csv_file_path);
+
+    Ok(deltas)
+}
+
+pub fn diff_heap_dumps(before_path: &str, after_path: &str) -> Result<Vec<heapdump::ClassDelta>> {
+    let before = heapdump::parse_histogram(&std::fs::read(before_path)?).map_err(|e| anyhow!("{}: {}", before_path, e))?;
+    let after = heapdump::parse_histogram(&std::fs::read(after_path)?).map_err(|e| anyhow!("{}: {}", after_path, e))?;
+    let deltas = heapdump::diff_histograms(&before, &after);
+
+    let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
+    let csv_file_path = naming::output_file("heapdump_diff", &timestamp, "csv");
+    let csv_file = File::create(&csv_file_path)?;
+    let mut csv_file = BufWriter::new(csv_file);
+    writeln!(csv_file, "class,instances_before,instances_after,bytes_before,bytes_after,instance_delta,byte_delta")?;
+    for d in &deltas {
+        writeln!(csv_file, "{},{},{},{},{},{},{}", d.class, d.instances_before, d.instances_after, d.bytes_before, d.bytes_after, d.instance_delta, d.byte_delta)?;
+    }
+    csv_file.flush()?;
+    info!("Heap dump diff written to {}", csv_file_path);
+
+    Ok(deltas)
+}
+
+/// Warns about a leak trace and marks it on the session's plots.
+fn record_leak(leak: leakcanary::LeakTrace, leaks: &mut Vec<leakcanary::LeakTrace>) -> Result<()> {
+    let retained = leak.retained_bytes.map_or(String::new(), |bytes| format!(", {} bytes retained", bytes));
+    warn!(format!("LeakCanary: {} leaking{}", leak.leaking_object(), retained));
+    markers::record_marker(&format!("leak: {}", leak.leaking_object()))?;
+    leaks.push(leak);
+    Ok(())
+}
+
+fn write_parse_diagnostics(diags: &ParseDiagnostics, timestamp: &str) -> Result<()> {
+    if diags.is_empty() {
+        return Ok(());
+    }
+    let json_file = naming::output_file("parse_diagnostics", timestamp, "json");
+    std::fs::write(&json_file, schema::to_versioned_json("parse_diagnostics", diags)?)?;
+    warn!(format!("{} parse issues recorded in {} (use --strict-parse to abort instead)", diags.issues.len(), json_file));
+    Ok(())
+}
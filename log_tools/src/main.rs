@@ -1,446 +1,982 @@
 use anyhow::{Result, anyhow};
-use clap::{Arg, Command as ClapCommand};
-use plotters::prelude::*;
-use plotters::style::RGBColor;
-use regex::Regex;
-use serde::{Deserialize, Serialize};
-use serde_json;
-use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Write};
-use std::process::{Command, Stdio};
-use std::time::{Duration, Instant};
-use once_cell::sync::Lazy; // Add dependency: once_cell
-
-macro_rules! warn {
-    ($msg:expr) => {
-        println!("WARNING: {}", $msg);
-    };
-}
+use std::io::{BufWriter, IsTerminal, Read, Write};
+use std::process::Command;
 
-#[derive(Clone, Serialize, Deserialize)]
-struct LogAnalyzerConfig {
-    package_name: String,
-    keyword_regex: String,
-    output_file: Option<String>,
-    sample_interval: u64,
-}
+#[macro_use]
+extern crate log_tools;
+
+use log_tools::{ANR_FINGERPRINTS_FILE, LogAnalyzer, LogAnalyzerConfig, MIN_SAMPLE_INTERVAL, compare_iterations, diff_heap_dumps, diff_memtop_files, print_comparison};
+use log_tools::bench::BenchOptions;
+use log_tools::net::NetTarget;
+use log_tools::preset::DeviceClass;
+use log_tools::{adb, anr, bundle, console, control, devenv, devices, exitinfo, filterbench, filters, heapdump, htmlreport, input, markers, multidevice, naming, offline, output, pkginfo, regexcheck, runsdb, scenario, schema, search, stability, startup, stats, uidump, units, upload, wireless};
 
-#[derive(Clone)]
-struct LogAnalyzer {
-    config: LogAnalyzerConfig,
-    adb_path: String,
+mod cli;
+mod plan;
+
+/// Output and error output of an adb command run on the host side.
+fn adb_output(args: &[&str]) -> Result<String> {
+    let output = Command::new("adb").args(args).output()?;
+    Ok(console::decode(&output.stdout) + &console::decode(&output.stderr))
 }
 
-#[derive(Serialize)]
-struct MemorySample {
-    timestamp: u64,
-    total_pss: u64,
-    native_heap: u64,
-    dalvik_heap: u64,
-    code: u64,
-    stack: u64,
-    graphics: u64,
-    private_dirty: u64,
-    shared_dirty: u64,
+/// `adb connect` to `endpoint`, or to the port its host now advertises.
+/// Returns the endpoint connected to.
+fn connect_wireless(endpoint: &str) -> Result<String> {
+    let output = adb_output(&["connect", endpoint])?;
+    if wireless::is_connected(&output) {
+        return Ok(endpoint.to_string());
+    }
+    let services = wireless::parse_connect_services(&adb_output(wireless::MDNS_ARGS)?);
+    if let Some(moved) = wireless::rediscover(&services, endpoint) {
+        if wireless::is_connected(&adb_output(&["connect", &moved])?) {
+            info!("{} now listens on {}", endpoint, moved);
+            return Ok(moved);
+        }
+    }
+    Err(anyhow!("Could not connect to {}: {}", endpoint, output.trim()))
 }
 
-#[derive(Serialize)]
-struct SoMemoryInfo {
-    name: String,
-    pss: u64,
-    private_dirty: u64,
-    shared_dirty: u64,
+/// `connect`: pairs when asked to, connects, and remembers the endpoints
+/// in the config file.
+fn run_connect(sub: &clap::ArgMatches, config: &LogAnalyzerConfig, config_path: Option<&String>) -> Result<()> {
+    let endpoints = match sub.get_one::<String>("endpoint") {
+        Some(endpoint) => vec![endpoint.clone()],
+        None if config.wireless_endpoints.is_empty() => return Err(anyhow!("Give a HOST:PORT endpoint; the config file remembers none")),
+        None => config.wireless_endpoints.clone(),
+    };
+    for endpoint in &endpoints {
+        wireless::endpoint_host(endpoint)?;
+    }
+    if let Some(pair) = sub.get_one::<String>("pair") {
+        wireless::endpoint_host(pair)?;
+        let output = adb_output(&["pair", pair, sub.get_one::<String>("code").unwrap()])?;
+        if !wireless::is_paired(&output) {
+            return Err(anyhow!("Pairing with {} failed: {}", pair, output.trim()));
+        }
+        info!("Paired with {}", pair);
+    }
+    let mut connected = Vec::new();
+    let mut failed = Vec::new();
+    for endpoint in &endpoints {
+        let endpoint = match connect_wireless(endpoint) {
+            Ok(endpoint) => endpoint,
+            Err(e) => {
+                warn!(e.to_string());
+                failed.push(endpoint.as_str());
+                continue;
+            }
+        };
+        info!("Connected to {}", endpoint);
+        let remembered = match config_path {
+            Some(path) => wireless::remember(path, &endpoint)?,
+            None => false,
+        };
+        if let (true, Some(path)) = (remembered, config_path) {
+            info!("Remembered {} in {}", endpoint, path);
+        }
+        connected.push(serde_json::json!({ "endpoint": endpoint, "remembered": remembered }));
+    }
+    if config_path.is_none() && !connected.is_empty() {
+        info!("Pass --config to remember the endpoint for later runs");
+    }
+    output::emit("connect", &connected)?;
+    if !failed.is_empty() {
+        return Err(anyhow!("Could not connect to {}", failed.join(", ")));
+    }
+    Ok(())
 }
 
-#[derive(Serialize)]
-struct ThreadInfo {
-    tid: String,
-    name: String,
-    state: String,
-    priority: String,
-    user_time: String,
-    system_time: String,
+/// Reconnects the remembered wireless endpoints that are not attached.
+/// One that cannot be reached is left to the device checks that follow.
+/// Returns whether any was reconnected.
+fn reconnect_wireless(config: &LogAnalyzerConfig, attached: &[devices::Device], config_path: Option<&String>) -> Result<bool> {
+    let mut reconnected = false;
+    for endpoint in &config.wireless_endpoints {
+        if attached.iter().any(|d| d.serial == *endpoint && d.is_online()) {
+            continue;
+        }
+        match connect_wireless(endpoint) {
+            Ok(now) => {
+                info!("Reconnected to {}", now);
+                if let (true, Some(path)) = (now != *endpoint, config_path) {
+                    wireless::remember(path, &now)?;
+                }
+                reconnected = true;
+            }
+            Err(e) => {
+                warn!(e.to_string());
+            }
+        }
+    }
+    Ok(reconnected)
 }
 
-#[cfg(windows)]
-fn setup_utf8() {
-    use std::os::windows::process::CommandExt;
-    let _ = std::process::Command::new("cmd")
-        .arg("/C")
-        .arg("chcp")
-        .arg("65001")
-        .creation_flags(0x08000000)
-        .status();
+/// `--devices`: this invocation once per device in child processes, then
+/// the devices' memory side by side.
+fn run_on_devices(config: LogAnalyzerConfig, attached: &[devices::Device], matches: &clap::ArgMatches, modes: &cli::Modes) -> Result<()> {
+    if !cli::is_session(matches) {
+        return Err(anyhow!("--devices runs measurement sessions (memory, logcat, ...) only"));
+    }
+    let args = multidevice::child_args(std::env::args().skip(1));
+    let failed = multidevice::run_sessions(config, attached, &args, modes.memory.is_some())?;
+    if !failed.is_empty() {
+        return Err(anyhow!("The session failed on {}", failed.join(", ")));
+    }
+    Ok(())
 }
+fn main() -> Result<()> {
+    console::setup();
+    output::set_console_output(true);
 
-// Precompiled regexes
-static SO_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s*(\d+)\s+(\d+)\s+(\d+)\s+(.+\.so)").unwrap());
-static MEM_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(.+):\s+(\d+)").unwrap());
-
-impl LogAnalyzer {
-    fn new(config: LogAnalyzerConfig) -> Self {
-        LogAnalyzer {
-            config,
-            adb_path: "adb".to_string(),
-        }
-    }
-
-    fn start_logcat(&self) -> Result<()> {
-        let re = Regex::new(&self.config.keyword_regex)?;
-        let mut output = Command::new(&self.adb_path)
-            .args(&["logcat", "-v", "time"])
-            .stdout(Stdio::piped())
-            .spawn()?;
-        let stdout = output.stdout.take().ok_or(anyhow!("Failed to get stdout"))?;
-        let mut reader = BufReader::new(stdout);
-        let mut buffer = Vec::new();
-
-        if let Some(ref file_path) = self.config.output_file {
-            let file = File::create(file_path)?;
-            let mut file = BufWriter::new(file);
-            while reader.read_until(b'\n', &mut buffer)? > 0 {
-                let line = String::from_utf8_lossy(&buffer);
-                if re.is_match(&line) {
-                    println!("Match found: {}", line);
-                    file.write_all(&buffer)?;
-                }
-                buffer.clear();
+    let matches = cli::command().get_matches();
+    if let Some((option, subcommand)) = cli::misplaced_option(&matches) {
+        return Err(anyhow!("{} belongs to the subcommand; pass it after its name: {} {}", option, subcommand, option));
+    }
+    output::set_json_output(matches.get_flag("json"));
+    let options = cli::options(&matches);
+    if let (Some(unit), Some(precision)) = (cli::value::<String>(options, "units"), cli::value::<usize>(options, "precision")) {
+        units::set_format(units::Unit::parse(unit), *precision);
+    }
+    let modes = cli::Modes::from_matches(&matches);
+    for (flag, subcommand) in &modes.deprecated {
+        warn!(format!("{} is deprecated and will be removed, use the `{}` subcommand instead", flag, subcommand));
+    }
+
+    let mut config = if let Some(config_path) = matches.get_one::<String>("config") {
+        serde_json::from_str(&console::read_text(config_path)?)?
+    } else {
+        LogAnalyzerConfig::default()
+    };
+
+    if let Some(compression) = cli::value::<String>(options, "compress") {
+        config.compress = Some(compression.clone());
+    }
+    if let Some(class) = cli::value::<String>(options, "device_class") {
+        config.device_class = Some(class.clone());
+    }
+    if let Some(serial) = matches.get_one::<String>("serial") {
+        config.serial = Some(serial.clone());
+    }
+    if let Some(serials) = cli::values(options, "devices") {
+        config.devices = serials;
+    }
+    if let Some(class) = config.device_class.clone() {
+        let device_class = match class.as_str() {
+            // Detection queries the device, which a dry run must not do;
+            // with several devices, each session detects its own.
+            "auto" if cli::flag(options, "dry_run") || !config.devices.is_empty() => DeviceClass::Phone,
+            "auto" => {
+                let output = Command::new("adb")
+                    .args(devices::serial_args(config.serial.as_deref()))
+                    .args(["shell", "getprop", "ro.build.characteristics"])
+                    .output()
+                    .map_err(|_| anyhow!("ADB is not installed or not found in PATH"))?;
+                DeviceClass::detect(&console::decode(&output.stdout))
             }
-            file.flush()?;
-        } else {
-            while reader.read_until(b'\n', &mut buffer)? > 0 {
-                let line = String::from_utf8_lossy(&buffer);
-                if re.is_match(&line) {
-                    println!("Match found: {}", line);
-                }
-                buffer.clear();
+            name => DeviceClass::parse(name).ok_or_else(|| anyhow!("Unknown device class {:?}", name))?,
+        };
+        let changes = device_class.apply(&mut config);
+        if !changes.is_empty() {
+            info!("{} preset: {}", device_class.name(), changes.join(", "));
+        }
+    }
+
+    cli::apply_options(&mut config, options)?;
+    if let Some(interval) = modes.interval {
+        config.sample_interval = interval;
+    }
+    if config.sample_interval < MIN_SAMPLE_INTERVAL {
+        return Err(anyhow!("Sample interval must be at least {}s", MIN_SAMPLE_INTERVAL));
+    }
+    if config.parquet && !cfg!(feature = "parquet") {
+        return Err(anyhow!("This build writes no Parquet files; rebuild with --features parquet"));
+    }
+    // Unknown names fail here rather than once logcat is running.
+    filters::TagFilter::load(&config.filter_presets, &config.presets)?;
+    if modes.tui && !std::io::stdout().is_terminal() {
+        return Err(anyhow!("--tui needs a terminal on stdout"));
+    }
+    if let (true, Some(path)) = (matches.subcommand_name() == Some("logcat"), &modes.output) {
+        config.output_file = Some(path.clone());
+    }
+
+    // adb itself picks the device from ANDROID_SERIAL when several are attached.
+    let serial = config.serial.clone().or_else(|| std::env::var("ANDROID_SERIAL").ok());
+    naming::set_context(&config.package_name, serial.as_deref());
+    naming::set_overwrite_policy(if matches.get_flag("force") {
+        naming::OverwritePolicy::Force
+    } else if cli::flag(options, "append") {
+        naming::OverwritePolicy::Append
+    } else {
+        naming::OverwritePolicy::Refuse
+    });
+    if cli::flag(options, "dry_run") {
+        plan::plan_invocation(&config, &matches, &modes)?.print()?;
+        return Ok(());
+    }
+    // Ctrl-C still ends the run at once, after undoing device changes.
+    ctrlc::set_handler(|| {
+        control::run_teardowns();
+        std::process::exit(130);
+    })?;
+    // Commands that only read files on the host need neither adb nor a device.
+    let host_only = matches.subcommand_name().is_some_and(|name| devices::HOST_SUBCOMMANDS.contains(&name))
+        || matches.subcommand_matches("pkg").is_some_and(|sub| sub.subcommand_name() == Some("diff"))
+        || matches.subcommand_matches("anr").is_some_and(|sub| sub.subcommand_name() == Some("top"))
+        || matches.subcommand_matches("heapdump").is_some_and(|sub| sub.contains_id("diff"))
+        || matches.subcommand_matches("regex").and_then(|sub| sub.subcommand_matches("test")).is_some_and(|sub| sub.contains_id("file"));
+    // A running server is enough; the binary is only needed to start one.
+    let server = adb::Server::from_env(None);
+    if !host_only && !server.is_running() && Command::new("adb").arg("version").output().is_err() {
+        return Err(anyhow!("No adb server is running and ADB is not installed or not found in PATH"));
+    }
+    let list_devices = || -> Result<Vec<devices::Device>> {
+        let output = match server.devices() {
+            Ok(output) => output,
+            Err(_) => console::decode(&Command::new("adb").args(devices::DEVICES_ARGS).output()?.stdout),
+        };
+        Ok(devices::parse_devices(&output))
+    };
+    let config_path = matches.get_one::<String>("config");
+    if let Some(sub) = matches.subcommand_matches("connect") {
+        return run_connect(sub, &config, config_path);
+    }
+    let mut attached = if host_only { Vec::new() } else { list_devices()? };
+    if !host_only && reconnect_wireless(&config, &attached, config_path)? {
+        attached = list_devices()?;
+    }
+    if matches.subcommand_matches("devices").is_some() {
+        if attached.is_empty() {
+            info!("No devices attached");
+        }
+        for device in &attached {
+            info!("{:<24} {:<14} model: {:<20} product: {}", device.serial, device.state,
+                device.model.as_deref().unwrap_or("-"), device.product.as_deref().unwrap_or("-"));
+        }
+        output::emit("devices", &attached)?;
+        return Ok(());
+    }
+    if !host_only && !config.devices.is_empty() {
+        return run_on_devices(config, &attached, &matches, &modes);
+    }
+    if !host_only {
+        devices::check_target(&attached, serial.as_deref())?;
+    }
+
+    let analyzer = LogAnalyzer::new(config);
+    let mut executed = false;
+    // Measurement sessions record the device environment for `diff-env`.
+    let mut env = None;
+    if cli::is_session(&matches) || matches!(matches.subcommand_name(), Some("startup" | "scenario" | "ab-test" | "bisect" | "eviction")) {
+        env = Some(analyzer.capture_env()?);
+        // And the build under test, for `pkg diff`; the app may not be
+        // installed yet (ab-test, bisect).
+        match analyzer.package_info() {
+            Ok(info) => {
+                analyzer.write_package_info(&info)?;
             }
+            Err(e) => info!("Package info not recorded: {}", e),
         }
-        output.kill()?;
-        output.wait()?;
-        Ok(())
-    }
-
-    fn monitor_memory(&self, duration: u64, output_image: &str) -> Result<Vec<MemorySample>> {
-        let start = Instant::now();
-        let mut samples = Vec::with_capacity((duration / self.config.sample_interval) as usize);
-        let mut buffer = String::new();
-
-        while start.elapsed().as_secs() < duration {
-            buffer.clear();
-            self.get_memory_info_into(&mut buffer)?;
-            let sample = MemorySample {
-                timestamp: start.elapsed().as_secs(),
-                total_pss: parse_memory_value(&buffer, "TOTAL PSS:")?,
-                native_heap: parse_memory_value(&buffer, "Native Heap:")?,
-                dalvik_heap: parse_memory_value(&buffer, "Dalvik Heap:")?,
-                code: parse_memory_value(&buffer, "Code:")?,
-                stack: parse_memory_value(&buffer, "Stack:")?,
-                graphics: parse_memory_value(&buffer, "Graphics:")?,
-                private_dirty: parse_memory_value(&buffer, "Private Dirty:")?,
-                shared_dirty: parse_memory_value(&buffer, "Shared Dirty:")?,
-            };
-            samples.push(sample);
-            std::thread::sleep(Duration::from_secs(self.config.sample_interval));
+    }
+    let mut memory_samples = None;
+    let mut so_memory = None;
+    let mut threads = None;
+    let mut thread_snapshots = Vec::new();
+    let mut leaks = Vec::new();
+
+    if modes.threads {
+        let threads = &*threads.insert(analyzer.analyze_threads()?);
+        info!("Thread Analysis:");
+        for thread in threads {
+            info!("TID: {:<6} Name: {:<20} State: {:<2} Priority: {:<3} User Time: {:<6} System Time: {}",
+                thread.tid, thread.name, thread.state, thread.priority, thread.user_time, thread.system_time);
         }
+        if let Some(path) = &modes.output {
+            naming::open_output(path)?.write_all(schema::to_versioned_json("threads", &threads)?.as_bytes())?;
+            info!("Threads written to {}", path);
+        }
+        output::emit("threads", threads)?;
+        executed = true;
+    }
 
-        self.plot_memory_curve(&samples, output_image)?;
+    if let Some((count, duration)) = modes.top_apps {
+        let samples = analyzer.monitor_top_apps(count, duration)?;
+        output::emit("top_apps", &samples)?;
+        executed = true;
+    } else if let (Some(duration), Some(thread_interval)) = (modes.memory, modes.thread_interval) {
+        let session = analyzer.run_session(duration, modes.plot_file(), thread_interval, modes.tui)?;
+        info!("Collected {} memory samples and {} timeline entries.", session.samples.len(), session.timeline.len());
+        output::emit("timeline", &session.timeline)?;
+        memory_samples = Some(session.samples);
+        leaks = session.leaks;
+        thread_snapshots = session.thread_snapshots;
+        executed = true;
+    } else if let Some(duration) = modes.memory {
+        let samples = analyzer.monitor_memory(duration, modes.plot_file())?;
+        info!("Collected {} memory samples.", samples.len());
+        output::emit("memory", &samples)?;
+        memory_samples = Some(samples);
+        executed = true;
+    }
 
-        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
-        let json_file = format!("memory_samples_{}.json", &timestamp);
-        let csv_file_path = format!("memory_samples_{}.csv", &timestamp);
-
-        let json = serde_json::to_string_pretty(&samples)?;
-        std::fs::write(&json_file, json)?;
-        println!("Memory samples written to {}", json_file);
-
-        let csv_file = File::create(&csv_file_path)?;
-        let mut csv_file = BufWriter::new(csv_file);
-        writeln!(
-            csv_file,
-            "timestamp,total_pss,native_heap,dalvik_heap,code,stack,graphics,private_dirty,shared_dirty"
-        )?;
-        for sample in &samples {
-            writeln!(
-                csv_file,
-                "{},{},{},{},{},{},{},{},{}",
-                sample.timestamp,
-                sample.total_pss,
-                sample.native_heap,
-                sample.dalvik_heap,
-                sample.code,
-                sample.stack,
-                sample.graphics,
-                sample.private_dirty,
-                sample.shared_dirty
-            )?;
-        }
-        csv_file.flush()?;
-        println!("Memory samples written to {}", csv_file_path);
-
-        Ok(samples)
-    }
-
-    fn plot_memory_curve(&self, samples: &[MemorySample], output: &str) -> Result<()> {
-        let root = BitMapBackend::new(output, (1200, 800)).into_drawing_area();
-        root.fill(&WHITE)?;
-
-        let max_pss = samples.iter().map(|s| s.total_pss as f64).max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap_or(1000.0) * 1.2;
-        let max_time = samples.last().map(|s| s.timestamp as f64).unwrap_or(1.0);
-
-        let mut chart = ChartBuilder::on(&root)
-            .caption("Detailed Memory Usage Over Time", ("sans-serif", 40).into_font())
-            .margin(10)
-            .x_label_area_size(30)
-            .y_label_area_size(50)
-            .build_cartesian_2d(0f64..max_time, 0f64..max_pss)?;
-
-        chart.configure_mesh().x_desc("Time (s)").y_desc("Memory (KB)").draw()?;
-
-        let colors = [RED, BLUE, GREEN, CYAN, MAGENTA, YELLOW, BLACK, RGBColor(128, 0, 128)];
-        let labels = ["Total PSS", "Native Heap", "Dalvik Heap", "Code", "Stack", "Graphics", "Private Dirty", "Shared Dirty"];
-        let data_fns: &[fn(&MemorySample) -> (f64, f64)] = &[
-            |s| (s.timestamp as f64, s.total_pss as f64),
-            |s| (s.timestamp as f64, s.native_heap as f64),
-            |s| (s.timestamp as f64, s.dalvik_heap as f64),
-            |s| (s.timestamp as f64, s.code as f64),
-            |s| (s.timestamp as f64, s.stack as f64),
-            |s| (s.timestamp as f64, s.graphics as f64),
-            |s| (s.timestamp as f64, s.private_dirty as f64),
-            |s| (s.timestamp as f64, s.shared_dirty as f64),
-        ];
-
-        for (i, (color, label)) in colors.iter().zip(labels.iter()).enumerate() {
-            let data: Vec<_> = samples.iter().map(data_fns[i]).collect();
-            let color_clone = *color;
-            chart.draw_series(LineSeries::new(data, color_clone))?
-                .label(*label)
-                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color_clone));
-        }
-
-        chart.configure_series_labels()
-            .background_style(&WHITE.mix(0.8))
-            .border_style(&BLACK)
-            .position(SeriesLabelPosition::UpperRight)
-            .draw()?;
-
-        root.present()?;
-        println!("Memory usage plot saved to {}", output);
-        Ok(())
-    }
-
-    fn analyze_threads(&self) -> Result<Vec<ThreadInfo>> {
-        let pid = self.get_pid()?;
-        let output = Command::new(&self.adb_path)
-            .args(&["shell", "ps", "-T", "-p", &pid])
-            .output()?;
-        let ps_output = String::from_utf8_lossy(&output.stdout);
-
-        let mut threads = Vec::new();
-        for line in ps_output.lines().skip(1) {
-            let fields: Vec<&str> = line.split_whitespace().collect();
-            if fields.len() >= 9 {
-                threads.push(ThreadInfo {
-                    tid: fields[1].to_string(),
-                    name: fields[8..].join(" "),
-                    state: fields[4].to_string(),
-                    priority: fields[5].to_string(),
-                    user_time: fields[6].to_string(),
-                    system_time: fields[7].to_string(),
-                });
+    if analyzer.config.html_report && (memory_samples.is_some() || threads.is_some()) {
+        let report = htmlreport::Report {
+            package: &analyzer.config.package_name,
+            env: env.as_ref(),
+            samples: memory_samples.as_deref(),
+            threads: threads.as_deref(),
+            leaks: &leaks,
+            plots: if memory_samples.is_some() { analyzer.memory_plots(modes.plot_file()) } else { Vec::new() },
+        };
+        let html_file = naming::output_file(htmlreport::REPORT_FILE_STEM, chrono::Local::now().format("%Y%m%d_%H%M%S"), "html");
+        std::fs::write(&html_file, htmlreport::render(&report)?)?;
+        info!("HTML report written to {}", html_file);
+    }
+
+    if modes.so_memory {
+        let so_libs = analyzer.analyze_so_memory()?;
+        info!("SO Library Memory Analysis:");
+        for so in &so_libs {
+            info!("Name: {:<30} PSS: {:>10}  Private Dirty: {:>10}  Shared Dirty: {:>10}",
+                so.name, units::kb(so.pss), units::kb(so.private_dirty), units::kb(so.shared_dirty));
+        }
+        if let Some(path) = &modes.output {
+            naming::open_output(path)?.write_all(schema::to_versioned_json("so_memory", &so_libs)?.as_bytes())?;
+            info!("Libraries written to {}", path);
+        }
+        output::emit("so_memory", &so_libs)?;
+        if let Some(totals) = analyzer.so_owner_totals(&so_libs)? {
+            info!("SO Library Memory by Owner:");
+            for t in &totals {
+                info!("Owner: {:<30} Libraries: {:>4}  PSS: {:>10}  Private Dirty: {:>10}  Shared Dirty: {:>10}",
+                    t.owner, t.libraries, units::kb(t.pss), units::kb(t.private_dirty), units::kb(t.shared_dirty));
             }
+            output::emit("so_memory_owners", &totals)?;
         }
+        so_memory = Some(so_libs);
+        executed = true;
+    }
 
-        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
-        let json_file = format!("thread_info_{}.json", &timestamp);
-        let csv_file_path = format!("thread_info_{}.csv", &timestamp);
-
-        let json = serde_json::to_string_pretty(&threads)?;
-        std::fs::write(&json_file, json)?;
-        println!("Thread info written to {}", json_file);
-
-        let csv_file = File::create(&csv_file_path)?;
-        let mut csv_file = BufWriter::new(csv_file);
-        writeln!(csv_file, "tid,name,state,priority,user_time,system_time")?;
-        for thread in &threads {
-            writeln!(
-                csv_file,
-                "{},{},{},{},{},{}",
-                thread.tid, thread.name, thread.state, thread.priority, thread.user_time, thread.system_time
-            )?;
-        }
-        csv_file.flush()?;
-        println!("Thread info written to {}", csv_file_path);
-
-        Ok(threads)
-    }
-
-    fn analyze_so_memory(&self) -> Result<Vec<SoMemoryInfo>> {
-        let mut buffer = String::new();
-        self.get_memory_info_into(&mut buffer)?;
-        let mut so_libs = Vec::new();
-        let lines = buffer.lines().collect::<Vec<_>>();
-        let mut in_so_section = false;
-
-        for (i, line) in lines.iter().enumerate() {
-            if line.contains("Native Heap") {
-                in_so_section = true;
-                continue;
+    if let Some(path) = &analyzer.config.db {
+        if memory_samples.is_some() || threads.is_some() || !thread_snapshots.is_empty() || so_memory.is_some() {
+            let mut db = runsdb::RunsDb::open(path)?;
+            let command = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+            let run_id = db.start_run(&analyzer.config.package_name, analyzer.config.serial.as_deref(), &command)?;
+            if let Some(samples) = &memory_samples {
+                db.insert_samples(run_id, samples)?;
             }
-            if in_so_section && (line.contains("Dalvik Heap") || line.trim().is_empty()) {
-                break;
+            db.insert_threads(run_id, threads.as_deref(), &thread_snapshots)?;
+            if let Some(so_libs) = &so_memory {
+                db.insert_so_memory(run_id, so_libs)?;
+            }
+            info!("Results inserted into {} as run {}", path, run_id);
+        }
+    }
+
+    if let Some(sub) = matches.subcommand_matches("procstats") {
+        let hours = *sub.get_one::<u32>("hours").unwrap_or(&24);
+        let stats = analyzer.analyze_procstats(hours)?;
+        info!("Procstats over the last {} hours:", hours);
+        for s in &stats {
+            info!("Process: {:<30} State: {:<12} Time: {:>6.2}%  PSS min/avg/max: {:>10}/{:>10}/{:>10}",
+                s.process, s.state, s.time_percent, units::kb(s.min_pss), units::kb(s.avg_pss), units::kb(s.max_pss));
+        }
+        output::emit("procstats", &stats)?;
+        executed = true;
+    }
+
+    if matches.subcommand_matches("battery").is_some() {
+        let usage = analyzer.power_use()?;
+        let share = usage.computed_drain_mah.filter(|drain| *drain > 0.0).map(|drain| format!(" ({:.1}% of the computed drain)", usage.power_mah / drain * 100.0));
+        info!("Estimated power use of {} ({}) since the last charge: {:.2} mAh{}", analyzer.config.package_name, usage.uid, usage.power_mah, share.unwrap_or_default());
+        output::emit("battery", &usage)?;
+        executed = true;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("memtop") {
+        let top = *sub.get_one::<usize>("top").unwrap_or(&20);
+        if let Some(mut files) = sub.get_many::<String>("diff") {
+            let (before, after) = (files.next().unwrap(), files.next().unwrap());
+            let deltas = diff_memtop_files(before, after)?;
+            info!("Largest PSS growth from {} to {}:", before, after);
+            for d in deltas.iter().take(top) {
+                info!("Name: {:<40} Before: {:>10}  After: {:>10}  Delta: {:>11}", d.name, units::kb(d.before), units::kb(d.after), units::delta_kb(d.delta));
             }
-            if i > 1000 { // Safety limit
-                warn!("Reached line limit in .so parsing, stopping");
-                break;
+            output::emit("memtop_diff", &deltas)?;
+        } else {
+            let processes = analyzer.memtop_snapshot()?;
+            let total: u64 = processes.iter().map(|p| p.pss).sum();
+            info!("Top {} processes by PSS (device total {}):", top, units::kb(total));
+            for (i, p) in processes.iter().enumerate().take(top) {
+                info!("{:>3}. Name: {:<40} PID: {:<6} PSS: {:>10}", i + 1, p.name, p.pid, units::kb(p.pss));
             }
-            if in_so_section && !line.trim().is_empty() {
-                if let Some(caps) = SO_REGEX.captures(line) {
-                    let pss = caps.get(1).and_then(|m| m.as_str().parse::<u64>().ok()).unwrap_or(0);
-                    let private_dirty = caps.get(2).and_then(|m| m.as_str().parse::<u64>().ok()).unwrap_or(0);
-                    let shared_dirty = caps.get(3).and_then(|m| m.as_str().parse::<u64>().ok()).unwrap_or(0);
-                    let name = caps.get(4).map_or("unknown.so", |m| m.as_str()).to_string();
-                    so_libs.push(SoMemoryInfo { name, pss, private_dirty, shared_dirty });
-                } else {
-                    warn!(format!("Failed to parse .so line: {}", line));
+            match processes.iter().position(|p| p.name == analyzer.config.package_name) {
+                Some(i) => info!("{} is #{} of {} with {}", analyzer.config.package_name, i + 1, processes.len(), units::kb(processes[i].pss)),
+                None => {
+                    warn!(format!("{} is not running", analyzer.config.package_name));
                 }
             }
+            output::emit("memtop", &processes)?;
         }
+        executed = true;
+    }
 
-        if so_libs.is_empty() {
-            warn!(format!("No .so libraries found in memory info for {}", self.config.package_name));
+    if let Some(sub) = matches.subcommand_matches("heapdump") {
+        let top = *sub.get_one::<usize>("top").unwrap();
+        if let Some(mut files) = sub.get_many::<String>("diff") {
+            let (before, after) = (files.next().unwrap(), files.next().unwrap());
+            let deltas = diff_heap_dumps(before, after)?;
+            info!("Largest class growth from {} to {}:", before, after);
+            for d in deltas.iter().take(top).filter(|d| d.byte_delta > 0 || d.instance_delta > 0) {
+                info!("Class: {:<60} Instances: {:>9} -> {:>9} ({:>+8})  Bytes: {:>+12}", d.class, d.instances_before, d.instances_after, d.instance_delta, d.byte_delta);
+            }
+            output::emit("heapdump_diff", &deltas)?;
         } else {
-            so_libs.sort_unstable_by(|a, b| b.pss.cmp(&a.pss));
+            let local = analyzer.heap_dump()?;
+            let histogram = heapdump::parse_histogram(&std::fs::read(&local)?)?;
+            let total: u64 = histogram.iter().map(|c| c.bytes).sum();
+            info!("Top {} classes by shallow size (heap total {}):", top, units::kb(total / 1024));
+            for c in histogram.iter().take(top) {
+                info!("Class: {:<60} Instances: {:>9}  Bytes: {:>12}", c.class, c.instances, c.bytes);
+            }
+            output::emit("heapdump", &serde_json::json!({ "file": local, "classes": histogram }))?;
+        }
+        executed = true;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("doze") {
+        match sub.get_one::<String>("action").map(String::as_str) {
+            Some("enter") => analyzer.set_doze(true)?,
+            Some("exit") => analyzer.set_doze(false)?,
+            _ => {}
         }
+        let status = analyzer.idle_status()?;
+        info!("Doze deep: {}  light: {}", status.deep, status.light);
+        output::emit("doze", &status)?;
+        executed = true;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("standby") {
+        if let Some(bucket) = sub.get_one::<String>("set") {
+            analyzer.set_standby_bucket(bucket)?;
+        }
+        let status = analyzer.idle_status()?;
+        info!("Standby bucket of {}: {}", analyzer.config.package_name, status.standby_bucket);
+        output::emit("standby", &status)?;
+        executed = true;
+    }
 
+    if let Some(sub) = matches.subcommand_matches("net") {
+        if let Some(toggle) = sub.subcommand_matches("toggle") {
+            let target = toggle.get_one::<String>("target").and_then(|t| NetTarget::parse(t)).unwrap();
+            let state = toggle.get_one::<String>("state").map(|s| s == "on");
+            let enabled = analyzer.toggle_network(target, state)?;
+            info!("{} turned {}", target.setting(), if enabled { "on" } else { "off" });
+        }
+        let status = analyzer.connectivity_status()?;
+        info!("Default network: {}{}", status.default_network, if status.validated { " (validated)" } else { "" });
+        output::emit("net", &status)?;
+        executed = true;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("device") {
+        match sub.subcommand() {
+            Some(("prep", prep)) => {
+                let applied = analyzer.prep_device(*prep.get_one::<u32>("brightness").unwrap())?;
+                output::emit("device_prep", &applied)?;
+            }
+            Some(("restore", _)) => {
+                let restored = analyzer.restore_device()?;
+                output::emit("device_restore", &restored)?;
+            }
+            _ => unreachable!("device requires a subcommand"),
+        }
+        executed = true;
+    }
+
+    if matches.subcommand_matches("bluetooth").is_some() {
+        let snap = analyzer.bluetooth_snapshot(analyzer.snapshot_time()?)?;
+        info!("Bluetooth activity of {}{}:", analyzer.config.package_name, if snap.registered { " (registered scanner)" } else { "" });
+        info!("LE scans started/stopped: {} / {}  Scan time: {} ms  Unfiltered scans: {}",
+            snap.scans_started, snap.scans_stopped, snap.total_scan_ms, snap.unfiltered_scans);
+        for scan in &snap.ongoing_scans {
+            info!("Ongoing scan: {}", scan);
+        }
+        info!("GATT clients: {}  GATT connections: {}", snap.gatt_clients, snap.gatt_connections);
+        if snap.has_unfiltered_ongoing_scan() {
+            warn!("An unfiltered BLE scan is currently running");
+        }
+        output::emit("bluetooth", &snap)?;
+        executed = true;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("analyze") {
+        let path = sub.get_one::<String>("file").unwrap();
+        let re = regex::bytes::Regex::new(&analyzer.config.keyword_regex)?;
+        let mut out: Box<dyn Write> = match sub.get_one::<String>("output") {
+            Some(output) => Box::new(BufWriter::new(naming::open_output(output)?)),
+            // Matches would interleave with the JSON documents on stdout.
+            None if output::json_output() => Box::new(std::io::sink()),
+            None => Box::new(BufWriter::new(std::io::stdout().lock())),
+        };
+        let summary = offline::filter_log(path, &re, &mut out)?;
+        drop(out);
+        info!("{} of {} lines matched {:?} in {:.1}s", summary.matches, summary.lines, analyzer.config.keyword_regex, summary.elapsed_ms as f64 / 1000.0);
+        output::emit("analyze", &summary)?;
+        executed = true;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("report") {
+        let dir = std::path::Path::new(sub.get_one::<String>("dir").unwrap());
+        let mut files = bundle::collect_artifacts(dir)?;
+        // Neither an earlier copy of the archive being written nor the
+        // config file, which can hold upload credentials.
+        let excluded: Vec<std::path::PathBuf> = [sub.get_one::<String>("bundle"), matches.get_one::<String>("config")]
+            .into_iter()
+            .flatten()
+            .filter_map(|path| std::fs::canonicalize(path).ok())
+            .collect();
+        files.retain(|f| std::fs::canonicalize(dir.join(f)).is_ok_and(|path| !excluded.contains(&path)));
+        let manifest = bundle::build_manifest(dir, &files, &analyzer.config.package_name)?;
+        for entry in &manifest.files {
+            info!("{:>10}  {}", entry.size, entry.path);
+        }
+        info!("{} artifact(s), {} bytes", manifest.files.len(), manifest.files.iter().map(|e| e.size).sum::<u64>());
+        let mut checksum = None;
+        let mut uploaded = None;
+        if let Some(out) = sub.get_one::<String>("bundle") {
+            naming::ensure_replaceable(out)?;
+            let sum = bundle::write_bundle(dir, &manifest, std::path::Path::new(out))?;
+            info!("Bundle written to {} (manifest sha256 {})", out, sum);
+            checksum = Some(sum);
+            if sub.get_flag("upload") {
+                let upload_config = analyzer.config.upload.as_ref().ok_or_else(|| anyhow!("--upload needs an \"upload\" section in the config file"))?;
+                let location = upload::upload_file(upload_config, std::path::Path::new(out))?;
+                info!("Bundle uploaded: {}", location);
+                uploaded = Some(location);
+            }
+        }
+        output::emit("report", &serde_json::json!({ "manifest": manifest, "manifest_sha256": checksum, "uploaded": uploaded }))?;
+        executed = true;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("startup") {
+        let series = analyzer.startup_iterations(*sub.get_one::<u32>("runs").unwrap(), sub.get_flag("pss"), &BenchOptions::from_matches(sub))?;
+        for (metric, values) in &series {
+            info!("{:<8} mean {:.1}  median {:.1}", metric, stats::mean(values), stats::median(values));
+        }
+        let json_file = naming::output_file("startup", chrono::Local::now().format("%Y%m%d_%H%M%S"), "json");
+        std::fs::write(&json_file, schema::to_versioned_json("iterations", &series)?)?;
+        info!("Startup iterations written to {}", json_file);
+        output::emit("startup", &series)?;
+        executed = true;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("eviction") {
+        let fillers: Vec<String> = sub.get_many::<String>("fillers").unwrap().cloned().collect();
+        let timeout = *sub.get_one::<u64>("timeout").unwrap();
+        let report = analyzer.eviction_runs(&fillers, timeout, *sub.get_one::<u32>("runs").unwrap(), &BenchOptions::from_matches(sub))?;
+        let (results, series) = (report.runs, report.series);
+        for (metric, values) in &series {
+            info!("{:<16} mean {:.1}  median {:.1}", metric, stats::mean(values), stats::median(values));
+        }
         let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S").to_string();
-        let json_file = format!("so_memory_{}.json", &timestamp);
-        let csv_file_path = format!("so_memory_{}.csv", &timestamp);
+        let runs_file = naming::output_file("eviction_runs", &timestamp, "json");
+        std::fs::write(&runs_file, schema::to_versioned_json("eviction_runs", &results)?)?;
+        let json_file = naming::output_file("eviction", &timestamp, "json");
+        std::fs::write(&json_file, schema::to_versioned_json("iterations", &series)?)?;
+        info!("Eviction runs written to {}, iterations to {}", runs_file, json_file);
+        output::emit("eviction", &results)?;
+        executed = true;
+    }
 
-        let json = serde_json::to_string_pretty(&so_libs)?;
-        std::fs::write(&json_file, json)?;
-        println!("SO memory info written to {}", json_file);
+    if let Some(sub) = matches.subcommand_matches("compare") {
+        let report = compare_iterations(sub.get_one::<String>("before").unwrap(), sub.get_one::<String>("after").unwrap())?;
+        for (metric, comparison) in &report {
+            print_comparison(metric, "", comparison);
+        }
+        if report.is_empty() {
+            warn!("The two files share no metrics");
+        }
+        output::emit("compare", &report)?;
+        executed = true;
+    }
 
-        let csv_file = File::create(&csv_file_path)?;
-        let mut csv_file = BufWriter::new(csv_file);
-        writeln!(csv_file, "name,pss,private_dirty,shared_dirty")?;
-        for so in &so_libs {
-            writeln!(csv_file, "{},{},{},{}", so.name, so.pss, so.private_dirty, so.shared_dirty)?;
+    if let Some(sub) = matches.subcommand_matches("diff-env") {
+        let path_a = devenv::resolve(sub.get_one::<String>("a").unwrap(), devenv::ENV_FILE_STEM)?;
+        let path_b = devenv::resolve(sub.get_one::<String>("b").unwrap(), devenv::ENV_FILE_STEM)?;
+        let env_a: devenv::DeviceEnv = schema::read_json_file(&path_a, devenv::ENV_FILE_STEM)?;
+        let env_b: devenv::DeviceEnv = schema::read_json_file(&path_b, devenv::ENV_FILE_STEM)?;
+        let changes = devenv::diff(&env_a, &env_b);
+        info!("A: {}\nB: {}", path_a, path_b);
+        for change in &changes {
+            info!("{:<56} {:<30} {}", change.key, change.a.as_deref().unwrap_or("-"), change.b.as_deref().unwrap_or("-"));
+        }
+        if changes.is_empty() {
+            info!("No differences in properties or settings");
+        }
+        output::emit("diff_env", &changes)?;
+        executed = true;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("pkg") {
+        match sub.subcommand() {
+            Some(("info", _)) => {
+                let info = analyzer.package_info()?;
+                info!("{} {} ({})  minSdk {}  targetSdk {}", info.package, info.version_name.as_deref().unwrap_or("-"),
+                    info.version_code.as_deref().unwrap_or("-"), info.min_sdk.map_or("-".to_string(), |s| s.to_string()),
+                    info.target_sdk.map_or("-".to_string(), |s| s.to_string()));
+                info!("Permissions: {} requested, {} granted, {} declared", info.requested_permissions.len(),
+                    info.granted_permissions.len(), info.declared_permissions.len());
+                for permission in &info.requested_permissions {
+                    info!("  {:<60} {}", permission, if info.granted_permissions.contains(permission) { "granted" } else { "" });
+                }
+                for (kind, components) in [("Activities", &info.activities), ("Services", &info.services), ("Receivers", &info.receivers), ("Providers", &info.providers)] {
+                    info!("{} ({}):", kind, components.len());
+                    for component in components {
+                        info!("  {}", component);
+                    }
+                }
+                info!("Package info written to {}", analyzer.write_package_info(&info)?);
+                output::emit("pkg_info", &info)?;
+            }
+            Some(("diff", diff)) => {
+                let path_a = devenv::resolve(diff.get_one::<String>("a").unwrap(), pkginfo::PACKAGE_INFO_STEM)?;
+                let path_b = devenv::resolve(diff.get_one::<String>("b").unwrap(), pkginfo::PACKAGE_INFO_STEM)?;
+                let info_a: pkginfo::PackageInfo = schema::read_json_file(&path_a, pkginfo::PACKAGE_INFO_STEM)?;
+                let info_b: pkginfo::PackageInfo = schema::read_json_file(&path_b, pkginfo::PACKAGE_INFO_STEM)?;
+                let changes = pkginfo::diff(&info_a, &info_b);
+                info!("A: {}\nB: {}", path_a, path_b);
+                for change in &changes {
+                    info!("{:<22} {:<50} {}", change.kind, change.a.as_deref().unwrap_or("-"), change.b.as_deref().unwrap_or("-"));
+                }
+                if changes.is_empty() {
+                    info!("No differences in versions, permissions or components");
+                }
+                output::emit("pkg_diff", &changes)?;
+            }
+            _ => unreachable!("pkg requires a subcommand"),
         }
-        csv_file.flush()?;
-        println!("SO memory info written to {}", csv_file_path);
+        executed = true;
+    }
 
-        Ok(so_libs)
+    if let Some(sub) = matches.subcommand_matches("mark") {
+        let marker = markers::record_marker(sub.get_one::<String>("label").unwrap())?;
+        info!("Marker {:?} added to {}", marker.label, markers::MARKERS_FILE);
+        output::emit("mark", &marker)?;
+        executed = true;
     }
 
-    fn get_memory_info_into(&self, buffer: &mut String) -> Result<()> {
-        let output = Command::new(&self.adb_path)
-            .args(&["shell", "dumpsys", "meminfo", &self.config.package_name])
-            .output()?;
-        buffer.clear();
-        buffer.push_str(&String::from_utf8_lossy(&output.stdout));
-        Ok(())
+    if let Some(sub) = matches.subcommand_matches("search") {
+        let re = regex::bytes::RegexBuilder::new(sub.get_one::<String>("pattern").unwrap())
+            .case_insensitive(sub.get_flag("ignore_case"))
+            .build()?;
+        let mut found = Vec::new();
+        let summary = search::search_sessions(sub.get_many::<String>("dir").unwrap(), &re, |m| {
+            if output::json_output() {
+                found.push(m);
+            } else {
+                info!("{}  {}:{}  {}", m.session, m.file, m.line, m.text);
+            }
+        })?;
+        info!("{} match(es) in {} session(s), {} log file(s) searched", summary.matches, summary.sessions.len(), summary.files);
+        output::emit("search", &found)?;
+        executed = true;
     }
 
-    fn get_pid(&self) -> Result<String> {
-        let output = Command::new(&self.adb_path)
-            .args(&["shell", "pidof", &self.config.package_name])
-            .output()?;
-        let pid = String::from_utf8_lossy(&output.stdout).trim().to_string();
-        if pid.is_empty() {
-            Err(anyhow!("Process {} not found on device", self.config.package_name))
+    if let Some(sub) = matches.subcommand_matches("trend") {
+        let trend = stability::trend(sub.get_many::<String>("dir").unwrap())?;
+        let sessions = &trend.sessions;
+        for (path, summary) in sessions {
+            let start = chrono::DateTime::from_timestamp_millis(summary.start_ms).map(|t| t.with_timezone(&chrono::Local));
+            info!(
+                "{}  {:>8.1} h  {:>3} crash(es) {:>3} native {:>3} ANR(s)  {:>7} crashes/h {:>7} ANRs/h  {}",
+                start.map_or_else(|| "-".to_string(), |t| t.format("%Y-%m-%d %H:%M").to_string()),
+                summary.duration_secs / 3600.0,
+                summary.counts.crashes,
+                summary.counts.native_crashes,
+                summary.counts.anrs,
+                // From the counts, as older files hold rates of sessions too short for one.
+                stability::format_rate(stability::per_hour(summary.counts.all_crashes(), summary.duration_secs)),
+                stability::format_rate(stability::per_hour(summary.counts.anrs, summary.duration_secs)),
+                path.display()
+            );
+        }
+        if sessions.is_empty() {
+            warn!("No stability files found");
         } else {
-            Ok(pid)
+            info!(
+                "{} session(s), {:.1} h: {} crashes/h, {} ANRs/h overall",
+                sessions.len(),
+                trend.duration_secs / 3600.0,
+                stability::format_rate(stability::per_hour(trend.counts.all_crashes(), trend.duration_secs)),
+                stability::format_rate(stability::per_hour(trend.counts.anrs, trend.duration_secs))
+            );
         }
+        let sessions: Vec<&stability::StabilitySummary> = sessions.iter().map(|(_, summary)| summary).collect();
+        output::emit("trend", &sessions)?;
+        executed = true;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("bench-filter") {
+        let path = sub.get_one::<String>("file").unwrap();
+        let mut raw = Vec::new();
+        offline::open_log(path, indicatif::ProgressBar::hidden())?.read_to_end(&mut raw)?;
+        let bench = filterbench::run(&analyzer.config, &raw)?;
+        info!("Replayed {} lines ({}) of {}", bench.lines, units::kb(bench.bytes as u64 / 1024), path);
+        for rate in bench.rules.iter().chain([&bench.combined]) {
+            info!("{:<18} {:>9} kept  {:>12.0} lines/s", rate.rule, rate.kept, rate.lines_per_sec);
+        }
+        info!("{} pipeline worker(s) filter {:.0} lines/s together", bench.workers, bench.capacity());
+        match bench.peak_lines_per_sec {
+            Some(peak) if bench.capacity() < peak as f64 => {
+                warn!(format!("The capture peaks at {} lines/s, more than the rules keep up with; live capture would drop lines", peak));
+            }
+            Some(peak) => info!("The capture peaks at {} lines/s, {:.0}x headroom", peak, bench.capacity() / peak.max(1) as f64),
+            None => info!("No logcat timestamps in the capture to compare its log volume with"),
+        }
+        output::emit("filter_bench", &bench)?;
+        executed = true;
     }
-}
 
-fn parse_memory_value(mem_info: &str, key: &str) -> Result<u64> {
-    for line in mem_info.lines() {
-        if let Some(caps) = MEM_REGEX.captures(line) {
-            if caps.get(1).map_or(false, |m| m.as_str().trim() == key) {
-                return caps.get(2)
-                    .and_then(|m| m.as_str().parse::<u64>().ok())
-                    .ok_or_else(|| anyhow!("Failed to parse {} value", key));
+    if let Some(sub) = matches.subcommand_matches("regex").and_then(|sub| sub.subcommand_matches("test")) {
+        let text = match sub.get_one::<String>("file") {
+            Some(path) => {
+                let mut bytes = Vec::new();
+                offline::open_log(path, indicatif::ProgressBar::hidden())?.read_to_end(&mut bytes)?;
+                console::decode(&bytes)
             }
+            None => analyzer.logcat_buffer()?,
+        };
+        let lines: Vec<&str> = text.lines().collect();
+        let examples = *sub.get_one::<usize>("examples").unwrap();
+        let mut reports = Vec::new();
+        for (name, pattern) in regexcheck::configured_patterns(&analyzer.config) {
+            let report = regexcheck::check(&name, &pattern, &lines, examples);
+            match &report.error {
+                Some(error) => info!("{} {:?}: does not compile: {}", name, pattern, error),
+                None => {
+                    info!("{} {:?}: {} of {} lines match, {:.1} MB/s, slowest line {:.1} us",
+                        name, pattern, report.matched, report.lines, report.mb_per_sec, report.slowest_line_us);
+                    for line in &report.examples {
+                        info!("    {}", line);
+                    }
+                }
+            }
+            if report.is_slow() {
+                warn!(format!("{} matches under {} MB/s and may fall behind a busy logcat", name, regexcheck::MIN_MB_PER_SEC));
+            }
+            reports.push(report);
+        }
+        output::emit("regex_test", &reports)?;
+        let failed = reports.iter().filter(|report| report.error.is_some()).count();
+        if failed > 0 {
+            return Err(anyhow!("{} of {} regexes do not compile", failed, reports.len()));
         }
+        executed = true;
     }
-    warn!(format!("Could not find {} in meminfo", key));
-    Ok(0)
-}
 
-fn main() -> Result<()> {
-    setup_utf8();
-    let adb_check = Command::new("adb").arg("version").output();
-    if adb_check.is_err() {
-        return Err(anyhow!("ADB is not installed or not found in PATH"));
-    }
-
-    let matches = ClapCommand::new("Android Log Analyzer")
-        .version("1.0")
-        .about("Analyzes Android logs, memory, and threads via ADB")
-        .arg(Arg::new("config").short('c').long("config").value_name("CONFIG").help("Path to JSON config file"))
-        .arg(Arg::new("package").short('p').long("package").value_name("PACKAGE").help("Target package name"))
-        .arg(Arg::new("regex").short('r').long("regex").value_name("REGEX").help("Keyword regex for log filtering"))
-        .arg(Arg::new("memory").short('m').long("memory").value_name("DURATION").help("Monitor and plot memory usage for specified duration (seconds)").default_missing_value("60"))
-        .arg(Arg::new("threads").short('t').long("threads").help("Analyze process threads").action(clap::ArgAction::SetTrue))
-        .arg(Arg::new("so_memory").short('s').long("so-memory").help("Analyze .so library memory usage").action(clap::ArgAction::SetTrue))
-        .get_matches();
+    if let Some(sub) = matches.subcommand_matches("ab-test") {
+        let report = analyzer.ab_test(
+            sub.get_one::<String>("apk_a").unwrap(),
+            sub.get_one::<String>("apk_b").unwrap(),
+            *sub.get_one::<u32>("iterations").unwrap(),
+            &BenchOptions::from_matches(sub),
+        )?;
+        let json_file = naming::output_file("ab_test", chrono::Local::now().format("%Y%m%d_%H%M%S"), "json");
+        std::fs::write(&json_file, schema::to_versioned_json("ab_test", &report)?)?;
+        info!("A/B results written to {}", json_file);
+        output::emit("ab_test", &report)?;
+        executed = true;
+    }
 
-    let mut config = if let Some(config_path) = matches.get_one::<String>("config") {
-        let file = File::open(config_path)?;
-        serde_json::from_reader(file)?
-    } else {
-        LogAnalyzerConfig {
-            package_name: "com.example.app".to_string(),
-            keyword_regex: "ERROR|WARNING".to_string(),
-            output_file: Some("filtered_logs.txt".to_string()),
-            sample_interval: 1,
+    if let Some(sub) = matches.subcommand_matches("bisect") {
+        let metric = startup::Metric::parse(sub.get_one::<String>("metric").unwrap()).unwrap();
+        let threshold = *sub.get_one::<u64>("threshold").unwrap();
+        let runs = *sub.get_one::<u32>("runs").unwrap();
+        let builds: Vec<String> = match (sub.get_many::<String>("apks"), sub.get_many::<String>("revs")) {
+            (Some(apks), _) => apks.cloned().collect(),
+            (None, Some(revs)) => revs.cloned().collect(),
+            (None, None) => return Err(anyhow!("bisect needs --apks or --revs")),
+        };
+        let build_cmd = sub.get_one::<String>("build_cmd").filter(|_| sub.contains_id("revs"));
+        let report = analyzer.bisect(&builds, build_cmd.map(String::as_str), metric, threshold, runs, &BenchOptions::from_matches(sub))?;
+        match &report.first_exceeding {
+            Some(build) => info!("First build exceeding {} {}: {}", threshold, metric.unit(), build),
+            None => info!("Newest build does not exceed {} {}, nothing to bisect", threshold, metric.unit()),
         }
-    };
+        output::emit("bisect", &report)?;
+        executed = true;
+    }
 
-    if let Some(package) = matches.get_one::<String>("package") {
-        config.package_name = package.clone();
+    if let Some(sub) = matches.subcommand_matches("scenario") {
+        let scenario = scenario::load_scenario(sub.get_one::<String>("file").unwrap())?;
+        let max_restarts = sub.get_one::<u32>("restart_on_crash").copied();
+        let bench = BenchOptions::from_matches(sub);
+        scenario::check_bench_options(&bench)?;
+        if let Some(matrix) = &scenario.matrix {
+            let cells = analyzer.run_matrix(&scenario, matrix, max_restarts, &bench)?;
+            info!("{:<48} {:>10} {:>14} {:>16}", "Configuration", "Start ms", "Launch PSS", "Scenario PSS");
+            for cell in &cells {
+                info!("{:<48} {:>10} {:>14} {:>16}", cell.config.label(), cell.startup_ms,
+                    cell.pss_after_launch_kb.map_or("n/a".to_string(), units::kb), units::kb(cell.pss_after_scenario_kb));
+            }
+            let json_file = naming::output_file("config_matrix", chrono::Local::now().format("%Y%m%d_%H%M%S"), "json");
+            std::fs::write(&json_file, schema::to_versioned_json("config_matrix", &cells)?)?;
+            info!("Matrix report written to {}", json_file);
+            output::emit("config_matrix", &cells)?;
+        } else {
+            let restarts = analyzer.run_scenario(&scenario, max_restarts, &bench)?;
+            output::emit("scenario", &serde_json::json!({ "passes": scenario.repeat, "crash_restarts": restarts }))?;
+        }
+        executed = true;
     }
-    if let Some(regex) = matches.get_one::<String>("regex") {
-        config.keyword_regex = regex.clone();
+
+    if let Some(sub) = matches.subcommand_matches("anr") {
+        if sub.subcommand_matches("collect").is_some() {
+            let collected = analyzer.collect_anr_traces()?;
+            info!("Collected {} new ANR trace(s) into {} and {}", collected.len(), anr::ANR_DB, anr::ANR_DIR);
+            for name in &collected {
+                info!("  {}", name);
+            }
+            output::emit("anr_collect", &collected)?;
+        } else if let Some(top) = sub.subcommand_matches("top") {
+            let traces = anr::stored_traces()?;
+            let fingerprints = anr::aggregate(&traces, &analyzer.config.package_name, *top.get_one::<usize>("depth").unwrap());
+            info!("{} trace(s), {} distinct main-thread stacks for {}", traces.len(), fingerprints.len(), analyzer.config.package_name);
+            for fp in fingerprints.iter().take(*top.get_one::<usize>("top").unwrap()) {
+                info!("{:>4}x {}", fp.count, fp.frames.first().map_or("?", |f| f.as_str()));
+                for frame in fp.frames.iter().skip(1) {
+                    info!("       {}", frame);
+                }
+            }
+            let json_file = ANR_FINGERPRINTS_FILE;
+            naming::ensure_replaceable(json_file)?;
+            std::fs::write(json_file, schema::to_versioned_json("anr_fingerprints", &fingerprints)?)?;
+            info!("ANR fingerprints written to {}", json_file);
+            output::emit("anr_top", &fingerprints)?;
+        }
+        executed = true;
     }
 
-    let analyzer = LogAnalyzer::new(config);
-    let mut executed = false;
+    if let Some(sub) = matches.subcommand_matches("exits") {
+        let sdk = analyzer.sdk_level()?;
+        if sdk < exitinfo::EXIT_INFO_MIN_SDK {
+            return Err(anyhow!("Exit info needs Android 11 (SDK {}), device is SDK {}", exitinfo::EXIT_INFO_MIN_SDK, sdk));
+        }
+        let exits = analyzer.exit_info()?;
+        info!("Recent exits of {} (newest first):", analyzer.config.package_name);
+        for exit in exits.iter().take(*sub.get_one::<usize>("top").unwrap()) {
+            info!("{}  PID: {:<6} Reason: {:<25} Subreason: {:<20} PSS: {:<8} {}",
+                exit.timestamp, exit.pid, exit.reason, exit.subreason, exit.pss, exit.description);
+        }
+        output::emit("exits", &exits)?;
+        executed = true;
+    }
 
-    if matches.get_flag("threads") {
-        let threads = analyzer.analyze_threads()?;
-        println!("Thread Analysis:");
-        for thread in &threads {
-            println!("TID: {:<6} Name: {:<20} State: {:<2} Priority: {:<3} User Time: {:<6} System Time: {}",
-                thread.tid, thread.name, thread.state, thread.priority, thread.user_time, thread.system_time);
+    if matches.subcommand_matches("boot").is_some() {
+        let report = analyzer.boot_report()?;
+        for milestone in report.kernel.iter().chain(&report.milestones) {
+            info!("{:<36} {:>8} ms", milestone.name, milestone.uptime_ms);
         }
+        match report.total_boot_ms {
+            Some(total) => info!("Total boot time: {} ms", total),
+            None => {
+                warn!("No boot_progress events found in the events log buffer");
+            }
+        }
+        if let Some(launch) = report.first_launch_ms {
+            info!("First launch of {} after boot: {} ms", analyzer.config.package_name, launch);
+        }
+        let json_file = naming::output_file("boot", chrono::Local::now().format("%Y%m%d_%H%M%S"), "json");
+        std::fs::write(&json_file, schema::to_versioned_json("boot", &report)?)?;
+        info!("Boot report written to {}", json_file);
+        output::emit("boot", &report)?;
         executed = true;
     }
 
-    if matches.contains_id("memory") {
-        let duration = matches.get_one::<String>("memory")
-            .and_then(|s| s.parse::<u64>().ok())
-            .unwrap_or_else(|| { warn!("Invalid duration specified, using default 60s"); 60 });
-        let samples = analyzer.monitor_memory(duration, "memory_plot.png")?;
-        println!("Collected {} memory samples.", samples.len());
+    if matches.subcommand_matches("splits").is_some() {
+        let report = analyzer.split_report()?;
+        info!("Splits of {}{}:", report.package, if report.instant { " (instant app)" } else { "" });
+        for split in &report.splits {
+            let loaded = match split.loaded {
+                Some(true) => "loaded",
+                Some(false) => "not loaded",
+                None => "unknown",
+            };
+            let lib_bytes: u64 = split.native_libs.iter().map(|lib| lib.size_bytes).sum();
+            info!("{:<32} {:>10}  native libs: {:>3} ({:>10})  {}",
+                split.name, units::kb(split.size_bytes / 1024), split.native_libs.len(), units::kb(lib_bytes / 1024), loaded);
+        }
+        let json_file = naming::output_file("splits", chrono::Local::now().format("%Y%m%d_%H%M%S"), "json");
+        std::fs::write(&json_file, schema::to_versioned_json("splits", &report)?)?;
+        info!("Split report written to {}", json_file);
+        output::emit("splits", &report)?;
         executed = true;
     }
 
-    if matches.get_flag("so_memory") {
-        let so_libs = analyzer.analyze_so_memory()?;
-        println!("SO Library Memory Analysis:");
-        for so in &so_libs {
-            println!("Name: {:<30} PSS: {:>8} KB  Private Dirty: {:>8} KB  Shared Dirty: {:>8} KB",
-                so.name, so.pss, so.private_dirty, so.shared_dirty);
+    if matches.subcommand_matches("stack").is_some() {
+        let snap = analyzer.activity_stack("manual")?;
+        info!("Resumed activity: {}", snap.resumed_activity.as_deref().unwrap_or("-"));
+        info!("Focused window: {}", snap.focused_window.as_deref().unwrap_or("-"));
+        for task in &snap.tasks {
+            info!("Task #{}", task.id);
+            for activity in &task.activities {
+                info!("  {}", activity);
+            }
         }
+        output::emit("stack", &snap)?;
+        executed = true;
+    }
+
+    if let Some(dump) = matches.subcommand_matches("ui").and_then(|sub| sub.subcommand_matches("dump")) {
+        let local = analyzer.ui_dump()?;
+        let mut metrics = None;
+        if dump.get_flag("metrics") {
+            let ui_metrics = uidump::ui_metrics(&console::read_text(&local)?)?;
+            info!("Nodes: {}  Max depth: {}", ui_metrics.node_count, ui_metrics.max_depth);
+            info!("Deepest path: {}", ui_metrics.deepest_path.join(" > "));
+            for (package, count) in &ui_metrics.nodes_by_package {
+                info!("  {:<50} {}", package, count);
+            }
+            let json_file = local.replace(".xml", "_metrics.json");
+            std::fs::write(&json_file, schema::to_versioned_json("ui_metrics", &ui_metrics)?)?;
+            info!("UI metrics written to {}", json_file);
+            metrics = Some(ui_metrics);
+        }
+        output::emit("ui_dump", &serde_json::json!({ "file": local, "metrics": metrics }))?;
+        executed = true;
+    }
+
+    if let Some(sub) = matches.subcommand_matches("input") {
+        let action = input::action_from_matches(sub)?;
+        let marker = analyzer.inject(&action)?;
+        output::emit("input", &marker)?;
         executed = true;
     }
 
     if !executed {
-        analyzer.start_logcat()?;
+        analyzer.start_logcat(modes.logcat_duration)?;
+    }
+
+    // Ahead of the budget check, so a run over budget is still uploaded.
+    if let Some(upload_config) = analyzer.config.upload.as_ref().filter(|u| u.after_session && env.is_some()) {
+        let out = naming::output_file("session_bundle", chrono::Local::now().format("%Y%m%d_%H%M%S"), "zip");
+        let dir = std::path::Path::new(".");
+        let mut excluded = vec![std::path::Path::new(&out)];
+        excluded.extend(matches.get_one::<String>("config").map(std::path::Path::new));
+        let manifest = bundle::build_manifest(dir, &bundle::session_artifacts(&excluded)?, &analyzer.config.package_name)?;
+        let checksum = bundle::write_bundle(dir, &manifest, std::path::Path::new(&out))?;
+        info!("Bundle of {} artifact(s) written to {} (manifest sha256 {})", manifest.files.len(), out, checksum);
+        let location = upload::upload_file(upload_config, std::path::Path::new(&out))?;
+        info!("Bundle uploaded: {}", location);
+        output::emit("upload", &serde_json::json!({ "bundle": out, "manifest_sha256": checksum, "uploaded": location }))?;
+    }
+
+    if let Some(path) = &analyzer.config.budgets {
+        analyzer.check_budgets(path, memory_samples.as_deref(), so_memory)?;
     }
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,164 @@
+//! Timeline markers shared by all collectors, appended as JSON lines so
+//! concurrent writers and crashed runs never corrupt earlier entries.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+
+use anyhow::Result;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+use crate::pause;
+
+pub const MARKERS_FILE: &str = "markers.jsonl";
+
+/// Read position of `take_live` in `MARKERS_FILE`, set by `follow`.
+static FOLLOWED: Mutex<Option<u64>> = Mutex::new(None);
+static TERMINAL: OnceCell<()> = OnceCell::new();
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Marker {
+    /// Host wall-clock time, milliseconds since the Unix epoch.
+    pub time_ms: i64,
+    pub label: String,
+}
+
+pub fn record_marker(label: &str) -> Result<Marker> {
+    let marker = Marker { time_ms: chrono::Local::now().timestamp_millis(), label: label.to_string() };
+    let mut file = OpenOptions::new().create(true).append(true).open(MARKERS_FILE)?;
+    writeln!(file, "{}", serde_json::to_string(&marker)?)?;
+    Ok(marker)
+}
+
+/// A marker placed on the time axis of a session.
+pub struct SessionMark {
+    /// Seconds since the session started.
+    pub offset_secs: f64,
+    pub time_ms: i64,
+    pub label: String,
+}
+
+/// Markers recorded between `start_ms` and `end_ms`, by this run or by
+/// `mark` in another terminal, merged with `extra` ones in time order.
+/// Unreadable lines are skipped.
+pub fn session_marks(start_ms: i64, end_ms: i64, extra: Vec<Marker>) -> Vec<SessionMark> {
+    let text = std::fs::read_to_string(MARKERS_FILE).unwrap_or_default();
+    let mut marks: Vec<SessionMark> = text
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Marker>(line).ok())
+        .chain(extra)
+        .filter(|m| m.time_ms >= start_ms && m.time_ms <= end_ms)
+        .map(|m| SessionMark { offset_secs: (m.time_ms - start_ms) as f64 / 1000.0, time_ms: m.time_ms, label: m.label })
+        .collect();
+    marks.sort_by_key(|m| m.time_ms);
+    marks
+}
+
+/// Line written into a logcat capture for a marker.
+pub fn marker_line(label: &str) -> String {
+    format!("--------- marker: {} ---------\n", label)
+}
+
+/// Label of a line typed during a session: `mark <label>` (quotes
+/// optional) or an empty line, which gets a numbered label.
+pub fn parse_mark_command(line: &str, count: usize) -> Option<String> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Some(format!("mark {}", count));
+    }
+    let label = line.strip_prefix("mark")?;
+    // "markdown" is not a mark command.
+    if label.starts_with(|c: char| !c.is_whitespace()) {
+        return None;
+    }
+    let label = label.trim();
+    let label = label.strip_prefix('"').and_then(|l| l.strip_suffix('"')).unwrap_or(label);
+    Some(if label.is_empty() { format!("mark {}", count) } else { label.to_string() })
+}
+
+/// Starts reading marker commands from the terminal, once per process;
+/// with `pausable`, `pause` and `resume` too. Piped stdin is left alone.
+pub fn listen_on_terminal(pausable: bool) {
+    if !std::io::stdin().is_terminal() {
+        return;
+    }
+    TERMINAL.get_or_init(|| {
+        std::thread::spawn(move || {
+            let mut count = 0;
+            for line in std::io::stdin().lock().lines().map_while(|line| line.ok()) {
+                if pausable && matches!(line.trim(), "pause" | "resume") {
+                    pause::set_paused(line.trim() == "pause");
+                    continue;
+                }
+                let Some(label) = parse_mark_command(&line, count + 1) else {
+                    warn!(format!("Unknown command {:?}; type mark <label> or press Enter", line.trim()));
+                    continue;
+                };
+                count += 1;
+                match record_marker(&label) {
+                    Ok(marker) => info!("Marker: {}", marker.label),
+                    Err(e) => {
+                        warn!(format!("Could not record marker {:?}: {}", label, e));
+                    }
+                }
+            }
+        });
+        if pausable {
+            info!("Type mark <label> or press Enter to add a marker, pause or resume to suspend sampling");
+        } else {
+            info!("Type mark <label> or press Enter to add a marker");
+        }
+    });
+}
+
+/// Keeps `listen_on_terminal` from reading the terminal, for a caller
+/// that reads keys from it itself.
+pub fn claim_terminal() {
+    let _ = TERMINAL.set(());
+}
+
+/// Starts following `MARKERS_FILE` from its current end, for a collector
+/// that puts the markers added while it runs into its own stream.
+pub fn follow() {
+    let end = std::fs::metadata(MARKERS_FILE).map_or(0, |m| m.len());
+    *FOLLOWED.lock().unwrap() = Some(end);
+}
+
+/// Markers appended to `MARKERS_FILE` since `follow` or the last call, by
+/// this run or by `mark` in another terminal. A line still being written
+/// is left for the next call.
+pub fn take_live() -> Vec<Marker> {
+    let mut followed = FOLLOWED.lock().unwrap();
+    let Some(offset) = followed.as_mut() else {
+        return Vec::new();
+    };
+    let Ok(mut file) = File::open(MARKERS_FILE) else {
+        return Vec::new();
+    };
+    // A file deleted and started over is read from its beginning.
+    if file.metadata().is_ok_and(|m| m.len() < *offset) {
+        *offset = 0;
+    }
+    let mut bytes = Vec::new();
+    if file.seek(SeekFrom::Start(*offset)).and_then(|_| file.read_to_end(&mut bytes)).is_err() {
+        return Vec::new();
+    }
+    let complete = bytes.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+    *offset += complete as u64;
+    String::from_utf8_lossy(&bytes[..complete]).lines().filter_map(|line| serde_json::from_str(line).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mark_command_needs_the_whole_word() {
+        assert_eq!(parse_mark_command("mark \"cold start\"", 1).as_deref(), Some("cold start"));
+        assert_eq!(parse_mark_command("mark", 3).as_deref(), Some("mark 3"));
+        assert_eq!(parse_mark_command("", 2).as_deref(), Some("mark 2"));
+        assert_eq!(parse_mark_command("markdown", 1), None);
+        assert_eq!(parse_mark_command("marker one", 1), None);
+    }
+}
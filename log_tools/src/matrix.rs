@@ -0,0 +1,141 @@
+//! Configuration matrix for scenarios: every combination of app locale,
+//! font scale and display density is applied in turn and measured.
+//!
+//! ```json
+//! "matrix": { "locales": ["en-US", "ar-EG"], "font_scales": [1.0, 1.3], "densities": [420, 560] }
+//! ```
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::compat;
+
+/// `cmd locale set-app-locales` exists from API 33.
+pub const APP_LOCALES_MIN_SDK: u32 = 33;
+
+// "Locales for com.example.app for user 0 are [fr-FR,en-US]"
+static APP_LOCALES_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"are \[([^\]]*)\]").unwrap());
+static OVERRIDE_DENSITY_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"Override density:\s*(\d+)").unwrap());
+
+/// Axes left empty keep the device's current value.
+#[derive(Default, Deserialize)]
+pub struct ConfigMatrix {
+    #[serde(default)]
+    pub locales: Vec<String>,
+    #[serde(default)]
+    pub font_scales: Vec<f64>,
+    #[serde(default)]
+    pub densities: Vec<u32>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct DeviceConfig {
+    pub locale: Option<String>,
+    pub font_scale: Option<f64>,
+    pub density: Option<u32>,
+}
+
+/// Device values in place before the matrix ran.
+pub struct OriginalConfig {
+    /// Comma-separated app locales; empty follows the system locale.
+    pub app_locales: String,
+    /// `None` when the setting was unset.
+    pub font_scale: Option<String>,
+    /// `None` without a `wm density` override.
+    pub density: Option<u32>,
+}
+
+#[derive(Serialize)]
+pub struct MatrixCell {
+    pub config: DeviceConfig,
+    pub startup_ms: u64,
+    pub pss_after_launch_kb: Option<u64>,
+    pub pss_after_scenario_kb: u64,
+}
+
+fn axis<T: Clone>(values: &[T]) -> Vec<Option<T>> {
+    if values.is_empty() { vec![None] } else { values.iter().cloned().map(Some).collect() }
+}
+
+impl ConfigMatrix {
+    /// Cartesian product of the axes, locale varying slowest.
+    pub fn configs(&self) -> Vec<DeviceConfig> {
+        let mut configs = Vec::new();
+        for locale in axis(&self.locales) {
+            for font_scale in axis(&self.font_scales) {
+                for density in axis(&self.densities) {
+                    configs.push(DeviceConfig { locale: locale.clone(), font_scale, density });
+                }
+            }
+        }
+        configs
+    }
+}
+
+impl DeviceConfig {
+    pub fn label(&self) -> String {
+        format!(
+            "locale {} font {} density {}",
+            self.locale.as_deref().unwrap_or("-"),
+            self.font_scale.map_or("-".to_string(), |s| s.to_string()),
+            self.density.map_or("-".to_string(), |d| d.to_string()),
+        )
+    }
+
+    pub fn commands(&self, package: &str, user: Option<u32>) -> Vec<Vec<String>> {
+        let mut commands = Vec::new();
+        if let Some(locale) = &self.locale {
+            commands.push(set_app_locales(package, user, locale));
+        }
+        if let Some(scale) = self.font_scale {
+            commands.push(args(&["settings", "put", "system", "font_scale", &scale.to_string()]));
+        }
+        if let Some(density) = self.density {
+            commands.push(args(&["wm", "density", &density.to_string()]));
+        }
+        commands
+    }
+}
+
+impl OriginalConfig {
+    pub fn restore_commands(&self, package: &str, user: Option<u32>, matrix: &ConfigMatrix) -> Vec<Vec<String>> {
+        let mut commands = Vec::new();
+        if !matrix.locales.is_empty() {
+            commands.push(set_app_locales(package, user, &self.app_locales));
+        }
+        if !matrix.font_scales.is_empty() {
+            commands.push(match &self.font_scale {
+                Some(scale) => args(&["settings", "put", "system", "font_scale", scale]),
+                None => args(&["settings", "delete", "system", "font_scale"]),
+            });
+        }
+        if !matrix.densities.is_empty() {
+            commands.push(match self.density {
+                Some(density) => args(&["wm", "density", &density.to_string()]),
+                None => args(&["wm", "density", "reset"]),
+            });
+        }
+        commands
+    }
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+}
+
+/// An empty `locales` resets the app to the system locale.
+fn set_app_locales(package: &str, user: Option<u32>, locales: &str) -> Vec<String> {
+    let mut command = args(&["cmd", "locale", "set-app-locales", package]);
+    command.extend(compat::user_args(user));
+    command.extend(args(&["--locales", &format!("'{}'", locales)]));
+    command
+}
+
+pub fn parse_app_locales(output: &str) -> String {
+    APP_LOCALES_REGEX.captures(output).map_or(String::new(), |caps| caps[1].trim().to_string())
+}
+
+pub fn parse_override_density(output: &str) -> Option<u32> {
+    OVERRIDE_DENSITY_REGEX.captures(output).and_then(|caps| caps[1].parse().ok())
+}
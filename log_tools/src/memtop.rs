@@ -0,0 +1,133 @@
+//! Device-wide PSS ranking from `dumpsys meminfo` without a package filter.
+
+use std::collections::BTreeMap;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+// "    245,123K: system (pid 1234)" / "  80000 kB: com.foo (pid 42 / activities)"
+static PROCESS_PSS_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\s*([\d,]+)\s*(?:K|kB):\s+(.+?)\s+\(pid\s+(\d+)").unwrap());
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ProcessPss {
+    pub name: String,
+    pub pid: u32,
+    pub pss: u64,
+}
+
+#[derive(Serialize)]
+pub struct PssDelta {
+    pub name: String,
+    pub before: u64,
+    pub after: u64,
+    pub delta: i64,
+}
+
+/// Parses the "Total PSS by process:" section, sorted by PSS descending.
+pub fn parse_total_pss(output: &str) -> Vec<ProcessPss> {
+    let mut processes = Vec::new();
+    let mut in_section = false;
+    for line in output.lines() {
+        if line.starts_with("Total PSS by process") {
+            in_section = true;
+            continue;
+        }
+        if !in_section {
+            continue;
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some(caps) = PROCESS_PSS_REGEX.captures(line) {
+            processes.push(ProcessPss {
+                name: caps[2].to_string(),
+                pid: caps[3].parse().unwrap_or(0),
+                pss: caps[1].replace(',', "").parse().unwrap_or(0),
+            });
+        }
+    }
+    processes.sort_by_key(|p| std::cmp::Reverse(p.pss));
+    processes
+}
+
+/// Compares two snapshots by process name (PIDs change across restarts),
+/// largest growth first. Processes missing from one side count as 0.
+pub fn diff_snapshots(before: &[ProcessPss], after: &[ProcessPss]) -> Vec<PssDelta> {
+    let mut totals: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+    for p in before {
+        totals.entry(&p.name).or_default().0 += p.pss;
+    }
+    for p in after {
+        totals.entry(&p.name).or_default().1 += p.pss;
+    }
+    let mut deltas: Vec<PssDelta> = totals
+        .into_iter()
+        .map(|(name, (before, after))| PssDelta {
+            name: name.to_string(),
+            before,
+            after,
+            delta: after as i64 - before as i64,
+        })
+        .collect();
+    deltas.sort_by_key(|d| std::cmp::Reverse(d.delta));
+    deltas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Head of `dumpsys meminfo` on API 31, which lists RSS before PSS.
+    const MEMINFO: &str = "\
+Applications Memory Usage (in Kilobytes):
+Uptime: 8812345 Realtime: 8812345
+
+Total RSS by process:
+    412,200K: system (pid 1234)
+    260,100K: com.example.app (pid 4242 / activities)
+
+Total PSS by process:
+    245,123K: system (pid 1234)
+     80,000K: com.example.app (pid 4242 / activities)
+    180,456K: com.android.systemui (pid 2345 / activities)
+     12,345K: com.example.app:remote (pid 4243)
+
+Total PSS by OOM adjustment:
+    245,123K: System
+";
+
+    #[test]
+    fn pss_section_sorted_by_size() {
+        let processes = parse_total_pss(MEMINFO);
+        let rows: Vec<(&str, u32, u64)> = processes.iter().map(|p| (p.name.as_str(), p.pid, p.pss)).collect();
+        assert_eq!(
+            rows,
+            [
+                ("system", 1234, 245123),
+                ("com.android.systemui", 2345, 180456),
+                ("com.example.app", 4242, 80000),
+                ("com.example.app:remote", 4243, 12345),
+            ]
+        );
+    }
+
+    #[test]
+    fn legacy_kb_lines() {
+        let output = "Total PSS by process:\n  80000 kB: com.foo (pid 42 / activities)\n  1500 kB: com.bar (pid 7)\n";
+        let processes = parse_total_pss(output);
+        assert_eq!((processes[0].name.as_str(), processes[0].pss), ("com.foo", 80000));
+        assert_eq!(processes.len(), 2);
+    }
+
+    #[test]
+    fn diff_matches_names_across_restarts() {
+        let p = |name: &str, pid, pss| ProcessPss { name: name.to_string(), pid, pss };
+        let before = [p("system", 1, 100), p("com.a", 10, 50), p("com.gone", 11, 30)];
+        let after = [p("system", 1, 110), p("com.a", 20, 90), p("com.new", 21, 5)];
+        let deltas = diff_snapshots(&before, &after);
+        let rows: Vec<(&str, i64)> = deltas.iter().map(|d| (d.name.as_str(), d.delta)).collect();
+        assert_eq!(rows, [("com.a", 40), ("system", 10), ("com.new", 5), ("com.gone", -30)]);
+    }
+}
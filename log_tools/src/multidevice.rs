@@ -0,0 +1,207 @@
+//! `--devices <SERIAL,...|all>`: the same memory or logcat session on
+//! several devices at once. Each device runs in a child process of this
+//! tool with `--serial`, inside a directory named after the serial, so its
+//! files, plots and logcat capture stay apart and every collector works as
+//! in a single-device run. Their output is shown prefixed with the serial.
+//! Once all are done, memory sessions get a combined plot and summary of
+//! every device in the starting directory.
+//!
+//! Children read no terminal input, so markers come from `mark` run in a
+//! device directory, and `--control` cannot be used.
+
+use std::collections::{BTreeMap, HashSet};
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::devices::{self, Device};
+use crate::{LogAnalyzer, LogAnalyzerConfig, clocksync, devenv, naming, output, pkgcompare, schema, stats};
+
+pub const DEVICES_PLOT_FILE: &str = "devices_comparison_plot.png";
+pub const SAMPLES_STEM: &str = "memory_samples";
+/// The effective configuration handed to each child, in its directory.
+pub const CHILD_CONFIG_FILE: &str = "session_config.json";
+
+/// Serials to run on: those requested, or every online device for "all".
+pub fn resolve_serials(requested: &[String], attached: &[Device]) -> Result<Vec<String>> {
+    let serials: Vec<String> = if requested.iter().any(|s| s == "all") {
+        attached.iter().filter(|d| d.is_online()).map(|d| d.serial.clone()).collect()
+    } else {
+        let mut seen = HashSet::new();
+        requested.iter().filter(|serial| seen.insert(serial.as_str())).cloned().collect()
+    };
+    if serials.is_empty() {
+        return Err(anyhow!("No online devices attached"));
+    }
+    for serial in &serials {
+        devices::check_target(attached, Some(serial))?;
+    }
+    Ok(serials)
+}
+
+/// Options each child gets its own value of through its config file:
+/// the device selection, the config itself, and the files that are
+/// resolved from the starting directory rather than the child's.
+const CHILD_CONFIG_OPTIONS: &[&str] = &["--devices", "--config", "-c", "--serial", "-d", "--db", "--budgets", "--so-owners", "--events-file"];
+
+/// This run's arguments without the options in the child's config file.
+pub fn child_args(args: impl Iterator<Item = String>) -> Vec<String> {
+    let mut kept = Vec::new();
+    let mut skip_value = false;
+    for arg in args {
+        if skip_value {
+            skip_value = false;
+            continue;
+        }
+        if CHILD_CONFIG_OPTIONS.contains(&arg.as_str()) {
+            skip_value = true;
+        } else if !CHILD_CONFIG_OPTIONS.iter().any(|option| option.starts_with("--") && arg.starts_with(&format!("{}=", option))) {
+            kept.push(arg);
+        }
+    }
+    kept
+}
+
+/// Runs the session given by `args` on each of `config.devices`: this
+/// tool as a child with `--serial` in the device's directory, reading its
+/// own copy of the config. With `memory`, the devices' memory is then
+/// compared. Returns the serials whose session failed.
+pub fn run_sessions(config: LogAnalyzerConfig, attached: &[Device], args: &[String], memory: bool) -> Result<Vec<String>> {
+    if config.control_socket.is_some() {
+        return Err(anyhow!("--control cannot be shared by several devices; run them separately"));
+    }
+    let serials = resolve_serials(&config.devices, attached)?;
+    if memory {
+        naming::ensure_replaceable(DEVICES_PLOT_FILE)?;
+    }
+    let exe = std::env::current_exe()?;
+    info!("Running on {} devices: {}", serials.len(), serials.join(", "));
+    let mut children = Vec::new();
+    for serial in &serials {
+        let dir = device_dir(serial);
+        std::fs::create_dir_all(&dir)?;
+        let mut child_config = config.clone();
+        child_config.serial = Some(serial.clone());
+        child_config.devices = Vec::new();
+        // Children run in their own directory: inputs and the database are
+        // resolved from here, and a shared absolute logcat file goes to
+        // each directory.
+        for path in [&mut child_config.budgets, &mut child_config.so_owners, &mut child_config.events_file, &mut child_config.db].into_iter().flatten() {
+            *path = std::path::absolute(&*path)?.to_string_lossy().into_owned();
+        }
+        if let Some(path) = child_config.output_file.as_mut().filter(|path| Path::new(path.as_str()).is_absolute()) {
+            *path = Path::new(path.as_str()).file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        }
+        std::fs::write(Path::new(&dir).join(CHILD_CONFIG_FILE), serde_json::to_string_pretty(&child_config)?)?;
+        let mut child = Command::new(&exe)
+            .current_dir(&dir)
+            .args(["--config", CHILD_CONFIG_FILE, "--serial", serial])
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let (stdout, stderr) = (child.stdout.take().ok_or(anyhow!("Failed to get stdout"))?, child.stderr.take().ok_or(anyhow!("Failed to get stderr"))?);
+        let (out_serial, err_serial) = (serial.clone(), serial.clone());
+        let forwarders = [
+            std::thread::spawn(move || forward(&out_serial, stdout, false)),
+            std::thread::spawn(move || forward(&err_serial, stderr, true)),
+        ];
+        children.push((serial, child, forwarders));
+    }
+    let mut failed = Vec::new();
+    for (serial, mut child, forwarders) in children {
+        let status = child.wait()?;
+        for forwarder in forwarders {
+            let _ = forwarder.join();
+        }
+        if !status.success() {
+            failed.push(serial.clone());
+        }
+    }
+    if memory {
+        LogAnalyzer::new(config).write_device_comparison(attached, &serials)?;
+    }
+    Ok(failed)
+}
+
+/// Directory of a device's session, relative to the starting one.
+pub fn device_dir(serial: &str) -> String {
+    naming::sanitize(serial)
+}
+
+/// Copies a child's stdout or stderr to ours line by line, prefixed with
+/// its serial. Under `--json`, documents on stdout are wrapped instead so
+/// they stay attributable.
+pub fn forward(serial: &str, stream: impl Read, stderr: bool) {
+    for line in BufReader::new(stream).lines().map_while(|line| line.ok()) {
+        if stderr {
+            eprintln!("[{}] {}", serial, line);
+        } else if let Some(document) = output::json_output().then(|| serde_json::from_str::<Value>(&line).ok()).flatten() {
+            let _ = output::emit("device_output", &serde_json::json!({ "serial": serial, "document": document }));
+        } else {
+            info!("[{}] {}", serial, line);
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct MetricSummary {
+    pub mean: f64,
+    pub peak: f64,
+    pub last: f64,
+}
+
+#[derive(Serialize)]
+pub struct DeviceSummary {
+    pub serial: String,
+    pub model: Option<String>,
+    pub samples_file: String,
+    pub samples: usize,
+    pub metrics: BTreeMap<&'static str, MetricSummary>,
+}
+
+/// Samples of a device's newest memory_samples file, as JSON objects.
+pub fn load_samples(dir: &str) -> Result<(String, Vec<Value>)> {
+    if !Path::new(dir).is_dir() {
+        return Err(anyhow!("{} was not created", dir));
+    }
+    let path = devenv::resolve(dir, SAMPLES_STEM)?;
+    let samples = schema::read_json_file(&path, SAMPLES_STEM)?;
+    Ok((path, samples))
+}
+
+/// (seconds, KB) points of `metric`.
+pub fn series(samples: &[Value], metric: &str) -> Vec<(f64, f64)> {
+    samples
+        .iter()
+        .filter_map(|s| Some((clocksync::sample_secs(s)?, s[metric].as_f64()?)))
+        .collect()
+}
+
+pub fn summarize(serial: &str, model: Option<String>, samples_file: String, samples: &[Value]) -> DeviceSummary {
+    let metrics = pkgcompare::METRICS
+        .iter()
+        .filter_map(|(metric, _)| {
+            let values: Vec<f64> = series(samples, metric).into_iter().map(|(_, v)| v).collect();
+            let last = *values.last()?;
+            Some((*metric, MetricSummary { mean: stats::mean(&values), peak: values.iter().copied().fold(0.0, f64::max), last }))
+        })
+        .collect();
+    DeviceSummary { serial: serial.to_string(), model, samples_file, samples: samples.len(), metrics }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn child_args_drop_options_the_child_config_carries() {
+        let args = ["memory", "--budgets", "budgets.json", "--so-owners=owners.json", "--events-file", "events.csv", "--db", "runs.db", "-d", "a", "--duration", "60"];
+        assert_eq!(child_args(args.into_iter().map(String::from)), ["memory", "--duration", "60"]);
+    }
+}
@@ -0,0 +1,188 @@
+//! Output file names: `<stem>_<package>[_<serial>]_<timestamp>.<ext>`,
+//! with the package and device serial made safe for any host filesystem
+//! and a `_2`, `_3`, ... suffix instead of overwriting an existing file.
+//! Fixed-name outputs (plots, the logcat file) follow the overwrite
+//! policy of `--force` / `--append` instead.
+
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use anyhow::{Result, anyhow};
+use once_cell::sync::OnceCell;
+
+static TAG: OnceCell<String> = OnceCell::new();
+/// When `set_context` ran, i.e. when the run started.
+static STARTED: OnceCell<SystemTime> = OnceCell::new();
+static OVERWRITE_POLICY: OnceCell<OverwritePolicy> = OnceCell::new();
+/// Every name `output_file` handed out, for the output guard.
+static ISSUED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+/// Other files the run writes: fixed-name outputs and files named after
+/// another one, for the session bundle.
+static RECORDED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Existing outputs are never touched; the run stops instead.
+    Refuse,
+    Force,
+    /// Appendable text outputs grow; anything else is still refused.
+    Append,
+}
+
+/// Sets the package and device serial included in every generated name.
+pub fn set_context(package: &str, serial: Option<&str>) {
+    let tag = match serial {
+        Some(serial) => format!("{}_{}", sanitize(package), sanitize(serial)),
+        None => sanitize(package),
+    };
+    let _ = TAG.set(tag);
+    let _ = STARTED.set(SystemTime::now());
+}
+
+/// Replaces everything but ASCII letters, digits, '.', '-' and '_', so
+/// serials such as "192.168.1.7:5555" become valid on Windows too.
+pub fn sanitize(component: &str) -> String {
+    component
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+        .collect()
+}
+
+fn tag() -> &'static str {
+    TAG.get().map_or("", String::as_str)
+}
+
+/// A fresh output file name; never the name of an existing file.
+pub fn output_file<T: Display>(stem: &str, timestamp: T, extension: &str) -> String {
+    let base = match tag() {
+        "" => format!("{}_{}", stem, timestamp),
+        tag => format!("{}_{}_{}", stem, tag, timestamp),
+    };
+    let mut name = format!("{}.{}", base, extension);
+    let mut n = 2;
+    while Path::new(&name).exists() {
+        name = format!("{}_{}.{}", base, n, extension);
+        n += 1;
+    }
+    ISSUED.lock().unwrap().push(name.clone());
+    name
+}
+
+/// How many names `output_file` has handed out so far.
+pub fn issued_count() -> usize {
+    ISSUED.lock().unwrap().len()
+}
+
+/// Bytes in the files `output_file` named after the first `from`.
+pub fn issued_bytes(from: usize) -> u64 {
+    let issued = ISSUED.lock().unwrap();
+    issued.iter().skip(from).filter_map(|name| std::fs::metadata(name).ok()).map(|meta| meta.len()).sum()
+}
+
+/// Notes a file the run writes under a name `output_file` did not give.
+pub fn record(path: &str) {
+    RECORDED.lock().unwrap().push(path.to_string());
+}
+
+/// Files this run named and then wrote, each once, in the order they
+/// were named. A fixed-name file left from an earlier run is skipped
+/// unless this run rewrote it.
+pub fn written_files() -> Vec<String> {
+    let mut files: Vec<String> = ISSUED.lock().unwrap().iter().chain(RECORDED.lock().unwrap().iter()).cloned().collect();
+    let mut seen = std::collections::HashSet::new();
+    files.retain(|name| {
+        let modified = std::fs::metadata(name).ok().filter(|meta| meta.is_file()).and_then(|meta| meta.modified().ok());
+        seen.insert(name.clone()) && modified.is_some_and(|at| STARTED.get().is_none_or(|started| at >= *started))
+    });
+    files
+}
+
+/// Name a rotated-out file moves to: `timestamp` inserted before the
+/// extensions, e.g. "capture_20240102_030405.txt.gz" for "capture.txt.gz".
+pub fn rotated_name<T: Display>(path: &str, timestamp: T) -> String {
+    let path = Path::new(path);
+    let file_name = path.file_name().map_or_else(String::new, |n| n.to_string_lossy().into_owned());
+    let (stem, extensions) = match file_name.split_once('.') {
+        Some((stem, extensions)) => (stem, format!(".{}", extensions)),
+        None => (file_name.as_str(), String::new()),
+    };
+    let with = |suffix: &str| path.with_file_name(format!("{}_{}{}{}", stem, timestamp, suffix, extensions)).to_string_lossy().into_owned();
+    let mut name = with("");
+    let mut n = 2;
+    while Path::new(&name).exists() {
+        name = with(&format!("_{}", n));
+        n += 1;
+    }
+    record(&name);
+    name
+}
+
+pub fn set_overwrite_policy(policy: OverwritePolicy) {
+    let _ = OVERWRITE_POLICY.set(policy);
+}
+
+fn overwrite_policy() -> OverwritePolicy {
+    OVERWRITE_POLICY.get().copied().unwrap_or(OverwritePolicy::Refuse)
+}
+
+/// Fails unless `path` is absent or `--force` allows replacing it. Call
+/// before long collections so a refusal comes before the work, not after.
+pub fn ensure_replaceable(path: &str) -> Result<()> {
+    record(path);
+    if !Path::new(path).exists() {
+        return Ok(());
+    }
+    match overwrite_policy() {
+        OverwritePolicy::Force => Ok(()),
+        OverwritePolicy::Append => Err(anyhow!("{} already exists and cannot be appended to; pass --force to replace it", path)),
+        OverwritePolicy::Refuse => Err(anyhow!("{} already exists; pass --force to replace it", path)),
+    }
+}
+
+/// Opens a text output such as the logcat file, appending under `--append`.
+pub fn open_output(path: &str) -> Result<File> {
+    record(path);
+    if Path::new(path).exists() {
+        match overwrite_policy() {
+            OverwritePolicy::Append => return Ok(OpenOptions::new().append(true).open(path)?),
+            OverwritePolicy::Force => {}
+            OverwritePolicy::Refuse => {
+                return Err(anyhow!("{} already exists; pass --force to replace it or --append to add to it", path));
+            }
+        }
+    }
+    Ok(File::create(path)?)
+}
+
+/// Expands the `_<timestamp>` placeholder of `--dry-run` file names to
+/// the form `output_file` produces.
+pub fn planned(text: &str) -> String {
+    match tag() {
+        "" => text.to_string(),
+        tag => text.replace("_<timestamp>", &format!("_{}_<timestamp>", tag)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn written_files_are_the_existing_recorded_files() {
+        let dir = std::env::temp_dir().join(format!("naming_written_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("plot.png").to_string_lossy().into_owned();
+        std::fs::write(&file, "png").unwrap();
+        let missing = dir.join("never_written.csv").to_string_lossy().into_owned();
+        for path in [&file, &missing, &file, &dir.to_string_lossy().into_owned()] {
+            record(path);
+        }
+        let written = written_files();
+        assert_eq!(written.iter().filter(|name| **name == file).count(), 1);
+        assert!(!written.contains(&missing) && !written.iter().any(|name| Path::new(name) == dir));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
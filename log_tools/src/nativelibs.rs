@@ -0,0 +1,250 @@
+//! `--native-libs`: when the app's native libraries were loaded and
+//! unloaded during a memory session, so a step in PSS can be put on the
+//! library behind it and on the marked action before it.
+//!
+//! Loads come from logcat with their own time: setting
+//! `debug.ld.app.<package>` to `dlopen` makes the linker log every dlopen
+//! of the app from its next start on, and nativeloader logs each
+//! `System.loadLibrary` of any app. The `.so` mappings of the process,
+//! read every sample through root or run-as, catch loads without a log
+//! line and are the only source of unloads, at sample resolution.
+//! Libraries loaded straight from an uncompressed APK map as the APK and
+//! are only seen in logcat.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::clocksync::{ClockSync, SampleTime};
+use crate::markers::{Marker, SessionMark};
+
+// "D/linker  ( 1234): dlopen(name=\"libfoo.so\", flags=0x2, extinfo=(null), caller=\"...\", ...) ..."
+static DLOPEN_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"linker\s*\(\s*(\d+)\): dlopen\(name="([^"]+)""#).unwrap());
+// "D/nativeloader( 1234): Load /data/app/~~x/com.example.app-y/lib/arm64/libfoo.so using ns clns-7 from class loader (caller=...): ok"
+static NATIVELOADER_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"nativeloader\s*\(\s*(\d+)\): Load (\S+) using .*: ok\s*$").unwrap());
+
+pub fn linker_debug_cmd(package: &str) -> String {
+    format!("setprop debug.ld.app.{} dlopen", package)
+}
+
+/// The `.so` lines of the process's mappings; quote-free so it runs
+/// unchanged under `su -c` and `run-as`.
+pub fn maps_cmd(pid: &str) -> String {
+    format!("grep -F .so /proc/{}/maps", pid)
+}
+
+pub fn is_library_log(line: &str) -> bool {
+    line.contains("dlopen(name=") || line.contains("nativeloader")
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LibraryEventKind {
+    Load,
+    Unload,
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LibrarySource {
+    Logcat,
+    Maps,
+}
+
+#[derive(Serialize)]
+pub struct LibraryEvent {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    pub kind: LibraryEventKind,
+    /// File name, e.g. "libfoo.so".
+    pub library: String,
+    /// Path as mapped or logged; a bare name when dlopen was given one.
+    pub path: String,
+    pub source: LibrarySource,
+    /// Total PSS of the first sample from the event on minus that of the
+    /// last sample before it, in KB.
+    pub pss_step_kb: Option<i64>,
+    /// Latest marker at or before the event.
+    pub after_marker: Option<String>,
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// File name to path of the libraries in `grep -F .so /proc/<pid>/maps`
+/// output.
+pub fn mapped_libraries(maps: &str) -> BTreeMap<String, String> {
+    maps.lines()
+        .filter_map(|line| line.split_whitespace().nth(5))
+        .filter(|path| path.starts_with('/') && path.ends_with(".so"))
+        .map(|path| (file_name(path).to_string(), path.to_string()))
+        .collect()
+}
+
+#[derive(Default)]
+pub struct LibraryTracker {
+    /// Libraries known to be loaded, by file name.
+    loaded: BTreeSet<String>,
+    /// Those of them seen in the last mappings read.
+    mapped: BTreeSet<String>,
+    pub events: Vec<LibraryEvent>,
+}
+
+impl LibraryTracker {
+    /// Takes the libraries mapped before the session as loaded.
+    pub fn baseline(&mut self, maps: &str) {
+        self.mapped = mapped_libraries(maps).into_keys().collect();
+        self.loaded = self.mapped.clone();
+    }
+
+    /// Records libraries mapped or unmapped since the last read, at `time`.
+    pub fn observe_maps(&mut self, maps: &str, time: SampleTime) {
+        let now = mapped_libraries(maps);
+        for (library, path) in &now {
+            if self.loaded.insert(library.clone()) {
+                self.push(time, LibraryEventKind::Load, library, path, LibrarySource::Maps);
+            }
+        }
+        for library in self.mapped.clone() {
+            if !now.contains_key(&library) {
+                self.loaded.remove(&library);
+                self.push(time, LibraryEventKind::Unload, &library, &library, LibrarySource::Maps);
+            }
+        }
+        self.mapped = now.into_keys().collect();
+    }
+
+    /// Records a load logged by the linker, or by nativeloader in process
+    /// `pid`, from `start_ms` on. A load the mappings already showed gets
+    /// the logged time when that is earlier.
+    pub fn observe_log(&mut self, line: &str, pid: Option<&str>, clock: &ClockSync, start_ms: i64) {
+        let path = if let Some(caps) = DLOPEN_REGEX.captures(line) {
+            caps[2].to_string()
+        } else if let Some(caps) = NATIVELOADER_REGEX.captures(line).filter(|caps| Some(&caps[1]) == pid) {
+            caps[2].to_string()
+        } else {
+            return;
+        };
+        let Some(host_ms) = clock.host_time_ms(line, None).filter(|ms| *ms >= start_ms) else {
+            return;
+        };
+        let time = clock.time_at(start_ms, host_ms);
+        let library = file_name(&path).to_string();
+        if self.loaded.insert(library.clone()) {
+            self.push(time, LibraryEventKind::Load, &library, &path, LibrarySource::Logcat);
+            return;
+        }
+        let mapped_load = self.events.iter_mut().rev().find(|e| e.library == library && e.kind == LibraryEventKind::Load);
+        if let Some(event) = mapped_load.filter(|e| e.source == LibrarySource::Maps && e.time.host_time_ms > host_ms) {
+            event.time = time;
+            event.source = LibrarySource::Logcat;
+        }
+    }
+
+    fn push(&mut self, time: SampleTime, kind: LibraryEventKind, library: &str, path: &str, source: LibrarySource) {
+        self.events.push(LibraryEvent {
+            time,
+            kind,
+            library: library.to_string(),
+            path: path.to_string(),
+            source,
+            pss_step_kb: None,
+            after_marker: None,
+        });
+    }
+}
+
+/// Fills in the PSS step and the preceding marker of each event, from
+/// (host time in ms, total PSS in KB) samples, and sorts the events by
+/// time.
+pub fn attribute(events: &mut [LibraryEvent], pss: &[(i64, u64)], marks: &[SessionMark]) {
+    events.sort_by_key(|e| e.time.host_time_ms);
+    for event in events.iter_mut() {
+        let at = event.time.host_time_ms;
+        let before = pss.iter().rev().find(|(ms, _)| *ms < at);
+        let after = pss.iter().find(|(ms, _)| *ms >= at);
+        if let (Some((_, before)), Some((_, after))) = (before, after) {
+            event.pss_step_kb = Some(*after as i64 - *before as i64);
+        }
+        event.after_marker = marks.iter().rev().find(|m| m.time_ms <= at).map(|m| m.label.clone());
+    }
+}
+
+/// Loads as markers for the memory plot.
+pub fn load_markers(events: &[LibraryEvent]) -> Vec<Marker> {
+    events
+        .iter()
+        .filter(|e| e.kind == LibraryEventKind::Load)
+        .map(|e| Marker { time_ms: e.time.host_time_ms, label: e.library.clone() })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APP_LIB: &str = "/data/app/~~w3A9q==/com.example.app-Xq1b==/lib/arm64";
+
+    fn maps(libraries: &[&str]) -> String {
+        libraries
+            .iter()
+            .enumerate()
+            .map(|(i, path)| format!("7a1c{:02x}3000-7a1c{:02x}f000 r-xp 00000000 fd:05 {}  {}\n", i, i, 123456 + i, path))
+            .collect()
+    }
+
+    #[test]
+    fn mapped_so_files_by_name() {
+        let output = format!("{}7b0000000-7b0001000 rw-p 00000000 00:00 0  [anon:.bss]\n", maps(&["/system/lib64/libc.so", "/apex/com.android.art/lib64/libart.so"]));
+        let libraries = mapped_libraries(&output);
+        assert_eq!(libraries.keys().collect::<Vec<_>>(), ["libart.so", "libc.so"]);
+        assert_eq!(libraries["libc.so"], "/system/lib64/libc.so");
+    }
+
+    #[test]
+    fn loads_and_unloads_from_maps_and_logcat() {
+        let clock = ClockSync::default();
+        let start_ms = clock.host_time_ms("03-10 15:00:00.000", None).unwrap();
+        let foo = format!("{}/libfoo.so", APP_LIB);
+        let bar = format!("{}/libbar.so", APP_LIB);
+
+        let mut tracker = LibraryTracker::default();
+        tracker.baseline(&maps(&["/system/lib64/libc.so"]));
+        tracker.observe_maps(&maps(&["/system/lib64/libc.so", &foo]), clock.time_at(start_ms, start_ms + 2000));
+        let logcat = format!(
+            "\
+03-10 15:00:01.250 D/linker  ( 4242): dlopen(name=\"libfoo.so\", flags=0x2, extinfo=(null), caller=\"/apex/com.android.art/lib64/libnativeloader.so\", caller_ns=clns-7@0x7b2c1e0a10, targetSdkVersion=33) ...
+03-10 15:00:02.600 D/nativeloader( 4242): Load {bar} using ns clns-7 from class loader (caller=/data/app/~~w3A9q==/com.example.app-Xq1b==/base.apk): ok
+03-10 15:00:02.700 D/nativeloader( 5120): Load /data/app/~~k1==/com.other.app-z==/lib/arm64/libother.so using ns clns-9 from class loader (caller=/data/app/~~k1==/com.other.app-z==/base.apk): ok
+",
+            bar = bar
+        );
+        for line in logcat.lines().filter(|line| is_library_log(line)) {
+            tracker.observe_log(line, Some("4242"), &clock, start_ms);
+        }
+        tracker.observe_maps(&maps(&["/system/lib64/libc.so", &bar]), clock.time_at(start_ms, start_ms + 4000));
+
+        let mut events = tracker.events;
+        let marks = [SessionMark { offset_secs: 1.0, time_ms: start_ms + 1000, label: "open camera".to_string() }];
+        attribute(&mut events, &[(start_ms, 100_000), (start_ms + 2000, 112_000), (start_ms + 4000, 109_000)], &marks);
+
+        let rows: Vec<(&str, &str, u64, Option<i64>)> = events
+            .iter()
+            .map(|e| {
+                let kind = if e.kind == LibraryEventKind::Load { "load" } else { "unload" };
+                (kind, e.library.as_str(), e.time.elapsed_ms, e.pss_step_kb)
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            [("load", "libfoo.so", 1250, Some(12_000)), ("load", "libbar.so", 2600, Some(-3_000)), ("unload", "libfoo.so", 4000, Some(-3_000))]
+        );
+        // The logged dlopen moved the mapped load to its earlier time.
+        assert!(events[0].source == LibrarySource::Logcat && events[0].path == foo);
+        assert!(events.iter().all(|e| e.after_marker.as_deref() == Some("open camera")));
+        assert_eq!(load_markers(&events).len(), 2);
+    }
+}
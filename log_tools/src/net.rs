@@ -0,0 +1,133 @@
+//! Connectivity control (Wi-Fi, mobile data, airplane mode) and sampling
+//! of the default network from `dumpsys connectivity`.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::clocksync::SampleTime;
+
+static DEFAULT_NETWORK_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"Active default network:\s*(\S+)").unwrap());
+static TRANSPORTS_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"Transports:\s*([A-Z_|]+)").unwrap());
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum NetTarget {
+    Wifi,
+    Data,
+    Airplane,
+}
+
+#[derive(Clone, Serialize)]
+pub struct ConnectivitySample {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    /// Transport of the default network (WIFI, CELLULAR, ...) or "none".
+    pub default_network: String,
+    pub validated: bool,
+}
+
+impl NetTarget {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "wifi" => Some(NetTarget::Wifi),
+            "data" => Some(NetTarget::Data),
+            "airplane" => Some(NetTarget::Airplane),
+            _ => None,
+        }
+    }
+
+    /// Global setting holding the current on/off state.
+    pub fn setting(&self) -> &'static str {
+        match self {
+            NetTarget::Wifi => "wifi_on",
+            NetTarget::Data => "mobile_data",
+            NetTarget::Airplane => "airplane_mode_on",
+        }
+    }
+
+    /// Shell commands switching the target on or off. `cmd connectivity
+    /// airplane-mode` exists from API 28; older releases need the setting
+    /// plus the broadcast apps listen for.
+    pub fn commands(&self, enable: bool, sdk: u32) -> Vec<String> {
+        let verb = if enable { "enable" } else { "disable" };
+        match self {
+            NetTarget::Wifi => vec![format!("svc wifi {}", verb)],
+            NetTarget::Data => vec![format!("svc data {}", verb)],
+            NetTarget::Airplane if sdk >= 28 => vec![format!("cmd connectivity airplane-mode {}", verb)],
+            NetTarget::Airplane => vec![
+                format!("settings put global airplane_mode_on {}", u8::from(enable)),
+                format!("am broadcast -a android.intent.action.AIRPLANE_MODE --ez state {}", enable),
+            ],
+        }
+    }
+}
+
+pub fn parse_connectivity(output: &str, time: SampleTime) -> ConnectivitySample {
+    let default_id = DEFAULT_NETWORK_REGEX
+        .captures(output)
+        .map(|caps| caps[1].to_string())
+        .filter(|id| id != "none");
+    let agent_line = default_id.as_ref().and_then(|id| {
+        let marker = format!("network{{{}}}", id);
+        output.lines().find(|line| line.contains(&marker) && line.contains("Transports:"))
+    });
+    let default_network = match (&default_id, agent_line) {
+        (None, _) => "none".to_string(),
+        (Some(_), Some(line)) => TRANSPORTS_REGEX
+            .captures(line)
+            .map_or("unknown".to_string(), |caps| caps[1].to_string()),
+        (Some(_), None) => "unknown".to_string(),
+    };
+    ConnectivitySample {
+        time,
+        default_network,
+        validated: agent_line.is_some_and(|line| line.contains("VALIDATED")),
+    }
+}
+
+/// Records a sample only when the default network or its validation changed.
+pub fn push_if_changed(samples: &mut Vec<ConnectivitySample>, sample: ConnectivitySample) {
+    let changed = samples
+        .last()
+        .is_none_or(|last| last.default_network != sample.default_network || last.validated != sample.validated);
+    if changed {
+        info!(
+            "[{}s] Default network: {}{}",
+            sample.time.timestamp,
+            sample.default_network,
+            if sample.validated { " (validated)" } else { "" }
+        );
+        samples.push(sample);
+    }
+}
+
+static RSSI_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"RSSI:\s*(-?\d+)").unwrap());
+// Anchored on ", " so newer "Tx Link speed:"/"Rx Link speed:" fields don't match.
+static LINK_SPEED_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r", Link speed:\s*(-?\d+)").unwrap());
+static FREQUENCY_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"Frequency:\s*(-?\d+)").unwrap());
+
+#[derive(Serialize)]
+pub struct WifiSample {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    pub rssi_dbm: i32,
+    pub link_speed_mbps: i32,
+    pub frequency_mhz: i32,
+}
+
+/// Shell command printing the current `mWifiInfo` line of `dumpsys wifi`.
+pub const WIFI_INFO_CMD: &str = "dumpsys wifi | grep mWifiInfo";
+
+/// Parses the first `mWifiInfo` line. Returns `None` when Wi-Fi is not
+/// connected (no line, or the RSSI is the -127 "invalid" placeholder).
+pub fn parse_wifi_info(output: &str, time: SampleTime) -> Option<WifiSample> {
+    let line = output.lines().find(|line| line.contains("mWifiInfo"))?;
+    let value = |re: &Regex| re.captures(line).and_then(|caps| caps[1].parse::<i32>().ok());
+    let rssi_dbm = value(&RSSI_REGEX).filter(|rssi| *rssi > -127)?;
+    Some(WifiSample {
+        time,
+        rssi_dbm,
+        link_speed_mbps: value(&LINK_SPEED_REGEX).unwrap_or(-1),
+        frequency_mhz: value(&FREQUENCY_REGEX).unwrap_or(-1),
+    })
+}
@@ -0,0 +1,57 @@
+//! Notification posting audit from `dumpsys notification --noredact`.
+//! Only posted-and-still-active notifications are listed there, so each
+//! poll records the keys that are new (or re-posted under a new record).
+
+use std::collections::{BTreeMap, HashSet};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::clocksync::SampleTime;
+
+// "NotificationRecord(0x0a1b2c3d: pkg=com.foo user=UserHandle{0} id=1 tag=null
+//  importance=3 key=0|com.foo|1|null|10123: Notification(channel=updates ..."
+static RECORD_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"NotificationRecord\((0x[0-9a-f]+): pkg=(\S+) user=UserHandle\{(-?\d+)\}.*?key=(\S+): Notification\(channel=(\S+)").unwrap()
+});
+
+#[derive(Clone, Serialize)]
+pub struct NotificationEvent {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    pub key: String,
+    pub channel: String,
+}
+
+#[derive(Default)]
+pub struct NotificationTracker {
+    seen: HashSet<String>,
+    pub events: Vec<NotificationEvent>,
+}
+
+impl NotificationTracker {
+    /// Records notifications of `package` (posted by `user`, when set) not
+    /// seen in earlier polls. The record address changes when a key is
+    /// re-posted, so it is part of the identity.
+    pub fn observe(&mut self, output: &str, package: &str, user: Option<u32>, time: SampleTime) {
+        for caps in RECORD_REGEX.captures_iter(output) {
+            if &caps[2] != package || user.is_some_and(|user| caps[3] != *user.to_string()) {
+                continue;
+            }
+            let identity = format!("{}@{}", &caps[4], &caps[1]);
+            if self.seen.insert(identity) {
+                info!("[{}s] Notification posted on channel {} ({})", time.timestamp, &caps[5], &caps[4]);
+                self.events.push(NotificationEvent { time, key: caps[4].to_string(), channel: caps[5].to_string() });
+            }
+        }
+    }
+
+    pub fn counts_by_channel(&self) -> BTreeMap<&str, u64> {
+        let mut counts = BTreeMap::new();
+        for event in &self.events {
+            *counts.entry(event.channel.as_str()).or_default() += 1;
+        }
+        counts
+    }
+}
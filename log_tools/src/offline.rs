@@ -0,0 +1,206 @@
+//! Offline filtering of saved logcat files, plain or compressed. The file
+//! is streamed in newline-aligned chunks and each chunk's lines are
+//! matched in parallel, so memory stays flat for multi-GB captures.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+use anyhow::Result;
+use indicatif::ProgressBar;
+use rayon::prelude::*;
+use regex::bytes::Regex;
+use serde::Serialize;
+
+use crate::progress;
+
+/// Bytes read per chunk before splitting on the last newline.
+pub const CHUNK_BYTES: usize = 8 << 20;
+
+#[derive(Serialize)]
+pub struct OfflineSummary {
+    pub file: String,
+    pub lines: u64,
+    pub matches: u64,
+    pub bytes: u64,
+    pub elapsed_ms: u64,
+}
+
+/// Counts compressed (on-disk) bytes for the progress bar, and notes when
+/// the file has been read to its end.
+struct ProgressReader<R> {
+    inner: R,
+    bar: ProgressBar,
+    at_end: Arc<AtomicBool>,
+}
+
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            self.at_end.store(true, Ordering::Relaxed);
+        }
+        self.bar.inc(n as u64);
+        Ok(n)
+    }
+}
+
+/// A decoder over a file that may lack its trailer: a capture stopped by
+/// Ctrl-C leaves the data up to its last flush decodable but no end of
+/// stream. An error once the whole file has been read ends the input
+/// instead, with a warning.
+struct Unfinished<R> {
+    inner: R,
+    at_end: Arc<AtomicBool>,
+    path: String,
+    ended: bool,
+}
+
+impl<R: Read> Read for Unfinished<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.ended {
+            return Ok(0);
+        }
+        match self.inner.read(buf) {
+            Err(e) if self.at_end.load(Ordering::Relaxed) => {
+                warn!(format!("{} ends in an unfinished compressed stream ({}); read up to there", self.path, e));
+                self.ended = true;
+                Ok(0)
+            }
+            result => result,
+        }
+    }
+}
+
+/// Opens `path`, decompressing `.gz` and `.zst` files (including the
+/// multi-member streams written by `--compress` with `--append`, and
+/// captures interrupted before their trailer was written).
+pub fn open_log(path: &str, bar: ProgressBar) -> Result<Box<dyn Read>> {
+    let at_end = Arc::new(AtomicBool::new(false));
+    let file = BufReader::new(ProgressReader { inner: File::open(path)?, bar, at_end: at_end.clone() });
+    let unfinished = |inner: Box<dyn Read>| -> Box<dyn Read> { Box::new(Unfinished { inner, at_end, path: path.to_string(), ended: false }) };
+    Ok(if path.ends_with(".gz") {
+        unfinished(Box::new(flate2::read::MultiGzDecoder::new(file)))
+    } else if path.ends_with(".zst") {
+        unfinished(Box::new(zstd::Decoder::with_buffer(file)?))
+    } else {
+        Box::new(file)
+    })
+}
+
+pub struct ChunkReader<R> {
+    reader: R,
+    /// Partial last line of the previous chunk.
+    carry: Vec<u8>,
+}
+
+impl<R: Read> ChunkReader<R> {
+    pub fn new(reader: R) -> Self {
+        ChunkReader { reader, carry: Vec::new() }
+    }
+
+    /// The next run of whole lines, or `None` at end of input.
+    pub fn next_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut chunk = std::mem::take(&mut self.carry);
+        let start = chunk.len();
+        chunk.resize(start + CHUNK_BYTES, 0);
+        let mut filled = start;
+        while filled < chunk.len() {
+            match self.reader.read(&mut chunk[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        chunk.truncate(filled);
+        if chunk.is_empty() {
+            return Ok(None);
+        }
+        // Keep a trailing partial line for the next chunk unless at EOF.
+        if filled == start + CHUNK_BYTES {
+            if let Some(last_newline) = chunk.iter().rposition(|b| *b == b'\n') {
+                self.carry = chunk.split_off(last_newline + 1);
+            }
+        }
+        Ok(Some(chunk))
+    }
+}
+
+/// Lines of `chunk` matching `re`, in file order, and the line count.
+pub fn match_chunk<'a>(re: &Regex, chunk: &'a [u8]) -> (Vec<&'a [u8]>, u64) {
+    let chunk = chunk.strip_suffix(b"\n").unwrap_or(chunk);
+    let lines = chunk.iter().filter(|b| **b == b'\n').count() as u64 + 1;
+    let matches = chunk.par_split(|b| *b == b'\n').filter(|line| re.is_match(line)).collect();
+    (matches, lines)
+}
+
+/// Writes the lines of the log at `path` matching `re` to `out`, with a
+/// progress bar over the bytes read.
+pub fn filter_log(path: &str, re: &Regex, out: &mut dyn Write) -> Result<OfflineSummary> {
+    let start = Instant::now();
+    let bytes = std::fs::metadata(path)?.len();
+    let bar = progress::bytes_bar(bytes);
+    let mut chunks = ChunkReader::new(open_log(path, bar.clone())?);
+    let (mut lines, mut matches) = (0, 0);
+    while let Some(chunk) = chunks.next_chunk()? {
+        let (hits, count) = match_chunk(re, &chunk);
+        lines += count;
+        matches += hits.len() as u64;
+        for line in hits {
+            out.write_all(line)?;
+            out.write_all(b"\n")?;
+        }
+    }
+    out.flush()?;
+    bar.finish_and_clear();
+    Ok(OfflineSummary { file: path.to_string(), lines, matches, bytes, elapsed_ms: start.elapsed().as_millis() as u64 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress::{self, Compression};
+
+    fn read_back(name: &str, compression: Compression, finish: bool) -> String {
+        let path = std::env::temp_dir().join(format!("log_tools_{}_{}", std::process::id(), name));
+        let path = compress::output_path(&path.to_string_lossy(), Some(compression));
+        let mut writer = compress::Writer::new(File::create(&path).unwrap(), Some(compression)).unwrap();
+        writer.write_all(b"01-01 00:00:00.000 E/App( 1): one\n01-01 00:00:00.001 E/App( 1): two\n").unwrap();
+        writer.flush().unwrap();
+        if finish {
+            writer.finish().unwrap();
+        } else {
+            // Dropping a zstd encoder would write nothing more; a gzip one would finish.
+            std::mem::forget(writer);
+        }
+        let mut text = String::new();
+        open_log(&path, ProgressBar::hidden()).unwrap().read_to_string(&mut text).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        text
+    }
+
+    #[test]
+    fn reads_finished_and_interrupted_captures() {
+        for (compression, name) in [(Compression::Gzip, "gz"), (Compression::Zstd, "zst")] {
+            for finish in [true, false] {
+                let text = read_back(&format!("{}_{}", name, finish), compression, finish);
+                assert_eq!(text.lines().count(), 2, "{} finished={}", name, finish);
+                assert!(text.ends_with("two\n"));
+            }
+        }
+    }
+
+    #[test]
+    fn filters_a_plain_capture() {
+        let path = std::env::temp_dir().join(format!("log_tools_{}_filter.log", std::process::id()));
+        std::fs::write(&path, "03-10 15:00:00.000 I/ActivityManager( 612): Start proc 4242:com.example.app/u0a57\n\
+03-10 15:00:00.120 E/AndroidRuntime( 4242): FATAL EXCEPTION: main\n\
+03-10 15:00:00.121 E/AndroidRuntime( 4242): java.lang.IllegalStateException: boom\n").unwrap();
+        let mut out = Vec::new();
+        let summary = filter_log(&path.to_string_lossy(), &Regex::new("AndroidRuntime").unwrap(), &mut out).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((summary.lines, summary.matches), (3, 2));
+        assert_eq!(String::from_utf8(out).unwrap().lines().last(), Some("03-10 15:00:00.121 E/AndroidRuntime( 4242): java.lang.IllegalStateException: boom"));
+    }
+}
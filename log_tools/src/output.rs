@@ -0,0 +1,77 @@
+//! Machine-readable output for `--json`. Each result is one JSON document
+//! per line on stdout, wrapped in a versioned envelope:
+//!
+//! ```json
+//! {"schema_version":1,"command":"threads","data":[...]}
+//! ```
+//!
+//! Most commands print one document when they finish. `logcat` (and the
+//! logcat side of `session`) instead streams one `"command":"logcat"`
+//! document per matching line as it arrives, with `data` a
+//! [`LogcatMatch`]:
+//!
+//! ```json
+//! {"schema_version":1,"command":"logcat","data":{"line":"10-16 13:50:08.123 E/App( 123): boom","host_time_ms":1792158608123}}
+//! ```
+//!
+//! The stream ends with the session; lines dropped by the footprint
+//! guard (see `guard`) are not emitted.
+//!
+//! Human-readable text moves to stderr in this mode (see `info!`). It is
+//! only printed at all once the CLI turns the console on; library callers
+//! get results back and nothing on their terminal.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+use serde::Serialize;
+
+/// Bumped whenever the shape of any `data` payload changes incompatibly.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+static CONSOLE_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize)]
+struct Envelope<'a, T: Serialize> {
+    schema_version: u32,
+    command: &'a str,
+    data: &'a T,
+}
+
+/// One matching logcat line in the `logcat` stream.
+#[derive(Serialize)]
+pub struct LogcatMatch<'a> {
+    /// The line as logcat printed it, without the trailing newline.
+    pub line: &'a str,
+    /// Host wall-clock time of the line in Unix milliseconds, from its
+    /// device timestamp and the clock offset; `null` when the line has
+    /// no timestamp.
+    pub host_time_ms: Option<i64>,
+}
+
+pub fn set_json_output(enabled: bool) {
+    JSON_OUTPUT.store(enabled, Ordering::Relaxed);
+}
+
+pub fn json_output() -> bool {
+    JSON_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Turns on progress messages, warnings and progress bars.
+pub fn set_console_output(enabled: bool) {
+    CONSOLE_OUTPUT.store(enabled, Ordering::Relaxed);
+}
+
+pub fn console_output() -> bool {
+    CONSOLE_OUTPUT.load(Ordering::Relaxed)
+}
+
+/// Prints `data` as the result of `command` when `--json` is active.
+pub fn emit<T: Serialize>(command: &str, data: &T) -> Result<()> {
+    if json_output() {
+        let envelope = Envelope { schema_version: JSON_SCHEMA_VERSION, command, data };
+        println!("{}", serde_json::to_string(&envelope)?);
+    }
+    Ok(())
+}
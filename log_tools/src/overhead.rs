@@ -0,0 +1,137 @@
+//! Self-measurement of memory sessions: how long each adb read of a sample
+//! took and how far the real sampling interval strayed from the requested
+//! one. Slow reads mean the tool itself keeps the device busy (dumpsys
+//! meminfo alone can take hundreds of milliseconds), and a stretched
+//! interval skews every per-second rate.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::stats;
+
+/// Read time above this share of the interval at p95 is worth a warning.
+const OVERHEAD_WARN_PERCENT: f64 = 25.0;
+/// Interval deviation above this share of the interval at p95 is worth a
+/// warning.
+const JITTER_WARN_PERCENT: f64 = 50.0;
+
+/// Reads recorded since the last `take`, `None` when not recording.
+static READS: Mutex<Option<Vec<(String, f64)>>> = Mutex::new(None);
+
+#[derive(Serialize)]
+pub struct SampleTiming {
+    pub timestamp: u64,
+    /// Since the previous sample; absent for the first sample and the
+    /// first one after a pause.
+    pub interval_ms: Option<f64>,
+    pub read_ms: f64,
+    /// Milliseconds per command, e.g. "dumpsys meminfo".
+    pub reads: BTreeMap<String, f64>,
+}
+
+#[derive(Serialize)]
+pub struct CommandTiming {
+    pub command: String,
+    pub count: usize,
+    pub p95_ms: f64,
+}
+
+#[derive(Serialize)]
+pub struct OverheadSummary {
+    pub samples: usize,
+    pub requested_interval_ms: f64,
+    pub read_p50_ms: f64,
+    pub read_p95_ms: f64,
+    pub overhead_p95_percent: f64,
+    pub jitter_p95_ms: f64,
+    /// Slowest first.
+    pub commands: Vec<CommandTiming>,
+}
+
+/// Starts recording the reads passed to `record`.
+pub fn start() {
+    *READS.lock().unwrap() = Some(Vec::new());
+}
+
+pub fn stop() {
+    *READS.lock().unwrap() = None;
+}
+
+/// Records a read of `args` that took `elapsed`, when recording.
+pub fn record<S: AsRef<std::ffi::OsStr>>(args: &[S], elapsed: Duration) {
+    if let Some(reads) = READS.lock().unwrap().as_mut() {
+        reads.push((command_label(args), elapsed.as_secs_f64() * 1000.0));
+    }
+}
+
+/// The timing of the sample taken at `timestamp` from the reads recorded
+/// since the last call.
+pub fn take_sample(timestamp: u64, interval: Option<Duration>) -> SampleTiming {
+    let reads = READS.lock().unwrap().as_mut().map(std::mem::take).unwrap_or_default();
+    let mut by_command = BTreeMap::new();
+    for (command, ms) in reads {
+        *by_command.entry(command).or_insert(0.0) += ms;
+    }
+    SampleTiming {
+        timestamp,
+        interval_ms: interval.map(|i| i.as_secs_f64() * 1000.0),
+        read_ms: by_command.values().sum(),
+        reads: by_command,
+    }
+}
+
+/// The first two words of a command, enough to tell dumpsys services and
+/// proc files apart.
+fn command_label<S: AsRef<std::ffi::OsStr>>(args: &[S]) -> String {
+    let joined: Vec<String> = args.iter().map(|a| a.as_ref().to_string_lossy().into_owned()).collect();
+    joined.join(" ").split_whitespace().take(2).collect::<Vec<_>>().join(" ")
+}
+
+pub fn summarize(timings: &[SampleTiming], interval: Duration) -> OverheadSummary {
+    let requested_ms = interval.as_secs_f64() * 1000.0;
+    let read_ms: Vec<f64> = timings.iter().map(|t| t.read_ms).collect();
+    let jitter_ms: Vec<f64> = timings.iter().filter_map(|t| t.interval_ms).map(|ms| (ms - requested_ms).abs()).collect();
+    let mut per_command: BTreeMap<&str, Vec<f64>> = BTreeMap::new();
+    for timing in timings {
+        for (command, ms) in &timing.reads {
+            per_command.entry(command).or_default().push(*ms);
+        }
+    }
+    let mut commands: Vec<CommandTiming> = per_command
+        .into_iter()
+        .map(|(command, ms)| CommandTiming { command: command.to_string(), count: ms.len(), p95_ms: stats::percentile(&ms, 95.0) })
+        .collect();
+    commands.sort_by(|a, b| b.p95_ms.total_cmp(&a.p95_ms));
+    let read_p95_ms = stats::percentile(&read_ms, 95.0);
+    OverheadSummary {
+        samples: timings.len(),
+        requested_interval_ms: requested_ms,
+        read_p50_ms: stats::percentile(&read_ms, 50.0),
+        read_p95_ms,
+        overhead_p95_percent: if requested_ms > 0.0 { read_p95_ms / requested_ms * 100.0 } else { 0.0 },
+        jitter_p95_ms: stats::percentile(&jitter_ms, 95.0),
+        commands,
+    }
+}
+
+/// Why the measurement may be distorting the results, if it may.
+pub fn distortion_warnings(summary: &OverheadSummary) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if summary.overhead_p95_percent > OVERHEAD_WARN_PERCENT {
+        let slowest = summary.commands.first().map_or(String::new(), |c| format!(", mostly {} at {:.0} ms", c.command, c.p95_ms));
+        warnings.push(format!(
+            "Sampling reads take {:.0} ms at p95, {:.0}% of the {:.0} ms interval{}; the tool itself may be loading the device, consider a longer sample_interval or fewer collectors",
+            summary.read_p95_ms, summary.overhead_p95_percent, summary.requested_interval_ms, slowest
+        ));
+    }
+    if summary.jitter_p95_ms > summary.requested_interval_ms * JITTER_WARN_PERCENT / 100.0 {
+        warnings.push(format!(
+            "Samples are {:.0} ms off the requested {:.0} ms interval at p95; per-second rates and timings between samples are skewed",
+            summary.jitter_p95_ms, summary.requested_interval_ms
+        ));
+    }
+    warnings
+}
@@ -0,0 +1,103 @@
+//! Ownership of native libraries, from a mapping file of `glob = owner`
+//! lines:
+//!
+//! ```text
+//! # first match wins
+//! libffmpeg*.so = MediaTeam
+//! libflutter.so = Vendor:Flutter
+//! ```
+//!
+//! Patterns without a `/` are matched against the library file name,
+//! others against the full path.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+
+use crate::{SoMemoryInfo, console};
+
+pub const UNOWNED: &str = "Unowned";
+
+pub struct OwnerMap {
+    rules: Vec<(String, String)>,
+}
+
+#[derive(Default, Serialize)]
+pub struct OwnerTotal {
+    pub owner: String,
+    pub libraries: usize,
+    pub pss: u64,
+    pub private_dirty: u64,
+    pub shared_dirty: u64,
+}
+
+pub fn load_owner_map(path: &str) -> Result<OwnerMap> {
+    let mut rules = Vec::new();
+    for (n, line) in console::read_text(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (pattern, owner) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("{}:{}: expected 'glob = owner'", path, n + 1))?;
+        rules.push((pattern.trim().to_string(), owner.trim().to_string()));
+    }
+    Ok(OwnerMap { rules })
+}
+
+impl OwnerMap {
+    pub fn owner_of(&self, library: &str) -> &str {
+        self.rules
+            .iter()
+            .find(|(pattern, _)| library_match(pattern, library))
+            .map_or(UNOWNED, |(_, owner)| owner.as_str())
+    }
+
+    /// Sums the libraries per owner, largest PSS first.
+    pub fn totals(&self, libs: &[SoMemoryInfo]) -> Vec<OwnerTotal> {
+        let mut by_owner: BTreeMap<&str, OwnerTotal> = BTreeMap::new();
+        for lib in libs {
+            let owner = self.owner_of(&lib.name);
+            let total = by_owner.entry(owner).or_insert_with(|| OwnerTotal { owner: owner.to_string(), ..Default::default() });
+            total.libraries += 1;
+            total.pss += lib.pss;
+            total.private_dirty += lib.private_dirty;
+            total.shared_dirty += lib.shared_dirty;
+        }
+        let mut totals: Vec<OwnerTotal> = by_owner.into_values().collect();
+        totals.sort_by_key(|t| std::cmp::Reverse(t.pss));
+        totals
+    }
+}
+
+/// Matches a library path the way mapping files do: patterns without a
+/// `/` against its file name, others against the full path.
+pub fn library_match(pattern: &str, library: &str) -> bool {
+    let file_name = library.rsplit('/').next().unwrap_or(library);
+    glob_match(pattern, if pattern.contains('/') { library } else { file_name })
+}
+
+/// `*` matches any run of characters, `?` exactly one.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let (p, t): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut pi, mut ti) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            backtrack = Some((pi, ti));
+            pi += 1;
+        } else if let Some((star, matched)) = backtrack {
+            pi = star + 1;
+            ti = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
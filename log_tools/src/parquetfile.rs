@@ -0,0 +1,90 @@
+//! `--parquet`: Parquet copies of the memory sample, thread and `.so`
+//! CSV files, with the same columns, for runs of several hours whose CSVs
+//! load slowly into pandas or polars. Built with the `parquet` feature
+//! only, as it pulls in the Arrow libraries.
+
+use std::fs::File;
+use std::sync::Arc;
+
+use anyhow::Result;
+use arrow_array::{ArrayRef, Float64Array, Int64Array, RecordBatch, StringArray, UInt64Array};
+use arrow_schema::{Field, Schema};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use crate::{MemorySample, SoMemoryInfo, ThreadInfo, compress};
+
+/// The Parquet file next to the CSV `csv_path`.
+pub fn parquet_path(csv_path: &str) -> String {
+    let csv_path = compress::plain_name(csv_path);
+    format!("{}.parquet", csv_path.strip_suffix(".csv").unwrap_or(csv_path))
+}
+
+fn write(path: &str, columns: Vec<(&str, ArrayRef)>) -> Result<()> {
+    let fields: Vec<Field> = columns.iter().map(|(name, array)| Field::new(*name, array.data_type().clone(), array.null_count() > 0)).collect();
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns.into_iter().map(|(_, array)| array).collect())?;
+    let props = WriterProperties::builder().set_compression(Compression::SNAPPY).build();
+    let mut writer = ArrowWriter::try_new(File::create(path)?, batch.schema(), Some(props))?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+fn u64s(values: impl Iterator<Item = u64>) -> ArrayRef {
+    Arc::new(values.collect::<UInt64Array>())
+}
+
+fn strings<'a>(values: impl Iterator<Item = &'a str>) -> ArrayRef {
+    Arc::new(values.map(Some).collect::<StringArray>())
+}
+
+/// `labels` holds the markers column of each sample, as in the CSV.
+pub fn write_memory_samples(path: &str, samples: &[MemorySample], derived: &[String], labels: &[String]) -> Result<()> {
+    let mut columns: Vec<(&str, ArrayRef)> = vec![
+        ("timestamp", u64s(samples.iter().map(|s| s.time.timestamp))),
+        ("elapsed_ms", u64s(samples.iter().map(|s| s.time.elapsed_ms))),
+        ("host_time_ms", Arc::new(samples.iter().map(|s| s.time.host_time_ms).collect::<Int64Array>())),
+        ("device_time_ms", Arc::new(samples.iter().map(|s| s.time.device_time_ms).collect::<Int64Array>())),
+        ("total_pss", u64s(samples.iter().map(|s| s.total_pss))),
+        ("native_heap", u64s(samples.iter().map(|s| s.native_heap))),
+        ("dalvik_heap", u64s(samples.iter().map(|s| s.dalvik_heap))),
+        ("code", u64s(samples.iter().map(|s| s.code))),
+        ("stack", u64s(samples.iter().map(|s| s.stack))),
+        ("graphics", u64s(samples.iter().map(|s| s.graphics))),
+        ("private_dirty", u64s(samples.iter().map(|s| s.private_dirty))),
+        ("shared_dirty", u64s(samples.iter().map(|s| s.shared_dirty))),
+    ];
+    for name in derived {
+        // Null where a derived metric has no value.
+        columns.push((name, Arc::new(samples.iter().map(|s| s.derived.get(name).copied()).collect::<Float64Array>())));
+    }
+    columns.push(("markers", strings(labels.iter().map(String::as_str))));
+    write(path, columns)
+}
+
+pub fn write_threads(path: &str, threads: &[ThreadInfo]) -> Result<()> {
+    write(
+        path,
+        vec![
+            ("tid", strings(threads.iter().map(|t| t.tid.as_str()))),
+            ("name", strings(threads.iter().map(|t| t.name.as_str()))),
+            ("state", strings(threads.iter().map(|t| t.state.as_str()))),
+            ("priority", strings(threads.iter().map(|t| t.priority.as_str()))),
+            ("user_time", strings(threads.iter().map(|t| t.user_time.as_str()))),
+            ("system_time", strings(threads.iter().map(|t| t.system_time.as_str()))),
+        ],
+    )
+}
+
+pub fn write_so_memory(path: &str, so_libs: &[SoMemoryInfo]) -> Result<()> {
+    write(
+        path,
+        vec![
+            ("name", strings(so_libs.iter().map(|so| so.name.as_str()))),
+            ("pss", u64s(so_libs.iter().map(|so| so.pss))),
+            ("private_dirty", u64s(so_libs.iter().map(|so| so.private_dirty))),
+            ("shared_dirty", u64s(so_libs.iter().map(|so| so.shared_dirty))),
+        ],
+    )
+}
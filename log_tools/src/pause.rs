@@ -0,0 +1,113 @@
+//! Pausing a memory session, e.g. while the device is set up by hand:
+//! `pause` and `resume` typed on the terminal or sent to the control
+//! socket. No samples are taken while paused and the paused time does not
+//! count towards the session duration. Each gap is kept as an interval and
+//! as a pair of "paused"/"resumed" markers, so plots and CSVs show it.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::markers;
+
+/// How often a paused session checks for `resume`.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+/// Pauses or resumes sampling; false when it already was in that state.
+pub fn set_paused(paused: bool) -> bool {
+    PAUSED.swap(paused, Ordering::Relaxed) != paused
+}
+
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+/// Session seconds, as in the sample timestamps.
+#[derive(Clone, Serialize)]
+pub struct PausedInterval {
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Follows the pause flag from a sampling loop.
+#[derive(Default)]
+pub struct PauseTracker {
+    since: Option<(u64, Instant)>,
+    paused_for: Duration,
+    pub intervals: Vec<PausedInterval>,
+}
+
+impl PauseTracker {
+    /// Whether sampling is paused at session second `timestamp`, opening or
+    /// closing a gap when the flag changed since the last call.
+    pub fn update(&mut self, timestamp: u64) -> bool {
+        match (is_paused(), self.since) {
+            (true, None) => {
+                self.since = Some((timestamp, Instant::now()));
+                info!("Sampling paused until resume");
+                record("paused");
+                true
+            }
+            (false, Some((start, at))) => {
+                self.since = None;
+                self.paused_for += at.elapsed();
+                self.intervals.push(PausedInterval { start, end: timestamp });
+                info!("Sampling resumed after {}s", timestamp - start);
+                record("resumed");
+                false
+            }
+            (paused, _) => paused,
+        }
+    }
+
+    /// Time spent paused so far, including a pause still going on.
+    pub fn paused_for(&self) -> Duration {
+        self.paused_for + self.since.map_or(Duration::ZERO, |(_, at)| at.elapsed())
+    }
+
+    /// The gaps of the session, one still open closed at `end`.
+    pub fn finish(mut self, end: u64) -> Vec<PausedInterval> {
+        if let Some((start, _)) = self.since.take() {
+            self.intervals.push(PausedInterval { start, end });
+        }
+        self.intervals
+    }
+}
+
+/// `points` cut where a pause falls between two of them, so plots leave
+/// the paused time blank instead of drawing a line across it.
+pub fn split_at_pauses(points: Vec<(f64, f64)>, paused: &[PausedInterval]) -> Vec<Vec<(f64, f64)>> {
+    let mut runs: Vec<Vec<(f64, f64)>> = Vec::new();
+    for point in points {
+        let gap = |last: &(f64, f64)| paused.iter().any(|p| last.0 < p.end as f64 && p.end as f64 <= point.0);
+        match runs.last_mut() {
+            Some(run) if !run.last().is_some_and(gap) => run.push(point),
+            _ => runs.push(vec![point]),
+        }
+    }
+    runs
+}
+
+fn record(label: &str) {
+    if let Err(e) = markers::record_marker(label) {
+        warn!(format!("Could not record marker {:?}: {}", label, e));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn series_break_at_pauses_only() {
+        let points = vec![(8.9, 1.0), (10.2, 2.0), (25.1, 3.0), (26.0, 4.0)];
+        // The pause was noticed at 10.9s, after the sample taken at 10.2s.
+        let runs = split_at_pauses(points.clone(), &[PausedInterval { start: 10, end: 25 }]);
+        assert_eq!(runs, vec![vec![(8.9, 1.0), (10.2, 2.0)], vec![(25.1, 3.0), (26.0, 4.0)]]);
+        assert_eq!(split_at_pauses(points.clone(), &[]), vec![points]);
+        assert!(split_at_pauses(Vec::new(), &[]).is_empty());
+    }
+}
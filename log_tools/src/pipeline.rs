@@ -0,0 +1,261 @@
+//! Parallel logcat filtering: a reader thread batches adb output, a worker
+//! pool decodes and matches the batches, and the consumer gets the kept
+//! lines back in device order. The queue to the workers is bounded; when it
+//! is full the reader drops the batch instead of falling behind the device,
+//! and the dropped lines are counted.
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, BufReader, Read};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use regex::Regex;
+
+use crate::{LogAnalyzerConfig, addresses, appmetrics, console, leakcanary, queries, selinux, stability, webview};
+use crate::filters::TagFilter;
+use crate::uichurn::UiChurn;
+
+/// Upper bound on lines per batch; a batch is also sent as soon as no more
+/// output is immediately available, so quiet logs are not delayed.
+pub const BATCH_LINES: usize = 512;
+/// Batches waiting for a worker before the reader starts dropping.
+pub const QUEUE_BATCHES: usize = 64;
+/// Minimum time between two drop warnings.
+const DROP_WARNING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Raw lines with the sequence number of their batch.
+type Batch = (u64, Vec<Vec<u8>>);
+/// Kept lines of one batch.
+type Filtered = (u64, Vec<FilteredLine>);
+
+/// Produces logcat lines for the reader thread.
+pub trait LineSource {
+    /// The next line including its newline, or None at the end of input.
+    fn next_line(&mut self) -> io::Result<Option<Vec<u8>>>;
+    /// Whether more input is already buffered, i.e. the current batch can
+    /// grow without waiting on the device.
+    fn has_buffered(&self) -> bool;
+}
+
+impl<R: Read> LineSource for BufReader<R> {
+    fn next_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut line = Vec::new();
+        Ok((self.read_until(b'\n', &mut line)? > 0).then_some(line))
+    }
+
+    fn has_buffered(&self) -> bool {
+        !self.buffer().is_empty()
+    }
+}
+
+#[derive(Clone)]
+pub struct FilterOptions {
+    pub regex: Regex,
+    /// Also keep unmatched lines that should trigger an activity stack
+    /// snapshot, and the rest of native crash dumps.
+    pub crash_triggers: bool,
+    /// Also keep unmatched SELinux denials.
+    pub selinux: bool,
+    pub ui_churn: bool,
+    /// Also keep unmatched SQLite slow query logs.
+    pub slow_queries: bool,
+    /// Presets a match must also get past.
+    pub tags: Arc<TagFilter>,
+}
+
+impl FilterOptions {
+    /// The filtering a capture with `config` does.
+    pub fn from_config(config: &LogAnalyzerConfig) -> Result<FilterOptions> {
+        Ok(FilterOptions {
+            regex: Regex::new(&config.keyword_regex)?,
+            crash_triggers: config.stack_snapshots,
+            selinux: config.selinux,
+            ui_churn: config.ui_churn,
+            slow_queries: config.slow_queries.is_some(),
+            tags: Arc::new(TagFilter::load(&config.filter_presets, &config.presets)?),
+        })
+    }
+}
+
+/// A line kept by the workers. `raw` is only filled for matches, which are
+/// written to the output file byte for byte.
+pub struct FilteredLine {
+    pub text: String,
+    pub raw: Vec<u8>,
+    pub matched: bool,
+}
+
+/// "crash", "native crash" or "anr" when the line starts one of those
+/// reports.
+pub fn crash_trigger(line: &str) -> Option<&'static str> {
+    if line.contains("FATAL EXCEPTION") {
+        Some("crash")
+    } else if line.contains("Fatal signal") {
+        Some("native crash")
+    } else if line.contains("ANR in ") {
+        Some("anr")
+    } else {
+        None
+    }
+}
+
+/// What a finished pipeline leaves behind.
+pub struct Finished {
+    pub churn: UiChurn,
+    pub dropped: u64,
+    /// Last line read from the input, kept or not.
+    pub last_line: Option<String>,
+}
+
+pub struct Pipeline {
+    results: Receiver<Filtered>,
+    pending: BTreeMap<u64, Vec<FilteredLine>>,
+    next: u64,
+    dropped: Arc<AtomicU64>,
+    reported: u64,
+    last_warning: Option<Instant>,
+    reader: JoinHandle<io::Result<Option<Vec<u8>>>>,
+    workers: Vec<JoinHandle<UiChurn>>,
+}
+
+impl Pipeline {
+    pub fn start<S: LineSource + Send + 'static>(source: S, options: FilterOptions) -> Pipeline {
+        let (batch_tx, batch_rx) = mpsc::sync_channel(QUEUE_BATCHES);
+        let (result_tx, results) = mpsc::sync_channel(QUEUE_BATCHES);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let reader = {
+            let dropped = dropped.clone();
+            thread::spawn(move || read_batches(source, batch_tx, &dropped))
+        };
+        let batch_rx = Arc::new(Mutex::new(batch_rx));
+        let options = Arc::new(options);
+        let workers = (0..worker_count())
+            .map(|_| {
+                let (batch_rx, result_tx, options) = (batch_rx.clone(), result_tx.clone(), options.clone());
+                thread::spawn(move || filter_batches(&batch_rx, &result_tx, &options))
+            })
+            .collect();
+        Pipeline { results, pending: BTreeMap::new(), next: 0, dropped, reported: 0, last_warning: None, reader, workers }
+    }
+
+    /// Kept lines of the next batch in device order, or None once the input
+    /// is exhausted.
+    pub fn next_batch(&mut self) -> Option<Vec<FilteredLine>> {
+        loop {
+            if let Some(batch) = self.pending.remove(&self.next) {
+                self.next += 1;
+                return Some(batch);
+            }
+            let (seq, batch) = self.results.recv().ok()?;
+            self.pending.insert(seq, batch);
+        }
+    }
+
+    /// Total dropped lines when more were dropped since the last call that
+    /// returned Some, at most once per second.
+    pub fn new_drops(&mut self) -> Option<u64> {
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        if dropped == self.reported || self.last_warning.is_some_and(|at| at.elapsed() < DROP_WARNING_INTERVAL) {
+            return None;
+        }
+        self.reported = dropped;
+        self.last_warning = Some(Instant::now());
+        Some(dropped)
+    }
+
+    /// Waits for the threads and merges the UI churn counters of the workers.
+    pub fn finish(self) -> Result<Finished> {
+        drop(self.results);
+        let last_line = self.reader.join().map_err(|_| anyhow!("logcat reader thread panicked"))??;
+        let mut churn = UiChurn::default();
+        for worker in self.workers {
+            churn.merge(worker.join().map_err(|_| anyhow!("logcat filter thread panicked"))?);
+        }
+        let last_line = last_line.map(|line| console::decode(&line));
+        Ok(Finished { churn, dropped: self.dropped.load(Ordering::Relaxed), last_line })
+    }
+}
+
+/// Returns the last line read.
+fn read_batches<S: LineSource>(mut source: S, batches: SyncSender<Batch>, dropped: &AtomicU64) -> io::Result<Option<Vec<u8>>> {
+    let mut batch = Vec::new();
+    let mut last = None;
+    let mut seq = 0;
+    loop {
+        let line = source.next_line()?;
+        let eof = line.is_none();
+        batch.extend(line);
+        let flush = eof || batch.len() >= BATCH_LINES || !source.has_buffered();
+        if flush && !batch.is_empty() {
+            last = batch.last().cloned();
+            match batches.try_send((seq, std::mem::take(&mut batch))) {
+                Ok(()) => seq += 1,
+                Err(TrySendError::Full((_, lines))) => {
+                    dropped.fetch_add(lines.len() as u64, Ordering::Relaxed);
+                }
+                Err(TrySendError::Disconnected(_)) => return Ok(last),
+            }
+        }
+        if eof {
+            return Ok(last);
+        }
+    }
+}
+
+/// Workers filtering in parallel; the reader and the consumer each keep
+/// a core busy too.
+pub fn worker_count() -> usize {
+    thread::available_parallelism().map_or(2, |n| n.get().saturating_sub(2)).max(1)
+}
+
+/// Whether a worker keeps `text`, and if so whether it is a match of the
+/// keyword regex and presets rather than a line another collector needs.
+pub fn keep(options: &FilterOptions, text: &str) -> Option<bool> {
+    if options.regex.is_match(text) && options.tags.allows(text) {
+        Some(true)
+    } else if (options.crash_triggers && (crash_trigger(text).is_some() || addresses::is_native_crash_line(text)))
+        || (options.selinux && selinux::is_denial(text))
+        || (options.slow_queries && queries::is_slow_query_log(text))
+        || appmetrics::is_metric(text)
+        || webview::is_renderer_crash(text)
+        || leakcanary::is_leakcanary_line(text)
+        || stability::is_report_line(text)
+    {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+fn filter_batches(
+    batches: &Mutex<Receiver<Batch>>,
+    results: &SyncSender<Filtered>,
+    options: &FilterOptions,
+) -> UiChurn {
+    let mut churn = UiChurn::default();
+    loop {
+        let Ok((seq, lines)) = batches.lock().unwrap().recv() else {
+            return churn;
+        };
+        let mut kept = Vec::new();
+        for raw in lines {
+            let text = console::decode(&raw);
+            if options.ui_churn {
+                churn.observe(&text);
+            }
+            match keep(options, &text) {
+                Some(true) => kept.push(FilteredLine { text, raw, matched: true }),
+                Some(false) => kept.push(FilteredLine { text, raw: Vec::new(), matched: false }),
+                None => {}
+            }
+        }
+        // Empty batches still go through to keep the sequence contiguous.
+        if results.send((seq, kept)).is_err() {
+            return churn;
+        }
+    }
+}
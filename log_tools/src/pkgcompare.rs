@@ -0,0 +1,92 @@
+//! `--compare-package <PKG>`: a second app sampled in the same memory
+//! session, e.g. a competitor or the previous version installed under
+//! another applicationId. Each of its samples is read right after the main
+//! app's and carries the same time stamps, so the two series line up
+//! sample for sample in the comparison chart and CSV.
+//!
+//! Samples in a row are not independent, so the session is summarized
+//! as one run per app (mean, median and peak) without a significance
+//! test; `compare` over the memory_samples files of several sessions
+//! tests a difference across runs.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::MemorySample;
+use crate::stats;
+
+pub const COMPARISON_PLOT_FILE: &str = "package_comparison_plot.png";
+
+/// Compared sample fields and their chart titles.
+pub const METRICS: &[(&str, &str)] =
+    &[("total_pss", "Total PSS"), ("native_heap", "Native Heap"), ("dalvik_heap", "Dalvik Heap"), ("graphics", "Graphics")];
+
+/// `dumpsys meminfo` output for a package without a running process.
+pub fn is_not_running(meminfo: &str) -> bool {
+    meminfo.contains("No process found")
+}
+
+pub fn value(sample: &MemorySample, metric: &str) -> u64 {
+    match metric {
+        "total_pss" => sample.total_pss,
+        "native_heap" => sample.native_heap,
+        "dalvik_heap" => sample.dalvik_heap,
+        _ => sample.graphics,
+    }
+}
+
+/// Sample pairs taken at the same time; samples the other app has no
+/// counterpart for are left out.
+pub fn aligned<'a>(main: &'a [MemorySample], other: &'a [MemorySample]) -> Vec<(&'a MemorySample, &'a MemorySample)> {
+    let by_time: BTreeMap<i64, &MemorySample> = other.iter().map(|s| (s.time.host_time_ms, s)).collect();
+    main.iter().filter_map(|s| by_time.get(&s.time.host_time_ms).map(|o| (s, *o))).collect()
+}
+
+/// B relative to A for one metric over one session.
+#[derive(Serialize)]
+pub struct MetricDifference {
+    pub mean_a: f64,
+    pub mean_b: f64,
+    pub median_a: f64,
+    pub median_b: f64,
+    pub peak_a: f64,
+    pub peak_b: f64,
+    /// `mean_b - mean_a`.
+    pub delta: f64,
+    pub delta_percent: f64,
+}
+
+#[derive(Serialize)]
+pub struct PackageComparison<'a> {
+    /// A, then B.
+    pub packages: [&'a str; 2],
+    pub samples_compared: usize,
+    pub comparison: BTreeMap<&'static str, MetricDifference>,
+    /// Samples of package B; package A's are in its memory_samples file.
+    pub samples: &'a [MemorySample],
+}
+
+/// Summary of each metric over the aligned samples, A being `main`.
+pub fn compare(pairs: &[(&MemorySample, &MemorySample)]) -> BTreeMap<&'static str, MetricDifference> {
+    METRICS
+        .iter()
+        .map(|(metric, _)| {
+            let a: Vec<f64> = pairs.iter().map(|(a, _)| value(a, metric) as f64).collect();
+            let b: Vec<f64> = pairs.iter().map(|(_, b)| value(b, metric) as f64).collect();
+            let (mean_a, mean_b) = (stats::mean(&a), stats::mean(&b));
+            let delta = mean_b - mean_a;
+            let difference = MetricDifference {
+                mean_a,
+                mean_b,
+                median_a: stats::median(&a),
+                median_b: stats::median(&b),
+                peak_a: a.iter().copied().fold(0.0, f64::max),
+                peak_b: b.iter().copied().fold(0.0, f64::max),
+                delta,
+                delta_percent: if mean_a != 0.0 { delta / mean_a * 100.0 } else { 0.0 },
+            };
+            (*metric, difference)
+        })
+        .collect()
+}
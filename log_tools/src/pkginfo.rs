@@ -0,0 +1,356 @@
+//! `pkg info`: what an installed build declares, from `dumpsys package`:
+//! versions, SDK levels, permissions and components. Measurement sessions
+//! save it next to the device environment, and `pkg diff` lists what
+//! changed between two builds for a release audit.
+//!
+//! dumpsys only names components reachable through an intent filter (plus
+//! every content provider), and those are what is listed as exported;
+//! components without a filter are not visible here.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::{Result, anyhow};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+pub const PACKAGE_INFO_STEM: &str = "package_info";
+
+// "        5f1c2d0 com.example.app/.MainActivity filter 8e3a1b"
+static COMPONENT_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s*[0-9a-f]+ (\S+/\S+)( filter [0-9a-f]+)?\s*$").unwrap());
+// "  Permission [com.example.app.permission.C2D_MESSAGE] (a1b2c3):"
+static PERMISSION_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s*Permission \[(.+?)\]").unwrap());
+// "  Package [com.example.app] (4b2e9f1):"
+static PACKAGE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s*Package \[(.+?)\]").unwrap());
+// "  com.example.app/.data.Provider:" under "Registered ContentProviders:"
+static PROVIDER_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s{2}(\S+/\S+):\s*$").unwrap());
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct PackageInfo {
+    pub package: String,
+    pub version_name: Option<String>,
+    pub version_code: Option<String>,
+    pub min_sdk: Option<u32>,
+    pub target_sdk: Option<u32>,
+    /// Permissions the app defines for others to hold.
+    pub declared_permissions: BTreeSet<String>,
+    pub requested_permissions: BTreeSet<String>,
+    /// Requested permissions granted to the app, install time or runtime.
+    pub granted_permissions: BTreeSet<String>,
+    pub activities: BTreeSet<String>,
+    pub services: BTreeSet<String>,
+    pub receivers: BTreeSet<String>,
+    pub providers: BTreeSet<String>,
+    /// Components of the app other apps can reach: those with an intent
+    /// filter, and content providers.
+    pub exported: BTreeSet<String>,
+}
+
+impl PackageInfo {
+    fn sets(&self) -> [(&'static str, &BTreeSet<String>); 8] {
+        [
+            ("declared permission", &self.declared_permissions),
+            ("requested permission", &self.requested_permissions),
+            ("granted permission", &self.granted_permissions),
+            ("activity", &self.activities),
+            ("service", &self.services),
+            ("receiver", &self.receivers),
+            ("provider", &self.providers),
+            ("exported", &self.exported),
+        ]
+    }
+
+    fn fields(&self) -> [(&'static str, Option<String>); 4] {
+        [
+            ("version name", self.version_name.clone()),
+            ("version code", self.version_code.clone()),
+            ("min SDK", self.min_sdk.map(|sdk| sdk.to_string())),
+            ("target SDK", self.target_sdk.map(|sdk| sdk.to_string())),
+        ]
+    }
+}
+
+/// Sections of `dumpsys package` output, by their unindented headers.
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    Other,
+    Activities,
+    Services,
+    Receivers,
+    Providers,
+    Permissions,
+    Package,
+}
+
+impl Section {
+    fn from_header(line: &str) -> Option<Section> {
+        if line.starts_with(' ') || line.trim().is_empty() {
+            return None;
+        }
+        Some(match line.trim_end_matches(':') {
+            "Activity Resolver Table" => Section::Activities,
+            "Service Resolver Table" => Section::Services,
+            "Receiver Resolver Table" => Section::Receivers,
+            "Registered ContentProviders" => Section::Providers,
+            "Permissions" => Section::Permissions,
+            "Packages" => Section::Package,
+            _ => Section::Other,
+        })
+    }
+}
+
+/// Parses `dumpsys package <package>`. Fails when the package is not
+/// installed.
+pub fn parse_dumpsys_package(output: &str, package: &str) -> Result<PackageInfo> {
+    let mut info = PackageInfo { package: package.to_string(), ..PackageInfo::default() };
+    let mut section = Section::Other;
+    let mut in_package = false;
+    // "requested permissions:", "install permissions:" or "runtime permissions:".
+    let mut permission_list = None;
+    let owned = |component: &str| component.split('/').next() == Some(package);
+    for line in output.lines() {
+        if let Some(next) = Section::from_header(line) {
+            section = next;
+            continue;
+        }
+        match section {
+            Section::Activities | Section::Services | Section::Receivers => {
+                let Some(caps) = COMPONENT_REGEX.captures(line) else {
+                    continue;
+                };
+                let component = caps[1].to_string();
+                if !owned(&component) {
+                    continue;
+                }
+                let set = match section {
+                    Section::Activities => &mut info.activities,
+                    Section::Services => &mut info.services,
+                    _ => &mut info.receivers,
+                };
+                set.insert(component.clone());
+                info.exported.insert(component);
+            }
+            Section::Providers => {
+                if let Some(caps) = PROVIDER_REGEX.captures(line).filter(|caps| owned(&caps[1])) {
+                    info.providers.insert(caps[1].to_string());
+                    info.exported.insert(caps[1].to_string());
+                }
+            }
+            Section::Permissions => {
+                if let Some(caps) = PERMISSION_REGEX.captures(line) {
+                    info.declared_permissions.insert(caps[1].to_string());
+                }
+            }
+            Section::Package => {
+                if let Some(caps) = PACKAGE_REGEX.captures(line) {
+                    in_package = &caps[1] == package;
+                    permission_list = None;
+                    continue;
+                }
+                if !in_package {
+                    continue;
+                }
+                parse_package_line(line, &mut info, &mut permission_list);
+            }
+            Section::Other => {}
+        }
+    }
+    if info.version_code.is_none() {
+        return Err(anyhow!("Package {} is not installed", package));
+    }
+    Ok(info)
+}
+
+fn parse_package_line(line: &str, info: &mut PackageInfo, permission_list: &mut Option<&'static str>) {
+    let trimmed = line.trim();
+    if trimmed.ends_with("permissions:") {
+        *permission_list = ["requested", "install", "runtime"].into_iter().find(|kind| trimmed.starts_with(kind));
+        return;
+    }
+    // Permission entries are "name" or "name: granted=true, flags=[ ... ]";
+    // anything else ends the list.
+    if let Some(kind) = *permission_list {
+        let (name, state) = trimmed.split_once(": ").unwrap_or((trimmed, ""));
+        if name.contains('.') && !name.contains('=') && !name.contains(' ') {
+            if kind == "requested" {
+                info.requested_permissions.insert(name.to_string());
+            } else if state.contains("granted=true") {
+                info.granted_permissions.insert(name.to_string());
+            }
+            return;
+        }
+        *permission_list = None;
+    }
+    // "versionCode=42 minSdk=24 targetSdk=34" and "versionName=1.2.3".
+    for field in trimmed.split_whitespace() {
+        match field.split_once('=') {
+            Some(("versionCode", value)) if info.version_code.is_none() => info.version_code = Some(value.to_string()),
+            Some(("minSdk", value)) => info.min_sdk = value.parse().ok(),
+            Some(("targetSdk", value)) => info.target_sdk = value.parse().ok(),
+            Some(("versionName", value)) if info.version_name.is_none() => info.version_name = Some(value.to_string()),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PackageChange {
+    /// "version code", "service", "requested permission", ...
+    pub kind: String,
+    pub a: Option<String>,
+    pub b: Option<String>,
+}
+
+/// Changed fields, then items only in `a` (removed) or only in `b` (added).
+pub fn diff(a: &PackageInfo, b: &PackageInfo) -> Vec<PackageChange> {
+    let mut changes: Vec<PackageChange> = a
+        .fields()
+        .into_iter()
+        .zip(b.fields())
+        .filter(|((_, value_a), (_, value_b))| value_a != value_b)
+        .map(|((kind, value_a), (_, value_b))| PackageChange { kind: kind.to_string(), a: value_a, b: value_b })
+        .collect();
+    for ((kind, set_a), (_, set_b)) in a.sets().into_iter().zip(b.sets()) {
+        let mut items: BTreeMap<&String, (bool, bool)> = BTreeMap::new();
+        for item in set_a {
+            items.entry(item).or_default().0 = true;
+        }
+        for item in set_b {
+            items.entry(item).or_default().1 = true;
+        }
+        for (item, (in_a, in_b)) in items {
+            if in_a != in_b {
+                changes.push(PackageChange {
+                    kind: kind.to_string(),
+                    a: in_a.then(|| item.clone()),
+                    b: in_b.then(|| item.clone()),
+                });
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `dumpsys package com.example.app` on API 33, trimmed.
+    const DUMPSYS_PACKAGE: &str = "\
+Activity Resolver Table:
+  Non-Data Actions:
+      android.intent.action.MAIN:
+        5f1c2d0 com.example.app/.MainActivity filter 8e3a1b
+          Action: \"android.intent.action.MAIN\"
+          Category: \"android.intent.category.LAUNCHER\"
+
+Receiver Resolver Table:
+  Non-Data Actions:
+      android.intent.action.BOOT_COMPLETED:
+        a91e2f3 com.example.app/.BootReceiver filter 4c1d2e
+
+Service Resolver Table:
+  Non-Data Actions:
+      com.google.firebase.MESSAGING_EVENT:
+        77ab120 com.example.app/com.google.firebase.messaging.FirebaseMessagingService filter 19fe0a2
+
+Registered ContentProviders:
+  com.example.app/androidx.startup.InitializationProvider:
+    Provider{3e2a1f0 com.example.app/androidx.startup.InitializationProvider}
+
+ContentProvider Authorities:
+  [com.example.app.androidx-startup]:
+    Provider{3e2a1f0 com.example.app/androidx.startup.InitializationProvider}
+
+Permissions:
+  Permission [com.example.app.permission.C2D_MESSAGE] (a1b2c3):
+    sourcePackage=com.example.app
+    uid=10094 gids=null type=0 prot=signature
+
+Key Set Manager:
+  [com.example.app]
+      Signing KeySets: 57
+
+Packages:
+  Package [com.example.app] (4b2e9f1):
+    userId=10094
+    pkg=Package{9d0e1a2 com.example.app}
+    codePath=/data/app/~~w3A9q==/com.example.app-Xq1b==
+    primaryCpuAbi=arm64-v8a
+    versionCode=42 minSdk=24 targetSdk=33
+    versionName=1.2.3
+    splits=[base]
+    flags=[ HAS_CODE ALLOW_CLEAR_USER_DATA ALLOW_BACKUP ]
+    requested permissions:
+      android.permission.INTERNET
+      android.permission.CAMERA
+      android.permission.POST_NOTIFICATIONS
+      com.example.app.permission.C2D_MESSAGE
+    install permissions:
+      android.permission.INTERNET: granted=true
+      com.example.app.permission.C2D_MESSAGE: granted=true
+    User 0: ceDataInode=12345 installed=true hidden=false suspended=false stopped=false notLaunched=false enabled=0 instant=false virtual=false
+      gids=[3003]
+      runtime permissions:
+        android.permission.CAMERA: granted=false, flags=[ USER_SENSITIVE_WHEN_GRANTED|USER_SENSITIVE_WHEN_DENIED]
+        android.permission.POST_NOTIFICATIONS: granted=true, flags=[ USER_SET|USER_SENSITIVE_WHEN_GRANTED|USER_SENSITIVE_WHEN_DENIED]
+      enabledComponents:
+        com.example.app.DebugActivity
+
+Hidden system packages:
+  Package [com.example.app] (1c2d3e4):
+    versionCode=1 minSdk=24 targetSdk=30
+    versionName=1.0
+
+Queries:
+  system apps queryable: false
+";
+
+    fn set(items: &[&str]) -> BTreeSet<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn package_fields_permissions_and_components() {
+        let info = parse_dumpsys_package(DUMPSYS_PACKAGE, "com.example.app").unwrap();
+        assert_eq!(info.version_code.as_deref(), Some("42"));
+        assert_eq!(info.version_name.as_deref(), Some("1.2.3"));
+        assert_eq!((info.min_sdk, info.target_sdk), (Some(24), Some(33)));
+        assert_eq!(info.declared_permissions, set(&["com.example.app.permission.C2D_MESSAGE"]));
+        assert_eq!(info.requested_permissions.len(), 4);
+        assert_eq!(
+            info.granted_permissions,
+            set(&["android.permission.INTERNET", "android.permission.POST_NOTIFICATIONS", "com.example.app.permission.C2D_MESSAGE"])
+        );
+        assert_eq!(info.activities, set(&["com.example.app/.MainActivity"]));
+        assert_eq!(info.receivers, set(&["com.example.app/.BootReceiver"]));
+        assert_eq!(info.services, set(&["com.example.app/com.google.firebase.messaging.FirebaseMessagingService"]));
+        assert_eq!(info.providers, set(&["com.example.app/androidx.startup.InitializationProvider"]));
+        assert_eq!(info.exported.len(), 4);
+    }
+
+    #[test]
+    fn missing_package_is_an_error() {
+        assert!(parse_dumpsys_package("Packages:\n\nQueries:\n", "com.example.app").is_err());
+    }
+
+    #[test]
+    fn diff_lists_field_and_set_changes() {
+        let a = parse_dumpsys_package(DUMPSYS_PACKAGE, "com.example.app").unwrap();
+        let newer = DUMPSYS_PACKAGE
+            .replace("versionCode=42", "versionCode=43")
+            .replace("      android.permission.CAMERA\n", "      android.permission.RECORD_AUDIO\n");
+        let b = parse_dumpsys_package(&newer, "com.example.app").unwrap();
+        let changes = diff(&a, &b);
+        let rows: Vec<(&str, Option<&str>, Option<&str>)> =
+            changes.iter().map(|c| (c.kind.as_str(), c.a.as_deref(), c.b.as_deref())).collect();
+        assert_eq!(
+            rows,
+            [
+                ("version code", Some("42"), Some("43")),
+                ("requested permission", Some("android.permission.CAMERA"), None),
+                ("requested permission", None, Some("android.permission.RECORD_AUDIO")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,810 @@
+//! `--dry-run`: the adb commands and output files an invocation would
+//! produce, derived from the same command builders the collectors use.
+//! Nothing is sent to the device.
+
+use anyhow::Result;
+use clap::ArgMatches;
+
+use crate::cli::{self, Modes};
+use log_tools::compat::{self, ParserProfile};
+use log_tools::bench::BenchOptions;
+use log_tools::net::NetTarget;
+use log_tools::{LogAnalyzerConfig, activities, adb, addresses, anr, appmetrics, charts, clocksync, components, compress, composite, dashboard, derived, devenv, devices, devprep, eviction, exitinfo, freezer, guard, heapdump, htmlreport, idle, input, markers, multidevice, naming, nativelibs, net, pkgcompare, pkginfo, pstore, queries, reboot, regexcheck, scenario, selinux, stability, stabilize, timeline, topapps, uichurn, uidump, vmstats, wakeups, webview, wireless};
+
+/// Assumed release when neither the config nor `--sdk` names one.
+const DRY_RUN_SDK: u32 = 34;
+
+#[derive(Default)]
+pub struct Plan {
+    lines: Vec<String>,
+    depth: usize,
+    /// "adb", or "adb -s <serial>" with a device chosen.
+    adb: String,
+}
+
+impl Plan {
+    fn push(&mut self, line: String) {
+        self.lines.push(format!("{}{}", "  ".repeat(self.depth), line));
+    }
+
+    fn adb(&mut self, args: &[&str]) {
+        self.push(format!("{} {}", self.adb, args.join(" ")));
+    }
+
+    fn shell<S: AsRef<str>>(&mut self, args: &[S]) {
+        let args: Vec<&str> = args.iter().map(|a| a.as_ref()).collect();
+        self.push(format!("{} shell LC_ALL=C {}", self.adb, args.join(" ")));
+    }
+
+    /// Commands tried as-is, then through `su`.
+    fn root_shell(&mut self, command: &str) {
+        self.push(format!("{} shell LC_ALL=C {}   (retried via su if empty)", self.adb, command));
+    }
+
+    fn write(&mut self, path: &str) {
+        self.push(format!("write {}", naming::planned(path)));
+    }
+
+    fn note(&mut self, note: &str) {
+        self.push(format!("# {}", note));
+    }
+
+    fn nested(&mut self, header: &str, body: impl FnOnce(&mut Plan)) {
+        self.push(header.to_string());
+        self.depth += 1;
+        body(self);
+        self.depth -= 1;
+    }
+
+    pub fn print(&self) -> Result<()> {
+        if crate::output::json_output() {
+            return crate::output::emit("dry_run", &self.lines);
+        }
+        println!("Dry run, nothing will be executed:");
+        for line in &self.lines {
+            println!("  {}", line);
+        }
+        Ok(())
+    }
+}
+
+pub fn plan_invocation(config: &LogAnalyzerConfig, matches: &ArgMatches, modes: &Modes) -> Result<Plan> {
+    let mut adb = vec!["adb"];
+    adb.extend(devices::serial_args(config.serial.as_deref()));
+    let mut plan = Plan { adb: adb.join(" "), ..Plan::default() };
+    if !config.devices.is_empty() {
+        let targets = if config.devices.iter().any(|s| s == "all") { "every online device".to_string() } else { config.devices.join(", ") };
+        plan.note(&format!(
+            "runs on {} in parallel, as a child with --serial in a directory named after the serial; adb below is adb -s <serial>",
+            targets
+        ));
+    }
+    if !config.wireless_endpoints.is_empty() && matches.subcommand_name() != Some("connect") {
+        plan.note(&format!("first, adb connect to each of {} that is not attached", config.wireless_endpoints.join(", ")));
+    }
+    plan.note(&format!("device commands below go to the adb server on port {} when it is running, as adb itself otherwise", adb::DEFAULT_PORT));
+    if !config.one_shot_shell {
+        plan.note("adb shell commands below run one after another in a single device shell kept open for the session");
+    }
+    let sdk = match config.sdk_level {
+        Some(sdk) => sdk,
+        None => {
+            plan.shell(&["getprop", "ro.build.version.sdk"]);
+            plan.note(&format!("assuming SDK {} below; pass --sdk to plan for another release", DRY_RUN_SDK));
+            DRY_RUN_SDK
+        }
+    };
+    let profile = ParserProfile::for_sdk(sdk);
+    let package = config.package_name.as_str();
+    let mut executed = false;
+
+    if cli::is_session(matches) || matches!(matches.subcommand_name(), Some("startup" | "scenario" | "ab-test" | "bisect" | "eviction")) {
+        plan.shell(&["getprop"]);
+        for namespace in devenv::SETTINGS_NAMESPACES {
+            plan.shell(&["settings", "list", namespace]);
+        }
+        plan.shell(webview::WEBVIEW_CMD);
+        plan.write(&format!("{}_<timestamp>.json", devenv::ENV_FILE_STEM));
+        plan.shell(&["dumpsys", "package", package]);
+        plan.write(&format!("{}_<timestamp>.json (when installed)", pkginfo::PACKAGE_INFO_STEM));
+    }
+
+    if modes.threads {
+        plan.shell(&profile.pid_ps_args());
+        plan.shell(&profile.thread_ps_args("<pid>"));
+        if profile.needs_task_times() {
+            plan.shell(&["cat", "/proc/<pid>/task/*/stat"]);
+        }
+        if config.parquet {
+            plan.write("thread_info_<timestamp>.parquet");
+        }
+        if let Some(path) = &modes.output {
+            plan.write(path);
+        }
+        executed = true;
+    }
+    if let Some((count, duration)) = modes.top_apps {
+        plan_clock_sync(&mut plan);
+        plan.nested(&format!("every {}s for {}s:", config.sample_interval, duration), |plan| {
+            plan.shell(&["dumpsys", "meminfo"]);
+        });
+        plan.note(&format!("the {} largest processes are re-ranked every {}s", count, topapps::RERANK_SECS));
+        plan.write(&format!("top_apps_<timestamp>.json, top_apps_<timestamp>.csv, {}", topapps::TOP_APPS_PLOT_FILE));
+        executed = true;
+    } else if let Some(duration) = modes.memory {
+        plan_memory(&mut plan, config, &profile, sdk, duration, modes.plot_file());
+        if let Some(interval) = modes.thread_interval {
+            plan.nested("meanwhile, on a second thread:", |plan| plan_logcat(plan, config, None));
+            plan.nested(&format!("and on a third, every {}s:", interval), |plan| {
+                plan.shell(&profile.pid_ps_args());
+                plan.shell(&profile.thread_ps_args("<pid>"));
+                if profile.needs_task_times() {
+                    plan.shell(&["cat", "/proc/<pid>/task/*/stat"]);
+                }
+            });
+            plan.note("the session ends with the memory sampling");
+            if modes.tui {
+                plan.note(&format!("shown on a live dashboard; e writes {}_<timestamp>.json", dashboard::EXPORT_FILE_STEM));
+            }
+            plan.write(&format!("{}_<timestamp>.json", timeline::TIMELINE_FILE_STEM));
+        }
+        executed = true;
+    }
+    if config.html_report && (modes.memory.is_some() || modes.threads) {
+        plan.write(&format!("{}_<timestamp>.html", htmlreport::REPORT_FILE_STEM));
+    }
+    if modes.so_memory {
+        plan.shell(&["dumpsys", "meminfo", meminfo_target(config)]);
+        if config.parquet {
+            plan.write("so_memory_<timestamp>.parquet");
+        }
+        if let Some(path) = &modes.output {
+            plan.write(path);
+        }
+        executed = true;
+    }
+    if let Some(path) = config.db.as_ref().filter(|_| modes.memory.is_some() || modes.threads || modes.so_memory) {
+        plan.write(&format!("{}   (rows added under a new run ID)", path));
+    }
+
+    match matches.subcommand() {
+        Some(("scenario", sub)) => {
+            let scenario = scenario::load_scenario(sub.get_one::<String>("file").unwrap())?;
+            let bench = BenchOptions::from_matches(sub);
+            scenario::check_bench_options(&bench)?;
+            let passes = bench.warmup + bench.iterations(scenario.repeat);
+            match &scenario.matrix {
+                Some(matrix) => {
+                    for device_config in matrix.configs() {
+                        let mut result = Ok(());
+                        plan.nested(&format!("configuration {}:", device_config.label()), |plan| {
+                            for command in device_config.commands(&config.package_name, config.user) {
+                                plan.shell(&command);
+                            }
+                            plan.note("cold start with PSS, then the scenario, then PSS again");
+                            plan_stabilize(plan, &bench);
+                            result = plan_scenario(plan, config, &scenario, passes, sdk, sub.get_one::<u32>("restart_on_crash").is_some());
+                        });
+                        result?;
+                    }
+                    plan.note("the original locale, font scale and density are restored");
+                    plan.write("config_matrix_<timestamp>.json");
+                }
+                None => {
+                    plan_stabilize(&mut plan, &bench);
+                    plan_scenario(&mut plan, config, &scenario, passes, sdk, sub.get_one::<u32>("restart_on_crash").is_some())?;
+                }
+            }
+        }
+        Some(("input", sub)) => {
+            for command in input::action_from_matches(sub)?.commands(sdk) {
+                plan.shell(&command);
+            }
+            plan.write(markers::MARKERS_FILE);
+        }
+        Some(("startup", sub)) => {
+            let bench = BenchOptions::from_matches(sub);
+            plan_stabilize(&mut plan, &bench);
+            let runs = bench.warmup + bench.iterations(*sub.get_one::<u32>("runs").unwrap());
+            plan.nested(&format!("{} run(s):", runs), |plan| plan_cold_start(plan, config, &profile, sub.get_flag("pss")));
+            plan.write("startup_<timestamp>.json");
+        }
+        Some(("ab-test", sub)) => {
+            let bench = BenchOptions::from_matches(sub);
+            let iterations = bench.warmup + bench.iterations(*sub.get_one::<u32>("iterations").unwrap());
+            plan.nested(&format!("{} iteration(s), the build order swapped every other one:", iterations), |plan| {
+                for apk in [sub.get_one::<String>("apk_a").unwrap(), sub.get_one::<String>("apk_b").unwrap()] {
+                    plan_install(plan, config, apk);
+                    plan_stabilize(plan, &bench);
+                    plan.note("an unmeasured cold start, then the measured one with PSS:");
+                    plan_cold_start(plan, config, &profile, false);
+                    plan_cold_start(plan, config, &profile, true);
+                }
+            });
+            plan.write("ab_test_<timestamp>.json");
+        }
+        Some(("bisect", sub)) => {
+            let bench = BenchOptions::from_matches(sub);
+            let runs = bench.warmup + bench.iterations(*sub.get_one::<u32>("runs").unwrap());
+            if let Some(cmd) = sub.get_one::<String>("build_cmd").filter(|_| sub.contains_id("revs")) {
+                plan.note(&format!("for each revision tried, {} is run on the host and its last output line is the APK", cmd));
+            }
+            plan.nested("for each build the binary search tries, newest first:", |plan| {
+                plan_install(plan, config, "<apk>");
+                plan_stabilize(plan, &bench);
+                plan.nested(&format!("{} run(s):", runs), |plan| {
+                    plan_cold_start(plan, config, &profile, sub.get_one::<String>("metric").map(String::as_str) == Some("pss"));
+                });
+            });
+        }
+        Some(("doze", sub)) => {
+            match sub.get_one::<String>("action").map(String::as_str) {
+                Some("enter") => {
+                    plan.shell(&["dumpsys", "battery", "unplug"]);
+                    plan.shell(&["dumpsys", "deviceidle", "force-idle", "deep"]);
+                }
+                Some("exit") => {
+                    plan.shell(&["dumpsys", "deviceidle", "unforce"]);
+                    plan.shell(&["dumpsys", "battery", "reset"]);
+                }
+                _ => {}
+            }
+            plan_idle_status(&mut plan, config);
+        }
+        Some(("standby", sub)) => {
+            if let Some(bucket) = sub.get_one::<String>("set") {
+                let mut command = package_command(config, &["am", "set-standby-bucket"]);
+                command.push(bucket.clone());
+                plan.shell(&command);
+            }
+            plan_idle_status(&mut plan, config);
+        }
+        Some(("net", sub)) => {
+            if let Some(toggle) = sub.subcommand_matches("toggle") {
+                let target = toggle.get_one::<String>("target").and_then(|t| NetTarget::parse(t)).unwrap();
+                let enable = match toggle.get_one::<String>("state") {
+                    Some(state) => state == "on",
+                    None => {
+                        plan.shell(&["settings", "get", "global", target.setting()]);
+                        plan.note("the state is flipped; planned below as switching it on");
+                        true
+                    }
+                };
+                for command in target.commands(enable, sdk) {
+                    plan.shell(&[command]);
+                }
+            }
+            plan.shell(&[clocksync::DEVICE_CLOCK_CMD]);
+            plan.shell(&["dumpsys", "connectivity"]);
+        }
+        Some(("eviction", sub)) => {
+            plan_stabilize(&mut plan, &BenchOptions::from_matches(sub));
+            let runs = sub.get_one::<u32>("runs").unwrap_or(&3);
+            plan.nested(&format!("{} run(s):", runs), |plan| {
+                plan.shell(&package_command(config, &["am", "force-stop"]));
+                plan_launch(plan, config);
+                plan.shell(&profile.pid_ps_args());
+                plan.shell(eviction::HOME_CMD);
+                plan.nested(&format!("every {}s until the process is gone or {}s pass:", eviction::POLL_SECS, sub.get_one::<u64>("timeout").unwrap_or(&600)), |plan| {
+                    plan.shell(&profile.pid_ps_args());
+                    plan.shell(&profile.meminfo_args(meminfo_target(config)));
+                    plan.note(&format!("every {}s the next of the fillers is launched with monkey", eviction::FILLER_INTERVAL_SECS));
+                });
+                if sdk >= exitinfo::EXIT_INFO_MIN_SDK {
+                    plan.shell(&package_command(config, &["dumpsys", "activity", "exit-info"]));
+                }
+                plan.note("the fillers are force-stopped");
+            });
+            plan.write("eviction_runs_<timestamp>.json, eviction_<timestamp>.json");
+        }
+        Some(("devices", _)) => plan.push(format!("adb {}", devices::DEVICES_ARGS.join(" "))),
+        Some(("connect", sub)) => {
+            if let Some(pair) = sub.get_one::<String>("pair") {
+                plan.push(format!("adb pair {} <code>", pair));
+            }
+            let endpoints = match sub.get_one::<String>("endpoint") {
+                Some(endpoint) => vec![endpoint.clone()],
+                None => config.wireless_endpoints.clone(),
+            };
+            for endpoint in &endpoints {
+                plan.push(format!("adb connect {}", endpoint));
+            }
+            plan.note(&format!("when refused: adb {}, then adb connect to the port its host advertises now", wireless::MDNS_ARGS.join(" ")));
+            match matches.get_one::<String>("config") {
+                Some(path) => plan.write(&format!("{}   (endpoints connected to, under {:?})", path, wireless::ENDPOINTS_KEY)),
+                None => plan.note("no --config, endpoints are not remembered"),
+            }
+        }
+        Some(("battery", _)) => {
+            plan.shell(&["dumpsys", "package", package]);
+            if profile.batterystats_proto {
+                plan.adb(&["exec-out", "dumpsys", "batterystats", "--proto"]);
+                plan.note("falls back to the text dump below if the proto is unusable");
+            }
+            plan.shell(&profile.batterystats_args(package));
+        }
+        Some(("procstats", sub)) => {
+            plan.shell(&["dumpsys", "procstats", "--hours", &sub.get_one::<u32>("hours").unwrap_or(&24).to_string(), package]);
+        }
+        Some(("memtop", sub)) if sub.get_many::<String>("diff").is_none() => {
+            plan.shell(&["dumpsys", "meminfo"]);
+            plan.write("memtop_<timestamp>.json");
+            plan.write("memtop_<timestamp>.csv");
+        }
+        Some(("memtop", _)) => plan.note("compares two local memtop files, no device commands"),
+        Some(("heapdump", sub)) if sub.contains_id("diff") => plan.note("compares two local heap dumps, no device commands"),
+        Some(("heapdump", _)) => {
+            let mut dumpheap = package_command(config, &["am", "dumpheap"]);
+            dumpheap.push(heapdump::DEVICE_DUMP_PATH.to_string());
+            plan.shell(&dumpheap);
+            plan.adb(&["pull", heapdump::DEVICE_DUMP_PATH, &naming::planned(&format!("{}_<timestamp>.hprof", heapdump::HEAP_DUMP_FILE_STEM))]);
+            plan.shell(&["rm", "-f", heapdump::DEVICE_DUMP_PATH]);
+        }
+        Some(("device", sub)) => match sub.subcommand() {
+            Some(("prep", prep)) => {
+                for (namespace, key, value) in devprep::prep_settings(*prep.get_one::<u32>("brightness").unwrap()) {
+                    plan.shell(&["settings", "get", namespace, key]);
+                    plan.shell(&["settings", "put", namespace, key, &value]);
+                }
+                plan.write(devprep::PREP_STATE_FILE);
+            }
+            _ => plan.note(&format!("settings from {} are restored", devprep::PREP_STATE_FILE)),
+        },
+        Some(("bluetooth", _)) => {
+            plan.shell(&[clocksync::DEVICE_CLOCK_CMD]);
+            plan.shell(&["dumpsys", "bluetooth_manager"]);
+        }
+        Some(("stack", _)) => {
+            plan.shell(&["dumpsys", "window", "windows"]);
+            if profile.activities_proto {
+                plan.adb(&["exec-out", "dumpsys", "activity", "--proto", "activities"]);
+                plan.note("falls back to the text dump below if the proto is unusable");
+            }
+            plan.shell(&["dumpsys", "activity", "activities"]);
+        }
+        Some(("boot", _)) => {
+            plan.shell(&[reboot::BOOT_ID_CMD]);
+            plan.adb(&["reboot"]);
+            plan.note("adb get-state and getprop sys.boot_completed until the device is back on a new boot_id");
+            plan.adb(&["logcat", "-b", "events", "-d", "-v", "time"]);
+            plan.write("boot_events_<timestamp>.txt");
+            plan.root_shell("dmesg");
+            plan.write("boot_dmesg_<timestamp>.txt");
+            plan.note("then one cold start of the app");
+            plan.write("boot_<timestamp>.json");
+        }
+        Some(("splits", _)) => {
+            plan.shell(&package_command(config, &["pm", "path"]));
+            plan.shell(&["stat", "-c", "'%s %n'", "<apks>"]);
+            plan.shell(&package_command(config, &["pm", "list", "packages", "--instant"]));
+            plan.shell(&profile.pid_ps_args());
+            plan.root_shell("cat /proc/<pid>/maps");
+            plan.adb(&["exec-out", "cat", "<apk>"]);
+            plan.note("once per split APK");
+            plan.write("splits_<timestamp>.json");
+        }
+        Some(("exits", _)) => plan.shell(&package_command(config, &["dumpsys", "activity", "exit-info"])),
+        Some(("ui", _)) => {
+            plan.shell(&["uiautomator", "dump", uidump::DEVICE_DUMP_PATH]);
+            plan.adb(&["pull", uidump::DEVICE_DUMP_PATH, &naming::planned("ui_dump_<timestamp>.xml")]);
+        }
+        Some(("pkg", sub)) if sub.subcommand_matches("info").is_some() => {
+            plan.shell(&["dumpsys", "package", package]);
+            plan.write(&format!("{}_<timestamp>.json", pkginfo::PACKAGE_INFO_STEM));
+        }
+        Some(("pkg", _)) => plan.note("compares two local files, no device commands"),
+        Some(("diff-env", _)) => plan.note("compares two local files, no device commands"),
+        Some(("search", _)) => plan.note("searches local session logs, no device commands"),
+        Some(("trend", _)) => plan.note("reads local stability files, no device commands"),
+        Some(("bench-filter", sub)) => {
+            plan.note(&format!("replays {} through the filter rules on the host, no device commands", sub.get_one::<String>("file").unwrap()));
+        }
+        Some(("regex", sub)) => match sub.subcommand_matches("test").and_then(|test| test.get_one::<String>("file")) {
+            Some(file) => plan.note(&format!("matches the config's regexes against {} on the host, no device commands", file)),
+            None => {
+                plan.shell(regexcheck::LOGCAT_DUMP_ARGS);
+                plan.note("the config's regexes are matched against it on the host");
+            }
+        },
+        Some(("mark", _)) => plan.write(markers::MARKERS_FILE),
+        Some(("compare", _)) => plan.note("compares two local iteration files, no device commands"),
+        Some(("report", sub)) => {
+            plan.note(&format!("lists the artifacts in {} on the host, no device commands", sub.get_one::<String>("dir").unwrap()));
+            if let Some(out) = sub.get_one::<String>("bundle") {
+                plan.write(out);
+                if sub.get_flag("upload") {
+                    plan.note("then uploaded by HTTP PUT to the config's upload endpoint");
+                }
+            }
+        }
+        Some(("analyze", sub)) => {
+            plan.note(&format!("{} is filtered on the host, no device commands", sub.get_one::<String>("file").unwrap()));
+            if let Some(output) = sub.get_one::<String>("output") {
+                plan.write(output);
+            }
+        }
+        Some(("anr", sub)) if sub.subcommand_matches("collect").is_some() => {
+            plan.root_shell(anr::LIST_CMD);
+            plan.root_shell("cat /data/anr/<trace>");
+            plan.note(&format!("for each file not in {} with this time and size, unless its contents are", anr::ANR_DB));
+            plan.write(&format!("{}, {}/<trace>", anr::ANR_DB, anr::ANR_DIR));
+        }
+        Some(("anr", _)) => plan.note(&format!("reads the traces stored in {}, no device commands", anr::ANR_DB)),
+        Some(("logcat", _)) => plan_logcat(&mut plan, config, modes.logcat_duration),
+        Some((name, _)) if cli::SESSION_SUBCOMMANDS.contains(&name) => {}
+        Some((name, _)) => plan.note(&format!("'{}' is not covered by --dry-run", name)),
+        None if !executed => plan_logcat(&mut plan, config, None),
+        None => {}
+    }
+    if !config.devices.is_empty() && modes.memory.is_some() {
+        plan.write(&format!("devices_comparison_<timestamp>.json, {}", multidevice::DEVICES_PLOT_FILE));
+    }
+    let measures = cli::is_session(matches) || matches!(matches.subcommand_name(), Some("startup" | "scenario" | "ab-test" | "bisect" | "eviction"));
+    if measures && config.upload.as_ref().is_some_and(|u| u.after_session) {
+        plan.write("session_bundle_<timestamp>.zip   (the files this run wrote, without the config)");
+        plan.note("then uploaded by HTTP PUT to the config's upload endpoint");
+    }
+    Ok(plan)
+}
+
+fn plan_clock_sync(plan: &mut Plan) {
+    plan.shell(&[clocksync::DEVICE_TZ_CMD]);
+    plan.shell(&[clocksync::DEVICE_CLOCK_CMD]);
+    plan.note(&format!("{} round trips at the start and every {}s; offsets go to clock_sync_<timestamp>.json", clocksync::ROUND_TRIPS, clocksync::RESYNC_SECS));
+}
+
+/// `args`, then `--user <id>` when targeted, then the package, as
+/// `LogAnalyzer::user_command` builds them.
+fn package_command(config: &LogAnalyzerConfig, args: &[&str]) -> Vec<String> {
+    let mut command = compat::user_command(args, config.user);
+    command.push(config.package_name.clone());
+    command
+}
+
+/// Mirrors `LogAnalyzer::launch_app`: monkey launches in the current user
+/// only, so other users go through `am start --user`.
+fn plan_launch(plan: &mut Plan, config: &LogAnalyzerConfig) {
+    if config.user.is_some() {
+        plan.shell(&package_command(config, &["cmd", "package", "resolve-activity", "--brief", "-c", "android.intent.category.LAUNCHER"]));
+        let mut start = compat::user_command(&["am", "start"], config.user);
+        start.extend(["-n".to_string(), "<launcher activity>".to_string()]);
+        plan.shell(&start);
+    } else {
+        plan.shell(&["monkey", "-p", &config.package_name, "-c", "android.intent.category.LAUNCHER", "1"]);
+    }
+}
+
+/// Mirrors `LogAnalyzer::install_apk`.
+fn plan_install(plan: &mut Plan, config: &LogAnalyzerConfig, apk: &str) {
+    let mut install = vec!["install".to_string()];
+    install.extend(compat::user_args(config.user));
+    install.extend(["-r".to_string(), "-d".to_string(), apk.to_string()]);
+    plan.adb(&install.iter().map(String::as_str).collect::<Vec<_>>());
+}
+
+/// Mirrors `LogAnalyzer::stabilize`: nothing without `--stabilize`.
+fn plan_stabilize(plan: &mut Plan, bench: &BenchOptions) {
+    if let Some(gate) = &bench.stabilize {
+        plan.nested(&format!("every {}s until the device settles or {}s pass:", stabilize::POLL_SECS, gate.timeout_secs), |plan| {
+            plan.shell(&[stabilize::DEVICE_STATE_CMD]);
+        });
+        plan.write(&format!("{}, stabilization_<timestamp>.json", markers::MARKERS_FILE));
+    }
+}
+
+/// Mirrors `LogAnalyzer::cold_start`.
+fn plan_cold_start(plan: &mut Plan, config: &LogAnalyzerConfig, profile: &ParserProfile, with_pss: bool) {
+    plan.shell(&package_command(config, &["cmd", "package", "resolve-activity", "--brief", "-c", "android.intent.category.LAUNCHER"]));
+    plan.shell(&package_command(config, &["am", "force-stop"]));
+    let mut start = compat::user_command(&["am", "start", "-W"], config.user);
+    start.extend(["-n".to_string(), "<launcher activity>".to_string()]);
+    plan.shell(&start);
+    if with_pss {
+        plan.note("5s later:");
+        plan.shell(&profile.meminfo_args(meminfo_target(config)));
+    }
+}
+
+/// Mirrors `LogAnalyzer::idle_status`.
+fn plan_idle_status(plan: &mut Plan, config: &LogAnalyzerConfig) {
+    plan.shell(&[idle::idle_state_cmd(&config.package_name, config.user)]);
+    plan.shell(&[clocksync::DEVICE_CLOCK_CMD]);
+}
+
+/// Mirrors `LogAnalyzer::meminfo_target`: a PID when a user is targeted.
+/// `name` as `--compress` writes it.
+fn compressed(config: &LogAnalyzerConfig, name: &str) -> String {
+    compress::output_path(name, config.compression().unwrap_or(None))
+}
+
+fn meminfo_target(config: &LogAnalyzerConfig) -> &str {
+    if config.user.is_some() { "<pid>" } else { &config.package_name }
+}
+
+fn plan_memory(plan: &mut Plan, config: &LogAnalyzerConfig, profile: &ParserProfile, sdk: u32, duration: u64, plot_file: &str) {
+    let package = config.package_name.as_str();
+    plan_clock_sync(plan);
+    plan_slow_queries(plan, config);
+    plan.adb(&appmetrics::logcat_args("<session start>"));
+    plan.note(&format!("kept running for {} lines until the session ends", appmetrics::METRIC_PREFIX));
+    plan_control(plan, config);
+    plan_guard(plan, config);
+    plan.shell(&profile.pid_ps_args());
+    if config.components {
+        plan.shell(components::BROADCAST_HISTORY_CMD);
+        plan.note("broadcasts already in the history are not counted");
+    }
+    if config.native_libs {
+        plan.shell(&[nativelibs::linker_debug_cmd(package)]);
+        plan.root_shell(&nativelibs::maps_cmd("<pid>"));
+        plan.note(&format!("without root: run-as {} {}, here and below", package, nativelibs::maps_cmd("<pid>")));
+        plan.note("linker dlopen and nativeloader lines of the app are read from the logcat stream");
+    }
+    if config.kernel_mem {
+        plan.root_shell("cat /proc/slabinfo");
+        plan.root_shell("cat /proc/vmallocinfo");
+    }
+    if config.bluetooth {
+        plan.shell(&["dumpsys", "bluetooth_manager"]);
+    }
+    let header = format!("every {}s for {}s:", config.sample_interval, duration);
+    plan.nested(&header, |plan| {
+        if config.samples_vm() {
+            plan.shell(vmstats::VM_SNAPSHOT_CMD);
+        }
+        if config.dmabuf {
+            plan.shell(&["dmabuf_dump", "<pid>", "2>/dev/null"]);
+            plan.shell(&["cat", "/proc/<pid>/fdinfo/*", "2>/dev/null"]);
+        }
+        if config.idle_state {
+            plan.shell(&[idle::idle_state_cmd(package, config.user)]);
+        }
+        if config.net_state {
+            plan.shell(&["dumpsys", "connectivity"]);
+        }
+        if config.wifi_signal {
+            plan.shell(&[net::WIFI_INFO_CMD]);
+        }
+        if config.wakeups || config.wakeup_budget.is_some() {
+            plan.shell(&[wakeups::wakeup_stats_cmd(package)]);
+        }
+        if config.notifications {
+            plan.shell(&["dumpsys", "notification", "--noredact"]);
+        }
+        if config.components {
+            plan.shell(components::BROADCAST_HISTORY_CMD);
+        }
+        if config.native_libs {
+            plan.root_shell(&nativelibs::maps_cmd("<pid>"));
+        }
+        if config.collects_panels() {
+            plan.shell(&[composite::proc_stat_cmd("<pid>")]);
+            plan.shell(&["dumpsys", "gfxinfo", meminfo_target(config)]);
+            plan.shell(&[stabilize::THERMAL_ZONES_CMD]);
+        }
+        plan.shell(&[freezer::freeze_state_cmd("<pid>")]);
+        if config.user.is_some() {
+            plan.shell(&profile.pid_ps_args());
+        }
+        if profile.meminfo_proto {
+            plan.adb(&["exec-out", "dumpsys", "meminfo", "--proto", meminfo_target(config)]);
+            plan.note("falls back to text meminfo if the proto is unusable");
+        } else {
+            plan.shell(&profile.meminfo_args(meminfo_target(config)));
+        }
+        if let Some(other) = &config.compare_package {
+            if config.user.is_some() {
+                plan.shell(&profile.pid_ps_args());
+            }
+            plan.shell(&profile.meminfo_args(if config.user.is_some() { "<pid>" } else { other }));
+        }
+    });
+    if config.kernel_mem {
+        plan.root_shell("cat /proc/slabinfo");
+        plan.root_shell("cat /proc/vmallocinfo");
+    }
+    if config.bluetooth {
+        plan.shell(&["dumpsys", "bluetooth_manager"]);
+    }
+    if config.components {
+        plan.adb(components::SERVICE_EVENTS_ARGS);
+    }
+    if config.slow_queries.is_some() {
+        plan.adb(queries::CONTENT_EVENTS_ARGS);
+    }
+    if sdk >= exitinfo::EXIT_INFO_MIN_SDK {
+        plan.shell(&package_command(config, &["dumpsys", "activity", "exit-info"]));
+    }
+
+    if let Some(path) = &config.events_file {
+        plan.note(&format!("events in {} are read at the end and drawn as markers", path));
+    }
+    match config.chart_format {
+        charts::ChartFormat::Png => plan.write(plot_file),
+        charts::ChartFormat::Interactive => plan.write(&charts::chart_file(plot_file)),
+    }
+    if config.native_libs {
+        plan.note("with native library loads as markers");
+    }
+    if config.composite_plot {
+        plan.write(&format!("{}, panels_<timestamp>.json", composite::COMPOSITE_PLOT_FILE));
+    }
+    if !config.derived_metrics.is_empty() {
+        plan.write(derived::DERIVED_PLOT_FILE);
+    }
+    plan.write("parse_diagnostics_<timestamp>.json   (if any)");
+    plan.write(&format!("app_metrics_<timestamp>.json, app_metrics_<timestamp>.csv, {}   (if any)", appmetrics::APP_METRICS_PLOT_FILE));
+    let optional = [
+        (config.idle_state, "idle_states_<timestamp>.json"),
+        (config.net_state, "connectivity_<timestamp>.json"),
+        (config.wifi_signal, "wifi_<timestamp>.json, wifi_<timestamp>.csv"),
+        (config.bluetooth, "bluetooth_<timestamp>.json"),
+        (config.notifications, "notifications_<timestamp>.json"),
+        (config.components, "components_<timestamp>.json, components_<timestamp>.csv, components_plot.png"),
+        (config.native_libs, "native_libs_<timestamp>.json   (if any)"),
+        (config.slow_queries.is_some(), "slow_queries_<timestamp>.json, slow_queries_<timestamp>.csv, slow_queries_plot.png   (if any)"),
+        (config.kernel_mem, "kernel_mem_<timestamp>.json"),
+        (sdk >= exitinfo::EXIT_INFO_MIN_SDK, "exit_info_<timestamp>.json"),
+    ];
+    for (enabled, files) in optional {
+        if enabled {
+            plan.write(files);
+        }
+    }
+    plan.write("sampling_overhead_<timestamp>.json");
+    plan.write("frozen_intervals_<timestamp>.json   (if frozen)");
+    plan.write("paused_intervals_<timestamp>.json   (if paused)");
+    plan.write(&format!("{}, {}", compressed(config, "memory_samples_<timestamp>.json"), compressed(config, "memory_samples_<timestamp>.csv")));
+    if config.parquet {
+        plan.write("memory_samples_<timestamp>.parquet");
+    }
+    if config.compare_package.is_some() {
+        plan.write(&format!("package_comparison_<timestamp>.json, package_comparison_<timestamp>.csv, {}", pkgcompare::COMPARISON_PLOT_FILE));
+    }
+    if config.wakeups || config.wakeup_budget.is_some() {
+        plan.write("wakeups_<timestamp>.csv, wakeups_plot.png");
+    }
+    if config.samples_vm() {
+        plan.write(&format!("{}, {}", compressed(config, "device_memory_<timestamp>.json"), compressed(config, "device_memory_<timestamp>.csv")));
+        plan.write(&format!("{}, {}, psi_plot.png", compressed(config, "psi_<timestamp>.json"), compressed(config, "psi_<timestamp>.csv")));
+    }
+    if config.dmabuf {
+        plan.write("dmabuf_<timestamp>.json, dmabuf_<timestamp>.csv");
+    }
+}
+
+fn plan_scenario(plan: &mut Plan, config: &LogAnalyzerConfig, scenario: &scenario::Scenario, passes: u32, sdk: u32, watchdog: bool) -> Result<()> {
+    let profile = ParserProfile::for_sdk(sdk);
+    let header = format!("{} pass(es) of {} step(s):", passes, scenario.steps.len());
+    let mut result = Ok(());
+    plan.nested(&header, |plan| {
+        for step in &scenario.steps {
+            if watchdog {
+                plan.shell(&profile.pid_ps_args());
+            }
+            match step {
+                scenario::Step::Sleep { ms } => plan.note(&format!("sleep {} ms", ms)),
+                scenario::Step::Checkpoint { name } => plan.note(&format!("checkpoint {}", name)),
+                scenario::Step::Launch => plan_launch(plan, config),
+                _ => match step.input_action() {
+                    Ok(Some(action)) => {
+                        for command in action.commands(sdk) {
+                            plan.shell(&command);
+                        }
+                        if config.stack_snapshots {
+                            plan.shell(&["dumpsys", "activity", "activities"]);
+                            plan.shell(&["dumpsys", "window", "windows"]);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => result = Err(e),
+                },
+            }
+        }
+    });
+    plan.write(markers::MARKERS_FILE);
+    if config.stack_snapshots {
+        plan.write(&compressed(config, activities::STACK_SNAPSHOTS_FILE));
+    }
+    if watchdog {
+        plan.root_shell(&addresses::maps_cmd("<pid>"));
+        plan.note(&format!("while the app runs, at most every {}s, retried via run-as if empty", addresses::REFRESH_SECS));
+        plan.note("on a crash: adb logcat -b crash -d -t 200 >> crashes.log with the native crash's addresses resolved, relaunch, resume at the last checkpoint");
+        plan.write(&format!("{}_<timestamp>.json   (crashes per hour and per pass)", stability::STABILITY_FILE_STEM));
+    }
+    result
+}
+
+fn plan_control(plan: &mut Plan, config: &LogAnalyzerConfig) {
+    if let Some(addr) = &config.control_socket {
+        plan.note(&format!("mark, snapshot, rotate, pause, resume and stop commands are taken on {}", addr));
+    }
+}
+
+fn plan_guard(plan: &mut Plan, config: &LogAnalyzerConfig) {
+    let guard = &config.guard;
+    let caps: Vec<String> = [
+        guard.max_output_mb.map(|mb| format!("{} MB of output", mb)),
+        guard.max_rss_mb.map(|mb| format!("{} MB of own memory", mb)),
+        guard.max_open_files.map(|n| format!("{} open files", n)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if caps.is_empty() {
+        return;
+    }
+    let action = match guard.action {
+        guard::GuardAction::Stop => "the session stops".to_string(),
+        guard::GuardAction::Downsample => format!("1 in {} lines or samples is kept, stopping at twice the cap", guard::DOWNSAMPLE_FACTOR),
+    };
+    plan.note(&format!("past {} {}", caps.join(", "), action));
+}
+
+fn plan_logcat(plan: &mut Plan, config: &LogAnalyzerConfig, duration: Option<u64>) {
+    plan_clock_sync(plan);
+    plan_control(plan, config);
+    plan_guard(plan, config);
+    if config.ui_churn {
+        plan.shell(&[uichurn::FRAGMENT_VERBOSE_CMD]);
+    }
+    plan_slow_queries(plan, config);
+    if config.selinux {
+        plan.shell(&[selinux::DOMAIN_PS_CMD]);
+    }
+    plan.shell(webview::WEBVIEW_CMD);
+    if config.binary_logcat {
+        plan.adb(&["exec-out", "logcat", "-B"]);
+        plan.note("entries are decoded on the host");
+    } else {
+        plan.adb(&["logcat", "-v", "time"]);
+    }
+    plan.note(&format!("lines matching {:?} are printed", config.keyword_regex));
+    if !config.filter_presets.is_empty() {
+        plan.note(&format!("minus lines the {} preset(s) filter out", config.filter_presets.join(", ")));
+        for name in config.filter_presets.iter().filter(|name| config.presets.contains_key(*name)) {
+            plan.note(&format!("{} keeps lines matching {:?}", name, config.presets[name]));
+        }
+    }
+    if let Some(secs) = duration {
+        plan.note(&format!("stopped after {}s", secs));
+    }
+    plan.note(&format!("{} lines are collected as app metrics", appmetrics::METRIC_PREFIX));
+    if config.persist_across_reboot {
+        plan.shell(&[reboot::BOOT_ID_CMD]);
+        plan.note("when logcat ends: adb get-state and getprop sys.boot_completed until the device is back, then logcat again with -T <last timestamp>");
+        plan.note(&format!("after a reboot: {}, then the first readable of {} (root) into last_kmsg_<timestamp>.txt and panic_<timestamp>.json",
+            pstore::BOOT_REASON_CMD, pstore::LAST_KMSG_PATHS.join(", ")));
+    }
+    if let Some(file) = &config.output_file {
+        plan.write(&compressed(config, file));
+    }
+    if config.stack_snapshots {
+        plan.write(&format!("{}   (on crash or ANR)", compressed(config, activities::STACK_SNAPSHOTS_FILE)));
+        plan.root_shell(&addresses::maps_cmd("<pid>"));
+        plan.note("at a native crash of the app, retried via run-as if empty");
+        plan.write(&format!("{}   (on native crash)", addresses::NATIVE_CRASHES_FILE));
+    }
+    if config.selinux {
+        plan.write("selinux_denials_<timestamp>.json");
+    }
+    plan.write(&format!("{}_<timestamp>.json   (on WebView renderer crashes)", webview::WEBVIEW_CRASHES_FILE_STEM));
+    plan.write(&format!("{}_<timestamp>.json   (the app's crashes and ANRs per hour)", stability::STABILITY_FILE_STEM));
+    if config.ui_churn {
+        plan.write("ui_churn_<timestamp>.json");
+    }
+    plan.write(&format!("app_metrics_<timestamp>.json, app_metrics_<timestamp>.csv, {}   (if any)", appmetrics::APP_METRICS_PLOT_FILE));
+    if config.slow_queries.is_some() {
+        plan.adb(queries::CONTENT_EVENTS_ARGS);
+        plan.write(&format!("slow_queries_<timestamp>.json, slow_queries_<timestamp>.csv, {}   (if any)", queries::QUERIES_PLOT_FILE));
+    }
+}
+
+fn plan_slow_queries(plan: &mut Plan, config: &LogAnalyzerConfig) {
+    if let Some(threshold) = config.slow_queries {
+        plan.shell(&["getprop", queries::THRESHOLD_PROP]);
+        plan.shell(&[queries::threshold_cmd(threshold)]);
+        plan.note("SQLiteConnection lines on the app's databases are collected as slow queries (userdebug and eng builds only)");
+        plan.note(&format!("{} is set back to the value read when the session ends or on Ctrl-C", queries::THRESHOLD_PROP));
+    }
+}
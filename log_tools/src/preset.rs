@@ -0,0 +1,75 @@
+//! Device-class presets for watches and TVs: slower sampling and no
+//! heavyweight or meaningless collectors, so the defaults suit the device.
+
+use crate::LogAnalyzerConfig;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DeviceClass {
+    Phone,
+    Wear,
+    Tv,
+}
+
+impl DeviceClass {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "phone" => Some(DeviceClass::Phone),
+            "wear" => Some(DeviceClass::Wear),
+            "tv" => Some(DeviceClass::Tv),
+            _ => None,
+        }
+    }
+
+    /// From `ro.build.characteristics`, e.g. "nosdcard,watch" or "tv".
+    pub fn detect(characteristics: &str) -> Self {
+        let traits: Vec<&str> = characteristics.trim().split(',').collect();
+        if traits.contains(&"watch") {
+            DeviceClass::Wear
+        } else if traits.contains(&"tv") {
+            DeviceClass::Tv
+        } else {
+            DeviceClass::Phone
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeviceClass::Phone => "phone",
+            DeviceClass::Wear => "wear",
+            DeviceClass::Tv => "tv",
+        }
+    }
+
+    /// Adjusts `config` for the class and describes each change. Applied
+    /// before command-line flags, so an explicit flag still wins.
+    pub fn apply(&self, config: &mut LogAnalyzerConfig) -> Vec<String> {
+        let mut changes = Vec::new();
+        let (min_interval, disabled): (f64, Vec<(&str, &mut bool)>) = match self {
+            DeviceClass::Phone => return changes,
+            // Small heaps and slow CPUs: every dump perturbs the app.
+            DeviceClass::Wear => (
+                5.0,
+                vec![
+                    ("dmabuf", &mut config.dmabuf),
+                    ("kernel_mem", &mut config.kernel_mem),
+                    // Wi-Fi is usually off while a phone proxies over Bluetooth.
+                    ("wifi_signal", &mut config.wifi_signal),
+                    ("stack_snapshots", &mut config.stack_snapshots),
+                ],
+            ),
+            // Mains powered: Doze and standby buckets never engage.
+            DeviceClass::Tv => (2.0, vec![("idle_state", &mut config.idle_state)]),
+        };
+        for (name, enabled) in disabled {
+            if *enabled {
+                *enabled = false;
+                changes.push(format!("{} disabled", name));
+            }
+        }
+        if config.sample_interval < min_interval {
+            config.sample_interval = min_interval;
+            changes.push(format!("sample interval raised to {}s", min_interval));
+        }
+        changes
+    }
+}
@@ -0,0 +1,150 @@
+//! Parser for `dumpsys procstats --hours N <package>` text output.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+// "  * com.example.app / u0a123 / v456:"
+static PROCESS_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s*\*\s+(\S+)\s+/\s+\S+\s+/\s+v\S+:").unwrap());
+// "          Cached: 30% (40MB-45MB-50MB/38MB-42MB-47MB over 7)", newer releases
+// append a third "/min-avg-max" RSS triple before " over".
+static STATE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\s*([A-Za-z ()]+):\s+([\d.]+)%(?:\s+\(([\d.]+\w*)-([\d.]+\w*)-([\d.]+\w*)/[^ ]*\s+over\s+(\d+)\))?").unwrap()
+});
+
+#[derive(Serialize)]
+pub struct ProcStateStats {
+    pub process: String,
+    pub state: String,
+    /// Share of the aggregation window spent in this state.
+    pub time_percent: f64,
+    pub min_pss: u64,
+    pub avg_pss: u64,
+    pub max_pss: u64,
+    pub samples: u64,
+}
+
+/// Extracts per-state PSS statistics for `package` and its `:suffix`
+/// processes from the first "AGGREGATED OVER" block.
+pub fn parse_procstats(output: &str, package: &str) -> Vec<ProcStateStats> {
+    let mut stats = Vec::new();
+    let mut in_aggregate = false;
+    let mut current: Option<String> = None;
+
+    for line in output.lines() {
+        if line.starts_with("AGGREGATED OVER") {
+            if in_aggregate {
+                break;
+            }
+            in_aggregate = true;
+            continue;
+        }
+        if !in_aggregate {
+            continue;
+        }
+        if let Some(caps) = PROCESS_REGEX.captures(line) {
+            let name = &caps[1];
+            let ours = name == package || name.strip_prefix(package).is_some_and(|rest| rest.starts_with(':'));
+            current = ours.then(|| name.to_string());
+            continue;
+        }
+        if !line.starts_with(' ') {
+            // A new top-level section ends the process list.
+            current = None;
+            continue;
+        }
+        let Some(process) = &current else {
+            continue;
+        };
+        if let Some(caps) = STATE_REGEX.captures(line) {
+            let size = |i: usize| caps.get(i).map_or(0, |m| parse_size_kb(m.as_str()));
+            stats.push(ProcStateStats {
+                process: process.clone(),
+                state: caps[1].trim().to_string(),
+                time_percent: caps[2].parse().unwrap_or(0.0),
+                min_pss: size(3),
+                avg_pss: size(4),
+                max_pss: size(5),
+                samples: caps.get(6).and_then(|m| m.as_str().parse().ok()).unwrap_or(0),
+            });
+        }
+    }
+    stats
+}
+
+/// Converts a DebugUtils.printSizeValue string ("812", "45KB", "1.2GB") to KB.
+fn parse_size_kb(value: &str) -> u64 {
+    let split = value.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(value.len());
+    let (number, suffix) = value.split_at(split);
+    let number: f64 = number.parse().unwrap_or(0.0);
+    let kb = match suffix {
+        "" | "B" => number / 1024.0,
+        "K" | "KB" => number,
+        "M" | "MB" => number * 1024.0,
+        "G" | "GB" => number * 1024.0 * 1024.0,
+        "T" | "TB" => number * 1024.0 * 1024.0 * 1024.0,
+        _ => 0.0,
+    };
+    kb.round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `dumpsys procstats --hours 24 com.example.app` on API 30, trimmed.
+    const PROCSTATS: &str = "\
+COMMITTED STATS FROM 2024-05-02-09-30-00 (checked in):
+  * system / 1000 / v34:
+           TOTAL: 100%
+AGGREGATED OVER LAST 24 HOURS:
+System memory usage:
+  SOff/Norm: 1 samples:
+    Cached: 1.2GB min, 1.2GB avg, 1.2GB max
+Summary:
+  * com.example.app / u0a123 / v456:
+           TOTAL: 81% (41MB-44MB-48MB/36MB-39MB-42MB/70MB-75MB-80MB over 12)
+             Top: 76% (41MB-45MB-48MB/36MB-40MB-42MB/70MB-76MB-80MB over 10)
+          Imp Fg: 0.53%
+         Service: 4.8% (30MB-31MB-32MB/28MB-29MB-30MB/60MB-61MB-62MB over 2)
+  * com.example.app:remote / u0a123 / v456:
+          Cached: 100% (10MB-11MB-12MB/9.0MB-9.5MB-10MB over 3)
+  * com.example.application / u0a124 / v1:
+             Top: 100% (20MB-20MB-20MB/18MB-18MB-18MB over 1)
+
+Run time Stats:
+  SOff/Norm: +1h0m0s0ms
+AGGREGATED OVER LAST 3 HOURS:
+Summary:
+  * com.example.app / u0a123 / v456:
+             Top: 100% (1MB-1MB-1MB/1MB-1MB-1MB over 1)
+";
+
+    #[test]
+    fn states_of_the_package_processes_from_the_first_aggregate() {
+        let stats = parse_procstats(PROCSTATS, "com.example.app");
+        let rows: Vec<(&str, &str)> = stats.iter().map(|s| (s.process.as_str(), s.state.as_str())).collect();
+        assert_eq!(
+            rows,
+            [
+                ("com.example.app", "TOTAL"),
+                ("com.example.app", "Top"),
+                ("com.example.app", "Imp Fg"),
+                ("com.example.app", "Service"),
+                ("com.example.app:remote", "Cached"),
+            ]
+        );
+        let top = &stats[1];
+        assert_eq!((top.time_percent, top.min_pss, top.avg_pss, top.max_pss, top.samples), (76.0, 41 * 1024, 45 * 1024, 48 * 1024, 10));
+        // A state without PSS samples keeps its time share.
+        assert_eq!((stats[2].time_percent, stats[2].avg_pss, stats[2].samples), (0.53, 0, 0));
+    }
+
+    #[test]
+    fn sizes_convert_to_kb() {
+        assert_eq!(parse_size_kb("812"), 1);
+        assert_eq!(parse_size_kb("45KB"), 45);
+        assert_eq!(parse_size_kb("9.5MB"), 9728);
+        assert_eq!(parse_size_kb("1.2GB"), 1258291);
+    }
+}
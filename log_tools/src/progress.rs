@@ -0,0 +1,51 @@
+//! Progress bars for long operations. Hidden when stdout is not a
+//! terminal so redirected output and CI logs stay clean, and when the
+//! console is off for library callers.
+
+use std::io::IsTerminal;
+use std::time::Duration;
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+fn visible() -> bool {
+    crate::output::console_output() && std::io::stdout().is_terminal()
+}
+
+/// A bar over `secs` seconds of wall time with an ETA and a message slot
+/// for the latest sample.
+pub fn timed_bar(secs: u64) -> ProgressBar {
+    if !visible() {
+        return ProgressBar::hidden();
+    }
+    let bar = ProgressBar::new(secs);
+    bar.set_style(
+        ProgressStyle::with_template("{spinner} [{elapsed_precise}] {wide_bar} {pos}/{len}s ETA {eta} {msg}")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    bar
+}
+
+pub fn spinner(message: &str) -> ProgressBar {
+    if !visible() {
+        return ProgressBar::hidden();
+    }
+    let spinner = ProgressBar::new_spinner();
+    spinner.set_message(message.to_string());
+    spinner.enable_steady_tick(Duration::from_millis(120));
+    spinner
+}
+
+/// A bar over `total` bytes of input with throughput and an ETA.
+pub fn bytes_bar(total: u64) -> ProgressBar {
+    if !visible() {
+        return ProgressBar::hidden();
+    }
+    let bar = ProgressBar::new(total);
+    bar.set_style(
+        ProgressStyle::with_template("{spinner} [{elapsed_precise}] {wide_bar} {bytes}/{total_bytes} {bytes_per_sec} ETA {eta}")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    bar
+}
@@ -0,0 +1,199 @@
+//! `dumpsys --proto` dumps of meminfo, activity and batterystats, decoded
+//! with the message types prost-build generates from the trimmed AOSP
+//! protos under proto/. Each caller falls back to the text dump when a
+//! release lacks the proto or it holds nothing for the app.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow};
+use prost::Message;
+
+use crate::MemorySample;
+use crate::activities::{self, ActivityStackSnapshot, TaskInfo};
+use crate::clocksync::SampleTime;
+use crate::compat::PowerUse;
+
+#[allow(clippy::all)]
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/dumpsys.rs"));
+}
+
+use generated::android::service::batterystats::BatteryStatsServiceDumpProto;
+use generated::com::android::server::am::{ActivityManagerServiceDumpActivitiesProto, MemInfoDumpProto};
+
+fn kb(value: i32) -> u64 {
+    value.max(0) as u64
+}
+
+/// Decodes a meminfo dump and converts the entry for `package` into a sample.
+pub fn parse_meminfo_proto(bytes: &[u8], package: &str, time: SampleTime) -> Result<MemorySample> {
+    let dump = MemInfoDumpProto::decode(bytes)?;
+    let process = dump
+        .app_processes
+        .into_iter()
+        .filter_map(|app| app.process_memory)
+        .find(|p| p.process_name() == package)
+        .ok_or_else(|| anyhow!("No meminfo proto entry for {}", package))?;
+    let summary = process.app_summary.unwrap_or_default();
+    let total = process.total_heap.and_then(|h| h.mem_info).unwrap_or_default();
+
+    Ok(MemorySample {
+        time,
+        total_pss: kb(total.total_pss_kb()),
+        native_heap: kb(summary.native_heap_pss_kb()),
+        dalvik_heap: kb(summary.java_heap_pss_kb()),
+        code: kb(summary.code_pss_kb()),
+        stack: kb(summary.stack_pss_kb()),
+        graphics: kb(summary.graphics_pss_kb()),
+        private_dirty: kb(total.private_dirty_kb()),
+        shared_dirty: kb(total.shared_dirty_kb()),
+        derived: BTreeMap::new(),
+    })
+}
+
+/// Decodes `dumpsys activity --proto activities` into a stack snapshot,
+/// with the focused window from the `dumpsys window windows` text.
+pub fn parse_activities_proto(bytes: &[u8], windows: &str, trigger: &str) -> Result<ActivityStackSnapshot> {
+    let supervisor = ActivityManagerServiceDumpActivitiesProto::decode(bytes)?
+        .activity_stack_supervisor
+        .ok_or_else(|| anyhow!("No activity stack in the activities proto"))?;
+    let mut snap = activities::empty_snapshot(trigger);
+    snap.resumed_activity = supervisor.resumed_activity.map(|identifier| identifier.title().to_string());
+    for task in supervisor.displays.iter().flat_map(|display| &display.stacks).flat_map(|stack| &stack.tasks) {
+        let activities = task.activities.iter().filter_map(|activity| activity.identifier.as_ref()).map(|i| i.title().to_string()).collect();
+        snap.tasks.push(TaskInfo { id: task.id().into(), activities });
+    }
+    snap.focused_window = activities::parse_focused_window(windows);
+    Ok(snap)
+}
+
+/// Decodes `dumpsys batterystats --proto` and reads the power estimate of
+/// `uid`, the full uid of the app's user; `label` is how text dumps print it.
+pub fn parse_power_use_proto(bytes: &[u8], uid: u32, label: &str) -> Result<PowerUse> {
+    let stats = BatteryStatsServiceDumpProto::decode(bytes)?
+        .batterystats
+        .ok_or_else(|| anyhow!("No batterystats in the proto dump"))?;
+    let item = stats
+        .uids
+        .iter()
+        .find(|entry| entry.uid() as u32 == uid)
+        .and_then(|entry| entry.power_use_item.as_ref())
+        .ok_or_else(|| anyhow!("No power estimate for {} in the batterystats proto", label))?;
+    let computed_drain_mah = stats.system.and_then(|system| system.power_use_summary).and_then(|summary| summary.computed_power_mah);
+    Ok(PowerUse { uid: label.to_string(), power_mah: item.computed_power_mah(), computed_drain_mah })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generated::android::os::{BatteryStatsProto, SystemProto, UidProto, system_proto, uid_proto};
+    use generated::com::android::server::am::{
+        ActivityDisplayProto, ActivityRecordProto, ActivityStackProto, ActivityStackSupervisorProto, TaskRecordProto, mem_info_dump_proto,
+    };
+    use generated::com::android::server::wm::IdentifierProto;
+
+    use mem_info_dump_proto::process_memory::{AppSummary, HeapInfo, MemoryInfo};
+    use mem_info_dump_proto::{AppData, ProcessMemory};
+
+    fn process(name: &str, total_pss_kb: i32) -> AppData {
+        AppData {
+            process_memory: Some(ProcessMemory {
+                pid: Some(4242),
+                process_name: Some(name.to_string()),
+                total_heap: Some(HeapInfo {
+                    mem_info: Some(MemoryInfo {
+                        name: Some("TOTAL".to_string()),
+                        total_pss_kb: Some(total_pss_kb),
+                        private_dirty_kb: Some(98_000),
+                        ..MemoryInfo::default()
+                    }),
+                    ..HeapInfo::default()
+                }),
+                app_summary: Some(AppSummary {
+                    java_heap_pss_kb: Some(21_000),
+                    native_heap_pss_kb: Some(35_000),
+                    code_pss_kb: Some(18_000),
+                    stack_pss_kb: Some(900),
+                    graphics_pss_kb: Some(12_000),
+                    ..AppSummary::default()
+                }),
+                ..ProcessMemory::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn meminfo_entry_of_the_package() {
+        let dump = MemInfoDumpProto {
+            uptime_duration_ms: Some(8_812_345),
+            app_processes: vec![process("com.example.app:remote", 12_345), process("com.example.app", 120_500)],
+            ..MemInfoDumpProto::default()
+        };
+        let sample = parse_meminfo_proto(&dump.encode_to_vec(), "com.example.app", SampleTime::default()).unwrap();
+        assert_eq!((sample.total_pss, sample.native_heap, sample.dalvik_heap, sample.graphics), (120_500, 35_000, 21_000, 12_000));
+        assert_eq!((sample.private_dirty, sample.shared_dirty), (98_000, 0));
+        assert!(parse_meminfo_proto(&dump.encode_to_vec(), "com.other.app", SampleTime::default()).is_err());
+        assert!(parse_meminfo_proto(b"\xff\xff", "com.example.app", SampleTime::default()).is_err());
+    }
+
+    fn identifier(title: &str) -> Option<IdentifierProto> {
+        Some(IdentifierProto { hash_code: Some(0x5f2a1c3), user_id: Some(0), title: Some(title.to_string()) })
+    }
+
+    fn task(id: i32, titles: &[&str]) -> TaskRecordProto {
+        let activities = titles.iter().map(|title| ActivityRecordProto { identifier: identifier(title), ..ActivityRecordProto::default() }).collect();
+        TaskRecordProto { id: Some(id), activities, ..TaskRecordProto::default() }
+    }
+
+    #[test]
+    fn activity_stack_from_displays_down() {
+        let dump = ActivityManagerServiceDumpActivitiesProto {
+            activity_stack_supervisor: Some(ActivityStackSupervisorProto {
+                displays: vec![ActivityDisplayProto {
+                    id: Some(0),
+                    stacks: vec![
+                        ActivityStackProto {
+                            id: Some(3),
+                            tasks: vec![task(12, &["com.example.app/.DetailActivity", "com.example.app/.MainActivity"])],
+                            ..ActivityStackProto::default()
+                        },
+                        ActivityStackProto { id: Some(0), tasks: vec![task(1, &["com.google.android.apps.nexuslauncher/.NexusLauncherActivity"])], ..ActivityStackProto::default() },
+                    ],
+                }],
+                focused_stack_id: Some(3),
+                resumed_activity: identifier("com.example.app/.DetailActivity"),
+            }),
+        };
+        let windows = "  mCurrentFocus=Window{8c3e1d u0 com.example.app/com.example.app.DetailActivity}\n";
+        let snap = parse_activities_proto(&dump.encode_to_vec(), windows, "manual").unwrap();
+        assert_eq!(snap.resumed_activity.as_deref(), Some("com.example.app/.DetailActivity"));
+        assert_eq!(snap.focused_window.as_deref(), Some("com.example.app/com.example.app.DetailActivity"));
+        let tasks: Vec<(i64, usize)> = snap.tasks.iter().map(|t| (t.id, t.activities.len())).collect();
+        assert_eq!(tasks, [(12, 2), (1, 1)]);
+        assert_eq!(snap.tasks[0].activities[1], "com.example.app/.MainActivity");
+        assert!(parse_activities_proto(&[], windows, "manual").is_err());
+    }
+
+    #[test]
+    fn power_estimate_of_the_uid() {
+        let uid = |uid: i32, mah: Option<f64>| UidProto {
+            uid: Some(uid),
+            power_use_item: mah.map(|mah| uid_proto::PowerUseItem { computed_power_mah: Some(mah), ..uid_proto::PowerUseItem::default() }),
+        };
+        let dump = BatteryStatsServiceDumpProto {
+            batterystats: Some(BatteryStatsProto {
+                report_version: Some(35),
+                uids: vec![uid(1000, Some(40.2)), uid(10057, Some(12.3)), uid(1_010_057, Some(1.5)), uid(10058, None)],
+                system: Some(SystemProto {
+                    power_use_summary: Some(system_proto::PowerUseSummary { computed_power_mah: Some(850.0), ..system_proto::PowerUseSummary::default() }),
+                }),
+                ..BatteryStatsProto::default()
+            }),
+        };
+        let bytes = dump.encode_to_vec();
+        let usage = parse_power_use_proto(&bytes, 10057, "u0a57").unwrap();
+        assert_eq!((usage.uid.as_str(), usage.power_mah, usage.computed_drain_mah), ("u0a57", 12.3, Some(850.0)));
+        assert_eq!(parse_power_use_proto(&bytes, 1_010_057, "u10a57").unwrap().power_mah, 1.5);
+        assert!(parse_power_use_proto(&bytes, 10058, "u0a58").is_err());
+    }
+}
@@ -0,0 +1,44 @@
+//! Post-mortem kernel log after an unexpected reboot: the pstore console
+//! ramoops of the previous boot (or `/proc/last_kmsg` on older kernels) and
+//! the boot reason, to tell kernel panics from ordinary reboots.
+
+use serde::Serialize;
+
+/// Tried in order; reading them needs root on production builds.
+pub const LAST_KMSG_PATHS: [&str; 4] = [
+    "/sys/fs/pstore/console-ramoops-0",
+    "/sys/fs/pstore/console-ramoops",
+    "/sys/fs/pstore/dmesg-ramoops-0",
+    "/proc/last_kmsg",
+];
+pub const BOOT_REASON_CMD: &str = "getprop sys.boot.reason";
+/// Lines kept in the report around the crash signature.
+const MAX_PANIC_LINES: usize = 20;
+
+const PANIC_SIGNATURES: [&str; 5] = ["Kernel panic", "Unable to handle kernel", "Internal error:", "BUG:", "watchdog bite"];
+
+#[derive(Serialize)]
+pub struct PanicReport {
+    /// e.g. "kernel_panic", "watchdog" or "reboot,userrequested".
+    pub boot_reason: String,
+    /// Device path the previous boot's kernel log was read from.
+    pub source: Option<String>,
+    /// Local copy of that log.
+    pub log_file: Option<String>,
+    pub interrupted_by_panic: bool,
+    pub panic_lines: Vec<String>,
+}
+
+pub fn panic_lines(log: &str) -> Vec<String> {
+    log.lines()
+        .filter(|line| PANIC_SIGNATURES.iter().any(|signature| line.contains(signature)))
+        .take(MAX_PANIC_LINES)
+        .map(|line| line.trim_end().to_string())
+        .collect()
+}
+
+/// A panic signature in the old log, or a boot reason naming a panic or a
+/// watchdog reset.
+pub fn is_panic(boot_reason: &str, panic_lines: &[String]) -> bool {
+    !panic_lines.is_empty() || boot_reason.contains("panic") || boot_reason.contains("watchdog")
+}
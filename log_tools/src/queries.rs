@@ -0,0 +1,162 @@
+//! `--slow-queries <MS>`: database stalls of the app during a session.
+//!
+//! SQLite statements come from the framework's slow query log, which
+//! `SQLiteConnection` writes for statements taking at least
+//! `db.log.slow_query_threshold` ms. The property is read on every
+//! statement, but only userdebug and eng builds log at all. Its earlier
+//! value is put back when the session ends, Ctrl-C included. A statement
+//! counts when its database is under the package's data directory.
+//! ContentResolver calls come from the `content_query_sample` and
+//! `content_update_sample` entries of the events buffer, which every build
+//! writes for calls the app made, all of those over 500 ms and a sampled
+//! share of faster ones; they are read when the session ends.
+
+use std::collections::BTreeMap;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::clocksync::{ClockSync, SampleTime};
+
+pub const QUERIES_PLOT_FILE: &str = "slow_queries_plot.png";
+pub const CONTENT_EVENTS_ARGS: &[&str] = &["logcat", "-b", "events", "-d", "-v", "time", "-s", "content_query_sample", "content_update_sample"];
+/// Rows of the slowest-queries table.
+pub const SLOWEST_ROWS: usize = 10;
+
+// "D/SQLiteConnection( 1234): executeForCursorWindow took 412ms - succeeded,
+// sql=\"SELECT * FROM items WHERE id=?\", path=/data/user/0/com.example.app/databases/app.db"
+static SQLITE_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"SQLiteConnection\(\s*\d+\): (\w+) took (\d+)ms - (\w+)(?:, sql="(.*?)")?.*?, path=(\S+)"#).unwrap());
+// "10-16 15:00:00.123 I/content_query_sample( 1234): [content://com.example.app.items/1,_id/title,,,612,com.example.app,100]"
+static CONTENT_EVENT_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"I/(content_query_sample|content_update_sample)\(\s*\d+\): \[(.*)\]").unwrap());
+
+pub const THRESHOLD_PROP: &str = "db.log.slow_query_threshold";
+
+pub fn threshold_cmd(threshold_ms: u64) -> String {
+    format!("setprop {} {}", THRESHOLD_PROP, threshold_ms)
+}
+
+/// Puts back the value `getprop` read before the session, an empty one
+/// when the property was unset.
+pub fn restore_threshold_cmd(old: &str) -> String {
+    format!("setprop {} '{}'", THRESHOLD_PROP, old)
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuerySource {
+    Sqlite,
+    ContentProvider,
+}
+
+#[derive(Serialize)]
+pub struct SlowQuery {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    pub source: QuerySource,
+    /// SQLite operation ("executeForCursorWindow", ...), or "query",
+    /// "insert", "update", "delete" for a ContentResolver call.
+    pub operation: String,
+    pub duration_ms: u64,
+    /// SQL statement, or content URI.
+    pub statement: String,
+    /// Database file of a SQLite statement.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub database: Option<String>,
+    /// Share of calls this fast that the events buffer samples.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_percent: Option<u32>,
+}
+
+pub fn is_slow_query_log(line: &str) -> bool {
+    line.contains("SQLiteConnection") && line.contains(" took ")
+}
+
+/// The slow statement in a logcat line, when it ran on a database of
+/// `package`, at the line's time from `start_ms` on.
+pub fn parse_sqlite_line(line: &str, package: &str, clock: &ClockSync, start_ms: i64) -> Option<SlowQuery> {
+    let caps = SQLITE_REGEX.captures(line)?;
+    let database = caps[5].trim_end_matches(',').to_string();
+    if !database.contains(&format!("/{}/", package)) {
+        return None;
+    }
+    let host_ms = clock.host_time_ms(line, None).filter(|ms| *ms >= start_ms)?;
+    Some(SlowQuery {
+        time: clock.time_at(start_ms, host_ms),
+        source: QuerySource::Sqlite,
+        operation: caps[1].to_string(),
+        duration_ms: caps[2].parse().ok()?,
+        statement: caps.get(4).map_or("", |sql| sql.as_str()).to_string(),
+        database: Some(database),
+        sample_percent: None,
+    })
+}
+
+/// ContentResolver calls by `package` in `logcat -b events` output, from
+/// `start_ms` on. The fields are read from both ends since the selection
+/// in the middle may hold commas.
+pub fn parse_content_events(output: &str, package: &str, clock: &ClockSync, start_ms: i64) -> Vec<SlowQuery> {
+    let mut queries = Vec::new();
+    for line in output.lines() {
+        let Some(caps) = CONTENT_EVENT_REGEX.captures(line) else {
+            continue;
+        };
+        let Some(host_ms) = clock.host_time_ms(line, None).filter(|ms| *ms >= start_ms) else {
+            continue;
+        };
+        let fields: Vec<&str> = caps[2].split(',').collect();
+        let query = &caps[1] == "content_query_sample";
+        // query: uri, projection, selection, sort order, time, package, percent
+        // update: uri, operation, selection, time, package, percent
+        let (Some(uri), [.., duration, caller, percent]) = (fields.first(), fields.as_slice()) else {
+            continue;
+        };
+        if *caller != package {
+            continue;
+        }
+        let operation = if query { "query" } else { fields.get(1).copied().unwrap_or("update") };
+        let Ok(duration_ms) = duration.parse() else {
+            continue;
+        };
+        queries.push(SlowQuery {
+            time: clock.time_at(start_ms, host_ms),
+            source: QuerySource::ContentProvider,
+            operation: operation.to_string(),
+            duration_ms,
+            statement: uri.to_string(),
+            database: None,
+            sample_percent: percent.parse().ok(),
+        });
+    }
+    queries
+}
+
+#[derive(Serialize)]
+pub struct StatementStats {
+    pub source: QuerySource,
+    pub statement: String,
+    pub count: u64,
+    pub total_ms: u64,
+    pub max_ms: u64,
+}
+
+/// Per-statement totals, slowest single run first.
+pub fn by_statement(queries: &[SlowQuery]) -> Vec<StatementStats> {
+    let mut stats: BTreeMap<(&str, bool), StatementStats> = BTreeMap::new();
+    for query in queries {
+        let entry = stats.entry((&query.statement, query.source == QuerySource::Sqlite)).or_insert_with(|| StatementStats {
+            source: query.source,
+            statement: query.statement.clone(),
+            count: 0,
+            total_ms: 0,
+            max_ms: 0,
+        });
+        entry.count += 1;
+        entry.total_ms += query.duration_ms;
+        entry.max_ms = entry.max_ms.max(query.duration_ms);
+    }
+    let mut stats: Vec<StatementStats> = stats.into_values().collect();
+    stats.sort_by_key(|s| std::cmp::Reverse((s.max_ms, s.total_ms)));
+    stats
+}
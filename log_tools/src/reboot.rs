@@ -0,0 +1,31 @@
+//! Keeping a logcat capture going when the device reboots or drops off
+//! adb: the stream is restarted with `-T` at the last seen timestamp and a
+//! marker line separates the two streams.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// Changes on every boot, telling a reboot apart from an adb reconnect.
+pub const BOOT_ID_CMD: &str = "cat /proc/sys/kernel/random/boot_id";
+/// How long to wait for the device to come back before ending the capture.
+pub const RECONNECT_TIMEOUT_SECS: u64 = 600;
+/// Seconds between device state polls while waiting.
+pub const POLL_SECS: u64 = 2;
+
+// Leading "MM-DD HH:MM:SS.mmm" of `-v time` lines, and of binary-decoded
+// lines whose extra fraction digits `-T` does not accept.
+static TIMESTAMP_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\d\d-\d\d \d\d:\d\d:\d\d\.\d{3}").unwrap());
+
+/// The timestamp `logcat -T` should resume from after `line`.
+pub fn logcat_timestamp(line: &str) -> Option<&str> {
+    TIMESTAMP_REGEX.find(line).map(|m| m.as_str())
+}
+
+/// Line written into the capture where the streams are stitched together.
+pub fn stitch_line(rebooted: bool, since: Option<&str>) -> String {
+    let what = if rebooted { "device rebooted" } else { "adb reconnected" };
+    match since {
+        Some(since) => format!("--------- {}, logcat resumed from {} ---------\n", what, since),
+        None => format!("--------- {}, logcat restarted ---------\n", what),
+    }
+}
@@ -0,0 +1,98 @@
+//! `regex test`: the config's keyword regex and preset regexes compiled
+//! and run over sample lines, a saved log or the device's logcat buffer,
+//! before a long capture depends on them. The regex crate matches in
+//! linear time, so no pattern backtracks catastrophically; what can still
+//! go wrong is a pattern that does not compile, outgrows the compiled
+//! size limit, or is slow enough per line to fall behind a busy logcat,
+//! which the measured throughput shows.
+
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::LogAnalyzerConfig;
+
+pub const LOGCAT_DUMP_ARGS: &[&str] = &["logcat", "-d", "-v", "time"];
+/// Below this a pattern may not keep up with logcat bursts on a busy
+/// device.
+pub const MIN_MB_PER_SEC: f64 = 5.0;
+/// Lines are matched again until this much time went by, so short
+/// samples still give a stable throughput.
+const MIN_TIMING: Duration = Duration::from_millis(100);
+
+#[derive(Serialize)]
+pub struct PatternReport {
+    /// `keyword_regex`, or `preset <name>` for the config's presets.
+    pub name: String,
+    pub pattern: String,
+    /// Why the pattern does not compile; nothing else is measured then.
+    pub error: Option<String>,
+    pub matched: usize,
+    pub lines: usize,
+    pub mb_per_sec: f64,
+    pub slowest_line_us: f64,
+    /// The first matching lines.
+    pub examples: Vec<String>,
+}
+
+impl PatternReport {
+    pub fn is_slow(&self) -> bool {
+        self.error.is_none() && self.lines > 0 && self.mb_per_sec < MIN_MB_PER_SEC
+    }
+}
+
+/// The regexes a capture with `config` can use, by name.
+pub fn configured_patterns(config: &LogAnalyzerConfig) -> Vec<(String, String)> {
+    let mut patterns = vec![("keyword_regex".to_string(), config.keyword_regex.clone())];
+    patterns.extend(config.presets.iter().map(|(name, pattern)| (format!("preset {}", name), pattern.clone())));
+    patterns
+}
+
+pub fn check(name: &str, pattern: &str, lines: &[&str], examples: usize) -> PatternReport {
+    let mut report = PatternReport {
+        name: name.to_string(),
+        pattern: pattern.to_string(),
+        error: None,
+        matched: 0,
+        lines: lines.len(),
+        mb_per_sec: 0.0,
+        slowest_line_us: 0.0,
+        examples: Vec::new(),
+    };
+    let re = match Regex::new(pattern) {
+        Ok(re) => re,
+        Err(e) => {
+            report.error = Some(e.to_string());
+            return report;
+        }
+    };
+    // First, so the per-line times below run on a warm matcher.
+    let bytes: usize = lines.iter().map(|line| line.len() + 1).sum();
+    if bytes > 0 {
+        let started = Instant::now();
+        let mut passes = 0;
+        while passes == 0 || started.elapsed() < MIN_TIMING {
+            for line in lines {
+                std::hint::black_box(re.is_match(line));
+            }
+            passes += 1;
+        }
+        report.mb_per_sec = (bytes * passes) as f64 / (1024.0 * 1024.0) / started.elapsed().as_secs_f64();
+    }
+
+    let mut slowest = Duration::ZERO;
+    for line in lines {
+        let started = Instant::now();
+        let matched = re.is_match(line);
+        slowest = slowest.max(started.elapsed());
+        if matched {
+            report.matched += 1;
+            if report.examples.len() < examples {
+                report.examples.push(line.to_string());
+            }
+        }
+    }
+    report.slowest_line_us = slowest.as_secs_f64() * 1e6;
+    report
+}
@@ -0,0 +1,143 @@
+//! `--db FILE`: a SQLite database runs add their memory samples, thread
+//! snapshots and `.so` rows to, next to the usual files, so the history
+//! of many runs can be queried with SQL instead of collecting timestamped
+//! CSVs. Every run gets a row in `runs`; the other tables reference it by
+//! `run_id`. Columns carry the names and units of the CSV files.
+//!
+//! ```sql
+//! SELECT runs.started_at, max(total_pss) FROM memory_samples
+//! JOIN runs ON runs.id = run_id WHERE package = 'com.example.app' GROUP BY run_id;
+//! ```
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use rusqlite::{Connection, params};
+
+use crate::{MemorySample, SoMemoryInfo, ThreadInfo, ThreadSnapshot};
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started_at TEXT NOT NULL,
+    package TEXT NOT NULL,
+    serial TEXT,
+    command TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS memory_samples (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    timestamp INTEGER NOT NULL,
+    host_time_ms INTEGER NOT NULL,
+    device_time_ms INTEGER NOT NULL,
+    total_pss INTEGER NOT NULL,
+    native_heap INTEGER NOT NULL,
+    dalvik_heap INTEGER NOT NULL,
+    code INTEGER NOT NULL,
+    stack INTEGER NOT NULL,
+    graphics INTEGER NOT NULL,
+    private_dirty INTEGER NOT NULL,
+    shared_dirty INTEGER NOT NULL,
+    -- Derived metrics by name, as a JSON object.
+    derived TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS threads (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    -- Host time of the snapshot; NULL for a one-off listing.
+    host_time_ms INTEGER,
+    tid TEXT NOT NULL,
+    name TEXT NOT NULL,
+    state TEXT NOT NULL,
+    priority TEXT NOT NULL,
+    user_time TEXT NOT NULL,
+    system_time TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS so_memory (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    name TEXT NOT NULL,
+    pss INTEGER NOT NULL,
+    private_dirty INTEGER NOT NULL,
+    shared_dirty INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS memory_samples_run ON memory_samples(run_id);
+CREATE INDEX IF NOT EXISTS threads_run ON threads(run_id);
+CREATE INDEX IF NOT EXISTS so_memory_run ON so_memory(run_id);
+";
+
+pub struct RunsDb {
+    conn: Connection,
+}
+
+impl RunsDb {
+    /// Opens `path`, creating the file and tables if needed.
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Connection::open(path).with_context(|| format!("Cannot open database {}", path))?;
+        // The devices of a --devices run share the file.
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        conn.execute_batch(SCHEMA)?;
+        Ok(RunsDb { conn })
+    }
+
+    /// Adds a run and returns its ID.
+    pub fn start_run(&self, package: &str, serial: Option<&str>, command: &str) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO runs (started_at, package, serial, command) VALUES (?1, ?2, ?3, ?4)",
+            params![chrono::Local::now().to_rfc3339(), package, serial, command],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn insert_samples(&mut self, run_id: i64, samples: &[MemorySample]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut insert = tx.prepare(
+                "INSERT INTO memory_samples VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            )?;
+            for s in samples {
+                insert.execute(params![
+                    run_id,
+                    s.time.timestamp,
+                    s.time.host_time_ms,
+                    s.time.device_time_ms,
+                    s.total_pss,
+                    s.native_heap,
+                    s.dalvik_heap,
+                    s.code,
+                    s.stack,
+                    s.graphics,
+                    s.private_dirty,
+                    s.shared_dirty,
+                    serde_json::to_string(&s.derived)?,
+                ])?;
+            }
+        }
+        Ok(tx.commit()?)
+    }
+
+    /// Inserts a one-off thread listing, or the snapshots a session polled.
+    pub fn insert_threads(&mut self, run_id: i64, threads: Option<&[ThreadInfo]>, snapshots: &[ThreadSnapshot]) -> Result<()> {
+        let listings = threads.map(|threads| (None, threads)).into_iter();
+        let polled = snapshots.iter().map(|s| (Some(s.time.host_time_ms), s.threads.as_slice()));
+        let tx = self.conn.transaction()?;
+        {
+            let mut insert = tx.prepare("INSERT INTO threads VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)")?;
+            for (time, threads) in listings.chain(polled) {
+                for t in threads {
+                    insert.execute(params![run_id, time, t.tid, t.name, t.state, t.priority, t.user_time, t.system_time])?;
+                }
+            }
+        }
+        Ok(tx.commit()?)
+    }
+
+    pub fn insert_so_memory(&mut self, run_id: i64, so_libs: &[SoMemoryInfo]) -> Result<()> {
+        let tx = self.conn.transaction()?;
+        {
+            let mut insert = tx.prepare("INSERT INTO so_memory VALUES (?1, ?2, ?3, ?4, ?5)")?;
+            for so in so_libs {
+                insert.execute(params![run_id, so.name, so.pss, so.private_dirty, so.shared_dirty])?;
+            }
+        }
+        Ok(tx.commit()?)
+    }
+}
@@ -0,0 +1,83 @@
+//! Scripted scenarios: a JSON list of input steps, sleeps and checkpoints.
+//!
+//! ```json
+//! { "steps": [
+//!     { "action": "launch" },
+//!     { "action": "checkpoint", "name": "home" },
+//!     { "action": "tap", "x": 540, "y": 1200 },
+//!     { "action": "sleep", "ms": 2000 }
+//! ], "repeat": 100 }
+//! ```
+//!
+//! An optional `"matrix"` repeats the scenario for every configuration in
+//! it; see [`crate::matrix`].
+
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+
+use crate::bench::BenchOptions;
+use crate::console;
+use crate::input::{self, InputAction};
+use crate::matrix::ConfigMatrix;
+
+#[derive(Deserialize)]
+pub struct Scenario {
+    pub steps: Vec<Step>,
+    /// Number of passes over `steps`.
+    #[serde(default = "default_repeat")]
+    pub repeat: u32,
+    #[serde(default)]
+    pub matrix: Option<ConfigMatrix>,
+}
+
+fn default_repeat() -> u32 {
+    1
+}
+
+fn default_swipe_ms() -> u64 {
+    300
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Step {
+    Tap { x: u32, y: u32 },
+    Swipe { from: (u32, u32), to: (u32, u32), #[serde(default = "default_swipe_ms")] duration_ms: u64 },
+    Text { text: String },
+    Keyevent { key: String },
+    /// `path` uses the `x,y;x,y` syntax of `input gesture --path`.
+    Gesture { path: String, duration_ms: u64 },
+    Sleep { ms: u64 },
+    /// After a crash the scenario resumes from the most recent checkpoint.
+    Checkpoint { name: String },
+    /// Starts the app through its launcher activity.
+    Launch,
+}
+
+impl Step {
+    pub fn input_action(&self) -> Result<Option<InputAction>> {
+        Ok(Some(match self {
+            Step::Tap { x, y } => InputAction::Tap { x: *x, y: *y },
+            Step::Swipe { from, to, duration_ms } => InputAction::Swipe { from: *from, to: *to, duration_ms: *duration_ms },
+            Step::Text { text } => InputAction::Text(text.clone()),
+            Step::Keyevent { key } => InputAction::KeyEvent(key.clone()),
+            Step::Gesture { path, duration_ms } => {
+                InputAction::Gesture { points: input::parse_points(path)?, duration_ms: *duration_ms }
+            }
+            Step::Sleep { .. } | Step::Checkpoint { .. } | Step::Launch => return Ok(None),
+        }))
+    }
+}
+
+pub fn load_scenario(path: &str) -> Result<Scenario> {
+    Ok(serde_json::from_str(&console::read_text(path)?)?)
+}
+
+/// Passes record markers, not values, so `--discard-outliers` has
+/// nothing to filter. `--warmup` and `--min-iterations` add passes.
+pub fn check_bench_options(bench: &BenchOptions) -> Result<()> {
+    if bench.discard_outliers {
+        return Err(anyhow!("scenario records no per-pass values; --discard-outliers applies to startup, eviction, ab-test and bisect"));
+    }
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! Versioned JSON output files. Every file is written as
+//!
+//! ```json
+//! { "schema_version": 1, "kind": "memory_samples", "data": ... }
+//! ```
+//!
+//! and read back through `read_json_file`, which upgrades files written by
+//! older releases step by step. Files from before versioning hold the bare
+//! `data` and read as version 0.
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+
+use crate::console;
+
+pub const FILE_SCHEMA_VERSION: u64 = 1;
+
+/// `MIGRATIONS[n]` upgrades a version `n` document to version `n + 1`.
+const MIGRATIONS: &[fn(Value, &str) -> Result<Value>] = &[wrap_bare_data];
+
+pub fn to_versioned_json<T: Serialize + ?Sized>(kind: &str, data: &T) -> Result<String> {
+    let document = json!({ "schema_version": FILE_SCHEMA_VERSION, "kind": kind, "data": data });
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+/// Reads a file of the given kind written by any release of the tool.
+pub fn read_json_file<T: DeserializeOwned>(path: &str, kind: &str) -> Result<T> {
+    let value: Value = serde_json::from_str(&console::read_text(path)?)?;
+    let value = upgrade(value, kind).map_err(|e| anyhow!("{}: {}", path, e))?;
+    let found = value["kind"].as_str().unwrap_or_default();
+    if found != kind {
+        return Err(anyhow!("{} holds {:?} data, expected {:?}", path, found, kind));
+    }
+    Ok(serde_json::from_value(value["data"].clone())?)
+}
+
+/// Reads a file of whatever kind it holds, returning the kind and data.
+/// The kind of a version 0 file is told from the shape of its data.
+pub fn read_any(path: &str) -> Result<(String, Value)> {
+    let value: Value = serde_json::from_str(&console::read_text(path)?)?;
+    let kind = match value.get("schema_version") {
+        Some(_) => "",
+        None => bare_kind(&value).ok_or_else(|| anyhow!("{} predates schema versioning and its kind cannot be told from its data", path))?,
+    };
+    let mut value = upgrade(value, kind).map_err(|e| anyhow!("{}: {}", path, e))?;
+    let kind = value["kind"].as_str().unwrap_or_default().to_string();
+    Ok((kind, value["data"].take()))
+}
+
+pub fn upgrade(mut value: Value, kind: &str) -> Result<Value> {
+    let mut version = value.get("schema_version").and_then(Value::as_u64).unwrap_or(0);
+    if version > FILE_SCHEMA_VERSION {
+        return Err(anyhow!("schema version {} is newer than this tool supports ({})", version, FILE_SCHEMA_VERSION));
+    }
+    while version < FILE_SCHEMA_VERSION {
+        value = MIGRATIONS[version as usize](value, kind)?;
+        version += 1;
+    }
+    Ok(value)
+}
+
+/// The kinds `read_any` callers compare, by shape: memory samples are an
+/// array of objects with a `total_pss`, iterations an object of number
+/// arrays.
+fn bare_kind(value: &Value) -> Option<&'static str> {
+    match value {
+        Value::Array(samples) if samples.first().is_some_and(|s| s.get("total_pss").is_some()) => Some("memory_samples"),
+        Value::Object(series) if !series.is_empty() && series.values().all(|v| v.as_array().is_some_and(|a| a.iter().all(Value::is_number))) => {
+            Some("iterations")
+        }
+        _ => None,
+    }
+}
+
+/// Version 0 files are the bare payload; the kind is taken on trust.
+fn wrap_bare_data(value: Value, kind: &str) -> Result<Value> {
+    Ok(json!({ "schema_version": 1, "kind": kind, "data": value }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_any_of(name: &str, text: &str) -> Result<(String, Value)> {
+        let path = std::env::temp_dir().join(format!("schema_test_{}_{}.json", std::process::id(), name));
+        std::fs::write(&path, text).unwrap();
+        let result = read_any(&path.to_string_lossy());
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn reads_unversioned_files_as_version_0() {
+        let (kind, data) = read_any_of("samples", r#"[{"timestamp": 1, "total_pss": 1000}]"#).unwrap();
+        assert_eq!(kind, "memory_samples");
+        assert_eq!(data[0]["total_pss"], 1000);
+        let (kind, _) = read_any_of("iterations", r#"{"startup_ms": [310, 305]}"#).unwrap();
+        assert_eq!(kind, "iterations");
+        assert!(read_any_of("unknown", r#"{"name": "x"}"#).is_err());
+    }
+
+    #[test]
+    fn reads_versioned_files() {
+        let text = to_versioned_json("iterations", &serde_json::json!({ "pss": [1.0] })).unwrap();
+        let (kind, data) = read_any_of("versioned", &text).unwrap();
+        assert_eq!(kind, "iterations");
+        assert_eq!(data["pss"][0], 1.0);
+    }
+}
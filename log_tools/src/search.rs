@@ -0,0 +1,156 @@
+//! `search`: grep over the logs of stored sessions. Every directory under
+//! the search roots that holds logs is a session, named by its path.
+//! Compressed captures (`.gz`, `.zst`) are read transparently and ANR
+//! traces count towards their parent session.
+
+use std::collections::BTreeSet;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use indicatif::ProgressBar;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use regex::bytes::Regex as BytesRegex;
+use serde::Serialize;
+
+use crate::{anr, offline, reboot};
+
+const LOG_EXTENSIONS: &[&str] = &["txt", "log"];
+
+// "[   12.345678] ..." in dmesg, console-ramoops and last_kmsg.
+static KERNEL_TIMESTAMP_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\[\s*\d+\.\d+\]").unwrap());
+
+#[derive(Serialize)]
+pub struct SearchMatch {
+    pub session: String,
+    pub file: String,
+    pub line: u64,
+    /// Logcat wall-clock or kernel uptime stamp at the start of the line.
+    pub timestamp: Option<String>,
+    pub text: String,
+}
+
+/// A log file of one session.
+pub struct SessionLog {
+    pub session: String,
+    /// Path relative to the session directory.
+    pub file: String,
+    pub path: PathBuf,
+}
+
+/// Log files under `root`, sorted by session and then file name. Hidden
+/// entries and build output are skipped.
+pub fn session_logs(root: &Path) -> Result<Vec<SessionLog>> {
+    let mut found = Vec::new();
+    collect_into(root, Path::new(""), None, &mut found)?;
+    found.sort_by(|a, b| (&a.session, &a.file).cmp(&(&b.session, &b.file)));
+    Ok(found)
+}
+
+fn collect_into(root: &Path, relative: &Path, trace_session: Option<&Path>, found: &mut Vec<SessionLog>) -> Result<()> {
+    for entry in std::fs::read_dir(root.join(relative))? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || name == "target" {
+            continue;
+        }
+        let path = relative.join(&name);
+        if entry.file_type()?.is_dir() {
+            let traces = trace_session.or((name == anr::ANR_DIR).then_some(relative));
+            collect_into(root, &path, traces, found)?;
+        } else if trace_session.is_some() || is_log_name(&name) {
+            let session_dir = trace_session.unwrap_or(relative);
+            let file = path.strip_prefix(session_dir).unwrap_or(&path);
+            found.push(SessionLog {
+                session: display_session(root, session_dir),
+                file: file.to_string_lossy().replace('\\', "/"),
+                path: root.join(&path),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// `dir` under `root`, without a leading "./" for the current directory.
+fn display_session(root: &Path, dir: &Path) -> String {
+    let path = match (root == Path::new("."), dir == Path::new("")) {
+        (_, true) => root.to_path_buf(),
+        (true, false) => dir.to_path_buf(),
+        (false, false) => root.join(dir),
+    };
+    path.to_string_lossy().replace('\\', "/")
+}
+
+fn is_log_name(name: &str) -> bool {
+    let name = name.strip_suffix(".gz").or_else(|| name.strip_suffix(".zst")).unwrap_or(name);
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| LOG_EXTENSIONS.contains(&ext))
+}
+
+/// The logcat or kernel timestamp a line starts with.
+pub fn line_timestamp(line: &str) -> Option<&str> {
+    reboot::logcat_timestamp(line).or_else(|| KERNEL_TIMESTAMP_REGEX.find(line).map(|m| m.as_str()))
+}
+
+/// What a search went through.
+#[derive(Default)]
+pub struct SearchSummary {
+    pub files: usize,
+    /// Sessions with at least one match.
+    pub sessions: BTreeSet<String>,
+    pub matches: u64,
+}
+
+/// Searches the session logs under each of `roots`, handing every match
+/// to `on_match`. A log that cannot be read to its end, such as the
+/// truncated capture of a crashed run, is warned about and skipped.
+pub fn search_sessions<'a>(roots: impl IntoIterator<Item = &'a String>, re: &BytesRegex, mut on_match: impl FnMut(SearchMatch)) -> Result<SearchSummary> {
+    let mut summary = SearchSummary::default();
+    for root in roots {
+        for log in session_logs(Path::new(root))? {
+            summary.files += 1;
+            let searched = search_log(&log, re, |m| {
+                summary.sessions.insert(m.session.clone());
+                summary.matches += 1;
+                on_match(m);
+                Ok(())
+            });
+            if let Err(e) = searched {
+                warn!(format!("{} could not be read to the end: {}", log.path.display(), e));
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// Calls `on_match` for each line of `log` matching `re`; returns the
+/// number of lines read.
+pub fn search_log(log: &SessionLog, re: &BytesRegex, mut on_match: impl FnMut(SearchMatch) -> Result<()>) -> Result<u64> {
+    let path = log.path.to_string_lossy();
+    let mut reader = BufReader::new(offline::open_log(&path, ProgressBar::hidden())?);
+    let mut buf = Vec::new();
+    let mut line = 0;
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(line);
+        }
+        line += 1;
+        let bytes = buf.strip_suffix(b"\n").unwrap_or(&buf);
+        let bytes = bytes.strip_suffix(b"\r").unwrap_or(bytes);
+        if !re.is_match(bytes) {
+            continue;
+        }
+        let text = String::from_utf8_lossy(bytes);
+        on_match(SearchMatch {
+            session: log.session.clone(),
+            file: log.file.clone(),
+            line,
+            timestamp: line_timestamp(&text).map(str::to_string),
+            text: text.into_owned(),
+        })?;
+    }
+}
@@ -0,0 +1,200 @@
+//! SELinux `avc: denied` messages of the target app, collected from the
+//! logcat stream (logd forwards the kernel audit log), deduplicated, with
+//! audit2allow-style rules as context for the policy owner.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+/// Process labels; toolbox `ps` has no -A.
+pub const DOMAIN_PS_CMD: &str = "ps -A -Z 2>/dev/null || ps -Z";
+/// Paths kept per denial as examples.
+const MAX_SAMPLES: usize = 5;
+
+// "avc: denied { read open } for pid=123 comm="com.example.app" name="stat"
+//  dev="proc" ino=4026 scontext=u:r:untrusted_app:s0:c94,c256,c512,c768
+//  tcontext=u:object_r:proc_stat:s0 tclass=file permissive=0 app=com.example.app"
+static DENIAL_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"avc:\s+denied\s+\{ ([^}]+) \} for (.*?)\bscontext=(\S+) tcontext=(\S+) tclass=(\S+)(?: permissive=(\d))?").unwrap()
+});
+static FIELD_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"\b(comm|name|path)="([^"]*)""#).unwrap());
+
+pub fn is_denial(line: &str) -> bool {
+    line.contains("avc:") && line.contains("denied")
+}
+
+/// SELinux label of the running `package` from `ps -Z` output.
+pub fn parse_domain(ps_output: &str, package: &str) -> Option<String> {
+    ps_output.lines().find_map(|line| {
+        let columns: Vec<&str> = line.split_whitespace().collect();
+        (columns.len() > 1 && columns.last() == Some(&package)).then(|| columns[0].to_string())
+    })
+}
+
+/// The type of a context, e.g. "untrusted_app" for
+/// "u:r:untrusted_app:s0:c94,c256".
+fn context_type(context: &str) -> &str {
+    context.split(':').nth(2).unwrap_or(context)
+}
+
+#[derive(Serialize)]
+pub struct Denial {
+    pub permissions: Vec<String>,
+    pub scontext: String,
+    pub tcontext: String,
+    pub tclass: String,
+    /// Logged in permissive mode, i.e. the access was not actually blocked.
+    pub permissive: bool,
+    pub count: u64,
+    /// Distinct `name=` / `path=` values, up to five.
+    pub samples: BTreeSet<String>,
+}
+
+#[derive(Serialize)]
+pub struct SelinuxReport {
+    pub domain: Option<String>,
+    pub denials: Vec<Denial>,
+    /// One audit2allow-style rule per source type, target type and class.
+    /// App domains are usually covered by neverallow rules, so these say
+    /// what was accessed rather than what to add to the policy.
+    pub suggested_rules: Vec<String>,
+}
+
+pub struct SelinuxTracker {
+    package: String,
+    /// Kernel `comm` of the app's main process: the package name cut to 15 bytes.
+    comm: String,
+    domain: Option<String>,
+    denials: BTreeMap<(String, String, String, String), Denial>,
+}
+
+impl SelinuxTracker {
+    pub fn new(package: &str, domain: Option<String>) -> Self {
+        let start = package.len().saturating_sub(15);
+        let comm = package.get(start..).unwrap_or(package).to_string();
+        SelinuxTracker { package: package.to_string(), comm, domain, denials: BTreeMap::new() }
+    }
+
+    /// Records the denial in `line` when it concerns the app: its exact
+    /// context (including MLS categories), its `app=` tag on Android 10+, or
+    /// its process name.
+    pub fn observe(&mut self, line: &str) {
+        let Some(caps) = DENIAL_REGEX.captures(line) else {
+            return;
+        };
+        let fields: BTreeMap<&str, &str> = FIELD_REGEX
+            .captures_iter(&caps[2])
+            .map(|c| (c.get(1).unwrap().as_str(), c.get(2).unwrap().as_str()))
+            .collect();
+        let ours = self.domain.as_deref() == Some(&caps[3])
+            || line.contains(&format!("app={}", self.package))
+            || fields.get("comm") == Some(&self.comm.as_str());
+        if !ours {
+            return;
+        }
+        let mut permissions: Vec<String> = caps[1].split_whitespace().map(str::to_string).collect();
+        permissions.sort();
+        let key = (permissions.join(" "), caps[3].to_string(), caps[4].to_string(), caps[5].to_string());
+        if !self.denials.contains_key(&key) {
+            info!("SELinux denial: {{ {} }} {} -> {} ({})", &caps[1], context_type(&caps[3]), &caps[4], &caps[5]);
+        }
+        let denial = self.denials.entry(key).or_insert_with(|| Denial {
+            permissions,
+            scontext: caps[3].to_string(),
+            tcontext: caps[4].to_string(),
+            tclass: caps[5].to_string(),
+            permissive: caps.get(6).is_some_and(|p| p.as_str() == "1"),
+            count: 0,
+            samples: BTreeSet::new(),
+        });
+        denial.count += 1;
+        if let Some(sample) = fields.get("path").or(fields.get("name")) {
+            if denial.samples.len() < MAX_SAMPLES {
+                denial.samples.insert(sample.to_string());
+            }
+        }
+    }
+
+    pub fn report(self) -> SelinuxReport {
+        let mut rules: BTreeMap<(String, String, String), BTreeSet<String>> = BTreeMap::new();
+        for denial in self.denials.values() {
+            let key = (context_type(&denial.scontext).to_string(), context_type(&denial.tcontext).to_string(), denial.tclass.clone());
+            rules.entry(key).or_default().extend(denial.permissions.iter().cloned());
+        }
+        let suggested_rules = rules
+            .into_iter()
+            .map(|((source, target, class), perms)| {
+                format!("allow {} {}:{} {{ {} }};", source, target, class, perms.into_iter().collect::<Vec<_>>().join(" "))
+            })
+            .collect();
+        let mut denials: Vec<Denial> = self.denials.into_values().collect();
+        denials.sort_by_key(|denial| std::cmp::Reverse(denial.count));
+        SelinuxReport { domain: self.domain, denials, suggested_rules }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOMAIN: &str = "u:r:untrusted_app:s0:c94,c256,c512,c768";
+
+    #[test]
+    fn domain_from_ps_z() {
+        let ps = "\
+LABEL                          USER           PID  PPID     VSZ    RSS WCHAN            ADDR S NAME
+u:r:zygote:s0                  root           612     1 14632448 128760 0                  0 S zygote64
+u:r:untrusted_app:s0:c94,c256,c512,c768 u0_a94 4243 612 14902312 98112 0              0 S com.example.app:remote
+u:r:untrusted_app:s0:c94,c256,c512,c768 u0_a94 4242 612 15231800 201344 0             0 S com.example.app
+";
+        assert_eq!(parse_domain(ps, "com.example.app").as_deref(), Some(DOMAIN));
+        assert_eq!(parse_domain(ps, "com.example"), None);
+    }
+
+    #[test]
+    fn denials_of_the_app_are_grouped() {
+        let logcat = format!(
+            "\
+10-16 12:00:01.234  4242  4242 W com.example.app: type=1400 audit(0.0:1234): avc: denied {{ read }} for name=\"stat\" dev=\"proc\" ino=4026531 scontext={d} tcontext=u:object_r:proc_stat:s0 tclass=file permissive=0 app=com.example.app
+10-16 12:00:01.240  4242  4242 W com.example.app: type=1400 audit(0.0:1235): avc: denied {{ read }} for name=\"stat\" dev=\"proc\" ino=4026531 scontext={d} tcontext=u:object_r:proc_stat:s0 tclass=file permissive=0 app=com.example.app
+10-16 12:00:01.300  4242  4260 W RenderThread: type=1400 audit(0.0:1236): avc: denied {{ open read }} for path=\"/sys/class/kgsl/kgsl-3d0/gpuclk\" dev=\"sysfs\" ino=33012 scontext={d} tcontext=u:object_r:sysfs_kgsl:s0 tclass=file permissive=0 app=com.example.app
+10-16 12:00:02.000   612   612 E SELinux : avc:  denied  {{ find }} for pid=4242 uid=10094 name=vendor.perf scontext={d} tcontext=u:object_r:vendor_perf_service:s0 tclass=service_manager permissive=0
+10-16 12:00:02.500  5120  5120 W com.other.app: type=1400 audit(0.0:1240): avc: denied {{ read }} for name=\"stat\" dev=\"proc\" ino=4026531 scontext=u:r:untrusted_app:s0:c95,c256,c512,c768 tcontext=u:object_r:proc_stat:s0 tclass=file permissive=0 app=com.other.app
+",
+            d = DOMAIN
+        );
+        let mut tracker = SelinuxTracker::new("com.example.app", Some(DOMAIN.to_string()));
+        logcat.lines().filter(|line| is_denial(line)).for_each(|line| tracker.observe(line));
+        let report = tracker.report();
+
+        let denials: Vec<(String, &str, u64)> =
+            report.denials.iter().map(|d| (d.permissions.join(" "), context_type(&d.tcontext), d.count)).collect();
+        assert_eq!(
+            denials,
+            [("read".to_string(), "proc_stat", 2), ("find".to_string(), "vendor_perf_service", 1), ("open read".to_string(), "sysfs_kgsl", 1)]
+        );
+        assert_eq!(report.denials[0].samples, BTreeSet::from(["stat".to_string()]));
+        assert_eq!(report.denials[2].samples, BTreeSet::from(["/sys/class/kgsl/kgsl-3d0/gpuclk".to_string()]));
+        assert_eq!(
+            report.suggested_rules,
+            [
+                "allow untrusted_app proc_stat:file { read };",
+                "allow untrusted_app sysfs_kgsl:file { open read };",
+                "allow untrusted_app vendor_perf_service:service_manager { find };",
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_comm_matches_without_a_domain() {
+        // The kernel keeps the last 15 bytes of a long process name.
+        let line = "10-16 12:00:03.000   800   800 I auditd  : type=1400 audit(0.0:88): avc: denied { write } for comm=\"mple.longername\" name=\"cache\" dev=\"dm-5\" ino=81 scontext=u:r:untrusted_app:s0:c12,c257,c512,c768 tcontext=u:object_r:system_data_file:s0 tclass=dir permissive=1";
+        let mut tracker = SelinuxTracker::new("com.example.longername", None);
+        tracker.observe(line);
+        let report = tracker.report();
+        assert_eq!(report.denials.len(), 1);
+        assert!(report.denials[0].permissive);
+    }
+}
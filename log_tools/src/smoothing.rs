@@ -0,0 +1,18 @@
+//! `--smooth <window>`: centered moving average of plotted series, for
+//! readable trend lines out of noisy 1-second samples. Only the drawn
+//! lines are smoothed; CSV and JSON exports keep the raw values.
+
+/// Each point's value replaced by the mean of the `window` points centered
+/// on it (an even window takes one more). Near the ends the window shrinks
+/// symmetrically, so the line keeps its length and its first and last
+/// measured values.
+pub fn moving_average(points: &[(f64, f64)], window: usize) -> Vec<(f64, f64)> {
+    let radius = window / 2;
+    (0..points.len())
+        .map(|i| {
+            let r = radius.min(i).min(points.len() - 1 - i);
+            let span = &points[i - r..=i + r];
+            (points[i].0, span.iter().map(|(_, y)| y).sum::<f64>() / span.len() as f64)
+        })
+        .collect()
+}
@@ -0,0 +1,77 @@
+//! Split APKs of an installed package (base, config and Play Feature
+//! Delivery splits): on-disk sizes, bundled native libraries and whether
+//! the running process has each split mapped.
+
+use std::collections::HashSet;
+use std::io::Cursor;
+
+use anyhow::Result;
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct NativeLib {
+    /// Entry path inside the APK, e.g. "lib/arm64-v8a/libfoo.so".
+    pub path: String,
+    pub size_bytes: u64,
+}
+
+#[derive(Serialize)]
+pub struct SplitApk {
+    /// "base" or the split name, e.g. "config.arm64_v8a" or "feature_camera".
+    pub name: String,
+    pub path: String,
+    pub size_bytes: u64,
+    pub native_libs: Vec<NativeLib>,
+    /// `None` when the app is not running or its maps are not readable.
+    pub loaded: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct SplitReport {
+    pub package: String,
+    pub instant: bool,
+    pub splits: Vec<SplitApk>,
+}
+
+/// APK paths from `pm path` lines such as "package:/data/app/~~x/com.foo-y/base.apk".
+pub fn parse_pm_path(output: &str) -> Vec<String> {
+    output.lines().filter_map(|line| line.trim().strip_prefix("package:")).map(str::to_string).collect()
+}
+
+pub fn split_name(path: &str) -> String {
+    let file = path.rsplit('/').next().unwrap_or(path);
+    let stem = file.strip_suffix(".apk").unwrap_or(file);
+    stem.strip_prefix("split_").unwrap_or(stem).to_string()
+}
+
+/// Sizes from `stat -c '%s %n'`, in the order of the paths given.
+pub fn parse_sizes(output: &str, paths: &[String]) -> Vec<u64> {
+    paths
+        .iter()
+        .map(|path| {
+            output
+                .lines()
+                .find_map(|line| line.trim().split_once(' ').filter(|(_, name)| name == path))
+                .and_then(|(size, _)| size.parse().ok())
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+/// `.so` entries under `lib/` with their uncompressed sizes.
+pub fn native_libs(apk: Vec<u8>) -> Result<Vec<NativeLib>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(apk))?;
+    let mut libs = Vec::new();
+    for i in 0..archive.len() {
+        let entry = archive.by_index_raw(i)?;
+        if entry.name().starts_with("lib/") && entry.name().ends_with(".so") {
+            libs.push(NativeLib { path: entry.name().to_string(), size_bytes: entry.size() });
+        }
+    }
+    Ok(libs)
+}
+
+/// Paths of the files mapped in `/proc/<pid>/maps`.
+pub fn mapped_paths(maps: &str) -> HashSet<&str> {
+    maps.lines().filter_map(|line| line.split_whitespace().nth(5)).filter(|path| path.starts_with('/')).collect()
+}
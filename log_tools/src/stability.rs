@@ -0,0 +1,286 @@
+//! Crash and ANR rates of a session, the stability KPIs crash reporting
+//! consoles track, so a lab run reads in the same terms: the app's Java
+//! crashes, native crashes and ANRs from logcat per hour of session time,
+//! and per pass of a scenario. Each run writes `stability_<timestamp>.json`,
+//! sessions without any crash included, since they count towards the rate
+//! too; `trend` lines the runs of a project up over time.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{addresses, filters, pipeline, schema};
+
+pub const STABILITY_FILE_STEM: &str = "stability";
+const SECS_PER_HOUR: f64 = 3600.0;
+/// Sessions shorter than this get no per-hour rates: one crash in the
+/// first minute would read as 60 an hour.
+pub const MIN_RATE_SECS: f64 = 600.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    Crash,
+    NativeCrash,
+    Anr,
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Counts {
+    pub crashes: u64,
+    pub native_crashes: u64,
+    pub anrs: u64,
+}
+
+impl Counts {
+    fn add(&mut self, kind: Kind) {
+        match kind {
+            Kind::Crash => self.crashes += 1,
+            Kind::NativeCrash => self.native_crashes += 1,
+            Kind::Anr => self.anrs += 1,
+        }
+    }
+
+    /// Java and native crashes.
+    pub fn all_crashes(&self) -> u64 {
+        self.crashes + self.native_crashes
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct StabilitySummary {
+    pub package: String,
+    /// Host wall clock at the start, ms since the Unix epoch.
+    pub start_ms: i64,
+    pub duration_secs: f64,
+    #[serde(flatten)]
+    pub counts: Counts,
+    /// Java and native crashes per hour of session time; none for
+    /// sessions shorter than [`MIN_RATE_SECS`].
+    pub crashes_per_hour: Option<f64>,
+    pub anrs_per_hour: Option<f64>,
+    /// Counts in each started hour of the session, the first hour first.
+    pub by_hour: Vec<Counts>,
+    /// Counts in each scenario pass, warm-up passes included; empty
+    /// outside scenarios.
+    #[serde(default)]
+    pub by_pass: Vec<Counts>,
+    #[serde(default)]
+    pub warmup_passes: u32,
+}
+
+/// `count` per hour of `duration_secs`, if that is long enough to tell.
+pub fn per_hour(count: u64, duration_secs: f64) -> Option<f64> {
+    (duration_secs >= MIN_RATE_SECS).then(|| count as f64 / (duration_secs / SECS_PER_HOUR))
+}
+
+/// A rate as printed, "n/a" when the session was too short for one.
+pub fn format_rate(rate: Option<f64>) -> String {
+    rate.map_or_else(|| "n/a".to_string(), |rate| format!("{:.2}", rate))
+}
+
+/// Whether the process `name` belongs to `package`, its `:remote` style
+/// processes included.
+fn is_app_process(name: &str, package: &str) -> bool {
+    name.strip_prefix(package).is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+}
+
+/// Lines the tracker reads: the start of a crash or ANR report, and the
+/// line naming the process of a Java crash.
+pub fn is_report_line(line: &str) -> bool {
+    pipeline::crash_trigger(line).is_some() || addresses::is_native_crash_line(line) || is_crash_process_line(line)
+}
+
+fn is_crash_process_line(line: &str) -> bool {
+    filters::line_tag(line) == Some("AndroidRuntime") && line.contains("): Process: ")
+}
+
+/// Reads crash and ANR reports of one app out of logcat lines.
+#[derive(Default)]
+pub struct ReportReader {
+    package: String,
+    /// A "FATAL EXCEPTION" line went by; the next process line says whose.
+    java_crash: bool,
+}
+
+impl ReportReader {
+    pub fn new(package: &str) -> Self {
+        ReportReader { package: package.to_string(), java_crash: false }
+    }
+
+    /// The report `line` completes, if it is one of the app's.
+    pub fn read(&mut self, line: &str) -> Option<Kind> {
+        if line.contains("FATAL EXCEPTION") {
+            self.java_crash = true;
+            return None;
+        }
+        if self.java_crash && is_crash_process_line(line) {
+            self.java_crash = false;
+            let process = line.split_once("): Process: ")?.1.split(',').next()?.trim();
+            return is_app_process(process, &self.package).then_some(Kind::Crash);
+        }
+        if let Some((_, rest)) = line.split_once("ANR in ") {
+            let process = rest.split_whitespace().next().unwrap_or_default();
+            return is_app_process(process, &self.package).then_some(Kind::Anr);
+        }
+        addresses::fatal_signal_pid(line, &self.package).map(|_| Kind::NativeCrash)
+    }
+}
+
+/// What killed the app according to the crash buffer dumped after it
+/// disappeared: its last report there, a Java crash when there is none.
+pub fn crash_buffer_kind(text: &str, package: &str) -> Kind {
+    let mut reader = ReportReader::new(package);
+    text.lines().filter_map(|line| reader.read(line)).filter(|kind| *kind != Kind::Anr).last().unwrap_or(Kind::Crash)
+}
+
+pub struct StabilityTracker {
+    package: String,
+    start_ms: i64,
+    /// Seconds into the session, what happened, and in which pass.
+    events: Vec<(f64, Kind, Option<usize>)>,
+}
+
+impl StabilityTracker {
+    pub fn new(package: &str, start_ms: i64) -> Self {
+        StabilityTracker { package: package.to_string(), start_ms, events: Vec::new() }
+    }
+
+    pub fn record(&mut self, kind: Kind, secs: f64, pass: Option<usize>) {
+        self.events.push((secs, kind, pass));
+    }
+
+    pub fn summary(&self, duration_secs: f64, passes: usize, warmup_passes: u32) -> StabilitySummary {
+        let mut counts = Counts::default();
+        let mut by_hour = vec![Counts::default(); ((duration_secs / SECS_PER_HOUR).ceil() as usize).max(1)];
+        let mut by_pass = vec![Counts::default(); passes];
+        for (secs, kind, pass) in &self.events {
+            counts.add(*kind);
+            let hour = ((secs / SECS_PER_HOUR) as usize).min(by_hour.len() - 1);
+            by_hour[hour].add(*kind);
+            if let Some(counts) = pass.and_then(|pass| by_pass.get_mut(pass)) {
+                counts.add(*kind);
+            }
+        }
+        StabilitySummary {
+            package: self.package.clone(),
+            start_ms: self.start_ms,
+            duration_secs,
+            counts,
+            crashes_per_hour: per_hour(counts.all_crashes(), duration_secs),
+            anrs_per_hour: per_hour(counts.anrs, duration_secs),
+            by_hour,
+            by_pass,
+            warmup_passes,
+        }
+    }
+}
+
+/// Stability files of several directories, oldest session first, with
+/// their totals.
+pub struct Trend {
+    pub sessions: Vec<(PathBuf, StabilitySummary)>,
+    pub duration_secs: f64,
+    pub counts: Counts,
+}
+
+pub fn trend<'a>(roots: impl IntoIterator<Item = &'a String>) -> Result<Trend> {
+    let mut sessions = Vec::new();
+    for root in roots {
+        sessions.extend(find_summaries(Path::new(root))?);
+    }
+    sessions.sort_by_key(|(_, summary)| summary.start_ms);
+    let (mut duration_secs, mut counts) = (0.0, Counts::default());
+    for (_, summary) in &sessions {
+        duration_secs += summary.duration_secs;
+        counts.crashes += summary.counts.crashes;
+        counts.native_crashes += summary.counts.native_crashes;
+        counts.anrs += summary.counts.anrs;
+    }
+    Ok(Trend { sessions, duration_secs, counts })
+}
+
+/// Stability files under `root`, oldest session first. Hidden entries and
+/// build output are skipped.
+pub fn find_summaries(root: &Path) -> Result<Vec<(PathBuf, StabilitySummary)>> {
+    let mut paths = Vec::new();
+    collect_into(root, &mut paths)?;
+    let mut found = Vec::new();
+    for path in paths {
+        let summary: StabilitySummary = schema::read_json_file(&path.to_string_lossy(), STABILITY_FILE_STEM)?;
+        found.push((path, summary));
+    }
+    found.sort_by_key(|(_, summary)| summary.start_ms);
+    Ok(found)
+}
+
+fn collect_into(dir: &Path, found: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || name == "target" {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            collect_into(&entry.path(), found)?;
+        } else if name.starts_with(&format!("{}_", STABILITY_FILE_STEM)) && name.ends_with(".json") {
+            found.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_cover_the_session_and_short_sessions_get_none() {
+        let mut tracker = StabilityTracker::new("com.example", 0);
+        tracker.record(Kind::Crash, 10.0, Some(0));
+        tracker.record(Kind::NativeCrash, 4000.0, Some(1));
+        tracker.record(Kind::Anr, 7199.0, None);
+        let summary = tracker.summary(7200.0, 2, 0);
+        assert_eq!(summary.crashes_per_hour, Some(1.0));
+        assert_eq!(summary.anrs_per_hour, Some(0.5));
+        assert_eq!(summary.by_hour.iter().map(|c| (c.all_crashes(), c.anrs)).collect::<Vec<_>>(), vec![(1, 0), (1, 1)]);
+        assert_eq!(summary.by_pass.iter().map(Counts::all_crashes).collect::<Vec<_>>(), vec![1, 1]);
+
+        let short = tracker.summary(MIN_RATE_SECS - 1.0, 0, 0);
+        assert_eq!((short.crashes_per_hour, short.anrs_per_hour), (None, None));
+        assert_eq!(format_rate(short.crashes_per_hour), "n/a");
+        assert_eq!(format_rate(per_hour(3, MIN_RATE_SECS)), "18.00");
+    }
+
+    #[test]
+    fn reports_of_other_apps_are_ignored() {
+        let mut reader = ReportReader::new("com.example");
+        let fatal = "10-16 12:00:00.000 E/AndroidRuntime(  123): FATAL EXCEPTION: main";
+        assert!(reader.read(fatal).is_none());
+        assert!(reader.read("10-16 12:00:00.001 E/AndroidRuntime(  123): Process: com.example.other, PID: 123").is_none());
+        assert!(reader.read(fatal).is_none());
+        let own = "10-16 12:00:01.001 E/AndroidRuntime(  124): Process: com.example:remote, PID: 124";
+        assert!(reader.read(own) == Some(Kind::Crash));
+        assert!(reader.read("10-16 12:00:02.000 E/ActivityManager(  500): ANR in com.example (com.example/.Main)") == Some(Kind::Anr));
+        assert!(reader.read("10-16 12:00:03.000 E/ActivityManager(  500): ANR in com.examples").is_none());
+    }
+
+    #[test]
+    fn trend_orders_sessions_of_all_directories_and_totals_them() {
+        let root = std::env::temp_dir().join(format!("stability_trend_{}", std::process::id()));
+        let (older, newer) = (root.join("nightly"), root.join("soak/pixel7"));
+        for (dir, start_ms, kind) in [(&newer, 1_700_003_600_000, Kind::Anr), (&older, 1_700_000_000_000, Kind::NativeCrash)] {
+            std::fs::create_dir_all(dir).unwrap();
+            let mut tracker = StabilityTracker::new("com.example", start_ms);
+            tracker.record(Kind::Crash, 60.0, None);
+            tracker.record(kind, 1200.0, None);
+            let summary = tracker.summary(1800.0, 0, 0);
+            std::fs::write(dir.join("stability_com.example_20231114_223320.json"), schema::to_versioned_json(STABILITY_FILE_STEM, &summary).unwrap()).unwrap();
+        }
+        let roots = [older.to_string_lossy().into_owned(), newer.to_string_lossy().into_owned()];
+        let trend = trend(roots.iter().rev()).unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(trend.sessions.iter().map(|(_, s)| s.start_ms).collect::<Vec<_>>(), [1_700_000_000_000, 1_700_003_600_000]);
+        assert_eq!((trend.duration_secs, trend.counts.crashes, trend.counts.native_crashes, trend.counts.anrs), (3600.0, 2, 1, 1));
+    }
+}
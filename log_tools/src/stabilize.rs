@@ -0,0 +1,119 @@
+//! Pre-measurement gate: waits until the device is cool, idle and not
+//! compiling or syncing in the background, so runs start from the same state.
+
+use clap::{Arg, ArgMatches};
+use serde::Serialize;
+
+/// Seconds between device state polls while waiting.
+pub const POLL_SECS: u64 = 5;
+
+/// Load average, CPU thermal zones, running dex2oat processes and active
+/// SyncManager syncs (which include Google Play services account syncs).
+pub const DEVICE_STATE_CMD: &str = "cat /proc/loadavg; \
+    for z in /sys/class/thermal/thermal_zone*; do echo \"thermal $(cat $z/type) $(cat $z/temp)\"; done; \
+    echo \"dex2oat $(pidof dex2oat dex2oat32 dex2oat64 | wc -w)\"; \
+    dumpsys content | grep 'Active Syncs'";
+
+/// Just the thermal zone part of `DEVICE_STATE_CMD`.
+pub const THERMAL_ZONES_CMD: &str = "for z in /sys/class/thermal/thermal_zone*; do echo \"thermal $(cat $z/type) $(cat $z/temp)\"; done";
+
+#[derive(Clone, Serialize)]
+pub struct StabilizeGate {
+    pub max_cpu_temp_c: f64,
+    pub max_load: f64,
+    pub timeout_secs: u64,
+}
+
+#[derive(Clone, Default, Serialize)]
+pub struct DeviceState {
+    /// Hottest CPU thermal zone; all zones when none is labelled as CPU.
+    pub cpu_temp_c: Option<f64>,
+    pub load_1m: f64,
+    pub dexopt_processes: u32,
+    pub active_syncs: u32,
+}
+
+#[derive(Serialize)]
+pub struct Stabilization {
+    pub stable: bool,
+    pub waited_secs: u64,
+    pub gate: StabilizeGate,
+    pub state: DeviceState,
+}
+
+pub fn args() -> [Arg; 4] {
+    [
+        Arg::new("stabilize").long("stabilize").help("Wait for a cool, idle device before measuring").action(clap::ArgAction::SetTrue),
+        Arg::new("max_cpu_temp").long("max-cpu-temp").value_name("CELSIUS").default_value("40").value_parser(clap::value_parser!(f64)),
+        Arg::new("max_load").long("max-load").value_name("LOAD").default_value("4").help("Highest 1-minute load average").value_parser(clap::value_parser!(f64)),
+        Arg::new("stabilize_timeout").long("stabilize-timeout").value_name("SECS").default_value("300").help("Measure anyway after waiting this long").value_parser(clap::value_parser!(u64)),
+    ]
+}
+
+impl StabilizeGate {
+    /// The gate configured by `args()`, or `None` without `--stabilize`.
+    pub fn from_matches(matches: &ArgMatches) -> Option<Self> {
+        matches.get_flag("stabilize").then(|| StabilizeGate {
+            max_cpu_temp_c: *matches.get_one::<f64>("max_cpu_temp").unwrap(),
+            max_load: *matches.get_one::<f64>("max_load").unwrap(),
+            timeout_secs: *matches.get_one::<u64>("stabilize_timeout").unwrap(),
+        })
+    }
+
+    /// Reasons the device is not ready yet; empty once it is.
+    pub fn blockers(&self, state: &DeviceState) -> Vec<String> {
+        let mut blockers = Vec::new();
+        if let Some(temp) = state.cpu_temp_c.filter(|t| *t > self.max_cpu_temp_c) {
+            blockers.push(format!("CPU {:.1}°C > {:.1}°C", temp, self.max_cpu_temp_c));
+        }
+        if state.load_1m > self.max_load {
+            blockers.push(format!("load {:.2} > {:.2}", state.load_1m, self.max_load));
+        }
+        if state.dexopt_processes > 0 {
+            blockers.push(format!("{} dex2oat process(es)", state.dexopt_processes));
+        }
+        if state.active_syncs > 0 {
+            blockers.push(format!("{} active sync(s)", state.active_syncs));
+        }
+        blockers
+    }
+}
+
+pub fn parse_device_state(output: &str) -> DeviceState {
+    let mut state = DeviceState { cpu_temp_c: parse_cpu_temp(output), ..Default::default() };
+    for (i, line) in output.lines().map(str::trim).enumerate() {
+        if i == 0 {
+            state.load_1m = line.split_whitespace().next().and_then(|v| v.parse().ok()).unwrap_or(0.0);
+        } else if let Some(count) = line.strip_prefix("dex2oat ") {
+            state.dexopt_processes = count.trim().parse().unwrap_or(0);
+        } else if let Some(count) = line.strip_prefix("Active Syncs:") {
+            state.active_syncs = count.trim().parse().unwrap_or(0);
+        }
+    }
+    state
+}
+
+/// Hottest CPU zone of the "thermal <type> <temp>" lines printed by
+/// `THERMAL_ZONES_CMD`; all zones when none is labelled as CPU.
+pub fn parse_cpu_temp(output: &str) -> Option<f64> {
+    let mut cpu_temps = Vec::new();
+    let mut all_temps = Vec::new();
+    for rest in output.lines().filter_map(|line| line.trim().strip_prefix("thermal ")) {
+        let mut parts = rest.split_whitespace();
+        let (Some(kind), Some(temp)) = (parts.next(), parts.next().and_then(|t| t.parse::<f64>().ok())) else {
+            continue;
+        };
+        // Most zones report millidegrees, a few whole degrees; disabled
+        // zones read negative or zero.
+        let celsius = if temp.abs() >= 1000.0 { temp / 1000.0 } else { temp };
+        if celsius <= 0.0 {
+            continue;
+        }
+        all_temps.push(celsius);
+        if kind.to_ascii_lowercase().contains("cpu") {
+            cpu_temps.push(celsius);
+        }
+    }
+    let temps = if cpu_temps.is_empty() { all_temps } else { cpu_temps };
+    temps.into_iter().reduce(f64::max)
+}
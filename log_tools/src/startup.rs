@@ -0,0 +1,66 @@
+//! Cold-start measurement with `am start -W` and the metrics builds are
+//! compared on.
+
+use anyhow::{Result, anyhow};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Metric {
+    /// `TotalTime` of a cold `am start -W`, in ms.
+    Startup,
+    /// Total PSS a few seconds after a cold start, in KB.
+    Pss,
+}
+
+impl Metric {
+    pub fn parse(name: &str) -> Option<Metric> {
+        match name {
+            "startup" => Some(Metric::Startup),
+            "pss" => Some(Metric::Pss),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Startup => "startup",
+            Metric::Pss => "pss",
+        }
+    }
+
+    pub fn value(&self, cold: &ColdStart) -> Option<u64> {
+        match self {
+            Metric::Startup => Some(cold.startup_ms),
+            Metric::Pss => cold.pss_kb,
+        }
+    }
+
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Metric::Startup => "ms",
+            Metric::Pss => "KB",
+        }
+    }
+}
+
+pub struct ColdStart {
+    pub startup_ms: u64,
+    pub pss_kb: Option<u64>,
+}
+
+/// Component of the launcher activity from
+/// `cmd package resolve-activity --brief`, whose last line is
+/// "com.example.app/.MainActivity".
+pub fn parse_launcher_activity(output: &str) -> Option<String> {
+    output.lines().map(str::trim).rfind(|line| line.contains('/')).map(str::to_string)
+}
+
+/// `TotalTime` from `am start -W` output (`WaitTime` on releases that
+/// lack it).
+pub fn parse_total_time(output: &str) -> Result<u64> {
+    for key in ["TotalTime:", "WaitTime:"] {
+        if let Some(value) = output.lines().find_map(|line| line.trim().strip_prefix(key)) {
+            return Ok(value.trim().parse()?);
+        }
+    }
+    Err(anyhow!("No TotalTime in am start output: {}", output.trim()))
+}
@@ -0,0 +1,229 @@
+//! Statistics for repeated-iteration measurements.
+
+use serde::Serialize;
+
+/// Significance level used for every verdict.
+pub const ALPHA: f64 = 0.05;
+/// Two-sided critical value of the standard normal at `ALPHA`.
+const Z_95: f64 = 1.959964;
+
+#[derive(Serialize)]
+pub struct MannWhitney {
+    pub u: f64,
+    /// Two-sided p-value from the normal approximation with tie
+    /// correction; reasonable from about 8 samples per group.
+    pub p_value: f64,
+}
+
+/// Mann-Whitney U test of whether `a` and `b` come from the same
+/// distribution.
+pub fn mann_whitney_u(a: &[f64], b: &[f64]) -> MannWhitney {
+    let (u, sigma) = u_statistic(a, b);
+    if sigma == 0.0 {
+        return MannWhitney { u, p_value: 1.0 };
+    }
+    let (n1, n2) = (a.len() as f64, b.len() as f64);
+    // Continuity-corrected z of the smaller U.
+    let z = ((n1 * n2 / 2.0 - u) - 0.5).max(0.0) / sigma;
+    MannWhitney { u, p_value: (2.0 * (1.0 - normal_cdf(z))).min(1.0) }
+}
+
+/// The smaller U and its standard deviation under the null hypothesis,
+/// with tie correction.
+fn u_statistic(a: &[f64], b: &[f64]) -> (f64, f64) {
+    let (n1, n2) = (a.len() as f64, b.len() as f64);
+    if a.is_empty() || b.is_empty() {
+        return (0.0, 0.0);
+    }
+    let mut all: Vec<(f64, bool)> = a.iter().map(|&v| (v, true)).chain(b.iter().map(|&v| (v, false))).collect();
+    all.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    // Average ranks over ties, collecting the tie correction term.
+    let mut rank_sum_a = 0.0;
+    let mut tie_term = 0.0;
+    let mut i = 0;
+    while i < all.len() {
+        let mut j = i;
+        while j + 1 < all.len() && all[j + 1].0 == all[i].0 {
+            j += 1;
+        }
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        let ties = (j - i + 1) as f64;
+        tie_term += ties * ties * ties - ties;
+        rank_sum_a += all[i..=j].iter().filter(|(_, in_a)| *in_a).count() as f64 * rank;
+        i = j + 1;
+    }
+
+    let u1 = rank_sum_a - n1 * (n1 + 1.0) / 2.0;
+    let n = n1 + n2;
+    let sigma = (n1 * n2 / 12.0 * ((n + 1.0) - tie_term / (n * (n - 1.0)))).sqrt();
+    (u1.min(n1 * n2 - u1), sigma)
+}
+
+/// Hodges-Lehmann shift of `b` against `a`, the median of all pairwise
+/// differences, with the 95% confidence interval that inverts the
+/// Mann-Whitney test: it leaves out zero about when that test finds a
+/// significant difference.
+fn shift_interval(a: &[f64], b: &[f64]) -> (f64, f64, f64) {
+    let mut differences: Vec<f64> = b.iter().flat_map(|y| a.iter().map(move |x| y - x)).collect();
+    if differences.is_empty() {
+        return (0.0, 0.0, 0.0);
+    }
+    differences.sort_by(f64::total_cmp);
+    let (_, sigma) = u_statistic(a, b);
+    let m = differences.len();
+    // The test rejects below this U; as many differences are cut off at
+    // each end as a rejected U can count. None with too few samples.
+    let critical = m as f64 / 2.0 - 0.5 - Z_95 * sigma;
+    let k = ((critical.ceil() - 1.0).max(0.0) as usize).min((m - 1) / 2);
+    (median(&differences), differences[k], differences[m - 1 - k])
+}
+
+/// B relative to A for one metric.
+#[derive(Serialize)]
+pub struct Comparison {
+    pub n_a: usize,
+    pub n_b: usize,
+    pub mean_a: f64,
+    pub mean_b: f64,
+    pub median_a: f64,
+    pub median_b: f64,
+    /// `mean_b - mean_a`.
+    pub delta: f64,
+    pub delta_percent: f64,
+    /// Hodges-Lehmann shift of B against A, the median of all pairwise
+    /// differences.
+    pub shift: f64,
+    /// 95% confidence interval of `shift` from the Mann-Whitney test
+    /// behind `significant`, so the two agree.
+    pub ci_low: f64,
+    pub ci_high: f64,
+    /// Hedges' g, the bias-corrected standardized mean difference.
+    pub effect_size: f64,
+    pub mann_whitney: MannWhitney,
+    /// Mann-Whitney p below `ALPHA`; rank-based so a single outlier
+    /// iteration cannot produce it alone.
+    pub significant: bool,
+}
+
+pub fn compare(a: &[f64], b: &[f64]) -> Comparison {
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let (var_a, var_b) = (variance(a), variance(b));
+    let (n_a, n_b) = (a.len() as f64, b.len() as f64);
+    let delta = mean_b - mean_a;
+    let (shift, ci_low, ci_high) = shift_interval(a, b);
+
+    let pooled = (((n_a - 1.0) * var_a + (n_b - 1.0) * var_b) / (n_a + n_b - 2.0)).sqrt();
+    let correction = 1.0 - 3.0 / (4.0 * (n_a + n_b) - 9.0);
+    let effect_size = if pooled > 0.0 { delta / pooled * correction } else { 0.0 };
+
+    let mann_whitney = mann_whitney_u(a, b);
+    Comparison {
+        n_a: a.len(),
+        n_b: b.len(),
+        mean_a,
+        mean_b,
+        median_a: median(a),
+        median_b: median(b),
+        delta,
+        delta_percent: if mean_a != 0.0 { delta / mean_a * 100.0 } else { 0.0 },
+        shift,
+        ci_low,
+        ci_high,
+        effect_size,
+        significant: mann_whitney.p_value < ALPHA,
+        mann_whitney,
+    }
+}
+
+/// Cohen's conventional labels for an effect size.
+pub fn effect_label(effect_size: f64) -> &'static str {
+    match effect_size.abs() {
+        g if g < 0.2 => "negligible",
+        g if g < 0.5 => "small",
+        g if g < 0.8 => "medium",
+        _ => "large",
+    }
+}
+
+pub fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() { 0.0 } else { values.iter().sum::<f64>() / values.len() as f64 }
+}
+
+/// Sample variance (n - 1 denominator).
+pub fn variance(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let m = mean(values);
+    values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / (values.len() - 1) as f64
+}
+
+pub fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    match sorted.len() {
+        0 => 0.0,
+        n if n % 2 == 1 => sorted[n / 2],
+        n => (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0,
+    }
+}
+
+/// Linear-interpolated percentile, `p` from 0 to 100.
+pub fn percentile(values: &[f64], p: f64) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let pos = p / 100.0 * (sorted.len() - 1) as f64;
+    let (lower, upper) = (pos.floor() as usize, pos.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (pos - lower as f64)
+}
+
+pub fn normal_cdf(x: f64) -> f64 {
+    0.5 * (1.0 + erf(x / std::f64::consts::SQRT_2))
+}
+
+/// Abramowitz and Stegun 7.1.26, accurate to about 1.5e-7.
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let poly = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let y = 1.0 - poly * (-x * x).exp();
+    if x >= 0.0 { y } else { -y }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verdict_and_interval_agree() {
+        let a: Vec<f64> = (0..12).map(|i| 100.0 + i as f64).collect();
+        let shifted: Vec<f64> = a.iter().map(|v| v + 20.0).collect();
+        let c = compare(&a, &shifted);
+        assert!(c.significant);
+        assert!(c.ci_low > 0.0 && c.ci_low <= c.shift && c.shift <= c.ci_high);
+        assert_eq!(c.shift, 20.0);
+
+        let c = compare(&a, &a);
+        assert!(!c.significant);
+        assert!(c.ci_low <= 0.0 && c.ci_high >= 0.0);
+    }
+
+    #[test]
+    fn one_outlier_does_not_decide() {
+        let a = [10.0, 11.0, 12.0, 13.0, 14.0, 15.0, 16.0, 17.0];
+        let mut b = a;
+        b[7] = 1000.0;
+        let c = compare(&a, &b);
+        assert!(!c.significant);
+        assert!(c.ci_low <= 0.0);
+    }
+
+    #[test]
+    fn identical_samples_have_p_one() {
+        assert_eq!(mann_whitney_u(&[5.0; 4], &[5.0; 4]).p_value, 1.0);
+        assert_eq!(mann_whitney_u(&[], &[1.0]).p_value, 1.0);
+    }
+}
@@ -0,0 +1,132 @@
+//! Plot colors and line widths, from the config's "plot_theme" section:
+//!
+//! ```json
+//! "plot_theme": {
+//!     "dark": true,
+//!     "palette": "colorblind",
+//!     "colors": ["#0072b2", "#d55e00"],
+//!     "line_width": 2
+//! }
+//! ```
+//!
+//! `colors` overrides the palette when set; series beyond its length
+//! wrap around.
+
+use anyhow::{Result, anyhow};
+use plotters::style::RGBColor;
+use serde::{Deserialize, Serialize};
+
+/// High-contrast defaults that stay readable on a white background.
+const DEFAULT_LIGHT: [RGBColor; 8] = [
+    RGBColor(214, 39, 40),
+    RGBColor(31, 119, 180),
+    RGBColor(44, 160, 44),
+    RGBColor(23, 190, 207),
+    RGBColor(148, 103, 189),
+    RGBColor(255, 127, 14),
+    RGBColor(0, 0, 0),
+    RGBColor(140, 86, 75),
+];
+
+const DEFAULT_DARK: [RGBColor; 8] = [
+    RGBColor(255, 99, 99),
+    RGBColor(100, 170, 255),
+    RGBColor(120, 220, 120),
+    RGBColor(80, 220, 230),
+    RGBColor(200, 160, 255),
+    RGBColor(255, 170, 60),
+    RGBColor(230, 230, 230),
+    RGBColor(220, 160, 140),
+];
+
+/// Okabe-Ito, distinguishable under the common color vision deficiencies.
+/// Its yellow is kept for dark backgrounds only and black for light ones.
+const COLORBLIND_LIGHT: [RGBColor; 8] = [
+    RGBColor(213, 94, 0),
+    RGBColor(0, 114, 178),
+    RGBColor(0, 158, 115),
+    RGBColor(86, 180, 233),
+    RGBColor(204, 121, 167),
+    RGBColor(230, 159, 0),
+    RGBColor(0, 0, 0),
+    RGBColor(153, 153, 153),
+];
+
+const COLORBLIND_DARK: [RGBColor; 8] = [
+    RGBColor(213, 94, 0),
+    RGBColor(86, 180, 233),
+    RGBColor(0, 158, 115),
+    RGBColor(204, 121, 167),
+    RGBColor(230, 159, 0),
+    RGBColor(240, 228, 66),
+    RGBColor(153, 153, 153),
+    RGBColor(0, 114, 178),
+];
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Palette {
+    #[default]
+    Default,
+    Colorblind,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlotTheme {
+    pub dark: bool,
+    pub palette: Palette,
+    /// Series colors as "#rrggbb", replacing the palette.
+    pub colors: Vec<String>,
+    pub line_width: u32,
+}
+
+impl Default for PlotTheme {
+    fn default() -> Self {
+        PlotTheme { dark: false, palette: Palette::Default, colors: Vec::new(), line_width: 2 }
+    }
+}
+
+impl PlotTheme {
+    pub fn background(&self) -> RGBColor {
+        if self.dark { RGBColor(30, 30, 30) } else { RGBColor(255, 255, 255) }
+    }
+
+    /// Text, axes and legend border.
+    pub fn foreground(&self) -> RGBColor {
+        if self.dark { RGBColor(230, 230, 230) } else { RGBColor(0, 0, 0) }
+    }
+
+    /// Grid lines, faint against the background.
+    pub fn grid(&self) -> RGBColor {
+        if self.dark { RGBColor(70, 70, 70) } else { RGBColor(220, 220, 220) }
+    }
+
+    /// Fill of highlighted periods such as freezer intervals.
+    pub fn shade(&self) -> RGBColor {
+        if self.dark { RGBColor(90, 110, 180) } else { RGBColor(120, 160, 255) }
+    }
+
+    /// Series colors in order.
+    pub fn series(&self) -> Result<Vec<RGBColor>> {
+        if !self.colors.is_empty() {
+            return self.colors.iter().map(|c| parse_hex(c)).collect();
+        }
+        Ok(match (self.palette, self.dark) {
+            (Palette::Default, false) => DEFAULT_LIGHT,
+            (Palette::Default, true) => DEFAULT_DARK,
+            (Palette::Colorblind, false) => COLORBLIND_LIGHT,
+            (Palette::Colorblind, true) => COLORBLIND_DARK,
+        }
+        .to_vec())
+    }
+}
+
+fn parse_hex(color: &str) -> Result<RGBColor> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    let channel = |i: usize| hex.get(i..i + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(r), Some(g), Some(b)) => Ok(RGBColor(r, g, b)),
+        _ => Err(anyhow!("Invalid plot color {:?}, expected #rrggbb", color)),
+    }
+}
@@ -0,0 +1,75 @@
+//! `session`: logcat, memory and the app's threads collected at once, one
+//! worker thread each, and merged into a single timeline ordered by host
+//! time. Memory sampling keeps its own outputs (plot, collectors, control
+//! socket, pause); logcat keeps its filtered file; threads are polled
+//! every `--thread-interval` seconds and summarized by state.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::clocksync::{ClockSync, SampleTime};
+use crate::markers::SessionMark;
+use crate::{MemorySample, ThreadInfo};
+
+pub const TIMELINE_FILE_STEM: &str = "timeline";
+pub const DEFAULT_THREAD_INTERVAL_SECS: u64 = 5;
+
+#[derive(Serialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum TimelineEvent {
+    /// A logcat line matching the keyword regex.
+    Log { line: String },
+    Memory { total_pss: u64, native_heap: u64, dalvik_heap: u64, graphics: u64 },
+    Threads {
+        count: usize,
+        /// Threads per `ps` state letter ("R", "S", "D", ...).
+        by_state: BTreeMap<String, usize>,
+    },
+    Marker { label: String },
+}
+
+#[derive(Serialize)]
+pub struct TimelineEntry {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    #[serde(flatten)]
+    pub event: TimelineEvent,
+}
+
+impl TimelineEntry {
+    pub fn log(time: SampleTime, line: &str) -> Self {
+        TimelineEntry { time, event: TimelineEvent::Log { line: line.trim_end().to_string() } }
+    }
+
+    pub fn memory(sample: &MemorySample) -> Self {
+        TimelineEntry {
+            time: sample.time,
+            event: TimelineEvent::Memory {
+                total_pss: sample.total_pss,
+                native_heap: sample.native_heap,
+                dalvik_heap: sample.dalvik_heap,
+                graphics: sample.graphics,
+            },
+        }
+    }
+
+    pub fn threads(time: SampleTime, threads: &[ThreadInfo]) -> Self {
+        let mut by_state = BTreeMap::new();
+        for thread in threads {
+            *by_state.entry(thread.state.clone()).or_insert(0) += 1;
+        }
+        TimelineEntry { time, event: TimelineEvent::Threads { count: threads.len(), by_state } }
+    }
+}
+
+/// Collected entries and session markers in host time order; entries at
+/// the same millisecond keep the order they were collected in.
+pub fn merge(mut entries: Vec<TimelineEntry>, marks: &[SessionMark], clock: &ClockSync, start_ms: i64) -> Vec<TimelineEntry> {
+    entries.extend(marks.iter().map(|mark| TimelineEntry {
+        time: clock.time_at(start_ms, mark.time_ms),
+        event: TimelineEvent::Marker { label: mark.label.clone() },
+    }));
+    entries.sort_by_key(|entry| entry.time.host_time_ms);
+    entries
+}
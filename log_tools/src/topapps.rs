@@ -0,0 +1,70 @@
+//! `top-apps <N>`: device-wide memory composition over time for platform
+//! teams. Every sample reads the "Total PSS by process" section of a
+//! package-less `dumpsys meminfo` and keeps the N largest processes by
+//! name, re-ranked every `RERANK_SECS`; everything else is summed into
+//! "other", so the stacked chart always adds up to the device total.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::clocksync::SampleTime;
+use crate::memtop::ProcessPss;
+use crate::stats;
+
+pub const TOP_APPS_PLOT_FILE: &str = "top_apps_plot.png";
+pub const RERANK_SECS: u64 = 30;
+pub const OTHER: &str = "other";
+
+#[derive(Serialize)]
+pub struct TopAppsSample {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    pub total_pss: u64,
+    /// PSS in KB of the processes tracked at this sample.
+    pub processes: BTreeMap<String, u64>,
+    /// Everything not tracked.
+    pub other: u64,
+}
+
+pub struct TopAppsTracker {
+    count: usize,
+    tracked: Vec<String>,
+    ranked_at: Option<u64>,
+}
+
+impl TopAppsTracker {
+    pub fn new(count: usize) -> Self {
+        TopAppsTracker { count, tracked: Vec::new(), ranked_at: None }
+    }
+
+    /// The sample of one device-wide snapshot, ranking it first when due.
+    /// Processes sharing a name (several instances, restarts) are summed.
+    pub fn sample(&mut self, time: SampleTime, processes: &[ProcessPss]) -> TopAppsSample {
+        let mut by_name: BTreeMap<&str, u64> = BTreeMap::new();
+        for process in processes {
+            *by_name.entry(&process.name).or_default() += process.pss;
+        }
+        if self.ranked_at.is_none_or(|at| time.timestamp >= at + RERANK_SECS) {
+            let mut ranked: Vec<(&str, u64)> = by_name.iter().map(|(name, pss)| (*name, *pss)).collect();
+            ranked.sort_by_key(|(_, pss)| std::cmp::Reverse(*pss));
+            self.tracked = ranked.iter().take(self.count).map(|(name, _)| name.to_string()).collect();
+            self.ranked_at = Some(time.timestamp);
+        }
+        let tracked: BTreeMap<String, u64> = self.tracked.iter().map(|name| (name.clone(), by_name.get(name.as_str()).copied().unwrap_or(0))).collect();
+        let total_pss: u64 = by_name.values().sum();
+        let other = total_pss.saturating_sub(tracked.values().sum());
+        TopAppsSample { time, total_pss, processes: tracked, other }
+    }
+}
+
+/// Every process tracked at some point, largest mean PSS over the session
+/// first (untracked samples count as 0).
+pub fn ranked_names(samples: &[TopAppsSample]) -> Vec<&str> {
+    let mut names: Vec<&str> = samples.iter().flat_map(|s| s.processes.keys().map(String::as_str)).collect();
+    names.sort_unstable();
+    names.dedup();
+    let mean = |name: &str| stats::mean(&samples.iter().map(|s| s.processes.get(name).copied().unwrap_or(0) as f64).collect::<Vec<_>>());
+    names.sort_by(|a, b| mean(b).total_cmp(&mean(a)));
+    names
+}
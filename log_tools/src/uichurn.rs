@@ -0,0 +1,66 @@
+//! UI-layer churn counters extracted from logcat: Compose recomposition
+//! debug logs and FragmentManager verbose lifecycle logs.
+
+use std::collections::BTreeMap;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+/// FragmentManager only logs lifecycle moves with its tag at VERBOSE.
+pub const FRAGMENT_VERBOSE_CMD: &str = "setprop log.tag.FragmentManager VERBOSE";
+
+// The widely used `LogCompositions(tag, msg)` helper logs
+// "Compositions: <msg> <count>" with a running count per call site.
+static COMPOSITIONS_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"Compositions: (.+?) (\d+)\s*$").unwrap());
+// "V/FragmentManager( 1234): moveto RESUMED: HomeFragment{3f1a2b} (...)"
+static FRAGMENT_MOVE_REGEX: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"FragmentManager.*?moveto (\w+): ([\w$]+)\{").unwrap());
+
+#[derive(Default, Serialize)]
+pub struct FragmentCounts {
+    /// Lifecycle transitions of any kind.
+    pub transitions: u64,
+    /// Times the fragment reached RESUMED, i.e. was (re)shown.
+    pub resumes: u64,
+}
+
+#[derive(Default, Serialize)]
+pub struct UiChurn {
+    /// Highest recomposition count seen per composable label.
+    pub recompositions: BTreeMap<String, u64>,
+    pub fragments: BTreeMap<String, FragmentCounts>,
+}
+
+impl UiChurn {
+    pub fn observe(&mut self, line: &str) {
+        if let Some(caps) = COMPOSITIONS_REGEX.captures(line) {
+            let count: u64 = caps[2].parse().unwrap_or(0);
+            let entry = self.recompositions.entry(caps[1].trim().to_string()).or_default();
+            *entry = (*entry).max(count);
+        } else if let Some(caps) = FRAGMENT_MOVE_REGEX.captures(line) {
+            let counts = self.fragments.entry(caps[2].to_string()).or_default();
+            counts.transitions += 1;
+            if &caps[1] == "RESUMED" {
+                counts.resumes += 1;
+            }
+        }
+    }
+
+    /// Folds in counters observed on another thread.
+    pub fn merge(&mut self, other: UiChurn) {
+        for (label, count) in other.recompositions {
+            let entry = self.recompositions.entry(label).or_default();
+            *entry = (*entry).max(count);
+        }
+        for (fragment, counts) in other.fragments {
+            let entry = self.fragments.entry(fragment).or_default();
+            entry.transitions += counts.transitions;
+            entry.resumes += counts.resumes;
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recompositions.is_empty() && self.fragments.is_empty()
+    }
+}
@@ -0,0 +1,43 @@
+//! View hierarchy metrics from a `uiautomator dump` XML file.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde::Serialize;
+
+/// Where the dump is written on the device before being pulled.
+pub const DEVICE_DUMP_PATH: &str = "/sdcard/window_dump.xml";
+
+#[derive(Serialize)]
+pub struct UiMetrics {
+    pub node_count: usize,
+    /// Depth of the deepest `node`, counting the window root as 1.
+    pub max_depth: usize,
+    /// Class names along the path to the deepest node.
+    pub deepest_path: Vec<String>,
+    /// Nodes per package, to tell the app's views from system UI.
+    pub nodes_by_package: BTreeMap<String, usize>,
+}
+
+pub fn ui_metrics(xml: &str) -> Result<UiMetrics> {
+    let doc = roxmltree::Document::parse(xml)?;
+    let mut metrics = UiMetrics { node_count: 0, max_depth: 0, deepest_path: Vec::new(), nodes_by_package: BTreeMap::new() };
+    for node in doc.descendants().filter(|n| n.has_tag_name("node")) {
+        metrics.node_count += 1;
+        let package = node.attribute("package").unwrap_or("").to_string();
+        *metrics.nodes_by_package.entry(package).or_default() += 1;
+
+        let depth = node.ancestors().filter(|n| n.has_tag_name("node")).count();
+        if depth > metrics.max_depth {
+            metrics.max_depth = depth;
+            let mut path: Vec<String> = node
+                .ancestors()
+                .filter(|n| n.has_tag_name("node"))
+                .map(|n| n.attribute("class").unwrap_or("?").to_string())
+                .collect();
+            path.reverse();
+            metrics.deepest_path = path;
+        }
+    }
+    Ok(metrics)
+}
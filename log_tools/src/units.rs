@@ -0,0 +1,63 @@
+//! Human-readable memory values for console tables and summaries. Files
+//! (CSV, JSON) always keep the raw KB the device reports; only text meant
+//! to be read goes through here, as chosen by `--units` and `--precision`.
+
+use once_cell::sync::OnceCell;
+
+static FORMAT: OnceCell<(Unit, usize)> = OnceCell::new();
+
+pub const DEFAULT_PRECISION: usize = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    /// KB below 1 MB, MB below 1 GB, GB above.
+    Auto,
+    Kb,
+    Mb,
+    Gb,
+}
+
+impl Unit {
+    pub fn parse(name: &str) -> Unit {
+        match name {
+            "kb" => Unit::Kb,
+            "mb" => Unit::Mb,
+            "gb" => Unit::Gb,
+            _ => Unit::Auto,
+        }
+    }
+
+    fn for_value(self, kb: u64) -> Unit {
+        match self {
+            Unit::Auto if kb < 1024 => Unit::Kb,
+            Unit::Auto if kb < 1024 * 1024 => Unit::Mb,
+            Unit::Auto => Unit::Gb,
+            unit => unit,
+        }
+    }
+}
+
+pub fn set_format(unit: Unit, precision: usize) {
+    let _ = FORMAT.set((unit, precision));
+}
+
+fn format() -> (Unit, usize) {
+    FORMAT.get().copied().unwrap_or((Unit::Auto, DEFAULT_PRECISION))
+}
+
+/// `kb` with its unit, e.g. "512 KB", "12.3 MB" or "1.2 GB" (binary
+/// multiples, as in meminfo).
+pub fn kb(kb: u64) -> String {
+    let (unit, precision) = format();
+    match unit.for_value(kb) {
+        Unit::Kb | Unit::Auto => format!("{} KB", kb),
+        Unit::Mb => format!("{:.*} MB", precision, kb as f64 / 1024.0),
+        Unit::Gb => format!("{:.*} GB", precision, kb as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+/// A KB difference with an explicit sign, e.g. "+3.4 MB" or "-120 KB".
+pub fn delta_kb(delta: i64) -> String {
+    let sign = if delta < 0 { '-' } else { '+' };
+    format!("{}{}", sign, kb(delta.unsigned_abs()))
+}
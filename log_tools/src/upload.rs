@@ -0,0 +1,113 @@
+//! Upload of session bundles by HTTP PUT, the only upload method. There is
+//! no S3 or GCS client: object stores are reached through their HTTP
+//! endpoints, with a presigned URL (S3, GCS) or a bearer token header (GCS
+//! XML API). `s3://` and `gs://` URLs are rejected.
+//!
+//! ```json
+//! "upload": {
+//!     "url": "https://storage.googleapis.com/lab-artifacts/{name}",
+//!     "headers": { "Authorization": "env:GCS_BEARER" },
+//!     "public_url": "https://storage.cloud.google.com/lab-artifacts/{name}",
+//!     "after_session": true
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UploadConfig {
+    /// HTTP(S) PUT target; `{name}` is replaced with the file name.
+    pub url: String,
+    /// Extra request headers. A value of the form `env:VAR` is read from
+    /// the environment so tokens stay out of config files.
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// Link printed after a successful upload; defaults to `url` without
+    /// its query string (which would hold presigned credentials).
+    pub public_url: Option<String>,
+    /// Bundle the files the run wrote and upload them when a measurement
+    /// session ends. The config file is never included.
+    #[serde(default)]
+    pub after_session: bool,
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+fn header_value(value: &str) -> Result<String> {
+    match value.strip_prefix("env:") {
+        Some(var) => std::env::var(var).map_err(|_| anyhow!("Upload header variable {} is not set", var)),
+        None => Ok(value.to_string()),
+    }
+}
+
+/// `url` without its query string, which may hold presigned credentials.
+fn without_query(url: &str) -> &str {
+    url.split('?').next().unwrap_or(url)
+}
+
+/// What went wrong, without the request URL ureq puts in its messages.
+fn describe(error: &ureq::Error, url: &str) -> String {
+    let text = match error {
+        ureq::Error::Status(code, _) => format!("HTTP {}", code),
+        ureq::Error::Transport(transport) => match transport.message() {
+            Some(message) => format!("{}: {}", transport.kind(), message),
+            None => transport.kind().to_string(),
+        },
+    };
+    text.replace(url, without_query(url))
+}
+
+/// Uploads `path`, retrying transport errors, 429 and 5xx responses with
+/// exponential backoff. Returns the shareable location.
+pub fn upload_file(config: &UploadConfig, path: &Path) -> Result<String> {
+    let name = path.file_name().and_then(|n| n.to_str()).ok_or_else(|| anyhow!("Invalid upload path {:?}", path))?;
+    if !config.url.starts_with("http://") && !config.url.starts_with("https://") {
+        return Err(anyhow!(
+            "Upload URL {} is not an HTTP(S) URL; only HTTP PUT is supported, use a presigned URL for S3 or GCS",
+            without_query(&config.url)
+        ));
+    }
+    let url = config.url.replace("{name}", name);
+    let body = std::fs::read(path)?;
+    let mut headers = Vec::with_capacity(config.headers.len());
+    for (key, value) in &config.headers {
+        headers.push((key.as_str(), header_value(value)?));
+    }
+
+    let mut attempt = 0;
+    loop {
+        let mut request = ureq::put(&url).set("Content-Type", "application/zip");
+        for (key, value) in &headers {
+            request = request.set(key, value);
+        }
+        let error = match request.send_bytes(&body) {
+            Ok(_) => break,
+            Err(ureq::Error::Status(code, response)) if code != 429 && code < 500 => {
+                let detail = response.into_string().unwrap_or_default();
+                return Err(anyhow!("Upload to {} rejected with HTTP {}: {}", without_query(&url), code, detail.trim()));
+            }
+            Err(e) => describe(&e, &url),
+        };
+        attempt += 1;
+        if attempt > config.retries {
+            return Err(anyhow!("Upload to {} failed after {} attempts: {}", without_query(&url), attempt, error));
+        }
+        let backoff = Duration::from_secs(1 << (attempt - 1).min(5));
+        warn!(format!("Upload attempt {} failed ({}), retrying in {}s", attempt, error, backoff.as_secs()));
+        std::thread::sleep(backoff);
+    }
+
+    Ok(match &config.public_url {
+        Some(public) => public.replace("{name}", name),
+        None => without_query(&url).to_string(),
+    })
+}
@@ -0,0 +1,197 @@
+//! Device-wide swap, zram and pressure stall (PSI) sampling.
+
+use serde::Serialize;
+
+use crate::clocksync::SampleTime;
+
+/// Shell command printing every source parsed by `parse_vm_snapshot`.
+/// Each PSI file is preceded by a `==psi <resource>` marker because their
+/// lines look identical. Missing files (no zram, pre-PSI kernels) are skipped.
+pub const VM_SNAPSHOT_CMD: &[&str] = &[
+    "cat /proc/meminfo /proc/vmstat /sys/block/zram0/mm_stat 2>/dev/null;",
+    "for r in cpu io memory; do echo ==psi $r; cat /proc/pressure/$r 2>/dev/null; done",
+];
+
+pub const PSI_RESOURCES: [&str; 3] = ["cpu", "io", "memory"];
+
+/// One `/proc/pressure/<resource>` file, as percentages of wall time.
+#[derive(Clone, Copy, Default, Serialize)]
+pub struct PsiReading {
+    pub some_avg10: f64,
+    pub some_avg60: f64,
+    pub full_avg10: f64,
+    pub full_avg60: f64,
+}
+
+#[derive(Serialize)]
+pub struct PsiSample {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    pub cpu: PsiReading,
+    pub io: PsiReading,
+    pub memory: PsiReading,
+}
+
+#[derive(Serialize)]
+pub struct PsiAlert {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    pub resource: String,
+    pub kind: String,
+    pub avg10: f64,
+}
+
+impl PsiSample {
+    pub fn from_snapshot(time: SampleTime, snap: &VmSnapshot) -> Self {
+        PsiSample { time, cpu: snap.psi_cpu, io: snap.psi_io, memory: snap.psi_memory }
+    }
+
+    pub fn reading(&self, resource: &str) -> &PsiReading {
+        match resource {
+            "cpu" => &self.cpu,
+            "io" => &self.io,
+            _ => &self.memory,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct VmSnapshot {
+    pub swap_total_kb: u64,
+    pub swap_free_kb: u64,
+    pub pswpin: u64,
+    pub pswpout: u64,
+    pub pgscan_kswapd: u64,
+    pub psi_cpu: PsiReading,
+    pub psi_io: PsiReading,
+    pub psi_memory: PsiReading,
+    pub zram_orig_kb: u64,
+    pub zram_compr_kb: u64,
+    pub zram_used_kb: u64,
+}
+
+#[derive(Serialize)]
+pub struct DeviceMemorySample {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    pub swap_used_kb: u64,
+    pub zram_orig_kb: u64,
+    pub zram_compr_kb: u64,
+    pub zram_used_kb: u64,
+    /// Pages swapped in/out per second since the previous sample.
+    pub swap_in_rate: f64,
+    pub swap_out_rate: f64,
+    /// Pages scanned by kswapd per second, i.e. background reclaim effort.
+    pub kswapd_scan_rate: f64,
+    pub psi_some_avg10: f64,
+    pub psi_full_avg10: f64,
+}
+
+/// Parses the output of `VM_SNAPSHOT_CMD`. Apart from PSI, each source has
+/// a distinct line shape, so only the PSI files need markers.
+pub fn parse_vm_snapshot(output: &str) -> VmSnapshot {
+    let mut snap = VmSnapshot::default();
+    let mut psi_resource = None;
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if let ["==psi", resource] = fields.as_slice() {
+            psi_resource = PSI_RESOURCES.iter().position(|r| r == resource);
+            continue;
+        }
+        if let Some(index) = psi_resource {
+            let psi = match index {
+                0 => &mut snap.psi_cpu,
+                1 => &mut snap.psi_io,
+                _ => &mut snap.psi_memory,
+            };
+            match fields.as_slice() {
+                ["some", rest @ ..] => {
+                    psi.some_avg10 = psi_value(rest, "avg10");
+                    psi.some_avg60 = psi_value(rest, "avg60");
+                }
+                ["full", rest @ ..] => {
+                    psi.full_avg10 = psi_value(rest, "avg10");
+                    psi.full_avg60 = psi_value(rest, "avg60");
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match fields.as_slice() {
+            ["SwapTotal:", value, ..] => snap.swap_total_kb = value.parse().unwrap_or(0),
+            ["SwapFree:", value, ..] => snap.swap_free_kb = value.parse().unwrap_or(0),
+            ["pswpin", value] => snap.pswpin = value.parse().unwrap_or(0),
+            ["pswpout", value] => snap.pswpout = value.parse().unwrap_or(0),
+            ["pgscan_kswapd", value] => snap.pgscan_kswapd = value.parse().unwrap_or(0),
+            // mm_stat: orig_data_size compr_data_size mem_used_total ... (bytes)
+            [orig, compr, used, ..] if fields.iter().all(|f| f.chars().all(|c| c.is_ascii_digit())) => {
+                snap.zram_orig_kb = orig.parse::<u64>().unwrap_or(0) / 1024;
+                snap.zram_compr_kb = compr.parse::<u64>().unwrap_or(0) / 1024;
+                snap.zram_used_kb = used.parse::<u64>().unwrap_or(0) / 1024;
+            }
+            _ => {}
+        }
+    }
+    snap
+}
+
+/// Reads `key=value` from a PSI line such as `avg10=0.12 avg60=0.05 ...`.
+pub fn psi_value(fields: &[&str], key: &str) -> f64 {
+    fields
+        .iter()
+        .find_map(|f| f.strip_prefix(key)?.strip_prefix('='))
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0.0)
+}
+
+impl DeviceMemorySample {
+    pub fn from_snapshots(time: SampleTime, snap: &VmSnapshot, prev: Option<&VmSnapshot>, elapsed_secs: f64) -> Self {
+        let rate = |now: u64, before: Option<u64>| match before {
+            Some(before) if elapsed_secs > 0.0 => now.saturating_sub(before) as f64 / elapsed_secs,
+            _ => 0.0,
+        };
+        DeviceMemorySample {
+            time,
+            swap_used_kb: snap.swap_total_kb.saturating_sub(snap.swap_free_kb),
+            zram_orig_kb: snap.zram_orig_kb,
+            zram_compr_kb: snap.zram_compr_kb,
+            zram_used_kb: snap.zram_used_kb,
+            swap_in_rate: rate(snap.pswpin, prev.map(|p| p.pswpin)),
+            swap_out_rate: rate(snap.pswpout, prev.map(|p| p.pswpout)),
+            kswapd_scan_rate: rate(snap.pgscan_kswapd, prev.map(|p| p.pgscan_kswapd)),
+            psi_some_avg10: snap.psi_memory.some_avg10,
+            psi_full_avg10: snap.psi_memory.full_avg10,
+        }
+    }
+}
+
+/// Tracks which PSI signals are above the alert threshold so an alert is
+/// raised once per excursion rather than on every sample.
+pub struct PsiAlerter {
+    threshold: f64,
+    active: Vec<(String, String)>,
+    pub alerts: Vec<PsiAlert>,
+}
+
+impl PsiAlerter {
+    pub fn new(threshold: f64) -> Self {
+        PsiAlerter { threshold, active: Vec::new(), alerts: Vec::new() }
+    }
+
+    pub fn check(&mut self, sample: &PsiSample) {
+        for resource in PSI_RESOURCES {
+            let reading = sample.reading(resource);
+            for (kind, avg10) in [("some", reading.some_avg10), ("full", reading.full_avg10)] {
+                let key = (resource.to_string(), kind.to_string());
+                let was_active = self.active.contains(&key);
+                if avg10 >= self.threshold && !was_active {
+                    warn!(format!("PSI {} {} avg10 at {:.2}% (threshold {:.2}%) at {}s", resource, kind, avg10, self.threshold, sample.time.timestamp));
+                    self.alerts.push(PsiAlert { time: sample.time, resource: key.0.clone(), kind: key.1.clone(), avg10 });
+                    self.active.push(key);
+                } else if avg10 < self.threshold && was_active {
+                    self.active.retain(|k| *k != key);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! Alarm wakeup counting from `dumpsys alarm` "Alarm Stats".
+
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct WakeupHour {
+    pub hour: u64,
+    pub wakeups: u64,
+}
+
+/// Narrows `dumpsys alarm` to the package's stats header lines.
+pub fn wakeup_stats_cmd(package: &str) -> String {
+    format!("dumpsys alarm | grep -F ':{} +'", package)
+}
+
+/// Sums the cumulative wakeup counts of lines such as
+/// `  u0a123:com.example.app +1s234ms running, 12 wakeups:`, only those of
+/// `user` when set.
+pub fn parse_wakeup_count(output: &str, package: &str, user: Option<u32>) -> Option<u64> {
+    let marker = format!(":{} +", package);
+    let user_prefix = user.map(|user| format!("u{}a", user));
+    let mut total = None;
+    for line in output.lines().filter(|line| line.contains(&marker)) {
+        if user_prefix.as_ref().is_some_and(|prefix| !line.trim_start().starts_with(prefix.as_str())) {
+            continue;
+        }
+        let count = line
+            .split(',')
+            .nth(1)
+            .and_then(|part| part.split_whitespace().next())
+            .and_then(|n| n.parse::<u64>().ok());
+        if let Some(count) = count {
+            *total.get_or_insert(0) += count;
+        }
+    }
+    total
+}
+
+/// Buckets cumulative counts `(elapsed_secs, count)` into wakeups per hour
+/// of session time. Counter resets (e.g. app reinstall) are treated as 0.
+pub fn wakeups_per_hour(counts: &[(u64, u64)]) -> Vec<WakeupHour> {
+    let mut hours: Vec<WakeupHour> = Vec::new();
+    for pair in counts.windows(2) {
+        let (_, before) = pair[0];
+        let (timestamp, after) = pair[1];
+        let hour = timestamp / 3600;
+        while hours.len() as u64 <= hour {
+            hours.push(WakeupHour { hour: hours.len() as u64, wakeups: 0 });
+        }
+        hours[hour as usize].wakeups += after.saturating_sub(before);
+    }
+    hours
+}
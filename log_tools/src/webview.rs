@@ -0,0 +1,58 @@
+//! The WebView provider the device runs, and WebView renderer process
+//! crashes reported in logcat. Hybrid apps often behave differently on another
+//! WebView build, so the provider and version are saved with the device
+//! environment of every session and with each renderer crash a logcat
+//! session sees.
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::clocksync::SampleTime;
+
+pub const WEBVIEW_CMD: &[&str] = &["dumpsys", "webviewupdate"];
+pub const WEBVIEW_CRASHES_FILE_STEM: &str = "webview_crashes";
+
+// "  Current WebView package (name, version): (com.google.android.webview, 120.0.6099.144)"
+static PROVIDER_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"Current WebView package \(name, version\): \(([^,]+), ([^)]+)\)").unwrap());
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebViewProvider {
+    pub package: String,
+    pub version: String,
+}
+
+impl std::fmt::Display for WebViewProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {}", self.package, self.version)
+    }
+}
+
+/// The current provider in `dumpsys webviewupdate` output; none when no
+/// valid provider is installed.
+pub fn parse_provider(output: &str) -> Option<WebViewProvider> {
+    let caps = PROVIDER_REGEX.captures(output)?;
+    Some(WebViewProvider { package: caps[1].trim().to_string(), version: caps[2].trim().to_string() })
+}
+
+/// Lines reporting that a WebView renderer died: the app's own
+/// `onRenderProcessGone` logging, and Chromium's crash and kill reports.
+pub fn is_renderer_crash(line: &str) -> bool {
+    line.contains("Render process gone")
+        || line.contains("AwBrowserTerminator")
+        || (line.contains("Renderer process (") && line.contains("crash detected"))
+}
+
+#[derive(Serialize)]
+pub struct RendererCrash {
+    #[serde(flatten)]
+    pub time: SampleTime,
+    pub line: String,
+}
+
+#[derive(Serialize)]
+pub struct WebViewCrashReport<'a> {
+    /// Provider at the start of the session.
+    pub provider: Option<&'a WebViewProvider>,
+    pub renderer_crashes: &'a [RendererCrash],
+}
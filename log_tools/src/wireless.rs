@@ -0,0 +1,75 @@
+//! Wireless debugging (Android 11+): `connect` pairs with and connects to
+//! a device over Wi-Fi and remembers its endpoint under
+//! `wireless_endpoints` in the config file, and every later run reconnects
+//! the remembered endpoints that are not attached before talking to the
+//! device.
+//!
+//! The device picks a new connect port whenever wireless debugging is
+//! turned back on, so an endpoint that refuses the connection is looked up
+//! again by host among the `_adb-tls-connect` services adb sees over mDNS.
+
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+
+use crate::console;
+
+pub const MDNS_ARGS: &[&str] = &["mdns", "services"];
+/// Key of the remembered endpoints in the config file.
+pub const ENDPOINTS_KEY: &str = "wireless_endpoints";
+const CONNECT_SERVICE: &str = "_adb-tls-connect._tcp";
+
+/// Checks that `endpoint` is "host:port" and returns the host.
+pub fn endpoint_host(endpoint: &str) -> Result<&str> {
+    match endpoint.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(host),
+        _ => Err(anyhow!("{:?} is not a host:port endpoint", endpoint)),
+    }
+}
+
+/// `adb connect` exits 0 even when it fails; its output tells.
+pub fn is_connected(output: &str) -> bool {
+    let output = output.trim_start();
+    output.starts_with("connected to") || output.starts_with("already connected to")
+}
+
+pub fn is_paired(output: &str) -> bool {
+    output.contains("Successfully paired")
+}
+
+/// Endpoints of the `_adb-tls-connect` services in `adb mdns services`
+/// output, whose lines are the tab-separated instance name, service and
+/// endpoint.
+pub fn parse_connect_services(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter(|line| line.contains(CONNECT_SERVICE))
+        .filter_map(|line| line.split_whitespace().last())
+        .filter(|endpoint| endpoint_host(endpoint).is_ok())
+        .map(str::to_string)
+        .collect()
+}
+
+/// The advertised endpoint on the host of `endpoint` when its port changed.
+pub fn rediscover(services: &[String], endpoint: &str) -> Option<String> {
+    let host = endpoint_host(endpoint).ok()?;
+    services.iter().find(|s| endpoint_host(s).ok() == Some(host) && s.as_str() != endpoint).cloned()
+}
+
+/// Stores `endpoint` under `wireless_endpoints` in the config file at
+/// `path`, replacing one on the same host. The file is edited as JSON, so
+/// its other keys stay as they are and in their order. Returns whether it
+/// changed.
+pub fn remember(path: &str, endpoint: &str) -> Result<bool> {
+    let host = endpoint_host(endpoint)?;
+    let mut config: Value = serde_json::from_str(&console::read_text(path)?)?;
+    let object = config.as_object_mut().ok_or_else(|| anyhow!("{} does not hold a JSON object", path))?;
+    let endpoints = object.entry(ENDPOINTS_KEY).or_insert_with(|| Value::Array(Vec::new()));
+    let list = endpoints.as_array_mut().ok_or_else(|| anyhow!("{} in {} is not a list", ENDPOINTS_KEY, path))?;
+    if list.iter().any(|e| e.as_str() == Some(endpoint)) {
+        return Ok(false);
+    }
+    list.retain(|e| e.as_str().and_then(|e| endpoint_host(e).ok()) != Some(host));
+    list.push(Value::String(endpoint.to_string()));
+    std::fs::write(path, serde_json::to_string_pretty(&config)? + "\n")?;
+    Ok(true)
+}